@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use cubesql::{
     di_service,
     sql::{AuthContext, AuthenticateResponse, SqlAuthService},
-    transport::LoadRequestMeta,
+    transport::{LoadRequestMeta, QueryPriority},
     CubeError,
 };
 use log::trace;
@@ -64,6 +64,15 @@ impl AuthContext for NativeAuthContext {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn query_priority(&self) -> Option<QueryPriority> {
+        self.security_context
+            .as_ref()?
+            .get("queryPriority")?
+            .as_str()?
+            .parse()
+            .ok()
+    }
 }
 
 #[async_trait]
@@ -5,6 +5,7 @@ use cubesql::{
     config::{Config, CubeServices},
     sql::SqlAuthService,
     transport::TransportService,
+    CubeError,
 };
 
 #[derive(Clone)]
@@ -38,10 +39,10 @@ impl NodeConfig {
         &self,
         transport: Arc<NodeBridgeTransport>,
         auth: Arc<NodeBridgeAuthService>,
-    ) -> CubeServices {
+    ) -> Result<CubeServices, CubeError> {
         let injector = self.config.injector();
 
-        self.config.configure().await;
+        self.config.configure().await?;
 
         injector
             .register_typed::<dyn TransportService, _, _, _>(async move |_| transport)
@@ -51,6 +52,6 @@ impl NodeConfig {
             .register_typed::<dyn SqlAuthService, _, _, _>(async move |_| auth)
             .await;
 
-        self.config.cube_services().await
+        Ok(self.config.cube_services().await)
     }
 }
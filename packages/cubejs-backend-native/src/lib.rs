@@ -24,7 +24,11 @@ use crate::cross::CLRepr;
 use auth::NodeBridgeAuthService;
 use config::NodeConfig;
 use cubesql::telemetry::LocalReporter;
-use cubesql::{config::CubeServices, telemetry::ReportingLogger, CubeError};
+use cubesql::{
+    config::{ConfigObj, CubeServices},
+    telemetry::ReportingLogger,
+    CubeError,
+};
 use log::Level;
 use logger::NodeBridgeLogger;
 use neon::prelude::*;
@@ -159,6 +163,7 @@ fn register_interface(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let channel = cx.channel();
 
     let runtime = tokio_runtime_node(&mut cx)?;
+    let config = NodeConfig::new(port, pg_port, nonce);
     let transport_service = NodeBridgeTransport::new(
         cx.channel(),
         transport_sql_api_load,
@@ -167,18 +172,24 @@ fn register_interface(mut cx: FunctionContext) -> JsResult<JsPromise> {
         transport_log_load_event,
         transport_sql_generator,
         transport_can_switch_user_for_session,
-    );
+    )
+    .with_folder_schemas(config.config.config_obj().folder_schemas());
     let auth_service = NodeBridgeAuthService::new(cx.channel(), check_auth);
 
     std::thread::spawn(move || {
-        let config = NodeConfig::new(port, pg_port, nonce);
-
         runtime.block_on(async move {
-            let services = Arc::new(
-                config
-                    .configure(Arc::new(transport_service), Arc::new(auth_service))
-                    .await,
-            );
+            let services = match config
+                .configure(Arc::new(transport_service), Arc::new(auth_service))
+                .await
+            {
+                Ok(services) => Arc::new(services),
+                Err(err) => {
+                    deferred.settle_with(&channel, move |mut cx| {
+                        cx.throw_error::<_, Handle<JsUndefined>>(err.to_string())
+                    });
+                    return;
+                }
+            };
 
             let services_arc = services.clone();
             let interface = SQLInterface::new(services_arc);
@@ -138,6 +138,13 @@ impl ValueObject for JsValueObject<'_> {
             .map_err(|e| {
                 CubeError::user(format!("Can't get '{}' field value: {}", field_name, e))
             })?;
+
+        self.field_value(value)
+    }
+}
+
+impl<'a> JsValueObject<'a> {
+    fn field_value(&mut self, value: Handle<'a, JsValue>) -> Result<FieldValue, CubeError> {
         if let Ok(s) = value.downcast::<JsString, _>(&mut self.cx) {
             Ok(FieldValue::String(s.value(&mut self.cx)))
         } else if let Ok(n) = value.downcast::<JsNumber, _>(&mut self.cx) {
@@ -148,6 +155,38 @@ impl ValueObject for JsValueObject<'_> {
             || value.downcast::<JsNull, _>(&mut self.cx).is_ok()
         {
             Ok(FieldValue::Null)
+        } else if let Ok(items) = value.downcast::<JsArray, _>(&mut self.cx) {
+            let items = items
+                .to_vec(&mut self.cx)
+                .map_err(|e| CubeError::user(format!("Can't read array value: {}", e)))?;
+
+            Ok(FieldValue::Array(
+                items
+                    .into_iter()
+                    .map(|item| self.field_value(item))
+                    .collect::<Result<_, _>>()?,
+            ))
+        } else if let Ok(object) = value.downcast::<JsObject, _>(&mut self.cx) {
+            let keys = object
+                .get_own_property_names(&mut self.cx)
+                .and_then(|keys| keys.to_vec(&mut self.cx))
+                .map_err(|e| CubeError::user(format!("Can't read object keys: {}", e)))?;
+
+            let mut fields = serde_json::Map::new();
+            for key in keys {
+                let key = key
+                    .downcast::<JsString, _>(&mut self.cx)
+                    .map_err(|e| CubeError::user(format!("Can't read object key: {}", e)))?
+                    .value(&mut self.cx);
+                let value = object
+                    .get::<JsValue, _, _>(&mut self.cx, key.as_str())
+                    .map_err(|e| {
+                        CubeError::user(format!("Can't get '{}' field value: {}", key, e))
+                    })?;
+                fields.insert(key, self.field_value(value)?.into_json());
+            }
+
+            Ok(FieldValue::Object(fields))
         } else {
             Err(CubeError::user(format!(
                 "Expected primitive value but found: {:?}",
@@ -36,6 +36,7 @@ pub struct NodeBridgeTransport {
     log_load_event: Arc<Root<JsFunction>>,
     sql_generators: Arc<Root<JsFunction>>,
     can_switch_user_for_session: Arc<Root<JsFunction>>,
+    folder_schemas: bool,
 }
 
 impl NodeBridgeTransport {
@@ -56,8 +57,14 @@ impl NodeBridgeTransport {
             log_load_event: Arc::new(log_load_event),
             sql_generators: Arc::new(sql_generators),
             can_switch_user_for_session: Arc::new(can_switch_user_for_session),
+            folder_schemas: false,
         }
     }
+
+    pub fn with_folder_schemas(mut self, folder_schemas: bool) -> Self {
+        self.folder_schemas = folder_schemas;
+        self
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -191,10 +198,11 @@ impl TransportService for NodeBridgeTransport {
         #[cfg(not(debug_assertions))]
         trace!("[transport] Meta <- <hidden>");
 
-        Ok(Arc::new(MetaContext::new(
+        Ok(Arc::new(MetaContext::new_with_folder_schemas(
             response.cubes.unwrap_or_default(),
             cube_to_data_source,
             data_source_to_sql_generator,
+            self.folder_schemas,
         )))
     }
 
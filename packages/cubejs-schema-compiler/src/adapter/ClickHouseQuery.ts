@@ -248,6 +248,7 @@ export class ClickHouseQuery extends BaseQuery {
     const templates = super.sqlTemplates();
     templates.quotes.identifiers = '`';
     templates.quotes.escape = '\\`';
+    templates.functions.RANDOM = '(rand() / 4294967296)';
     return templates;
   }
 }
@@ -91,6 +91,7 @@ export class MysqlQuery extends BaseQuery {
     const templates = super.sqlTemplates();
     templates.quotes.identifiers = '`';
     templates.quotes.escape = '\\`';
+    templates.functions.RANDOM = 'RAND()';
     return templates;
   }
 }
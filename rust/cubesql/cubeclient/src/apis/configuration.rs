@@ -10,6 +10,8 @@ pub struct ConfigurationImpl<T> {
     pub oauth_access_token: Option<String>,
     pub bearer_access_token: Option<String>,
     pub api_key: Option<ApiKey>,
+    /// Reading of a response body is aborted as soon as it exceeds this number of bytes
+    pub max_response_size: Option<usize>,
     // TODO: take an oauth2 token source, similar to the go one
 }
 
@@ -33,8 +35,14 @@ impl Configuration {
             oauth_access_token: None,
             bearer_access_token: None,
             api_key: None,
+            max_response_size: None,
         }
     }
+
+    /// Configuration with a preconfigured client, e.g. with a proxy or custom timeouts
+    pub fn with_client(client: reqwest::Client) -> Configuration {
+        Configuration::new(ClientBuilder::new(client).build())
+    }
 }
 
 impl Default for Configuration {
@@ -2,7 +2,7 @@ use log::{debug, error};
 use reqwest;
 use uuid::Uuid;
 
-use super::{configuration, Error};
+use super::{configuration, read_content, Error};
 use crate::apis::ResponseContent;
 
 /// struct for typed errors of method [`load_v1`]
@@ -26,10 +26,12 @@ pub enum MetaV1Error {
 pub async fn load_v1(
     configuration: &configuration::Configuration,
     v1_load_request: Option<crate::models::V1LoadRequest>,
+    request_id: Option<String>,
 ) -> Result<crate::models::V1LoadResponse, Error<LoadV1Error>> {
     let local_var_client = &configuration.client;
 
-    let request_id = Uuid::new_v4().to_string();
+    // Spans of the request are sent as "<request_id>-span-<n>"
+    let request_id = request_id.unwrap_or_else(|| Uuid::new_v4().to_string());
     let mut span_counter: u32 = 1;
 
     loop {
@@ -47,16 +49,20 @@ pub async fn load_v1(
         };
         local_var_req_builder = local_var_req_builder.json(&v1_load_request);
 
-        local_var_req_builder = local_var_req_builder.header(
-            "x-request-id",
-            format!("{}-span-{}", request_id, span_counter),
-        );
+        let span_request_id = format!("{}-span-{}", request_id, span_counter);
+        local_var_req_builder =
+            local_var_req_builder.header("x-request-id", span_request_id.clone());
 
         let local_var_req = local_var_req_builder.build()?;
         let local_var_resp = local_var_client.execute(local_var_req).await?;
 
         let local_var_status = local_var_resp.status();
-        let local_var_content = local_var_resp.text().await?;
+        let local_var_content = read_content(
+            local_var_resp,
+            configuration.max_response_size,
+            &span_request_id,
+        )
+        .await?;
 
         if !local_var_status.is_client_error() && !local_var_status.is_server_error() {
             let response_ok =
@@ -123,8 +129,8 @@ pub async fn meta_v1(
     let mut local_var_req_builder =
         local_var_client.request(reqwest::Method::GET, local_var_uri_str.as_str());
 
-    let request_id = Uuid::new_v4().to_string();
-    local_var_req_builder = local_var_req_builder.header("x-request-id", request_id + "-span-1");
+    let request_id = Uuid::new_v4().to_string() + "-span-1";
+    local_var_req_builder = local_var_req_builder.header("x-request-id", request_id.clone());
 
     if let Some(ref local_var_user_agent) = local_var_configuration.user_agent {
         local_var_req_builder =
@@ -138,7 +144,12 @@ pub async fn meta_v1(
     let local_var_resp = local_var_client.execute(local_var_req).await?;
 
     let local_var_status = local_var_resp.status();
-    let local_var_content = local_var_resp.text().await?;
+    let local_var_content = read_content(
+        local_var_resp,
+        local_var_configuration.max_response_size,
+        &request_id,
+    )
+    .await?;
 
     if !local_var_status.is_client_error() && !local_var_status.is_server_error() {
         serde_json::from_str(&local_var_content).map_err(Error::from)
@@ -234,10 +245,35 @@ mod tests {
         let mut configuration = Configuration::new(client);
         configuration.base_path = server.uri();
 
-        let resp = load_v1(&configuration, None).await;
+        let resp = load_v1(&configuration, None, None).await;
         match resp {
             Ok(_) => {}
             Err(e) => panic!("must be successful, {:?}", e),
         };
     }
+
+    #[tokio::test]
+    async fn test_max_response_size() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/load"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(1024)))
+            .mount(&server)
+            .await;
+
+        let reqwest_client = Client::builder().build().unwrap();
+        let client = ClientBuilder::new(reqwest_client).build();
+
+        let mut configuration = Configuration::new(client);
+        configuration.base_path = server.uri();
+        configuration.max_response_size = Some(100);
+
+        match load_v1(&configuration, None, None).await {
+            Err(Error::ResponseTooLarge { limit, request_id }) => {
+                assert_eq!(limit, 100);
+                assert!(request_id.ends_with("-span-1"));
+            }
+            resp => panic!("must fail with too large response, {:?}", resp),
+        };
+    }
 }
@@ -14,6 +14,7 @@ pub enum Error<T> {
     Serde(serde_json::Error),
     Io(std::io::Error),
     ResponseError(ResponseContent<T>),
+    ResponseTooLarge { limit: usize, request_id: String },
 }
 
 impl<T> fmt::Display for Error<T> {
@@ -24,6 +25,13 @@ impl<T> fmt::Display for Error<T> {
             Error::Serde(e) => ("serde", e.to_string()),
             Error::Io(e) => ("IO", e.to_string()),
             Error::ResponseError(e) => ("response", format!("{} {}", e.status, e.content)),
+            Error::ResponseTooLarge { limit, request_id } => (
+                "response",
+                format!(
+                    "body exceeds the limit of {} bytes (request id: {})",
+                    limit, request_id
+                ),
+            ),
         };
         write!(f, "error in {}: {}", module, e)
     }
@@ -40,6 +48,7 @@ impl<T: fmt::Debug> error::Error for Error<T> {
             Error::Serde(e) => e,
             Error::Io(e) => e,
             Error::ResponseError(_) => return None,
+            Error::ResponseTooLarge { .. } => return None,
         })
     }
 }
@@ -62,6 +71,37 @@ impl<T> From<std::io::Error> for Error<T> {
     }
 }
 
+/// Reads the body without buffering more than `max_size` bytes, so a huge response fails before
+/// it's parsed
+pub(crate) async fn read_content<T>(
+    mut response: reqwest::Response,
+    max_size: Option<usize>,
+    request_id: &str,
+) -> Result<String, Error<T>> {
+    let limit = match max_size {
+        Some(limit) => limit,
+        None => return Ok(response.text().await?),
+    };
+    let too_large = || Error::ResponseTooLarge {
+        limit,
+        request_id: request_id.to_string(),
+    };
+
+    if matches!(response.content_length(), Some(length) if length > limit as u64) {
+        return Err(too_large());
+    }
+
+    let mut content = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if content.len() + chunk.len() > limit {
+            return Err(too_large());
+        }
+        content.extend_from_slice(&chunk);
+    }
+
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
 pub fn urlencode<T: AsRef<str>>(s: T) -> String {
     ::url::form_urlencoded::byte_serialize(s.as_ref().as_bytes()).collect()
 }
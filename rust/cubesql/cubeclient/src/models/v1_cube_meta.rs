@@ -22,6 +22,8 @@ pub struct V1CubeMeta {
     pub segments: Vec<crate::models::V1CubeMetaSegment>,
     #[serde(rename = "joins", skip_serializing_if = "Option::is_none")]
     pub joins: Option<Vec<crate::models::V1CubeMetaJoin>>,
+    #[serde(rename = "folder", default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
 }
 
 impl V1CubeMeta {
@@ -39,6 +41,7 @@ impl V1CubeMeta {
             dimensions,
             segments,
             joins,
+            folder: None,
         }
     }
 }
@@ -14,10 +14,16 @@ pub struct V1CubeMetaDimension {
     pub name: String,
     #[serde(rename = "type")]
     pub _type: String,
+    #[serde(rename = "granularities", skip_serializing_if = "Option::is_none")]
+    pub granularities: Option<Vec<crate::models::V1CubeMetaDimensionGranularity>>,
 }
 
 impl V1CubeMetaDimension {
     pub fn new(name: String, _type: String) -> V1CubeMetaDimension {
-        V1CubeMetaDimension { name, _type }
+        V1CubeMetaDimension {
+            name,
+            _type,
+            granularities: None,
+        }
     }
 }
@@ -0,0 +1,32 @@
+/*
+ * Cube.js
+ *
+ * Cube.js Swagger Schema
+ *
+ * The version of the OpenAPI document: 1.0.0
+ *
+ * Generated by: https://openapi-generator.tech
+ */
+
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct V1CubeMetaDimensionGranularity {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "title", skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(rename = "interval", skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+    #[serde(rename = "offset", skip_serializing_if = "Option::is_none")]
+    pub offset: Option<String>,
+}
+
+impl V1CubeMetaDimensionGranularity {
+    pub fn new(name: String) -> V1CubeMetaDimensionGranularity {
+        V1CubeMetaDimensionGranularity {
+            name,
+            title: None,
+            interval: None,
+            offset: None,
+        }
+    }
+}
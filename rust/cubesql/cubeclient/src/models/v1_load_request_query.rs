@@ -28,6 +28,8 @@ pub struct V1LoadRequestQuery {
     pub filters: Option<Vec<crate::models::V1LoadRequestQueryFilterItem>>,
     #[serde(rename = "ungrouped", skip_serializing_if = "Option::is_none")]
     pub ungrouped: Option<bool>,
+    #[serde(rename = "timezone", skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
 }
 
 impl V1LoadRequestQuery {
@@ -42,6 +44,7 @@ impl V1LoadRequestQuery {
             offset: None,
             filters: None,
             ungrouped: None,
+            timezone: None,
         }
     }
 }
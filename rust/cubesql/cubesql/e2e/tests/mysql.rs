@@ -51,7 +51,7 @@ impl MySqlIntegrationTestSuite {
                 c
             });
 
-            config.configure().await;
+            config.configure().await.unwrap();
             let services = config.cube_services().await;
             services.wait_processing_loops().await.unwrap();
         });
@@ -63,7 +63,7 @@ impl PostgresIntegrationTestSuite {
                 c
             });
 
-            config.configure().await;
+            config.configure().await.unwrap();
             let services = config.cube_services().await;
             services.wait_processing_loops().await.unwrap();
         });
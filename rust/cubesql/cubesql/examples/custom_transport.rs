@@ -0,0 +1,178 @@
+//! SQL API over an in-memory data set, without Cube API.
+//!
+//! CUBESQL_PG_PORT=5432 cargo run --example custom_transport
+//! psql -h 127.0.0.1 -p 5432 -U user -c 'SELECT status, MEASURE(count) FROM Orders GROUP BY 1'
+
+use std::{any::Any, collections::HashMap, sync::Arc};
+
+use cubesql::ext::{
+    async_trait, AuthContext, AuthContextRef, AuthenticateResponse, Config, CubeError,
+    CubeStreamReceiver, LoadRequestMeta, MemberField, MetaContext, SchemaRef, SpanId,
+    SqlAuthService, SqlQuery, SqlResponse, TransportService, V1CubeMeta, V1CubeMetaDimension,
+    V1CubeMetaMeasure, V1LoadRequestQuery, V1LoadResponse, V1LoadResult, V1LoadResultAnnotation,
+};
+use serde_json::{json, Value};
+
+#[derive(Debug)]
+struct StaticAuthContext {
+    user: String,
+}
+
+impl AuthContext for StaticAuthContext {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Every user is allowed without a password
+#[derive(Debug)]
+struct StaticAuth;
+
+#[async_trait]
+impl SqlAuthService for StaticAuth {
+    async fn authenticate(
+        &self,
+        user: Option<String>,
+        password: Option<String>,
+    ) -> Result<AuthenticateResponse, CubeError> {
+        Ok(AuthenticateResponse {
+            context: Arc::new(StaticAuthContext {
+                user: user.unwrap_or_else(|| "default".to_string()),
+            }),
+            password,
+            skip_password_check: true,
+        })
+    }
+}
+
+/// Serves the `Orders` cube from rows grouped by status
+#[derive(Debug)]
+struct StaticTransport {
+    meta: Arc<MetaContext>,
+    counts: Vec<(&'static str, i64)>,
+}
+
+impl StaticTransport {
+    fn new() -> Self {
+        let cube = V1CubeMeta::new(
+            "Orders".to_string(),
+            vec![V1CubeMetaMeasure::new(
+                "Orders.count".to_string(),
+                "number".to_string(),
+            )],
+            vec![V1CubeMetaDimension::new(
+                "Orders.status".to_string(),
+                "string".to_string(),
+            )],
+            vec![],
+            None,
+        );
+
+        Self {
+            meta: Arc::new(MetaContext::new(vec![cube], HashMap::new(), HashMap::new())),
+            counts: vec![("new", 3), ("processing", 2), ("shipped", 5)],
+        }
+    }
+
+    fn rows(&self, query: &V1LoadRequestQuery) -> Vec<Value> {
+        let with_status = query
+            .dimensions
+            .as_ref()
+            .map(|dimensions| dimensions.iter().any(|d| d == "Orders.status"))
+            .unwrap_or(false);
+
+        if with_status {
+            self.counts
+                .iter()
+                .map(|(status, count)| json!({ "Orders.status": status, "Orders.count": count }))
+                .collect()
+        } else {
+            let total = self.counts.iter().map(|(_, count)| count).sum::<i64>();
+            vec![json!({ "Orders.count": total })]
+        }
+    }
+}
+
+#[async_trait]
+impl TransportService for StaticTransport {
+    async fn meta(&self, _ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
+        Ok(self.meta.clone())
+    }
+
+    async fn sql(
+        &self,
+        _span_id: Option<Arc<SpanId>>,
+        _query: V1LoadRequestQuery,
+        _ctx: AuthContextRef,
+        _meta_fields: LoadRequestMeta,
+        _member_to_alias: Option<HashMap<String, String>>,
+        _expression_params: Option<Vec<Option<String>>>,
+    ) -> Result<SqlResponse, CubeError> {
+        Err(CubeError::user(
+            "SQL push down is not supported by the static transport".to_string(),
+        ))
+    }
+
+    async fn load(
+        &self,
+        _span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        _sql_query: Option<SqlQuery>,
+        ctx: AuthContextRef,
+        _meta_fields: LoadRequestMeta,
+    ) -> Result<V1LoadResponse, CubeError> {
+        if let Some(ctx) = ctx.as_any().downcast_ref::<StaticAuthContext>() {
+            println!("Load for {}: {:?}", ctx.user, query);
+        }
+
+        let annotation = V1LoadResultAnnotation::new(json!({}), json!({}), json!({}), json!({}));
+        Ok(V1LoadResponse::new(vec![V1LoadResult::new(
+            annotation,
+            self.rows(&query),
+        )]))
+    }
+
+    async fn load_stream(
+        &self,
+        _span_id: Option<Arc<SpanId>>,
+        _query: V1LoadRequestQuery,
+        _sql_query: Option<SqlQuery>,
+        _ctx: AuthContextRef,
+        _meta_fields: LoadRequestMeta,
+        _schema: SchemaRef,
+        _member_fields: Vec<MemberField>,
+    ) -> Result<CubeStreamReceiver, CubeError> {
+        Err(CubeError::user(
+            "Streaming is not supported by the static transport".to_string(),
+        ))
+    }
+
+    async fn can_switch_user_for_session(
+        &self,
+        _ctx: AuthContextRef,
+        _to_user: String,
+    ) -> Result<bool, CubeError> {
+        Ok(false)
+    }
+
+    async fn log_load_state(
+        &self,
+        _span_id: Option<Arc<SpanId>>,
+        _ctx: AuthContextRef,
+        _meta_fields: LoadRequestMeta,
+        _event: String,
+        _properties: Value,
+    ) -> Result<(), CubeError> {
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), CubeError> {
+    let config = Config::default()
+        .with_transport(Arc::new(StaticTransport::new()))
+        .with_auth_service(Arc::new(StaticAuth));
+    config.configure().await?;
+
+    config.cube_services().await.wait_processing_loops().await
+}
@@ -1,6 +1,10 @@
 use cubesql::{
+    compile::check::check_sql,
     config::{Config, CubeServices},
+    sql::{DatabaseProtocol, SessionManager, SqlAuthService},
     telemetry::{LocalReporter, ReportingLogger},
+    transport::{export_meta_snapshot, TransportService},
+    CubeError,
 };
 
 use log::Level;
@@ -37,8 +41,81 @@ fn main() {
     let config = Config::default();
 
     let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+
+    // cubesqld export-meta <path> - store data model for offline usage via CUBESQL_META_SNAPSHOT.
+    // The model is fetched with CUBESQL_CUBE_TOKEN, models of other security contexts need an
+    // export with their own token.
+    let args = env::args().collect::<Vec<_>>();
+    if args.get(1).map(|a| a.as_str()) == Some("export-meta") {
+        let path = args
+            .get(2)
+            .cloned()
+            .unwrap_or_else(|| "cubesql-meta.json".to_string());
+
+        let result = runtime.block_on(async move {
+            config.configure().await?;
+            let injector = config.injector();
+            export_meta_snapshot(
+                injector.get_service_typed::<dyn TransportService>().await,
+                injector.get_service_typed::<dyn SqlAuthService>().await,
+                &path,
+            )
+            .await
+            .map(|snapshot| (snapshot, path))
+        });
+
+        match result {
+            Ok((snapshot, path)) => println!(
+                "📦 Meta snapshot with {} cubes is written to {}",
+                snapshot.cubes.len(),
+                path
+            ),
+            Err(e) => {
+                eprintln!("Unable to export meta snapshot: {}", e);
+                std::process::exit(2);
+            }
+        }
+
+        return;
+    }
+
+    // cubesqld check [--mysql] <file.sql>... - plan statements without executing them, for CI
+    if args.get(1).map(|a| a.as_str()) == Some("check") {
+        let protocol = if args.iter().any(|a| a == "--mysql") {
+            DatabaseProtocol::MySQL
+        } else {
+            DatabaseProtocol::PostgreSQL
+        };
+        let files = args
+            .iter()
+            .skip(2)
+            .filter(|a| !a.starts_with("--"))
+            .cloned()
+            .collect::<Vec<_>>();
+        if files.is_empty() {
+            eprintln!("Usage: cubesqld check [--mysql] <file.sql>...");
+            std::process::exit(2);
+        }
+
+        let result = runtime.block_on(async move {
+            config.configure().await?;
+            check_files(&config, protocol, files).await
+        });
+
+        match result {
+            Ok(failed) => std::process::exit(if failed { 1 } else { 0 }),
+            Err(e) => {
+                eprintln!("Unable to check SQL files: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
     runtime.block_on(async move {
-        config.configure().await;
+        if let Err(e) = config.configure().await {
+            log::error!("Unable to start cubesql: {}", e);
+            std::process::exit(1);
+        }
         let services = config.cube_services().await;
         log::debug!("Cube SQL Start");
         stop_on_ctrl_c(&services).await;
@@ -46,6 +123,71 @@ fn main() {
     });
 }
 
+/// Returns true if any statement failed to plan
+async fn check_files(
+    config: &Config,
+    protocol: DatabaseProtocol,
+    files: Vec<String>,
+) -> Result<bool, CubeError> {
+    let injector = config.injector();
+    let auth = injector.get_service_typed::<dyn SqlAuthService>().await;
+    let transport = injector.get_service_typed::<dyn TransportService>().await;
+    let session_manager = injector.get_service_typed::<SessionManager>().await;
+
+    let auth_context = auth.authenticate(None, None).await?.context;
+    let meta = transport.meta(auth_context.clone()).await?;
+
+    let mut failed = false;
+    for file in files {
+        let query = match std::fs::read_to_string(&file) {
+            Ok(query) => query,
+            Err(e) => {
+                println!("ERROR {}: {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        let session = session_manager
+            .create_session(protocol.clone(), "127.0.0.1".to_string(), 0)
+            .await;
+        session.state.set_auth_context(Some(auth_context.clone()));
+
+        match check_sql(&query, meta.clone(), session.clone()).await {
+            Ok(checks) => {
+                for (i, check) in checks.iter().enumerate() {
+                    match &check.error {
+                        None if check.skipped => {
+                            println!("SKIPPED {}#{}: not a query", file, i + 1)
+                        }
+                        None => println!(
+                            "OK {}#{}: pushdown: {}, members: [{}]",
+                            file,
+                            i + 1,
+                            check.pushdown,
+                            check.members.join(", ")
+                        ),
+                        Some(error) => {
+                            failed = true;
+                            println!("ERROR {}#{}: {}\n   {}", file, i + 1, error, check.query);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                failed = true;
+                println!("ERROR {}: {}", file, e.message());
+            }
+        }
+
+        session_manager
+            .drop_session(session.state.connection_id)
+            .await;
+    }
+
+    Ok(failed)
+}
+
 async fn stop_on_ctrl_c(s: &CubeServices) {
     let s = s.clone();
     tokio::spawn(async move {
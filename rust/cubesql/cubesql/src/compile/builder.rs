@@ -160,6 +160,7 @@ impl QueryBuilder {
                     None
                 },
                 ungrouped: None,
+                timezone: None,
             },
             meta: self.meta,
         }
@@ -0,0 +1,223 @@
+use std::{fmt, sync::Arc};
+
+use cubeclient::models::{V1LoadRequestQuery, V1LoadRequestQueryFilterItem};
+use datafusion::logical_plan::LogicalPlan;
+use itertools::Itertools;
+use sqlparser::ast;
+
+use crate::{
+    compile::{
+        convert_statement_to_cube_query,
+        engine::df::{scan::CubeScanNode, wrapper::CubeScanWrapperNode},
+        find_cube_scans_deep_search,
+        parser::{parse_pushdown_hint, parse_sql_to_statements},
+        CompilationResult, MetaContext, QueryPlan,
+    },
+    sql::Session,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pushdown {
+    /// Whole query is answered by Cube
+    Full,
+    /// Cube loads the data, but part of the query is evaluated by cubesql
+    Partial,
+    /// Query doesn't touch cubes (metadata, constants)
+    None,
+}
+
+impl fmt::Display for Pushdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pushdown::Full => write!(f, "full"),
+            Pushdown::Partial => write!(f, "partial"),
+            Pushdown::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Result of planning a single statement without executing it
+#[derive(Debug, Clone)]
+pub struct StatementCheck {
+    pub query: String,
+    pub pushdown: Pushdown,
+    pub members: Vec<String>,
+    /// Statement is not a query (DDL, SET, etc.), it's not planned because planning would
+    /// apply it to the session or persist it
+    pub skipped: bool,
+    pub error: Option<String>,
+}
+
+impl StatementCheck {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Plans every query from the script, planning errors are reported per statement.
+/// Other statements are skipped. A script which can't be parsed is reported as error.
+pub async fn check_sql(
+    query: &String,
+    meta: Arc<MetaContext>,
+    session: Arc<Session>,
+) -> CompilationResult<Vec<StatementCheck>> {
+    session.state.set_pushdown_hint(parse_pushdown_hint(query));
+    let statements = parse_sql_to_statements(query, session.state.protocol.clone(), &mut None)?;
+
+    let mut result = Vec::with_capacity(statements.len());
+    for stmt in statements {
+        if !matches!(stmt, ast::Statement::Query(_)) {
+            result.push(StatementCheck {
+                query: stmt.to_string(),
+                pushdown: Pushdown::None,
+                members: vec![],
+                skipped: true,
+                error: None,
+            });
+            continue;
+        }
+
+        let plan =
+            convert_statement_to_cube_query(&stmt, meta.clone(), session.clone(), &mut None, None)
+                .await;
+
+        let check = match plan {
+            Ok(QueryPlan::DataFusionSelect(_, plan, _)) => {
+                let scans = find_cube_scans_deep_search(Arc::new(plan.clone()), false);
+
+                StatementCheck {
+                    query: stmt.to_string(),
+                    pushdown: plan_pushdown(&plan, &scans),
+                    members: scans
+                        .iter()
+                        .flat_map(|scan| request_members(&scan.request))
+                        .unique()
+                        .collect(),
+                    skipped: false,
+                    error: None,
+                }
+            }
+            Ok(_) => StatementCheck {
+                query: stmt.to_string(),
+                pushdown: Pushdown::None,
+                members: vec![],
+                skipped: false,
+                error: None,
+            },
+            Err(err) => StatementCheck {
+                query: stmt.to_string(),
+                pushdown: Pushdown::None,
+                members: vec![],
+                skipped: false,
+                error: Some(err.message()),
+            },
+        };
+
+        result.push(check);
+    }
+
+    Ok(result)
+}
+
+fn plan_pushdown(plan: &LogicalPlan, scans: &Vec<CubeScanNode>) -> Pushdown {
+    if scans.is_empty() {
+        return Pushdown::None;
+    }
+
+    match plan {
+        LogicalPlan::Extension(ext)
+            if ext.node.as_any().is::<CubeScanNode>()
+                || ext.node.as_any().is::<CubeScanWrapperNode>() =>
+        {
+            Pushdown::Full
+        }
+        _ => Pushdown::Partial,
+    }
+}
+
+fn request_members(request: &V1LoadRequestQuery) -> Vec<String> {
+    let mut members = Vec::new();
+    members.extend(request.measures.iter().flatten().cloned());
+    members.extend(request.dimensions.iter().flatten().cloned());
+    members.extend(request.segments.iter().flatten().cloned());
+    members.extend(
+        request
+            .time_dimensions
+            .iter()
+            .flatten()
+            .map(|td| td.dimension.clone()),
+    );
+    for filter in request.filters.iter().flatten() {
+        filter_members(filter, &mut members);
+    }
+
+    members
+}
+
+fn filter_members(filter: &V1LoadRequestQueryFilterItem, members: &mut Vec<String>) {
+    members.extend(filter.member.clone());
+
+    for item in filter.or.iter().chain(filter.and.iter()).flatten() {
+        if let Ok(item) = serde_json::from_value::<V1LoadRequestQueryFilterItem>(item.clone()) {
+            filter_members(&item, members);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compile::test::{get_test_session, get_test_tenant_ctx},
+        sql::session::DatabaseProtocol,
+    };
+
+    #[tokio::test]
+    async fn test_check_sql() {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL).await;
+
+        let result = check_sql(
+            &"SELECT COUNT(*), customer_gender FROM KibanaSampleDataEcommerce GROUP BY 2;
+            SELECT unknown_column FROM KibanaSampleDataEcommerce;
+            SELECT 1;
+            SET application_name = 'check';
+            CREATE VIEW female_orders AS SELECT 1;"
+                .to_string(),
+            get_test_tenant_ctx(),
+            session.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.len(), 5);
+
+        assert!(result[0].is_ok());
+        assert_eq!(result[0].pushdown, Pushdown::Full);
+        assert_eq!(
+            result[0].members,
+            vec![
+                "KibanaSampleDataEcommerce.count".to_string(),
+                "KibanaSampleDataEcommerce.customer_gender".to_string(),
+            ]
+        );
+
+        assert!(!result[1].is_ok());
+        assert!(result[1].error.as_ref().unwrap().contains("unknown_column"));
+
+        assert!(result[2].is_ok());
+        assert_eq!(result[2].pushdown, Pushdown::None);
+        assert!(result[2].members.is_empty());
+        assert!(!result[2].skipped);
+
+        // Session statements and DDL are not applied
+        assert!(result[3].is_ok() && result[3].skipped);
+        assert!(result[4].is_ok() && result[4].skipped);
+        assert_ne!(
+            session
+                .state
+                .get_variable("application_name")
+                .map(|v| v.value.to_string()),
+            Some("check".to_string())
+        );
+    }
+}
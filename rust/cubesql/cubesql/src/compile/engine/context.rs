@@ -10,6 +10,15 @@ use log::warn;
 
 use crate::sql::{session::DatabaseProtocol, ServerManager, SessionState};
 
+/// Variables which are changed per connection by `SET NAMES`, unscoped `@@name` reads them from
+/// the session as MySQL does
+const SESSION_SCOPED_VARIABLES: &[&str] = &[
+    "character_set_client",
+    "character_set_connection",
+    "character_set_results",
+    "collation_connection",
+];
+
 pub struct VariablesProvider {
     session: Arc<SessionState>,
     server: Arc<ServerManager>,
@@ -88,6 +97,15 @@ impl VarProvider for VariablesProvider {
                     return self.get_session_value(identifier, VarType::System);
                 }
 
+                if identifier.len() == 1 {
+                    let key = identifier[0][2..].to_ascii_lowercase();
+                    if SESSION_SCOPED_VARIABLES.contains(&key.as_str()) {
+                        if let Some(var) = self.session.get_variable(&key) {
+                            return Ok(var.value.clone());
+                        }
+                    }
+                }
+
                 return self.get_global_value(identifier);
             }
             ('@', _) => return self.get_session_value(identifier, VarType::UserDefined),
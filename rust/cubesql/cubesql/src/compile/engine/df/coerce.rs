@@ -80,3 +80,92 @@ pub fn least_coercion(lhs_type: &DataType, rhs_type: &DataType) -> Option<DataTy
 
     hack_ty.or_else(|| numerical_coercion(lhs_type, rhs_type))
 }
+
+/// Common type of a column of UNION (INTERSECT, EXCEPT) inputs, numbers are widened
+pub fn union_coercion(lhs_type: &DataType, rhs_type: &DataType) -> Option<DataType> {
+    if lhs_type == rhs_type {
+        return Some(lhs_type.clone());
+    }
+
+    match (lhs_type, rhs_type) {
+        (DataType::Null, other) | (other, DataType::Null) => Some(other.clone()),
+        (DataType::Utf8 | DataType::LargeUtf8, DataType::Utf8 | DataType::LargeUtf8) => {
+            Some(DataType::LargeUtf8)
+        }
+        (DataType::Decimal(lp, ls), DataType::Decimal(rp, rs)) => {
+            let scale = *ls.max(rs);
+            let integer_digits = (lp - ls).max(rp - rs);
+            Some(DataType::Decimal((integer_digits + scale).min(38), scale))
+        }
+        (DataType::Decimal(_, _), DataType::Float16 | DataType::Float32 | DataType::Float64)
+        | (DataType::Float16 | DataType::Float32 | DataType::Float64, DataType::Decimal(_, _)) => {
+            Some(DataType::Float64)
+        }
+        (DataType::Decimal(_, scale), other) | (other, DataType::Decimal(_, scale))
+            if is_numeric(other) =>
+        {
+            Some(DataType::Decimal(38, *scale))
+        }
+        (lhs, rhs) if is_numeric(lhs) && is_numeric(rhs) => {
+            let is_float = |dt: &DataType| {
+                matches!(
+                    dt,
+                    DataType::Float16 | DataType::Float32 | DataType::Float64
+                )
+            };
+            if is_float(lhs) || is_float(rhs) {
+                Some(DataType::Float64)
+            } else if !is_signed_numeric(lhs) && !is_signed_numeric(rhs) {
+                Some(DataType::UInt64)
+            } else {
+                Some(DataType::Int64)
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union_coercion() {
+        let cases = vec![
+            (DataType::Int64, DataType::Int64, Some(DataType::Int64)),
+            (DataType::Int64, DataType::Float64, Some(DataType::Float64)),
+            (DataType::Float32, DataType::Int32, Some(DataType::Float64)),
+            (DataType::Int32, DataType::Int64, Some(DataType::Int64)),
+            (DataType::UInt32, DataType::UInt64, Some(DataType::UInt64)),
+            (DataType::UInt64, DataType::Int8, Some(DataType::Int64)),
+            (DataType::Null, DataType::Float64, Some(DataType::Float64)),
+            (
+                DataType::Utf8,
+                DataType::LargeUtf8,
+                Some(DataType::LargeUtf8),
+            ),
+            (
+                DataType::Decimal(10, 2),
+                DataType::Decimal(12, 4),
+                Some(DataType::Decimal(14, 4)),
+            ),
+            (
+                DataType::Int64,
+                DataType::Decimal(10, 2),
+                Some(DataType::Decimal(38, 2)),
+            ),
+            (
+                DataType::Decimal(10, 2),
+                DataType::Float64,
+                Some(DataType::Float64),
+            ),
+            (DataType::Utf8, DataType::Int64, None),
+            (DataType::Boolean, DataType::Int64, None),
+        ];
+
+        for (lhs, rhs, expected) in cases {
+            assert_eq!(union_coercion(&lhs, &rhs), expected, "{} {}", lhs, rhs);
+            assert_eq!(union_coercion(&rhs, &lhs), expected, "{} {}", rhs, lhs);
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use std::sync::Arc;
+
+use datafusion::{
+    arrow::{
+        array::{ArrayRef, StringArray, StringBuilder},
+        datatypes::DataType,
+    },
+    error::{DataFusionError, Result},
+    physical_plan::{
+        coalesce_batches::CoalesceBatchesExec,
+        coalesce_partitions::CoalescePartitionsExec,
+        expressions::{Column, PhysicalSortExpr},
+        filter::FilterExec,
+        functions::{make_scalar_function, ScalarFunctionExpr},
+        projection::ProjectionExec,
+        repartition::RepartitionExec,
+        sorts::sort::SortExec,
+        ExecutionPlan,
+    },
+};
+
+use super::scan::CubeScanExecutionPlan;
+use crate::{compile::engine::udf::ScalarFunctionImplementation, transport::QueryWarnings};
+
+/// Languages which sort accented Latin letters as their base letters. Other languages have
+/// letters of their own, e.g. `ä` goes after `z` in Swedish and `ñ` after `n` in Spanish.
+const LATIN_LANGUAGES: &[&str] = &["de", "en", "fr", "it", "nl", "pt"];
+
+/// Ordering of strings which are sorted by SQL API, from `lc_collate` of the session
+#[derive(Debug, Clone, PartialEq)]
+pub enum Collation {
+    /// Byte ordering, `C` and `POSIX` locales
+    Binary,
+    /// Locale of one of `LATIN_LANGUAGES`: letters are compared without accents and case first,
+    /// then accents, then case, lower case goes first
+    Latin(String),
+    /// Locale whose ordering isn't implemented, strings are sorted by bytes
+    Unsupported(String),
+}
+
+impl Collation {
+    pub fn from_lc_collate(lc_collate: &str) -> Self {
+        // en_US.UTF-8 and en-us.utf8 are the same locale
+        let locale = lc_collate.trim().to_lowercase().replace('-', "_");
+        let locale = locale.split('.').next().unwrap_or_default().to_string();
+        let language = locale
+            .split(|c| c == '_' || c == '@')
+            .next()
+            .unwrap_or_default();
+
+        match locale.as_str() {
+            "" | "c" | "posix" | "ucs_basic" => Self::Binary,
+            _ if LATIN_LANGUAGES.contains(&language) => Self::Latin(locale),
+            _ => Self::Unsupported(locale),
+        }
+    }
+
+    /// Writes the string whose byte ordering is the ordering of the collation to `key`
+    pub fn write_sort_key(&self, value: &str, key: &mut String) {
+        key.clear();
+        match self {
+            Self::Binary | Self::Unsupported(_) => key.push_str(value),
+            Self::Latin(_) => {
+                for c in value.chars() {
+                    match expand_letter(c) {
+                        Some(letters) => key.push_str(letters),
+                        None => key.extend(fold_accent(c).to_lowercase()),
+                    }
+                }
+                key.push('\0');
+                key.extend(value.chars().map(|c| {
+                    if fold_accent(c) != c || expand_letter(c).is_some() {
+                        '1'
+                    } else {
+                        '0'
+                    }
+                }));
+                key.push('\0');
+                key.extend(
+                    value
+                        .chars()
+                        .map(|c| if c.is_uppercase() { '1' } else { '0' }),
+                );
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Binary | Self::Unsupported(_) => "byte".to_string(),
+            Self::Latin(locale) => format!("'{}'", locale),
+        }
+    }
+}
+
+/// Letters which are sorted as two letters
+fn expand_letter(c: char) -> Option<&'static str> {
+    match c {
+        'ß' | 'ẞ' => Some("ss"),
+        'æ' | 'Æ' => Some("ae"),
+        'œ' | 'Œ' => Some("oe"),
+        _ => None,
+    }
+}
+
+/// Base letter of the accented Latin letter
+fn fold_accent(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ă' | 'Ą' => 'A',
+        'ç' | 'ć' | 'č' => 'c',
+        'Ç' | 'Ć' | 'Č' => 'C',
+        'ď' => 'd',
+        'Ď' => 'D',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => 'e',
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ė' | 'Ę' | 'Ě' => 'E',
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' => 'i',
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' | 'Į' => 'I',
+        'ł' | 'ľ' => 'l',
+        'Ł' | 'Ľ' => 'L',
+        'ñ' | 'ń' | 'ň' => 'n',
+        'Ñ' | 'Ń' | 'Ň' => 'N',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => 'o',
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ő' => 'O',
+        'ř' => 'r',
+        'Ř' => 'R',
+        'ś' | 'š' | 'ş' => 's',
+        'Ś' | 'Š' | 'Ş' => 'S',
+        'ť' | 'ţ' => 't',
+        'Ť' | 'Ţ' => 'T',
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' | 'ų' => 'u',
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' | 'Ű' | 'Ų' => 'U',
+        'ý' | 'ÿ' => 'y',
+        'Ý' | 'Ÿ' => 'Y',
+        'ź' | 'ż' | 'ž' => 'z',
+        'Ź' | 'Ż' | 'Ž' => 'Z',
+        c => c,
+    }
+}
+
+/// Sorts of strings which are executed by DataFusion use the collation. ORDER BY of strings over
+/// a CubeScan, which wasn't pushed down to Cube, is pushed down when the data source orders
+/// strings by the same collation, otherwise it's flagged by a warning.
+pub fn apply_collation(
+    plan: Arc<dyn ExecutionPlan>,
+    collation: Option<&Collation>,
+    source_collation: Option<&Collation>,
+    warnings: &QueryWarnings,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    let children = plan.children();
+    let plan = if children.is_empty() {
+        plan
+    } else {
+        let new_children = children
+            .iter()
+            .map(|child| apply_collation(child.clone(), collation, source_collation, warnings))
+            .collect::<Result<Vec<_>>>()?;
+        plan.with_new_children(new_children)?
+    };
+
+    let sort = match plan.as_any().downcast_ref::<SortExec>() {
+        Some(sort) => sort,
+        None => return Ok(plan),
+    };
+
+    let schema = sort.input().schema();
+    let string_keys = sort
+        .expr()
+        .iter()
+        .map(|sort_expr| Ok(sort_expr.expr.data_type(&schema)? == DataType::Utf8))
+        .collect::<Result<Vec<_>>>()?;
+    if !string_keys.iter().any(|is_string| *is_string) {
+        return Ok(plan);
+    }
+
+    let collation = collation.unwrap_or(&Collation::Binary);
+    // Sort with preserved partitioning is a part of another operator
+    if sort.output_partitioning().partition_count() != 1 {
+        return Ok(plan);
+    }
+
+    if is_over_cube_scan(sort.input()) {
+        if source_collation == Some(collation) {
+            if let Some(plan) = push_sort_to_cube_scan(sort)? {
+                return Ok(plan);
+            }
+        }
+
+        warnings.push(format!(
+            "ORDER BY of text is evaluated by SQL API with {} collation, the order can differ from the data source",
+            collation.describe()
+        ));
+    }
+
+    let collation = match collation {
+        Collation::Latin(_) => collation.clone(),
+        Collation::Unsupported(locale) => {
+            warnings.push(format!(
+                "lc_collate '{}' isn't supported by SQL API, text is ordered by bytes",
+                locale
+            ));
+            return Ok(plan);
+        }
+        Collation::Binary => return Ok(plan),
+    };
+
+    let exprs = sort
+        .expr()
+        .iter()
+        .zip(string_keys.into_iter())
+        .map(|(sort_expr, is_string)| {
+            if !is_string {
+                return sort_expr.clone();
+            }
+
+            PhysicalSortExpr {
+                expr: Arc::new(ScalarFunctionExpr::new(
+                    "collation_sort_key",
+                    collation_sort_key_fun(collation.clone()),
+                    vec![sort_expr.expr.clone()],
+                    &DataType::Utf8,
+                )),
+                options: sort_expr.options,
+            }
+        })
+        .collect();
+
+    Ok(Arc::new(SortExec::try_new(exprs, sort.input().clone())?))
+}
+
+fn collation_sort_key_fun(collation: Collation) -> ScalarFunctionImplementation {
+    make_scalar_function(move |args: &[ArrayRef]| {
+        let strings = args[0]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| {
+                DataFusionError::Execution(
+                    "collation sort key is expected to be computed for strings".to_string(),
+                )
+            })?;
+
+        let mut builder = StringBuilder::new(strings.len());
+        let mut key = String::new();
+        for value in strings.iter() {
+            match value {
+                Some(value) => {
+                    collation.write_sort_key(value, &mut key);
+                    builder.append_value(&key)?;
+                }
+                None => builder.append_null()?,
+            }
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    })
+}
+
+fn is_over_cube_scan(plan: &Arc<dyn ExecutionPlan>) -> bool {
+    if plan.as_any().is::<CubeScanExecutionPlan>() {
+        return true;
+    }
+
+    match plan.children().as_slice() {
+        [child] => is_over_cube_scan(child),
+        _ => false,
+    }
+}
+
+/// Replaces the sort by ORDER BY of the CubeScan below it. Only projections, filters and
+/// repartitioning can be between them, repartitioning is removed to keep the order of the scan.
+/// Sort keys must be members, with NULLs placed as PostgreSQL places them by default, as in
+/// ORDER BY pushed down by the rewriter.
+fn push_sort_to_cube_scan(sort: &SortExec) -> Result<Option<Arc<dyn ExecutionPlan>>> {
+    let mut chain = vec![];
+    let mut node = sort.input().clone();
+    let scan = loop {
+        if let Some(scan) = node.as_any().downcast_ref::<CubeScanExecutionPlan>() {
+            break scan.clone();
+        }
+
+        let any = node.as_any();
+        if any.is::<ProjectionExec>() || any.is::<FilterExec>() || any.is::<CoalesceBatchesExec>() {
+            chain.push(node.clone());
+        } else if !any.is::<RepartitionExec>() && !any.is::<CoalescePartitionsExec>() {
+            return Ok(None);
+        }
+
+        node = match node.children().as_slice() {
+            [child] => child.clone(),
+            _ => return Ok(None),
+        };
+    };
+
+    let mut order = vec![];
+    for sort_expr in sort.expr() {
+        if sort_expr.options.nulls_first != sort_expr.options.descending {
+            return Ok(None);
+        }
+        let mut index = match sort_expr.expr.as_any().downcast_ref::<Column>() {
+            Some(column) => column.index(),
+            None => return Ok(None),
+        };
+        for node in chain.iter() {
+            if let Some(projection) = node.as_any().downcast_ref::<ProjectionExec>() {
+                index = match projection.expr()[index].0.as_any().downcast_ref::<Column>() {
+                    Some(column) => column.index(),
+                    None => return Ok(None),
+                };
+            }
+        }
+        let member = match scan.member_at(index) {
+            Some(member) => member.to_string(),
+            None => return Ok(None),
+        };
+        let direction = if sort_expr.options.descending {
+            "desc"
+        } else {
+            "asc"
+        };
+        order.push(vec![member, direction.to_string()]);
+    }
+
+    let mut plan: Arc<dyn ExecutionPlan> = match scan.with_order(order) {
+        Some(scan) => Arc::new(scan),
+        None => return Ok(None),
+    };
+    for node in chain.into_iter().rev() {
+        plan = node.with_new_children(vec![plan])?;
+    }
+
+    Ok(Some(plan))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(collation: &Collation, values: &mut Vec<&str>) {
+        let mut key = String::new();
+        values.sort_by_cached_key(|value| {
+            collation.write_sort_key(value, &mut key);
+            key.clone()
+        });
+    }
+
+    #[test]
+    fn test_collation_sort_key() {
+        assert_eq!(Collation::from_lc_collate("C"), Collation::Binary);
+        assert_eq!(Collation::from_lc_collate("C.UTF-8"), Collation::Binary);
+        assert_eq!(
+            Collation::from_lc_collate("sv_SE.UTF-8"),
+            Collation::Unsupported("sv_se".to_string())
+        );
+        assert_eq!(
+            Collation::from_lc_collate("en-US.utf8"),
+            Collation::from_lc_collate("en_US.UTF-8")
+        );
+
+        let collation = Collation::from_lc_collate("en_US.utf8");
+        assert_eq!(collation, Collation::Latin("en_us".to_string()));
+
+        let mut values = vec![
+            "b", "B", "a", "Ä", "Zebra", "ä", "A", "apple", "éclair", "eclair",
+        ];
+        sort(&collation, &mut values);
+        assert_eq!(
+            values,
+            vec!["a", "A", "ä", "Ä", "apple", "b", "B", "eclair", "éclair", "Zebra"]
+        );
+
+        let mut values = vec!["Strasse", "Straße", "Strasze", "Strase"];
+        sort(&Collation::from_lc_collate("de_DE.UTF-8"), &mut values);
+        assert_eq!(values, vec!["Strase", "Strasse", "Straße", "Strasze"]);
+
+        let mut values = vec!["b", "B", "a"];
+        sort(&Collation::Binary, &mut values);
+        assert_eq!(values, vec!["B", "a", "b"]);
+
+        let mut values = vec!["z", "ä", "a"];
+        sort(&Collation::from_lc_collate("sv_SE"), &mut values);
+        assert_eq!(values, vec!["a", "z", "ä"]);
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::HashSet,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use datafusion::{
+    arrow::{
+        array::{ArrayRef, StringArray},
+        compute::cast,
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        error::Result as ArrowResult,
+        record_batch::RecordBatch,
+    },
+    error::Result,
+    physical_plan::{
+        coalesce_partitions::CoalescePartitionsExec, repartition::RepartitionExec, ExecutionPlan,
+        Partitioning, RecordBatchStream, SendableRecordBatchStream,
+    },
+};
+use futures::{Stream, StreamExt};
+
+use super::scan::CubeScanExecutionPlan;
+
+/// Columns of smaller batches are not worth encoding
+const DICTIONARY_MIN_ROWS: usize = 64;
+
+/// Dictionary type of encoded string columns
+pub fn dictionary_string_type() -> DataType {
+    DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+}
+
+/// Encodes string columns with low cardinality as dictionaries: a column is encoded when the
+/// number of distinct values doesn't exceed `max_ratio` of the rows
+pub fn encode_dictionaries(batch: RecordBatch, max_ratio: f64) -> ArrowResult<RecordBatch> {
+    if batch.num_rows() < DICTIONARY_MIN_ROWS {
+        return Ok(batch);
+    }
+
+    let max_distinct = (batch.num_rows() as f64 * max_ratio) as usize;
+    let mut encoded = false;
+    let mut fields = Vec::with_capacity(batch.num_columns());
+    let mut columns = Vec::with_capacity(batch.num_columns());
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        if field.data_type() == &DataType::Utf8 && is_low_cardinality(column, max_distinct) {
+            fields.push(Field::new(
+                field.name(),
+                dictionary_string_type(),
+                field.is_nullable(),
+            ));
+            columns.push(cast(column, &dictionary_string_type())?);
+            encoded = true;
+        } else {
+            fields.push(field.clone());
+            columns.push(column.clone());
+        }
+    }
+
+    if !encoded {
+        return Ok(batch);
+    }
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+}
+
+fn is_low_cardinality(column: &ArrayRef, max_distinct: usize) -> bool {
+    let strings = match column.as_any().downcast_ref::<StringArray>() {
+        Some(strings) => strings,
+        None => return false,
+    };
+
+    let mut distinct = HashSet::new();
+    for value in strings.iter().flatten() {
+        if distinct.insert(value) && distinct.len() > max_distinct {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Batches of the stream have dictionary columns, while the schema keeps the plain types
+pub struct DictionaryEncodingStream {
+    input: SendableRecordBatchStream,
+    max_ratio: f64,
+}
+
+impl DictionaryEncodingStream {
+    pub fn new(input: SendableRecordBatchStream, max_ratio: f64) -> Self {
+        Self { input, max_ratio }
+    }
+}
+
+impl Stream for DictionaryEncodingStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let max_ratio = self.max_ratio;
+        self.input.poll_next_unpin(cx).map(|batch| {
+            batch.map(|batch| batch.and_then(|batch| encode_dictionaries(batch, max_ratio)))
+        })
+    }
+}
+
+impl RecordBatchStream for DictionaryEncodingStream {
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+}
+
+/// DataFusion operators check types of the input batches against the schema of the plan, so
+/// dictionaries are produced only by a CubeScan whose batches are returned to the client as is
+pub fn apply_dictionary_encoding(
+    plan: Arc<dyn ExecutionPlan>,
+    max_ratio: f64,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    if max_ratio <= 0.0 {
+        return Ok(plan);
+    }
+
+    if let Some(scan) = plan.as_any().downcast_ref::<CubeScanExecutionPlan>() {
+        return Ok(Arc::new(scan.with_dictionary_encoding(max_ratio)));
+    }
+
+    let passes_batches = plan.as_any().is::<CoalescePartitionsExec>()
+        || plan
+            .as_any()
+            .downcast_ref::<RepartitionExec>()
+            .map(|repartition| {
+                matches!(repartition.partitioning(), Partitioning::RoundRobinBatch(_))
+            })
+            .unwrap_or(false);
+    match plan.children().as_slice() {
+        [child] if passes_batches => {
+            let new_child = apply_dictionary_encoding(child.clone(), max_ratio)?;
+            plan.with_new_children(vec![new_child])
+        }
+        _ => Ok(plan),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::dataframe::batch_to_dataframe;
+    use datafusion::arrow::{
+        array::{Array, DictionaryArray, Int32Array},
+        datatypes::Int32Type,
+    };
+
+    #[test]
+    fn test_encode_dictionaries() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("status", DataType::Utf8, true),
+            Field::new("id", DataType::Utf8, false),
+            Field::new("count", DataType::Int32, false),
+        ]));
+        let statuses = ["new", "processing", "shipped"];
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(
+                    (0..100)
+                        .map(|i| if i == 0 { None } else { Some(statuses[i % 3]) })
+                        .collect::<StringArray>(),
+                ),
+                Arc::new(
+                    (0..100)
+                        .map(|i| Some(i.to_string()))
+                        .collect::<StringArray>(),
+                ),
+                Arc::new(Int32Array::from((0..100).collect::<Vec<_>>())),
+            ],
+        )
+        .unwrap();
+
+        let encoded = encode_dictionaries(batch.clone(), 0.5).unwrap();
+        assert_eq!(
+            encoded.schema().field(0).data_type(),
+            &dictionary_string_type()
+        );
+        assert_eq!(encoded.schema().field(1).data_type(), &DataType::Utf8);
+        assert_eq!(encoded.schema().field(2).data_type(), &DataType::Int32);
+
+        let status = encoded
+            .column(0)
+            .as_any()
+            .downcast_ref::<DictionaryArray<Int32Type>>()
+            .unwrap();
+        assert_eq!(status.values().len(), 3);
+        assert!(status.is_null(0));
+
+        // Clients receive the same values
+        let frame = batch_to_dataframe(batch.schema().as_ref(), &vec![batch.clone()]).unwrap();
+        let encoded_frame =
+            batch_to_dataframe(batch.schema().as_ref(), &vec![encoded.clone()]).unwrap();
+        assert_eq!(encoded_frame.print(), frame.print());
+
+        // Small batches are kept as is
+        let small = encode_dictionaries(batch.slice(0, 10), 0.5).unwrap();
+        assert_eq!(small.schema().field(0).data_type(), &DataType::Utf8);
+    }
+}
@@ -0,0 +1,297 @@
+use std::{str::FromStr, sync::Arc};
+
+use datafusion::{
+    error::Result,
+    logical_plan::JoinType,
+    physical_plan::{
+        expressions::Column,
+        hash_join::{HashJoinExec, PartitionMode},
+        projection::ProjectionExec,
+        repartition::RepartitionExec,
+        ExecutionPlan, Partitioning, PhysicalExpr,
+    },
+};
+
+use super::scan::CubeScanExecutionPlan;
+use crate::CubeError;
+
+/// Strategy of joins between CubeScans, `SET cubesql_join_strategy`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinStrategy {
+    /// Broadcast of the smaller side when it's small enough, partitioned hash join otherwise
+    Auto,
+    /// The smaller side is collected and joined with every partition of the other side
+    Broadcast,
+    /// Both sides are repartitioned by the join keys, so every partition builds only a part of
+    /// the hash table
+    Partitioned,
+}
+
+impl Default for JoinStrategy {
+    fn default() -> Self {
+        Self::Auto
+    }
+}
+
+impl FromStr for JoinStrategy {
+    type Err = CubeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "broadcast" => Ok(Self::Broadcast),
+            "partitioned" => Ok(Self::Partitioned),
+            _ => Err(CubeError::user(format!(
+                "Unknown join strategy: {}, expected auto, broadcast or partitioned",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JoinDecision {
+    /// Build side is collected, `swap` moves the right side to the build side
+    Broadcast {
+        swap: bool,
+    },
+    Partitioned,
+    Keep,
+}
+
+/// Selects how hash joins of two CubeScans are executed. DataFusion plans them without
+/// statistics, so a big side can be collected into a single hash table. Rows of a CubeScan are
+/// estimated by the limit of its request.
+#[derive(Debug, Clone)]
+pub struct CubeJoinPlanner {
+    strategy: JoinStrategy,
+    broadcast_max_rows: usize,
+    target_partitions: usize,
+}
+
+impl CubeJoinPlanner {
+    pub fn new(
+        strategy: JoinStrategy,
+        broadcast_max_rows: usize,
+        target_partitions: usize,
+    ) -> Self {
+        Self {
+            strategy,
+            broadcast_max_rows,
+            target_partitions,
+        }
+    }
+
+    pub fn optimize(&self, plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+        let children = plan.children();
+        let plan = if children.is_empty() {
+            plan
+        } else {
+            let new_children = children
+                .iter()
+                .map(|child| self.optimize(child.clone()))
+                .collect::<Result<Vec<_>>>()?;
+            plan.with_new_children(new_children)?
+        };
+
+        match plan.as_any().downcast_ref::<HashJoinExec>() {
+            Some(join) => self
+                .plan_join(join)
+                .map(|new_plan| new_plan.unwrap_or(plan)),
+            None => Ok(plan),
+        }
+    }
+
+    fn plan_join(&self, join: &HashJoinExec) -> Result<Option<Arc<dyn ExecutionPlan>>> {
+        let (left_rows, right_rows) = match (
+            estimated_scan_rows(join.left()),
+            estimated_scan_rows(join.right()),
+        ) {
+            (Some(left_rows), Some(right_rows)) => (left_rows, right_rows),
+            // Not a join of cubes
+            _ => return Ok(None),
+        };
+
+        let decision = self.decide(join.join_type(), left_rows, right_rows);
+        log::trace!(
+            "Join of CubeScans with estimated rows {} and {}: {:?}",
+            left_rows,
+            right_rows,
+            decision
+        );
+
+        match decision {
+            JoinDecision::Broadcast { swap: false } => {
+                if join.partition_mode() == &PartitionMode::CollectLeft {
+                    return Ok(None);
+                }
+
+                Ok(Some(Arc::new(HashJoinExec::try_new(
+                    without_hash_repartition(join.left()),
+                    without_hash_repartition(join.right()),
+                    join.on().to_vec(),
+                    join.join_type(),
+                    PartitionMode::CollectLeft,
+                    join.null_equals_null(),
+                )?)))
+            }
+            JoinDecision::Broadcast { swap: true } => Ok(Some(Self::swap_inner_join(join)?)),
+            JoinDecision::Partitioned => {
+                if join.partition_mode() == &PartitionMode::Partitioned {
+                    return Ok(None);
+                }
+
+                let (left_keys, right_keys): (Vec<_>, Vec<_>) = join
+                    .on()
+                    .iter()
+                    .map(|(left, right)| {
+                        (
+                            Arc::new(left.clone()) as Arc<dyn PhysicalExpr>,
+                            Arc::new(right.clone()) as Arc<dyn PhysicalExpr>,
+                        )
+                    })
+                    .unzip();
+
+                Ok(Some(Arc::new(HashJoinExec::try_new(
+                    Arc::new(RepartitionExec::try_new(
+                        join.left().clone(),
+                        Partitioning::Hash(left_keys, self.target_partitions),
+                    )?),
+                    Arc::new(RepartitionExec::try_new(
+                        join.right().clone(),
+                        Partitioning::Hash(right_keys, self.target_partitions),
+                    )?),
+                    join.on().to_vec(),
+                    join.join_type(),
+                    PartitionMode::Partitioned,
+                    join.null_equals_null(),
+                )?)))
+            }
+            JoinDecision::Keep => Ok(None),
+        }
+    }
+
+    fn decide(&self, join_type: &JoinType, left_rows: usize, right_rows: usize) -> JoinDecision {
+        // Only inner joins are symmetric, the build side of other joins is fixed
+        let swap = right_rows < left_rows && join_type == &JoinType::Inner;
+        let build_rows = if swap { right_rows } else { left_rows };
+
+        match self.strategy {
+            JoinStrategy::Broadcast => JoinDecision::Broadcast { swap },
+            JoinStrategy::Partitioned if self.target_partitions > 1 => JoinDecision::Partitioned,
+            JoinStrategy::Partitioned => JoinDecision::Keep,
+            JoinStrategy::Auto if build_rows <= self.broadcast_max_rows => {
+                JoinDecision::Broadcast { swap }
+            }
+            JoinStrategy::Auto if self.target_partitions > 1 => JoinDecision::Partitioned,
+            JoinStrategy::Auto => JoinDecision::Keep,
+        }
+    }
+
+    /// Builds the hash table from the right side, columns are projected back to the order of
+    /// the original join
+    fn swap_inner_join(join: &HashJoinExec) -> Result<Arc<dyn ExecutionPlan>> {
+        let swapped = Arc::new(HashJoinExec::try_new(
+            without_hash_repartition(join.right()),
+            without_hash_repartition(join.left()),
+            join.on()
+                .iter()
+                .map(|(left, right)| (right.clone(), left.clone()))
+                .collect(),
+            join.join_type(),
+            PartitionMode::CollectLeft,
+            join.null_equals_null(),
+        )?);
+
+        let left_len = join.left().schema().fields().len();
+        let right_len = join.right().schema().fields().len();
+        let schema = join.schema();
+        let exprs = schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                let swapped_index = if index < left_len {
+                    right_len + index
+                } else {
+                    index - left_len
+                };
+
+                (
+                    Arc::new(Column::new(field.name(), swapped_index)) as Arc<dyn PhysicalExpr>,
+                    field.name().to_string(),
+                )
+            })
+            .collect();
+
+        Ok(Arc::new(ProjectionExec::try_new(exprs, swapped)?))
+    }
+}
+
+/// Partitioned joins are planned with both inputs repartitioned by the join keys, which is
+/// useless for a broadcast
+fn without_hash_repartition(plan: &Arc<dyn ExecutionPlan>) -> Arc<dyn ExecutionPlan> {
+    match plan.as_any().downcast_ref::<RepartitionExec>() {
+        Some(repartition) if matches!(repartition.partitioning(), Partitioning::Hash(_, _)) => {
+            repartition.input().clone()
+        }
+        _ => plan.clone(),
+    }
+}
+
+/// Rows of the CubeScan below nodes which don't add rows, None if there is no CubeScan
+fn estimated_scan_rows(plan: &Arc<dyn ExecutionPlan>) -> Option<usize> {
+    if let Some(scan) = plan.as_any().downcast_ref::<CubeScanExecutionPlan>() {
+        return Some(scan.estimated_rows());
+    }
+
+    match plan.children().as_slice() {
+        [child] => estimated_scan_rows(child),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cube_join_decision() {
+        let planner = CubeJoinPlanner::new(JoinStrategy::Auto, 1000, 4);
+        assert_eq!(
+            planner.decide(&JoinType::Inner, 100, 50000),
+            JoinDecision::Broadcast { swap: false }
+        );
+        assert_eq!(
+            planner.decide(&JoinType::Inner, 50000, 100),
+            JoinDecision::Broadcast { swap: true }
+        );
+        // Build side of a left join is fixed
+        assert_eq!(
+            planner.decide(&JoinType::Left, 50000, 100),
+            JoinDecision::Partitioned
+        );
+        assert_eq!(
+            planner.decide(&JoinType::Inner, 50000, 50000),
+            JoinDecision::Partitioned
+        );
+
+        let single_partition = CubeJoinPlanner::new(JoinStrategy::Auto, 1000, 1);
+        assert_eq!(
+            single_partition.decide(&JoinType::Inner, 50000, 50000),
+            JoinDecision::Keep
+        );
+
+        let broadcast = CubeJoinPlanner::new(JoinStrategy::Broadcast, 1000, 4);
+        assert_eq!(
+            broadcast.decide(&JoinType::Inner, 50000, 50000),
+            JoinDecision::Broadcast { swap: false }
+        );
+
+        assert_eq!(
+            "Partitioned".parse::<JoinStrategy>().unwrap(),
+            JoinStrategy::Partitioned
+        );
+        assert!("merge".parse::<JoinStrategy>().is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    any::Any,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        datatypes::{Schema, SchemaRef},
+        error::Result as ArrowResult,
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{
+        memory::MemoryExec, ExecutionPlan, RecordBatchStream, SendableRecordBatchStream,
+    },
+};
+use futures::{Stream, StreamExt};
+
+use crate::{compile::engine::provider::TableName, sql::SessionState};
+
+/// Buffered result of the previous statement of the session, it's scanned by `cube_last_result()`
+#[derive(Debug)]
+pub struct LastResult {
+    pub schema: SchemaRef,
+    pub batches: Vec<RecordBatch>,
+}
+
+fn batch_size(batch: &RecordBatch) -> usize {
+    batch
+        .columns()
+        .iter()
+        .map(|column| column.get_array_memory_size())
+        .sum()
+}
+
+/// Collects batches of a result until they exceed `max_size` bytes, larger results are not
+/// retained
+#[derive(Debug)]
+pub struct LastResultBuffer {
+    max_size: usize,
+    size: usize,
+    batches: Option<Vec<RecordBatch>>,
+}
+
+impl LastResultBuffer {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            size: 0,
+            batches: if max_size > 0 { Some(vec![]) } else { None },
+        }
+    }
+
+    pub fn push(&mut self, batch: &RecordBatch) {
+        if let Some(batches) = &mut self.batches {
+            self.size += batch_size(batch);
+            if self.size > self.max_size {
+                self.batches = None;
+            } else {
+                batches.push(batch.clone());
+            }
+        }
+    }
+
+    pub fn finish(self, schema: SchemaRef) -> Option<Arc<LastResult>> {
+        self.batches
+            .map(|batches| Arc::new(LastResult { schema, batches }))
+    }
+}
+
+/// Passes batches of the result to the client as is, the result is retained by the session when
+/// the stream is finished. A failed or abandoned statement keeps the previous result.
+pub struct LastResultStream {
+    input: SendableRecordBatchStream,
+    buffer: Option<LastResultBuffer>,
+    session_state: Arc<SessionState>,
+}
+
+impl LastResultStream {
+    pub fn new(
+        input: SendableRecordBatchStream,
+        session_state: Arc<SessionState>,
+        max_size: usize,
+    ) -> Self {
+        Self {
+            input,
+            buffer: Some(LastResultBuffer::new(max_size)),
+            session_state,
+        }
+    }
+}
+
+impl Stream for LastResultStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.input.poll_next_unpin(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(batch))) => {
+                if let Some(buffer) = &mut self.buffer {
+                    buffer.push(batch);
+                }
+            }
+            Poll::Ready(Some(Err(_))) => self.buffer = None,
+            Poll::Ready(None) => {
+                if let Some(buffer) = self.buffer.take() {
+                    let schema = self.input.schema();
+                    self.session_state.set_last_result(buffer.finish(schema));
+                }
+            }
+            Poll::Pending => {}
+        }
+
+        poll
+    }
+}
+
+impl RecordBatchStream for LastResultStream {
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+}
+
+/// `cube_last_result()`, the result of the previous statement is captured while planning
+pub struct LastResultProvider {
+    result: Option<Arc<LastResult>>,
+}
+
+impl LastResultProvider {
+    pub fn new(result: Option<Arc<LastResult>>) -> Self {
+        Self { result }
+    }
+}
+
+impl TableName for LastResultProvider {
+    fn table_name(&self) -> &str {
+        "cube_last_result"
+    }
+}
+
+#[async_trait]
+impl TableProvider for LastResultProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    fn schema(&self) -> SchemaRef {
+        match &self.result {
+            Some(result) => result.schema.clone(),
+            None => Arc::new(Schema::empty()),
+        }
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let result = self.result.as_ref().ok_or_else(|| {
+            DataFusionError::Execution(
+                "cube_last_result() has no result to scan: CUBESQL_LAST_RESULT_MAX_SIZE is not set, or the previous statement didn't return rows or its result exceeded it".to_string(),
+            )
+        })?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[result.batches.clone()],
+            result.schema.clone(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::{
+        array::Int64Array,
+        datatypes::{DataType, Field},
+    };
+
+    #[test]
+    fn test_last_result_buffer() {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int64Array::from((0..100).collect::<Vec<_>>()))],
+        )
+        .unwrap();
+
+        let mut buffer = LastResultBuffer::new(1024 * 1024);
+        buffer.push(&batch);
+        buffer.push(&batch);
+        let result = buffer.finish(schema.clone()).unwrap();
+        assert_eq!(result.batches.len(), 2);
+
+        // Results which exceed the retention size are dropped
+        let mut buffer = LastResultBuffer::new(batch_size(&batch) + 1);
+        buffer.push(&batch);
+        buffer.push(&batch);
+        assert!(buffer.finish(schema.clone()).is_none());
+
+        // Disabled
+        let mut buffer = LastResultBuffer::new(0);
+        buffer.push(&batch);
+        assert!(buffer.finish(schema).is_none());
+    }
+}
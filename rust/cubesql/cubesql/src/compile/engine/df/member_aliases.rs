@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use datafusion::logical_plan::Column;
+
+use super::scan::MemberField;
+
+// Shorter prefixes of a key can't be told apart from an unrelated column
+const MIN_TRUNCATED_ALIAS_LENGTH: usize = 16;
+
+/// Aliases of output columns, produced while SQL is generated for a wrapped plan. BI tools quote
+/// long column names and the alias of a column in the generated SQL doesn't always match the
+/// field of the planned schema, e.g. by case or quotes.
+#[derive(Debug, Clone, Default)]
+pub struct MemberAliasRegistry {
+    aliases: HashMap<String, String>,
+}
+
+impl MemberAliasRegistry {
+    pub fn new(column_remapping: Option<&HashMap<Column, Column>>) -> Self {
+        let aliases = column_remapping
+            .into_iter()
+            .flatten()
+            .map(|(column, alias)| (column.name.clone(), alias.name.clone()))
+            .collect();
+
+        Self { aliases }
+    }
+
+    /// Member which is returned by Cube for the output column
+    pub fn member_field(&self, column: &str) -> MemberField {
+        let alias = self.aliases.get(column).or_else(|| {
+            let unquoted = unquote(column);
+            self.aliases
+                .iter()
+                .find(|(original, _)| unquote(original).eq_ignore_ascii_case(unquoted))
+                .map(|(_, alias)| alias)
+        });
+
+        MemberField::Member(
+            alias
+                .map(|alias| alias.as_str())
+                .unwrap_or(column)
+                .to_string(),
+        )
+    }
+}
+
+/// Members which are missing in the response are matched to the keys which were returned by the
+/// data source instead: case folded (Snowflake, Oracle) or truncated (Postgres truncates
+/// identifiers to 63 bytes). Only an unambiguous key is used, otherwise the member is kept as is.
+pub fn match_response_keys(member_fields: &[MemberField], keys: &[String]) -> Vec<MemberField> {
+    member_fields
+        .iter()
+        .map(|field| match field {
+            MemberField::Member(member) if !keys.contains(member) => {
+                let candidates = keys
+                    .iter()
+                    .filter(|key| {
+                        key.eq_ignore_ascii_case(member)
+                            || (key.len() >= MIN_TRUNCATED_ALIAS_LENGTH
+                                && member.len() > key.len()
+                                && member.is_char_boundary(key.len())
+                                && member[..key.len()].eq_ignore_ascii_case(key))
+                    })
+                    .collect::<Vec<_>>();
+
+                match candidates.as_slice() {
+                    [key] => MemberField::Member(key.to_string()),
+                    _ => field.clone(),
+                }
+            }
+            _ => field.clone(),
+        })
+        .collect()
+}
+
+fn unquote(name: &str) -> &str {
+    ['"', '`']
+        .iter()
+        .find_map(|quote| {
+            name.strip_prefix(*quote)
+                .and_then(|name| name.strip_suffix(*quote))
+        })
+        .unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::scalar::ScalarValue;
+
+    fn member(name: &str) -> MemberField {
+        MemberField::Member(name.to_string())
+    }
+
+    #[test]
+    fn test_member_alias_registry() {
+        // Tableau: SUM("KibanaSampleDataEcommerce"."count") AS "sum:count:ok"
+        let remapping = HashMap::from([
+            (
+                Column::from_name("sum:count:ok"),
+                Column::from_name("sum_count_ok_"),
+            ),
+            (
+                Column::from_name("Customer Gender (group)"),
+                Column::from_name("customer_gender"),
+            ),
+        ]);
+        let registry = MemberAliasRegistry::new(Some(&remapping));
+
+        assert_eq!(
+            registry.member_field("sum:count:ok"),
+            member("sum_count_ok_")
+        );
+        assert_eq!(
+            registry.member_field("\"customer gender (group)\""),
+            member("customer_gender")
+        );
+        assert_eq!(registry.member_field("avg_price"), member("avg_price"));
+        assert_eq!(
+            MemberAliasRegistry::new(None).member_field("c1"),
+            member("c1")
+        );
+    }
+
+    #[test]
+    fn test_match_response_keys() {
+        let member_fields = vec![
+            member("KibanaSampleDataEcommerce.customer_gender"),
+            // Tableau alias of a calculation, Postgres returns it truncated to 63 bytes
+            member("calculation_1234567890123456789_lower_customer_gender_of_the_order"),
+            // Power BI alias, Snowflake returns it in upper case
+            member("c1"),
+            member("KibanaSampleDataEcommerce.missing"),
+            MemberField::Literal(ScalarValue::Int64(Some(1))),
+        ];
+        let keys = vec![
+            "KibanaSampleDataEcommerce.customer_gender".to_string(),
+            "calculation_1234567890123456789_lower_customer_gender_of_the_o".to_string(),
+            "C1".to_string(),
+        ];
+
+        assert_eq!(
+            match_response_keys(&member_fields, &keys),
+            vec![
+                member("KibanaSampleDataEcommerce.customer_gender"),
+                member("calculation_1234567890123456789_lower_customer_gender_of_the_o"),
+                member("C1"),
+                member("KibanaSampleDataEcommerce.missing"),
+                MemberField::Literal(ScalarValue::Int64(Some(1))),
+            ]
+        );
+
+        // Ambiguous prefix is not matched
+        let keys = vec![
+            "calculation_1234".to_string(),
+            "CALCULATION_1234".to_string(),
+        ];
+        assert_eq!(
+            match_response_keys(&[member("calculation_1234567")], &keys),
+            vec![member("calculation_1234567")]
+        );
+    }
+}
@@ -1,6 +1,18 @@
 pub mod coerce;
+pub mod collation;
 pub mod columar;
+pub mod dictionary;
+pub mod join_strategy;
+pub mod last_result;
+pub mod member_aliases;
 pub mod optimizers;
 pub mod planner;
+pub mod result_processor;
+pub mod runtime;
+pub mod scalar_subquery;
 pub mod scan;
+pub mod scan_schemas;
+pub mod set_operations;
+pub mod shared_scan;
+pub mod transform_pool;
 pub mod wrapper;
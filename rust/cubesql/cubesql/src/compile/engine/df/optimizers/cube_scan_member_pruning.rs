@@ -0,0 +1,399 @@
+use std::{collections::HashSet, sync::Arc};
+
+use datafusion::{
+    error::Result,
+    logical_plan::{
+        plan::{Aggregate, Extension, Limit, Projection, Sort},
+        Column, DFField, DFSchema, Filter, LogicalPlan,
+    },
+    optimizer::{
+        optimizer::{OptimizerConfig, OptimizerRule},
+        utils::{exprlist_to_columns, from_plan},
+    },
+};
+
+use crate::compile::engine::df::scan::{CubeScanNode, MemberField};
+
+/// Cube Scan Member Pruning optimizer rule removes members of CubeScans which are not
+/// consumed by the nodes above them, so the load request doesn't query them.
+/// Only measures (and dimensions of ungrouped queries) are pruned, because dropping
+/// a dimension changes the granularity of a grouped query.
+/// This rule is applied to the plan after rewriting, when CubeScans are already in place.
+#[derive(Default)]
+pub struct CubeScanMemberPruning {}
+
+impl CubeScanMemberPruning {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl OptimizerRule for CubeScanMemberPruning {
+    fn optimize(
+        &self,
+        plan: &LogicalPlan,
+        optimizer_config: &OptimizerConfig,
+    ) -> Result<LogicalPlan> {
+        member_pruning(self, plan, None, optimizer_config)
+    }
+
+    fn name(&self) -> &str {
+        "__cube__cube_scan_member_pruning"
+    }
+}
+
+/// Recursively optimizes plan, collecting the columns required by the parent nodes.
+/// `None` means every column of the input is required.
+fn member_pruning(
+    optimizer: &CubeScanMemberPruning,
+    plan: &LogicalPlan,
+    required: Option<HashSet<Column>>,
+    optimizer_config: &OptimizerConfig,
+) -> Result<LogicalPlan> {
+    match plan {
+        LogicalPlan::Projection(Projection {
+            expr,
+            input,
+            schema,
+            alias,
+        }) => {
+            let mut columns = HashSet::new();
+            exprlist_to_columns(expr, &mut columns)?;
+
+            Ok(LogicalPlan::Projection(Projection {
+                expr: expr.clone(),
+                input: Arc::new(member_pruning(
+                    optimizer,
+                    input,
+                    Some(columns),
+                    optimizer_config,
+                )?),
+                schema: schema.clone(),
+                alias: alias.clone(),
+            }))
+        }
+        LogicalPlan::Filter(Filter { predicate, input }) => {
+            let required = with_expr_columns(required, &[predicate.clone()])?;
+
+            Ok(LogicalPlan::Filter(Filter {
+                predicate: predicate.clone(),
+                input: Arc::new(member_pruning(
+                    optimizer,
+                    input,
+                    required,
+                    optimizer_config,
+                )?),
+            }))
+        }
+        LogicalPlan::Sort(Sort { expr, input }) => {
+            let required = with_expr_columns(required, expr)?;
+
+            Ok(LogicalPlan::Sort(Sort {
+                expr: expr.clone(),
+                input: Arc::new(member_pruning(
+                    optimizer,
+                    input,
+                    required,
+                    optimizer_config,
+                )?),
+            }))
+        }
+        LogicalPlan::Limit(Limit { skip, fetch, input }) => Ok(LogicalPlan::Limit(Limit {
+            skip: *skip,
+            fetch: *fetch,
+            input: Arc::new(member_pruning(
+                optimizer,
+                input,
+                required,
+                optimizer_config,
+            )?),
+        })),
+        LogicalPlan::Aggregate(Aggregate {
+            input,
+            group_expr,
+            aggr_expr,
+            schema,
+        }) => {
+            let mut columns = HashSet::new();
+            exprlist_to_columns(group_expr, &mut columns)?;
+            exprlist_to_columns(aggr_expr, &mut columns)?;
+
+            Ok(LogicalPlan::Aggregate(Aggregate {
+                input: Arc::new(member_pruning(
+                    optimizer,
+                    input,
+                    Some(columns),
+                    optimizer_config,
+                )?),
+                group_expr: group_expr.clone(),
+                aggr_expr: aggr_expr.clone(),
+                schema: schema.clone(),
+            }))
+        }
+        LogicalPlan::Extension(Extension { node }) => {
+            if let Some(cube_scan) = node.as_any().downcast_ref::<CubeScanNode>() {
+                if let Some(required) = required {
+                    return Ok(LogicalPlan::Extension(Extension {
+                        node: Arc::new(prune_cube_scan(cube_scan, &required)?),
+                    }));
+                }
+            }
+
+            Ok(plan.clone())
+        }
+        other => {
+            // Columns used by other nodes are not tracked, so everything is required below them.
+            let inputs = other
+                .inputs()
+                .into_iter()
+                .map(|input| member_pruning(optimizer, input, None, optimizer_config))
+                .collect::<Result<Vec<_>>>()?;
+
+            from_plan(other, &other.expressions(), &inputs)
+        }
+    }
+}
+
+fn with_expr_columns(
+    required: Option<HashSet<Column>>,
+    expr: &[datafusion::logical_plan::Expr],
+) -> Result<Option<HashSet<Column>>> {
+    match required {
+        Some(mut columns) => {
+            exprlist_to_columns(expr, &mut columns)?;
+            Ok(Some(columns))
+        }
+        None => Ok(None),
+    }
+}
+
+fn is_field_required(field: &DFField, required: &HashSet<Column>) -> bool {
+    required.iter().any(|column| {
+        column.name == *field.name()
+            && match (&column.relation, field.qualifier()) {
+                (Some(relation), Some(qualifier)) => relation == qualifier,
+                _ => true,
+            }
+    })
+}
+
+fn prune_cube_scan(node: &CubeScanNode, required: &HashSet<Column>) -> Result<CubeScanNode> {
+    let request = &node.request;
+    let ordered = request
+        .order
+        .iter()
+        .flatten()
+        .filter_map(|o| o.first())
+        .collect::<HashSet<_>>();
+    let is_prunable_member = |member: &String| {
+        if ordered.contains(member) {
+            return false;
+        }
+
+        request.measures.iter().flatten().any(|m| m == member)
+            || (request.ungrouped == Some(true)
+                && request.dimensions.iter().flatten().any(|d| d == member))
+    };
+
+    let mut fields = Vec::with_capacity(node.member_fields.len());
+    let mut member_fields = Vec::with_capacity(node.member_fields.len());
+    for (field, member_field) in node.schema.fields().iter().zip(node.member_fields.iter()) {
+        let keep = is_field_required(field, required)
+            || match member_field {
+                MemberField::Member(member) => !is_prunable_member(member),
+                MemberField::Literal(_) => false,
+            };
+
+        if keep {
+            fields.push(field.clone());
+            member_fields.push(member_field.clone());
+        }
+    }
+
+    if member_fields.len() == node.member_fields.len() {
+        return Ok(node.clone());
+    }
+
+    // The same member can be referenced by multiple fields, keep it while any of them is used
+    let used_members = member_fields
+        .iter()
+        .filter_map(|f| match f {
+            MemberField::Member(member) => Some(member),
+            MemberField::Literal(_) => None,
+        })
+        .collect::<HashSet<_>>();
+    let retain_used = |members: &Option<Vec<String>>| {
+        members.as_ref().map(|members| {
+            members
+                .iter()
+                .filter(|m| !is_prunable_member(*m) || used_members.contains(*m))
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let mut pruned_request = request.clone();
+    pruned_request.measures = retain_used(&request.measures);
+    pruned_request.dimensions = retain_used(&request.dimensions);
+
+    // An empty request would turn into a no-members query with different semantics
+    let has_members = pruned_request.measures.iter().flatten().next().is_some()
+        || pruned_request.dimensions.iter().flatten().next().is_some()
+        || pruned_request
+            .time_dimensions
+            .iter()
+            .flatten()
+            .any(|td| td.granularity.is_some());
+    if !has_members {
+        return Ok(node.clone());
+    }
+
+    Ok(CubeScanNode {
+        schema: Arc::new(DFSchema::new_with_metadata(
+            fields,
+            node.schema.metadata().clone(),
+        )?),
+        member_fields,
+        request: pruned_request,
+        ..node.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile::engine::df::scan::CubeScanOptions, sql::HttpAuthContext};
+    use cubeclient::models::V1LoadRequestQuery;
+    use datafusion::{
+        arrow::datatypes::DataType,
+        logical_plan::{col, lit, LogicalPlanBuilder},
+    };
+
+    fn cube_scan(member_fields: Vec<&str>, request: V1LoadRequestQuery) -> LogicalPlan {
+        let fields = member_fields
+            .iter()
+            .map(|m| {
+                DFField::new(
+                    Some("t"),
+                    m.split('.').last().unwrap(),
+                    DataType::Float64,
+                    true,
+                )
+            })
+            .collect();
+
+        LogicalPlan::Extension(Extension {
+            node: Arc::new(CubeScanNode::new(
+                Arc::new(DFSchema::new_with_metadata(fields, Default::default()).unwrap()),
+                member_fields
+                    .iter()
+                    .map(|m| MemberField::Member(m.to_string()))
+                    .collect(),
+                request,
+                Arc::new(HttpAuthContext {
+                    access_token: "access_token".to_string(),
+                    base_path: "base_path".to_string(),
+                }),
+                CubeScanOptions {
+                    change_user: None,
+                    max_records: None,
+                },
+                vec!["Orders".to_string()],
+                None,
+            )),
+        })
+    }
+
+    fn optimized_scan(plan: &LogicalPlan) -> CubeScanNode {
+        let optimized = CubeScanMemberPruning::new()
+            .optimize(plan, &OptimizerConfig::new())
+            .expect("failed to optimize plan");
+
+        let mut plan = &optimized;
+        loop {
+            match plan {
+                LogicalPlan::Extension(Extension { node }) => {
+                    return node
+                        .as_any()
+                        .downcast_ref::<CubeScanNode>()
+                        .unwrap()
+                        .clone()
+                }
+                other => plan = other.inputs()[0],
+            }
+        }
+    }
+
+    #[test]
+    fn test_prune_unused_measures() -> Result<()> {
+        let request = V1LoadRequestQuery {
+            measures: Some(vec![
+                "Orders.count".to_string(),
+                "Orders.amount".to_string(),
+                "Orders.avg".to_string(),
+            ]),
+            dimensions: Some(vec!["Orders.status".to_string()]),
+            order: Some(vec![vec!["Orders.avg".to_string(), "desc".to_string()]]),
+            ..V1LoadRequestQuery::new()
+        };
+        let plan = LogicalPlanBuilder::from(cube_scan(
+            vec![
+                "Orders.status",
+                "Orders.count",
+                "Orders.amount",
+                "Orders.avg",
+            ],
+            request,
+        ))
+        .project(vec![col("t.count")])?
+        .build()?;
+
+        let scan = optimized_scan(&plan);
+        // Dimension defines granularity and ordered measure defines order of the rows
+        assert_eq!(
+            scan.request.measures,
+            Some(vec!["Orders.count".to_string(), "Orders.avg".to_string()])
+        );
+        assert_eq!(
+            scan.request.dimensions,
+            Some(vec!["Orders.status".to_string()])
+        );
+        assert_eq!(scan.member_fields.len(), 3);
+        assert_eq!(scan.schema.fields().len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_ungrouped_dimensions() -> Result<()> {
+        let request = V1LoadRequestQuery {
+            dimensions: Some(vec!["Orders.status".to_string(), "Orders.city".to_string()]),
+            ungrouped: Some(true),
+            ..V1LoadRequestQuery::new()
+        };
+        let plan = LogicalPlanBuilder::from(cube_scan(
+            vec!["Orders.status", "Orders.city"],
+            request.clone(),
+        ))
+        .filter(col("t.city").eq(lit(1)))?
+        .project(vec![col("t.status")])?
+        .build()?;
+
+        let scan = optimized_scan(&plan);
+        assert_eq!(scan.request, request);
+
+        let plan =
+            LogicalPlanBuilder::from(cube_scan(vec!["Orders.status", "Orders.city"], request))
+                .project(vec![col("t.status")])?
+                .build()?;
+
+        let scan = optimized_scan(&plan);
+        assert_eq!(
+            scan.request.dimensions,
+            Some(vec!["Orders.status".to_string()])
+        );
+
+        Ok(())
+    }
+}
@@ -1,9 +1,11 @@
 pub mod utils;
 
+mod cube_scan_member_pruning;
 mod filter_push_down;
 mod limit_push_down;
 mod sort_push_down;
 
+pub use cube_scan_member_pruning::CubeScanMemberPruning;
 pub use filter_push_down::FilterPushDown;
 pub use limit_push_down::LimitPushDown;
 pub use sort_push_down::SortPushDown;
@@ -4,22 +4,95 @@ use async_trait::async_trait;
 use datafusion::{
     error::Result,
     execution::context::{QueryPlanner, SessionState},
-    logical_plan::LogicalPlan,
-    physical_plan::{planner::DefaultPhysicalPlanner, ExecutionPlan, PhysicalPlanner},
+    logical_plan::{plan::Extension, LogicalPlan},
+    physical_plan::{
+        coalesce_batches::CoalesceBatchesExec,
+        coalesce_partitions::CoalescePartitionsExec,
+        expressions::{Column, PhysicalSortExpr},
+        filter::FilterExec,
+        limit::{GlobalLimitExec, LocalLimitExec},
+        planner::DefaultPhysicalPlanner,
+        projection::ProjectionExec,
+        repartition::RepartitionExec,
+        sorts::sort::SortExec,
+        ExecutionPlan, Partitioning, PhysicalPlanner,
+    },
 };
 
-use crate::transport::{LoadRequestMeta, TransportService};
+use crate::{
+    sql::MemberUsageStats,
+    transport::{LoadLimiter, LoadRequestMeta, TransportService},
+};
 
-use super::scan::CubeScanExtensionPlanner;
+use super::{
+    collation::{apply_collation, Collation},
+    dictionary::apply_dictionary_encoding,
+    join_strategy::{CubeJoinPlanner, JoinStrategy},
+    result_processor::ResultProcessors,
+    scalar_subquery::ScalarSubqueryNode,
+    scan::{CubeScanExecutionPlan, CubeScanExtensionPlanner, ResponseDateFormats},
+    scan_schemas::ScanSchemaCache,
+    shared_scan::SharedCubeScans,
+    transform_pool::TransformPool,
+};
 
 pub struct CubeQueryPlanner {
     pub transport: Arc<dyn TransportService>,
     pub meta: LoadRequestMeta,
+    pub member_usage: Arc<MemberUsageStats>,
+    pub load_limiter: Arc<LoadLimiter>,
+    pub transform_pool: Arc<TransformPool>,
+    pub date_formats: Arc<ResponseDateFormats>,
+    pub result_processors: Arc<ResultProcessors>,
+    pub stream_mode: bool,
+    pub shared_scan_max_rows: usize,
+    pub scan_schemas: Option<Arc<ScanSchemaCache>>,
+    pub fetch_size: Option<usize>,
+    pub join_strategy: JoinStrategy,
+    pub join_broadcast_max_rows: usize,
+    pub collation: Option<Collation>,
+    /// Collation of strings ordered by the data source, ORDER BY is pushed down when it's the same
+    pub source_collation: Option<Collation>,
+    pub dictionary_max_ratio: f64,
 }
 
 impl CubeQueryPlanner {
-    pub fn new(transport: Arc<dyn TransportService>, meta: LoadRequestMeta) -> Self {
-        Self { transport, meta }
+    pub fn new(
+        transport: Arc<dyn TransportService>,
+        meta: LoadRequestMeta,
+        member_usage: Arc<MemberUsageStats>,
+        load_limiter: Arc<LoadLimiter>,
+        transform_pool: Arc<TransformPool>,
+        date_formats: Arc<ResponseDateFormats>,
+        result_processors: Arc<ResultProcessors>,
+        stream_mode: bool,
+        shared_scan_max_rows: usize,
+        scan_schemas: Option<Arc<ScanSchemaCache>>,
+        fetch_size: Option<usize>,
+        join_strategy: JoinStrategy,
+        join_broadcast_max_rows: usize,
+        collation: Option<Collation>,
+        source_collation: Option<Collation>,
+        dictionary_max_ratio: f64,
+    ) -> Self {
+        Self {
+            transport,
+            meta,
+            member_usage,
+            load_limiter,
+            transform_pool,
+            date_formats,
+            result_processors,
+            stream_mode,
+            shared_scan_max_rows,
+            scan_schemas,
+            fetch_size,
+            join_strategy,
+            join_broadcast_max_rows,
+            collation,
+            source_collation,
+            dictionary_max_ratio,
+        }
     }
 }
 
@@ -32,15 +105,186 @@ impl QueryPlanner for CubeQueryPlanner {
         logical_plan: &LogicalPlan,
         session_state: &SessionState,
     ) -> Result<Arc<dyn ExecutionPlan>> {
+        if let LogicalPlan::Extension(Extension { node }) = logical_plan {
+            if let Some(node) = node.as_any().downcast_ref::<ScalarSubqueryNode>() {
+                return node.create_physical_plan().await;
+            }
+        }
+
         let physical_planner = DefaultPhysicalPlanner::with_extension_planners(vec![Arc::new(
             CubeScanExtensionPlanner {
                 transport: self.transport.clone(),
                 meta: self.meta.clone(),
+                member_usage: self.member_usage.clone(),
+                load_limiter: self.load_limiter.clone(),
+                transform_pool: self.transform_pool.clone(),
+                date_formats: self.date_formats.clone(),
+                result_processors: self.result_processors.clone(),
+                stream_mode: self.stream_mode,
+                shared_scans: Arc::new(SharedCubeScans::new(self.shared_scan_max_rows)),
+                scan_schemas: self.scan_schemas.clone(),
+                fetch_size: self.fetch_size,
             },
         )]);
         // Delegate most work of physical planning to the default physical planner
-        physical_planner
+        let plan = physical_planner
             .create_physical_plan(logical_plan, session_state)
-            .await
+            .await?;
+
+        let plan = preserve_cube_scan_ordering(plan)?;
+        let plan = CubeJoinPlanner::new(
+            self.join_strategy,
+            self.join_broadcast_max_rows,
+            session_state.config.target_partitions,
+        )
+        .optimize(plan)?;
+        let plan = ensure_cube_scan_ordering(plan)?;
+
+        let plan = apply_collation(
+            plan,
+            self.collation.as_ref(),
+            self.source_collation.as_ref(),
+            self.meta.warnings(),
+        )?;
+
+        apply_dictionary_encoding(plan, self.dictionary_max_ratio)
+    }
+}
+
+/// Cube returns rows in the order of the load request, but round robin repartitioning, which
+/// is inserted by the physical optimizer above CubeScan, interleaves streamed batches and the
+/// order is lost. Nodes between the scan and the top of the plan (projections, filters) keep the
+/// order of their input, so it's enough to read ordered scans in a single partition. Paged scans
+/// are read in a single partition too, repartitioning polls them eagerly and loads every page.
+fn preserve_cube_scan_ordering(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+    if let Some(repartition) = plan.as_any().downcast_ref::<RepartitionExec>() {
+        if let Partitioning::RoundRobinBatch(_) = repartition.partitioning() {
+            let input = repartition.input();
+            if let Some(scan) = input.as_any().downcast_ref::<CubeScanExecutionPlan>() {
+                if scan.is_ordered() || scan.is_paged() {
+                    return Ok(input.clone());
+                }
+            }
+        }
+    }
+
+    let children = plan.children();
+    if children.is_empty() {
+        return Ok(plan);
+    }
+
+    let new_children = children
+        .iter()
+        .map(|child| preserve_cube_scan_ordering(child.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    if children
+        .iter()
+        .zip(new_children.iter())
+        .all(|(child, new_child)| Arc::ptr_eq(child, new_child))
+    {
+        return Ok(plan);
+    }
+
+    plan.with_new_children(new_children)
+}
+
+/// Rows of an ordered CubeScan which can still be reordered on the way to the top of the plan,
+/// e.g. by a repartitioning which wasn't removed, are sorted by the pushed down ORDER BY again.
+pub fn ensure_cube_scan_ordering(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+    if reorders_rows(&plan) {
+        let sort_exprs = match pushed_sort_exprs(&plan) {
+            Some(sort_exprs) if !sort_exprs.is_empty() => sort_exprs,
+            _ => return Ok(plan),
+        };
+        let input: Arc<dyn ExecutionPlan> = if plan.output_partitioning().partition_count() > 1 {
+            Arc::new(CoalescePartitionsExec::new(plan))
+        } else {
+            plan
+        };
+        return Ok(Arc::new(SortExec::try_new(sort_exprs, input)?));
     }
+
+    if !keeps_order(&plan) {
+        return Ok(plan);
+    }
+
+    match plan.children().as_slice() {
+        [child] => {
+            let new_child = ensure_cube_scan_ordering(child.clone())?;
+            if Arc::ptr_eq(child, &new_child) {
+                Ok(plan)
+            } else {
+                plan.with_new_children(vec![new_child])
+            }
+        }
+        _ => Ok(plan),
+    }
+}
+
+/// Sort expressions of ORDER BY pushed down to a CubeScan which is read by `plan` without
+/// reordering of the columns, expressed over the output of `plan`
+fn pushed_sort_exprs(plan: &Arc<dyn ExecutionPlan>) -> Option<Vec<PhysicalSortExpr>> {
+    if let Some(scan) = plan.as_any().downcast_ref::<CubeScanExecutionPlan>() {
+        return if scan.is_ordered() {
+            Some(scan.request_sort_exprs())
+        } else {
+            None
+        };
+    }
+
+    if !keeps_order(plan) && !reorders_rows(plan) {
+        return None;
+    }
+    let input_sort_exprs = match plan.children().as_slice() {
+        [child] => pushed_sort_exprs(child)?,
+        _ => return None,
+    };
+
+    let projection = match plan.as_any().downcast_ref::<ProjectionExec>() {
+        Some(projection) => projection,
+        None => return Some(input_sort_exprs),
+    };
+    // Only columns which are passed by the projection as is can be sorted by
+    let schema = projection.schema();
+    Some(
+        input_sort_exprs
+            .into_iter()
+            .map_while(|sort_expr| {
+                let column = sort_expr.expr.as_any().downcast_ref::<Column>()?;
+                let index = projection.expr().iter().position(|(expr, _)| {
+                    expr.as_any()
+                        .downcast_ref::<Column>()
+                        .map(|c| c.index() == column.index())
+                        .unwrap_or(false)
+                })?;
+                Some(PhysicalSortExpr {
+                    expr: Arc::new(Column::new(schema.field(index).name(), index)),
+                    options: sort_expr.options,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Operators which pass rows of their only input in the same order
+fn keeps_order(plan: &Arc<dyn ExecutionPlan>) -> bool {
+    let plan = plan.as_any();
+    plan.is::<ProjectionExec>()
+        || plan.is::<FilterExec>()
+        || plan.is::<CoalesceBatchesExec>()
+        || plan.is::<GlobalLimitExec>()
+        || plan.is::<LocalLimitExec>()
+}
+
+/// Operators which interleave rows of their input partitions
+fn reorders_rows(plan: &Arc<dyn ExecutionPlan>) -> bool {
+    if plan.as_any().is::<RepartitionExec>() {
+        return true;
+    }
+
+    plan.as_any().is::<CoalescePartitionsExec>()
+        && plan
+            .children()
+            .iter()
+            .any(|child| child.output_partitioning().partition_count() > 1)
 }
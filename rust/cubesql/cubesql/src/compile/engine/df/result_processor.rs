@@ -0,0 +1,400 @@
+use std::{
+    fmt,
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, RwLock as RwLockSync},
+    task::{Context, Poll},
+};
+
+use datafusion::{
+    arrow::{
+        array::{Array, ArrayRef, Float64Array, StringArray},
+        datatypes::{DataType, SchemaRef},
+        error::{ArrowError, Result as ArrowResult},
+        record_batch::RecordBatch,
+    },
+    physical_plan::{RecordBatchStream, SendableRecordBatchStream},
+};
+use futures::{Stream, StreamExt};
+use log::warn;
+
+use super::scan::MemberField;
+use crate::CubeError;
+
+/// Transformation of the rows returned by CubeScan before they are sent to the client, e.g.
+/// masking or formatting of values. Columns are matched to members by `member_fields`, which
+/// are ordered as the columns of the batch. The schema of the batch must be kept.
+pub trait ResultProcessor: fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    fn process(
+        &self,
+        batch: RecordBatch,
+        member_fields: &[MemberField],
+    ) -> Result<RecordBatch, CubeError>;
+}
+
+/// Built-in processors which can be enabled by CUBESQL_RESULT_PROCESSORS
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultProcessorConfig {
+    /// Masks string values of the member, e-mails keep the first character and the domain
+    Mask { member: String },
+    /// Rounds numeric values of the member to the scale
+    Round { member: String, scale: u32 },
+}
+
+impl ResultProcessorConfig {
+    /// CUBESQL_RESULT_PROCESSORS is a comma separated list of `mask:<member>` and
+    /// `round:<member>:<scale>` entries, invalid entries are ignored
+    pub fn from_env() -> Vec<Self> {
+        std::env::var("CUBESQL_RESULT_PROCESSORS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|entry| entry.trim())
+                    .filter(|entry| !entry.is_empty())
+                    .filter_map(|entry| match entry.parse() {
+                        Ok(config) => Some(config),
+                        Err(err) => {
+                            warn!("Ignoring entry of CUBESQL_RESULT_PROCESSORS: {}", err);
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn processor(&self) -> Arc<dyn ResultProcessor> {
+        match self {
+            Self::Mask { member } => Arc::new(MaskProcessor {
+                member: member.clone(),
+            }),
+            Self::Round { member, scale } => Arc::new(RoundProcessor {
+                member: member.clone(),
+                scale: *scale,
+            }),
+        }
+    }
+}
+
+impl FromStr for ResultProcessorConfig {
+    type Err = CubeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').map(|part| part.trim()).collect::<Vec<_>>();
+        match parts.as_slice() {
+            [kind, member] if kind.eq_ignore_ascii_case("mask") => Ok(Self::Mask {
+                member: member.to_string(),
+            }),
+            [kind, member, scale] if kind.eq_ignore_ascii_case("round") => Ok(Self::Round {
+                member: member.to_string(),
+                scale: scale.parse().map_err(|_| {
+                    CubeError::user(format!("Invalid scale of the result processor: {}", s))
+                })?,
+            }),
+            _ => Err(CubeError::user(format!(
+                "Unknown result processor: {}, expected mask:<member> or round:<member>:<scale>",
+                s
+            ))),
+        }
+    }
+}
+
+/// Processors which are applied to the results of every CubeScan, in the order of registration.
+/// Embedding code registers own processors through `ServerManager::result_processors`.
+#[derive(Debug, Default)]
+pub struct ResultProcessors {
+    processors: RwLockSync<Vec<Arc<dyn ResultProcessor>>>,
+}
+
+impl ResultProcessors {
+    pub fn new(configs: &[ResultProcessorConfig]) -> Self {
+        Self {
+            processors: RwLockSync::new(configs.iter().map(|config| config.processor()).collect()),
+        }
+    }
+
+    pub fn register(&self, processor: Arc<dyn ResultProcessor>) {
+        self.processors
+            .write()
+            .expect("failed to unlock result processors for writing")
+            .push(processor);
+    }
+
+    fn processors(&self) -> Vec<Arc<dyn ResultProcessor>> {
+        self.processors
+            .read()
+            .expect("failed to unlock result processors for reading")
+            .clone()
+    }
+
+    pub fn process(
+        &self,
+        mut batch: RecordBatch,
+        member_fields: &[MemberField],
+    ) -> Result<RecordBatch, CubeError> {
+        for processor in self.processors() {
+            let schema = batch.schema();
+            batch = processor.process(batch, member_fields)?;
+            if batch.schema() != schema {
+                return Err(CubeError::internal(format!(
+                    "Result processor {} changed the schema of the result",
+                    processor.name()
+                )));
+            }
+        }
+
+        Ok(batch)
+    }
+
+    /// Applies processors to the batches of the stream, the stream is returned as is when there
+    /// are no processors
+    pub fn process_stream(
+        self: &Arc<Self>,
+        stream: SendableRecordBatchStream,
+        member_fields: Arc<Vec<MemberField>>,
+    ) -> SendableRecordBatchStream {
+        if self.processors().is_empty() {
+            return stream;
+        }
+
+        Box::pin(ResultProcessorStream {
+            schema: stream.schema(),
+            input: stream,
+            processors: self.clone(),
+            member_fields,
+        })
+    }
+}
+
+struct ResultProcessorStream {
+    schema: SchemaRef,
+    input: SendableRecordBatchStream,
+    processors: Arc<ResultProcessors>,
+    member_fields: Arc<Vec<MemberField>>,
+}
+
+impl Stream for ResultProcessorStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.input.poll_next_unpin(cx).map(|batch| {
+            batch.map(|batch| {
+                batch.and_then(|batch| {
+                    self.processors
+                        .process(batch, &self.member_fields)
+                        .map_err(|err| ArrowError::ComputeError(err.message))
+                })
+            })
+        })
+    }
+}
+
+impl RecordBatchStream for ResultProcessorStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Replaces columns of the member by the result of the function
+fn map_member_columns(
+    batch: RecordBatch,
+    member_fields: &[MemberField],
+    member: &str,
+    fun: impl Fn(&ArrayRef) -> Option<ArrayRef>,
+) -> Result<RecordBatch, CubeError> {
+    let mut changed = false;
+    let columns = batch
+        .columns()
+        .iter()
+        .enumerate()
+        .map(|(index, column)| match member_fields.get(index) {
+            Some(MemberField::Member(name)) if name == member => match fun(column) {
+                Some(column) => {
+                    changed = true;
+                    column
+                }
+                None => column.clone(),
+            },
+            _ => column.clone(),
+        })
+        .collect::<Vec<_>>();
+    if !changed {
+        return Ok(batch);
+    }
+
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+#[derive(Debug)]
+pub struct MaskProcessor {
+    member: String,
+}
+
+impl MaskProcessor {
+    fn mask(value: &str) -> String {
+        match value.split_once('@') {
+            Some((local, domain)) => format!(
+                "{}***@{}",
+                local.chars().next().map(String::from).unwrap_or_default(),
+                domain
+            ),
+            None => "***".to_string(),
+        }
+    }
+}
+
+impl ResultProcessor for MaskProcessor {
+    fn name(&self) -> &str {
+        "mask"
+    }
+
+    fn process(
+        &self,
+        batch: RecordBatch,
+        member_fields: &[MemberField],
+    ) -> Result<RecordBatch, CubeError> {
+        map_member_columns(batch, member_fields, &self.member, |column| {
+            let strings = column.as_any().downcast_ref::<StringArray>()?;
+
+            Some(Arc::new(
+                strings
+                    .iter()
+                    .map(|value| value.map(Self::mask))
+                    .collect::<StringArray>(),
+            ))
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RoundProcessor {
+    member: String,
+    scale: u32,
+}
+
+impl ResultProcessor for RoundProcessor {
+    fn name(&self) -> &str {
+        "round"
+    }
+
+    fn process(
+        &self,
+        batch: RecordBatch,
+        member_fields: &[MemberField],
+    ) -> Result<RecordBatch, CubeError> {
+        let factor = 10_f64.powi(self.scale as i32);
+
+        map_member_columns(batch, member_fields, &self.member, |column| {
+            if column.data_type() != &DataType::Float64 {
+                return None;
+            }
+            let numbers = column.as_any().downcast_ref::<Float64Array>()?;
+
+            Some(Arc::new(
+                numbers
+                    .iter()
+                    .map(|value| value.map(|value| (value * factor).round() / factor))
+                    .collect::<Float64Array>(),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::datatypes::{Field, Schema};
+
+    fn batch() -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("email", DataType::Utf8, true),
+                Field::new("amount", DataType::Float64, true),
+                Field::new("name", DataType::Utf8, true),
+            ])),
+            vec![
+                Arc::new(StringArray::from(vec![
+                    Some("john@example.com"),
+                    Some("unknown"),
+                    None,
+                ])),
+                Arc::new(Float64Array::from(vec![Some(1.2345), Some(2.0), None])),
+                Arc::new(StringArray::from(vec![Some("John"), Some("Jane"), None])),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_result_processors() {
+        let member_fields = vec![
+            MemberField::Member("Users.email".to_string()),
+            MemberField::Member("Orders.amount".to_string()),
+            MemberField::Member("Users.name".to_string()),
+        ];
+        let configs = "mask:Users.email, round:Orders.amount:2"
+            .split(',')
+            .map(|entry| entry.trim().parse())
+            .collect::<Result<Vec<ResultProcessorConfig>, _>>()
+            .unwrap();
+        let processors = ResultProcessors::new(&configs);
+
+        let batch = processors.process(batch(), &member_fields).unwrap();
+        let emails = batch.columns()[0]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            emails.iter().collect::<Vec<_>>(),
+            vec![Some("j***@example.com"), Some("***"), None]
+        );
+        let amounts = batch.columns()[1]
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(
+            amounts.iter().collect::<Vec<_>>(),
+            vec![Some(1.23), Some(2.0), None]
+        );
+        let names = batch.columns()[2]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            names.iter().collect::<Vec<_>>(),
+            vec![Some("John"), Some("Jane"), None]
+        );
+
+        assert!("round:Orders.amount"
+            .parse::<ResultProcessorConfig>()
+            .is_err());
+        assert!("hash:Users.email".parse::<ResultProcessorConfig>().is_err());
+    }
+
+    #[derive(Debug)]
+    struct DroppingProcessor;
+
+    impl ResultProcessor for DroppingProcessor {
+        fn name(&self) -> &str {
+            "dropping"
+        }
+
+        fn process(
+            &self,
+            batch: RecordBatch,
+            _member_fields: &[MemberField],
+        ) -> Result<RecordBatch, CubeError> {
+            Ok(batch.project(&[0])?)
+        }
+    }
+
+    #[test]
+    fn test_result_processor_schema_is_kept() {
+        let processors = ResultProcessors::default();
+        processors.register(Arc::new(DroppingProcessor));
+
+        assert!(processors.process(batch(), &[]).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::{path::PathBuf, sync::Arc};
+
+use datafusion::execution::{
+    disk_manager::DiskManagerConfig,
+    memory_manager::MemoryManagerConfig,
+    runtime_env::{RuntimeConfig, RuntimeEnv},
+};
+use log::warn;
+
+use crate::CubeError;
+
+/// Runtime of plans which are executed by DataFusion, it's shared by all queries of the server.
+/// Sorts which don't fit into `memory_limit` bytes, e.g. ORDER BY over a large stream of an
+/// ungrouped CubeScan, spill sorted runs to `spill_path` instead of buffering the whole input.
+pub fn create_runtime_env(
+    memory_limit: usize,
+    spill_path: Option<&str>,
+) -> Result<Arc<RuntimeEnv>, CubeError> {
+    let mut config = RuntimeConfig::new();
+    if memory_limit > 0 {
+        config = config.with_memory_manager(MemoryManagerConfig::try_new_limit(memory_limit, 1.0)?);
+    }
+    if let Some(spill_path) = spill_path {
+        config = config.with_disk_manager(DiskManagerConfig::NewSpecified(vec![PathBuf::from(
+            spill_path,
+        )]));
+    }
+
+    Ok(Arc::new(RuntimeEnv::new(config)?))
+}
+
+/// Falls back to the default runtime when the configured one can't be created, e.g. the spill
+/// path is not writable
+pub fn create_runtime_env_or_default(
+    memory_limit: usize,
+    spill_path: Option<&str>,
+) -> Arc<RuntimeEnv> {
+    create_runtime_env(memory_limit, spill_path).unwrap_or_else(|err| {
+        warn!(
+            "Unable to create runtime with sort spilling (CUBESQL_SORT_MEMORY_LIMIT_MB, CUBESQL_SORT_SPILL_PATH), sorts won't spill: {}",
+            err
+        );
+
+        Arc::new(RuntimeEnv::new(RuntimeConfig::new()).expect("Unable to create default runtime"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::{
+        arrow::{
+            array::{Array, Int64Array},
+            datatypes::{DataType, Field, Schema},
+            record_batch::RecordBatch,
+        },
+        execution::context::TaskContext,
+        physical_plan::{
+            collect, expressions::col, memory::MemoryExec, sorts::sort::SortExec, ExecutionPlan,
+            PhysicalSortExpr,
+        },
+    };
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_sort_spill() {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int64, false)]));
+        let batches = (0..16)
+            .map(|i| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int64Array::from(
+                        (0..1024)
+                            .map(|n| (n * 7919 + i) % 16384)
+                            .collect::<Vec<i64>>(),
+                    ))],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let spill_path = std::env::temp_dir();
+        let runtime = create_runtime_env(64 * 1024, spill_path.to_str()).unwrap();
+        let task = Arc::new(TaskContext::new(
+            "test".to_string(),
+            "session".to_string(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            runtime,
+        ));
+
+        let sort = Arc::new(
+            SortExec::try_new(
+                vec![PhysicalSortExpr {
+                    expr: col("n", &schema).unwrap(),
+                    options: Default::default(),
+                }],
+                Arc::new(MemoryExec::try_new(&[batches], schema.clone(), None).unwrap()),
+            )
+            .unwrap(),
+        );
+        let result = collect(sort.clone(), task).await.unwrap();
+
+        let values = result
+            .iter()
+            .flat_map(|batch| {
+                let column = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap();
+                (0..column.len())
+                    .map(|i| column.value(i))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values.len(), 16 * 1024);
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // Surfaced by EXPLAIN ANALYZE
+        let spilled_bytes = sort
+            .metrics()
+            .and_then(|metrics| metrics.sum_by_name("spilled_bytes"))
+            .map(|spilled_bytes| spilled_bytes.as_usize())
+            .unwrap_or(0);
+        assert!(spilled_bytes > 0);
+    }
+}
@@ -0,0 +1,303 @@
+use std::{any::Any, collections::HashMap, fmt, sync::Arc};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::datatypes::DataType,
+    dataframe::DataFrame as DFDataFrame,
+    error::{DataFusionError, Result},
+    execution::context::SessionContext as DFSessionContext,
+    logical_plan::{DFSchemaRef, Expr, LogicalPlan, UserDefinedLogicalNode},
+    physical_plan::ExecutionPlan,
+    scalar::ScalarValue,
+};
+use sqlparser::ast;
+
+/// Plans the query again once the values of its scalar subqueries are known
+#[async_trait]
+pub trait ScalarSubqueryPlanner: Send + Sync {
+    /// Values are keyed by the text of the subquery
+    async fn plan(
+        &self,
+        values: HashMap<String, ast::Expr>,
+    ) -> Result<(LogicalPlan, Arc<DFSessionContext>)>;
+}
+
+/// Uncorrelated scalar subquery which is planned on its own
+pub struct ScalarSubquery {
+    /// Text of the subquery, it's used to find the subquery in the query
+    pub key: String,
+    /// Name of the only column of the subquery
+    pub name: String,
+    pub data_type: DataType,
+    pub plan: LogicalPlan,
+    pub ctx: Arc<DFSessionContext>,
+}
+
+impl ScalarSubquery {
+    pub fn new(
+        key: String,
+        name: String,
+        data_type: DataType,
+        plan: LogicalPlan,
+        ctx: Arc<DFSessionContext>,
+    ) -> Self {
+        Self {
+            key,
+            name,
+            data_type,
+            plan,
+            ctx,
+        }
+    }
+
+    /// Typed NULL which stands for the value until the subquery is executed, None if values of
+    /// this type can't be inlined
+    pub fn placeholder(data_type: &DataType) -> Option<ast::Expr> {
+        let data_type = match data_type {
+            DataType::Boolean => ast::DataType::Boolean,
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32 => ast::DataType::BigInt(None),
+            DataType::Float32 | DataType::Float64 => ast::DataType::Double,
+            DataType::Utf8 | DataType::LargeUtf8 => ast::DataType::Text,
+            DataType::Date32 => ast::DataType::Date,
+            DataType::Timestamp(_, None) => ast::DataType::Timestamp,
+            _ => return None,
+        };
+
+        Some(ast::Expr::Cast {
+            expr: Box::new(ast::Expr::Value(ast::Value::Null)),
+            data_type,
+        })
+    }
+
+    async fn evaluate(&self) -> Result<ast::Expr> {
+        let batches = DFDataFrame::new(self.ctx.state.clone(), &self.plan)
+            .collect()
+            .await?;
+
+        let mut rows = batches.iter().filter(|batch| batch.num_rows() > 0);
+        let value = match (rows.next(), rows.next()) {
+            (None, _) => None,
+            (Some(batch), None) if batch.num_rows() == 1 => {
+                Some(ScalarValue::try_from_array(batch.column(0), 0)?)
+            }
+            _ => {
+                return Err(DataFusionError::Execution(
+                    "more than one row returned by a subquery used as an expression".to_string(),
+                ))
+            }
+        };
+
+        let expr = match value {
+            Some(value) if !value.is_null() => scalar_to_ast_expr(&value),
+            _ => Self::placeholder(&self.data_type),
+        };
+
+        expr.ok_or_else(|| {
+            DataFusionError::Execution(format!(
+                "Value of a scalar subquery can't be inlined: {}",
+                self.key
+            ))
+        })
+    }
+}
+
+/// Query with scalar subqueries over cubes. Subqueries are executed before the physical planning
+/// of the query, which is then planned again with their values inlined as literals.
+pub struct ScalarSubqueryNode {
+    pub subqueries: Vec<ScalarSubquery>,
+    /// Plan of the query with typed NULLs in place of the subqueries, it defines the schema
+    pub placeholder_plan: Arc<LogicalPlan>,
+    pub planner: Arc<dyn ScalarSubqueryPlanner>,
+}
+
+impl ScalarSubqueryNode {
+    pub fn new(
+        subqueries: Vec<ScalarSubquery>,
+        placeholder_plan: Arc<LogicalPlan>,
+        planner: Arc<dyn ScalarSubqueryPlanner>,
+    ) -> Self {
+        Self {
+            subqueries,
+            placeholder_plan,
+            planner,
+        }
+    }
+
+    pub async fn create_physical_plan(&self) -> Result<Arc<dyn ExecutionPlan>> {
+        let mut values = HashMap::new();
+        for subquery in self.subqueries.iter() {
+            values.insert(subquery.key.clone(), subquery.evaluate().await?);
+        }
+
+        let (plan, ctx) = self.planner.plan(values).await?;
+
+        DFDataFrame::new(ctx.state.clone(), &plan)
+            .create_physical_plan()
+            .await
+    }
+}
+
+impl fmt::Debug for ScalarSubqueryNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_for_explain(f)
+    }
+}
+
+impl UserDefinedLogicalNode for ScalarSubqueryNode {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        vec![]
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        self.placeholder_plan.schema()
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        vec![]
+    }
+
+    fn fmt_for_explain(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ScalarSubqueries: [{}]",
+            self.subqueries
+                .iter()
+                .map(|subquery| subquery.key.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    fn from_template(
+        &self,
+        exprs: &[Expr],
+        inputs: &[LogicalPlan],
+    ) -> Arc<dyn UserDefinedLogicalNode + Send + Sync> {
+        assert_eq!(inputs.len(), 0, "input size inconsistent");
+        assert_eq!(exprs.len(), 0, "expression size inconsistent");
+
+        Arc::new(ScalarSubqueryNode {
+            subqueries: self
+                .subqueries
+                .iter()
+                .map(|subquery| {
+                    ScalarSubquery::new(
+                        subquery.key.clone(),
+                        subquery.name.clone(),
+                        subquery.data_type.clone(),
+                        subquery.plan.clone(),
+                        subquery.ctx.clone(),
+                    )
+                })
+                .collect(),
+            placeholder_plan: self.placeholder_plan.clone(),
+            planner: self.planner.clone(),
+        })
+    }
+}
+
+/// Literal of the same type as the placeholder of the value
+fn scalar_to_ast_expr(value: &ScalarValue) -> Option<ast::Expr> {
+    let cast = |value: String, data_type: ast::DataType| ast::Expr::Cast {
+        expr: Box::new(ast::Expr::Value(ast::Value::SingleQuotedString(value))),
+        data_type,
+    };
+    let number = |value: String| Some(ast::Expr::Value(ast::Value::Number(value, false)));
+
+    match value {
+        ScalarValue::Boolean(Some(v)) => Some(ast::Expr::Value(ast::Value::Boolean(*v))),
+        ScalarValue::Int8(Some(v)) => number(v.to_string()),
+        ScalarValue::Int16(Some(v)) => number(v.to_string()),
+        ScalarValue::Int32(Some(v)) => number(v.to_string()),
+        ScalarValue::Int64(Some(v)) => number(v.to_string()),
+        ScalarValue::UInt8(Some(v)) => number(v.to_string()),
+        ScalarValue::UInt16(Some(v)) => number(v.to_string()),
+        ScalarValue::UInt32(Some(v)) => number(v.to_string()),
+        // A literal without a fraction would be planned as an integer
+        ScalarValue::Float32(Some(v)) if v.is_finite() => number(format!("{:?}", v)),
+        ScalarValue::Float64(Some(v)) if v.is_finite() => number(format!("{:?}", v)),
+        ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => {
+            Some(ast::Expr::Value(ast::Value::SingleQuotedString(v.clone())))
+        }
+        ScalarValue::Date32(Some(v)) => {
+            let date = chrono::NaiveDate::from_ymd_opt(1970, 1, 1)?
+                .checked_add_signed(chrono::Duration::days(*v as i64))?;
+
+            Some(cast(
+                date.format("%Y-%m-%d").to_string(),
+                ast::DataType::Date,
+            ))
+        }
+        ScalarValue::TimestampNanosecond(Some(v), None) => {
+            let timestamp = chrono::NaiveDateTime::from_timestamp_opt(
+                v.div_euclid(1_000_000_000),
+                v.rem_euclid(1_000_000_000) as u32,
+            )?;
+
+            Some(cast(
+                timestamp.format("%Y-%m-%d %H:%M:%S%.f").to_string(),
+                ast::DataType::Timestamp,
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalar_to_ast_expr() {
+        let cases = vec![
+            (ScalarValue::Int32(Some(42)), "42"),
+            (ScalarValue::Float64(Some(1.0)), "1.0"),
+            (ScalarValue::Float32(Some(0.5)), "0.5"),
+            (ScalarValue::Utf8(Some("it's".to_string())), "'it''s'"),
+            (ScalarValue::Boolean(Some(true)), "true"),
+            (
+                ScalarValue::Date32(Some(19000)),
+                "CAST('2022-01-08' AS DATE)",
+            ),
+            (
+                ScalarValue::TimestampNanosecond(Some(1_500_000_000), None),
+                "CAST('1970-01-01 00:00:01.500' AS TIMESTAMP)",
+            ),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(scalar_to_ast_expr(&value).unwrap().to_string(), expected);
+        }
+
+        assert_eq!(
+            scalar_to_ast_expr(&ScalarValue::Float64(Some(f64::NAN))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_placeholder() {
+        assert_eq!(
+            ScalarSubquery::placeholder(&DataType::Int32)
+                .unwrap()
+                .to_string(),
+            "CAST(NULL AS BIGINT)"
+        );
+        assert_eq!(
+            ScalarSubquery::placeholder(&DataType::Utf8)
+                .unwrap()
+                .to_string(),
+            "CAST(NULL AS TEXT)"
+        );
+        assert_eq!(ScalarSubquery::placeholder(&DataType::UInt64), None);
+    }
+}
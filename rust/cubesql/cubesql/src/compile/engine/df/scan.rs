@@ -1,17 +1,23 @@
 use std::{
     any::Any,
+    collections::HashMap,
     fmt,
+    pin::Pin,
+    str::FromStr,
     sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
-use cubeclient::models::{V1LoadRequestQuery, V1LoadResult, V1LoadResultAnnotation};
+use cubeclient::models::{V1LoadRequestQuery, V1LoadResponse, V1LoadResult};
 pub use datafusion::{
     arrow::{
         array::{
-            ArrayRef, BooleanBuilder, Date32Builder, Float64Builder, Int32Builder, Int64Builder,
-            StringBuilder,
+            new_null_array, ArrayRef, BinaryBuilder, BooleanBuilder, Date32Builder, Date64Builder,
+            DecimalBuilder, Float64Builder, Int32Builder, Int64Builder, IntervalDayTimeBuilder,
+            IntervalMonthDayNanoBuilder, LargeBinaryBuilder, StringBuilder,
+            Time64MicrosecondBuilder,
         },
         datatypes::{DataType, SchemaRef},
         error::{ArrowError, Result as ArrowResult},
@@ -25,43 +31,291 @@ pub use datafusion::{
         Partitioning, PhysicalPlanner, RecordBatchStream, SendableRecordBatchStream, Statistics,
     },
 };
-use futures::Stream;
+use futures::{future::BoxFuture, FutureExt, Stream};
 use log::warn;
 
 use crate::{
     compile::{
-        engine::df::wrapper::{CubeScanWrapperNode, SqlQuery},
+        engine::df::{
+            dictionary::DictionaryEncodingStream,
+            member_aliases::match_response_keys,
+            result_processor::ResultProcessors,
+            scan_schemas::ScanSchemaCache,
+            shared_scan::{SharedCubeScan, SharedCubeScans},
+            transform_pool::TransformPool,
+            wrapper::{CubeScanWrapperNode, SqlQuery},
+        },
         find_cube_scans_deep_search,
         rewrite::WrappedSelectType,
     },
-    sql::AuthContextRef,
-    transport::{CubeStreamReceiver, LoadRequestMeta, SpanId, TransportService},
+    sql::{AuthContextRef, MemberUsageStats},
+    transport::{
+        CubeRequest, CubeStreamReceiver, LastCubeRequest, LoadLimiter, LoadPermit, LoadRequestMeta,
+        MemoryPressure, QueryWarnings, SpanId, TransportCall, TransportService, TransportTimeouts,
+    },
     CubeError,
 };
-use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike};
+use chrono_tz::Tz;
 use datafusion::{
     arrow::{
-        array::{TimestampMillisecondBuilder, TimestampNanosecondBuilder},
-        datatypes::TimeUnit,
+        array::{
+            ListBuilder, StructArray, TimestampMillisecondBuilder, TimestampNanosecondArray,
+            TimestampNanosecondBuilder,
+        },
+        buffer::MutableBuffer,
+        compute::{cast, SortOptions},
+        datatypes::{Field, IntervalUnit, Schema, TimeUnit},
+        util::bit_util,
     },
     execution::context::TaskContext,
     logical_plan::JoinType,
+    physical_plan::{expressions::Column as PhysicalColumn, memory::MemoryStream},
     scalar::ScalarValue,
 };
-use serde_json::{json, Value};
+use serde_json::{Map, Value};
+use uuid::Uuid;
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum MemberField {
     Member(String),
     Literal(ScalarValue),
 }
 
+/// Behavior when the query explicitly requests more rows than `CUBEJS_DB_QUERY_LIMIT`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueryLimitOverflow {
+    /// Result is silently truncated to the query limit
+    Truncate,
+    /// Result is truncated to the query limit and the client receives a warning
+    Warn,
+    /// Requested limit is sent to Cube as is, up to `CUBESQL_MAX_QUERY_LIMIT`
+    Honor,
+    /// Requested rows are loaded by pages of the query limit size, up to `CUBESQL_MAX_QUERY_LIMIT`
+    Paginate,
+}
+
+impl FromStr for QueryLimitOverflow {
+    type Err = CubeError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "truncate" => Ok(QueryLimitOverflow::Truncate),
+            "warn" => Ok(QueryLimitOverflow::Warn),
+            "honor" => Ok(QueryLimitOverflow::Honor),
+            "paginate" => Ok(QueryLimitOverflow::Paginate),
+            _ => Err(CubeError::user(format!(
+                "Unknown query limit overflow behavior '{}', expected one of: truncate, warn, honor, paginate",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryLimitSettings {
+    /// Default limit of the Cube query, CUBEJS_DB_QUERY_LIMIT
+    pub query_limit: i32,
+    /// Hard limit for honored and paginated queries, CUBESQL_MAX_QUERY_LIMIT
+    pub max_query_limit: i32,
+    /// CUBESQL_LIMIT_OVERFLOW
+    pub overflow: QueryLimitOverflow,
+}
+
+impl QueryLimitSettings {
+    pub fn from_env() -> Self {
+        let query_limit = std::env::var("CUBEJS_DB_QUERY_LIMIT")
+            .ok()
+            .map(|v| v.parse::<i32>().unwrap())
+            .unwrap_or(50000);
+        let max_query_limit = std::env::var("CUBESQL_MAX_QUERY_LIMIT")
+            .ok()
+            .map(|v| v.parse::<i32>().unwrap())
+            .unwrap_or(1_000_000)
+            .max(query_limit);
+        let overflow = match std::env::var("CUBESQL_LIMIT_OVERFLOW") {
+            Ok(v) => v.parse().unwrap_or_else(|err: CubeError| {
+                warn!("Ignoring CUBESQL_LIMIT_OVERFLOW: {}", err);
+                QueryLimitOverflow::Truncate
+            }),
+            Err(_) => QueryLimitOverflow::Truncate,
+        };
+
+        Self {
+            query_limit,
+            max_query_limit,
+            overflow,
+        }
+    }
+
+    /// Max number of rows which can be returned by one CubeScan
+    pub fn max_limit(&self) -> i32 {
+        match self.overflow {
+            QueryLimitOverflow::Truncate | QueryLimitOverflow::Warn => self.query_limit,
+            QueryLimitOverflow::Honor | QueryLimitOverflow::Paginate => self.max_query_limit,
+        }
+    }
+
+    /// Sets the limit of the request which is sent to Cube. Returns the page size if the request
+    /// must be loaded by pages.
+    pub fn apply(
+        &self,
+        request: &mut V1LoadRequestQuery,
+        can_paginate: bool,
+        warnings: &QueryWarnings,
+    ) -> Option<i32> {
+        let requested = match request.limit {
+            None => {
+                request.limit = Some(self.query_limit);
+                return None;
+            }
+            Some(limit) if limit <= self.query_limit => return None,
+            Some(limit) => limit,
+        };
+
+        let overflow = match self.overflow {
+            QueryLimitOverflow::Paginate if !can_paginate => QueryLimitOverflow::Warn,
+            overflow => overflow,
+        };
+        let limit = match overflow {
+            QueryLimitOverflow::Truncate | QueryLimitOverflow::Warn => self.query_limit,
+            QueryLimitOverflow::Honor | QueryLimitOverflow::Paginate => {
+                requested.min(self.max_query_limit)
+            }
+        };
+        request.limit = Some(limit);
+
+        if limit < requested && overflow != QueryLimitOverflow::Truncate {
+            warnings.push(format!(
+                "Query requested {} rows, but the result is truncated to {} rows by the query limit",
+                requested, limit
+            ));
+        }
+
+        match overflow {
+            QueryLimitOverflow::Paginate => Some(self.query_limit),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CubeScanOptions {
     pub change_user: Option<String>,
     pub max_records: Option<usize>,
 }
 
+/// Per-node limits of rows from `SET cubesql_max_records`, e.g. `Orders=100000, 50000`: a scan of
+/// Orders can return up to 100000 rows, other scans up to 50000. A scan of several cubes takes
+/// the largest limit of them. Limits can't exceed the max limit of the server.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaxRecordsOverrides {
+    pub default: Option<usize>,
+    pub cubes: HashMap<String, usize>,
+}
+
+impl MaxRecordsOverrides {
+    pub fn for_cubes(&self, cubes: &[String], max_limit: usize) -> Option<usize> {
+        cubes
+            .iter()
+            .filter_map(|cube| self.cubes.get(cube).cloned())
+            .max()
+            .or(self.default)
+            .map(|limit| limit.min(max_limit))
+    }
+}
+
+impl FromStr for MaxRecordsOverrides {
+    type Err = CubeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_limit = |limit: &str| {
+            limit.trim().parse::<usize>().map_err(|_| {
+                CubeError::user(format!(
+                    "Unable to parse cubesql_max_records: '{}' is not a number of rows",
+                    limit.trim()
+                ))
+            })
+        };
+
+        let mut overrides = Self::default();
+        for item in s.split(',').map(|item| item.trim()) {
+            if item.is_empty() {
+                continue;
+            }
+
+            match item.split_once('=') {
+                Some((cube, limit)) => {
+                    overrides
+                        .cubes
+                        .insert(cube.trim().to_string(), parse_limit(limit)?);
+                }
+                None => overrides.default = Some(parse_limit(item)?),
+            }
+        }
+
+        Ok(overrides)
+    }
+}
+
+/// Error of a CubeScan which returned `max_records` rows, its result can be truncated. The meta
+/// describes the offending request for clients as JSON, it's not a part of the message since
+/// filter values can be sensitive.
+pub struct MaxRecordsExceeded;
+
+impl MaxRecordsExceeded {
+    pub const META_KEY: &'static str = "maxRecordsExceeded";
+
+    pub fn error(request: &V1LoadRequestQuery, max_records: usize, rows: usize) -> CubeError {
+        let cubes = request_cubes(request);
+        let request = serde_json::to_value(request).unwrap_or(Value::Null);
+        let detail = serde_json::json!({
+            "cubes": cubes,
+            "maxRecords": max_records,
+            "rows": rows,
+            "request": request,
+        });
+
+        CubeError::user(format!(
+            "Cube query to {} returned {} rows and exceeded the maximum row limit ({}). JOIN/UNION is not possible as it will produce incorrect results. Try filtering the results more precisely, moving post-processing functions to an outer query or raising the limit with SET cubesql_max_records.",
+            cubes.join(", "),
+            rows,
+            max_records,
+        ))
+        .with_meta(Some(HashMap::from([(
+            Self::META_KEY.to_string(),
+            detail.to_string(),
+        )])))
+    }
+}
+
+/// Cubes of the members of the request in the order of appearance
+fn request_cubes(request: &V1LoadRequestQuery) -> Vec<String> {
+    let members = request
+        .measures
+        .iter()
+        .flatten()
+        .chain(request.dimensions.iter().flatten())
+        .chain(request.segments.iter().flatten())
+        .chain(
+            request
+                .time_dimensions
+                .iter()
+                .flatten()
+                .map(|td| &td.dimension),
+        );
+
+    let mut cubes: Vec<String> = vec![];
+    for member in members {
+        let cube = member.split('.').next().unwrap_or(member);
+        if !cubes.iter().any(|c| c == cube) {
+            cubes.push(cube.to_string());
+        }
+    }
+
+    cubes
+}
+
 #[derive(Debug, Clone)]
 pub struct CubeScanNode {
     pub schema: DFSchemaRef,
@@ -262,11 +516,7 @@ impl UserDefinedLogicalNode for WrappedSelectNode {
             .map(|i| Arc::new(inputs[i].clone()))
             .collect::<Vec<_>>();
         let mut joins_expr = vec![];
-        let join_types = self
-            .joins
-            .iter()
-            .map(|(_, _, t)| t.clone())
-            .collect::<Vec<_>>();
+        let join_types = self.joins.iter().map(|(_, _, t)| *t).collect::<Vec<_>>();
         let mut filter_expr = vec![];
         let mut having_expr = vec![];
         let mut order_expr = vec![];
@@ -274,9 +524,9 @@ impl UserDefinedLogicalNode for WrappedSelectNode {
         let mut group_expr = vec![];
         let mut aggregate_expr = vec![];
         let mut window_expr = vec![];
-        let limit = None;
-        let offset = None;
-        let alias = None;
+        let limit = self.limit;
+        let offset = self.offset;
+        let alias = self.alias.clone();
 
         let mut exprs_iter = exprs.iter();
         for _ in self.projection_expr.iter() {
@@ -341,6 +591,62 @@ impl UserDefinedLogicalNode for WrappedSelectNode {
 pub struct CubeScanExtensionPlanner {
     pub transport: Arc<dyn TransportService>,
     pub meta: LoadRequestMeta,
+    pub member_usage: Arc<MemberUsageStats>,
+    pub load_limiter: Arc<LoadLimiter>,
+    pub transform_pool: Arc<TransformPool>,
+    pub date_formats: Arc<ResponseDateFormats>,
+    pub result_processors: Arc<ResultProcessors>,
+    /// CUBESQL_STREAM_MODE
+    pub stream_mode: bool,
+    pub shared_scans: Arc<SharedCubeScans>,
+    pub scan_schemas: Option<Arc<ScanSchemaCache>>,
+    /// Fetch size of the cursor which is planned
+    pub fetch_size: Option<usize>,
+}
+
+impl CubeScanExtensionPlanner {
+    /// Arrow schema of the scan, it's converted only once for executions of a prepared statement
+    fn scan_schema(
+        &self,
+        df_schema: &DFSchemaRef,
+        member_fields: &[MemberField],
+    ) -> (SchemaRef, Arc<Vec<MemberField>>) {
+        match &self.scan_schemas {
+            Some(scan_schemas) => scan_schemas.get_or_insert(df_schema, member_fields),
+            None => (
+                SchemaRef::new(df_schema.as_ref().into()),
+                Arc::new(member_fields.to_vec()),
+            ),
+        }
+    }
+
+    /// Scans with the same key return the same data, so they can share a single load. Streamed
+    /// scans are never shared, shared result is buffered in memory.
+    fn shared_scan(
+        &self,
+        schema: &SchemaRef,
+        member_fields: &[MemberField],
+        request: &V1LoadRequestQuery,
+        wrapped_sql: &Option<SqlQuery>,
+        options: &CubeScanOptions,
+    ) -> Option<Arc<SharedCubeScan>> {
+        let limits = QueryLimitSettings::from_env();
+        if is_stream_mode(self.stream_mode, request, limits.query_limit) {
+            return None;
+        }
+
+        let expected_rows = request
+            .limit
+            .unwrap_or(limits.query_limit)
+            .min(limits.max_limit())
+            .max(0) as usize;
+        let key = format!(
+            "{:?}",
+            (schema, member_fields, request, wrapped_sql, options)
+        );
+
+        self.shared_scans.register(key, expected_rows)
+    }
 }
 
 impl ExtensionPlanner for CubeScanExtensionPlanner {
@@ -359,9 +665,23 @@ impl ExtensionPlanner for CubeScanExtensionPlanner {
                 assert_eq!(physical_inputs.len(), 0, "Inconsistent number of inputs");
 
                 // figure out input name
+                let (schema, member_fields) =
+                    self.scan_schema(scan_node.schema(), &scan_node.member_fields);
+                let shared = self.shared_scan(
+                    &schema,
+                    &member_fields,
+                    &scan_node.request,
+                    &None,
+                    &scan_node.options,
+                );
                 Some(Arc::new(CubeScanExecutionPlan {
-                    schema: SchemaRef::new(scan_node.schema().as_ref().into()),
-                    member_fields: scan_node.member_fields.clone(),
+                    ordering: output_ordering_for_request(
+                        &scan_node.request,
+                        &member_fields,
+                        &schema,
+                    ),
+                    schema,
+                    member_fields,
                     transport: self.transport.clone(),
                     request: scan_node.request.clone(),
                     wrapped_sql: None,
@@ -369,6 +689,15 @@ impl ExtensionPlanner for CubeScanExtensionPlanner {
                     options: scan_node.options.clone(),
                     meta: self.meta.clone(),
                     span_id: scan_node.span_id.clone(),
+                    member_usage: self.member_usage.clone(),
+                    load_limiter: self.load_limiter.clone(),
+                    transform_pool: self.transform_pool.clone(),
+                    date_formats: self.date_formats.clone(),
+                    result_processors: self.result_processors.clone(),
+                    stream_mode: self.stream_mode,
+                    shared,
+                    fetch_size: self.fetch_size,
+                    dictionary_max_ratio: None,
                 }))
             } else if let Some(wrapper_node) = node.as_any().downcast_ref::<CubeScanWrapperNode>() {
                 // TODO
@@ -383,27 +712,51 @@ impl ExtensionPlanner for CubeScanExtensionPlanner {
                             wrapper_node
                         )))?;
 
-                let schema = SchemaRef::new(wrapper_node.schema().as_ref().into());
+                let member_fields = wrapper_node.member_fields.as_ref().ok_or_else(|| {
+                    DataFusionError::Internal(format!(
+                        "Member fields are not set for wrapper node. Optimization wasn't performed: {:?}",
+                        wrapper_node
+                    ))
+                })?;
+                let (schema, member_fields) =
+                    self.scan_schema(wrapper_node.schema(), member_fields);
+                let request = wrapper_node
+                    .request
+                    .clone()
+                    .unwrap_or(scan_node.request.clone());
+                let wrapped_sql = Some(wrapper_node.wrapped_sql.as_ref().ok_or_else(|| {
+                    DataFusionError::Internal(format!(
+                        "Wrapped SQL is not set for wrapper node. Optimization wasn't performed: {:?}",
+                        wrapper_node
+                    ))
+                })?.clone());
+                let shared = self.shared_scan(
+                    &schema,
+                    &member_fields,
+                    &request,
+                    &wrapped_sql,
+                    &scan_node.options,
+                );
                 Some(Arc::new(CubeScanExecutionPlan {
+                    ordering: output_ordering_for_request(&request, &member_fields, &schema),
                     schema,
-                    member_fields: wrapper_node.member_fields.as_ref().ok_or_else(|| {
-                        DataFusionError::Internal(format!(
-                            "Member fields are not set for wrapper node. Optimization wasn't performed: {:?}",
-                            wrapper_node
-                        ))
-                    })?.clone(),
+                    member_fields,
                     transport: self.transport.clone(),
-                    request: wrapper_node.request.clone().unwrap_or(scan_node.request.clone()),
-                    wrapped_sql: Some(wrapper_node.wrapped_sql.as_ref().ok_or_else(|| {
-                        DataFusionError::Internal(format!(
-                            "Wrapped SQL is not set for wrapper node. Optimization wasn't performed: {:?}",
-                            wrapper_node
-                        ))
-                    })?.clone()),
+                    request,
+                    wrapped_sql,
                     auth_context: scan_node.auth_context.clone(),
                     options: scan_node.options.clone(),
                     meta: self.meta.clone(),
                     span_id: scan_node.span_id.clone(),
+                    member_usage: self.member_usage.clone(),
+                    load_limiter: self.load_limiter.clone(),
+                    transform_pool: self.transform_pool.clone(),
+                    date_formats: self.date_formats.clone(),
+                    result_processors: self.result_processors.clone(),
+                    stream_mode: self.stream_mode,
+                    shared,
+                    fetch_size: self.fetch_size,
+                    dictionary_max_ratio: None,
                 }))
             } else {
                 None
@@ -412,11 +765,73 @@ impl ExtensionPlanner for CubeScanExtensionPlanner {
     }
 }
 
-#[derive(Debug)]
-struct CubeScanExecutionPlan {
+/// Sort expressions of ORDER BY which was pushed down to the load request. Only the prefix of the
+/// order which can be expressed over output columns is returned. Cube doesn't control the
+/// placement of NULLs, so NULLs are placed as PostgreSQL does by default: as the largest values.
+fn request_sort_exprs(
+    request: &V1LoadRequestQuery,
+    member_fields: &[MemberField],
+    schema: &SchemaRef,
+) -> Vec<PhysicalSortExpr> {
+    let mut sort_exprs = vec![];
+
+    for order in request.order.iter().flatten() {
+        let (member, direction) = match order.as_slice() {
+            [member, direction] => (member, direction),
+            _ => break,
+        };
+        let index = match member_fields
+            .iter()
+            .position(|f| matches!(f, MemberField::Member(m) if m == member))
+        {
+            Some(index) => index,
+            None => break,
+        };
+        let descending = direction.to_lowercase() == "desc";
+
+        sort_exprs.push(PhysicalSortExpr {
+            expr: Arc::new(PhysicalColumn::new(schema.field(index).name(), index)),
+            options: SortOptions {
+                descending,
+                nulls_first: descending,
+            },
+        });
+    }
+
+    sort_exprs
+}
+
+/// Ordering of rows which is guaranteed by Cube when ORDER BY was pushed down to the load request.
+/// Placement of NULLs depends on the data source, so the ordering is guaranteed only up to the
+/// first nullable column.
+fn output_ordering_for_request(
+    request: &V1LoadRequestQuery,
+    member_fields: &[MemberField],
+    schema: &SchemaRef,
+) -> Option<Vec<PhysicalSortExpr>> {
+    let ordering = request_sort_exprs(request, member_fields, schema)
+        .into_iter()
+        .take_while(|sort_expr| {
+            sort_expr
+                .expr
+                .nullable(schema)
+                .map(|nullable| !nullable)
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+
+    if ordering.is_empty() {
+        None
+    } else {
+        Some(ordering)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CubeScanExecutionPlan {
     // Options from logical node
     schema: SchemaRef,
-    member_fields: Vec<MemberField>,
+    member_fields: Arc<Vec<MemberField>>,
     request: V1LoadRequestQuery,
     wrapped_sql: Option<SqlQuery>,
     auth_context: AuthContextRef,
@@ -426,6 +841,307 @@ struct CubeScanExecutionPlan {
     // injected by extension planner
     meta: LoadRequestMeta,
     span_id: Option<Arc<SpanId>>,
+    // Ordering pushed down to Cube
+    ordering: Option<Vec<PhysicalSortExpr>>,
+    member_usage: Arc<MemberUsageStats>,
+    load_limiter: Arc<LoadLimiter>,
+    transform_pool: Arc<TransformPool>,
+    date_formats: Arc<ResponseDateFormats>,
+    result_processors: Arc<ResultProcessors>,
+    // Scans without a limit are streamed
+    stream_mode: bool,
+    // Load shared with identical scans of the same query
+    shared: Option<Arc<SharedCubeScan>>,
+    // Rows are loaded by pages on demand when the scan is read by a cursor
+    fetch_size: Option<usize>,
+    // Strings with low cardinality are returned as dictionaries
+    dictionary_max_ratio: Option<f64>,
+}
+
+impl CubeScanExecutionPlan {
+    /// Batches are returned to the client as is, so string columns can be dictionary encoded
+    pub fn with_dictionary_encoding(&self, max_ratio: f64) -> Self {
+        Self {
+            dictionary_max_ratio: Some(max_ratio),
+            ..self.clone()
+        }
+    }
+
+    /// Rows are returned in the order requested from Cube, which must be kept by the plan above
+    pub fn is_ordered(&self) -> bool {
+        self.request
+            .order
+            .as_ref()
+            .map(|order| !order.is_empty())
+            .unwrap_or(false)
+    }
+
+    /// ORDER BY is pushed down to Cube. It's None when the order can't be changed without changing
+    /// the loaded rows or the load is shared with other scans.
+    pub fn with_order(&self, order: Vec<Vec<String>>) -> Option<Self> {
+        if self.wrapped_sql.is_some()
+            || self.shared.is_some()
+            || self.request.limit.is_some()
+            || self.request.offset.is_some()
+        {
+            return None;
+        }
+
+        let mut request = self.request.clone();
+        request.order = Some(order);
+        let ordering = output_ordering_for_request(&request, &self.member_fields, &self.schema);
+
+        Some(Self {
+            request,
+            ordering,
+            ..self.clone()
+        })
+    }
+
+    /// Member of the output column, None for columns which aren't loaded as members
+    pub fn member_at(&self, index: usize) -> Option<&str> {
+        match self.member_fields.get(index) {
+            Some(MemberField::Member(member)) => Some(member.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Sort expressions of ORDER BY which was pushed down to Cube, expressed over output columns
+    pub fn request_sort_exprs(&self) -> Vec<PhysicalSortExpr> {
+        request_sort_exprs(&self.request, &self.member_fields, &self.schema)
+    }
+
+    /// Rows are loaded by pages when they are read by a cursor
+    pub fn is_paged(&self) -> bool {
+        self.fetch_size.is_some() && self.wrapped_sql.is_none()
+    }
+
+    /// Upper bound of the returned rows by the limit of the request, unknown without a limit
+    pub fn estimated_rows(&self) -> usize {
+        self.request
+            .limit
+            .map(|limit| limit.max(0) as usize)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Loads the whole result at once, the load slot is released before the transformation
+    async fn load_batch(
+        &self,
+        request: V1LoadRequestQuery,
+        meta: LoadRequestMeta,
+        page_size: Option<i32>,
+    ) -> Result<RecordBatch> {
+        // Batch extracts go to the end of the queue when the limit is reached
+        let load_permit = self.load_limiter.acquire(meta.priority()).await;
+        let data = load_data(
+            self.span_id.clone(),
+            request,
+            self.auth_context.clone(),
+            self.transport.clone(),
+            meta,
+            self.options.clone(),
+            self.wrapped_sql.clone(),
+            page_size,
+        )
+        .await?
+        .data;
+        drop(load_permit);
+
+        transform_on_pool(
+            &self.transform_pool,
+            data,
+            self.schema.clone(),
+            self.member_fields.clone(),
+            self.date_formats.clone(),
+        )
+        .await
+    }
+
+    fn paged_stream(
+        &self,
+        request: V1LoadRequestQuery,
+        meta: LoadRequestMeta,
+        fetch_size: i32,
+        max_page_size: i32,
+    ) -> CubeScanPagedStream {
+        CubeScanPagedStream {
+            limit: request.limit.unwrap_or(max_page_size),
+            schema: self.schema.clone(),
+            member_fields: self.member_fields.clone(),
+            request,
+            auth_context: self.auth_context.clone(),
+            transport: self.transport.clone(),
+            meta,
+            options: self.options.clone(),
+            span_id: self.span_id.clone(),
+            load_limiter: self.load_limiter.clone(),
+            transform_pool: self.transform_pool.clone(),
+            date_formats: self.date_formats.clone(),
+            loaded: 0,
+            page_size: fetch_size,
+            max_page_size,
+            page: None,
+            finished: false,
+        }
+    }
+
+    /// Stream of the loaded rows, before they are passed to the result processors
+    async fn execute_scan(&self) -> Result<SendableRecordBatchStream> {
+        let limits = QueryLimitSettings::from_env();
+        let query_limit = limits.query_limit;
+
+        // Streamed scans are not registered as shared, see `shared_scan`
+        let shared = self.shared.as_ref().filter(|shared| shared.is_shared());
+        let stream_mode = is_stream_mode(self.stream_mode, &self.request, query_limit);
+
+        if is_no_members_query(&self.request) {
+            let num_rows = no_members_num_rows(&self.request, query_limit);
+            let batch = no_members_batch(self.schema.clone(), &self.member_fields, num_rows)
+                .map_err(|e| DataFusionError::Execution(e.message))?;
+
+            return Ok(Box::pin(MemoryStream::try_new(
+                vec![batch],
+                self.schema.clone(),
+                None,
+            )?));
+        }
+
+        self.member_usage.record_request(&self.request);
+
+        let mut meta = self.meta.clone();
+        meta.set_change_user(self.options.change_user.clone());
+        // Span is not created for MySQL connections
+        meta.set_request_id(Some(match &self.span_id {
+            Some(span_id) => span_id.span_id.clone(),
+            None => Uuid::new_v4().to_string(),
+        }));
+        meta.update_timeout()
+            .map_err(|err| DataFusionError::Execution(err.to_string()))?;
+
+        let mut request = self.request.clone();
+        // Stream mode loads all requested rows, the limited request is used only as a fallback
+        let page_size = if stream_mode {
+            if request.limit.unwrap_or_default() > query_limit || request.limit.is_none() {
+                request.limit = Some(query_limit);
+            }
+
+            None
+        } else {
+            // Wrapped SQL has own LIMIT/OFFSET, it can't be split into pages
+            limits.apply(&mut request, self.wrapped_sql.is_none(), meta.warnings())
+        };
+        meta.tag_heavy_request(&request);
+
+        let mut one_shot_stream = CubeScanOneShotStream::new(
+            self.schema.clone(),
+            self.member_fields.clone(),
+            request.clone(),
+            self.auth_context.clone(),
+            self.transport.clone(),
+            meta.clone(),
+            self.options.clone(),
+            self.wrapped_sql.clone(),
+            self.span_id.clone(),
+            self.transform_pool.clone(),
+            self.date_formats.clone(),
+        );
+
+        if let Some(shared) = shared {
+            one_shot_stream.data = Some(
+                shared
+                    .load(|| self.load_batch(request.clone(), meta.clone(), page_size))
+                    .await?,
+            );
+
+            return Ok(Box::pin(CubeScanStreamRouter::new(
+                None,
+                one_shot_stream,
+                self.schema.clone(),
+                None,
+            )));
+        }
+
+        if let Some(fetch_size) = self.fetch_size {
+            let fetch_size = fetch_size.min(i32::MAX as usize) as i32;
+            let limit = request.limit.unwrap_or(query_limit);
+            // Wrapped SQL has own LIMIT/OFFSET and checks of max records need the whole result
+            if !stream_mode
+                && self.wrapped_sql.is_none()
+                && self.options.max_records.is_none()
+                && fetch_size < limit
+            {
+                return Ok(Box::pin(self.paged_stream(
+                    request,
+                    meta,
+                    fetch_size,
+                    query_limit,
+                )));
+            }
+        }
+
+        if stream_mode {
+            // Slot is held until the stream is exhausted or dropped, batch extracts go to the end
+            // of the queue when the limit is reached
+            let load_permit = self.load_limiter.acquire(meta.priority()).await;
+
+            let started = Instant::now();
+            let trace = meta.trace().cloned();
+            let request_id = meta.request_id().map(|id| id.to_string());
+            let last_cube_request = meta.last_cube_request().clone();
+            let memory_pressure = meta.memory_pressure().cloned();
+            let result = self
+                .transport
+                .load_stream(
+                    self.span_id.clone(),
+                    self.request.clone(),
+                    self.wrapped_sql.clone(),
+                    self.auth_context.clone(),
+                    meta,
+                    self.schema.clone(),
+                    self.member_fields.as_ref().clone(),
+                )
+                .await;
+            // Rows are streamed later, only opening of the stream is measured
+            if let Some(trace) = trace {
+                trace.record(
+                    "load_stream",
+                    &self.request,
+                    self.wrapped_sql.as_ref().map(|q| q.sql.as_str()),
+                    started.elapsed(),
+                    result
+                        .as_ref()
+                        .map(|_| None)
+                        .map_err(|err| err.message.clone()),
+                );
+            }
+            let result = record_cube_request(
+                &last_cube_request,
+                request_id.as_deref(),
+                "load_stream",
+                started,
+                result,
+            );
+            let stream = result.map_err(DataFusionError::Execution)?;
+            let chunk_timeout = TransportTimeouts::from_env().get(TransportCall::StreamChunk);
+            let main_stream = CubeScanMemoryStream::new(stream, chunk_timeout, memory_pressure);
+
+            return Ok(Box::pin(CubeScanStreamRouter::new(
+                Some(main_stream),
+                one_shot_stream,
+                self.schema.clone(),
+                Some(load_permit),
+            )));
+        }
+
+        one_shot_stream.data = Some(self.load_batch(request, meta, page_size).await?);
+
+        Ok(Box::pin(CubeScanStreamRouter::new(
+            None,
+            one_shot_stream,
+            self.schema.clone(),
+            None,
+        )))
+    }
 }
 
 #[derive(Debug)]
@@ -433,14 +1149,58 @@ pub enum FieldValue {
     String(String),
     Number(f64),
     Bool(bool),
+    /// Value of an array dimension
+    Array(Vec<FieldValue>),
+    /// Nested object, it's passed through as JSON or mapped to a struct
+    Object(Map<String, Value>),
     Null,
 }
 
+impl FieldValue {
+    pub fn into_json(self) -> Value {
+        match self {
+            FieldValue::String(v) => Value::String(v),
+            FieldValue::Number(v) => serde_json::Number::from_f64(v)
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+            FieldValue::Bool(v) => Value::Bool(v),
+            FieldValue::Array(items) => {
+                Value::Array(items.into_iter().map(FieldValue::into_json).collect())
+            }
+            FieldValue::Object(v) => Value::Object(v),
+            FieldValue::Null => Value::Null,
+        }
+    }
+}
+
 pub trait ValueObject {
     fn len(&mut self) -> std::result::Result<usize, CubeError>;
 
     fn get(&mut self, index: usize, field_name: &str)
         -> std::result::Result<FieldValue, CubeError>;
+
+    /// Keys of the returned rows, None if they are unknown
+    fn keys(&mut self) -> std::result::Result<Option<Vec<String>>, CubeError> {
+        Ok(None)
+    }
+}
+
+fn json_field_value(value: Value) -> std::result::Result<FieldValue, CubeError> {
+    Ok(match value {
+        Value::String(s) => FieldValue::String(s),
+        Value::Number(n) => FieldValue::Number(n.as_f64().ok_or(DataFusionError::Execution(
+            format!("Can't convert {:?} to float", n),
+        ))?),
+        Value::Bool(b) => FieldValue::Bool(b),
+        Value::Null => FieldValue::Null,
+        Value::Array(items) => FieldValue::Array(
+            items
+                .into_iter()
+                .map(json_field_value)
+                .collect::<std::result::Result<_, _>>()?,
+        ),
+        Value::Object(v) => FieldValue::Object(v),
+    })
 }
 
 pub struct JsonValueObject {
@@ -477,27 +1237,24 @@ impl ValueObject for JsonValueObject {
             .unwrap_or(&Value::Null)
             // TODO expose strings as references to avoid clonning
             .clone();
-        Ok(match value {
-            Value::String(s) => FieldValue::String(s),
-            Value::Number(n) => FieldValue::Number(n.as_f64().ok_or(
-                DataFusionError::Execution(format!("Can't convert {:?} to float", n)),
-            )?),
-            Value::Bool(b) => FieldValue::Bool(b),
-            Value::Null => FieldValue::Null,
-            x => {
-                return Err(CubeError::user(format!(
-                    "Expected primitive value but found: {:?}",
-                    x
-                )));
-            }
-        })
+
+        json_field_value(value)
+    }
+
+    fn keys(&mut self) -> std::result::Result<Option<Vec<String>>, CubeError> {
+        Ok(self
+            .rows
+            .first()
+            .and_then(|row| row.as_object())
+            .map(|row| row.keys().cloned().collect()))
     }
 }
 
 macro_rules! build_column {
-    ($data_type:expr, $builder_ty:ty, $response:expr, $field_name:expr, { $($builder_block:tt)* }, { $($scalar_block:tt)* }) => {{
+    // Builders which are parameterized by the type, e.g. precision and scale of decimals
+    ($data_type:expr, new = $new_builder:expr, $response:expr, $field_name:expr, { $($builder_block:tt)* }, { $($scalar_block:tt)* }) => {{
         let len = $response.len()?;
-        let mut builder = <$builder_ty>::new(len);
+        let mut builder = ($new_builder)(len);
 
         match $field_name {
             MemberField::Member(field_name) => {
@@ -534,7 +1291,10 @@ macro_rules! build_column {
         };
 
         Arc::new(builder.finish()) as ArrayRef
-    }}
+    }};
+    ($data_type:expr, $builder_ty:ty, $response:expr, $field_name:expr, { $($builder_block:tt)* }, { $($scalar_block:tt)* }) => {{
+        build_column!($data_type, new = |len| <$builder_ty>::new(len), $response, $field_name, { $($builder_block)* }, { $($scalar_block)* })
+    }};
 }
 
 #[async_trait]
@@ -553,7 +1313,7 @@ impl ExecutionPlan for CubeScanExecutionPlan {
     }
 
     fn output_ordering(&self) -> Option<&[PhysicalSortExpr]> {
-        None
+        self.ordering.as_deref()
     }
 
     fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
@@ -575,92 +1335,15 @@ impl ExecutionPlan for CubeScanExecutionPlan {
         _partition: usize,
         _context: Arc<TaskContext>,
     ) -> Result<SendableRecordBatchStream> {
-        // TODO: move envs to config
-        let stream_mode = std::env::var("CUBESQL_STREAM_MODE")
-            .ok()
-            .map(|v| v.parse::<bool>().unwrap())
-            .unwrap_or(false);
-        let query_limit = std::env::var("CUBEJS_DB_QUERY_LIMIT")
-            .ok()
-            .map(|v| v.parse::<i32>().unwrap())
-            .unwrap_or(50000);
-
-        let stream_mode = match (stream_mode, self.request.limit) {
-            (true, None) => true,
-            (true, Some(limit)) if limit > query_limit => true,
-            (_, _) => false,
-        };
-
-        let mut request = self.request.clone();
-        if request.limit.unwrap_or_default() > query_limit || request.limit.is_none() {
-            request.limit = Some(query_limit);
-        }
-
-        let mut meta = self.meta.clone();
-        meta.set_change_user(self.options.change_user.clone());
-
-        let mut one_shot_stream = CubeScanOneShotStream::new(
-            self.schema.clone(),
-            self.member_fields.clone(),
-            request.clone(),
-            self.auth_context.clone(),
-            self.transport.clone(),
-            meta.clone(),
-            self.options.clone(),
-            self.wrapped_sql.clone(),
-            self.span_id.clone(),
-        );
-
-        if stream_mode {
-            let result = self
-                .transport
-                .load_stream(
-                    self.span_id.clone(),
-                    self.request.clone(),
-                    self.wrapped_sql.clone(),
-                    self.auth_context.clone(),
-                    meta,
-                    self.schema.clone(),
-                    self.member_fields.clone(),
-                )
-                .await;
-            let stream = result.map_err(|err| DataFusionError::Execution(err.to_string()))?;
-            let main_stream = CubeScanMemoryStream::new(stream);
+        let stream = self.execute_scan().await?;
+        let stream = self
+            .result_processors
+            .process_stream(stream, self.member_fields.clone());
 
-            return Ok(Box::pin(CubeScanStreamRouter::new(
-                Some(main_stream),
-                one_shot_stream,
-                self.schema.clone(),
-            )));
-        }
-
-        let mut response = JsonValueObject::new(
-            load_data(
-                self.span_id.clone(),
-                request,
-                self.auth_context.clone(),
-                self.transport.clone(),
-                meta.clone(),
-                self.options.clone(),
-                self.wrapped_sql.clone(),
-            )
-            .await?
-            .data,
-        );
-        one_shot_stream.data = Some(
-            transform_response(
-                &mut response,
-                one_shot_stream.schema.clone(),
-                &one_shot_stream.member_fields,
-            )
-            .map_err(|e| DataFusionError::Execution(e.message.to_string()))?,
-        );
-
-        Ok(Box::pin(CubeScanStreamRouter::new(
-            None,
-            one_shot_stream,
-            self.schema.clone(),
-        )))
+        Ok(match self.dictionary_max_ratio {
+            Some(max_ratio) => Box::pin(DictionaryEncodingStream::new(stream, max_ratio)),
+            None => stream,
+        })
     }
 
     fn fmt_as(&self, t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -693,7 +1376,7 @@ impl ExecutionPlan for CubeScanExecutionPlan {
 struct CubeScanOneShotStream {
     data: Option<RecordBatch>,
     schema: SchemaRef,
-    member_fields: Vec<MemberField>,
+    member_fields: Arc<Vec<MemberField>>,
     request: V1LoadRequestQuery,
     auth_context: AuthContextRef,
     transport: Arc<dyn TransportService>,
@@ -701,12 +1384,14 @@ struct CubeScanOneShotStream {
     options: CubeScanOptions,
     wrapped_sql: Option<SqlQuery>,
     span_id: Option<Arc<SpanId>>,
+    transform_pool: Arc<TransformPool>,
+    date_formats: Arc<ResponseDateFormats>,
 }
 
 impl CubeScanOneShotStream {
     pub fn new(
         schema: SchemaRef,
-        member_fields: Vec<MemberField>,
+        member_fields: Arc<Vec<MemberField>>,
         request: V1LoadRequestQuery,
         auth_context: AuthContextRef,
         transport: Arc<dyn TransportService>,
@@ -714,6 +1399,8 @@ impl CubeScanOneShotStream {
         options: CubeScanOptions,
         wrapped_sql: Option<SqlQuery>,
         span_id: Option<Arc<SpanId>>,
+        transform_pool: Arc<TransformPool>,
+        date_formats: Arc<ResponseDateFormats>,
     ) -> Self {
         Self {
             data: None,
@@ -726,9 +1413,43 @@ impl CubeScanOneShotStream {
             options,
             wrapped_sql,
             span_id,
+            transform_pool,
+            date_formats,
         }
     }
 
+    /// Loads the data when the stream can't be used
+    fn load(&self) -> BoxFuture<'static, Result<RecordBatch>> {
+        let span_id = self.span_id.clone();
+        let request = self.request.clone();
+        let auth_context = self.auth_context.clone();
+        let transport = self.transport.clone();
+        let meta = self.meta.clone();
+        let options = self.options.clone();
+        let wrapped_sql = self.wrapped_sql.clone();
+        let schema = self.schema.clone();
+        let member_fields = self.member_fields.clone();
+        let transform_pool = self.transform_pool.clone();
+        let date_formats = self.date_formats.clone();
+
+        Box::pin(async move {
+            let data = load_data(
+                span_id,
+                request,
+                auth_context,
+                transport,
+                meta,
+                options,
+                wrapped_sql,
+                None,
+            )
+            .await?
+            .data;
+
+            transform_on_pool(&transform_pool, data, schema, member_fields, date_formats).await
+        })
+    }
+
     fn poll_next(&mut self) -> Option<ArrowResult<RecordBatch>> {
         if let Some(batch) = self.data.take() {
             Some(Ok(batch))
@@ -740,27 +1461,104 @@ impl CubeScanOneShotStream {
 
 struct CubeScanMemoryStream {
     receiver: CubeStreamReceiver,
+    chunk_timeout: Option<Duration>,
+    // Started when the consumer waits for a chunk, reset when a chunk arrives
+    chunk_deadline: Option<Pin<Box<tokio::time::Sleep>>>,
+    // Chunks aren't received while loads are shed, so the bounded channel pauses the load
+    memory_pressure: Option<Arc<MemoryPressure>>,
+    relief: Option<BoxFuture<'static, Result<(), CubeError>>>,
 }
 
 impl CubeScanMemoryStream {
-    pub fn new(receiver: CubeStreamReceiver) -> Self {
-        Self { receiver }
+    pub fn new(
+        receiver: CubeStreamReceiver,
+        chunk_timeout: Option<Duration>,
+        memory_pressure: Option<Arc<MemoryPressure>>,
+    ) -> Self {
+        Self {
+            receiver,
+            chunk_timeout,
+            chunk_deadline: None,
+            memory_pressure,
+            relief: None,
+        }
     }
 
     fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<ArrowResult<RecordBatch>>> {
-        self.receiver.poll_recv(cx).map(|res| match res {
+        if let Some(relief) = self.poll_relief(cx) {
+            return relief;
+        }
+
+        let res = match self.receiver.poll_recv(cx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return self.poll_chunk_deadline(cx),
+        };
+        self.chunk_deadline = None;
+
+        Poll::Ready(match res {
             Some(Some(Ok(chunk))) => Some(Ok(chunk)),
             Some(Some(Err(err))) => Some(Err(ArrowError::ComputeError(err.to_string()))),
             Some(None) => None,
             None => None,
         })
     }
+
+    /// Waits for the relief of memory pressure before the next chunk, None when it's not shed
+    fn poll_relief(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Option<Poll<Option<ArrowResult<RecordBatch>>>> {
+        if self.relief.is_none() {
+            let memory_pressure = self.memory_pressure.as_ref()?;
+            if !memory_pressure.is_shedding() {
+                return None;
+            }
+            let memory_pressure = memory_pressure.clone();
+            self.relief = Some(async move { memory_pressure.wait_for_relief().await }.boxed());
+        }
+
+        let res = match self.relief.as_mut()?.poll_unpin(cx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Some(Poll::Pending),
+        };
+        self.relief = None;
+
+        match res {
+            Ok(()) => None,
+            Err(err) => Some(Poll::Ready(Some(Err(ArrowError::ExternalError(Box::new(
+                err,
+            )))))),
+        }
+    }
+
+    fn poll_chunk_deadline(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<ArrowResult<RecordBatch>>> {
+        let timeout = match self.chunk_timeout {
+            Some(timeout) => timeout,
+            None => return Poll::Pending,
+        };
+        let deadline = self
+            .chunk_deadline
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+        if deadline.poll_unpin(cx).is_pending() {
+            return Poll::Pending;
+        }
+        self.chunk_deadline = None;
+
+        let err = TransportTimeouts::timeout_error(TransportCall::StreamChunk, timeout);
+        Poll::Ready(Some(Err(ArrowError::ComputeError(err.to_string()))))
+    }
 }
 
 struct CubeScanStreamRouter {
     main_stream: Option<CubeScanMemoryStream>,
     one_shot_stream: CubeScanOneShotStream,
+    // Load of the one shot stream after the main stream was rejected by Cube
+    fallback: Option<BoxFuture<'static, Result<RecordBatch>>>,
     schema: SchemaRef,
+    _load_permit: Option<LoadPermit>,
 }
 
 impl CubeScanStreamRouter {
@@ -768,11 +1566,14 @@ impl CubeScanStreamRouter {
         main_stream: Option<CubeScanMemoryStream>,
         one_shot_stream: CubeScanOneShotStream,
         schema: SchemaRef,
+        load_permit: Option<LoadPermit>,
     ) -> Self {
         Self {
             main_stream,
             one_shot_stream,
+            fallback: None,
             schema,
+            _load_permit: load_permit,
         }
     }
 }
@@ -784,6 +1585,22 @@ impl Stream for CubeScanStreamRouter {
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Self::Item>> {
+        if let Some(fallback) = &mut self.fallback {
+            let result = match fallback.poll_unpin(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            };
+            self.fallback = None;
+
+            return Poll::Ready(match result {
+                Ok(batch) => {
+                    self.one_shot_stream.data = Some(batch);
+                    self.one_shot_stream.poll_next()
+                }
+                Err(e) => Some(Err(e.into())),
+            });
+        }
+
         match &mut self.main_stream {
             Some(main_stream) => {
                 let next = main_stream.poll_next(cx);
@@ -795,11 +1612,9 @@ impl Stream for CubeScanStreamRouter {
                         warn!("{}", err);
 
                         self.main_stream = None;
+                        self.fallback = Some(self.one_shot_stream.load());
 
-                        return Poll::Ready(match load_to_stream_sync(&mut self.one_shot_stream) {
-                            Ok(_) => self.one_shot_stream.poll_next(),
-                            Err(e) => Some(Err(e.into())),
-                        });
+                        return self.poll_next(cx);
                     }
                 }
 
@@ -817,6 +1632,120 @@ impl RecordBatchStream for CubeScanStreamRouter {
     }
 }
 
+/// Loads rows by pages on demand, the next page is requested only after the previous one was
+/// read. The first page has the fetch size of the cursor and following pages grow up to the query
+/// limit, so cursors which are abandoned after a few fetches don't load the whole result.
+struct CubeScanPagedStream {
+    schema: SchemaRef,
+    member_fields: Arc<Vec<MemberField>>,
+    request: V1LoadRequestQuery,
+    auth_context: AuthContextRef,
+    transport: Arc<dyn TransportService>,
+    meta: LoadRequestMeta,
+    options: CubeScanOptions,
+    span_id: Option<Arc<SpanId>>,
+    load_limiter: Arc<LoadLimiter>,
+    transform_pool: Arc<TransformPool>,
+    date_formats: Arc<ResponseDateFormats>,
+    // Total number of rows which can be loaded
+    limit: i32,
+    loaded: i32,
+    page_size: i32,
+    max_page_size: i32,
+    page: Option<BoxFuture<'static, Result<RecordBatch>>>,
+    finished: bool,
+}
+
+impl CubeScanPagedStream {
+    fn current_page_limit(&self) -> i32 {
+        self.page_size.min(self.limit - self.loaded)
+    }
+
+    fn load_page(&self) -> BoxFuture<'static, Result<RecordBatch>> {
+        let mut request = self.request.clone();
+        request.offset = Some(self.request.offset.unwrap_or(0) + self.loaded);
+        request.limit = Some(self.current_page_limit());
+
+        let span_id = self.span_id.clone();
+        let auth_context = self.auth_context.clone();
+        let transport = self.transport.clone();
+        let meta = self.meta.clone();
+        let options = self.options.clone();
+        let load_limiter = self.load_limiter.clone();
+        let schema = self.schema.clone();
+        let member_fields = self.member_fields.clone();
+        let transform_pool = self.transform_pool.clone();
+        let date_formats = self.date_formats.clone();
+
+        Box::pin(async move {
+            let load_permit = load_limiter.acquire(meta.priority()).await;
+            let data = load_data(
+                span_id,
+                request,
+                auth_context,
+                transport,
+                meta,
+                options,
+                None,
+                None,
+            )
+            .await?
+            .data;
+            drop(load_permit);
+
+            transform_on_pool(&transform_pool, data, schema, member_fields, date_formats).await
+        })
+    }
+}
+
+impl Stream for CubeScanPagedStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+
+        if self.page.is_none() {
+            self.page = Some(self.load_page());
+        }
+
+        let result = match self.page.as_mut().unwrap().poll_unpin(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.page = None;
+
+        let batch = match result {
+            Ok(batch) => batch,
+            Err(e) => {
+                self.finished = true;
+
+                return Poll::Ready(Some(Err(e.into())));
+            }
+        };
+
+        let received = batch.num_rows() as i32;
+        let requested = self.current_page_limit();
+        self.loaded += received;
+        if received < requested || self.loaded >= self.limit {
+            self.finished = true;
+        }
+        self.page_size = (self.page_size * 2).min(self.max_page_size);
+
+        Poll::Ready(Some(Ok(batch)))
+    }
+}
+
+impl RecordBatchStream for CubeScanPagedStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
 async fn load_data(
     span_id: Option<Arc<SpanId>>,
     request: V1LoadRequestQuery,
@@ -825,117 +1754,889 @@ async fn load_data(
     meta: LoadRequestMeta,
     options: CubeScanOptions,
     sql_query: Option<SqlQuery>,
+    page_size: Option<i32>,
 ) -> ArrowResult<V1LoadResult> {
-    let no_members_query = request.measures.as_ref().map(|v| v.len()).unwrap_or(0) == 0
+    let started = Instant::now();
+    let traced = meta.trace().map(|trace| {
+        (
+            trace.clone(),
+            request.clone(),
+            sql_query.as_ref().map(|q| q.sql.clone()),
+        )
+    });
+    let request_id = meta.request_id().map(|id| id.to_string());
+    let last_cube_request = meta.last_cube_request().clone();
+    let max_records_request = options.max_records.map(|_| request.clone());
+    let result = match page_size {
+        Some(page_size) => {
+            load_pages(span_id, request, auth_context, transport, meta, page_size).await
+        }
+        None => {
+            transport
+                .load(span_id, request, sql_query, auth_context, meta)
+                .await
+        }
+    };
+    if let Some((trace, request, sql)) = traced {
+        trace.record(
+            "load",
+            &request,
+            sql.as_deref(),
+            started.elapsed(),
+            match &result {
+                Ok(response) => Ok(response.results.last().map(|r| r.data.len())),
+                Err(err) => Err(err.message.clone()),
+            },
+        );
+    }
+    let result = record_cube_request(
+        &last_cube_request,
+        request_id.as_deref(),
+        "load",
+        started,
+        result,
+    );
+    let mut response = result.map_err(ArrowError::ComputeError)?;
+    if let Some(data) = response.results.pop() {
+        match (options.max_records, max_records_request, data.data.len()) {
+            (Some(max_records), Some(request), len) if len >= max_records => {
+                return Err(ArrowError::ExternalError(Box::new(
+                    MaxRecordsExceeded::error(&request, max_records, len),
+                )));
+            }
+            _ => (),
+        }
+
+        Ok(data)
+    } else {
+        Err(ArrowError::ComputeError(format!(
+            "Unable to extract result from Cube.js response",
+        )))
+    }
+}
+
+/// Remembers the request for `SHOW LAST CUBE REQUEST`, errors reference the request id, so they
+/// can be matched with logs of Cube
+fn record_cube_request<T>(
+    last_cube_request: &LastCubeRequest,
+    request_id: Option<&str>,
+    method: &str,
+    started: Instant,
+    result: std::result::Result<T, CubeError>,
+) -> std::result::Result<T, String> {
+    let request_id = match request_id {
+        Some(request_id) => request_id,
+        None => return result.map_err(|err| err.to_string()),
+    };
+
+    last_cube_request.record(CubeRequest {
+        request_id: request_id.to_string(),
+        method: method.to_string(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        error: result.as_ref().err().map(|err| err.message.clone()),
+    });
+
+    result.map_err(|err| format!("{} (Cube request id: {})", err, request_id))
+}
+
+/// With CUBESQL_STREAM_MODE, scans without a limit or with a limit above the query limit are
+/// streamed instead of being loaded at once
+fn is_stream_mode(stream_mode: bool, request: &V1LoadRequestQuery, query_limit: i32) -> bool {
+    match (stream_mode, request.limit) {
+        (true, None) => true,
+        (true, Some(limit)) if limit > query_limit => true,
+        (_, _) => false,
+    }
+}
+
+/// Request without measures and dimensions (e.g. `SELECT 1 FROM cube LIMIT 5`) isn't sent to
+/// Cube, its rows contain only literal values
+fn is_no_members_query(request: &V1LoadRequestQuery) -> bool {
+    request.measures.as_ref().map(|v| v.len()).unwrap_or(0) == 0
         && request.dimensions.as_ref().map(|v| v.len()).unwrap_or(0) == 0
         && request
             .time_dimensions
             .as_ref()
             .map(|v| v.iter().filter(|d| d.granularity.is_some()).count())
             .unwrap_or(0)
-            == 0;
-    let result = if no_members_query {
-        let limit = request.limit.unwrap_or(1);
-        let mut data = Vec::new();
-        for _ in 0..limit {
-            data.push(serde_json::Value::Null)
-        }
-        V1LoadResult::new(
-            V1LoadResultAnnotation {
-                measures: json!(Vec::<serde_json::Value>::new()),
-                dimensions: json!(Vec::<serde_json::Value>::new()),
-                segments: json!(Vec::<serde_json::Value>::new()),
-                time_dimensions: json!(Vec::<serde_json::Value>::new()),
-            },
-            data,
-        )
-    } else {
-        let result = transport
-            .load(span_id, request, sql_query, auth_context, meta)
-            .await;
-        let mut response = result.map_err(|err| ArrowError::ComputeError(err.to_string()))?;
-        if let Some(data) = response.results.pop() {
-            match (options.max_records, data.data.len()) {
-                (Some(max_records), len) if len >= max_records => {
-                    return Err(ArrowError::ComputeError(format!("One of the Cube queries exceeded the maximum row limit ({}). JOIN/UNION is not possible as it will produce incorrect results. Try filtering the results more precisely or moving post-processing functions to an outer query.", max_records)));
+            == 0
+}
+
+/// Rows are generated in memory, the limit is capped the same as for Cube queries
+fn no_members_num_rows(request: &V1LoadRequestQuery, query_limit: i32) -> usize {
+    request.limit.unwrap_or(1).clamp(0, query_limit.max(0)) as usize
+}
+
+fn no_members_batch(
+    schema: SchemaRef,
+    member_fields: &Vec<MemberField>,
+    num_rows: usize,
+) -> std::result::Result<RecordBatch, CubeError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .zip(member_fields.iter())
+        .map(|(field, member_field)| match member_field {
+            MemberField::Literal(value) if !value.is_null() => {
+                let array = value.to_array_of_size(num_rows);
+                if array.data_type() == field.data_type() {
+                    Ok(array)
+                } else {
+                    Ok(cast(&array, field.data_type())?)
+                }
+            }
+            _ => Ok(new_null_array(field.data_type(), num_rows)),
+        })
+        .collect::<std::result::Result<Vec<_>, CubeError>>()?;
+
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+/// Loads the requested rows by several queries with offsets, results are merged into the response
+/// of the first page. Cube applies the default ordering, so pages are consistent with each other.
+async fn load_pages(
+    span_id: Option<Arc<SpanId>>,
+    request: V1LoadRequestQuery,
+    auth_context: AuthContextRef,
+    transport: Arc<dyn TransportService>,
+    meta: LoadRequestMeta,
+    page_size: i32,
+) -> std::result::Result<V1LoadResponse, CubeError> {
+    let limit = request.limit.unwrap_or(page_size);
+    let offset = request.offset.unwrap_or(0);
+
+    let mut response: Option<V1LoadResponse> = None;
+    let mut loaded = 0;
+    while loaded < limit {
+        let mut page = request.clone();
+        page.offset = Some(offset + loaded);
+        page.limit = Some(page_size.min(limit - loaded));
+
+        let mut page_meta = meta.clone();
+        page_meta.update_timeout()?;
+        let mut page_response = transport
+            .load(span_id.clone(), page, None, auth_context.clone(), page_meta)
+            .await?;
+        let data = match page_response.results.pop() {
+            Some(data) => data,
+            None => {
+                return Err(CubeError::internal(
+                    "Unable to extract result from Cube.js response".to_string(),
+                ))
+            }
+        };
+
+        let received = data.data.len() as i32;
+        match response.as_mut() {
+            Some(response) => {
+                if let Some(result) = response.results.last_mut() {
+                    result.data.extend(data.data);
                 }
-                (_, _) => (),
             }
+            None => {
+                page_response.results.push(data);
+                response = Some(page_response);
+            }
+        }
 
-            data
-        } else {
-            return Err(ArrowError::ComputeError(format!(
-                "Unable to extract result from Cube.js response",
-            )));
+        loaded += received;
+        if received < page_size {
+            break;
         }
-    };
+    }
 
-    Ok(result)
+    response.ok_or_else(|| CubeError::internal("Query was not loaded".to_string()))
 }
 
-fn load_to_stream_sync(one_shot_stream: &mut CubeScanOneShotStream) -> Result<()> {
-    let span_id = one_shot_stream.span_id.clone();
-    let req = one_shot_stream.request.clone();
-    let auth = one_shot_stream.auth_context.clone();
-    let transport = one_shot_stream.transport.clone();
-    let meta = one_shot_stream.meta.clone();
-    let options = one_shot_stream.options.clone();
-    let wrapped_sql = one_shot_stream.wrapped_sql.clone();
+/// Converts the loaded rows to Arrow on the transform pool, it takes a while for big results
+async fn transform_on_pool(
+    transform_pool: &Arc<TransformPool>,
+    data: Vec<Value>,
+    schema: SchemaRef,
+    member_fields: Arc<Vec<MemberField>>,
+    date_formats: Arc<ResponseDateFormats>,
+) -> Result<RecordBatch> {
+    transform_pool
+        .run(move || {
+            let mut response = JsonValueObject::new(data);
+            transform_response(&mut response, schema, &member_fields, &date_formats)
+        })
+        .await
+        .and_then(|result| result)
+        .map_err(|e| DataFusionError::Execution(e.message.to_string()))
+}
 
-    let handle = tokio::runtime::Handle::current();
-    let res = std::thread::spawn(move || {
-        handle.block_on(load_data(
-            span_id,
-            req,
-            auth,
-            transport,
-            meta,
-            options,
-            wrapped_sql,
-        ))
-    })
-    .join()
-    .map_err(|_| DataFusionError::Execution(format!("Can't load to stream")))?;
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimestampFormat {
+    /// chrono format, formats without time parse dates at midnight
+    Pattern(String),
+    Rfc3339,
+    /// Seconds since the Unix epoch, strings and numbers are accepted
+    EpochSeconds,
+    /// Milliseconds since the Unix epoch, strings and numbers are accepted
+    EpochMillis,
+}
 
-    let mut response = JsonValueObject::new(res.unwrap().data);
-    one_shot_stream.data = Some(
-        transform_response(
-            &mut response,
-            one_shot_stream.schema.clone(),
-            &one_shot_stream.member_fields,
-        )
-        .map_err(|e| DataFusionError::Execution(e.message.to_string()))?,
-    );
+impl TimestampFormat {
+    fn parse(&self, s: &str) -> std::result::Result<NaiveDateTime, String> {
+        match self {
+            TimestampFormat::Pattern(format) => NaiveDateTime::parse_from_str(s, format)
+                .or_else(|err| {
+                    NaiveDate::parse_from_str(s, format)
+                        .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+                        .map_err(|_| err)
+                })
+                .map_err(|e| e.to_string()),
+            TimestampFormat::Rfc3339 => DateTime::parse_from_rfc3339(s)
+                .map(|dt| dt.naive_utc())
+                .map_err(|e| e.to_string()),
+            TimestampFormat::EpochSeconds => Self::parse_epoch(s, 1),
+            TimestampFormat::EpochMillis => Self::parse_epoch(s, 1000),
+        }
+    }
 
-    Ok(())
+    fn parse_epoch(s: &str, units_per_second: i64) -> std::result::Result<NaiveDateTime, String> {
+        let value = s.parse::<i64>().map_err(|e| e.to_string())?;
+        let nanos = value.rem_euclid(units_per_second) * (1_000_000_000 / units_per_second);
+
+        NaiveDateTime::from_timestamp_opt(value.div_euclid(units_per_second), nanos as u32)
+            .ok_or_else(|| "timestamp is out of range".to_string())
+    }
 }
 
-pub fn transform_response<V: ValueObject>(
-    response: &mut V,
-    schema: SchemaRef,
-    member_fields: &Vec<MemberField>,
-) -> std::result::Result<RecordBatch, CubeError> {
-    let mut columns = vec![];
+impl FromStr for TimestampFormat {
+    type Err = CubeError;
 
-    for (i, schema_field) in schema.fields().iter().enumerate() {
-        let field_name = &member_fields[i];
-        let column = match schema_field.data_type() {
-            DataType::Utf8 => {
-                build_column!(
-                    DataType::Utf8,
-                    StringBuilder,
-                    response,
-                    field_name,
-                    {
-                        (FieldValue::String(v), builder) => builder.append_value(v)?,
-                        (FieldValue::Bool(v), builder) => builder.append_value(if v { "true" } else { "false" })?,
-                        (FieldValue::Number(v), builder) => builder.append_value(v.to_string())?,
-                    },
-                    {
-                        (ScalarValue::Utf8(v), builder) => builder.append_option(v.as_ref())?,
-                    }
-                )
-            }
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rfc3339" => Ok(Self::Rfc3339),
+            "epoch_seconds" => Ok(Self::EpochSeconds),
+            "epoch_millis" => Ok(Self::EpochMillis),
+            _ if s.contains('%') => Ok(Self::Pattern(s.to_string())),
+            _ => Err(CubeError::user(format!(
+                "Unknown timestamp format: {}, expected a chrono format, rfc3339, epoch_seconds or epoch_millis",
+                s
+            ))),
+        }
+    }
+}
+
+/// Formats of timestamps and dates in Cube responses. Order matters, formats are probed from
+/// the top until the first match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseDateFormats {
+    pub formats: Vec<TimestampFormat>,
+}
+
+impl Default for ResponseDateFormats {
+    fn default() -> Self {
+        Self {
+            formats: vec![
+                TimestampFormat::Pattern("%Y-%m-%dT%H:%M:%S.%f".to_string()),
+                TimestampFormat::Pattern("%Y-%m-%d %H:%M:%S.%f".to_string()),
+                TimestampFormat::Pattern("%Y-%m-%dT%H:%M:%S".to_string()),
+                TimestampFormat::Rfc3339,
+            ],
+        }
+    }
+}
+
+impl ResponseDateFormats {
+    /// CUBESQL_RESPONSE_DATE_FORMATS is a comma separated list of formats, `default` is replaced
+    /// with the built-in formats
+    pub fn from_env() -> Self {
+        match std::env::var("CUBESQL_RESPONSE_DATE_FORMATS") {
+            Ok(value) => Self::parse(&value).unwrap_or_else(|err| {
+                warn!("Ignoring CUBESQL_RESPONSE_DATE_FORMATS: {}", err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn parse(value: &str) -> std::result::Result<Self, CubeError> {
+        let mut formats = vec![];
+        for format in value.split(',').map(|f| f.trim()).filter(|f| !f.is_empty()) {
+            if format.eq_ignore_ascii_case("default") {
+                formats.extend(Self::default().formats);
+            } else {
+                formats.push(format.parse()?);
+            }
+        }
+
+        if formats.is_empty() {
+            return Err(CubeError::user("No formats specified".to_string()));
+        }
+
+        Ok(Self { formats })
+    }
+}
+
+/// Parser for a single timestamp column. Values in a column are almost always returned in the
+/// same format, that's why format is detected on the first value and reused for the following
+/// ones. Probing of other formats happens only when the cached format doesn't match.
+#[derive(Debug)]
+pub struct TimestampColumnParser<'a> {
+    formats: &'a [TimestampFormat],
+    format: Option<usize>,
+}
+
+impl<'a> TimestampColumnParser<'a> {
+    pub fn new(formats: &'a ResponseDateFormats) -> Self {
+        Self {
+            formats: &formats.formats,
+            format: None,
+        }
+    }
+
+    pub fn detected_format(&self) -> Option<&TimestampFormat> {
+        self.format.map(|i| &self.formats[i])
+    }
+
+    pub fn parse(&mut self, s: &str) -> std::result::Result<NaiveDateTime, CubeError> {
+        if let Some(format) = self.detected_format() {
+            if let Ok(timestamp) = format.parse(s) {
+                return Ok(timestamp);
+            }
+        }
+
+        let mut last_error = None;
+
+        for (i, format) in self.formats.iter().enumerate() {
+            if self.format == Some(i) {
+                continue;
+            }
+
+            match format.parse(s) {
+                Ok(timestamp) => {
+                    if self.format.is_none() {
+                        self.format = Some(i);
+                    }
+
+                    return Ok(timestamp);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(CubeError::internal(format!(
+            "Can't parse timestamp: '{}': {}",
+            s,
+            last_error.unwrap_or_else(|| "unknown format".to_string())
+        )))
+    }
+
+    /// UTC time of a timestamp, values without an offset are local times in `timezone`
+    pub fn parse_in_timezone(
+        &mut self,
+        s: &str,
+        timezone: &Tz,
+    ) -> std::result::Result<NaiveDateTime, CubeError> {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(s) {
+            return Ok(timestamp.naive_utc());
+        }
+
+        let timestamp = self.parse(s)?;
+        timezone
+            .from_local_datetime(&timestamp)
+            .earliest()
+            .map(|timestamp| timestamp.naive_utc())
+            .ok_or_else(|| {
+                CubeError::user(format!(
+                    "Timestamp '{}' doesn't exist in time zone {}",
+                    s, timezone
+                ))
+            })
+    }
+}
+
+/// Timestamps with a time zone, primitive builders produce arrays without it
+struct TimestampTzBuilder {
+    values: Vec<Option<i64>>,
+    timezone: String,
+}
+
+impl TimestampTzBuilder {
+    fn new(capacity: usize, timezone: String) -> Self {
+        Self {
+            values: Vec::with_capacity(capacity),
+            timezone,
+        }
+    }
+
+    fn append_value(&mut self, value: i64) -> ArrowResult<()> {
+        self.values.push(Some(value));
+        Ok(())
+    }
+
+    fn append_null(&mut self) -> ArrowResult<()> {
+        self.values.push(None);
+        Ok(())
+    }
+
+    fn append_option(&mut self, value: Option<i64>) -> ArrowResult<()> {
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> TimestampNanosecondArray {
+        TimestampNanosecondArray::from_opt_vec(
+            std::mem::take(&mut self.values),
+            Some(self.timezone.clone()),
+        )
+    }
+}
+
+/// Counts can be serialized by drivers as floats (e.g. "5.0"), they are accepted while there is
+/// no fractional part
+fn parse_int64_value(s: &str) -> std::result::Result<i64, std::num::ParseIntError> {
+    s.parse::<i64>().or_else(|err| match s.parse::<f64>() {
+        Ok(v) if v.fract() == 0.0 && v.abs() < i64::MAX as f64 => Ok(v as i64),
+        _ => Err(err),
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct IntervalParts {
+    months: i32,
+    days: i32,
+    nanos: i64,
+}
+
+impl IntervalParts {
+    /// Numbers are durations in seconds, e.g. a difference of epochs
+    fn from_seconds(seconds: f64) -> std::result::Result<Self, CubeError> {
+        Ok(Self {
+            nanos: (seconds * 1_000_000_000.0).round() as i64,
+            ..Self::default()
+        })
+    }
+
+    fn add(&mut self, amount: f64, unit: &str) -> std::result::Result<(), CubeError> {
+        let nanos_in = |unit_nanos: f64| (amount * unit_nanos).round() as i64;
+        match unit.to_lowercase().as_str() {
+            "y" | "year" | "years" => self.months += (amount * 12.0) as i32,
+            "mon" | "mons" | "month" | "months" => self.months += amount as i32,
+            "w" | "week" | "weeks" => self.days += (amount * 7.0) as i32,
+            "d" | "day" | "days" => self.days += amount as i32,
+            "h" | "hour" | "hours" => self.nanos += nanos_in(3_600_000_000_000.0),
+            "min" | "mins" | "minute" | "minutes" => self.nanos += nanos_in(60_000_000_000.0),
+            "s" | "sec" | "secs" | "second" | "seconds" => self.nanos += nanos_in(1_000_000_000.0),
+            unit => {
+                return Err(CubeError::user(format!(
+                    "Unknown unit of interval: '{}'",
+                    unit
+                )))
+            }
+        };
+
+        Ok(())
+    }
+
+    fn to_day_time(&self) -> std::result::Result<i64, CubeError> {
+        if self.months != 0 {
+            return Err(CubeError::user(
+                "Interval with months can't be represented as IntervalDayTime".to_string(),
+            ));
+        }
+
+        let millis = self.nanos / 1_000_000;
+        Ok(((self.days as i64) << 32) | (millis as u32 as i64))
+    }
+
+    fn to_month_day_nano(&self) -> i128 {
+        (((self.months as u32 as u128) << 96)
+            | ((self.days as u32 as u128) << 64)
+            | (self.nanos as u64 as u128)) as i128
+    }
+}
+
+/// Intervals are sent in the Postgres format (`1 day 02:03:04`, `0 years 1 mons 0 days ...`)
+/// or as ISO 8601 durations (`P1DT2H`)
+fn parse_interval_value(s: &str) -> std::result::Result<IntervalParts, CubeError> {
+    let value = s.trim();
+    let error = || CubeError::user(format!("Can't parse interval: '{}'", s));
+    let mut interval = IntervalParts::default();
+    if value.is_empty() {
+        return Err(error());
+    }
+
+    let (negative, iso) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    if let Some(iso) = iso.strip_prefix('P') {
+        let mut in_time = false;
+        let mut amount = String::new();
+        for c in iso.chars() {
+            match c {
+                'T' if amount.is_empty() => in_time = true,
+                '0'..='9' | '.' | '-' => amount.push(c),
+                unit => {
+                    let number = amount.parse::<f64>().map_err(|_| error())?;
+                    let unit = match (unit, in_time) {
+                        ('M', true) => "min",
+                        ('M', false) => "mon",
+                        (unit, _) => match unit {
+                            'Y' => "y",
+                            'W' => "w",
+                            'D' => "d",
+                            'H' => "h",
+                            'S' => "s",
+                            _ => return Err(error()),
+                        },
+                    };
+                    interval.add(if negative { -number } else { number }, unit)?;
+                    amount.clear();
+                }
+            }
+        }
+
+        return if amount.is_empty() {
+            Ok(interval)
+        } else {
+            Err(error())
+        };
+    }
+
+    let mut tokens = value.split_whitespace();
+    while let Some(token) = tokens.next() {
+        if token.contains(':') {
+            let (sign, time) = match token.strip_prefix('-') {
+                Some(time) => (-1.0, time),
+                None => (1.0, token.strip_prefix('+').unwrap_or(token)),
+            };
+            let parts = time
+                .split(':')
+                .map(|part| part.parse::<f64>().map_err(|_| error()))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            match parts.as_slice() {
+                [hours, mins] => {
+                    interval.add(sign * hours, "h")?;
+                    interval.add(sign * mins, "min")?;
+                }
+                [hours, mins, secs] => {
+                    interval.add(sign * hours, "h")?;
+                    interval.add(sign * mins, "min")?;
+                    interval.add(sign * secs, "s")?;
+                }
+                _ => return Err(error()),
+            }
+        } else {
+            let amount = token.parse::<f64>().map_err(|_| error())?;
+            let unit = tokens.next().ok_or_else(error)?;
+            interval.add(amount, unit)?;
+        }
+    }
+
+    Ok(interval)
+}
+
+/// Scaled integer of a decimal, which doesn't fit the precision, is None
+fn decimal_from_f64(value: f64, precision: usize, scale: usize) -> Option<i128> {
+    let scaled = (value * 10_f64.powi(scale as i32)).round();
+    if !scaled.is_finite() || scaled.abs() >= 10_f64.powi(precision as i32) {
+        return None;
+    }
+
+    Some(scaled as i128)
+}
+
+/// High precision numbers are sent by Cube as strings, they are parsed to the scaled integer
+/// without going through f64, so monetary values round-trip exactly. Extra fraction digits are
+/// rounded half away from zero, other notations are parsed as f64.
+fn parse_decimal_value(s: &str, precision: usize, scale: usize) -> Option<i128> {
+    let s = s.trim();
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (integer.is_empty() && fraction.is_empty()) || !is_digits(integer) || !is_digits(fraction) {
+        return decimal_from_f64(s.parse::<f64>().ok()?, precision, scale);
+    }
+
+    let mut value: i128 = 0;
+    for digit in integer
+        .bytes()
+        .chain(fraction.bytes().chain(std::iter::repeat(b'0')).take(scale))
+    {
+        value = value.checked_mul(10)?.checked_add((digit - b'0') as i128)?;
+    }
+    if fraction.len() > scale && fraction.as_bytes()[scale] >= b'5' {
+        value = value.checked_add(1)?;
+    }
+    if value >= 10_i128.checked_pow(precision as u32)? {
+        return None;
+    }
+
+    Some(if negative { -value } else { value })
+}
+
+/// Literals can be typed differently from their columns after const folding (e.g. NULL is
+/// Boolean and numbers are Int64), they are cast to the column type before building the column
+fn coerce_literal_member_field(
+    member_field: &MemberField,
+    data_type: &DataType,
+) -> std::result::Result<MemberField, CubeError> {
+    let value = match member_field {
+        MemberField::Literal(value) if &value.get_datatype() != data_type => value,
+        _ => return Ok(member_field.clone()),
+    };
+
+    let coerced = if value.is_null() {
+        ScalarValue::try_from(data_type)
+    } else {
+        cast(&value.to_array_of_size(1), data_type)
+            .map_err(DataFusionError::from)
+            .and_then(|array| ScalarValue::try_from_array(&array, 0))
+    };
+
+    coerced.map(MemberField::Literal).map_err(|e| {
+        CubeError::user(format!(
+            "Unable to map value {:?} to {:?}: {}",
+            value, data_type, e
+        ))
+    })
+}
+
+fn parse_date_value(
+    s: &str,
+    parser: &mut TimestampColumnParser,
+) -> std::result::Result<NaiveDate, CubeError> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        // FIXME: temporary solution for cases when expected type is Date32
+        // but underlying data is a Timestamp
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%dT00:00:00.000"))
+        .or_else(|_| parser.parse(s).map(|timestamp| timestamp.date()))
+        .map_err(|e| CubeError::user(format!("Can't parse date: '{}': {}", s, e)))
+}
+
+fn days_since_epoch(date: NaiveDate) -> i32 {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    date.num_days_from_ce() - epoch.num_days_from_ce()
+}
+
+/// Time of day is sent as `HH:MM:SS[.fff]`, time of a timestamp is used otherwise
+fn parse_time_value(
+    s: &str,
+    parser: &mut TimestampColumnParser,
+) -> std::result::Result<NaiveTime, CubeError> {
+    NaiveTime::parse_from_str(s, "%H:%M:%S%.f")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .or_else(|_| parser.parse(s).map(|timestamp| timestamp.time()))
+        .map_err(|e| CubeError::user(format!("Can't parse time: '{}': {}", s, e)))
+}
+
+/// Array dimensions, elements are converted as values of the scalar columns
+fn build_list_column<V: ValueObject>(
+    response: &mut V,
+    field_name: &MemberField,
+    item_type: &DataType,
+) -> std::result::Result<ArrayRef, CubeError> {
+    let len = response.len()?;
+    let field_name = match field_name {
+        MemberField::Member(field_name) => field_name,
+        MemberField::Literal(value) => {
+            let list_type = DataType::List(Box::new(Field::new("item", item_type.clone(), true)));
+            return if value.is_null() {
+                Ok(new_null_array(&list_type, len))
+            } else {
+                Err(CubeError::user(format!(
+                    "Unable to map value {:?} to {:?}",
+                    value, list_type
+                )))
+            };
+        }
+    };
+
+    macro_rules! build_list {
+        ($values_builder:expr, { $($item_block:tt)* }) => {{
+            let mut builder = ListBuilder::new($values_builder);
+            for i in 0..len {
+                match response.get(i, field_name)? {
+                    FieldValue::Array(items) => {
+                        for item in items {
+                            match (item, builder.values()) {
+                                (FieldValue::Null, values) => values.append_null()?,
+                                $($item_block)*
+                                (v, _) => {
+                                    return Err(CubeError::user(format!(
+                                        "Unable to map value {:?} to {:?}",
+                                        v, item_type
+                                    )));
+                                }
+                            }
+                        }
+                        builder.append(true)?;
+                    }
+                    FieldValue::Null => builder.append(false)?,
+                    v => {
+                        return Err(CubeError::user(format!(
+                            "Unable to map value {:?} to a list of {:?}",
+                            v, item_type
+                        )));
+                    }
+                }
+            }
+
+            Arc::new(builder.finish()) as ArrayRef
+        }};
+    }
+
+    Ok(match item_type {
+        DataType::Utf8 => build_list!(StringBuilder::new(len), {
+            (FieldValue::String(v), values) => values.append_value(v)?,
+            (FieldValue::Number(v), values) => values.append_value(v.to_string())?,
+            (FieldValue::Bool(v), values) => values.append_value(v.to_string())?,
+        }),
+        DataType::Int64 => build_list!(Int64Builder::new(len), {
+            (FieldValue::Number(v), values) => values.append_value(v.round() as i64)?,
+            (FieldValue::String(v), values) => values.append_option(parse_int64_value(&v).ok())?,
+        }),
+        DataType::Float64 => build_list!(Float64Builder::new(len), {
+            (FieldValue::Number(v), values) => values.append_value(v)?,
+            (FieldValue::String(v), values) => values.append_option(v.parse::<f64>().ok())?,
+        }),
+        DataType::Boolean => build_list!(BooleanBuilder::new(len), {
+            (FieldValue::Bool(v), values) => values.append_value(v)?,
+            (FieldValue::String(v), values) => values.append_option(match v.as_str() {
+                "true" | "1" => Some(true),
+                "false" | "0" => Some(false),
+                _ => None,
+            })?,
+        }),
+        item_type => {
+            return Err(CubeError::user(format!(
+                "List of {:?} is not supported in response transformation",
+                item_type
+            )))
+        }
+    })
+}
+
+/// Binary values are sent as strings: hex with the Postgres `\x` prefix or base64
+/// Nested objects as a struct column, fields of the objects are mapped by the same rules as
+/// members of the response
+fn build_struct_column<V: ValueObject>(
+    response: &mut V,
+    field_name: &MemberField,
+    fields: &Vec<Field>,
+    date_formats: &ResponseDateFormats,
+) -> std::result::Result<ArrayRef, CubeError> {
+    let len = response.len()?;
+    let field_name = match field_name {
+        MemberField::Member(field_name) => field_name,
+        MemberField::Literal(value) => {
+            let struct_type = DataType::Struct(fields.clone());
+            return if value.is_null() {
+                Ok(new_null_array(&struct_type, len))
+            } else {
+                Err(CubeError::user(format!(
+                    "Unable to map value {:?} to {:?}",
+                    value, struct_type
+                )))
+            };
+        }
+    };
+
+    let mut rows = Vec::with_capacity(len);
+    let mut validity = MutableBuffer::new_null(len);
+    for i in 0..len {
+        match response.get(i, field_name)? {
+            FieldValue::Object(object) => {
+                bit_util::set_bit(validity.as_slice_mut(), i);
+                rows.push(Value::Object(object));
+            }
+            FieldValue::Null => rows.push(Value::Object(Map::new())),
+            v => {
+                return Err(CubeError::user(format!(
+                    "Unable to map value {:?} to {:?}",
+                    v,
+                    DataType::Struct(fields.clone())
+                )));
+            }
+        }
+    }
+
+    let member_fields = fields
+        .iter()
+        .map(|field| MemberField::Member(field.name().to_string()))
+        .collect();
+    let batch = transform_response(
+        &mut JsonValueObject::new(rows),
+        Arc::new(Schema::new(fields.clone())),
+        &member_fields,
+        date_formats,
+    )?;
+
+    Ok(Arc::new(StructArray::from((
+        fields
+            .iter()
+            .cloned()
+            .zip(batch.columns().iter().cloned())
+            .collect::<Vec<_>>(),
+        validity.into(),
+    ))))
+}
+
+fn decode_binary(value: &str) -> Result<Vec<u8>, CubeError> {
+    match value.strip_prefix("\\x") {
+        Some(hex) => {
+            if hex.len() % 2 != 0 {
+                return Err(CubeError::user(format!(
+                    "Hex value has an odd number of digits: {}",
+                    value
+                )));
+            }
+
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| {
+                    hex.get(i..i + 2)
+                        .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                        .ok_or_else(|| CubeError::user(format!("Invalid hex value: {}", value)))
+                })
+                .collect()
+        }
+        None => Ok(base64::decode(value)?),
+    }
+}
+
+pub fn transform_response<V: ValueObject>(
+    response: &mut V,
+    schema: SchemaRef,
+    member_fields: &Vec<MemberField>,
+    date_formats: &ResponseDateFormats,
+) -> std::result::Result<RecordBatch, CubeError> {
+    let mut columns = vec![];
+    let member_fields = match response.keys()? {
+        Some(keys) => match_response_keys(member_fields, &keys),
+        None => member_fields.clone(),
+    };
+
+    for (i, schema_field) in schema.fields().iter().enumerate() {
+        let field_name = &coerce_literal_member_field(&member_fields[i], schema_field.data_type())?;
+        let column = match schema_field.data_type() {
+            DataType::Utf8 => {
+                build_column!(
+                    DataType::Utf8,
+                    StringBuilder,
+                    response,
+                    field_name,
+                    {
+                        (FieldValue::String(v), builder) => builder.append_value(v)?,
+                        (FieldValue::Bool(v), builder) => builder.append_value(if v { "true" } else { "false" })?,
+                        (FieldValue::Number(v), builder) => builder.append_value(v.to_string())?,
+                        (FieldValue::Object(v), builder) => builder.append_value(Value::Object(v).to_string())?,
+                        (v @ FieldValue::Array(_), builder) => builder.append_value(v.into_json().to_string())?,
+                    },
+                    {
+                        (ScalarValue::Utf8(v), builder) => builder.append_option(v.as_ref())?,
+                    }
+                )
+            }
             DataType::Int32 => {
                 build_column!(
                     DataType::Int32,
@@ -969,7 +2670,7 @@ pub fn transform_response<V: ValueObject>(
                     field_name,
                     {
                         (FieldValue::Number(number), builder) => builder.append_value(number.round() as i64)?,
-                        (FieldValue::String(s), builder) => match s.parse::<i64>() {
+                        (FieldValue::String(s), builder) => match parse_int64_value(&s) {
                             Ok(v) => builder.append_value(v)?,
                             Err(error) => {
                                 warn!(
@@ -1011,6 +2712,45 @@ pub fn transform_response<V: ValueObject>(
                     }
                 )
             }
+            DataType::Decimal(precision, scale) => {
+                let (precision, scale) = (*precision, *scale);
+                build_column!(
+                    DataType::Decimal(precision, scale),
+                    new = |len| DecimalBuilder::new(len, precision, scale),
+                    response,
+                    field_name,
+                    {
+                        (FieldValue::Number(number), builder) => match decimal_from_f64(number, precision, scale) {
+                            Some(v) => builder.append_value(v)?,
+                            None => {
+                                warn!(
+                                    "Unable to map value {} to Decimal({}, {})",
+                                    number, precision, scale
+                                );
+
+                                builder.append_null()?
+                            }
+                        },
+                        (FieldValue::String(s), builder) => match parse_decimal_value(&s, precision, scale) {
+                            Some(v) => builder.append_value(v)?,
+                            None => {
+                                warn!(
+                                    "Unable to parse value as Decimal({}, {}): {}",
+                                    precision, scale, s
+                                );
+
+                                builder.append_null()?
+                            }
+                        },
+                    },
+                    {
+                        (ScalarValue::Decimal128(v, _, _), builder) => match v {
+                            Some(v) => builder.append_value(*v)?,
+                            None => builder.append_null()?,
+                        },
+                    }
+                )
+            }
             DataType::Boolean => {
                 build_column!(
                     DataType::Boolean,
@@ -1035,29 +2775,28 @@ pub fn transform_response<V: ValueObject>(
                 )
             }
             DataType::Timestamp(TimeUnit::Nanosecond, None) => {
+                let mut parser = TimestampColumnParser::new(date_formats);
+                let mut append = |builder: &mut TimestampNanosecondBuilder,
+                                  s: &str|
+                 -> std::result::Result<(), CubeError> {
+                    let timestamp = parser.parse(s)?;
+                    // TODO switch parsing to microseconds
+                    if timestamp.timestamp_millis() > (((1 as i64) << 62) / 1_000_000) {
+                        builder.append_null()?;
+                    } else {
+                        builder.append_value(timestamp.timestamp_nanos())?;
+                    }
+                    Ok(())
+                };
                 build_column!(
                     DataType::Timestamp(TimeUnit::Nanosecond, None),
                     TimestampNanosecondBuilder,
                     response,
                     field_name,
                     {
-                        (FieldValue::String(s), builder) => {
-                            let timestamp = NaiveDateTime::parse_from_str(s.as_str(), "%Y-%m-%dT%H:%M:%S.%f")
-                                .or_else(|_| NaiveDateTime::parse_from_str(s.as_str(), "%Y-%m-%d %H:%M:%S.%f"))
-                                .or_else(|_| NaiveDateTime::parse_from_str(s.as_str(), "%Y-%m-%dT%H:%M:%S"))
-                                .map_err(|e| {
-                                    DataFusionError::Execution(format!(
-                                        "Can't parse timestamp: '{}': {}",
-                                        s, e
-                                    ))
-                                })?;
-                            // TODO switch parsing to microseconds
-                            if timestamp.timestamp_millis() > (((1 as i64) << 62) / 1_000_000) {
-                                builder.append_null()?;
-                            } else {
-                                builder.append_value(timestamp.timestamp_nanos())?;
-                            }
-                        },
+                        (FieldValue::String(s), builder) => append(builder, s.as_str())?,
+                        // Epoch formats accept numbers
+                        (FieldValue::Number(n), builder) => append(builder, &n.to_string())?,
                     },
                     {
                         (ScalarValue::TimestampNanosecond(v, None), builder) => builder.append_option(v.clone())?,
@@ -1065,76 +2804,253 @@ pub fn transform_response<V: ValueObject>(
                 )
             }
             DataType::Timestamp(TimeUnit::Millisecond, None) => {
+                let mut parser = TimestampColumnParser::new(date_formats);
+                let mut append = |builder: &mut TimestampMillisecondBuilder,
+                                  s: &str|
+                 -> std::result::Result<(), CubeError> {
+                    let timestamp = parser.parse(s)?;
+                    // TODO switch parsing to microseconds
+                    if timestamp.timestamp_millis() > (((1 as i64) << 62) / 1_000_000) {
+                        builder.append_null()?;
+                    } else {
+                        builder.append_value(timestamp.timestamp_millis())?;
+                    }
+                    Ok(())
+                };
                 build_column!(
                     DataType::Timestamp(TimeUnit::Millisecond, None),
                     TimestampMillisecondBuilder,
                     response,
                     field_name,
                     {
-                        (FieldValue::String(s), builder) => {
-                            let timestamp = NaiveDateTime::parse_from_str(s.as_str(), "%Y-%m-%dT%H:%M:%S.%f")
-                                .or_else(|_| NaiveDateTime::parse_from_str(s.as_str(), "%Y-%m-%d %H:%M:%S.%f"))
-                                .or_else(|_| NaiveDateTime::parse_from_str(s.as_str(), "%Y-%m-%dT%H:%M:%S"))
-                                .map_err(|e| {
-                                    DataFusionError::Execution(format!(
-                                        "Can't parse timestamp: '{}': {}",
-                                        s, e
-                                    ))
-                                })?;
-                            // TODO switch parsing to microseconds
-                            if timestamp.timestamp_millis() > (((1 as i64) << 62) / 1_000_000) {
-                                builder.append_null()?;
-                            } else {
-                                builder.append_value(timestamp.timestamp_millis())?;
-                            }
-                        },
+                        (FieldValue::String(s), builder) => append(builder, s.as_str())?,
+                        // Epoch formats accept numbers
+                        (FieldValue::Number(n), builder) => append(builder, &n.to_string())?,
                     },
                     {
                         (ScalarValue::TimestampMillisecond(v, None), builder) => builder.append_option(v.clone())?,
                     }
                 )
             }
-            DataType::Date32 => {
+            DataType::Timestamp(TimeUnit::Nanosecond, Some(tz)) => {
+                let timezone = tz.parse::<Tz>().map_err(|err| {
+                    CubeError::user(format!(
+                        "Unknown time zone of column '{}': {}",
+                        schema_field.name(),
+                        err
+                    ))
+                })?;
+                let mut parser = TimestampColumnParser::new(date_formats);
+                let mut append = |builder: &mut TimestampTzBuilder,
+                                  s: &str|
+                 -> std::result::Result<(), CubeError> {
+                    let timestamp = parser.parse_in_timezone(s, &timezone)?;
+                    if timestamp.timestamp_millis() > (((1 as i64) << 62) / 1_000_000) {
+                        builder.append_null()?;
+                    } else {
+                        builder.append_value(timestamp.timestamp_nanos())?;
+                    }
+                    Ok(())
+                };
                 build_column!(
-                    DataType::Date32,
-                    Date32Builder,
+                    DataType::Timestamp(TimeUnit::Nanosecond, Some(tz.clone())),
+                    new = |len| TimestampTzBuilder::new(len, tz.clone()),
                     response,
                     field_name,
                     {
-                        (FieldValue::String(s), builder) => {
-                            let date = NaiveDate::parse_from_str(s.as_str(), "%Y-%m-%d")
-                                // FIXME: temporary solution for cases when expected type is Date32
-                                // but underlying data is a Timestamp
-                                .or_else(|_| NaiveDate::parse_from_str(s.as_str(), "%Y-%m-%dT00:00:00.000"))
-                                .map_err(|e| {
-                                    DataFusionError::Execution(format!(
-                                        "Can't parse date: '{}': {}",
-                                        s, e
-                                    ))
-                                });
-                            match date {
-                                Ok(date) => {
-                                    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
-                                    let days_since_epoch = date.num_days_from_ce()  - epoch.num_days_from_ce();
-                                    builder.append_value(days_since_epoch)?;
-                                }
-                                Err(error) => {
-                                    log::error!(
-                                        "Unable to parse value as Date32: {}",
-                                        error.to_string()
-                                    );
-
-                                    builder.append_null()?
-                                }
-                            }
-                        }
+                        (FieldValue::String(s), builder) => append(builder, s.as_str())?,
+                        (FieldValue::Number(n), builder) => append(builder, &n.to_string())?,
                     },
                     {
-                        (ScalarValue::Date32(v), builder) => builder.append_option(v.clone())?,
+                        (ScalarValue::TimestampNanosecond(v, _), builder) => builder.append_option(v.clone())?,
                     }
                 )
             }
-            t => {
+            DataType::Date32 => {
+                let mut parser = TimestampColumnParser::new(date_formats);
+                build_column!(
+                    DataType::Date32,
+                    Date32Builder,
+                    response,
+                    field_name,
+                    {
+                        (FieldValue::String(s), builder) => match parse_date_value(s.as_str(), &mut parser) {
+                            Ok(date) => builder.append_value(days_since_epoch(date))?,
+                            Err(error) => {
+                                log::error!(
+                                    "Unable to parse value as Date32: {}",
+                                    error.to_string()
+                                );
+
+                                builder.append_null()?
+                            }
+                        },
+                    },
+                    {
+                        (ScalarValue::Date32(v), builder) => builder.append_option(v.clone())?,
+                    }
+                )
+            }
+            DataType::Date64 => {
+                let mut parser = TimestampColumnParser::new(date_formats);
+                build_column!(
+                    DataType::Date64,
+                    Date64Builder,
+                    response,
+                    field_name,
+                    {
+                        (FieldValue::String(s), builder) => match parse_date_value(s.as_str(), &mut parser) {
+                            Ok(date) => builder.append_value(days_since_epoch(date) as i64 * 86_400_000)?,
+                            Err(error) => {
+                                log::error!(
+                                    "Unable to parse value as Date64: {}",
+                                    error.to_string()
+                                );
+
+                                builder.append_null()?
+                            }
+                        },
+                    },
+                    {
+                        (ScalarValue::Date64(v), builder) => builder.append_option(v.clone())?,
+                    }
+                )
+            }
+            DataType::Time64(TimeUnit::Microsecond) => {
+                let mut parser = TimestampColumnParser::new(date_formats);
+                build_column!(
+                    DataType::Time64(TimeUnit::Microsecond),
+                    Time64MicrosecondBuilder,
+                    response,
+                    field_name,
+                    {
+                        (FieldValue::String(s), builder) => match parse_time_value(s.as_str(), &mut parser) {
+                            Ok(time) => builder.append_value(
+                                time.num_seconds_from_midnight() as i64 * 1_000_000
+                                    + time.nanosecond() as i64 / 1_000,
+                            )?,
+                            Err(error) => {
+                                log::error!(
+                                    "Unable to parse value as Time64: {}",
+                                    error.to_string()
+                                );
+
+                                builder.append_null()?
+                            }
+                        },
+                    },
+                    {}
+                )
+            }
+            DataType::Interval(IntervalUnit::DayTime) => {
+                let append = |builder: &mut IntervalDayTimeBuilder,
+                              value: std::result::Result<IntervalParts, CubeError>|
+                 -> std::result::Result<(), CubeError> {
+                    match value.and_then(|interval| interval.to_day_time()) {
+                        Ok(v) => builder.append_value(v)?,
+                        Err(error) => {
+                            warn!("Unable to parse value as IntervalDayTime: {}", error);
+
+                            builder.append_null()?
+                        }
+                    }
+                    Ok(())
+                };
+                build_column!(
+                    DataType::Interval(IntervalUnit::DayTime),
+                    IntervalDayTimeBuilder,
+                    response,
+                    field_name,
+                    {
+                        (FieldValue::String(s), builder) => append(builder, parse_interval_value(s.as_str()))?,
+                        (FieldValue::Number(n), builder) => append(builder, IntervalParts::from_seconds(n))?,
+                    },
+                    {
+                        (ScalarValue::IntervalDayTime(v), builder) => builder.append_option(v.clone())?,
+                    }
+                )
+            }
+            DataType::Interval(IntervalUnit::MonthDayNano) => {
+                let append = |builder: &mut IntervalMonthDayNanoBuilder,
+                              value: std::result::Result<IntervalParts, CubeError>|
+                 -> std::result::Result<(), CubeError> {
+                    match value {
+                        Ok(interval) => builder.append_value(interval.to_month_day_nano())?,
+                        Err(error) => {
+                            warn!("Unable to parse value as IntervalMonthDayNano: {}", error);
+
+                            builder.append_null()?
+                        }
+                    }
+                    Ok(())
+                };
+                build_column!(
+                    DataType::Interval(IntervalUnit::MonthDayNano),
+                    IntervalMonthDayNanoBuilder,
+                    response,
+                    field_name,
+                    {
+                        (FieldValue::String(s), builder) => append(builder, parse_interval_value(s.as_str()))?,
+                        (FieldValue::Number(n), builder) => append(builder, IntervalParts::from_seconds(n))?,
+                    },
+                    {
+                        (ScalarValue::IntervalMonthDayNano(v), builder) => builder.append_option(v.clone())?,
+                    }
+                )
+            }
+            DataType::List(field) => build_list_column(response, field_name, field.data_type())?,
+            DataType::Struct(fields) => {
+                build_struct_column(response, field_name, fields, date_formats)?
+            }
+            DataType::Binary => {
+                build_column!(
+                    DataType::Binary,
+                    BinaryBuilder,
+                    response,
+                    field_name,
+                    {
+                        (FieldValue::String(s), builder) => match decode_binary(s.as_str()) {
+                            Ok(v) => builder.append_value(v)?,
+                            Err(error) => {
+                                warn!("Unable to decode value as binary: {}", error);
+
+                                builder.append_null()?
+                            }
+                        },
+                    },
+                    {
+                        (ScalarValue::Binary(v), builder) => match v {
+                            Some(v) => builder.append_value(v)?,
+                            None => builder.append_null()?,
+                        },
+                    }
+                )
+            }
+            DataType::LargeBinary => {
+                build_column!(
+                    DataType::LargeBinary,
+                    LargeBinaryBuilder,
+                    response,
+                    field_name,
+                    {
+                        (FieldValue::String(s), builder) => match decode_binary(s.as_str()) {
+                            Ok(v) => builder.append_value(v)?,
+                            Err(error) => {
+                                warn!("Unable to decode value as binary: {}", error);
+
+                                builder.append_null()?
+                            }
+                        },
+                    },
+                    {
+                        (ScalarValue::LargeBinary(v), builder) => match v {
+                            Some(v) => builder.append_value(v)?,
+                            None => builder.append_null()?,
+                        },
+                    }
+                )
+            }
+            t => {
                 return Err(CubeError::user(format!(
                     "Type {} is not supported in response transformation from Cube",
                     t,
@@ -1152,7 +3068,10 @@ pub fn transform_response<V: ValueObject>(
 mod tests {
     use super::*;
     use crate::{
-        compile::{engine::df::wrapper::SqlQuery, MetaContext},
+        compile::{
+            engine::df::{planner::ensure_cube_scan_ordering, wrapper::SqlQuery},
+            MetaContext,
+        },
         sql::{session::DatabaseProtocol, HttpAuthContext},
         transport::SqlResponse,
         CubeError,
@@ -1160,16 +3079,27 @@ mod tests {
     use cubeclient::models::V1LoadResponse;
     use datafusion::{
         arrow::{
-            array::{BooleanArray, Float64Array, StringArray, TimestampNanosecondArray},
+            array::{
+                BinaryArray, BooleanArray, Date32Array, Date64Array, DecimalArray, Float64Array,
+                Int64Array, IntervalDayTimeArray, IntervalMonthDayNanoArray, ListArray,
+                StringArray, Time64MicrosecondArray, TimestampMillisecondArray,
+                TimestampNanosecondArray,
+            },
             datatypes::{Field, Schema},
         },
         execution::{
             context::TaskContext,
             runtime_env::{RuntimeConfig, RuntimeEnv},
         },
-        physical_plan::common,
+        logical_plan::{lit, DFSchema, EmptyRelation, Extension},
+        optimizer::utils::from_plan,
+        physical_plan::{
+            coalesce_partitions::CoalescePartitionsExec, common, projection::ProjectionExec,
+            repartition::RepartitionExec, sorts::sort::SortExec,
+        },
         scalar::ScalarValue,
     };
+    use serde_json::json;
     use std::{collections::HashMap, result::Result};
 
     fn get_test_load_meta(protocol: DatabaseProtocol) -> LoadRequestMeta {
@@ -1207,7 +3137,7 @@ mod tests {
             async fn load(
                 &self,
                 _span_id: Option<Arc<SpanId>>,
-                _query: V1LoadRequestQuery,
+                query: V1LoadRequestQuery,
                 _sql_query: Option<SqlQuery>,
                 _ctx: AuthContextRef,
                 _meta_fields: LoadRequestMeta,
@@ -1230,7 +3160,10 @@ mod tests {
                     }
                 "#;
 
-                let result: V1LoadResult = serde_json::from_str(response).unwrap();
+                let mut result: V1LoadResult = serde_json::from_str(response).unwrap();
+                let offset = query.offset.unwrap_or(0) as usize;
+                let limit = query.limit.unwrap_or(i32::MAX) as usize;
+                result.data = result.data.into_iter().skip(offset).take(limit).collect();
 
                 Ok(V1LoadResponse {
                     pivot_query: None,
@@ -1305,17 +3238,19 @@ mod tests {
 
         let scan_node = CubeScanExecutionPlan {
             schema: schema.clone(),
-            member_fields: schema
-                .fields()
-                .iter()
-                .map(|f| {
-                    if f.name() == "KibanaSampleDataEcommerce.is_female" {
-                        MemberField::Literal(ScalarValue::Boolean(None))
-                    } else {
-                        MemberField::Member(f.name().to_string())
-                    }
-                })
-                .collect(),
+            member_fields: Arc::new(
+                schema
+                    .fields()
+                    .iter()
+                    .map(|f| {
+                        if f.name() == "KibanaSampleDataEcommerce.is_female" {
+                            MemberField::Literal(ScalarValue::Boolean(None))
+                        } else {
+                            MemberField::Member(f.name().to_string())
+                        }
+                    })
+                    .collect(),
+            ),
             request: V1LoadRequestQuery {
                 measures: Some(vec![
                     "KibanaSampleDataEcommerce.count".to_string(),
@@ -1332,6 +3267,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             },
             wrapped_sql: None,
             auth_context: Arc::new(HttpAuthContext {
@@ -1345,6 +3281,16 @@ mod tests {
             transport: get_test_transport(),
             meta: get_test_load_meta(DatabaseProtocol::PostgreSQL),
             span_id: None,
+            ordering: None,
+            member_usage: Arc::new(MemberUsageStats::new()),
+            load_limiter: Arc::new(LoadLimiter::new(0)),
+            transform_pool: Arc::new(TransformPool::new(1)),
+            date_formats: Arc::new(ResponseDateFormats::default()),
+            result_processors: Arc::new(ResultProcessors::default()),
+            stream_mode: false,
+            shared: None,
+            fetch_size: None,
+            dictionary_max_ratio: None,
         };
 
         let runtime = Arc::new(
@@ -1358,7 +3304,7 @@ mod tests {
             HashMap::new(),
             runtime,
         ));
-        let stream = scan_node.execute(0, task).await.unwrap();
+        let stream = scan_node.execute(0, task.clone()).await.unwrap();
         let batches = common::collect(stream).await.unwrap();
 
         assert_eq!(
@@ -1405,6 +3351,1076 @@ mod tests {
                 ],
             )
             .unwrap()
+        );
+
+        // Cursor fetching by 2 rows: the first page has the fetch size, the next one is doubled
+        let scan_node = CubeScanExecutionPlan {
+            fetch_size: Some(2),
+            ..scan_node
+        };
+        let stream = scan_node.execute(0, task).await.unwrap();
+        let paged_batches = common::collect(stream).await.unwrap();
+
+        assert_eq!(
+            paged_batches
+                .iter()
+                .map(|batch| batch.num_rows())
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(
+            paged_batches[1].column(4),
+            &batches[0].column(4).slice(2, 3)
+        );
+    }
+
+    #[test]
+    fn test_timestamp_column_parser() -> Result<(), CubeError> {
+        let date_formats = ResponseDateFormats::default();
+        let mut parser = TimestampColumnParser::new(&date_formats);
+
+        assert_eq!(
+            parser.parse("2022-01-01 00:00:00.000")?.timestamp_nanos(),
+            1640995200000000000
+        );
+        assert_eq!(
+            parser.detected_format(),
+            Some(&TimestampFormat::Pattern(
+                "%Y-%m-%d %H:%M:%S.%f".to_string()
+            ))
+        );
+
+        // Fallback to other formats must not override detected format
+        assert_eq!(
+            parser.parse("2022-01-01T00:00:00")?.timestamp_nanos(),
+            1640995200000000000
+        );
+        assert_eq!(
+            parser.detected_format(),
+            Some(&TimestampFormat::Pattern(
+                "%Y-%m-%d %H:%M:%S.%f".to_string()
+            ))
+        );
+
+        // RFC3339 with offsets is normalized to UTC
+        assert_eq!(
+            parser
+                .parse("2022-01-01T03:00:00.000+03:00")?
+                .timestamp_nanos(),
+            1640995200000000000
+        );
+        assert_eq!(
+            parser.parse("2022-01-01T00:00:00Z")?.timestamp_nanos(),
+            1640995200000000000
+        );
+
+        assert!(parser.parse("not a timestamp").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_date_formats() -> Result<(), CubeError> {
+        let date_formats = ResponseDateFormats::parse("%d.%m.%Y, epoch_millis, default")?;
+        assert_eq!(date_formats.formats.len(), 6);
+        assert!(ResponseDateFormats::parse("dd.mm.yyyy").is_err());
+
+        let mut parser = TimestampColumnParser::new(&date_formats);
+        assert_eq!(
+            parser.parse("01.01.2022")?.timestamp_nanos(),
+            1640995200000000000
+        );
+        assert_eq!(
+            parser.parse("1640995200123")?.timestamp_nanos(),
+            1640995200123000000
+        );
+        assert_eq!(
+            parser.parse("2022-01-01T00:00:00")?.timestamp_nanos(),
+            1640995200000000000
+        );
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(
+                "KibanaSampleDataEcommerce.order_date",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                true,
+            ),
+            Field::new("KibanaSampleDataEcommerce.day", DataType::Date32, true),
+        ]));
+        let member_fields = schema
+            .fields()
+            .iter()
+            .map(|f| MemberField::Member(f.name().to_string()))
+            .collect();
+        let mut response = JsonValueObject::new(vec![json!({
+            "KibanaSampleDataEcommerce.order_date": 1640995200000_i64,
+            "KibanaSampleDataEcommerce.day": "02.01.2022",
+        })]);
+        let batch = transform_response(&mut response, schema, &member_fields, &date_formats)?;
+        assert_eq!(
+            batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<TimestampMillisecondArray>()
+                .unwrap(),
+            &TimestampMillisecondArray::from(vec![1640995200000])
+        );
+        assert_eq!(
+            batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<Date32Array>()
+                .unwrap(),
+            &Date32Array::from(vec![18994])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_ordering_for_request() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("KibanaSampleDataEcommerce.count", DataType::Int64, true),
+            Field::new(
+                "KibanaSampleDataEcommerce.customerGender",
+                DataType::Utf8,
+                false,
+            ),
+        ]));
+        let member_fields = schema
+            .fields()
+            .iter()
+            .map(|f| MemberField::Member(f.name().to_string()))
+            .collect::<Vec<_>>();
+        let mut request = V1LoadRequestQuery::new();
+
+        assert!(output_ordering_for_request(&request, &member_fields, &schema).is_none());
+
+        request.order = Some(vec![
+            vec![
+                "KibanaSampleDataEcommerce.customerGender".to_string(),
+                "desc".to_string(),
+            ],
+            // Not projected, ordering can't be expressed from here
+            vec![
+                "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+                "asc".to_string(),
+            ],
+            vec![
+                "KibanaSampleDataEcommerce.count".to_string(),
+                "asc".to_string(),
+            ],
+        ]);
+
+        let ordering = output_ordering_for_request(&request, &member_fields, &schema).unwrap();
+        assert_eq!(ordering.len(), 1);
+        assert_eq!(
+            ordering[0]
+                .expr
+                .as_any()
+                .downcast_ref::<PhysicalColumn>()
+                .unwrap(),
+            &PhysicalColumn::new("KibanaSampleDataEcommerce.customerGender", 1)
+        );
+        assert!(ordering[0].options.descending);
+
+        // NULLs can be placed anywhere by the data source
+        request.order = Some(vec![vec![
+            "KibanaSampleDataEcommerce.count".to_string(),
+            "desc".to_string(),
+        ]]);
+        assert!(output_ordering_for_request(&request, &member_fields, &schema).is_none());
+
+        let sort_exprs = request_sort_exprs(&request, &member_fields, &schema);
+        assert_eq!(sort_exprs.len(), 1);
+        assert_eq!(
+            sort_exprs[0]
+                .expr
+                .as_any()
+                .downcast_ref::<PhysicalColumn>()
+                .unwrap(),
+            &PhysicalColumn::new("KibanaSampleDataEcommerce.count", 0)
+        );
+        assert!(sort_exprs[0].options.descending);
+        assert!(sort_exprs[0].options.nulls_first);
+    }
+
+    #[test]
+    fn test_ordered_scan_physical_plan() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("KibanaSampleDataEcommerce.count", DataType::Int64, false),
+            Field::new(
+                "KibanaSampleDataEcommerce.customerGender",
+                DataType::Utf8,
+                true,
+            ),
+        ]));
+        let member_fields = Arc::new(
+            schema
+                .fields()
+                .iter()
+                .map(|f| MemberField::Member(f.name().to_string()))
+                .collect::<Vec<_>>(),
+        );
+        let mut request = V1LoadRequestQuery::new();
+        request.order = Some(vec![
+            vec![
+                "KibanaSampleDataEcommerce.count".to_string(),
+                "desc".to_string(),
+            ],
+            vec![
+                "KibanaSampleDataEcommerce.customerGender".to_string(),
+                "asc".to_string(),
+            ],
+        ]);
+
+        let scan: Arc<dyn ExecutionPlan> = Arc::new(CubeScanExecutionPlan {
+            ordering: output_ordering_for_request(&request, &member_fields, &schema),
+            schema: schema.clone(),
+            member_fields,
+            request,
+            wrapped_sql: None,
+            auth_context: Arc::new(HttpAuthContext {
+                access_token: "access_token".to_string(),
+                base_path: "base_path".to_string(),
+            }),
+            options: CubeScanOptions {
+                change_user: None,
+                max_records: None,
+            },
+            transport: get_test_transport(),
+            meta: get_test_load_meta(DatabaseProtocol::PostgreSQL),
+            span_id: None,
+            member_usage: Arc::new(MemberUsageStats::new()),
+            load_limiter: Arc::new(LoadLimiter::new(0)),
+            transform_pool: Arc::new(TransformPool::new(1)),
+            date_formats: Arc::new(ResponseDateFormats::default()),
+            result_processors: Arc::new(ResultProcessors::default()),
+            stream_mode: false,
+            shared: None,
+            fetch_size: None,
+            dictionary_max_ratio: None,
+        });
+        // NULLs of the nullable column can be placed anywhere by the data source
+        assert_eq!(scan.output_ordering().map(|o| o.len()), Some(1));
+
+        // Read in a single partition, the order of the scan is kept
+        let projection: Arc<dyn ExecutionPlan> = Arc::new(
+            ProjectionExec::try_new(
+                vec![
+                    (
+                        Arc::new(PhysicalColumn::new(schema.field(1).name(), 1)),
+                        "gender".to_string(),
+                    ),
+                    (
+                        Arc::new(PhysicalColumn::new(schema.field(0).name(), 0)),
+                        "count".to_string(),
+                    ),
+                ],
+                scan.clone(),
+            )
+            .unwrap(),
+        );
+        let plan = ensure_cube_scan_ordering(projection.clone()).unwrap();
+        assert!(Arc::ptr_eq(&plan, &projection));
+
+        // Rows are interleaved by the repartitioning, they are sorted again above it
+        let repartition =
+            Arc::new(RepartitionExec::try_new(scan, Partitioning::RoundRobinBatch(4)).unwrap());
+        let plan =
+            ensure_cube_scan_ordering(projection.with_new_children(vec![repartition]).unwrap())
+                .unwrap();
+        let sort = plan.children()[0].clone();
+        let sort = sort.as_any().downcast_ref::<SortExec>().unwrap();
+        assert_eq!(
+            sort.expr()
+                .iter()
+                .map(|sort_expr| (
+                    sort_expr.expr.to_string(),
+                    sort_expr.options.descending,
+                    sort_expr.options.nulls_first
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("KibanaSampleDataEcommerce.count@0".to_string(), true, true),
+                (
+                    "KibanaSampleDataEcommerce.customerGender@1".to_string(),
+                    false,
+                    false
+                ),
+            ]
+        );
+        assert_eq!(sort.output_partitioning().partition_count(), 1);
+        assert!(sort.input().as_any().is::<CoalescePartitionsExec>());
+    }
+
+    #[test]
+    fn test_transform_response_count_as_int64() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "KibanaSampleDataEcommerce.count",
+            DataType::Int64,
+            true,
+        )]));
+        let member_fields = vec![MemberField::Member(
+            "KibanaSampleDataEcommerce.count".to_string(),
+        )];
+
+        let mut response = JsonValueObject::new(
+            vec![
+                json!(5),
+                json!(5.0),
+                json!("5"),
+                json!("5.0"),
+                json!("5.5"),
+                json!(null),
+            ]
+            .into_iter()
+            .map(|v| json!({ "KibanaSampleDataEcommerce.count": v }))
+            .collect(),
+        );
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
         )
+        .unwrap();
+
+        assert_eq!(
+            batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap(),
+            &Int64Array::from(vec![Some(5), Some(5), Some(5), Some(5), None, None])
+        );
+    }
+
+    #[test]
+    fn test_transform_response_binary() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "payload",
+            DataType::Binary,
+            true,
+        )]));
+        let member_fields = vec![MemberField::Member("payload".to_string())];
+
+        let mut response = JsonValueObject::new(
+            vec![
+                json!("\\xdead01"),
+                json!("3q0B"),
+                json!(null),
+                json!("\\xdea"),
+            ]
+            .into_iter()
+            .map(|v| json!({ "payload": v }))
+            .collect(),
+        );
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
+        )
+        .unwrap();
+
+        let column = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .unwrap();
+        assert_eq!(column.value(0), &[0xde, 0xad, 0x01]);
+        assert_eq!(column.value(1), &[0xde, 0xad, 0x01]);
+        assert!(column.is_null(2));
+        // Odd number of hex digits
+        assert!(column.is_null(3));
+    }
+
+    #[test]
+    fn test_transform_response_literal_coercion() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("count", DataType::Int64, true),
+            Field::new("label", DataType::Utf8, true),
+            Field::new("ratio", DataType::Float64, true),
+            Field::new("day", DataType::Date32, true),
+            Field::new("flag", DataType::Boolean, true),
+        ]));
+        let member_fields = vec![
+            MemberField::Literal(ScalarValue::Utf8(Some("5".to_string()))),
+            MemberField::Literal(ScalarValue::Boolean(None)),
+            MemberField::Literal(ScalarValue::Int64(Some(2))),
+            MemberField::Literal(ScalarValue::Utf8(None)),
+            MemberField::Literal(ScalarValue::Boolean(Some(true))),
+        ];
+        let mut response = JsonValueObject::new(vec![json!({}), json!({})]);
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap(),
+            &Int64Array::from(vec![5; 2])
+        );
+        assert_eq!(batch.column(1).null_count(), 2);
+        assert_eq!(
+            batch
+                .column(2)
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap(),
+            &Float64Array::from(vec![2.0; 2])
+        );
+        assert_eq!(batch.column(3).null_count(), 2);
+        assert_eq!(batch.column(4).null_count(), 0);
+    }
+
+    #[test]
+    fn test_transform_response_decimal() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "KibanaSampleDataEcommerce.taxful_total_price",
+            DataType::Decimal(18, 2),
+            true,
+        )]));
+        let member_fields = vec![MemberField::Member(
+            "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+        )];
+        let mut response = JsonValueObject::new(
+            [
+                json!("12345678901234.57"),
+                json!(10.5),
+                json!("-0.005"),
+                json!(null),
+                json!("1e2"),
+                json!("123456789012345678"),
+            ]
+            .into_iter()
+            .map(|v| json!({ "KibanaSampleDataEcommerce.taxful_total_price": v }))
+            .collect(),
+        );
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
+        )
+        .unwrap();
+
+        let column = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<DecimalArray>()
+            .unwrap();
+        assert_eq!(column.value(0), 1234567890123457);
+        assert_eq!(column.value(1), 1050);
+        // Rounded half away from zero
+        assert_eq!(column.value(2), -1);
+        assert!(column.is_null(3));
+        assert_eq!(column.value(4), 10000);
+        // Exceeds the precision
+        assert!(column.is_null(5));
+    }
+
+    #[test]
+    fn test_transform_response_date64_and_time64() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("day", DataType::Date64, true),
+            Field::new("time", DataType::Time64(TimeUnit::Microsecond), true),
+        ]));
+        let member_fields = vec![
+            MemberField::Member("day".to_string()),
+            MemberField::Member("time".to_string()),
+        ];
+        let mut response = JsonValueObject::new(vec![
+            json!({ "day": "2023-01-02", "time": "13:45:30.125" }),
+            json!({ "day": "2023-01-02T10:20:30.000", "time": "2023-01-02T10:20:30.000" }),
+            json!({ "day": null, "time": "invalid" }),
+        ]);
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
+        )
+        .unwrap();
+
+        let days = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Date64Array>()
+            .unwrap();
+        assert_eq!(days.value(0), 1672617600000);
+        assert_eq!(days.value(1), 1672617600000);
+        assert!(days.is_null(2));
+
+        let times = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Time64MicrosecondArray>()
+            .unwrap();
+        assert_eq!(times.value(0), 49530125000);
+        assert_eq!(times.value(1), 37230000000);
+        assert!(times.is_null(2));
+    }
+
+    #[test]
+    fn test_transform_response_timestamp_tz() {
+        let timestamp_type =
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("America/New_York".to_string()));
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "order_date",
+            timestamp_type.clone(),
+            true,
+        )]));
+        let member_fields = vec![MemberField::Member("order_date".to_string())];
+        let mut response = JsonValueObject::new(vec![
+            // Local time of the time zone
+            json!({ "order_date": "2024-01-15T09:30:00.000" }),
+            json!({ "order_date": "2024-07-15T09:30:00.000" }),
+            json!({ "order_date": "2024-01-15T09:30:00+01:00" }),
+            json!({ "order_date": null }),
+        ]);
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
+        )
+        .unwrap();
+
+        assert_eq!(batch.column(0).data_type(), &timestamp_type);
+        let timestamps = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampNanosecondArray>()
+            .unwrap();
+        assert_eq!(timestamps.value(0), 1705329000000000000);
+        assert_eq!(timestamps.value(1), 1721050200000000000);
+        assert_eq!(timestamps.value(2), 1705307400000000000);
+        assert!(timestamps.is_null(3));
+    }
+
+    #[test]
+    fn test_transform_response_list() {
+        let list = |item_type| DataType::List(Box::new(Field::new("item", item_type, true)));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("tags", list(DataType::Utf8), true),
+            Field::new("scores", list(DataType::Float64), true),
+        ]));
+        let member_fields = vec![
+            MemberField::Member("tags".to_string()),
+            MemberField::Member("scores".to_string()),
+        ];
+        let mut response = JsonValueObject::new(vec![
+            json!({ "tags": ["new", null, "sale"], "scores": [1.5, "2"] }),
+            json!({ "tags": null, "scores": [] }),
+        ]);
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
+        )
+        .unwrap();
+
+        let tags = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        let first = tags.value(0);
+        let first = first.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(first.value(0), "new");
+        assert!(first.is_null(1));
+        assert_eq!(first.value(2), "sale");
+        assert!(tags.is_null(1));
+
+        let scores = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        assert_eq!(
+            scores.value(0).as_any().downcast_ref::<Float64Array>(),
+            Some(&Float64Array::from(vec![1.5, 2.0]))
+        );
+        assert_eq!(scores.value(1).len(), 0);
+    }
+
+    #[test]
+    fn test_transform_response_object() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("properties", DataType::Utf8, true),
+            Field::new(
+                "address",
+                DataType::Struct(vec![
+                    Field::new("city", DataType::Utf8, true),
+                    Field::new("zip", DataType::Int64, true),
+                ]),
+                true,
+            ),
+        ]));
+        let member_fields = vec![
+            MemberField::Member("properties".to_string()),
+            MemberField::Member("address".to_string()),
+        ];
+        let mut response = JsonValueObject::new(vec![
+            json!({ "properties": { "color": "red", "sizes": [1, 2] }, "address": { "city": "Oslo", "zip": 150 } }),
+            json!({ "properties": null, "address": null }),
+        ]);
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
+        )
+        .unwrap();
+
+        let properties = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(properties.value(0)).unwrap(),
+            json!({ "color": "red", "sizes": [1, 2] })
+        );
+        assert!(properties.is_null(1));
+
+        let address = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap();
+        assert_eq!(
+            address.column(0).as_any().downcast_ref::<StringArray>(),
+            Some(&StringArray::from(vec![Some("Oslo"), None]))
+        );
+        assert_eq!(
+            address.column(1).as_any().downcast_ref::<Int64Array>(),
+            Some(&Int64Array::from(vec![Some(150), None]))
+        );
+        assert!(address.is_valid(0));
+        assert!(address.is_null(1));
+    }
+
+    #[test]
+    fn test_parse_interval_value() {
+        let interval = |months, days, nanos| IntervalParts {
+            months,
+            days,
+            nanos,
+        };
+
+        assert_eq!(
+            parse_interval_value("1 day 02:03:04.5").unwrap(),
+            interval(0, 1, 7_384_500_000_000)
+        );
+        assert_eq!(
+            parse_interval_value("0 years 14 mons 3 days 0 hours 0 mins 0.00 secs").unwrap(),
+            interval(14, 3, 0)
+        );
+        assert_eq!(
+            parse_interval_value("-00:00:01").unwrap(),
+            interval(0, 0, -1_000_000_000)
+        );
+        assert_eq!(
+            parse_interval_value("P1Y2M3DT4H5M6S").unwrap(),
+            interval(14, 3, 14_706_000_000_000)
+        );
+        assert!(parse_interval_value("3 fortnights").is_err());
+        assert!(parse_interval_value("").is_err());
+    }
+
+    #[test]
+    fn test_transform_response_interval() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("day_time", DataType::Interval(IntervalUnit::DayTime), true),
+            Field::new(
+                "month_day_nano",
+                DataType::Interval(IntervalUnit::MonthDayNano),
+                true,
+            ),
+        ]));
+        let member_fields = vec![
+            MemberField::Member("day_time".to_string()),
+            MemberField::Member("month_day_nano".to_string()),
+        ];
+        let mut response = JsonValueObject::new(vec![
+            json!({ "day_time": "2 days 00:00:01.5", "month_day_nano": "1 mon 2 days" }),
+            json!({ "day_time": 90, "month_day_nano": null }),
+            json!({ "day_time": "1 mon", "month_day_nano": "00:00:00.000001" }),
+        ]);
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
+        )
+        .unwrap();
+
+        let day_time = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<IntervalDayTimeArray>()
+            .unwrap();
+        assert_eq!(day_time.value(0), (2 << 32) | 1500);
+        assert_eq!(day_time.value(1), 90_000);
+        // Months can't be represented
+        assert!(day_time.is_null(2));
+
+        let month_day_nano = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<IntervalMonthDayNanoArray>()
+            .unwrap();
+        assert_eq!(month_day_nano.value(0), (1 << 96) | (2 << 64));
+        assert!(month_day_nano.is_null(1));
+        assert_eq!(month_day_nano.value(2), 1_000);
+    }
+
+    #[test]
+    fn test_transform_response_truncated_aliases() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("customer_gender", DataType::Utf8, true),
+            Field::new(
+                "avg_taxful_total_price_of_orders_by_customer_gender_and_region",
+                DataType::Float64,
+                true,
+            ),
+        ]));
+        let member_fields = vec![
+            MemberField::Member("customer_gender".to_string()),
+            MemberField::Member(
+                "avg_taxful_total_price_of_orders_by_customer_gender_and_region_ok".to_string(),
+            ),
+        ];
+        // Data source of wrapped SQL folds the case and truncates long aliases
+        let mut response = JsonValueObject::new(vec![json!({
+            "CUSTOMER_GENDER": "female",
+            "AVG_TAXFUL_TOTAL_PRICE_OF_ORDERS_BY_CUSTOMER_GENDER_AND_REGION": 12.5,
+        })]);
+        let batch = transform_response(
+            &mut response,
+            schema,
+            &member_fields,
+            &ResponseDateFormats::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap(),
+            &StringArray::from(vec!["female"])
+        );
+        assert_eq!(
+            batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap(),
+            &Float64Array::from(vec![12.5])
+        );
+    }
+
+    #[test]
+    fn test_no_members_num_rows() {
+        let request = |limit: Option<i32>| V1LoadRequestQuery {
+            limit,
+            ..V1LoadRequestQuery::new()
+        };
+
+        assert_eq!(no_members_num_rows(&request(None), 50000), 1);
+        assert_eq!(no_members_num_rows(&request(Some(5)), 50000), 5);
+        assert_eq!(no_members_num_rows(&request(Some(-1)), 50000), 0);
+        assert_eq!(
+            no_members_num_rows(&request(Some(2000000000)), 50000),
+            50000
+        );
+    }
+
+    #[test]
+    fn test_no_members_batch() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("one", DataType::Int64, true),
+            Field::new("customer_gender", DataType::Utf8, true),
+            Field::new("flag", DataType::Boolean, true),
+            Field::new("ratio", DataType::Float64, true),
+        ]));
+        let member_fields = vec![
+            MemberField::Literal(ScalarValue::Int64(Some(1))),
+            MemberField::Member("KibanaSampleDataEcommerce.customer_gender".to_string()),
+            MemberField::Literal(ScalarValue::Boolean(None)),
+            MemberField::Literal(ScalarValue::Int64(Some(2))),
+        ];
+
+        let batch = no_members_batch(schema.clone(), &member_fields, 5).unwrap();
+        assert_eq!(batch.schema(), schema);
+        assert_eq!(batch.num_rows(), 5);
+        assert_eq!(
+            batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap(),
+            &Int64Array::from(vec![1; 5])
+        );
+        assert_eq!(batch.column(1).null_count(), 5);
+        assert_eq!(batch.column(2).null_count(), 5);
+        assert_eq!(
+            batch
+                .column(3)
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap(),
+            &Float64Array::from(vec![2.0; 5])
+        );
+    }
+
+    #[test]
+    fn test_query_limit_overflow() {
+        let settings = |overflow| QueryLimitSettings {
+            query_limit: 100,
+            max_query_limit: 1000,
+            overflow,
+        };
+        let request = |limit: Option<i32>| {
+            let mut request = V1LoadRequestQuery::new();
+            request.limit = limit;
+            request
+        };
+
+        let cases = vec![
+            // overflow, can paginate, requested limit, limit, page size, warnings
+            (QueryLimitOverflow::Truncate, true, None, 100, None, 0),
+            (QueryLimitOverflow::Warn, true, Some(50), 50, None, 0),
+            (QueryLimitOverflow::Truncate, true, Some(500), 100, None, 0),
+            (QueryLimitOverflow::Warn, true, Some(500), 100, None, 1),
+            (QueryLimitOverflow::Honor, true, Some(500), 500, None, 0),
+            (QueryLimitOverflow::Honor, true, Some(5000), 1000, None, 1),
+            (
+                QueryLimitOverflow::Paginate,
+                true,
+                Some(500),
+                500,
+                Some(100),
+                0,
+            ),
+            (
+                QueryLimitOverflow::Paginate,
+                true,
+                Some(5000),
+                1000,
+                Some(100),
+                1,
+            ),
+            // Wrapped SQL falls back to the truncation with a warning
+            (QueryLimitOverflow::Paginate, false, Some(500), 100, None, 1),
+        ];
+
+        for (overflow, can_paginate, requested, limit, page_size, warnings_count) in cases {
+            let warnings = QueryWarnings::default();
+            let mut request = request(requested);
+            assert_eq!(
+                settings(overflow).apply(&mut request, can_paginate, &warnings),
+                page_size,
+                "{:?} {:?}",
+                overflow,
+                requested
+            );
+            assert_eq!(request.limit, Some(limit), "{:?} {:?}", overflow, requested);
+            assert_eq!(warnings.take().len(), warnings_count);
+        }
+    }
+
+    #[test]
+    fn test_max_records_overrides() {
+        let overrides: MaxRecordsOverrides = "Orders = 100000, 500, Users=2000".parse().unwrap();
+        assert_eq!(overrides.default, Some(500));
+        assert_eq!(
+            overrides.for_cubes(&["Orders".to_string(), "Users".to_string()], 1_000_000),
+            Some(100000)
+        );
+        assert_eq!(
+            overrides.for_cubes(&["Users".to_string()], 1_000_000),
+            Some(2000)
+        );
+        assert_eq!(
+            overrides.for_cubes(&["Products".to_string()], 1_000_000),
+            Some(500)
+        );
+        // Sessions can't raise limits above the max limit of the server
+        assert_eq!(
+            overrides.for_cubes(&["Orders".to_string()], 50000),
+            Some(50000)
+        );
+
+        assert_eq!(
+            "".parse::<MaxRecordsOverrides>().unwrap(),
+            MaxRecordsOverrides::default()
+        );
+        assert!("Orders=many".parse::<MaxRecordsOverrides>().is_err());
+    }
+
+    #[test]
+    fn test_max_records_exceeded_error() {
+        let mut request = V1LoadRequestQuery::new();
+        request.measures = Some(vec!["Orders.count".to_string()]);
+        request.dimensions = Some(vec!["Orders.status".to_string(), "Users.city".to_string()]);
+        request.limit = Some(100);
+
+        // Passes through DataFusion as is
+        let error: CubeError = DataFusionError::ArrowError(ArrowError::ExternalError(Box::new(
+            MaxRecordsExceeded::error(&request, 100, 100),
+        )))
+        .into();
+        assert!(error
+            .message
+            .starts_with("Cube query to Orders, Users returned 100 rows and exceeded the maximum row limit (100)"));
+        assert!(!error.message.contains("Orders.status"));
+
+        let detail: Value = serde_json::from_str(
+            error
+                .meta()
+                .and_then(|meta| meta.get(MaxRecordsExceeded::META_KEY))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(detail["cubes"], json!(["Orders", "Users"]));
+        assert_eq!(detail["maxRecords"], 100);
+        assert_eq!(detail["rows"], 100);
+        assert_eq!(detail["request"]["limit"], 100);
+    }
+
+    #[test]
+    fn test_tag_heavy_request() {
+        let request = |ungrouped: bool, limit: Option<i32>| {
+            let mut request = V1LoadRequestQuery::new();
+            request.ungrouped = Some(ungrouped);
+            request.limit = limit;
+            request
+        };
+        let tag = |min_limit: i32, request: &V1LoadRequestQuery| {
+            let mut meta = LoadRequestMeta::new("postgres".to_string(), "sql".to_string(), None);
+            meta.set_replica_routing_min_limit(min_limit);
+            meta.tag_heavy_request(request);
+            meta.prefer_replica()
+        };
+
+        assert!(!tag(0, &request(true, Some(100000))));
+        assert!(tag(10000, &request(true, Some(10))));
+        assert!(tag(10000, &request(false, Some(10000))));
+        assert!(!tag(10000, &request(false, Some(100))));
+
+        let mut meta = LoadRequestMeta::new("postgres".to_string(), "sql".to_string(), None);
+        meta.set_replica_routing_min_limit(10000);
+        meta.tag_heavy_request(&request(true, Some(10)));
+        assert_eq!(
+            serde_json::to_value(&meta).unwrap()["preferReplica"],
+            serde_json::Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_wrapped_select_from_template() -> Result<(), DataFusionError> {
+        let empty = Arc::new(LogicalPlan::EmptyRelation(EmptyRelation {
+            produce_one_row: false,
+            schema: Arc::new(DFSchema::empty()),
+        }));
+        let plan = LogicalPlan::Extension(Extension {
+            node: Arc::new(WrappedSelectNode::new(
+                Arc::new(DFSchema::empty()),
+                WrappedSelectType::Projection,
+                vec![lit(1)],
+                vec![],
+                vec![],
+                vec![],
+                empty.clone(),
+                vec![(empty.clone(), lit(true), JoinType::Left)],
+                vec![lit(true)],
+                vec![],
+                Some(10),
+                Some(5),
+                vec![],
+                Some("t".to_string()),
+                false,
+            )),
+        });
+
+        // Optimizers rebuild nodes from their expressions and inputs
+        let inputs = plan.inputs().into_iter().cloned().collect::<Vec<_>>();
+        let plan = from_plan(&plan, &plan.expressions(), &inputs)?;
+        let node = match &plan {
+            LogicalPlan::Extension(Extension { node }) => node
+                .as_any()
+                .downcast_ref::<WrappedSelectNode>()
+                .expect("WrappedSelectNode is expected"),
+            _ => panic!("Extension plan is expected"),
+        };
+
+        assert_eq!(node.limit, Some(10));
+        assert_eq!(node.offset, Some(5));
+        assert_eq!(node.alias, Some("t".to_string()));
+        assert_eq!(node.joins.len(), 1);
+        assert_eq!(node.joins[0].1, lit(true));
+        assert_eq!(node.joins[0].2, JoinType::Left);
+        assert_eq!(node.filter_expr, vec![lit(true)]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_memory_stream_chunk_timeout() {
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        let mut stream = CubeScanMemoryStream::new(receiver, Some(Duration::from_millis(10)), None);
+
+        let batch = RecordBatch::new_empty(Arc::new(Schema::empty()));
+        sender.send(Some(Ok(batch))).await.unwrap();
+        let next = futures::future::poll_fn(|cx| stream.poll_next(cx)).await;
+        assert!(matches!(next, Some(Ok(_))));
+
+        // The sender is alive but silent
+        let next = futures::future::poll_fn(|cx| stream.poll_next(cx)).await;
+        match next {
+            Some(Err(ArrowError::ComputeError(err))) => {
+                assert_eq!(err, "Load stream received no data for 0 seconds")
+            }
+            _ => panic!("Timeout error is expected"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_stream_memory_pressure() {
+        fn high_rss() -> Option<u64> {
+            Some(2000)
+        }
+
+        let memory_pressure =
+            MemoryPressure::new(1000, 800, Duration::from_millis(50)).with_rss_reader(high_rss);
+        memory_pressure.sample();
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        let mut stream = CubeScanMemoryStream::new(receiver, None, Some(Arc::new(memory_pressure)));
+
+        // The chunk isn't received while loads are shed
+        let batch = RecordBatch::new_empty(Arc::new(Schema::empty()));
+        sender.send(Some(Ok(batch))).await.unwrap();
+        let next = futures::future::poll_fn(|cx| stream.poll_next(cx)).await;
+        match next {
+            Some(Err(err)) => assert!(CubeError::from(err)
+                .meta()
+                .map(|meta| meta.contains_key(MemoryPressure::META_KEY))
+                .unwrap_or(false)),
+            _ => panic!("Memory pressure error is expected"),
+        }
     }
 }
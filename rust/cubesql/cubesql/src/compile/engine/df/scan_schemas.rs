@@ -0,0 +1,114 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+use datafusion::{arrow::datatypes::SchemaRef, logical_plan::DFSchemaRef};
+
+use super::scan::MemberField;
+
+// Statement with a lot of different scans (e.g. depending on parameters) is not worth caching
+const MAX_SCANS: usize = 16;
+
+#[derive(Debug)]
+struct ScanSchema {
+    schema: SchemaRef,
+    member_fields: Arc<Vec<MemberField>>,
+}
+
+/// Arrow schemas and member fields of CubeScans of a prepared statement. Every execution of the
+/// statement plans the same scans, so they are converted from the logical plan only once.
+#[derive(Debug, Default)]
+pub struct ScanSchemaCache {
+    scans: Mutex<HashMap<u64, ScanSchema>>,
+}
+
+impl ScanSchemaCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scans.lock().unwrap().len()
+    }
+
+    pub fn get_or_insert(
+        &self,
+        df_schema: &DFSchemaRef,
+        member_fields: &[MemberField],
+    ) -> (SchemaRef, Arc<Vec<MemberField>>) {
+        let key = Self::key(df_schema, member_fields);
+        if let Some(scan) = self.scans.lock().unwrap().get(&key) {
+            return (scan.schema.clone(), scan.member_fields.clone());
+        }
+
+        let schema = SchemaRef::new(df_schema.as_ref().into());
+        let member_fields = Arc::new(member_fields.to_vec());
+        let mut scans = self.scans.lock().unwrap();
+        if scans.len() < MAX_SCANS {
+            scans.insert(
+                key,
+                ScanSchema {
+                    schema: schema.clone(),
+                    member_fields: member_fields.clone(),
+                },
+            );
+        }
+
+        (schema, member_fields)
+    }
+
+    /// Hash of the fields which end up in the Arrow schema, it's computed without the lock
+    fn key(df_schema: &DFSchemaRef, member_fields: &[MemberField]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for field in df_schema.fields() {
+            field.qualifier().hash(&mut hasher);
+            field.name().hash(&mut hasher);
+            field.data_type().hash(&mut hasher);
+            field.is_nullable().hash(&mut hasher);
+        }
+        member_fields.hash(&mut hasher);
+
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::{
+        arrow::datatypes::DataType,
+        logical_plan::{DFField, DFSchema},
+    };
+    use std::collections::HashMap;
+
+    fn df_schema(names: &[&str]) -> DFSchemaRef {
+        let fields = names
+            .iter()
+            .map(|name| DFField::new(None, name, DataType::Utf8, true))
+            .collect();
+
+        Arc::new(DFSchema::new_with_metadata(fields, HashMap::new()).unwrap())
+    }
+
+    #[test]
+    fn test_scan_schema_cache() {
+        let cache = ScanSchemaCache::new();
+        let member_fields = vec![MemberField::Member("Orders.status".to_string())];
+
+        let (schema, fields) = cache.get_or_insert(&df_schema(&["status"]), &member_fields);
+        assert_eq!(schema.fields().len(), 1);
+
+        // Logical plan of the next execution has a new, but equal schema
+        let (cached_schema, cached_fields) =
+            cache.get_or_insert(&df_schema(&["status"]), &member_fields);
+        assert!(Arc::ptr_eq(&schema, &cached_schema));
+        assert!(Arc::ptr_eq(&fields, &cached_fields));
+        assert_eq!(cache.len(), 1);
+
+        let (other_schema, _) = cache.get_or_insert(&df_schema(&["city"]), &member_fields);
+        assert!(!Arc::ptr_eq(&schema, &other_schema));
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -0,0 +1,280 @@
+use std::sync::Arc;
+
+use datafusion::{
+    error::{DataFusionError, Result},
+    logical_plan::{
+        build_join_schema,
+        plan::{Join, Limit, Sort},
+        DFSchemaRef, Expr, JoinConstraint, JoinType, LogicalPlan, LogicalPlanBuilder,
+    },
+    sql::{
+        parser::Statement as DFStatement,
+        planner::{ContextProvider, SqlToRel},
+    },
+};
+use sqlparser::ast;
+
+use super::coerce::union_coercion;
+
+/// Plans the statement, `INTERSECT` and `EXCEPT` of queries are planned as a semi (anti) join
+/// of the distinct rows of the left query with the right query. Every side is planned on its
+/// own, so CubeScans of the sides are rewritten independently and the set operation is
+/// performed by DataFusion.
+pub fn plan_statement<S: ContextProvider>(
+    planner: &SqlToRel<S>,
+    stmt: &ast::Statement,
+) -> Result<LogicalPlan> {
+    match stmt {
+        ast::Statement::Query(query) => plan_query(planner, query),
+        _ => planner.statement_to_plan(DFStatement::Statement(Box::new(stmt.clone()))),
+    }
+}
+
+fn plan_query<S: ContextProvider>(
+    planner: &SqlToRel<S>,
+    query: &ast::Query,
+) -> Result<LogicalPlan> {
+    let (op, left, right) = match &query.body {
+        ast::SetExpr::SetOperation {
+            op: op @ (ast::SetOperator::Intersect | ast::SetOperator::Except),
+            all,
+            left,
+            right,
+        } => {
+            if *all {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "{} ALL is not supported",
+                    op
+                )));
+            }
+
+            (op, left, right)
+        }
+        _ => {
+            return planner.statement_to_plan(DFStatement::Statement(Box::new(
+                ast::Statement::Query(Box::new(query.clone())),
+            )))
+        }
+    };
+
+    let left = plan_query(planner, &side_query(query, left))?;
+    let right = plan_query(planner, &side_query(query, right))?;
+    let (left, right) = match coerce_set_inputs(vec![left, right], &op.to_string())?.as_slice() {
+        [left, right] => (left.clone(), right.clone()),
+        _ => unreachable!("two inputs are coerced"),
+    };
+
+    let left_fields = left.schema().fields();
+    let right_fields = right.schema().fields();
+    let mut on = Vec::with_capacity(left_fields.len());
+    for (left_field, right_field) in left_fields.iter().zip(right_fields.iter()) {
+        on.push((
+            left_field.qualified_column(),
+            right_field.qualified_column(),
+        ));
+    }
+
+    let join_type = match op {
+        ast::SetOperator::Intersect => JoinType::Semi,
+        _ => JoinType::Anti,
+    };
+    let left = Arc::new(LogicalPlanBuilder::from(left).distinct()?.build()?);
+    let right = Arc::new(right);
+    let schema = Arc::new(build_join_schema(
+        left.schema(),
+        right.schema(),
+        &join_type,
+    )?);
+
+    let plan = LogicalPlan::Join(Join {
+        left,
+        right,
+        on,
+        join_type,
+        join_constraint: JoinConstraint::On,
+        schema,
+        null_equals_null: true,
+    });
+
+    let plan = plan_order_by(plan, &query.order_by)?;
+    plan_limit(plan, query)
+}
+
+/// Casts columns of the inputs of a set operation to their common types, e.g. a measure which is
+/// Int64 in one input and Float64 in another one is widened to Float64
+pub fn coerce_set_inputs(inputs: Vec<LogicalPlan>, op: &str) -> Result<Vec<LogicalPlan>> {
+    let first = match inputs.first() {
+        Some(first) => first.schema().clone(),
+        None => return Ok(inputs),
+    };
+    if inputs
+        .iter()
+        .any(|input| input.schema().fields().len() != first.fields().len())
+    {
+        return Err(DataFusionError::Plan(format!(
+            "each {} query must have the same number of columns",
+            op
+        )));
+    }
+
+    let mut types = first
+        .fields()
+        .iter()
+        .map(|field| field.data_type().clone())
+        .collect::<Vec<_>>();
+    for input in inputs.iter().skip(1) {
+        for (data_type, field) in types.iter_mut().zip(input.schema().fields()) {
+            *data_type = union_coercion(data_type, field.data_type()).ok_or_else(|| {
+                DataFusionError::Plan(format!(
+                    "{} types {} and {} cannot be matched",
+                    op,
+                    data_type,
+                    field.data_type()
+                ))
+            })?;
+        }
+    }
+
+    inputs
+        .into_iter()
+        .map(|input| {
+            let fields = input.schema().fields().clone();
+            if fields
+                .iter()
+                .zip(types.iter())
+                .all(|(field, data_type)| field.data_type() == data_type)
+            {
+                return Ok(input);
+            }
+
+            let expr = fields
+                .iter()
+                .zip(types.iter())
+                .map(|(field, data_type)| {
+                    let column = Expr::Column(field.qualified_column());
+                    if field.data_type() == data_type {
+                        column
+                    } else {
+                        Expr::Cast {
+                            expr: Box::new(column),
+                            data_type: data_type.clone(),
+                        }
+                        .alias(field.name())
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            LogicalPlanBuilder::from(input).project(expr)?.build()
+        })
+        .collect()
+}
+
+/// Query of one side of the set operation, ordering and limits belong to the whole operation
+fn side_query(query: &ast::Query, body: &ast::SetExpr) -> ast::Query {
+    let mut side = query.clone();
+    side.body = body.clone();
+    side.order_by = vec![];
+    side.limit = None;
+    side.offset = None;
+    side.fetch = None;
+
+    side
+}
+
+fn plan_order_by(plan: LogicalPlan, order_by: &[ast::OrderByExpr]) -> Result<LogicalPlan> {
+    if order_by.is_empty() {
+        return Ok(plan);
+    }
+
+    let schema = plan.schema().clone();
+    let expr = order_by
+        .iter()
+        .map(|order_by| {
+            let asc = order_by.asc.unwrap_or(true);
+
+            Ok(Expr::Sort {
+                expr: Box::new(output_column(&schema, &order_by.expr)?),
+                asc,
+                nulls_first: order_by.nulls_first.unwrap_or(!asc),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(LogicalPlan::Sort(Sort {
+        expr,
+        input: Arc::new(plan),
+    }))
+}
+
+/// Set operations can be ordered only by the output columns, referenced by name or position
+fn output_column(schema: &DFSchemaRef, expr: &ast::Expr) -> Result<Expr> {
+    let field = match expr {
+        ast::Expr::Identifier(ident) => schema.fields().iter().find(|field| {
+            if ident.quote_style.is_some() {
+                field.name() == &ident.value
+            } else {
+                field.name().eq_ignore_ascii_case(&ident.value)
+            }
+        }),
+        ast::Expr::Value(ast::Value::Number(position, _)) => position
+            .parse::<usize>()
+            .ok()
+            .filter(|position| *position > 0)
+            .and_then(|position| schema.fields().get(position - 1)),
+        _ => {
+            return Err(DataFusionError::NotImplemented(format!(
+                "ORDER BY {} of INTERSECT or EXCEPT is not supported, use an output column",
+                expr
+            )))
+        }
+    };
+
+    match field {
+        Some(field) => Ok(Expr::Column(field.qualified_column())),
+        None => Err(DataFusionError::Plan(format!(
+            "ORDER BY term {} does not match any result column",
+            expr
+        ))),
+    }
+}
+
+fn plan_limit(plan: LogicalPlan, query: &ast::Query) -> Result<LogicalPlan> {
+    if query.fetch.is_some() {
+        return Err(DataFusionError::NotImplemented(
+            "FETCH of INTERSECT or EXCEPT is not supported, use LIMIT".to_string(),
+        ));
+    }
+
+    let skip = match &query.offset {
+        Some(offset) => Some(row_count(&offset.value, "OFFSET")?),
+        None => None,
+    };
+    let fetch = match &query.limit {
+        Some(limit) => Some(row_count(limit, "LIMIT")?),
+        None => None,
+    };
+    if skip.is_none() && fetch.is_none() {
+        return Ok(plan);
+    }
+
+    Ok(LogicalPlan::Limit(Limit {
+        skip,
+        fetch,
+        input: Arc::new(plan),
+    }))
+}
+
+fn row_count(expr: &ast::Expr, clause: &str) -> Result<usize> {
+    match expr {
+        ast::Expr::Value(ast::Value::Number(n, _)) => n.parse::<usize>().map_err(|_| {
+            DataFusionError::Plan(format!(
+                "{} must be a non-negative integer, got {}",
+                clause, n
+            ))
+        }),
+        _ => Err(DataFusionError::NotImplemented(format!(
+            "{} {} of INTERSECT or EXCEPT is not supported",
+            clause, expr
+        ))),
+    }
+}
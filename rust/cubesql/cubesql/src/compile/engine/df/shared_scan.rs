@@ -0,0 +1,185 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use datafusion::{
+    arrow::record_batch::RecordBatch,
+    error::{DataFusionError, Result},
+};
+use tokio::sync::Mutex as AsyncMutex;
+
+/// CubeScans with the same request inside one query, for example a CTE which is referenced
+/// multiple times, share a single load from Cube. Registry lives for a single physical plan.
+#[derive(Debug)]
+pub struct SharedCubeScans {
+    /// Max number of rows which are buffered for the other consumers, 0 disables sharing
+    max_rows: usize,
+    scans: Mutex<HashMap<String, Arc<SharedCubeScan>>>,
+}
+
+impl SharedCubeScans {
+    pub fn new(max_rows: usize) -> Self {
+        Self {
+            max_rows,
+            scans: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Registers a consumer of the scan, key must identify the load request and its output.
+    /// Scans which can return more than max_rows are not shared: they are loaded (or streamed)
+    /// by every consumer, as buffering them would hold the whole result in memory.
+    pub fn register(&self, key: String, expected_rows: usize) -> Option<Arc<SharedCubeScan>> {
+        if self.max_rows == 0 || expected_rows > self.max_rows {
+            return None;
+        }
+
+        let mut scans = self.scans.lock().unwrap();
+        let scan = scans
+            .entry(key)
+            .or_insert_with(|| Arc::new(SharedCubeScan::new(self.max_rows)))
+            .clone();
+        scan.consumers.fetch_add(1, Ordering::SeqCst);
+        scan.remaining.fetch_add(1, Ordering::SeqCst);
+
+        Some(scan)
+    }
+}
+
+#[derive(Debug)]
+enum SharedResult {
+    NotLoaded,
+    Buffered(RecordBatch),
+    // Result exceeded max_rows, every remaining consumer loads it on its own
+    TooLarge,
+}
+
+#[derive(Debug)]
+pub struct SharedCubeScan {
+    consumers: AtomicUsize,
+    // Consumers which haven't received the result yet, the last one takes the buffer
+    remaining: AtomicUsize,
+    max_rows: usize,
+    result: AsyncMutex<SharedResult>,
+}
+
+impl SharedCubeScan {
+    fn new(max_rows: usize) -> Self {
+        Self {
+            consumers: AtomicUsize::new(0),
+            remaining: AtomicUsize::new(0),
+            max_rows,
+            result: AsyncMutex::new(SharedResult::NotLoaded),
+        }
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.consumers.load(Ordering::SeqCst) > 1
+    }
+
+    /// The first consumer runs the load, the others wait and reuse its result. The buffer is
+    /// released as soon as the last consumer receives it. When the result exceeds max_rows, it's
+    /// not buffered and every other consumer loads the data on its own.
+    pub async fn load<F, Fut>(&self, load: F) -> Result<RecordBatch>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<RecordBatch>>,
+    {
+        let mut result = self.result.lock().await;
+        let remaining = self.remaining.fetch_sub(1, Ordering::SeqCst) - 1;
+
+        match std::mem::replace(&mut *result, SharedResult::NotLoaded) {
+            SharedResult::NotLoaded => {
+                // A failed load leaves the result unset, the next consumer retries it
+                let batch = load().await?;
+                if batch.num_rows() > self.max_rows {
+                    *result = SharedResult::TooLarge;
+                } else if remaining > 0 {
+                    *result = SharedResult::Buffered(batch.clone());
+                }
+
+                Ok(batch)
+            }
+            SharedResult::Buffered(batch) => {
+                if remaining > 0 {
+                    *result = SharedResult::Buffered(batch.clone());
+                }
+
+                Ok(batch)
+            }
+            SharedResult::TooLarge => {
+                *result = SharedResult::TooLarge;
+                drop(result);
+                load().await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::{
+        array::Int64Array,
+        datatypes::{DataType, Field, Schema},
+    };
+
+    fn batch(rows: i64) -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("n", DataType::Int64, false)])),
+            vec![Arc::new(Int64Array::from((0..rows).collect::<Vec<_>>()))],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_shared_cube_scan_single_load() -> Result<()> {
+        let scans = SharedCubeScans::new(10);
+        let first = scans.register("a".to_string(), 5).unwrap();
+        let second = scans.register("a".to_string(), 5).unwrap();
+        let other = scans.register("b".to_string(), 5).unwrap();
+        assert!(first.is_shared());
+        assert!(!other.is_shared());
+
+        let loads = AtomicUsize::new(0);
+        let load = || async {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok(batch(5))
+        };
+        let (a, b) = tokio::join!(first.load(load), second.load(load));
+        assert_eq!(a?.num_rows(), 5);
+        assert_eq!(b?.num_rows(), 5);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        // The last consumer took the buffer
+        assert!(matches!(
+            *first.result.lock().await,
+            SharedResult::NotLoaded
+        ));
+
+        // Scans which can exceed the threshold are not shared
+        let scans = SharedCubeScans::new(3);
+        assert!(scans.register("a".to_string(), 10).is_none());
+
+        // Results which exceed the expected size aren't buffered either
+        let first = scans.register("a".to_string(), 3).unwrap();
+        let second = scans.register("a".to_string(), 3).unwrap();
+        let loads = AtomicUsize::new(0);
+        let load = || async {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok(batch(5))
+        };
+        first.load(load).await?;
+        second.load(load).await?;
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+        assert!(SharedCubeScans::new(0)
+            .register("a".to_string(), 0)
+            .is_none());
+
+        Ok(())
+    }
+}
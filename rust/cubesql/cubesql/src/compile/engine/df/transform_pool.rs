@@ -0,0 +1,195 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+
+use crate::CubeError;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransformPoolMetrics {
+    /// Number of finished tasks
+    pub tasks: u64,
+    /// Number of tasks which are waiting for a free slot right now
+    pub waiting: usize,
+    /// Number of tasks which are running right now
+    pub running: usize,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    pub total_run: Duration,
+}
+
+/// Runs CPU heavy work, such as conversion of JSON responses to Arrow, on the blocking threads
+/// instead of the async runtime which serves connections. The number of concurrent tasks is
+/// limited, the rest waits in the queue.
+#[derive(Debug)]
+pub struct TransformPool {
+    max_concurrent: usize,
+    semaphore: Arc<Semaphore>,
+    metrics: Mutex<TransformPoolMetrics>,
+}
+
+/// Task is counted as waiting until it gets a slot or it's cancelled
+struct WaitGuard<'a> {
+    pool: &'a TransformPool,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        self.pool.update_metrics(|metrics| metrics.waiting -= 1);
+    }
+}
+
+/// Task is counted as running until it's finished or panicked
+struct RunGuard {
+    pool: Arc<TransformPool>,
+    started: Instant,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let run = self.started.elapsed();
+        self.pool.update_metrics(|metrics| {
+            metrics.running -= 1;
+            metrics.tasks += 1;
+            metrics.total_run += run;
+        });
+    }
+}
+
+impl TransformPool {
+    pub fn new(max_concurrent: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+
+        Self {
+            max_concurrent,
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            metrics: Mutex::new(TransformPoolMetrics::default()),
+        }
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    pub async fn run<F, R>(self: &Arc<Self>, task: F) -> Result<R, CubeError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let started = Instant::now();
+        let permit = {
+            self.update_metrics(|metrics| metrics.waiting += 1);
+            let _guard = WaitGuard { pool: self };
+
+            self.semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|err| CubeError::internal(err.to_string()))?
+        };
+
+        let wait = started.elapsed();
+        self.update_metrics(|metrics| {
+            metrics.running += 1;
+            metrics.total_wait += wait;
+            if wait > metrics.max_wait {
+                metrics.max_wait = wait;
+            }
+        });
+
+        let guard = RunGuard {
+            pool: self.clone(),
+            started: Instant::now(),
+        };
+        let result = tokio::task::spawn_blocking(move || {
+            // Slot and metrics are released even if the task panics
+            let _permit = permit;
+            let _guard = guard;
+
+            task()
+        })
+        .await;
+
+        result.map_err(|err| {
+            if err.is_panic() {
+                CubeError::panic(err.into_panic())
+            } else {
+                CubeError::internal(err.to_string())
+            }
+        })
+    }
+
+    pub fn metrics(&self) -> TransformPoolMetrics {
+        self.metrics
+            .lock()
+            .expect("failed to lock transform pool metrics")
+            .clone()
+    }
+
+    fn update_metrics(&self, f: impl FnOnce(&mut TransformPoolMetrics)) {
+        let mut metrics = self
+            .metrics
+            .lock()
+            .expect("failed to lock transform pool metrics");
+        f(&mut metrics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_transform_pool_concurrency() -> Result<(), CubeError> {
+        let pool = Arc::new(TransformPool::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let handles = (0..8)
+            .map(|i| {
+                let pool = pool.clone();
+                let running = running.clone();
+                let max_running = max_running.clone();
+                tokio::spawn(async move {
+                    pool.run(move || {
+                        let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_running.fetch_max(current, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(10));
+                        running.fetch_sub(1, Ordering::SeqCst);
+
+                        i * 2
+                    })
+                    .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.await.unwrap()?, i * 2);
+        }
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+
+        let metrics = pool.metrics();
+        assert_eq!(metrics.tasks, 8);
+        assert_eq!(metrics.waiting, 0);
+        assert_eq!(metrics.running, 0);
+        assert!(metrics.max_wait > Duration::ZERO);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_transform_pool_panic() {
+        let pool = Arc::new(TransformPool::new(1));
+
+        let result = pool.run(|| panic!("transform failed")).await;
+        assert!(result.is_err());
+
+        // Slot is released after panic
+        assert_eq!(pool.run(|| 1).await.unwrap(), 1);
+        assert_eq!(pool.metrics().waiting, 0);
+    }
+}
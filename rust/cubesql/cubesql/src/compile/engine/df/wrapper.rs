@@ -1,8 +1,13 @@
 use crate::{
     compile::{
-        engine::df::scan::{CubeScanNode, DataType, MemberField, WrappedSelectNode},
+        engine::df::{
+            member_aliases::MemberAliasRegistry,
+            scan::{CubeScanNode, DataType, MemberField, WrappedSelectNode},
+        },
+        find_cube_scans_deep_search,
         rewrite::WrappedSelectType,
     },
+    config::ConfigObj,
     sql::AuthContextRef,
     transport::{
         AliasedColumn, LoadRequestMeta, MetaContext, SpanId, SqlGenerator, SqlTemplates,
@@ -25,7 +30,7 @@ use regex::{Captures, Regex};
 use serde_derive::*;
 use std::{any::Any, collections::HashMap, fmt, future::Future, pin::Pin, result, sync::Arc};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SqlQuery {
     pub sql: String,
     pub values: Vec<Option<String>>,
@@ -92,6 +97,53 @@ impl SqlQuery {
             Err(e) => Err(e),
         }
     }
+
+    /// Values are always sent as bind params, the query is rejected if it doesn't fit
+    /// the limits of the data source
+    pub fn apply_limits(
+        &mut self,
+        limits: &WrappedSqlLimits,
+        sql_templates: Arc<SqlTemplates>,
+    ) -> result::Result<(), CubeError> {
+        self.finalize_query(sql_templates)
+            .map_err(|e| CubeError::internal(e.to_string()))?;
+
+        if limits.max_params > 0 && self.values.len() > limits.max_params {
+            return Err(CubeError::user(format!(
+                "Generated SQL for the data source has too many params: {}, the limit is {}. \
+                Reduce the number of values in IN lists and literals or increase CUBESQL_WRAPPER_MAX_PARAMS",
+                self.values.len(),
+                limits.max_params
+            )));
+        }
+
+        if limits.max_sql_length > 0 && self.sql.len() > limits.max_sql_length {
+            return Err(CubeError::user(format!(
+                "Generated SQL for the data source is too large: {} bytes, the limit is {} bytes. \
+                Reduce the number of values in IN lists and literals or increase CUBESQL_WRAPPER_MAX_SQL_LENGTH",
+                self.sql.len(),
+                limits.max_sql_length
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Limits for SQL which is pushed down to the data source, zero means no limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WrappedSqlLimits {
+    pub max_sql_length: usize,
+    pub max_params: usize,
+}
+
+impl WrappedSqlLimits {
+    pub fn new(config_obj: &dyn ConfigObj) -> Self {
+        Self {
+            max_sql_length: config_obj.wrapper_max_sql_length(),
+            max_params: config_obj.wrapper_max_params(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -139,6 +191,32 @@ impl CubeScanWrapperNode {
             span_id: self.span_id.clone(),
         }
     }
+
+    /// Wrapped SQL under EXPLAIN of the data source, it's used by `EXPLAIN (SOURCE)`
+    pub fn explain_sql(&self) -> result::Result<SqlQuery, CubeError> {
+        let sql = self.wrapped_sql.as_ref().ok_or_else(|| {
+            CubeError::internal(format!(
+                "Wrapped SQL is not set for wrapper node. Optimization wasn't performed: {:?}",
+                self
+            ))
+        })?;
+        let sql_generator = find_cube_scans_deep_search(self.wrapped_plan.clone(), false)
+            .iter()
+            .flat_map(|node| node.used_cubes.iter())
+            .find_map(|cube| self.meta.cube_to_data_source.get(cube))
+            .and_then(|data_source| self.meta.data_source_to_sql_generator.get(data_source))
+            .ok_or_else(|| {
+                CubeError::internal(format!(
+                    "Can't explain wrapped select: no sql generator found: {:?}",
+                    self
+                ))
+            })?;
+
+        Ok(SqlQuery::new(
+            sql_generator.get_sql_templates().explain(sql.sql.clone())?,
+            sql.values.clone(),
+        ))
+    }
 }
 
 fn expr_name(e: &Expr, schema: &Arc<DFSchema>) -> Result<String> {
@@ -166,6 +244,7 @@ impl CubeScanWrapperNode {
         &self,
         transport: Arc<dyn TransportService>,
         load_request_meta: Arc<LoadRequestMeta>,
+        limits: WrappedSqlLimits,
     ) -> result::Result<Self, CubeError> {
         let schema = self.schema();
         let (sql, request, member_fields) = Self::generate_sql_for_node(
@@ -177,19 +256,12 @@ impl CubeScanWrapperNode {
         )
         .await
         .and_then(|SqlGenerationResult { data_source, mut sql, request, column_remapping, .. }| -> result::Result<_, CubeError> {
-            let member_fields = if let Some(column_remapping) = column_remapping {
-                schema
-                    .fields()
-                    .iter()
-                    .map(|f| MemberField::Member(column_remapping.get(&Column::from_name(f.name().to_string())).map(|x| x.name.to_string()).unwrap_or(f.name().to_string())))
-                    .collect()
-            } else {
-                schema
-                    .fields()
-                    .iter()
-                    .map(|f| MemberField::Member(f.name().to_string()))
-                    .collect()
-            };
+            let aliases = MemberAliasRegistry::new(column_remapping.as_ref());
+            let member_fields = schema
+                .fields()
+                .iter()
+                .map(|f| aliases.member_field(f.name()))
+                .collect();
             let data_source = data_source.ok_or_else(|| CubeError::internal(format!(
                 "Can't generate SQL for wrapped select: no data source returned"
             )))?;
@@ -204,7 +276,7 @@ impl CubeScanWrapperNode {
                     ))
                 })?
                 .get_sql_templates();
-            sql.finalize_query(sql_templates).map_err(|e| CubeError::internal(e.to_string()))?;
+            sql.apply_limits(&limits, sql_templates)?;
             Ok((sql, request, member_fields))
         })?;
         Ok(self.with_sql_and_request(sql, request, member_fields))
@@ -1608,3 +1680,59 @@ impl UserDefinedLogicalNode for CubeScanWrapperNode {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_limits() {
+        let sql_templates = Arc::new(
+            SqlTemplates::new(HashMap::from([(
+                "params/param".to_string(),
+                "${{ param_index + 1 }}".to_string(),
+            )]))
+            .unwrap(),
+        );
+        let new_query = || {
+            SqlQuery::new(
+                "SELECT * FROM orders WHERE status IN ($0$, $1$) AND notes = $0$".to_string(),
+                vec![
+                    Some("x\\' OR 1 = 1 --".to_string()),
+                    Some("shipped".to_string()),
+                ],
+            )
+        };
+
+        let mut query = new_query();
+        let limits = WrappedSqlLimits {
+            max_sql_length: 0,
+            max_params: 3,
+        };
+        query.apply_limits(&limits, sql_templates.clone()).unwrap();
+        // Values are never inlined into SQL
+        assert_eq!(
+            query.sql,
+            "SELECT * FROM orders WHERE status IN ($1, $2) AND notes = $3"
+        );
+        assert_eq!(query.values.len(), 3);
+
+        let limits = WrappedSqlLimits {
+            max_sql_length: 0,
+            max_params: 2,
+        };
+        let err = new_query()
+            .apply_limits(&limits, sql_templates.clone())
+            .unwrap_err();
+        assert!(err.message.contains("CUBESQL_WRAPPER_MAX_PARAMS"));
+
+        let limits = WrappedSqlLimits {
+            max_sql_length: 32,
+            max_params: 0,
+        };
+        let err = new_query()
+            .apply_limits(&limits, sql_templates)
+            .unwrap_err();
+        assert!(err.message.contains("CUBESQL_WRAPPER_MAX_SQL_LENGTH"));
+    }
+}
@@ -0,0 +1,135 @@
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{Array, StringBuilder, UInt64Builder},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::{
+    compile::engine::provider::TableName,
+    transport::{LoadLimiter, LoadQueueMetrics, QueryPriority},
+};
+
+struct CubeSqlLoadQueueStatsBuilder {
+    priority: StringBuilder,
+    loads: UInt64Builder,
+    queued: UInt64Builder,
+    waiting: UInt64Builder,
+    total_wait_ms: UInt64Builder,
+    max_wait_ms: UInt64Builder,
+}
+
+impl CubeSqlLoadQueueStatsBuilder {
+    fn new(capacity: usize) -> Self {
+        Self {
+            priority: StringBuilder::new(capacity),
+            loads: UInt64Builder::new(capacity),
+            queued: UInt64Builder::new(capacity),
+            waiting: UInt64Builder::new(capacity),
+            total_wait_ms: UInt64Builder::new(capacity),
+            max_wait_ms: UInt64Builder::new(capacity),
+        }
+    }
+
+    fn add_class(&mut self, priority: QueryPriority, metrics: &LoadQueueMetrics) {
+        self.priority.append_value(priority.as_str()).unwrap();
+        self.loads.append_value(metrics.loads).unwrap();
+        self.queued.append_value(metrics.queued).unwrap();
+        self.waiting.append_value(metrics.waiting as u64).unwrap();
+        self.total_wait_ms
+            .append_value(metrics.total_wait.as_millis() as u64)
+            .unwrap();
+        self.max_wait_ms
+            .append_value(metrics.max_wait.as_millis() as u64)
+            .unwrap();
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+
+        columns.push(Arc::new(self.priority.finish()));
+        columns.push(Arc::new(self.loads.finish()));
+        columns.push(Arc::new(self.queued.finish()));
+        columns.push(Arc::new(self.waiting.finish()));
+        columns.push(Arc::new(self.total_wait_ms.finish()));
+        columns.push(Arc::new(self.max_wait_ms.finish()));
+
+        columns
+    }
+}
+
+/// `cubesql.load_queue_stats`, queue wait of loads per priority class
+pub struct CubeSqlLoadQueueStatsProvider {
+    limiter: Arc<LoadLimiter>,
+}
+
+impl CubeSqlLoadQueueStatsProvider {
+    pub fn new(limiter: Arc<LoadLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl TableName for CubeSqlLoadQueueStatsProvider {
+    fn table_name(&self) -> &str {
+        "cubesql.load_queue_stats"
+    }
+}
+
+#[async_trait]
+impl TableProvider for CubeSqlLoadQueueStatsProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("priority", DataType::Utf8, false),
+            Field::new("loads", DataType::UInt64, false),
+            Field::new("queued", DataType::UInt64, false),
+            Field::new("waiting", DataType::UInt64, false),
+            Field::new("total_wait_ms", DataType::UInt64, false),
+            Field::new("max_wait_ms", DataType::UInt64, false),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let classes = self.limiter.metrics();
+        let mut builder = CubeSqlLoadQueueStatsBuilder::new(classes.len());
+
+        for (priority, metrics) in classes.iter() {
+            builder.add_class(*priority, metrics);
+        }
+
+        let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
@@ -0,0 +1,137 @@
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{Array, StringBuilder, TimestampNanosecondBuilder, UInt64Builder},
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::{
+    compile::engine::provider::TableName,
+    sql::{MemberUsage, MemberUsageStats},
+};
+
+struct CubeSqlMemberUsageStatsBuilder {
+    member: StringBuilder,
+    cube: StringBuilder,
+    member_type: StringBuilder,
+    queries: UInt64Builder,
+    filters: UInt64Builder,
+    last_used: TimestampNanosecondBuilder,
+}
+
+impl CubeSqlMemberUsageStatsBuilder {
+    fn new(capacity: usize) -> Self {
+        Self {
+            member: StringBuilder::new(capacity),
+            cube: StringBuilder::new(capacity),
+            member_type: StringBuilder::new(capacity),
+            queries: UInt64Builder::new(capacity),
+            filters: UInt64Builder::new(capacity),
+            last_used: TimestampNanosecondBuilder::new(capacity),
+        }
+    }
+
+    fn add_member(&mut self, member: &str, usage: &MemberUsage) {
+        self.member.append_value(member).unwrap();
+        self.cube.append_value(&usage.cube).unwrap();
+        self.member_type.append_value(usage.kind.as_str()).unwrap();
+        self.queries.append_value(usage.queries).unwrap();
+        self.filters.append_value(usage.filters).unwrap();
+        self.last_used
+            .append_value(usage.last_used.timestamp_nanos())
+            .unwrap();
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+
+        columns.push(Arc::new(self.member.finish()));
+        columns.push(Arc::new(self.cube.finish()));
+        columns.push(Arc::new(self.member_type.finish()));
+        columns.push(Arc::new(self.queries.finish()));
+        columns.push(Arc::new(self.filters.finish()));
+        columns.push(Arc::new(self.last_used.finish()));
+
+        columns
+    }
+}
+
+/// `cubesql.member_usage_stats`, it's available for both MySQL & Postgres protocols
+pub struct CubeSqlMemberUsageStatsProvider {
+    stats: Arc<MemberUsageStats>,
+}
+
+impl CubeSqlMemberUsageStatsProvider {
+    pub fn new(stats: Arc<MemberUsageStats>) -> Self {
+        Self { stats }
+    }
+}
+
+impl TableName for CubeSqlMemberUsageStatsProvider {
+    fn table_name(&self) -> &str {
+        "cubesql.member_usage_stats"
+    }
+}
+
+#[async_trait]
+impl TableProvider for CubeSqlMemberUsageStatsProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("member", DataType::Utf8, false),
+            Field::new("cube", DataType::Utf8, false),
+            Field::new("member_type", DataType::Utf8, false),
+            Field::new("queries", DataType::UInt64, false),
+            Field::new("filters", DataType::UInt64, false),
+            Field::new(
+                "last_used",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let members = self.stats.snapshot();
+        let mut builder = CubeSqlMemberUsageStatsBuilder::new(members.len());
+
+        for (member, usage) in members.iter() {
+            builder.add_member(member, usage);
+        }
+
+        let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
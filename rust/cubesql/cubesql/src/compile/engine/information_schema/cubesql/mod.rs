@@ -0,0 +1,13 @@
+pub mod load_queue_stats;
+pub mod member_usage_stats;
+pub mod scan_schema_cache_stats;
+pub mod statement_classes;
+pub mod transform_pool_stats;
+pub mod unsupported_queries;
+
+pub use load_queue_stats::*;
+pub use member_usage_stats::*;
+pub use scan_schema_cache_stats::*;
+pub use statement_classes::*;
+pub use transform_pool_stats::*;
+pub use unsupported_queries::*;
@@ -0,0 +1,83 @@
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{Array, BooleanArray, UInt64Array},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::{compile::engine::provider::TableName, sql::StatementSchemasStats};
+
+/// `cubesql.scan_schema_cache_stats`, counters of the scan schema cache of the current session.
+/// Stats are captured while planning, so the query doesn't count itself.
+pub struct CubeSqlScanSchemaCacheStatsProvider {
+    enabled: bool,
+    stats: StatementSchemasStats,
+}
+
+impl CubeSqlScanSchemaCacheStatsProvider {
+    pub fn new(enabled: bool, stats: StatementSchemasStats) -> Self {
+        Self { enabled, stats }
+    }
+}
+
+impl TableName for CubeSqlScanSchemaCacheStatsProvider {
+    fn table_name(&self) -> &str {
+        "cubesql.scan_schema_cache_stats"
+    }
+}
+
+#[async_trait]
+impl TableProvider for CubeSqlScanSchemaCacheStatsProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("enabled", DataType::Boolean, false),
+            Field::new("entries", DataType::UInt64, false),
+            Field::new("hits", DataType::UInt64, false),
+            Field::new("misses", DataType::UInt64, false),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let columns: Vec<Arc<dyn Array>> = vec![
+            Arc::new(BooleanArray::from(vec![self.enabled])),
+            Arc::new(UInt64Array::from(vec![self.stats.entries as u64])),
+            Arc::new(UInt64Array::from(vec![self.stats.hits])),
+            Arc::new(UInt64Array::from(vec![self.stats.misses])),
+        ];
+        let batch = RecordBatch::try_new(self.schema(), columns)?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
@@ -0,0 +1,122 @@
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{Array, StringBuilder, TimestampNanosecondBuilder, UInt64Builder},
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::{
+    compile::engine::provider::TableName,
+    sql::{StatementClassStats, StatementClassUsage},
+};
+
+struct CubeSqlStatementClassesBuilder {
+    class: StringBuilder,
+    statements: UInt64Builder,
+    last_seen: TimestampNanosecondBuilder,
+}
+
+impl CubeSqlStatementClassesBuilder {
+    fn new(capacity: usize) -> Self {
+        Self {
+            class: StringBuilder::new(capacity),
+            statements: UInt64Builder::new(capacity),
+            last_seen: TimestampNanosecondBuilder::new(capacity),
+        }
+    }
+
+    fn add_class(&mut self, usage: &StatementClassUsage) {
+        self.class.append_value(usage.class.as_str()).unwrap();
+        self.statements.append_value(usage.statements).unwrap();
+        self.last_seen
+            .append_option(usage.last_seen.map(|last_seen| last_seen.timestamp_nanos()))
+            .unwrap();
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+
+        columns.push(Arc::new(self.class.finish()));
+        columns.push(Arc::new(self.statements.finish()));
+        columns.push(Arc::new(self.last_seen.finish()));
+
+        columns
+    }
+}
+
+/// `cubesql.statement_classes`, it's available for both MySQL & Postgres protocols
+pub struct CubeSqlStatementClassesProvider {
+    stats: Arc<StatementClassStats>,
+}
+
+impl CubeSqlStatementClassesProvider {
+    pub fn new(stats: Arc<StatementClassStats>) -> Self {
+        Self { stats }
+    }
+}
+
+impl TableName for CubeSqlStatementClassesProvider {
+    fn table_name(&self) -> &str {
+        "cubesql.statement_classes"
+    }
+}
+
+#[async_trait]
+impl TableProvider for CubeSqlStatementClassesProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("class", DataType::Utf8, false),
+            Field::new("statements", DataType::UInt64, false),
+            Field::new(
+                "last_seen",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                true,
+            ),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let classes = self.stats.snapshot();
+        let mut builder = CubeSqlStatementClassesBuilder::new(classes.len());
+
+        for usage in classes.iter() {
+            builder.add_class(usage);
+        }
+
+        let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
@@ -0,0 +1,98 @@
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{Array, UInt64Builder},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::compile::engine::{df::transform_pool::TransformPool, provider::TableName};
+
+/// `cubesql.transform_pool_stats`, queue and run time of JSON to Arrow transforms
+pub struct CubeSqlTransformPoolStatsProvider {
+    pool: Arc<TransformPool>,
+}
+
+impl CubeSqlTransformPoolStatsProvider {
+    pub fn new(pool: Arc<TransformPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl TableName for CubeSqlTransformPoolStatsProvider {
+    fn table_name(&self) -> &str {
+        "cubesql.transform_pool_stats"
+    }
+}
+
+#[async_trait]
+impl TableProvider for CubeSqlTransformPoolStatsProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("max_concurrent", DataType::UInt64, false),
+            Field::new("tasks", DataType::UInt64, false),
+            Field::new("running", DataType::UInt64, false),
+            Field::new("waiting", DataType::UInt64, false),
+            Field::new("total_wait_ms", DataType::UInt64, false),
+            Field::new("max_wait_ms", DataType::UInt64, false),
+            Field::new("total_run_ms", DataType::UInt64, false),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let metrics = self.pool.metrics();
+        let values = vec![
+            self.pool.max_concurrent() as u64,
+            metrics.tasks,
+            metrics.running as u64,
+            metrics.waiting as u64,
+            metrics.total_wait.as_millis() as u64,
+            metrics.max_wait.as_millis() as u64,
+            metrics.total_run.as_millis() as u64,
+        ];
+
+        let columns = values
+            .into_iter()
+            .map(|value| {
+                let mut builder = UInt64Builder::new(1);
+                builder.append_value(value).unwrap();
+
+                Arc::new(builder.finish()) as Arc<dyn Array>
+            })
+            .collect();
+        let batch = RecordBatch::try_new(self.schema(), columns)?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
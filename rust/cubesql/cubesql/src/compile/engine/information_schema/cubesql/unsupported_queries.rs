@@ -0,0 +1,134 @@
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{Array, StringBuilder, TimestampNanosecondBuilder, UInt64Builder},
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::{
+    compile::engine::provider::TableName,
+    sql::{UnsupportedQuery, UnsupportedQueryStats},
+};
+
+struct CubeSqlUnsupportedQueriesBuilder {
+    category: StringBuilder,
+    reason: StringBuilder,
+    queries: UInt64Builder,
+    sanitized_query: StringBuilder,
+    last_seen: TimestampNanosecondBuilder,
+}
+
+impl CubeSqlUnsupportedQueriesBuilder {
+    fn new(capacity: usize) -> Self {
+        Self {
+            category: StringBuilder::new(capacity),
+            reason: StringBuilder::new(capacity),
+            queries: UInt64Builder::new(capacity),
+            sanitized_query: StringBuilder::new(capacity),
+            last_seen: TimestampNanosecondBuilder::new(capacity),
+        }
+    }
+
+    fn add_query(&mut self, query: &UnsupportedQuery) {
+        self.category.append_value(query.category.as_str()).unwrap();
+        self.reason.append_value(&query.reason).unwrap();
+        self.queries.append_value(query.queries).unwrap();
+        self.sanitized_query
+            .append_option(query.sanitized_query.as_ref())
+            .unwrap();
+        self.last_seen
+            .append_value(query.last_seen.timestamp_nanos())
+            .unwrap();
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+
+        columns.push(Arc::new(self.category.finish()));
+        columns.push(Arc::new(self.reason.finish()));
+        columns.push(Arc::new(self.queries.finish()));
+        columns.push(Arc::new(self.sanitized_query.finish()));
+        columns.push(Arc::new(self.last_seen.finish()));
+
+        columns
+    }
+}
+
+/// `cubesql.unsupported_queries`, it's available for both MySQL & Postgres protocols
+pub struct CubeSqlUnsupportedQueriesProvider {
+    stats: Arc<UnsupportedQueryStats>,
+}
+
+impl CubeSqlUnsupportedQueriesProvider {
+    pub fn new(stats: Arc<UnsupportedQueryStats>) -> Self {
+        Self { stats }
+    }
+}
+
+impl TableName for CubeSqlUnsupportedQueriesProvider {
+    fn table_name(&self) -> &str {
+        "cubesql.unsupported_queries"
+    }
+}
+
+#[async_trait]
+impl TableProvider for CubeSqlUnsupportedQueriesProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Utf8, false),
+            Field::new("reason", DataType::Utf8, false),
+            Field::new("queries", DataType::UInt64, false),
+            Field::new("sanitized_query", DataType::Utf8, true),
+            Field::new(
+                "last_seen",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let queries = self.stats.snapshot();
+        let mut builder = CubeSqlUnsupportedQueriesBuilder::new(queries.len());
+
+        for query in queries.iter() {
+            builder.add_query(query);
+        }
+
+        let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
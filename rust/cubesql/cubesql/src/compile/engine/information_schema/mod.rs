@@ -1,3 +1,4 @@
+pub mod cubesql;
 pub mod mysql;
 pub mod postgres;
 pub mod redshift;
@@ -0,0 +1,152 @@
+use std::{any::Any, sync::Arc};
+
+use crate::compile::engine::provider::TableName;
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{Array, ArrayRef, StringBuilder, UInt32Builder},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+/// Character sets which can be negotiated with `SET NAMES`: name, default collation, description
+/// and max length of a character. Results are always encoded as UTF-8, so only UTF-8 is listed.
+pub const CHARACTER_SETS: &[(&str, &str, &str, u32)] = &[
+    ("utf8mb3", "utf8mb3_general_ci", "UTF-8 Unicode", 3),
+    ("utf8mb4", "utf8mb4_0900_ai_ci", "UTF-8 Unicode", 4),
+];
+
+/// Resolves aliases, `utf8` is an alias of `utf8mb3` since MySQL 8.0
+pub fn find_character_set(name: &str) -> Option<(&'static str, &'static str)> {
+    let name = name.to_ascii_lowercase();
+    let name = match name.as_str() {
+        "utf8" => "utf8mb3",
+        name => name,
+    };
+
+    CHARACTER_SETS
+        .iter()
+        .find(|(charset, _, _, _)| *charset == name)
+        .map(|(charset, collation, _, _)| (*charset, *collation))
+}
+
+struct InformationSchemaCharacterSetsBuilder {
+    character_set_names: StringBuilder,
+    default_collate_names: StringBuilder,
+    descriptions: StringBuilder,
+    maxlens: UInt32Builder,
+}
+
+impl InformationSchemaCharacterSetsBuilder {
+    fn new() -> Self {
+        let capacity = CHARACTER_SETS.len();
+
+        Self {
+            character_set_names: StringBuilder::new(capacity),
+            default_collate_names: StringBuilder::new(capacity),
+            descriptions: StringBuilder::new(capacity),
+            maxlens: UInt32Builder::new(capacity),
+        }
+    }
+
+    fn add_character_set(
+        &mut self,
+        character_set_name: impl AsRef<str>,
+        default_collate_name: impl AsRef<str>,
+        description: impl AsRef<str>,
+        maxlen: u32,
+    ) {
+        self.character_set_names
+            .append_value(character_set_name.as_ref())
+            .unwrap();
+        self.default_collate_names
+            .append_value(default_collate_name.as_ref())
+            .unwrap();
+        self.descriptions
+            .append_value(description.as_ref())
+            .unwrap();
+        self.maxlens.append_value(maxlen).unwrap();
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+
+        columns.push(Arc::new(self.character_set_names.finish()));
+        columns.push(Arc::new(self.default_collate_names.finish()));
+        columns.push(Arc::new(self.descriptions.finish()));
+        columns.push(Arc::new(self.maxlens.finish()));
+
+        columns
+    }
+}
+
+pub struct InfoSchemaCharacterSetsProvider {
+    data: Arc<Vec<ArrayRef>>,
+}
+
+impl TableName for InfoSchemaCharacterSetsProvider {
+    fn table_name(&self) -> &str {
+        "information_schema.character_sets"
+    }
+}
+
+impl InfoSchemaCharacterSetsProvider {
+    pub fn new() -> Self {
+        let mut builder = InformationSchemaCharacterSetsBuilder::new();
+
+        for (name, default_collation, description, maxlen) in CHARACTER_SETS {
+            builder.add_character_set(name, default_collation, description, *maxlen);
+        }
+
+        Self {
+            data: Arc::new(builder.finish()),
+        }
+    }
+}
+
+#[async_trait]
+impl TableProvider for InfoSchemaCharacterSetsProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("CHARACTER_SET_NAME", DataType::Utf8, false),
+            Field::new("DEFAULT_COLLATE_NAME", DataType::Utf8, false),
+            Field::new("DESCRIPTION", DataType::Utf8, false),
+            Field::new("MAXLEN", DataType::UInt32, false),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let batch = RecordBatch::try_new(self.schema(), self.data.to_vec())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
@@ -163,9 +163,10 @@ impl InfoSchemaColumnsProvider {
 
         for cube in meta.cubes.iter() {
             let position = 0;
+            let schema_name = cube.sql_schema().unwrap_or_else(|| "db".to_string());
 
             for column in cube.get_columns() {
-                builder.add_column("def", "db", cube.name.clone(), &column, position)
+                builder.add_column("def", &schema_name, cube.name.clone(), &column, position)
             }
         }
 
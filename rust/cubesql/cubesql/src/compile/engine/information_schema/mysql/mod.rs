@@ -1,3 +1,4 @@
+pub mod character_sets;
 pub mod collations;
 pub mod columns;
 pub mod ext;
@@ -15,7 +15,7 @@ use datafusion::{
 };
 
 use super::utils::new_string_array_with_placeholder;
-use crate::compile::engine::provider::TableName;
+use crate::compile::{engine::provider::TableName, CubeMetaSchema};
 
 struct InformationSchemaSchemataBuilder {
     catalog_names: StringBuilder,
@@ -85,7 +85,7 @@ impl TableName for InfoSchemaSchemataProvider {
 }
 
 impl InfoSchemaSchemataProvider {
-    pub fn new() -> Self {
+    pub fn new(cube_schemas: &Vec<CubeMetaSchema>) -> Self {
         let mut builder = InformationSchemaSchemataBuilder::new();
         // information_schema
         builder.add_schema("information_schema", "utf8", "utf8_general_ci");
@@ -94,6 +94,10 @@ impl InfoSchemaSchemataProvider {
         builder.add_schema("sys", "utf8mb4", "utf8mb4_0900_ai_ci");
         builder.add_schema("test", "utf8mb4", "utf8mb4_0900_ai_ci");
 
+        for schema in cube_schemas.iter() {
+            builder.add_schema(&schema.name, "utf8mb4", "utf8mb4_0900_ai_ci");
+        }
+
         Self {
             data: Arc::new(builder.finish()),
         }
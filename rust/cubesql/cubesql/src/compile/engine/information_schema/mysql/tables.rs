@@ -1,6 +1,9 @@
 use std::{any::Any, sync::Arc};
 
-use crate::{compile::engine::provider::TableName, transport::MetaContext};
+use crate::{
+    compile::engine::provider::TableName,
+    transport::{MetaContext, V1CubeMetaExt},
+};
 use async_trait::async_trait;
 use datafusion::{
     arrow::{
@@ -153,7 +156,11 @@ impl InfoSchemaTableProvider {
         builder.add_table("def", "performance_schema", "global_variables");
 
         for cube in meta.cubes.iter() {
-            builder.add_table("def", "db", cube.name.clone());
+            builder.add_table(
+                "def",
+                cube.sql_schema().unwrap_or_else(|| "db".to_string()),
+                cube.name.clone(),
+            );
         }
 
         Self {
@@ -317,9 +317,10 @@ impl InfoSchemaColumnsProvider {
 
         for cube in cubes {
             let mut position = 1;
+            let schema_name = cube.sql_schema().unwrap_or_else(|| "public".to_string());
 
             for column in cube.get_columns() {
-                builder.add_column(db_name, "public", cube.name.clone(), &column, position);
+                builder.add_column(db_name, &schema_name, cube.name.clone(), &column, position);
 
                 position += 1;
             }
@@ -207,7 +207,7 @@ impl PgCatalogClassProvider {
             builder.add_class(&PgClass {
                 oid: table.oid,
                 relname: table.name.clone(),
-                relnamespace: 2200,
+                relnamespace: table.namespace_oid,
                 reltype: table.record_oid,
                 relam: 2,
                 relfilenode: 0,
@@ -14,9 +14,11 @@ use datafusion::{
     physical_plan::{memory::MemoryExec, ExecutionPlan},
 };
 
-struct PgNamespace {
+use crate::compile::CubeMetaSchema;
+
+struct PgNamespace<'a> {
     oid: u32,
-    nspname: &'static str,
+    nspname: &'a str,
     nspowner: u32,
     nspacl: &'static str,
 }
@@ -63,7 +65,7 @@ pub struct PgCatalogNamespaceProvider {
 }
 
 impl PgCatalogNamespaceProvider {
-    pub fn new() -> Self {
+    pub fn new(cube_schemas: &Vec<CubeMetaSchema>) -> Self {
         let mut builder = PgCatalogNamespaceBuilder::new();
         builder.add_namespace(&PgNamespace {
             oid: 11,
@@ -84,6 +86,15 @@ impl PgCatalogNamespaceProvider {
             nspacl: "{test=UC/test,=U/test}",
         });
 
+        for schema in cube_schemas.iter() {
+            builder.add_namespace(&PgNamespace {
+                oid: schema.oid,
+                nspname: &schema.name,
+                nspowner: 10,
+                nspacl: "{test=UC/test,=U/test}",
+            });
+        }
+
         Self {
             data: Arc::new(builder.finish()),
         }
@@ -2,7 +2,7 @@ use std::{any::Any, sync::Arc};
 
 use async_trait::async_trait;
 
-use crate::sql::{extended::PreparedStatement, SessionState};
+use crate::sql::{extended::PreparedStatement, PreparedStatementUsage, SessionState};
 use datafusion::{
     arrow::{
         array::{
@@ -41,7 +41,12 @@ impl PgPreparedStatementsBuilder {
         }
     }
 
-    fn add_prepared_statement(&mut self, name: &str, statement: &PreparedStatement) {
+    fn add_prepared_statement(
+        &mut self,
+        name: &str,
+        statement: &PreparedStatement,
+        usage: &PreparedStatementUsage,
+    ) {
         self.name.append_value(name).unwrap();
         self.prepare_time
             .append_value(statement.get_created().timestamp_nanos())
@@ -63,9 +68,11 @@ impl PgPreparedStatementsBuilder {
         self.from_sql
             .append_value(statement.get_from_sql())
             .unwrap();
-        // TODO: Stats
+        // Every execution is planned with bound values, it's a custom plan in terms of Postgres
         self.generic_plans.append_value(0).unwrap();
-        self.custom_plans.append_value(0).unwrap();
+        self.custom_plans
+            .append_value(usage.executions as i64)
+            .unwrap();
     }
 
     fn finish(mut self) -> Vec<Arc<dyn Array>> {
@@ -132,8 +139,8 @@ impl TableProvider for PgPreparedStatementsProvider {
         let statements = self.session.statements.read().await;
         let mut builder = PgPreparedStatementsBuilder::new(statements.len());
 
-        for (name, statement) in statements.iter() {
-            builder.add_prepared_statement(&name, statement);
+        for (name, statement, usage) in statements.iter() {
+            builder.add_prepared_statement(&name, statement, usage);
         }
 
         let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
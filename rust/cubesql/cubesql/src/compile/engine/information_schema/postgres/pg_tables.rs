@@ -14,6 +14,8 @@ use datafusion::{
     physical_plan::{memory::MemoryExec, ExecutionPlan},
 };
 
+use crate::transport::V1CubeMetaExt;
+
 use super::utils::{new_boolean_array_with_placeholder, new_string_array_with_placeholder};
 
 struct PgCatalogTablesBuilder {
@@ -84,7 +86,11 @@ impl PgCatalogTableProvider {
         let mut builder = PgCatalogTablesBuilder::new();
 
         for cube in cubes {
-            builder.add_table("public", cube.name.clone(), current_user);
+            builder.add_table(
+                cube.sql_schema().unwrap_or_else(|| "public".to_string()),
+                cube.name.clone(),
+                current_user,
+            );
         }
 
         Self {
@@ -74,12 +74,14 @@ impl InfoSchemaRoleColumnGrantsBuilder {
     }
 }
 
+/// Served as both `role_column_grants` and `column_privileges`
 pub struct InfoSchemaRoleColumnGrantsProvider {
+    name: String,
     data: Arc<Vec<ArrayRef>>,
 }
 
 impl InfoSchemaRoleColumnGrantsProvider {
-    pub fn new(db_name: &str, current_user: &str, cubes: &Vec<V1CubeMeta>) -> Self {
+    pub fn new(name: &str, db_name: &str, current_user: &str, cubes: &Vec<V1CubeMeta>) -> Self {
         let mut builder = InfoSchemaRoleColumnGrantsBuilder::new(cubes.len());
 
         for cube in cubes {
@@ -87,7 +89,7 @@ impl InfoSchemaRoleColumnGrantsProvider {
                 builder.add_column(
                     current_user,
                     db_name,
-                    "public",
+                    cube.sql_schema().unwrap_or_else(|| "public".to_string()),
                     cube.name.clone(),
                     &column.get_name(),
                     &"SELECT",
@@ -96,9 +98,14 @@ impl InfoSchemaRoleColumnGrantsProvider {
         }
 
         Self {
+            name: name.to_string(),
             data: Arc::new(builder.finish()),
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 #[async_trait]
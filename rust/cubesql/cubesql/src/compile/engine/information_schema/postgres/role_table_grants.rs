@@ -1,5 +1,6 @@
 use std::{any::Any, sync::Arc};
 
+use crate::transport::V1CubeMetaExt;
 use async_trait::async_trait;
 use cubeclient::models::V1CubeMeta;
 use datafusion::{
@@ -72,22 +73,36 @@ impl InfoSchemaRoleTableGrantsBuilder {
     }
 }
 
+/// Served as both `role_table_grants` and `table_privileges`, cubes are already filtered by
+/// the security context of the user, so every visible member is granted
 pub struct InfoSchemaRoleTableGrantsProvider {
+    name: String,
     data: Arc<Vec<ArrayRef>>,
 }
 
 impl InfoSchemaRoleTableGrantsProvider {
-    pub fn new(db_name: &str, current_user: &str, cubes: &Vec<V1CubeMeta>) -> Self {
+    pub fn new(name: &str, db_name: &str, current_user: &str, cubes: &Vec<V1CubeMeta>) -> Self {
         let mut builder = InfoSchemaRoleTableGrantsBuilder::new(cubes.len());
 
         for cube in cubes {
-            builder.add_table(current_user, db_name, "public", cube.name.clone(), "SELECT");
+            builder.add_table(
+                current_user,
+                db_name,
+                cube.sql_schema().unwrap_or_else(|| "public".to_string()),
+                cube.name.clone(),
+                "SELECT",
+            );
         }
 
         Self {
+            name: name.to_string(),
             data: Arc::new(builder.finish()),
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 #[async_trait]
@@ -14,6 +14,8 @@ use datafusion::{
     physical_plan::{memory::MemoryExec, ExecutionPlan},
 };
 
+use crate::transport::V1CubeMetaExt;
+
 use super::utils::new_string_array_with_placeholder;
 
 struct InformationSchemaTablesBuilder {
@@ -127,7 +129,12 @@ impl InfoSchemaTableProvider {
         builder.add_table(db_name, "information_schema", "pg_tables", "VIEW");
 
         for cube in cubes {
-            builder.add_table(db_name, "public", &cube.name, "BASE TABLE");
+            builder.add_table(
+                db_name,
+                cube.sql_schema().unwrap_or_else(|| "public".to_string()),
+                &cube.name,
+                "BASE TABLE",
+            );
         }
 
         Self {
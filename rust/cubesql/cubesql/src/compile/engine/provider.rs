@@ -19,7 +19,16 @@ use crate::{
     CubeError,
 };
 
+use super::df::last_result::LastResultProvider;
+
+use super::information_schema::cubesql::{
+    CubeSqlLoadQueueStatsProvider, CubeSqlMemberUsageStatsProvider,
+    CubeSqlScanSchemaCacheStatsProvider, CubeSqlStatementClassesProvider,
+    CubeSqlTransformPoolStatsProvider, CubeSqlUnsupportedQueriesProvider,
+};
+
 use super::information_schema::mysql::{
+    character_sets::InfoSchemaCharacterSetsProvider as MySqlSchemaCharacterSetsProvider,
     collations::InfoSchemaCollationsProvider as MySqlSchemaCollationsProvider,
     columns::InfoSchemaColumnsProvider as MySqlSchemaColumnsProvider,
     key_column_usage::InfoSchemaKeyColumnUsageProvider as MySqlSchemaKeyColumnUsageProvider,
@@ -68,6 +77,8 @@ pub struct CubeContext {
     pub meta: Arc<MetaContext>,
     pub sessions: Arc<SessionManager>,
     pub session_state: Arc<SessionState>,
+    /// Time zone of load requests, conversions of time dimensions to it are pushed down to Cube
+    pub request_timezone: Option<String>,
 }
 
 impl CubeContext {
@@ -82,9 +93,15 @@ impl CubeContext {
             meta,
             sessions,
             session_state,
+            request_timezone: None,
         }
     }
 
+    pub fn with_request_timezone(mut self, request_timezone: Option<String>) -> Self {
+        self.request_timezone = request_timezone;
+        self
+    }
+
     pub fn table_name_by_table_provider(
         &self,
         table_provider: Arc<dyn datasource::TableProvider>,
@@ -171,10 +188,26 @@ impl DatabaseProtocol {
             t.table_name().to_string()
         } else if let Some(t) = any.downcast_ref::<MySqlSchemaCollationsProvider>() {
             t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<MySqlSchemaCharacterSetsProvider>() {
+            t.table_name().to_string()
         } else if let Some(t) = any.downcast_ref::<MySqlPerfSchemaVariablesProvider>() {
             t.table_name().to_string()
         } else if let Some(t) = any.downcast_ref::<MySqlSchemaProcesslistProvider>() {
             t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlMemberUsageStatsProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlLoadQueueStatsProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlTransformPoolStatsProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlUnsupportedQueriesProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlStatementClassesProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlScanSchemaCacheStatsProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<LastResultProvider>() {
+            t.table_name().to_string()
         } else {
             return Err(CubeError::internal(format!(
                 "Unknown table provider with schema: {:?}",
@@ -203,6 +236,11 @@ impl DatabaseProtocol {
         };
 
         match db.as_str() {
+            "db" if table == "cube_last_result" => {
+                return Some(Arc::new(LastResultProvider::new(
+                    context.session_state.last_result(),
+                )))
+            }
             "db" => {
                 if let Some(cube) = context
                     .meta
@@ -231,7 +269,11 @@ impl DatabaseProtocol {
                 "key_column_usage" => {
                     return Some(Arc::new(MySqlSchemaKeyColumnUsageProvider::new()))
                 }
-                "schemata" => return Some(Arc::new(MySqlSchemaSchemataProvider::new())),
+                "schemata" => {
+                    return Some(Arc::new(MySqlSchemaSchemataProvider::new(
+                        &context.meta.schemas,
+                    )))
+                }
                 "processlist" => {
                     return Some(Arc::new(MySqlSchemaProcesslistProvider::new(
                         context.sessions.clone(),
@@ -241,6 +283,7 @@ impl DatabaseProtocol {
                     return Some(Arc::new(MySqlSchemaReferentialConstraintsProvider::new()))
                 }
                 "collations" => return Some(Arc::new(MySqlSchemaCollationsProvider::new())),
+                "character_sets" => return Some(Arc::new(MySqlSchemaCharacterSetsProvider::new())),
                 _ => return None,
             },
             "performance_schema" => match table.as_str() {
@@ -261,7 +304,45 @@ impl DatabaseProtocol {
                 }
                 _ => return None,
             },
-            _ => return None,
+            "cubesql" if table == "member_usage_stats" => {
+                return Some(Arc::new(CubeSqlMemberUsageStatsProvider::new(
+                    context.sessions.server.member_usage.clone(),
+                )))
+            }
+            "cubesql" if table == "load_queue_stats" => {
+                return Some(Arc::new(CubeSqlLoadQueueStatsProvider::new(
+                    context.sessions.server.load_limiter.clone(),
+                )))
+            }
+            "cubesql" if table == "transform_pool_stats" => {
+                return Some(Arc::new(CubeSqlTransformPoolStatsProvider::new(
+                    context.sessions.server.transform_pool.clone(),
+                )))
+            }
+            "cubesql" if table == "unsupported_queries" => {
+                return Some(Arc::new(CubeSqlUnsupportedQueriesProvider::new(
+                    context.sessions.server.unsupported_queries.clone(),
+                )))
+            }
+            "cubesql" if table == "statement_classes" => {
+                return Some(Arc::new(CubeSqlStatementClassesProvider::new(
+                    context.sessions.server.statement_classes.clone(),
+                )))
+            }
+            "cubesql" if table == "scan_schema_cache_stats" => {
+                return Some(Arc::new(CubeSqlScanSchemaCacheStatsProvider::new(
+                    context.session_state.scan_schema_cache_enabled(),
+                    context.session_state.statement_schemas.stats(),
+                )))
+            }
+            // Cubes can be organized into schemas by folders
+            schema => {
+                if let Some(cube) = context.meta.find_cube_in_schema(schema, &table) {
+                    return Some(Arc::new(CubeTableProvider::new(cube.clone())));
+                } else {
+                    return None;
+                }
+            }
         }
     }
 
@@ -284,10 +365,10 @@ impl DatabaseProtocol {
             "information_schema.referential_constraints".to_string()
         } else if let Some(_) = any.downcast_ref::<PostgresSchemaTableConstraintsProvider>() {
             "information_schema.table_constraints".to_string()
-        } else if let Some(_) = any.downcast_ref::<PostgresInfoSchemaRoleTableGrantsProvider>() {
-            "information_schema.role_table_grants".to_string()
-        } else if let Some(_) = any.downcast_ref::<PostgresInfoSchemaRoleColumnGrantsProvider>() {
-            "information_schema.role_column_grants".to_string()
+        } else if let Some(t) = any.downcast_ref::<PostgresInfoSchemaRoleTableGrantsProvider>() {
+            format!("information_schema.{}", t.name())
+        } else if let Some(t) = any.downcast_ref::<PostgresInfoSchemaRoleColumnGrantsProvider>() {
+            format!("information_schema.{}", t.name())
         } else if let Some(_) = any.downcast_ref::<PgCatalogTableProvider>() {
             "pg_catalog.pg_tables".to_string()
         } else if let Some(_) = any.downcast_ref::<PgCatalogTypeProvider>() {
@@ -338,6 +419,20 @@ impl DatabaseProtocol {
             "pg_catalog.pg_user".to_string()
         } else if let Some(_) = any.downcast_ref::<PgCatalogExtensionProvider>() {
             "pg_catalog.pg_extension".to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlMemberUsageStatsProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlLoadQueueStatsProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlTransformPoolStatsProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlUnsupportedQueriesProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlStatementClassesProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<CubeSqlScanSchemaCacheStatsProvider>() {
+            t.table_name().to_string()
+        } else if let Some(t) = any.downcast_ref::<LastResultProvider>() {
+            t.table_name().to_string()
         } else if let Some(_) = any.downcast_ref::<RedshiftSvvTablesTableProvider>() {
             "public.svv_tables".to_string()
         } else if let Some(_) = any.downcast_ref::<RedshiftSvvExternalSchemasTableProvider>() {
@@ -441,6 +536,11 @@ impl DatabaseProtocol {
                     "get_late_binding_view_cols_unpacked" => {
                         return Some(Arc::new(RedshiftLateBindingViewUnpackedTableProvider::new()))
                     }
+                    "cube_last_result" => {
+                        return Some(Arc::new(LastResultProvider::new(
+                            context.session_state.last_result(),
+                        )))
+                    }
                     _ => {}
                 };
             }
@@ -468,15 +568,17 @@ impl DatabaseProtocol {
                 "referential_constraints" => {
                     return Some(Arc::new(PostgresSchemaReferentialConstraintsProvider::new()))
                 }
-                "role_table_grants" => {
+                name @ ("role_table_grants" | "table_privileges") => {
                     return Some(Arc::new(PostgresInfoSchemaRoleTableGrantsProvider::new(
+                        name,
                         &context.session_state.database().unwrap_or("db".to_string()),
                         &context.session_state.user().unwrap_or("test".to_string()),
                         &context.meta.cubes,
                     )))
                 }
-                "role_column_grants" => {
+                name @ ("role_column_grants" | "column_privileges") => {
                     return Some(Arc::new(PostgresInfoSchemaRoleColumnGrantsProvider::new(
+                        name,
                         &context.session_state.database().unwrap_or("db".to_string()),
                         &context.session_state.user().unwrap_or("test".to_string()),
                         &context.meta.cubes,
@@ -509,7 +611,11 @@ impl DatabaseProtocol {
                 "pg_type" => {
                     return Some(Arc::new(PgCatalogTypeProvider::new(&context.meta.tables)))
                 }
-                "pg_namespace" => return Some(Arc::new(PgCatalogNamespaceProvider::new())),
+                "pg_namespace" => {
+                    return Some(Arc::new(PgCatalogNamespaceProvider::new(
+                        &context.meta.schemas,
+                    )))
+                }
                 "pg_range" => return Some(Arc::new(PgCatalogRangeProvider::new())),
                 "pg_attrdef" => return Some(Arc::new(PgCatalogAttrdefProvider::new())),
                 "pg_attribute" => {
@@ -570,7 +676,43 @@ impl DatabaseProtocol {
                 "pg_extension" => return Some(Arc::new(PgCatalogExtensionProvider::new())),
                 _ => return None,
             },
-            _ => return None,
+            "cubesql" if table == "member_usage_stats" => {
+                return Some(Arc::new(CubeSqlMemberUsageStatsProvider::new(
+                    context.sessions.server.member_usage.clone(),
+                )))
+            }
+            "cubesql" if table == "load_queue_stats" => {
+                return Some(Arc::new(CubeSqlLoadQueueStatsProvider::new(
+                    context.sessions.server.load_limiter.clone(),
+                )))
+            }
+            "cubesql" if table == "transform_pool_stats" => {
+                return Some(Arc::new(CubeSqlTransformPoolStatsProvider::new(
+                    context.sessions.server.transform_pool.clone(),
+                )))
+            }
+            "cubesql" if table == "unsupported_queries" => {
+                return Some(Arc::new(CubeSqlUnsupportedQueriesProvider::new(
+                    context.sessions.server.unsupported_queries.clone(),
+                )))
+            }
+            "cubesql" if table == "statement_classes" => {
+                return Some(Arc::new(CubeSqlStatementClassesProvider::new(
+                    context.sessions.server.statement_classes.clone(),
+                )))
+            }
+            "cubesql" if table == "scan_schema_cache_stats" => {
+                return Some(Arc::new(CubeSqlScanSchemaCacheStatsProvider::new(
+                    context.session_state.scan_schema_cache_enabled(),
+                    context.session_state.statement_schemas.stats(),
+                )))
+            }
+            // Cubes can be organized into schemas by folders
+            schema => {
+                if let Some(cube) = context.meta.find_cube_in_schema(schema, &table) {
+                    return Some(Arc::new(CubeTableProvider::new(cube.clone())));
+                }
+            }
         }
 
         None
@@ -43,7 +43,8 @@ use crate::{
         coerce::{if_coercion, least_coercion},
         columar::if_then_else,
     },
-    sql::SessionState,
+    sql::{ServerVersion, SessionState},
+    transport::{MetaContext, V1CubeMetaExt},
 };
 
 pub type ReturnTypeFunction = Arc<dyn Fn(&[DataType]) -> Result<Arc<DataType>> + Send + Sync>;
@@ -139,7 +140,7 @@ pub fn create_current_user_udf(state: Arc<SessionState>, name: &str, with_host:
 pub fn create_session_user_udf(state: Arc<SessionState>) -> ScalarUDF {
     let fun = make_scalar_function(move |_args: &[ArrayRef]| {
         let mut builder = StringBuilder::new(1);
-        if let Some(user) = &state.user() {
+        if let Some(user) = &state.session_user() {
             builder.append_value(user.clone()).unwrap();
         } else {
             builder.append_null()?;
@@ -157,6 +158,64 @@ pub fn create_session_user_udf(state: Arc<SessionState>) -> ScalarUDF {
     )
 }
 
+pub fn create_cube_user_switched_udf(state: Arc<SessionState>) -> ScalarUDF {
+    let fun = make_scalar_function(move |_args: &[ArrayRef]| {
+        let mut builder = BooleanBuilder::new(1);
+        builder.append_value(state.is_user_switched())?;
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    create_udf(
+        "cube_user_switched",
+        vec![],
+        Arc::new(DataType::Boolean),
+        Volatility::Stable,
+        fun,
+    )
+}
+
+/// Attribute of the security context by a dotted path, e.g. `cube_security_context('user.tenant')`.
+/// Strings are returned as is, other values as JSON.
+pub fn create_cube_security_context_udf(state: Arc<SessionState>) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 1);
+
+        let paths = downcast_string_arg!(args[0], "attribute", i32);
+        let security_context = state
+            .auth_context()
+            .and_then(|auth_context| auth_context.security_context());
+
+        let result = paths
+            .iter()
+            .map(|path| {
+                let value =
+                    path.zip(security_context.as_ref())
+                        .and_then(|(path, security_context)| {
+                            path.split('.')
+                                .try_fold(security_context, |value, key| value.get(key))
+                        })?;
+
+                match value {
+                    serde_json::Value::Null => None,
+                    serde_json::Value::String(value) => Some(value.clone()),
+                    value => Some(value.to_string()),
+                }
+            })
+            .collect::<StringArray>();
+
+        Ok(Arc::new(result) as ArrayRef)
+    });
+
+    create_udf(
+        "cube_security_context",
+        vec![DataType::Utf8],
+        Arc::new(DataType::Utf8),
+        Volatility::Stable,
+        fun,
+    )
+}
+
 pub fn create_connection_id_udf(state: Arc<SessionState>) -> ScalarUDF {
     let fun = make_scalar_function(move |_args: &[ArrayRef]| {
         let mut builder = UInt32Builder::new(1);
@@ -2114,14 +2173,43 @@ pub fn create_pg_table_is_visible_udf() -> ScalarUDF {
     )
 }
 
+/// Longest sleep of pg_sleep() and sleep(), longer sleeps are cut to it
+const MAX_SLEEP_SECS: f64 = 300.0;
+
+/// UDFs are synchronous, the worker of a multi-threaded runtime hands its other tasks over while
+/// the statement sleeps
+fn sleep_in_udf(name: &str, secs: f64) -> Result<()> {
+    if !secs.is_finite() {
+        return Err(DataFusionError::Execution(format!(
+            "{}() expects a finite number of seconds, actual: {}",
+            name, secs
+        )));
+    }
+
+    if secs <= 0.0 {
+        return Ok(());
+    }
+
+    let duration = core::time::Duration::from_secs_f64(secs.min(MAX_SLEEP_SECS));
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| thread::sleep(duration))
+        }
+        _ => thread::sleep(duration),
+    }
+
+    Ok(())
+}
+
 pub fn create_pg_sleep_udf() -> ScalarUDF {
     let fun = make_scalar_function(move |args: &[ArrayRef]| {
         assert!(args.len() == 1);
 
-        let secs_arr = downcast_primitive_arg!(args[0], "secs", Int64Type);
+        let secs_arr = cast(&args[0], &DataType::Float64)?;
+        let secs_arr = downcast_primitive_arg!(secs_arr, "secs", Float64Type);
 
         if !secs_arr.is_null(0) {
-            thread::sleep(core::time::Duration::new(secs_arr.value(0) as u64, 0));
+            sleep_in_udf("pg_sleep", secs_arr.value(0))?;
         }
 
         let mut result = StringBuilder::new(1);
@@ -2134,7 +2222,112 @@ pub fn create_pg_sleep_udf() -> ScalarUDF {
 
     ScalarUDF::new(
         "pg_sleep",
-        &Signature::exact(vec![DataType::Int64], Volatility::Volatile),
+        &Signature::one_of(
+            vec![
+                TypeSignature::Exact(vec![DataType::Int64]),
+                TypeSignature::Exact(vec![DataType::Float64]),
+            ],
+            Volatility::Volatile,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
+// MySQL version of pg_sleep, returns 0 when the sleep was not interrupted
+pub fn create_sleep_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 1);
+
+        let secs_arr = cast(&args[0], &DataType::Float64)?;
+        let secs_arr = downcast_primitive_arg!(secs_arr, "secs", Float64Type);
+
+        if !secs_arr.is_null(0) {
+            sleep_in_udf("sleep", secs_arr.value(0))?;
+        }
+
+        let mut result = Int64Builder::new(1);
+        result.append_value(0)?;
+
+        Ok(Arc::new(result.finish()))
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Int64)));
+
+    ScalarUDF::new(
+        "sleep",
+        &Signature::one_of(
+            vec![
+                TypeSignature::Exact(vec![DataType::Int64]),
+                TypeSignature::Exact(vec![DataType::Float64]),
+            ],
+            Volatility::Volatile,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
+pub fn create_pg_is_in_recovery_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |_: &[ArrayRef]| {
+        let mut builder = BooleanBuilder::new(1);
+        builder.append_value(false)?;
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Boolean)));
+
+    ScalarUDF::new(
+        "pg_is_in_recovery",
+        &Signature::exact(vec![], Volatility::Volatile),
+        &return_type,
+        &fun,
+    )
+}
+
+pub fn create_pg_client_encoding_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |_: &[ArrayRef]| {
+        let mut builder = StringBuilder::new(1);
+        builder.append_value("UTF8")?;
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        "pg_client_encoding",
+        &Signature::exact(vec![], Volatility::Stable),
+        &return_type,
+        &fun,
+    )
+}
+
+lazy_static! {
+    static ref SERVER_START_TIME: NaiveDateTime = chrono::Utc::now().naive_utc();
+}
+
+/// Used for pg_postmaster_start_time and pg_conf_load_time, configuration is loaded only once
+/// on start up, so both of them return the time of the first call within the process
+pub fn create_server_start_time_udf(name: &'static str) -> ScalarUDF {
+    let fun = make_scalar_function(move |_: &[ArrayRef]| {
+        let mut builder = TimestampNanosecondArray::builder(1);
+        builder.append_value(SERVER_START_TIME.timestamp_nanos())?;
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| {
+        Ok(Arc::new(DataType::Timestamp(
+            TimeUnit::Nanosecond,
+            Some("UTC".to_string()),
+        )))
+    });
+
+    ScalarUDF::new(
+        name,
+        &Signature::exact(vec![], Volatility::Stable),
         &return_type,
         &fun,
     )
@@ -2749,6 +2942,137 @@ pub fn create_has_schema_privilege_udf(state: Arc<SessionState>) -> ScalarUDF {
     )
 }
 
+/// Only SELECT is granted. Cubes are checked against the meta of the user, which is already
+/// filtered by its security context. Unknown oids are system relations of pg_class.
+pub fn create_has_table_privilege_udf(
+    state: Arc<SessionState>,
+    meta: Arc<MetaContext>,
+) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let (users, tables, privileges) = if args.len() == 3 {
+            (
+                Some(downcast_string_arg!(args[0], "user", i32)),
+                &args[1],
+                downcast_string_arg!(args[2], "privilege", i32),
+            )
+        } else {
+            (
+                None,
+                &args[0],
+                downcast_string_arg!(args[1], "privilege", i32),
+            )
+        };
+
+        let tables = match tables.data_type() {
+            DataType::UInt32 => downcast_primitive_arg!(tables, "table", OidType)
+                .iter()
+                .map(|oid| oid.map(|_| Ok(())))
+                .collect::<Vec<_>>(),
+            _ => downcast_string_arg!(tables, "table", i32)
+                .iter()
+                .map(|table| table.map(|table| check_table_exists(&meta, table)))
+                .collect::<Vec<_>>(),
+        };
+
+        let result = izip!(tables, privileges)
+            .enumerate()
+            .map(|(i, args)| {
+                Ok(match args {
+                    (Some(table), Some(privilege)) => {
+                        match (users, state.user()) {
+                            (Some(users), Some(session_user)) => {
+                                let user = users.value(i);
+                                if user != session_user {
+                                    return Err(DataFusionError::Execution(format!(
+                                        "role \"{}\" does not exist",
+                                        user
+                                    )));
+                                }
+                            }
+                            _ => (),
+                        }
+
+                        table?;
+
+                        let mut granted = false;
+                        for privilege in privilege.split(',') {
+                            let privilege = privilege.trim().to_ascii_uppercase();
+                            let privilege = privilege
+                                .strip_suffix(" WITH GRANT OPTION")
+                                .unwrap_or(&privilege);
+                            match privilege {
+                                "SELECT" => granted = true,
+                                "INSERT" | "UPDATE" | "DELETE" | "TRUNCATE" | "REFERENCES"
+                                | "TRIGGER" => (),
+                                _ => {
+                                    return Err(DataFusionError::Execution(format!(
+                                        "unrecognized privilege type: \"{}\"",
+                                        privilege
+                                    )))
+                                }
+                            }
+                        }
+
+                        Some(granted)
+                    }
+                    _ => None,
+                })
+            })
+            .collect::<Result<BooleanArray>>();
+
+        Ok(Arc::new(result?))
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Boolean)));
+
+    ScalarUDF::new(
+        "has_table_privilege",
+        &Signature::one_of(
+            vec![
+                TypeSignature::Exact(vec![DataType::Utf8, DataType::Utf8]),
+                TypeSignature::Exact(vec![DataType::UInt32, DataType::Utf8]),
+                TypeSignature::Exact(vec![DataType::Utf8, DataType::Utf8, DataType::Utf8]),
+                TypeSignature::Exact(vec![DataType::Utf8, DataType::UInt32, DataType::Utf8]),
+            ],
+            Volatility::Stable,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
+fn check_table_exists(meta: &MetaContext, table: &str) -> Result<()> {
+    let parts = table
+        .split('.')
+        .map(|part| part.trim_matches('"'))
+        .collect::<Vec<_>>();
+    let exists = match parts.as_slice() {
+        ["pg_catalog" | "information_schema", _] => true,
+        ["public", name] => meta
+            .cubes
+            .iter()
+            .any(|cube| cube.sql_schema().is_none() && cube.name.eq_ignore_ascii_case(name)),
+        [schema, name] => meta.find_cube_in_schema(schema, name).is_some(),
+        [name] => {
+            name.starts_with("pg_")
+                || meta
+                    .cubes
+                    .iter()
+                    .any(|cube| cube.name.eq_ignore_ascii_case(name))
+        }
+        _ => false,
+    };
+
+    if exists {
+        Ok(())
+    } else {
+        Err(DataFusionError::Execution(format!(
+            "relation \"{}\" does not exist",
+            table
+        )))
+    }
+}
+
 pub fn create_pg_total_relation_size_udf() -> ScalarUDF {
     let fun = make_scalar_function(move |args: &[ArrayRef]| {
         assert!(args.len() == 1);
@@ -3101,28 +3425,63 @@ pub fn create_sha1_udf() -> ScalarUDF {
     )
 }
 
-pub fn create_current_setting_udf() -> ScalarUDF {
+pub fn create_current_setting_udf(
+    state: Arc<SessionState>,
+    server_version: ServerVersion,
+) -> ScalarUDF {
     let fun = make_scalar_function(move |args: &[ArrayRef]| {
-        assert!(args.len() == 1);
+        assert!(args.len() == 1 || args.len() == 2);
 
         let setting_names = downcast_string_arg!(args[0], "str", i32);
+        let missing_ok = if args.len() == 2 {
+            Some(downcast_boolean_arr!(args[1], "missing_ok"))
+        } else {
+            None
+        };
 
         let result = setting_names
             .iter()
-            .map(|setting_name| {
-                if let Some(setting_name) = setting_name {
-                    Ok(Some(match setting_name.to_ascii_lowercase().as_str() {
-                        "max_index_keys" => "32".to_string(), // Taken from PostgreSQL
-                        "search_path" => "\"$user\", public".to_string(), // Taken from PostgreSQL
-                        "server_version_num" => "140002".to_string(), // Matches 14.2
-                        setting_name => Err(DataFusionError::Execution(format!(
+            .enumerate()
+            .map(|(i, setting_name)| {
+                let setting_name = match setting_name {
+                    Some(setting_name) => setting_name.to_ascii_lowercase(),
+                    None => return Ok(None),
+                };
+
+                if let Some(var) = state.get_variable(&setting_name) {
+                    return Ok(match var.value {
+                        ScalarValue::Utf8(v) => v,
+                        ScalarValue::Boolean(Some(true)) => Some("on".to_string()),
+                        ScalarValue::Boolean(Some(false)) => Some("off".to_string()),
+                        v if v.is_null() => None,
+                        v => Some(v.to_string()),
+                    });
+                }
+
+                if let Some(value) = server_version.postgres_parameter(&setting_name) {
+                    return Ok(Some(value.clone()));
+                }
+
+                Ok(Some(match setting_name.as_str() {
+                    "max_index_keys" => "32".to_string(), // Taken from PostgreSQL
+                    "search_path" => "\"$user\", public".to_string(), // Taken from PostgreSQL
+                    "server_version_num" => server_version.postgres_version_num(),
+                    "server_version" => server_version.postgres_version().to_string(),
+                    "server_encoding" | "client_encoding" => "UTF8".to_string(),
+                    "datestyle" => "ISO, MDY".to_string(),
+                    "integer_datetimes" => "on".to_string(),
+                    "is_superuser" => "off".to_string(),
+                    "intervalstyle" => "postgres".to_string(),
+                    setting_name => match &missing_ok {
+                        Some(missing_ok) if !missing_ok.is_null(i) && missing_ok.value(i) => {
+                            return Ok(None)
+                        }
+                        _ => Err(DataFusionError::Execution(format!(
                             "unrecognized configuration parameter \"{}\"",
                             setting_name
                         )))?,
-                    }))
-                } else {
-                    Ok(None)
-                }
+                    },
+                }))
             })
             .collect::<Result<StringArray>>()?;
 
@@ -3133,7 +3492,13 @@ pub fn create_current_setting_udf() -> ScalarUDF {
 
     ScalarUDF::new(
         "current_setting",
-        &Signature::exact(vec![DataType::Utf8], Volatility::Stable),
+        &Signature::one_of(
+            vec![
+                TypeSignature::Exact(vec![DataType::Utf8]),
+                TypeSignature::Exact(vec![DataType::Utf8, DataType::Boolean]),
+            ],
+            Volatility::Stable,
+        ),
         &return_type,
         &fun,
     )
@@ -3786,20 +4151,6 @@ pub fn register_fun_stubs(mut ctx: SessionContext) -> SessionContext {
         rettyp = Boolean,
         vol = Stable
     );
-    register_fun_stub!(
-        udf,
-        "has_table_privilege",
-        tsigs = [
-            [Utf8, Utf8],
-            [Oid, Utf8],
-            [Utf8, Utf8, Utf8],
-            [Utf8, Oid, Utf8],
-            [Oid, Utf8, Utf8],
-            [Oid, Oid, Utf8],
-        ],
-        rettyp = Boolean,
-        vol = Stable
-    );
     register_fun_stub!(
         udf,
         "has_tablespace_privilege",
@@ -3988,13 +4339,6 @@ pub fn register_fun_stubs(mut ctx: SessionContext) -> SessionContext {
         rettyp = Int32,
         vol = Stable
     );
-    register_fun_stub!(
-        udf,
-        "pg_client_encoding",
-        argc = 0,
-        rettyp = Utf8,
-        vol = Stable
-    );
     register_fun_stub!(
         udf,
         "pg_collation_actual_version",
@@ -4023,13 +4367,6 @@ pub fn register_fun_stubs(mut ctx: SessionContext) -> SessionContext {
         rettyp = Int32,
         vol = Stable
     );
-    register_fun_stub!(
-        udf,
-        "pg_conf_load_time",
-        argc = 0,
-        rettyp = TimestampTz,
-        vol = Stable
-    );
     register_fun_stub!(
         udf,
         "pg_conversion_is_visible",
@@ -4232,13 +4569,6 @@ pub fn register_fun_stubs(mut ctx: SessionContext) -> SessionContext {
         rettyp = Int64,
         vol = Stable
     );
-    register_fun_stub!(
-        udf,
-        "pg_is_in_recovery",
-        argc = 0,
-        rettyp = Boolean,
-        vol = Volatile
-    );
     register_fun_stub!(
         udf,
         "pg_is_wal_replay_paused",
@@ -4302,13 +4632,6 @@ pub fn register_fun_stubs(mut ctx: SessionContext) -> SessionContext {
         rettyp = Regclass,
         vol = Stable
     );
-    register_fun_stub!(
-        udf,
-        "pg_postmaster_start_time",
-        argc = 0,
-        rettyp = TimestampTz,
-        vol = Stable
-    );
     register_fun_stub!(
         udf,
         "pg_promote",
@@ -4679,6 +5002,8 @@ pub fn register_fun_stubs(mut ctx: SessionContext) -> SessionContext {
     register_fun_stub!(udaf, "bit_or", tsigs = [[Int16], [Int32], [Int64],]);
     register_fun_stub!(udaf, "bit_xor", tsigs = [[Int16], [Int32], [Int64],]);
     register_fun_stub!(udaf, "every", tsig = [Boolean], rettyp = Boolean);
+    register_fun_stub!(udaf, "first", argc = 1);
+    register_fun_stub!(udaf, "last", argc = 1);
     register_fun_stub!(udaf, "median", argc = 1);
     register_fun_stub!(
         udaf,
@@ -1,4 +1,4 @@
-use std::{backtrace::Backtrace, collections::HashMap};
+use std::{backtrace::Backtrace, collections::HashMap, fmt};
 
 #[derive(thiserror::Error, Debug)]
 pub enum CompilationError {
@@ -10,6 +10,103 @@ pub enum CompilationError {
     Unsupported(String, Option<HashMap<String, String>>),
     #[error("SQLCompilationError: Fatal: {0}")]
     Fatal(String, Option<HashMap<String, String>>),
+    #[error("SQLCompilationError: User: {0}")]
+    MemberNotFound(Box<MemberNotFound>, Option<HashMap<String, String>>),
+}
+
+/// Identifier which can't be resolved to a member of the cube
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberNotFound {
+    pub identifier: String,
+    /// Cube where the member was searched, None if it's not known which cube was meant
+    pub cube: Option<String>,
+    /// Closest member names, the best match goes first
+    pub suggestions: Vec<String>,
+}
+
+impl MemberNotFound {
+    pub const MAX_SUGGESTIONS: usize = 3;
+
+    /// Key of the error meta which is set for unresolved members
+    pub const META_KEY: &'static str = "unresolvedMember";
+
+    pub fn new(
+        identifier: String,
+        cube: Option<String>,
+        candidates: impl IntoIterator<Item = String>,
+    ) -> Self {
+        let needle = identifier.to_lowercase();
+        let mut candidates = candidates
+            .into_iter()
+            .map(|candidate| (edit_distance(&needle, &candidate.to_lowercase()), candidate))
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates.dedup_by(|a, b| a.1 == b.1);
+
+        // Too distant names only confuse
+        let max_distance = (needle.chars().count() / 2).max(2);
+        let suggestions = candidates
+            .into_iter()
+            .filter(|(distance, _)| *distance <= max_distance)
+            .take(Self::MAX_SUGGESTIONS)
+            .map(|(_, candidate)| candidate)
+            .collect();
+
+        Self {
+            identifier,
+            cube,
+            suggestions,
+        }
+    }
+
+    pub fn to_meta(&self) -> HashMap<String, String> {
+        let mut meta = HashMap::from([
+            (Self::META_KEY.to_string(), self.identifier.clone()),
+            ("suggestions".to_string(), self.suggestions.join(",")),
+        ]);
+        if let Some(cube) = &self.cube {
+            meta.insert("cube".to_string(), cube.clone());
+        }
+
+        meta
+    }
+}
+
+impl fmt::Display for MemberNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.cube {
+            Some(cube) => write!(
+                f,
+                "Member '{}' is not found in cube '{}'",
+                self.identifier, cube
+            )?,
+            None => write!(f, "Member '{}' is not found", self.identifier)?,
+        };
+
+        if !self.suggestions.is_empty() {
+            write!(f, ". Did you mean: {}?", self.suggestions.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Levenshtein distance
+fn edit_distance(left: &str, right: &str) -> usize {
+    let right = right.chars().collect::<Vec<_>>();
+    let mut prev = (0..=right.len()).collect::<Vec<_>>();
+    let mut current = vec![0; right.len() + 1];
+
+    for (i, l) in left.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, r) in right.iter().enumerate() {
+            let cost = if l == *r { 0 } else { 1 };
+            current[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut current);
+    }
+
+    prev[right.len()]
 }
 
 impl PartialEq for CompilationError {
@@ -31,6 +128,10 @@ impl PartialEq for CompilationError {
                 CompilationError::Fatal(right, _) => left == right,
                 _ => false,
             },
+            CompilationError::MemberNotFound(left, _) => match other {
+                CompilationError::MemberNotFound(right, _) => left == right,
+                _ => false,
+            },
         }
     }
 
@@ -46,6 +147,7 @@ impl CompilationError {
             CompilationError::User(_, _) => None,
             CompilationError::Unsupported(_, _) => None,
             CompilationError::Fatal(_, _) => None,
+            CompilationError::MemberNotFound(_, _) => None,
         }
     }
 
@@ -55,6 +157,7 @@ impl CompilationError {
             CompilationError::User(_, _) => None,
             CompilationError::Unsupported(_, _) => None,
             CompilationError::Fatal(_, _) => None,
+            CompilationError::MemberNotFound(_, _) => None,
         }
     }
 }
@@ -79,6 +182,12 @@ impl CompilationError {
     pub fn fatal(message: String) -> Self {
         Self::Fatal(message, None)
     }
+
+    pub fn member_not_found(err: MemberNotFound) -> Self {
+        let meta = Some(err.to_meta());
+
+        Self::MemberNotFound(Box::new(err), meta)
+    }
 }
 
 impl CompilationError {
@@ -88,6 +197,7 @@ impl CompilationError {
             | CompilationError::User(msg, _)
             | CompilationError::Unsupported(msg, _) => msg.clone(),
             CompilationError::Fatal(msg, _) => msg.clone(),
+            CompilationError::MemberNotFound(err, _) => err.to_string(),
         }
     }
 
@@ -97,6 +207,7 @@ impl CompilationError {
             CompilationError::User(_, meta) => CompilationError::User(msg, meta),
             CompilationError::Unsupported(_, meta) => CompilationError::Unsupported(msg, meta),
             CompilationError::Fatal(_, meta) => CompilationError::Fatal(msg, meta),
+            CompilationError::MemberNotFound(_, meta) => CompilationError::User(msg, meta),
         }
     }
 }
@@ -108,6 +219,14 @@ impl CompilationError {
             CompilationError::User(msg, _) => CompilationError::User(msg, meta),
             CompilationError::Unsupported(msg, _) => CompilationError::Unsupported(msg, meta),
             CompilationError::Fatal(msg, _) => CompilationError::Fatal(msg, meta),
+            CompilationError::MemberNotFound(err, _) => {
+                // Details of the member are kept along with the new meta
+                let meta = meta.map(|mut meta| {
+                    meta.extend(err.to_meta());
+                    meta
+                });
+                CompilationError::MemberNotFound(err, meta)
+            }
         }
     }
 }
@@ -125,3 +244,39 @@ impl From<serde_json::Error> for CompilationError {
         CompilationError::internal(format!("{:?}", v))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_member_not_found_suggestions() {
+        let candidates = vec![
+            "count",
+            "customer_gender",
+            "order_date",
+            "last_mod",
+            "maxPrice",
+            "minPrice",
+        ]
+        .into_iter()
+        .map(|c| c.to_string());
+
+        let err = MemberNotFound::new(
+            "maxprise".to_string(),
+            Some("KibanaSampleDataEcommerce".to_string()),
+            candidates.clone(),
+        );
+        assert_eq!(err.suggestions, vec!["maxPrice", "minPrice"]);
+        assert_eq!(
+            err.to_string(),
+            "Member 'maxprise' is not found in cube 'KibanaSampleDataEcommerce'. Did you mean: maxPrice, minPrice?"
+        );
+
+        let err = MemberNotFound::new("Order_Data".to_string(), None, candidates);
+        assert_eq!(err.suggestions, vec!["order_date"]);
+
+        let err = MemberNotFound::new("x".to_string(), None, vec![]);
+        assert_eq!(err.to_string(), "Member 'x' is not found");
+    }
+}
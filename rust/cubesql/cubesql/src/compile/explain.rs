@@ -0,0 +1,125 @@
+use datafusion::logical_plan::{plan::Limit, LogicalPlan};
+use serde_json::{json, Value};
+
+use crate::compile::engine::df::{scan::CubeScanNode, wrapper::CubeScanWrapperNode};
+
+/// Plan of `EXPLAIN (FORMAT JSON)`: the tree of logical plan nodes with CubeScan requests and
+/// the decisions of the push down. Rows are estimated by limits, it's an upper bound.
+pub fn plan_to_json(plan: &LogicalPlan) -> Value {
+    let mut cube_scans = vec![];
+    let plan_json = node_to_json(plan, &mut cube_scans);
+
+    json!({
+        "plan": plan_json,
+        "cube_scans": cube_scans,
+        "estimated_rows": estimated_rows(plan),
+    })
+}
+
+fn node_to_json(plan: &LogicalPlan, cube_scans: &mut Vec<Value>) -> Value {
+    let schema = plan
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| {
+            json!({
+                "name": field.qualified_name(),
+                "type": field.data_type().to_string(),
+                "nullable": field.is_nullable(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if let LogicalPlan::Extension(ext) = plan {
+        if let Some(scan) = ext.node.as_any().downcast_ref::<CubeScanNode>() {
+            let request = &scan.request;
+            cube_scans.push(json!({
+                "request": request,
+                "used_cubes": scan.used_cubes,
+                "change_user": scan.options.change_user,
+                "pushdown": {
+                    "wrapped_sql": false,
+                    "ungrouped": request.ungrouped.unwrap_or(false),
+                    "filters": request.filters.as_ref().map(|f| f.len()).unwrap_or(0),
+                    "order": request.order.as_ref().map(|o| !o.is_empty()).unwrap_or(false),
+                    "limit": request.limit,
+                    "offset": request.offset,
+                },
+            }));
+
+            return json!({
+                "node": "CubeScan",
+                "schema": schema,
+                "estimated_rows": estimated_rows(plan),
+                "cube_scan": cube_scans.len() - 1,
+            });
+        }
+
+        if let Some(wrapper) = ext.node.as_any().downcast_ref::<CubeScanWrapperNode>() {
+            let wrapped_plan = node_to_json(&wrapper.wrapped_plan, cube_scans);
+            if let Some(sql) = &wrapper.wrapped_sql {
+                cube_scans.push(json!({
+                    "request": wrapper.request,
+                    "sql": sql.sql,
+                    "values": sql.values,
+                    "pushdown": {
+                        "wrapped_sql": true,
+                    },
+                }));
+            }
+
+            return json!({
+                "node": "CubeScanWrapper",
+                "schema": schema,
+                "wrapped_plan": wrapped_plan,
+                "cube_scan": wrapper.wrapped_sql.as_ref().map(|_| cube_scans.len() - 1),
+            });
+        }
+    }
+
+    let description = plan.display().to_string();
+    let node = description
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let children = plan
+        .inputs()
+        .into_iter()
+        .map(|input| node_to_json(input, cube_scans))
+        .collect::<Vec<_>>();
+
+    json!({
+        "node": node,
+        "description": description,
+        "schema": schema,
+        "estimated_rows": estimated_rows(plan),
+        "children": children,
+    })
+}
+
+fn estimated_rows(plan: &LogicalPlan) -> Option<usize> {
+    match plan {
+        LogicalPlan::Extension(ext) => ext
+            .node
+            .as_any()
+            .downcast_ref::<CubeScanNode>()
+            .and_then(|scan| scan.request.limit)
+            .map(|limit| limit.max(0) as usize),
+        LogicalPlan::Limit(Limit { fetch, input, .. }) => match (fetch, estimated_rows(input)) {
+            (Some(fetch), Some(rows)) => Some((*fetch).min(rows)),
+            (Some(fetch), None) => Some(*fetch),
+            (None, rows) => rows,
+        },
+        LogicalPlan::Projection(_)
+        | LogicalPlan::Filter(_)
+        | LogicalPlan::Sort(_)
+        | LogicalPlan::Aggregate(_)
+        | LogicalPlan::Window(_)
+        | LogicalPlan::Subquery(_) => match plan.inputs().as_slice() {
+            [input] => estimated_rows(input),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -1,10 +1,12 @@
+use async_trait::async_trait;
 use core::fmt;
-use cubeclient::models::V1LoadRequestQuery;
+use cubeclient::models::{V1CubeMeta, V1LoadRequestQuery};
 use datafusion::{
     arrow::datatypes::DataType,
+    error::DataFusionError,
     execution::context::{
-        default_session_builder, SessionConfig as DFSessionConfig,
-        SessionContext as DFSessionContext,
+        SessionConfig as DFSessionConfig, SessionContext as DFSessionContext,
+        SessionState as DFSessionState,
     },
     logical_plan::{
         plan::{Analyze, Explain, Extension, Projection, ToStringifiedPlan},
@@ -18,11 +20,12 @@ use datafusion::{
     physical_plan::ExecutionPlan,
     prelude::*,
     scalar::ScalarValue,
-    sql::{parser::Statement as DFStatement, planner::SqlToRel},
+    sql::planner::SqlToRel,
     variable::VarType,
 };
 use itertools::Itertools;
 use log::warn;
+use regex::Regex;
 use serde::Serialize;
 use sqlparser::ast::{self, escape_single_quote_string};
 use std::{
@@ -36,43 +39,52 @@ use self::{
     engine::{
         context::VariablesProvider,
         df::{
-            optimizers::{FilterPushDown, LimitPushDown, SortPushDown},
+            collation::Collation,
+            optimizers::{CubeScanMemberPruning, FilterPushDown, LimitPushDown, SortPushDown},
             planner::CubeQueryPlanner,
-            scan::{CubeScanNode, MemberField},
+            scalar_subquery::{ScalarSubquery, ScalarSubqueryNode, ScalarSubqueryPlanner},
+            scan::{CubeScanNode, MemberField, QueryLimitSettings},
+            set_operations::plan_statement,
+            wrapper::WrappedSqlLimits,
         },
-        information_schema::mysql::ext::CubeColumnMySqlExt,
+        information_schema::mysql::{character_sets::find_character_set, ext::CubeColumnMySqlExt},
         provider::CubeContext,
         udf::{
             create_array_lower_udf, create_array_to_string_udf, create_array_upper_udf,
             create_charindex_udf, create_connection_id_udf, create_convert_tz_udf,
-            create_cube_regclass_cast_udf, create_current_schema_udf, create_current_schemas_udf,
+            create_cube_regclass_cast_udf, create_cube_security_context_udf,
+            create_cube_user_switched_udf, create_current_schema_udf, create_current_schemas_udf,
             create_current_setting_udf, create_current_timestamp_udf, create_current_user_udf,
             create_date_add_udf, create_date_sub_udf, create_date_to_timestamp_udf,
             create_date_udf, create_dateadd_udf, create_datediff_udf, create_dayofmonth_udf,
             create_dayofweek_udf, create_dayofyear_udf, create_db_udf, create_ends_with_udf,
             create_format_type_udf, create_generate_series_udtf, create_generate_subscripts_udtf,
-            create_has_schema_privilege_udf, create_hour_udf, create_if_udf,
-            create_inet_server_addr_udf, create_instr_udf, create_interval_mul_udf,
+            create_has_schema_privilege_udf, create_has_table_privilege_udf, create_hour_udf,
+            create_if_udf, create_inet_server_addr_udf, create_instr_udf, create_interval_mul_udf,
             create_isnull_udf, create_json_build_object_udf, create_least_udf, create_locate_udf,
             create_makedate_udf, create_measure_udaf, create_minute_udf, create_pg_backend_pid_udf,
-            create_pg_datetime_precision_udf, create_pg_encoding_to_char_udf,
-            create_pg_expandarray_udtf, create_pg_get_constraintdef_udf, create_pg_get_expr_udf,
-            create_pg_get_indexdef_udf, create_pg_get_serial_sequence_udf,
-            create_pg_get_userbyid_udf, create_pg_is_other_temp_schema, create_pg_my_temp_schema,
-            create_pg_numeric_precision_udf, create_pg_numeric_scale_udf,
+            create_pg_client_encoding_udf, create_pg_datetime_precision_udf,
+            create_pg_encoding_to_char_udf, create_pg_expandarray_udtf,
+            create_pg_get_constraintdef_udf, create_pg_get_expr_udf, create_pg_get_indexdef_udf,
+            create_pg_get_serial_sequence_udf, create_pg_get_userbyid_udf,
+            create_pg_is_in_recovery_udf, create_pg_is_other_temp_schema, create_pg_my_temp_schema,
+            create_pg_numeric_precision_udf, create_pg_numeric_scale_udf, create_pg_sleep_udf,
             create_pg_table_is_visible_udf, create_pg_total_relation_size_udf,
             create_pg_truetypid_udf, create_pg_truetypmod_udf, create_pg_type_is_visible_udf,
             create_position_udf, create_quarter_udf, create_quote_ident_udf,
-            create_regexp_substr_udf, create_second_udf, create_session_user_udf, create_sha1_udf,
-            create_str_to_date_udf, create_time_format_udf, create_timediff_udf,
-            create_to_char_udf, create_to_date_udf, create_to_regtype_udf, create_ucase_udf,
-            create_unnest_udtf, create_user_udf, create_version_udf, create_year_udf,
-            register_fun_stubs,
+            create_regexp_substr_udf, create_second_udf, create_server_start_time_udf,
+            create_session_user_udf, create_sha1_udf, create_sleep_udf, create_str_to_date_udf,
+            create_time_format_udf, create_timediff_udf, create_to_char_udf, create_to_date_udf,
+            create_to_regtype_udf, create_ucase_udf, create_unnest_udtf, create_user_udf,
+            create_version_udf, create_year_udf, register_fun_stubs,
         },
     },
-    parser::parse_sql_to_statement,
+    parser::{
+        explain_json_query, explain_source_query, parse_pushdown_hint, parse_sql_to_statement,
+        PushdownHint,
+    },
     qtrace::Qtrace,
-    rewrite::converter::LogicalPlanToLanguageConverter,
+    rewrite::{converter::LogicalPlanToLanguageConverter, rewriter::Rewriter},
 };
 use crate::{
     compile::engine::df::scan::CubeScanOptions,
@@ -81,20 +93,26 @@ use crate::{
         dataframe,
         session::DatabaseProtocol,
         statement::{
-            ApproximateCountDistinctVisitor, CastReplacer, RedshiftDatePartReplacer,
-            SensitiveDataSanitizer, ToTimestampReplacer, UdfWildcardArgReplacer,
+            ApproximateCountDistinctVisitor, BucketReplacer, CastReplacer,
+            CompareDateRangeReplacer, FetchFirstReplacer, GranularityReplacer,
+            ProjectionAliasReplacer, RedshiftDatePartReplacer, ScalarSubqueryReplacer,
+            SensitiveDataSanitizer, TimezoneReplacer, ToTimestampReplacer, UdfWildcardArgReplacer,
+            ViewReplacer,
         },
         types::{CommandCompletion, StatusFlags},
-        ColumnFlags, ColumnType, Session, SessionManager, SessionState,
+        ColumnFlags, ColumnType, DatabaseMapping, HttpAuthContext, PlanningFailures, Session,
+        SessionManager, SessionState, StatementClass,
     },
     transport::{df_data_type_by_column_type, V1CubeMetaExt},
     CubeError, CubeErrorCauseType,
 };
 
 pub mod builder;
+pub mod check;
 pub mod context;
 pub mod engine;
 pub mod error;
+pub mod explain;
 mod legacy_compiler;
 pub mod parser;
 pub mod qtrace;
@@ -106,9 +124,14 @@ pub mod test;
 pub use crate::transport::ctx::*;
 use crate::{
     compile::engine::df::wrapper::CubeScanWrapperNode,
-    transport::{LoadRequestMeta, SpanId, TransportService},
+    transport::{LoadRequestMeta, SpanId, SqlView, TransportService},
 };
-pub use error::{CompilationError, CompilationResult};
+pub use error::{CompilationError, CompilationResult, MemberNotFound};
+
+lazy_static! {
+    static ref FIELD_NOT_FOUND_REGEX: Regex =
+        Regex::new(r"No field named '(?P<field>[^']+)'").unwrap();
+}
 
 #[derive(Clone)]
 struct QueryPlanner {
@@ -430,29 +453,22 @@ impl QueryPlanner {
         qtrace: &mut Option<Qtrace>,
         span_id: Option<Arc<SpanId>>,
     ) -> CompilationResult<QueryPlan> {
+        let stmt = &self.expand_granularities(&self.expand_views(stmt).await?);
         let plan = match (stmt, &self.state.protocol) {
             (ast::Statement::Query(q), _) => {
-                self.select_to_plan(stmt, q, qtrace, span_id.clone()).await
+                self.query_to_plan(stmt, q, qtrace, span_id.clone()).await
             }
             (ast::Statement::SetTransaction { .. }, _) => Ok(QueryPlan::MetaTabular(
                 StatusFlags::empty(),
                 Box::new(dataframe::DataFrame::new(vec![], vec![])),
             )),
-            (ast::Statement::SetNames { charset_name, .. }, DatabaseProtocol::MySQL) => {
-                if !(charset_name.eq_ignore_ascii_case("utf8")
-                    || charset_name.eq_ignore_ascii_case("utf8mb4"))
-                {
-                    warn!(
-                        "SET NAME does not support non utf8 charsets, input: {}",
-                        charset_name
-                    );
-                };
-
-                Ok(QueryPlan::MetaTabular(
-                    StatusFlags::empty(),
-                    Box::new(dataframe::DataFrame::new(vec![], vec![])),
-                ))
-            }
+            (
+                ast::Statement::SetNames {
+                    charset_name,
+                    collation_name,
+                },
+                DatabaseProtocol::MySQL,
+            ) => self.set_names_to_plan(charset_name, collation_name),
             (ast::Statement::Kill { .. }, DatabaseProtocol::MySQL) => Ok(QueryPlan::MetaOk(
                 StatusFlags::empty(),
                 CommandCompletion::Select(0),
@@ -501,6 +517,17 @@ impl QueryPlanner {
                 self.explain_table_to_plan(&table_name, span_id.clone())
                     .await
             }
+            (ast::Statement::Explain { statement, .. }, _)
+                if explain_source_query(statement).is_some() =>
+            {
+                self.explain_source_to_plan(&statement, span_id.clone())
+                    .await
+            }
+            (ast::Statement::Explain { statement, .. }, _)
+                if explain_json_query(statement).is_some() =>
+            {
+                self.explain_json_to_plan(&statement, span_id.clone()).await
+            }
             (
                 ast::Statement::Explain {
                     statement,
@@ -534,14 +561,48 @@ impl QueryPlanner {
                     CommandCompletion::Rollback,
                 ))
             }
+            (
+                ast::Statement::CreateView {
+                    or_replace,
+                    materialized,
+                    name,
+                    columns,
+                    query,
+                    ..
+                },
+                _,
+            ) => {
+                self.create_view_to_plan(name, columns, query, *or_replace, *materialized)
+                    .await
+            }
+            (
+                ast::Statement::Drop {
+                    object_type: ast::ObjectType::View,
+                    if_exists,
+                    names,
+                    ..
+                },
+                _,
+            ) => self.drop_view_to_plan(names, *if_exists).await,
             (ast::Statement::Discard { object_type }, DatabaseProtocol::PostgreSQL) => {
-                // TODO: Cursors + Portals
-                self.state.clear_prepared_statements().await;
+                // Cursors and portals are owned by the connection, see the Postgres shim
+                match object_type {
+                    ast::DiscardObject::ALL if self.state.is_in_transaction() => {
+                        Err(CompilationError::user(
+                            "DISCARD ALL cannot run inside a transaction block".to_string(),
+                        ))
+                    }
+                    _ => {
+                        if let ast::DiscardObject::ALL = object_type {
+                            self.state.discard_all().await;
+                        }
 
-                Ok(QueryPlan::MetaOk(
-                    StatusFlags::empty(),
-                    CommandCompletion::Discard(object_type.to_string()),
-                ))
+                        Ok(QueryPlan::MetaOk(
+                            StatusFlags::empty(),
+                            CommandCompletion::Discard(object_type.to_string()),
+                        ))
+                    }
+                }
             }
             _ => Err(CompilationError::unsupported(format!(
                 "Unsupported query type: {}",
@@ -571,6 +632,29 @@ impl QueryPlanner {
         span_id: Option<Arc<SpanId>>,
     ) -> CompilationResult<QueryPlan> {
         let name = variable.to_vec()[0].value.clone();
+        if name.eq_ignore_ascii_case("grants") {
+            return self.show_grants_to_plan(variable);
+        }
+        if name.eq_ignore_ascii_case("cubesql_features") {
+            return Ok(self.show_features_to_plan());
+        }
+        if variable.len() == 3
+            && variable
+                .iter()
+                .zip(["last", "query", "trace"])
+                .all(|(ident, word)| ident.value.eq_ignore_ascii_case(word))
+        {
+            return Ok(self.show_last_query_trace_to_plan());
+        }
+        if variable.len() == 3
+            && variable
+                .iter()
+                .zip(["last", "cube", "request"])
+                .all(|(ident, word)| ident.value.eq_ignore_ascii_case(word))
+        {
+            return Ok(self.show_last_cube_request_to_plan());
+        }
+
         if self.state.protocol == DatabaseProtocol::PostgreSQL {
             let full_variable = variable.iter().map(|v| v.value.to_lowercase()).join("_");
             let full_variable = match full_variable.as_str() {
@@ -607,21 +691,31 @@ impl QueryPlanner {
                         ColumnType::String,
                         ColumnFlags::empty(),
                     )],
-                    vec![
-                        dataframe::Row::new(vec![dataframe::TableValue::String("db".to_string())]),
-                        dataframe::Row::new(vec![dataframe::TableValue::String(
-                            "information_schema".to_string(),
-                        )]),
-                        dataframe::Row::new(vec![dataframe::TableValue::String(
-                            "mysql".to_string(),
-                        )]),
-                        dataframe::Row::new(vec![dataframe::TableValue::String(
-                            "performance_schema".to_string(),
-                        )]),
-                        dataframe::Row::new(vec![dataframe::TableValue::String("sys".to_string())]),
-                    ],
+                    self.session_manager
+                        .server
+                        .config_obj
+                        .databases()
+                        .names()
+                        .into_iter()
+                        .map(|name| dataframe::Row::new(vec![dataframe::TableValue::String(name)]))
+                        .collect(),
                 )),
             ))
+        } else if name.eq_ignore_ascii_case("charset")
+            || (name.eq_ignore_ascii_case("character")
+                && variable
+                    .get(1)
+                    .map(|v| v.value.eq_ignore_ascii_case("set"))
+                    .unwrap_or(false))
+        {
+            let stmt = parse_sql_to_statement(
+                &"SELECT `CHARACTER_SET_NAME` AS `Charset`, `DESCRIPTION` AS `Description`, `DEFAULT_COLLATE_NAME` AS `Default collation`, `MAXLEN` AS `Maxlen` FROM `information_schema`.`CHARACTER_SETS` ORDER BY `Charset`".to_string(),
+                self.state.protocol.clone(),
+                &mut None,
+            )?;
+
+            self.create_df_logical_plan(stmt, &mut None, span_id.clone())
+                .await
         } else if name.eq_ignore_ascii_case("processlist") {
             let stmt = parse_sql_to_statement(
                 &"SELECT * FROM information_schema.processlist".to_string(),
@@ -652,7 +746,18 @@ impl QueryPlanner {
                             ColumnFlags::NOT_NULL,
                         ),
                     ],
-                    vec![],
+                    self.state
+                        .warnings()
+                        .into_iter()
+                        .map(|message| {
+                            dataframe::Row::new(vec![
+                                dataframe::TableValue::String("Warning".to_string()),
+                                // ER_UNKNOWN_ERROR, there are no specific codes for these warnings
+                                dataframe::TableValue::Int32(1105),
+                                dataframe::TableValue::String(message),
+                            ])
+                        })
+                        .collect(),
                 )),
             ))
         } else {
@@ -667,6 +772,202 @@ impl QueryPlanner {
         }
     }
 
+    /// Grants are derived from the meta of the user, which is already filtered by its security
+    /// context, so only visible members of cubes are listed
+    fn show_grants_to_plan(&self, variable: &Vec<ast::Ident>) -> CompilationResult<QueryPlan> {
+        let user = self.state.user().ok_or_else(|| {
+            CompilationError::user("SHOW GRANTS requires an authenticated user".to_string())
+        })?;
+        // SHOW GRANTS [FOR user], quoted user names are skipped by the parser
+        if let [_, for_ident, grantee, ..] = variable.as_slice() {
+            if for_ident.value.eq_ignore_ascii_case("for")
+                && !grantee.value.eq_ignore_ascii_case("current_user")
+                && grantee.value != user
+            {
+                return Err(CompilationError::user(format!(
+                    "SHOW GRANTS is supported only for the current user: {}",
+                    grantee.value
+                )));
+            }
+        }
+
+        let columns = |cube: &V1CubeMeta, quote: &dyn Fn(&str) -> String| {
+            cube.get_columns()
+                .iter()
+                .map(|column| quote(&column.get_name()))
+                .join(", ")
+        };
+        // Statements in the dialect of the protocol, as the database would print them
+        let (header, grants) = match self.state.protocol {
+            DatabaseProtocol::MySQL => {
+                let quote = |name: &str| format!("`{}`", name.replace('`', "``"));
+                let grantee = format!("{}@{}", quote(&user), quote("%"));
+                let database = self.state.database().unwrap_or("db".to_string());
+
+                let mut grants = vec![format!("GRANT USAGE ON *.* TO {}", grantee)];
+                for cube in self.meta.cubes.iter() {
+                    grants.push(format!(
+                        "GRANT SELECT ({}) ON {}.{} TO {}",
+                        columns(cube, &quote),
+                        quote(&cube.sql_schema().unwrap_or_else(|| database.clone())),
+                        quote(&cube.name),
+                        grantee
+                    ));
+                }
+
+                (format!("Grants for {}@%", user), grants)
+            }
+            DatabaseProtocol::PostgreSQL => {
+                let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+                let grantee = quote(&user);
+                let schema =
+                    |cube: &V1CubeMeta| cube.sql_schema().unwrap_or_else(|| "public".to_string());
+
+                let mut grants = self
+                    .meta
+                    .cubes
+                    .iter()
+                    .map(schema)
+                    .unique()
+                    .sorted()
+                    .map(|schema| {
+                        format!("GRANT USAGE ON SCHEMA {} TO {}", quote(&schema), grantee)
+                    })
+                    .collect::<Vec<_>>();
+                for cube in self.meta.cubes.iter() {
+                    grants.push(format!(
+                        "GRANT SELECT ({}) ON {}.{} TO {}",
+                        columns(cube, &quote),
+                        quote(&schema(cube)),
+                        quote(&cube.name),
+                        grantee
+                    ));
+                }
+
+                (format!("Grants for {}", user), grants)
+            }
+        };
+
+        Ok(QueryPlan::MetaTabular(
+            StatusFlags::empty(),
+            Box::new(dataframe::DataFrame::new(
+                vec![dataframe::Column::new(
+                    header,
+                    ColumnType::String,
+                    ColumnFlags::empty(),
+                )],
+                grants
+                    .into_iter()
+                    .map(|grant| dataframe::Row::new(vec![dataframe::TableValue::String(grant)]))
+                    .collect(),
+            )),
+        ))
+    }
+
+    /// Trace of the last query which was run after `SET cubesql_trace_next_query = on`
+    fn show_last_query_trace_to_plan(&self) -> QueryPlan {
+        QueryPlan::MetaTabular(
+            StatusFlags::empty(),
+            Box::new(dataframe::DataFrame::new(
+                vec![dataframe::Column::new(
+                    "trace".to_string(),
+                    ColumnType::String,
+                    ColumnFlags::empty(),
+                )],
+                self.state
+                    .last_query_trace()
+                    .into_iter()
+                    .map(|trace| dataframe::Row::new(vec![dataframe::TableValue::String(trace)]))
+                    .collect(),
+            )),
+        )
+    }
+
+    /// Id of the last request to Cube, it's sent as `x-request-id` and logged by Cube
+    fn show_last_cube_request_to_plan(&self) -> QueryPlan {
+        let columns = vec![
+            ("request_id", ColumnType::String),
+            ("method", ColumnType::String),
+            ("duration_ms", ColumnType::Int64),
+            ("error", ColumnType::String),
+        ];
+
+        QueryPlan::MetaTabular(
+            StatusFlags::empty(),
+            Box::new(dataframe::DataFrame::new(
+                columns
+                    .into_iter()
+                    .map(|(name, column_type)| {
+                        dataframe::Column::new(name.to_string(), column_type, ColumnFlags::empty())
+                    })
+                    .collect(),
+                self.state
+                    .last_cube_request()
+                    .into_iter()
+                    .map(|request| {
+                        dataframe::Row::new(vec![
+                            dataframe::TableValue::String(request.request_id),
+                            dataframe::TableValue::String(request.method),
+                            dataframe::TableValue::Int64(request.duration_ms as i64),
+                            match request.error {
+                                Some(error) => dataframe::TableValue::String(error),
+                                None => dataframe::TableValue::Null,
+                            },
+                        ])
+                    })
+                    .collect(),
+            )),
+        )
+    }
+
+    fn show_features_to_plan(&self) -> QueryPlan {
+        let features = self
+            .session_manager
+            .server
+            .config_obj
+            .features()
+            .session_features(&self.state);
+
+        QueryPlan::MetaTabular(
+            StatusFlags::empty(),
+            Box::new(dataframe::DataFrame::new(
+                vec![
+                    dataframe::Column::new(
+                        "name".to_string(),
+                        ColumnType::String,
+                        ColumnFlags::empty(),
+                    ),
+                    dataframe::Column::new(
+                        "enabled".to_string(),
+                        ColumnType::Boolean,
+                        ColumnFlags::empty(),
+                    ),
+                    dataframe::Column::new(
+                        "rollout".to_string(),
+                        ColumnType::String,
+                        ColumnFlags::empty(),
+                    ),
+                    dataframe::Column::new(
+                        "description".to_string(),
+                        ColumnType::String,
+                        ColumnFlags::empty(),
+                    ),
+                ],
+                features
+                    .into_iter()
+                    .map(|feature| {
+                        dataframe::Row::new(vec![
+                            dataframe::TableValue::String(feature.name.to_string()),
+                            dataframe::TableValue::Boolean(feature.enabled),
+                            dataframe::TableValue::String(feature.rollout),
+                            dataframe::TableValue::String(feature.description.to_string()),
+                        ])
+                    })
+                    .collect(),
+            )),
+        )
+    }
+
     async fn show_variables_to_plan(
         &self,
         filter: &Option<ast::ShowStatementFilter>,
@@ -947,62 +1248,672 @@ WHERE `TABLE_SCHEMA` = '{}'",
     ) -> Pin<Box<dyn Future<Output = Result<QueryPlan, CompilationError>> + Send>> {
         let self_cloned = self.clone();
 
-        let statement = statement.clone();
-        // This Boxing construct here because of recursive call to self.plan()
-        Box::pin(async move {
-            // TODO span_id ?
-            let plan = self_cloned.plan(&statement, &mut None, None).await?;
+        let statement = statement.clone();
+        // This Boxing construct here because of recursive call to self.plan()
+        Box::pin(async move {
+            // TODO span_id ?
+            let plan = self_cloned.plan(&statement, &mut None, None).await?;
+
+            match plan {
+                QueryPlan::MetaOk(_, _) | QueryPlan::MetaTabular(_, _) => Ok(QueryPlan::MetaTabular(
+                    StatusFlags::empty(),
+                    Box::new(dataframe::DataFrame::new(
+                        vec![dataframe::Column::new(
+                            "Execution Plan".to_string(),
+                            ColumnType::String,
+                            ColumnFlags::empty(),
+                        )],
+                        vec![dataframe::Row::new(vec![dataframe::TableValue::String(
+                            "This query doesnt have a plan, because it already has values for response"
+                                .to_string(),
+                        )])],
+                    )),
+                )),
+                QueryPlan::DataFusionSelect(flags, plan, context) => {
+                    let plan = Arc::new(plan);
+                    let schema = LogicalPlan::explain_schema();
+                    let schema = schema.to_dfschema_ref().map_err(|err| {
+                        CompilationError::internal(format!(
+                            "Unable to get DF schema for explain plan: {}",
+                            err
+                        ))
+                    })?;
+
+                    let explain_plan = if analyze {
+                        LogicalPlan::Analyze(Analyze {
+                            verbose,
+                            input: plan,
+                            schema,
+                        })
+                    } else {
+                        let stringified_plans = vec![plan.to_stringified(PlanType::InitialLogicalPlan)];
+
+                        LogicalPlan::Explain(Explain {
+                            verbose,
+                            plan,
+                            stringified_plans,
+                            schema,
+                        })
+                    };
+
+                    Ok(QueryPlan::DataFusionSelect(flags, explain_plan, context))
+                }
+            }
+        })
+    }
+
+    fn explain_source_to_plan(
+        &self,
+        statement: &Box<ast::Statement>,
+        span_id: Option<Arc<SpanId>>,
+    ) -> Pin<Box<dyn Future<Output = Result<QueryPlan, CompilationError>> + Send>> {
+        let self_cloned = self.clone();
+
+        let query = explain_source_query(statement).cloned();
+        // This Boxing construct here because of recursive call to self.plan()
+        Box::pin(async move {
+            let query = query.ok_or_else(|| {
+                CompilationError::internal("EXPLAIN (SOURCE) query is not found".to_string())
+            })?;
+            let plan = self_cloned
+                .plan(
+                    &ast::Statement::Query(Box::new(query)),
+                    &mut None,
+                    span_id.clone(),
+                )
+                .await?;
+
+            let wrappers = match &plan {
+                QueryPlan::DataFusionSelect(_, plan, _) => find_cube_scan_wrappers(plan),
+                QueryPlan::MetaOk(_, _) | QueryPlan::MetaTabular(_, _) => vec![],
+            };
+            if wrappers.is_empty() {
+                return Err(CompilationError::user(
+                    "EXPLAIN (SOURCE) is supported only for queries pushed down to the data source"
+                        .to_string(),
+                ));
+            }
+
+            let transport = self_cloned.session_manager.server.transport.clone();
+            let mut rows = vec![];
+            for wrapper in wrappers {
+                let scan_node = find_cube_scans_deep_search(wrapper.wrapped_plan.clone(), true)
+                    .into_iter()
+                    .next()
+                    .unwrap();
+                let request = wrapper.request.clone().unwrap_or(scan_node.request.clone());
+                let sql_query = wrapper
+                    .explain_sql()
+                    .map_err(|e| CompilationError::internal(e.message))?;
+                let mut meta_fields = self_cloned.load_request_meta();
+                meta_fields.set_change_user(scan_node.options.change_user.clone());
+
+                let plan_rows = transport
+                    .explain_source(
+                        span_id.clone(),
+                        request,
+                        sql_query,
+                        wrapper.auth_context.clone(),
+                        meta_fields,
+                    )
+                    .await
+                    .map_err(|e| match e.cause {
+                        CubeErrorCauseType::User(_) => CompilationError::user(e.message),
+                        CubeErrorCauseType::Internal(_) => CompilationError::internal(e.message),
+                    })?;
+
+                rows.extend(plan_rows.iter().map(|row| {
+                    dataframe::Row::new(vec![dataframe::TableValue::String(
+                        explain_source_row_to_string(row),
+                    )])
+                }));
+            }
+
+            Ok(QueryPlan::MetaTabular(
+                StatusFlags::empty(),
+                Box::new(dataframe::DataFrame::new(
+                    vec![dataframe::Column::new(
+                        "QUERY PLAN".to_string(),
+                        ColumnType::String,
+                        ColumnFlags::empty(),
+                    )],
+                    rows,
+                )),
+            ))
+        })
+    }
+
+    fn explain_json_to_plan(
+        &self,
+        statement: &Box<ast::Statement>,
+        span_id: Option<Arc<SpanId>>,
+    ) -> Pin<Box<dyn Future<Output = Result<QueryPlan, CompilationError>> + Send>> {
+        let self_cloned = self.clone();
+
+        let query = explain_json_query(statement).cloned();
+        // This Boxing construct here because of recursive call to self.plan()
+        Box::pin(async move {
+            let query = query.ok_or_else(|| {
+                CompilationError::internal("EXPLAIN (FORMAT JSON) query is not found".to_string())
+            })?;
+            let plan = self_cloned
+                .plan(&ast::Statement::Query(Box::new(query)), &mut None, span_id)
+                .await?;
+
+            let explain = match &plan {
+                QueryPlan::DataFusionSelect(_, plan, _) => explain::plan_to_json(plan),
+                QueryPlan::MetaOk(_, _) | QueryPlan::MetaTabular(_, _) => serde_json::json!({
+                    "plan": null,
+                    "cube_scans": [],
+                    "estimated_rows": null,
+                }),
+            };
+            let explain = serde_json::to_string_pretty(&explain).map_err(|e| {
+                CompilationError::internal(format!("Unable to serialize the plan: {}", e))
+            })?;
+
+            Ok(QueryPlan::MetaTabular(
+                StatusFlags::empty(),
+                Box::new(dataframe::DataFrame::new(
+                    vec![dataframe::Column::new(
+                        "QUERY PLAN".to_string(),
+                        ColumnType::String,
+                        ColumnFlags::empty(),
+                    )],
+                    vec![dataframe::Row::new(vec![dataframe::TableValue::String(
+                        explain,
+                    )])],
+                )),
+            ))
+        })
+    }
+
+    async fn load_views(&self) -> CompilationResult<Arc<HashMap<String, ast::Query>>> {
+        let auth_context = match self.state.auth_context() {
+            Some(auth_context) => auth_context,
+            None => return Ok(Arc::new(HashMap::new())),
+        };
+
+        let views = self
+            .session_manager
+            .server
+            .transport
+            .views(auth_context)
+            .await
+            .map_err(|e| CompilationError::internal(format!("Error loading views: {}", e)))?;
+
+        let protocol = self.state.protocol.clone();
+        Ok(self.session_manager.server.parsed_views.get_or_parse(
+            &protocol.to_string(),
+            views,
+            |view| match parse_sql_to_statement(&view.sql, protocol.clone(), &mut None)
+                .map(|stmt| rewrite_statement(&stmt))
+            {
+                Ok(ast::Statement::Query(query)) => Some(*query),
+                _ => {
+                    warn!("Unable to parse view '{}', it's ignored", view.name);
+                    None
+                }
+            },
+        ))
+    }
+
+    async fn expand_views(&self, stmt: &ast::Statement) -> CompilationResult<ast::Statement> {
+        match stmt {
+            ast::Statement::Query(_)
+            | ast::Statement::Explain { .. }
+            | ast::Statement::Declare { .. } => (),
+            _ => return Ok(stmt.clone()),
+        };
+
+        let views = self.load_views().await?;
+        if views.is_empty() {
+            return Ok(stmt.clone());
+        }
+
+        Ok(ViewReplacer::new(views).replace(stmt))
+    }
+
+    fn expand_granularities(&self, stmt: &ast::Statement) -> ast::Statement {
+        match stmt {
+            ast::Statement::Query(_)
+            | ast::Statement::Explain { .. }
+            | ast::Statement::Declare { .. } => (),
+            _ => return stmt.clone(),
+        };
+
+        let custom_granularities = self
+            .meta
+            .cubes
+            .iter()
+            .flat_map(|cube| cube.dimensions.iter())
+            .flat_map(|dimension| dimension.granularities.iter().flatten())
+            .map(|granularity| granularity.name.to_lowercase())
+            .collect();
+
+        GranularityReplacer::new(self.state.fiscal_year_start_month(), custom_granularities)
+            .replace(stmt)
+    }
+
+    /// Collation of strings sorted by DataFusion, byte ordering is used when it's None
+    fn local_collation(&self) -> Option<Collation> {
+        if !self.feature_enabled("collated_local_sort") {
+            return None;
+        }
+
+        self.state
+            .lc_collate()
+            .map(|lc_collate| Collation::from_lc_collate(&lc_collate))
+    }
+
+    fn feature_enabled(&self, name: &str) -> bool {
+        self.session_manager
+            .server
+            .config_obj
+            .features()
+            .is_enabled(name, &self.state)
+    }
+
+    /// Uncorrelated scalar subqueries over cubes in the projection (`SELECT (SELECT MAX(x) FROM
+    /// cube)`) can't be rewritten into a single CubeScan. They are only planned here, the query
+    /// is executed by `ScalarSubqueryNode` which evaluates each distinct subquery once and plans
+    /// the query again with the values inlined as literals.
+    async fn query_to_plan(
+        &self,
+        stmt: &ast::Statement,
+        q: &Box<ast::Query>,
+        qtrace: &mut Option<Qtrace>,
+        span_id: Option<Arc<SpanId>>,
+    ) -> CompilationResult<QueryPlan> {
+        let subqueries = if self.feature_enabled("scalar_subquery_inlining") {
+            self.plan_scalar_subqueries(stmt, span_id.clone()).await?
+        } else {
+            vec![]
+        };
+        if subqueries.is_empty() {
+            return self.select_to_plan(stmt, q, qtrace, span_id).await;
+        }
+
+        let aliases = subqueries
+            .iter()
+            .map(|subquery| (subquery.key.clone(), subquery.name.clone()))
+            .collect::<HashMap<_, _>>();
+        let placeholders = subqueries
+            .iter()
+            .filter_map(|subquery| {
+                ScalarSubquery::placeholder(&subquery.data_type)
+                    .map(|placeholder| (subquery.key.clone(), placeholder))
+            })
+            .collect();
+        let (flags, placeholder_plan, ctx) = match self
+            .select_with_values_to_plan(
+                stmt,
+                placeholders,
+                aliases.clone(),
+                qtrace,
+                span_id.clone(),
+            )
+            .await?
+        {
+            QueryPlan::DataFusionSelect(flags, plan, ctx) => (flags, plan, ctx),
+            plan => return Ok(plan),
+        };
+
+        let planner = Arc::new(ScalarSubqueryStatement {
+            planner: self.clone(),
+            stmt: stmt.clone(),
+            aliases,
+            span_id,
+        });
+        let node = ScalarSubqueryNode::new(subqueries, Arc::new(placeholder_plan), planner);
+
+        Ok(QueryPlan::DataFusionSelect(
+            flags,
+            LogicalPlan::Extension(Extension {
+                node: Arc::new(node),
+            }),
+            ctx,
+        ))
+    }
+
+    async fn select_with_values_to_plan(
+        &self,
+        stmt: &ast::Statement,
+        values: HashMap<String, ast::Expr>,
+        aliases: HashMap<String, String>,
+        qtrace: &mut Option<Qtrace>,
+        span_id: Option<Arc<SpanId>>,
+    ) -> CompilationResult<QueryPlan> {
+        let stmt = ScalarSubqueryReplacer::new(values)
+            .with_aliases(aliases)
+            .replace(stmt);
+        match &stmt {
+            ast::Statement::Query(q) => self.select_to_plan(&stmt, q, qtrace, span_id).await,
+            _ => Err(CompilationError::internal(
+                "Query with scalar subqueries is expected".to_string(),
+            )),
+        }
+    }
+
+    /// Subqueries which are not over cubes, correlated or of types which can't be inlined are
+    /// left to DataFusion
+    async fn plan_scalar_subqueries(
+        &self,
+        stmt: &ast::Statement,
+        span_id: Option<Arc<SpanId>>,
+    ) -> CompilationResult<Vec<ScalarSubquery>> {
+        let mut result: Vec<ScalarSubquery> = vec![];
+        for subquery in ScalarSubqueryReplacer::find(stmt) {
+            let key = subquery.to_string();
+            if result.iter().any(|planned| planned.key == key) {
+                continue;
+            }
+
+            let mut tables = vec![];
+            Self::collect_tables(&subquery, &mut tables);
+            let over_cube = tables.iter().any(|(_, table)| {
+                self.meta
+                    .cubes
+                    .iter()
+                    .any(|cube| cube.name.eq_ignore_ascii_case(table))
+            });
+            if !over_cube {
+                continue;
+            }
+
+            let (plan, ctx) = match self
+                .create_df_logical_plan(
+                    ast::Statement::Query(Box::new(subquery.clone())),
+                    &mut None,
+                    span_id.clone(),
+                )
+                .await
+            {
+                Ok(QueryPlan::DataFusionSelect(_, plan, ctx)) => (plan, ctx),
+                Ok(_) => continue,
+                Err(err) if Self::is_outer_reference(stmt, &subquery, &err) => continue,
+                Err(err) => return Err(err),
+            };
+
+            let field = match plan.schema().fields().as_slice() {
+                [field] => field.clone(),
+                _ => {
+                    return Err(CompilationError::user(
+                        "subquery must return only one column".to_string(),
+                    ))
+                }
+            };
+            if ScalarSubquery::placeholder(field.data_type()).is_none() {
+                continue;
+            }
+
+            result.push(ScalarSubquery::new(
+                key,
+                field.name().clone(),
+                field.data_type().clone(),
+                plan,
+                Arc::new(ctx),
+            ));
+        }
+
+        Ok(result)
+    }
+
+    /// Correlated subqueries can't be planned on their own, they fail on a column of a table
+    /// which is referenced only by the outer query
+    fn is_outer_reference(
+        stmt: &ast::Statement,
+        subquery: &ast::Query,
+        err: &CompilationError,
+    ) -> bool {
+        let message = match err {
+            CompilationError::Internal(message, _, _) => message,
+            _ => return false,
+        };
+        let qualifier = match FIELD_NOT_FOUND_REGEX
+            .captures(message)
+            .and_then(|captures| captures.name("field"))
+            .and_then(|field| field.as_str().rsplit_once('.'))
+        {
+            Some((qualifier, _)) => qualifier,
+            None => return false,
+        };
+
+        let mut inner_tables = vec![];
+        Self::collect_tables(subquery, &mut inner_tables);
+        let mut outer_tables = vec![];
+        if let ast::Statement::Query(query) = stmt {
+            Self::collect_tables(query, &mut outer_tables);
+        }
+        let has_alias = |tables: &Vec<(String, String)>| {
+            tables
+                .iter()
+                .any(|(alias, _)| alias.eq_ignore_ascii_case(qualifier))
+        };
+
+        has_alias(&outer_tables) && !has_alias(&inner_tables)
+    }
+
+    /// DataFusion reports unknown columns as a schema error without any context. It's turned
+    /// into a user error with the cube where the column was searched and the closest members.
+    fn member_not_found(&self, stmt: &ast::Statement, message: &str) -> Option<MemberNotFound> {
+        let field = FIELD_NOT_FOUND_REGEX
+            .captures(message)?
+            .name("field")?
+            .as_str();
+        let (qualifier, identifier) = match field.rsplit_once('.') {
+            Some((qualifier, identifier)) => (Some(qualifier), identifier),
+            None => (None, field),
+        };
+
+        let mut tables = Vec::new();
+        if let ast::Statement::Query(query) = stmt {
+            Self::collect_tables(query, &mut tables);
+        }
+        let cubes = tables
+            .iter()
+            .filter_map(|(alias, table)| {
+                self.meta
+                    .cubes
+                    .iter()
+                    .find(|cube| cube.name.eq_ignore_ascii_case(table))
+                    .map(|cube| (alias, cube))
+            })
+            .collect::<Vec<_>>();
+
+        let cube = match qualifier {
+            Some(qualifier) => cubes
+                .iter()
+                .find(|(alias, _)| alias.eq_ignore_ascii_case(qualifier))
+                .map(|(_, cube)| *cube),
+            None if cubes.len() == 1 => Some(cubes[0].1),
+            None => None,
+        };
+        let candidates = match cube {
+            Some(cube) => vec![cube],
+            // Not a cube, e.g. a system table
+            None if qualifier.is_some() => return None,
+            None => cubes
+                .iter()
+                .map(|(_, cube)| *cube)
+                .unique_by(|c| &c.name)
+                .collect(),
+        };
+        if candidates.is_empty() {
+            return None;
+        }
+
+        Some(MemberNotFound::new(
+            identifier.to_string(),
+            cube.map(|cube| cube.name.clone()),
+            candidates
+                .into_iter()
+                .flat_map(|cube| cube.get_columns())
+                .map(|column| column.get_name().clone()),
+        ))
+    }
+
+    /// Tables referenced by the query and subqueries in FROM, as (alias, table name)
+    fn collect_tables(query: &ast::Query, tables: &mut Vec<(String, String)>) {
+        let select = match &query.body {
+            ast::SetExpr::Select(select) => select,
+            _ => return,
+        };
+
+        for twj in select.from.iter() {
+            let factors =
+                std::iter::once(&twj.relation).chain(twj.joins.iter().map(|j| &j.relation));
+            for factor in factors {
+                match factor {
+                    ast::TableFactor::Table { name, alias, .. } => {
+                        if let Some(table) = name.0.last() {
+                            let alias = alias.as_ref().map_or(&table.value, |a| &a.name.value);
+                            tables.push((alias.clone(), table.value.clone()));
+                        }
+                    }
+                    ast::TableFactor::Derived { subquery, .. } => {
+                        Self::collect_tables(subquery, tables)
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn view_error(e: CubeError) -> CompilationError {
+        match e.cause {
+            CubeErrorCauseType::User(_) => CompilationError::user(e.message),
+            CubeErrorCauseType::Internal(_) => CompilationError::internal(e.message),
+        }
+    }
+
+    /// Views are referenced by unqualified names, quotes are not a part of the name
+    fn view_name(name: &ast::ObjectName) -> CompilationResult<String> {
+        match &name.0[..] {
+            [ident] => Ok(ident.value.clone()),
+            _ => Err(CompilationError::user(format!(
+                "View name must not be qualified: {}",
+                name
+            ))),
+        }
+    }
+
+    async fn create_view_to_plan(
+        &self,
+        name: &ast::ObjectName,
+        columns: &Vec<ast::Ident>,
+        query: &Box<ast::Query>,
+        or_replace: bool,
+        materialized: bool,
+    ) -> CompilationResult<QueryPlan> {
+        if materialized {
+            return Err(CompilationError::unsupported(
+                "CREATE MATERIALIZED VIEW is not supported".to_string(),
+            ));
+        }
+        if !columns.is_empty() {
+            return Err(CompilationError::unsupported(
+                "Column list in CREATE VIEW is not supported, please use aliases in the query"
+                    .to_string(),
+            ));
+        }
+
+        let view_name = Self::view_name(name)?;
+        if self.meta.find_cube_with_name(&view_name).is_some() {
+            return Err(CompilationError::user(format!(
+                "Unable to create view '{}': cube with the same name exists",
+                view_name
+            )));
+        }
+
+        // Query is planned to check it before the view is shared with other sessions
+        let stmt = self
+            .expand_views(&rewrite_statement(&ast::Statement::Query(query.clone())))
+            .await?;
+        if let ast::Statement::Query(expanded) = &stmt {
+            self.select_to_plan(&stmt, expanded, &mut None, None)
+                .await?;
+        }
 
-            match plan {
-                QueryPlan::MetaOk(_, _) | QueryPlan::MetaTabular(_, _) => Ok(QueryPlan::MetaTabular(
-                    StatusFlags::empty(),
-                    Box::new(dataframe::DataFrame::new(
-                        vec![dataframe::Column::new(
-                            "Execution Plan".to_string(),
-                            ColumnType::String,
-                            ColumnFlags::empty(),
-                        )],
-                        vec![dataframe::Row::new(vec![dataframe::TableValue::String(
-                            "This query doesnt have a plan, because it already has values for response"
-                                .to_string(),
-                        )])],
-                    )),
-                )),
-                QueryPlan::DataFusionSelect(flags, plan, context) => {
-                    let plan = Arc::new(plan);
-                    let schema = LogicalPlan::explain_schema();
-                    let schema = schema.to_dfschema_ref().map_err(|err| {
-                        CompilationError::internal(format!(
-                            "Unable to get DF schema for explain plan: {}",
-                            err
-                        ))
-                    })?;
+        let auth_context = self.state.auth_context().ok_or_else(|| {
+            CompilationError::internal("Unable to create view without auth context".to_string())
+        })?;
+        self.session_manager
+            .server
+            .transport
+            .save_view(
+                auth_context,
+                SqlView::new(view_name, query.to_string()),
+                or_replace,
+            )
+            .await
+            .map_err(Self::view_error)?;
 
-                    let explain_plan = if analyze {
-                        LogicalPlan::Analyze(Analyze {
-                            verbose,
-                            input: plan,
-                            schema,
-                        })
-                    } else {
-                        let stringified_plans = vec![plan.to_stringified(PlanType::InitialLogicalPlan)];
+        Ok(QueryPlan::MetaOk(
+            StatusFlags::empty(),
+            CommandCompletion::CreateView,
+        ))
+    }
 
-                        LogicalPlan::Explain(Explain {
-                            verbose,
-                            plan,
-                            stringified_plans,
-                            schema,
-                        })
-                    };
+    async fn drop_view_to_plan(
+        &self,
+        names: &Vec<ast::ObjectName>,
+        if_exists: bool,
+    ) -> CompilationResult<QueryPlan> {
+        let auth_context = self.state.auth_context().ok_or_else(|| {
+            CompilationError::internal("Unable to drop view without auth context".to_string())
+        })?;
 
-                    Ok(QueryPlan::DataFusionSelect(flags, explain_plan, context))
-                }
+        for name in names {
+            let view_name = Self::view_name(name)?;
+            let dropped = self
+                .session_manager
+                .server
+                .transport
+                .drop_view(auth_context.clone(), view_name.clone())
+                .await
+                .map_err(Self::view_error)?;
+            if !dropped && !if_exists {
+                return Err(CompilationError::user(format!(
+                    "View '{}' does not exist",
+                    view_name
+                )));
             }
-        })
+        }
+
+        Ok(QueryPlan::MetaOk(
+            StatusFlags::empty(),
+            CommandCompletion::DropView,
+        ))
     }
 
     fn use_to_plan(&self, db_name: &ast::Ident) -> Result<QueryPlan, CompilationError> {
-        self.state.set_database(Some(db_name.value.clone()));
+        let databases = self.session_manager.server.config_obj.databases();
+        if let Some(database) = databases.find(&db_name.value) {
+            if database.base_path.is_some() {
+                let is_http = self
+                    .state
+                    .auth_context()
+                    .map(|ctx| ctx.as_any().is::<HttpAuthContext>())
+                    .unwrap_or(false);
+                if !is_http {
+                    return Err(CompilationError::user(format!(
+                        "Database '{}' is mapped to a Cube API base path, which is supported only in standalone mode",
+                        database.name
+                    )));
+                }
+            }
+
+            self.state.set_database(Some(database.name.clone()));
+            self.state.set_base_path(database.base_path.clone());
+        } else if !databases.strict || DatabaseMapping::is_system_database(&db_name.value) {
+            self.state.set_database(Some(db_name.value.clone()));
+        } else {
+            return Err(CompilationError::user(format!(
+                "Unknown database '{}'",
+                db_name.value
+            )));
+        }
 
         Ok(QueryPlan::MetaOk(
             StatusFlags::empty(),
@@ -1032,6 +1943,69 @@ WHERE `TABLE_SCHEMA` = '{}'",
         }
     }
 
+    /// Results are always encoded as UTF-8, SET NAMES only changes charset variables of the session
+    fn set_names_to_plan(
+        &self,
+        charset_name: &str,
+        collation_name: &Option<String>,
+    ) -> Result<QueryPlan, CompilationError> {
+        let (charset, default_collation) = match find_character_set(charset_name) {
+            Some(charset) => charset,
+            None => {
+                warn!(
+                    "SET NAME does not support non utf8 charsets, input: {}",
+                    charset_name
+                );
+
+                return Ok(QueryPlan::MetaTabular(
+                    StatusFlags::empty(),
+                    Box::new(dataframe::DataFrame::new(vec![], vec![])),
+                ));
+            }
+        };
+
+        let collation = match collation_name {
+            Some(collation) => {
+                let collation = collation.to_ascii_lowercase();
+                if !collation.starts_with(&format!("{}_", charset)) {
+                    return Err(CompilationError::user(format!(
+                        "COLLATION '{}' is not valid for CHARACTER SET '{}'",
+                        collation, charset
+                    )));
+                }
+
+                collation
+            }
+            None => default_collation.to_string(),
+        };
+
+        let mut variables = [
+            "character_set_client",
+            "character_set_connection",
+            "character_set_results",
+        ]
+        .iter()
+        .map(|name| {
+            DatabaseVariable::system(
+                name.to_string(),
+                ScalarValue::Utf8(Some(charset.to_string())),
+                None,
+            )
+        })
+        .collect::<Vec<_>>();
+        variables.push(DatabaseVariable::system(
+            "collation_connection".to_string(),
+            ScalarValue::Utf8(Some(collation)),
+            None,
+        ));
+        self.state.set_variables(variables);
+
+        Ok(QueryPlan::MetaTabular(
+            StatusFlags::SERVER_STATE_CHANGED,
+            Box::new(dataframe::DataFrame::new(vec![], vec![])),
+        ))
+    }
+
     async fn set_variable_to_plan(
         &self,
         key_values: &Vec<ast::SetVariableKeyValue>,
@@ -1217,17 +2191,59 @@ WHERE `TABLE_SCHEMA` = '{}'",
         }
     }
 
+    fn load_request_meta(&self) -> LoadRequestMeta {
+        let mut meta = self.state.get_load_request_meta();
+        meta.set_memory_pressure(Some(self.session_manager.server.memory_pressure.clone()));
+        meta.set_replica_routing_min_limit(
+            self.session_manager
+                .server
+                .config_obj
+                .replica_routing_min_limit(),
+        );
+        meta.set_meta_context(Some(self.meta.clone()));
+        meta
+    }
+
     fn create_execution_ctx(&self) -> DFSessionContext {
         let query_planner = Arc::new(CubeQueryPlanner::new(
             self.session_manager.server.transport.clone(),
-            self.state.get_load_request_meta(),
+            self.load_request_meta(),
+            self.session_manager.server.member_usage.clone(),
+            self.session_manager.server.load_limiter.clone(),
+            self.session_manager.server.transform_pool.clone(),
+            self.session_manager.server.date_formats.clone(),
+            self.session_manager.server.result_processors.clone(),
+            self.session_manager.server.config_obj.stream_mode(),
+            self.session_manager
+                .server
+                .config_obj
+                .shared_scan_max_rows(),
+            self.state.scan_schemas(),
+            self.state.cursor_fetch_size(),
+            self.state.join_strategy(),
+            self.session_manager
+                .server
+                .config_obj
+                .join_broadcast_max_rows(),
+            self.local_collation(),
+            self.session_manager
+                .server
+                .config_obj
+                .data_source_collation()
+                .as_deref()
+                .map(Collation::from_lc_collate),
+            self.session_manager
+                .server
+                .config_obj
+                .dictionary_encoding_max_ratio(),
         ));
         let mut ctx = DFSessionContext::with_state(
-            default_session_builder(
+            DFSessionState::with_config_rt(
                 DFSessionConfig::new()
                     .create_default_catalog_and_schema(false)
                     .with_information_schema(false)
                     .with_default_catalog_and_schema("db", "public"),
+                self.session_manager.server.runtime_env.clone(),
             )
             .with_query_planner(query_planner),
         );
@@ -1246,8 +2262,9 @@ WHERE `TABLE_SCHEMA` = '{}'",
         }
 
         // udf
+        let server_version = self.session_manager.server.config_obj.server_version();
         if self.state.protocol == DatabaseProtocol::MySQL {
-            ctx.register_udf(create_version_udf("8.0.25".to_string()));
+            ctx.register_udf(create_version_udf(server_version.mysql.clone()));
             ctx.register_udf(create_db_udf("database".to_string(), self.state.clone()));
             ctx.register_udf(create_db_udf("schema".to_string(), self.state.clone()));
             ctx.register_udf(create_current_user_udf(
@@ -1256,10 +2273,9 @@ WHERE `TABLE_SCHEMA` = '{}'",
                 true,
             ));
             ctx.register_udf(create_user_udf(self.state.clone()));
+            ctx.register_udf(create_sleep_udf());
         } else if self.state.protocol == DatabaseProtocol::PostgreSQL {
-            ctx.register_udf(create_version_udf(
-                "PostgreSQL 14.1 on x86_64-cubesql".to_string(),
-            ));
+            ctx.register_udf(create_version_udf(server_version.postgres_version_string()));
             ctx.register_udf(create_db_udf(
                 "current_database".to_string(),
                 self.state.clone(),
@@ -1274,10 +2290,22 @@ WHERE `TABLE_SCHEMA` = '{}'",
                 false,
             ));
             ctx.register_udf(create_current_user_udf(self.state.clone(), "user", false));
+            ctx.register_udf(create_current_user_udf(
+                self.state.clone(),
+                "current_role",
+                false,
+            ));
             ctx.register_udf(create_session_user_udf(self.state.clone()));
+            ctx.register_udf(create_pg_sleep_udf());
+            ctx.register_udf(create_pg_is_in_recovery_udf());
+            ctx.register_udf(create_pg_client_encoding_udf());
+            ctx.register_udf(create_server_start_time_udf("pg_postmaster_start_time"));
+            ctx.register_udf(create_server_start_time_udf("pg_conf_load_time"));
         }
 
         ctx.register_udf(create_connection_id_udf(self.state.clone()));
+        ctx.register_udf(create_cube_user_switched_udf(self.state.clone()));
+        ctx.register_udf(create_cube_security_context_udf(self.state.clone()));
         ctx.register_udf(create_pg_backend_pid_udf(self.state.clone()));
         ctx.register_udf(create_instr_udf());
         ctx.register_udf(create_ucase_udf());
@@ -1322,6 +2350,10 @@ WHERE `TABLE_SCHEMA` = '{}'",
         ctx.register_udf(create_pg_my_temp_schema());
         ctx.register_udf(create_pg_is_other_temp_schema());
         ctx.register_udf(create_has_schema_privilege_udf(self.state.clone()));
+        ctx.register_udf(create_has_table_privilege_udf(
+            self.state.clone(),
+            self.meta.clone(),
+        ));
         ctx.register_udf(create_pg_total_relation_size_udf());
         ctx.register_udf(create_cube_regclass_cast_udf());
         ctx.register_udf(create_pg_get_serial_sequence_udf());
@@ -1333,7 +2365,10 @@ WHERE `TABLE_SCHEMA` = '{}'",
         ctx.register_udf(create_date_to_timestamp_udf());
         ctx.register_udf(create_to_date_udf());
         ctx.register_udf(create_sha1_udf());
-        ctx.register_udf(create_current_setting_udf());
+        ctx.register_udf(create_current_setting_udf(
+            self.state.clone(),
+            server_version.clone(),
+        ));
         ctx.register_udf(create_quote_ident_udf());
         ctx.register_udf(create_pg_encoding_to_char_udf());
         ctx.register_udf(create_array_to_string_udf());
@@ -1399,6 +2434,9 @@ WHERE `TABLE_SCHEMA` = '{}'",
             _ => (),
         }
 
+        let (stmt, request_timezone) =
+            TimezoneReplacer::new(self.meta.cube_time_dimensions()).replace(&stmt);
+
         let ctx = self.create_execution_ctx();
 
         let df_state = Arc::new(ctx.state.write().clone());
@@ -1407,23 +2445,25 @@ WHERE `TABLE_SCHEMA` = '{}'",
             self.meta.clone(),
             self.session_manager.clone(),
             self.state.clone(),
-        );
+        )
+        .with_request_timezone(request_timezone);
         let df_query_planner = SqlToRel::new_with_options(&cube_ctx, true);
 
-        let plan = df_query_planner
-            .statement_to_plan(DFStatement::Statement(Box::new(stmt.clone())))
-            .map_err(|err| {
-                let message = format!("Initial planning error: {}", err,);
-                let meta = Some(HashMap::from([
-                    ("query".to_string(), stmt.to_string()),
-                    (
-                        "sanitizedQuery".to_string(),
-                        SensitiveDataSanitizer::new().replace(&stmt).to_string(),
-                    ),
-                ]));
+        let plan = plan_statement(&df_query_planner, stmt).map_err(|err| {
+            let message = format!("Initial planning error: {}", err,);
+            let meta = Some(HashMap::from([
+                ("query".to_string(), stmt.to_string()),
+                (
+                    "sanitizedQuery".to_string(),
+                    SensitiveDataSanitizer::new().replace(&stmt).to_string(),
+                ),
+            ]));
 
-                CompilationError::internal(message).with_meta(meta)
-            })?;
+            match self.member_not_found(stmt, &err.to_string()) {
+                Some(err) => CompilationError::member_not_found(err).with_meta(meta),
+                None => CompilationError::internal(message).with_meta(meta),
+            }
+        })?;
         if let Some(qtrace) = qtrace {
             qtrace.set_df_plan(&plan);
         }
@@ -1458,6 +2498,12 @@ WHERE `TABLE_SCHEMA` = '{}'",
 
         self.reauthenticate_if_needed().await?;
 
+        let sql_push_down = match self.state.pushdown_hint() {
+            Some(PushdownHint::Force) => true,
+            Some(PushdownHint::Forbid) => false,
+            None => Rewriter::sql_push_down_enabled(),
+        };
+
         let result = converter
             .take_rewriter()
             .find_best_plan(
@@ -1465,6 +2511,7 @@ WHERE `TABLE_SCHEMA` = '{}'",
                 self.state.auth_context().unwrap(),
                 qtrace,
                 span_id.clone(),
+                sql_push_down,
             )
             .await
             .map_err(|e| match e.cause {
@@ -1502,6 +2549,10 @@ WHERE `TABLE_SCHEMA` = '{}'",
         }
 
         let rewrite_plan = result?;
+        // Rewrites can leave members in CubeScans which are not used by the final projection
+        let rewrite_plan = CubeScanMemberPruning::new()
+            .optimize(&rewrite_plan, &optimizer_config)
+            .unwrap_or(rewrite_plan);
 
         // DF optimizes logical plan (second time) on physical plan creation
         // It's not safety to use all optimizers from DF for OLAP queries, because it will lead to errors
@@ -1520,7 +2571,8 @@ WHERE `TABLE_SCHEMA` = '{}'",
         log::debug!("Rewrite: {:#?}", rewrite_plan);
         let rewrite_plan = Self::evaluate_wrapped_sql(
             self.session_manager.server.transport.clone(),
-            Arc::new(self.state.get_load_request_meta()),
+            Arc::new(self.load_request_meta()),
+            WrappedSqlLimits::new(self.session_manager.server.config_obj.as_ref()),
             rewrite_plan,
         )
         .await?;
@@ -1538,6 +2590,7 @@ WHERE `TABLE_SCHEMA` = '{}'",
     fn evaluate_wrapped_sql(
         transport_service: Arc<dyn TransportService>,
         load_request_meta: Arc<LoadRequestMeta>,
+        limits: WrappedSqlLimits,
         plan: LogicalPlan,
     ) -> Pin<Box<dyn Future<Output = CompilationResult<LogicalPlan>> + Send>> {
         Box::pin(async move {
@@ -1549,9 +2602,20 @@ WHERE `TABLE_SCHEMA` = '{}'",
                     return Ok(LogicalPlan::Extension(Extension {
                         node: Arc::new(
                             wrapper
-                                .generate_sql(transport_service.clone(), load_request_meta.clone())
+                                .generate_sql(
+                                    transport_service.clone(),
+                                    load_request_meta.clone(),
+                                    limits,
+                                )
                                 .await
-                                .map_err(|e| CompilationError::internal(e.to_string()))?,
+                                .map_err(|e| match e.cause {
+                                    CubeErrorCauseType::User(_) => {
+                                        CompilationError::user(e.message)
+                                    }
+                                    CubeErrorCauseType::Internal(_) => {
+                                        CompilationError::internal(e.to_string())
+                                    }
+                                })?,
                         ),
                     }));
                 }
@@ -1562,6 +2626,7 @@ WHERE `TABLE_SCHEMA` = '{}'",
                     Self::evaluate_wrapped_sql(
                         transport_service.clone(),
                         load_request_meta.clone(),
+                        limits,
                         input.clone(),
                     )
                     .await?,
@@ -1604,6 +2669,10 @@ pub fn rewrite_statement(stmt: &ast::Statement) -> ast::Statement {
     let stmt = UdfWildcardArgReplacer::new().replace(&stmt);
     let stmt = RedshiftDatePartReplacer::new().replace(&stmt);
     let stmt = ApproximateCountDistinctVisitor::new().replace(&stmt);
+    let stmt = CompareDateRangeReplacer::new().replace(&stmt);
+    let stmt = BucketReplacer::new().replace(&stmt);
+    let stmt = ProjectionAliasReplacer::new().replace(&stmt);
+    let stmt = FetchFirstReplacer::new().replace(&stmt);
 
     stmt
 }
@@ -1620,8 +2689,157 @@ pub async fn convert_statement_to_cube_query(
         qtrace.set_visitor_replaced_statement(&stmt);
     }
 
-    let planner = QueryPlanner::new(session.state.clone(), meta, session.session_manager.clone());
-    planner.plan(&stmt, qtrace, span_id).await
+    if let ast::Statement::Query(_) = &stmt {
+        check_meta_staleness(&meta, &session)?;
+    }
+
+    let statement_fingerprint = PlanningFailures::fingerprint(&session.state, &stmt);
+    session
+        .state
+        .set_last_statement_fingerprint(Some(statement_fingerprint.clone()));
+
+    let planning_failures = session.server.planning_failures.clone();
+    let fingerprint = match &stmt {
+        ast::Statement::Query(_)
+            if planning_failures.is_enabled() && session.state.planning_failure_cache() =>
+        {
+            Some(statement_fingerprint)
+        }
+        _ => None,
+    };
+    if let Some(fingerprint) = &fingerprint {
+        if let Some(err) = planning_failures.get(fingerprint, &meta) {
+            log::debug!("Returning cached planning failure for {}", fingerprint);
+            session.report_unsupported_query(&err);
+            return Err(err);
+        }
+    }
+
+    let planner = QueryPlanner::new(
+        session.state.clone(),
+        meta.clone(),
+        session.session_manager.clone(),
+    );
+    let result = planner.plan(&stmt, qtrace, span_id).await;
+    if let Err(err) = &result {
+        session.report_unsupported_query(err);
+        if let Some(fingerprint) = fingerprint {
+            planning_failures.insert(fingerprint, &meta, err);
+        }
+    }
+
+    let plan = result.and_then(|plan| check_memory_pressure(plan, &session))?;
+    session.record_statement_class(classify_statement(&stmt, &plan));
+
+    Ok(plan)
+}
+
+/// Class of the planned statement for `cubesql.statement_classes` and telemetry
+fn classify_statement(stmt: &ast::Statement, plan: &QueryPlan) -> StatementClass {
+    match stmt {
+        ast::Statement::ShowVariable { .. }
+        | ast::Statement::ShowVariables { .. }
+        | ast::Statement::ShowCollation { .. }
+        | ast::Statement::ShowColumns { .. }
+        | ast::Statement::ShowCreate { .. }
+        | ast::Statement::ShowTables { .. }
+        | ast::Statement::Explain { .. }
+        | ast::Statement::ExplainTable { .. } => return StatementClass::Metadata,
+        ast::Statement::Query(_) => (),
+        _ => return StatementClass::Utility,
+    }
+
+    let logical_plan = match plan {
+        QueryPlan::DataFusionSelect(_, logical_plan, _) => logical_plan,
+        QueryPlan::MetaOk(_, _) | QueryPlan::MetaTabular(_, _) => {
+            return StatementClass::Introspection
+        }
+    };
+
+    // Requests which are sent to Cube, wrapped SQL has own request
+    struct FindLoadRequestsVisitor(Vec<V1LoadRequestQuery>);
+
+    impl PlanVisitor for FindLoadRequestsVisitor {
+        type Error = CubeError;
+
+        fn pre_visit(&mut self, plan: &LogicalPlan) -> Result<bool, Self::Error> {
+            if let LogicalPlan::Extension(ext) = plan {
+                if let Some(scan_node) = ext.node.as_any().downcast_ref::<CubeScanNode>() {
+                    self.0.push(scan_node.request.clone());
+                } else if let Some(wrapper_node) =
+                    ext.node.as_any().downcast_ref::<CubeScanWrapperNode>()
+                {
+                    match &wrapper_node.request {
+                        Some(request) => self.0.push(request.clone()),
+                        None => {
+                            wrapper_node.wrapped_plan.accept(self)?;
+                        }
+                    }
+                }
+            }
+            Ok(true)
+        }
+    }
+
+    let mut visitor = FindLoadRequestsVisitor(Vec::new());
+    if logical_plan.accept(&mut visitor).is_err() {
+        return StatementClass::Grouped;
+    }
+
+    if visitor.0.is_empty() {
+        StatementClass::Introspection
+    } else if visitor
+        .0
+        .iter()
+        .any(|request| request.ungrouped.unwrap_or(false))
+    {
+        StatementClass::Ungrouped
+    } else {
+        StatementClass::Grouped
+    }
+}
+
+/// Queries which can load a lot of rows are rejected while the server is low on memory, the
+/// error isn't cached as a planning failure. It's checked only at planning time, portals and
+/// cursors planned earlier are not rejected
+fn check_memory_pressure(plan: QueryPlan, session: &Session) -> CompilationResult<QueryPlan> {
+    let memory_pressure = &session.server.memory_pressure;
+    let logical_plan = match &plan {
+        QueryPlan::DataFusionSelect(_, logical_plan, _) if memory_pressure.is_enabled() => {
+            logical_plan
+        }
+        _ => return Ok(plan),
+    };
+
+    let query_limit = QueryLimitSettings::from_env().query_limit;
+    for scan in find_cube_scans_deep_search(Arc::new(logical_plan.clone()), false) {
+        memory_pressure.check_request(&scan.request, query_limit)?;
+    }
+
+    Ok(plan)
+}
+
+/// Queries are planned against the cached model when its refresh fails, it can be outdated
+/// during a migration of the model
+fn check_meta_staleness(meta: &MetaContext, session: &Session) -> CompilationResult<()> {
+    let config = &session.server.config_obj;
+    let threshold = config.meta_stale_threshold_secs();
+    let stale_for = match meta.stale_for() {
+        Some(stale_for) if threshold > 0 && stale_for.as_secs() >= threshold => stale_for,
+        _ => return Ok(()),
+    };
+
+    let message = format!(
+        "Schema may be stale: data model couldn't be refreshed for {} seconds",
+        stale_for.as_secs()
+    );
+    match config.meta_stale_policy() {
+        MetaStalePolicy::Reject => Err(CompilationError::user(message)),
+        MetaStalePolicy::Notice => {
+            session.state.add_warning(message);
+            Ok(())
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize)]
@@ -1722,6 +2940,41 @@ impl fmt::Debug for QueryPlan {
     }
 }
 
+/// Plans the query again once the values of its scalar subqueries are known
+struct ScalarSubqueryStatement {
+    planner: QueryPlanner,
+    stmt: ast::Statement,
+    aliases: HashMap<String, String>,
+    span_id: Option<Arc<SpanId>>,
+}
+
+#[async_trait]
+impl ScalarSubqueryPlanner for ScalarSubqueryStatement {
+    async fn plan(
+        &self,
+        values: HashMap<String, ast::Expr>,
+    ) -> Result<(LogicalPlan, Arc<DFSessionContext>), DataFusionError> {
+        let plan = self
+            .planner
+            .select_with_values_to_plan(
+                &self.stmt,
+                values,
+                self.aliases.clone(),
+                &mut None,
+                self.span_id.clone(),
+            )
+            .await
+            .map_err(|e| DataFusionError::Execution(e.message()))?;
+
+        match plan {
+            QueryPlan::DataFusionSelect(_, plan, ctx) => Ok((plan, Arc::new(ctx))),
+            QueryPlan::MetaOk(_, _) | QueryPlan::MetaTabular(_, _) => Err(
+                DataFusionError::Internal("Query with scalar subqueries is expected".to_string()),
+            ),
+        }
+    }
+}
+
 impl QueryPlan {
     pub fn as_logical_plan(&self) -> LogicalPlan {
         match self {
@@ -1766,10 +3019,61 @@ pub async fn convert_sql_to_cube_query(
     meta: Arc<MetaContext>,
     session: Arc<Session>,
 ) -> CompilationResult<QueryPlan> {
-    let stmt = parse_sql_to_statement(&query, session.state.protocol.clone(), &mut None)?;
+    session.state.set_pushdown_hint(parse_pushdown_hint(query));
+    let stmt = parse_sql_to_statement(&query, session.state.protocol.clone(), &mut None).map_err(
+        |err| {
+            session.report_unsupported_query(&err);
+            err
+        },
+    )?;
     convert_statement_to_cube_query(&stmt, meta, session, &mut None, None).await
 }
 
+/// Top level wrappers with generated SQL, nested wrappers are a part of their SQL
+fn find_cube_scan_wrappers(parent: &LogicalPlan) -> Vec<CubeScanWrapperNode> {
+    pub struct FindCubeScanWrapperVisitor(Vec<CubeScanWrapperNode>);
+
+    impl PlanVisitor for FindCubeScanWrapperVisitor {
+        type Error = CubeError;
+
+        fn pre_visit(&mut self, plan: &LogicalPlan) -> Result<bool, Self::Error> {
+            if let LogicalPlan::Extension(ext) = plan {
+                if let Some(wrapper_node) = ext.node.as_any().downcast_ref::<CubeScanWrapperNode>()
+                {
+                    if wrapper_node.wrapped_sql.is_some() {
+                        self.0.push(wrapper_node.clone());
+                    }
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+    }
+
+    let mut visitor = FindCubeScanWrapperVisitor(Vec::new());
+    parent.accept(&mut visitor).unwrap();
+    visitor.0
+}
+
+/// Data sources return the plan as rows, one line per row or a record per row
+fn explain_source_row_to_string(row: &serde_json::Value) -> String {
+    let value_to_string = |value: &serde_json::Value| match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+
+    match row {
+        serde_json::Value::Object(fields) if fields.len() == 1 => {
+            fields.values().map(value_to_string).join("")
+        }
+        serde_json::Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value_to_string(value)))
+            .join(", "),
+        other => value_to_string(other),
+    }
+}
+
 pub fn find_cube_scans_deep_search(
     parent: Arc<LogicalPlan>,
     panic_if_empty: bool,
@@ -1809,7 +3113,10 @@ mod tests {
     use cubeclient::models::{
         V1CubeMeta, V1LoadRequestQueryFilterItem, V1LoadRequestQueryTimeDimension,
     };
-    use datafusion::{dataframe::DataFrame as DFDataFrame, logical_plan::plan::Filter};
+    use datafusion::{
+        dataframe::DataFrame as DFDataFrame,
+        logical_plan::plan::{Filter, Sort},
+    };
     use pretty_assertions::assert_eq;
     use regex::Regex;
 
@@ -1826,10 +3133,16 @@ mod tests {
             },
         },
         config::{ConfigObj, ConfigObjImpl},
-        sql::{dataframe::batch_to_dataframe, types::StatusFlags},
+        sql::{
+            dataframe::{arrow_to_column_type, batch_to_dataframe},
+            types::StatusFlags,
+            MappedDatabase,
+        },
     };
     use datafusion::{logical_plan::PlanVisitor, physical_plan::displayable};
     use log::Level;
+    use msql_srv::ColumnType as MysqlColumnType;
+    use pg_srv::PgTypeId;
     use serde_json::json;
     use simple_logger::SimpleLogger;
 
@@ -2003,10 +3316,45 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
 
+    #[tokio::test]
+    async fn test_select_dimension_via_any_value() {
+        for fun in ["ANY_VALUE", "FIRST", "LAST"] {
+            let query_plan = convert_select_to_query_plan(
+                format!(
+                    "SELECT customer_gender, {}(notes) AS notes, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1",
+                    fun
+                ),
+                DatabaseProtocol::PostgreSQL,
+            )
+            .await;
+
+            let logical_plan = query_plan.as_logical_plan();
+            assert_eq!(
+                logical_plan.find_cube_scan().request,
+                V1LoadRequestQuery {
+                    measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string()]),
+                    segments: Some(vec![]),
+                    dimensions: Some(vec![
+                        "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                        "KibanaSampleDataEcommerce.notes".to_string(),
+                    ]),
+                    time_dimensions: None,
+                    order: None,
+                    limit: None,
+                    offset: None,
+                    filters: None,
+                    ungrouped: None,
+                    ..Default::default()
+                }
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_select_number() {
         let query_plan = convert_select_to_query_plan(
@@ -2026,6 +3374,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -2049,6 +3398,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -2077,6 +3427,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -2109,6 +3460,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -2123,6 +3475,286 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_group_by_ordinal_and_alias() {
+        init_logger();
+
+        for query in [
+            "SELECT customer_gender AS gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1",
+            "SELECT customer_gender AS gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY gender",
+        ] {
+            let query_plan =
+                convert_select_to_query_plan(query.to_string(), DatabaseProtocol::MySQL).await;
+
+            let request = query_plan.as_logical_plan().find_cube_scan().request;
+            assert_eq!(
+                request.measures,
+                Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+            );
+            assert_eq!(
+                request.dimensions,
+                Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()])
+            );
+            assert_eq!(request.ungrouped, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_group_by_all() {
+        init_logger();
+
+        for protocol in [DatabaseProtocol::MySQL, DatabaseProtocol::PostgreSQL] {
+            let query_plan = convert_select_to_query_plan(
+                "SELECT customer_gender AS gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY ALL"
+                    .to_string(),
+                protocol.clone(),
+            )
+            .await;
+
+            let request = query_plan.as_logical_plan().find_cube_scan().request;
+            assert_eq!(
+                request.measures,
+                Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+            );
+            assert_eq!(
+                request.dimensions,
+                Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()])
+            );
+            assert_eq!(request.ungrouped, None);
+
+            let result = convert_sql_to_cube_query(
+                &"SELECT * FROM KibanaSampleDataEcommerce GROUP BY ALL".to_string(),
+                get_test_tenant_ctx(),
+                get_test_session(protocol).await,
+            )
+            .await;
+            match result {
+                Err(CompilationError::Unsupported(msg, _)) => assert_eq!(
+                    msg,
+                    "GROUP BY ALL with a wildcard projection is not supported"
+                ),
+                other => panic!("Expected unsupported error, actual: {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_count_measures_data_type() {
+        init_logger();
+
+        for protocol in [DatabaseProtocol::MySQL, DatabaseProtocol::PostgreSQL] {
+            for query in [
+                "SELECT COUNT(*), MEASURE(countDistinct) FROM KibanaSampleDataEcommerce",
+                "SELECT COUNT(DISTINCT agentCount), COUNT(DISTINCT agentCountApprox) FROM Logs",
+            ] {
+                let query_plan =
+                    convert_select_to_query_plan(query.to_string(), protocol.clone()).await;
+
+                let logical_plan = query_plan.as_logical_plan();
+                let data_types = logical_plan
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|f| f.data_type().clone())
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    data_types,
+                    vec![DataType::Int64, DataType::Int64],
+                    "{} over {}",
+                    query,
+                    protocol.to_string()
+                );
+
+                // Types of result columns in both protocols are integers, not decimals
+                for data_type in data_types.iter() {
+                    let column_type = arrow_to_column_type(data_type.clone()).unwrap();
+                    assert_eq!(column_type, ColumnType::Int64);
+                    assert_eq!(column_type.to_mysql(), MysqlColumnType::MYSQL_TYPE_LONGLONG);
+                    assert_eq!(column_type.to_pg_tid(), PgTypeId::INT8);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trace_next_query() {
+        init_logger();
+
+        let meta = get_test_tenant_ctx();
+        let session = get_test_session(DatabaseProtocol::PostgreSQL).await;
+        let query = "SELECT COUNT(*) FROM KibanaSampleDataEcommerce";
+        assert!(Qtrace::for_session(query, &session.state).is_none());
+
+        session.state.set_variables(vec![DatabaseVariable::system(
+            "cubesql_trace_next_query".to_string(),
+            ScalarValue::Boolean(Some(true)),
+            None,
+        )]);
+        let mut qtrace = Qtrace::for_session(query, &session.state);
+        assert!(session.state.transport_trace().is_some());
+        // Only the next query is traced
+        assert!(Qtrace::for_session(query, &session.state).is_none());
+
+        let statement = parse_sql_to_statement(
+            &query.to_string(),
+            DatabaseProtocol::PostgreSQL,
+            &mut qtrace,
+        )
+        .unwrap();
+        if let Some(qtrace) = &mut qtrace {
+            qtrace.push_statement(&statement);
+        }
+        convert_statement_to_cube_query(
+            &statement,
+            meta.clone(),
+            session.clone(),
+            &mut qtrace,
+            None,
+        )
+        .await
+        .unwrap();
+        qtrace.unwrap().finish(&session.state);
+        assert!(session.state.transport_trace().is_none());
+
+        let trace =
+            match convert_sql_to_cube_query(&"SHOW LAST QUERY TRACE".to_string(), meta, session)
+                .await
+                .unwrap()
+            {
+                QueryPlan::MetaTabular(_, frame) => match frame.get_rows()[0].values()[0].clone() {
+                    dataframe::TableValue::String(trace) => trace,
+                    value => panic!("Unexpected trace: {:?}", value),
+                },
+                _ => panic!("SHOW LAST QUERY TRACE must return a table"),
+            };
+        let trace: serde_json::Value = serde_json::from_str(&trace).unwrap();
+        assert_eq!(trace["originalQuery"], query);
+        assert_eq!(
+            trace["statements"][0]["cubeScans"][0]["measures"][0],
+            "KibanaSampleDataEcommerce.count"
+        );
+        assert!(trace["statements"][0]["originalGraph"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_show_last_cube_request() {
+        init_logger();
+
+        let meta = get_test_tenant_ctx();
+        let session = get_test_session(DatabaseProtocol::PostgreSQL).await;
+
+        let show = || async {
+            match convert_sql_to_cube_query(
+                &"SHOW LAST CUBE REQUEST".to_string(),
+                meta.clone(),
+                session.clone(),
+            )
+            .await
+            .unwrap()
+            {
+                QueryPlan::MetaTabular(_, frame) => frame
+                    .get_rows()
+                    .iter()
+                    .map(|row| format!("{:?}", row.values()))
+                    .collect::<Vec<_>>(),
+                _ => panic!("SHOW LAST CUBE REQUEST must return a table"),
+            }
+        };
+        assert!(show().await.is_empty());
+
+        session
+            .state
+            .get_load_request_meta()
+            .last_cube_request()
+            .record(crate::transport::CubeRequest {
+                request_id: "5a6c8b5e-span-1".to_string(),
+                method: "load".to_string(),
+                duration_ms: 15,
+                error: Some("Query timeout".to_string()),
+            });
+
+        assert_eq!(
+            show().await,
+            vec![
+                r#"[String("5a6c8b5e-span-1"), String("load"), Int64(15), String("Query timeout")]"#
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_and_drop_view() {
+        init_logger();
+
+        let meta = get_test_tenant_ctx();
+        let session = get_test_session(DatabaseProtocol::PostgreSQL).await;
+        let plan = |query: &str| {
+            let query = query.to_string();
+            let meta = meta.clone();
+            let session = session.clone();
+            async move { convert_sql_to_cube_query(&query, meta, session).await }
+        };
+
+        let create_view = "CREATE VIEW female_orders AS SELECT customer_gender, MEASURE(count) AS cnt FROM KibanaSampleDataEcommerce WHERE customer_gender = 'female' GROUP BY 1";
+        assert!(matches!(
+            plan(create_view).await.unwrap(),
+            QueryPlan::MetaOk(_, CommandCompletion::CreateView)
+        ));
+        assert_eq!(
+            plan(create_view).await.err().unwrap().to_string(),
+            "SQLCompilationError: User: View 'female_orders' already exists"
+        );
+        // Invalid queries are not stored
+        assert!(
+            plan("CREATE VIEW broken AS SELECT unknown FROM KibanaSampleDataEcommerce")
+                .await
+                .is_err()
+        );
+        assert!(plan("CREATE VIEW KibanaSampleDataEcommerce AS SELECT 1")
+            .await
+            .is_err());
+
+        let query_plan = plan("SELECT cnt FROM female_orders").await.unwrap();
+        let request = query_plan.as_logical_plan().find_cube_scan().request;
+        assert_eq!(
+            request.measures,
+            Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+        );
+        assert_eq!(
+            request.dimensions,
+            Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()])
+        );
+        assert_eq!(
+            request.filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                operator: Some("equals".to_string()),
+                values: Some(vec!["female".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+
+        assert!(plan("DROP VIEW public.female_orders").await.is_err());
+        // The name is normalized as in CREATE VIEW
+        assert!(matches!(
+            plan("DROP VIEW \"female_orders\"").await.unwrap(),
+            QueryPlan::MetaOk(_, CommandCompletion::DropView)
+        ));
+        assert_eq!(
+            plan("DROP VIEW female_orders")
+                .await
+                .err()
+                .unwrap()
+                .to_string(),
+            "SQLCompilationError: User: View 'female_orders' does not exist"
+        );
+        plan("DROP VIEW IF EXISTS female_orders").await.unwrap();
+        assert!(plan("SELECT cnt FROM female_orders").await.is_err());
+    }
+
     #[tokio::test]
     async fn test_change_user_via_filter() {
         init_logger();
@@ -2150,6 +3782,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -2181,6 +3814,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -2216,6 +3850,7 @@ mod tests {
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -2251,6 +3886,7 @@ mod tests {
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -2295,6 +3931,7 @@ mod tests {
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -2377,6 +4014,7 @@ mod tests {
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -2422,6 +4060,7 @@ mod tests {
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -2446,6 +4085,7 @@ mod tests {
             offset: None,
             filters: None,
             ungrouped: None,
+            ..Default::default()
         };
 
         let cube_scan = query_plan.as_logical_plan().find_cube_scan();
@@ -2493,6 +4133,7 @@ mod tests {
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -2534,6 +4175,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -2564,6 +4206,7 @@ mod tests {
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             ),
             (
@@ -2584,6 +4227,7 @@ mod tests {
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 }
             ),
             // test_order_indentifier_default
@@ -2604,6 +4248,7 @@ mod tests {
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             ),
             // test_order_compound_identifier_default
@@ -2624,6 +4269,7 @@ mod tests {
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             ),
             // test_order_indentifier_asc
@@ -2644,6 +4290,7 @@ mod tests {
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             ),
             // test_order_indentifier_desc
@@ -2664,6 +4311,7 @@ mod tests {
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             ),
             // test_order_identifer_alias_ident_no_escape
@@ -2684,6 +4332,7 @@ mod tests {
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             ),
             // test_order_identifer_alias_ident_escape
@@ -2704,6 +4353,7 @@ mod tests {
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             ),
         ];
@@ -2752,6 +4402,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -2780,6 +4431,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -2860,6 +4512,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -2890,6 +4543,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -2947,6 +4601,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
     }
@@ -3005,6 +4660,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
     }
@@ -3035,6 +4691,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3063,6 +4720,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3097,6 +4755,7 @@ mod tests {
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3154,6 +4813,7 @@ mod tests {
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -3190,6 +4850,7 @@ mod tests {
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3224,6 +4885,7 @@ mod tests {
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3282,6 +4944,7 @@ mod tests {
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3326,6 +4989,7 @@ ORDER BY \"COUNT(count)\" DESC"
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3376,6 +5040,7 @@ ORDER BY \"COUNT(count)\" DESC"
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3453,6 +5118,7 @@ ORDER BY \"COUNT(count)\" DESC"
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3482,6 +5148,7 @@ ORDER BY \"COUNT(count)\" DESC"
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3548,6 +5215,7 @@ ORDER BY \"COUNT(count)\" DESC"
                     and: None,
                 },]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3601,6 +5269,7 @@ ORDER BY \"COUNT(count)\" DESC"
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -3647,6 +5316,7 @@ ORDER BY \"COUNT(count)\" DESC"
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3673,6 +5343,7 @@ ORDER BY \"COUNT(count)\" DESC"
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3703,6 +5374,7 @@ ORDER BY \"COUNT(count)\" DESC"
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -3728,6 +5400,7 @@ ORDER BY \"COUNT(count)\" DESC"
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3758,6 +5431,7 @@ ORDER BY \"COUNT(count)\" DESC"
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3790,6 +5464,7 @@ ORDER BY \"COUNT(count)\" DESC"
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3822,6 +5497,7 @@ ORDER BY \"COUNT(count)\" DESC"
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3886,6 +5562,7 @@ ORDER BY \"COUNT(count)\" DESC"
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3936,6 +5613,7 @@ ORDER BY \"COUNT(count)\" DESC"
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -3990,6 +5668,7 @@ ORDER BY \"COUNT(count)\" DESC"
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -4145,6 +5824,7 @@ limit
                     },
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -4178,6 +5858,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -4223,6 +5904,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -4277,6 +5959,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -4296,6 +5979,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 },
             ),
             (
@@ -4310,6 +5994,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 },
             ),
             (
@@ -4324,6 +6009,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 },
             ),
             (
@@ -4338,6 +6024,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 },
             ),
             (
@@ -4352,6 +6039,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 },
             ),
             (
@@ -4366,6 +6054,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 },
             ),
             (
@@ -4380,6 +6069,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 },
             ),
         ];
@@ -4420,10 +6110,70 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
 
+    #[tokio::test]
+    async fn test_select_from_cube_schema() {
+        let mut meta = get_string_cube_meta();
+        meta[0].folder = Some("Strings".to_string());
+
+        let meta_ctx = |folder_schemas| {
+            Arc::new(MetaContext::new_with_folder_schemas(
+                meta.clone(),
+                HashMap::new(),
+                HashMap::new(),
+                folder_schemas,
+            ))
+        };
+
+        let logical_plan = convert_sql_to_cube_query(
+            &"SELECT MAX(someString) FROM strings.StringCube".to_string(),
+            meta_ctx(true),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await
+        .unwrap()
+        .as_logical_plan();
+
+        assert_eq!(
+            logical_plan.find_cube_scan().request.measures,
+            Some(vec!["StringCube.someString".to_string()])
+        );
+
+        let query = convert_sql_to_cube_query(
+            &"SELECT MAX(someString) FROM other.StringCube".to_string(),
+            meta_ctx(true),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await;
+        assert!(query.is_err());
+
+        // Without CUBESQL_FOLDER_SCHEMAS the cube stays in the default schema
+        let query = convert_sql_to_cube_query(
+            &"SELECT MAX(someString) FROM strings.StringCube".to_string(),
+            meta_ctx(false),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await;
+        assert!(query.is_err());
+
+        let logical_plan = convert_sql_to_cube_query(
+            &"SELECT MAX(someString) FROM public.StringCube".to_string(),
+            meta_ctx(false),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await
+        .unwrap()
+        .as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request.measures,
+            Some(vec!["StringCube.someString".to_string()])
+        );
+    }
+
     #[tokio::test]
     async fn test_select_error() {
         let variants = vec![
@@ -4512,6 +6262,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 }
             );
 
@@ -4586,6 +6337,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 }
             )
         }
@@ -4618,8 +6370,131 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_date_trunc_custom_granularity() {
+        init_logger();
+
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT DATE_TRUNC('Fiscal_Year', order_date) AS fy, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await
+        .as_logical_plan();
+
+        assert_eq!(
+            logical_plan.find_cube_scan().request.time_dimensions,
+            Some(vec![V1LoadRequestQueryTimeDimension {
+                dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                granularity: Some("fiscal_year".to_string()),
+                date_range: None,
+            }])
+        );
+
+        // Not declared on the dimension
+        let query = convert_sql_to_cube_query(
+            &"SELECT DATE_TRUNC('fiscal_year', last_mod) AS fy, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1"
+                .to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await;
+        if let Ok(plan) = query {
+            assert_ne!(
+                plan.as_logical_plan()
+                    .find_cube_scan()
+                    .request
+                    .time_dimensions
+                    .and_then(|tds| tds.into_iter().next())
+                    .and_then(|td| td.granularity),
+                Some("fiscal_year".to_string())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_member_not_found_error() {
+        init_logger();
+
+        let query = convert_sql_to_cube_query(
+            &"SELECT k.customer_gendr FROM KibanaSampleDataEcommerce k".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await;
+        match query {
+            Err(CompilationError::MemberNotFound(err, meta)) => {
+                assert_eq!(err.identifier, "customer_gendr");
+                assert_eq!(err.cube, Some("KibanaSampleDataEcommerce".to_string()));
+                assert_eq!(err.suggestions[0], "customer_gender");
+                assert_eq!(
+                    meta.unwrap().get(MemberNotFound::META_KEY),
+                    Some(&"customer_gendr".to_string())
+                );
             }
+            other => panic!("Expected member not found error, actual: {:?}", other),
+        }
+
+        // Unknown column of a system table is not a member
+        let query = convert_sql_to_cube_query(
+            &"SELECT relnam FROM pg_catalog.pg_class".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await;
+        assert!(matches!(query, Err(CompilationError::Internal(..))));
+    }
+
+    #[tokio::test]
+    async fn test_scalar_subquery_evaluated_on_execution() {
+        init_logger();
+
+        // The test transport can't load anything, subqueries must not be executed by planning
+        let query = convert_sql_to_cube_query(
+            &"SELECT (SELECT MAX(maxPrice) FROM KibanaSampleDataEcommerce) AS max_price, (SELECT MAX(maxPrice) FROM KibanaSampleDataEcommerce) / 2 AS half".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await
+        .unwrap();
+        let plan = query.as_logical_plan();
+        let node = match &plan {
+            LogicalPlan::Extension(Extension { node }) => node
+                .as_any()
+                .downcast_ref::<ScalarSubqueryNode>()
+                .expect("Expected scalar subqueries"),
+            other => panic!("Expected scalar subqueries, actual: {:?}", other),
+        };
+        assert_eq!(
+            node.subqueries
+                .iter()
+                .map(|subquery| subquery.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["SELECT MAX(maxPrice) FROM KibanaSampleDataEcommerce"]
+        );
+        assert_eq!(
+            plan.schema()
+                .fields()
+                .iter()
+                .map(|field| field.name().as_str())
+                .collect::<Vec<_>>(),
+            vec!["max_price", "half"]
+        );
+
+        // Errors of uncorrelated subqueries are reported
+        let query = convert_sql_to_cube_query(
+            &"SELECT (SELECT MAX(maxPrise) FROM KibanaSampleDataEcommerce) AS max_price"
+                .to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
         )
+        .await;
+        assert!(matches!(query, Err(CompilationError::MemberNotFound(..))));
     }
 
     #[tokio::test]
@@ -4796,6 +6671,39 @@ limit
         )
     }
 
+    #[tokio::test]
+    async fn test_where_filter_projection_alias() {
+        let query_plan = convert_select_to_query_plan(
+            "SELECT customer_gender AS gender, COUNT(*) AS cnt
+                FROM KibanaSampleDataEcommerce
+                WHERE gender = 'female'
+                GROUP BY 1
+                ORDER BY gender DESC"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await;
+
+        let request = query_plan.as_logical_plan().find_cube_scan().request;
+        assert_eq!(
+            request.filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                operator: Some("equals".to_string()),
+                values: Some(vec!["female".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+        assert_eq!(
+            request.order,
+            Some(vec![vec![
+                "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                "desc".to_string(),
+            ]])
+        );
+    }
+
     #[tokio::test]
     async fn test_where_filter_simple() {
         let to_check = vec![
@@ -5950,6 +7858,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
         assert_eq!(
@@ -5989,6 +7898,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -6026,6 +7936,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -6057,6 +7968,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -6103,6 +8015,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -6142,6 +8055,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -6179,6 +8093,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -6228,6 +8143,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -6269,6 +8185,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -6316,6 +8233,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -7536,6 +9454,37 @@ limit
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_set_names() -> Result<(), CubeError> {
+        let (_, flags) =
+            execute_query_with_flags("SET NAMES utf8mb4".to_string(), DatabaseProtocol::MySQL)
+                .await?;
+        assert_eq!(flags, StatusFlags::SERVER_STATE_CHANGED);
+
+        let (output, _) = execute_queries_with_flags(
+            vec![
+                "SET NAMES utf8 COLLATE utf8mb3_bin".to_string(),
+                "SELECT @@character_set_client AS a, @@character_set_results AS b, @@collation_connection AS c".to_string(),
+            ],
+            DatabaseProtocol::MySQL,
+        )
+        .await?;
+        assert!(output.contains("| utf8mb3 | utf8mb3 | utf8mb3_bin |"));
+
+        let result = execute_query(
+            "SET NAMES utf8mb4 COLLATE utf8mb3_bin".to_string(),
+            DatabaseProtocol::MySQL,
+        )
+        .await;
+        assert!(result.is_err());
+
+        let output =
+            execute_query("SHOW CHARACTER SET".to_string(), DatabaseProtocol::MySQL).await?;
+        assert!(output.contains("| utf8mb4 | UTF-8 Unicode | utf8mb4_0900_ai_ci | 4      |"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_set_user() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -7566,6 +9515,24 @@ limit
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_user_introspection() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "user_introspection",
+            execute_queries_with_flags(
+                vec![
+                    "SET user = 'good_user'".to_string(),
+                    "SELECT session_user AS su, current_user AS cu, current_role() AS cr, cube_user_switched() AS switched, cube_security_context('tenant') AS tenant".to_string()
+                ],
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+            .0
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_pg_backend_pid() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -8859,6 +10826,46 @@ limit
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_has_table_privilege_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "has_table_privilege",
+            execute_query(
+                "SELECT
+                    has_table_privilege('KibanaSampleDataEcommerce', 'SELECT') a,
+                    has_table_privilege('ovr', 'public.Logs', 'INSERT, SELECT') b,
+                    has_table_privilege('pg_catalog.pg_class', 'UPDATE') c
+                "
+                .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_show_grants() -> Result<(), CubeError> {
+        let grants = execute_query("SHOW GRANTS".to_string(), DatabaseProtocol::MySQL).await?;
+        assert!(grants.contains("Grants for ovr@%"));
+        assert!(grants.contains("GRANT USAGE ON *.* TO `ovr`@`%`"));
+        assert!(grants.contains("`agentCount`"));
+        assert!(grants.contains("ON `db`.`Logs` TO `ovr`@`%`"));
+
+        let grants = execute_query(
+            "SHOW GRANTS FOR CURRENT_USER".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await?;
+        assert!(grants.contains("Grants for ovr"));
+        assert!(grants.contains("GRANT USAGE ON SCHEMA \"public\" TO \"ovr\""));
+        assert!(grants.contains("ON \"public\".\"Logs\" TO \"ovr\""));
+        assert!(!grants.contains("@"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_pg_total_relation_size() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -8914,6 +10921,301 @@ limit
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_discard_all_resets_session() {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL).await;
+        let meta = get_test_tenant_ctx();
+
+        convert_sql_to_cube_query(
+            &"SET fiscal_year_start_month = 4".to_string(),
+            meta.clone(),
+            session.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(session.state.fiscal_year_start_month(), 4);
+
+        session.state.begin_transaction();
+        let result =
+            convert_sql_to_cube_query(&"DISCARD ALL".to_string(), meta.clone(), session.clone())
+                .await;
+        assert!(matches!(result, Err(CompilationError::User(..))));
+        session.state.end_transaction();
+
+        convert_sql_to_cube_query(&"DISCARD PLANS".to_string(), meta.clone(), session.clone())
+            .await
+            .unwrap();
+        assert_eq!(session.state.fiscal_year_start_month(), 4);
+
+        convert_sql_to_cube_query(&"DISCARD ALL".to_string(), meta, session.clone())
+            .await
+            .unwrap();
+        assert_eq!(session.state.fiscal_year_start_month(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_statement_classes() {
+        let session = get_test_session(DatabaseProtocol::MySQL).await;
+        let meta = get_test_tenant_ctx();
+
+        for (query, class) in [
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce",
+                StatementClass::Grouped,
+            ),
+            (
+                "SELECT taxful_total_price FROM KibanaSampleDataEcommerce ORDER BY taxful_total_price",
+                StatementClass::Ungrouped,
+            ),
+            (
+                "SELECT * FROM information_schema.tables",
+                StatementClass::Introspection,
+            ),
+            ("SHOW TABLES", StatementClass::Metadata),
+            ("SET autocommit = 1", StatementClass::Utility),
+        ] {
+            convert_sql_to_cube_query(&query.to_string(), meta.clone(), session.clone())
+                .await
+                .unwrap();
+            assert_eq!(session.state.last_statement_class(), Some(class), "{}", query);
+        }
+
+        let statements = session
+            .server
+            .statement_classes
+            .snapshot()
+            .into_iter()
+            .map(|usage| usage.statements)
+            .collect::<Vec<_>>();
+        assert_eq!(statements, vec![1, 1, 1, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_statement_timeout_load_meta() {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL).await;
+        let meta = get_test_tenant_ctx();
+        assert_eq!(session.state.statement_timeout(), None);
+        assert_eq!(session.state.get_load_request_meta().timeout_ms(), None);
+
+        for (value, expected) in [
+            ("5000", Some(5000)),
+            ("'30s'", Some(30000)),
+            ("'2min'", Some(120000)),
+            ("0", None),
+            ("'5 days'", None),
+        ] {
+            convert_sql_to_cube_query(
+                &format!("SET statement_timeout = {}", value),
+                meta.clone(),
+                session.clone(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                session.state.get_load_request_meta().timeout_ms(),
+                expected,
+                "statement_timeout = {}",
+                value
+            );
+        }
+
+        // Remaining budget is sent with loads, expired statements are not loaded at all
+        let mut load_meta = session.state.get_load_request_meta();
+        load_meta.set_statement_timeout(Some(std::time::Duration::from_secs(60)));
+        load_meta.update_timeout().unwrap();
+        assert!(load_meta.timeout_ms().unwrap() <= 60000);
+
+        load_meta.set_statement_timeout(Some(std::time::Duration::ZERO));
+        assert!(load_meta.update_timeout().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_explain_format_json() {
+        init_logger();
+
+        let plan = convert_sql_to_cube_query(
+            &"EXPLAIN (FORMAT JSON) SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1 LIMIT 10".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await
+        .unwrap();
+        let explain = match plan {
+            QueryPlan::MetaTabular(_, frame) => match frame.get_rows()[0].values()[0].clone() {
+                dataframe::TableValue::String(explain) => explain,
+                value => panic!("Unexpected plan: {:?}", value),
+            },
+            _ => panic!("EXPLAIN (FORMAT JSON) must return a table"),
+        };
+        let explain: serde_json::Value = serde_json::from_str(&explain).unwrap();
+
+        let cube_scan = &explain["cube_scans"][0];
+        assert_eq!(
+            cube_scan["request"]["dimensions"],
+            json!(["KibanaSampleDataEcommerce.customer_gender"])
+        );
+        assert_eq!(
+            cube_scan["request"]["measures"],
+            json!(["KibanaSampleDataEcommerce.count"])
+        );
+        assert_eq!(cube_scan["pushdown"]["limit"], json!(10));
+        assert_eq!(explain["estimated_rows"], json!(10));
+        assert!(explain["plan"]["node"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_stale_meta_policy() {
+        let query = "SELECT COUNT(*) FROM KibanaSampleDataEcommerce".to_string();
+        let stale_meta = Arc::new(
+            get_test_tenant_ctx()
+                .with_refresh_failure(SystemTime::now() - std::time::Duration::from_secs(120)),
+        );
+
+        let mut config = ConfigObjImpl::default();
+        config.meta_stale_threshold_secs = 60;
+        let session =
+            get_test_session_with_config(DatabaseProtocol::PostgreSQL, Arc::new(config.clone()))
+                .await;
+        let err = convert_sql_to_cube_query(&query, stale_meta.clone(), session.clone())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Schema may be stale"));
+        // Session statements are not affected
+        convert_sql_to_cube_query(
+            &"SET fiscal_year_start_month = 4".to_string(),
+            stale_meta.clone(),
+            session,
+        )
+        .await
+        .unwrap();
+
+        config.meta_stale_policy = MetaStalePolicy::Notice;
+        let session =
+            get_test_session_with_config(DatabaseProtocol::PostgreSQL, Arc::new(config)).await;
+        convert_sql_to_cube_query(&query, stale_meta, session.clone())
+            .await
+            .unwrap();
+        assert_eq!(session.state.warnings().len(), 1);
+        assert!(session.state.warnings()[0].starts_with("Schema may be stale"));
+    }
+
+    #[tokio::test]
+    async fn test_use_mapped_database() {
+        let mut config = ConfigObjImpl::default();
+        config.databases = DatabaseMapping {
+            databases: vec![
+                MappedDatabase {
+                    name: "prod".to_string(),
+                    base_path: None,
+                },
+                MappedDatabase {
+                    name: "staging".to_string(),
+                    base_path: Some("http://staging:4000/cubejs-api".to_string()),
+                },
+            ],
+            strict: true,
+        };
+        let session = get_test_session_with_config(DatabaseProtocol::MySQL, Arc::new(config)).await;
+        let meta = get_test_tenant_ctx();
+
+        let base_path = |session: &Arc<Session>| {
+            let auth_context = session.state.auth_context().unwrap();
+            let http_ctx = auth_context
+                .as_any()
+                .downcast_ref::<HttpAuthContext>()
+                .unwrap();
+            http_ctx.base_path.clone()
+        };
+
+        match convert_sql_to_cube_query(
+            &"SHOW DATABASES".to_string(),
+            meta.clone(),
+            session.clone(),
+        )
+        .await
+        .unwrap()
+        {
+            QueryPlan::MetaTabular(_, frame) => assert_eq!(
+                frame
+                    .get_rows()
+                    .iter()
+                    .map(|row| row.values()[0].to_string())
+                    .collect::<Vec<_>>(),
+                vec![
+                    "prod",
+                    "staging",
+                    "information_schema",
+                    "mysql",
+                    "performance_schema",
+                    "sys"
+                ]
+            ),
+            _ => panic!("SHOW DATABASES must return MetaTabular"),
+        }
+
+        convert_sql_to_cube_query(&"USE staging".to_string(), meta.clone(), session.clone())
+            .await
+            .unwrap();
+        assert_eq!(session.state.database(), Some("staging".to_string()));
+        assert_eq!(base_path(&session), "http://staging:4000/cubejs-api");
+
+        convert_sql_to_cube_query(
+            &"USE information_schema".to_string(),
+            meta.clone(),
+            session.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(base_path(&session), "http://staging:4000/cubejs-api");
+
+        convert_sql_to_cube_query(&"USE prod".to_string(), meta.clone(), session.clone())
+            .await
+            .unwrap();
+        assert_eq!(base_path(&session), "base_path");
+
+        let result =
+            convert_sql_to_cube_query(&"USE unknown".to_string(), meta, session.clone()).await;
+        assert!(matches!(result, Err(CompilationError::User(..))));
+        assert_eq!(session.state.database(), Some("prod".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_use_unmapped_database() {
+        // Without CUBESQL_DATABASES any database can be used, as before the mapping
+        let session = get_test_session(DatabaseProtocol::MySQL).await;
+        let meta = get_test_tenant_ctx();
+
+        match convert_sql_to_cube_query(
+            &"SHOW DATABASES".to_string(),
+            meta.clone(),
+            session.clone(),
+        )
+        .await
+        .unwrap()
+        {
+            QueryPlan::MetaTabular(_, frame) => assert_eq!(
+                frame
+                    .get_rows()
+                    .iter()
+                    .map(|row| row.values()[0].to_string())
+                    .collect::<Vec<_>>(),
+                vec![
+                    "db",
+                    "information_schema",
+                    "mysql",
+                    "performance_schema",
+                    "sys"
+                ]
+            ),
+            _ => panic!("SHOW DATABASES must return MetaTabular"),
+        }
+
+        convert_sql_to_cube_query(&"USE analytics".to_string(), meta, session.clone())
+            .await
+            .unwrap();
+        assert_eq!(session.state.database(), Some("analytics".to_string()));
+    }
+
     #[tokio::test]
     async fn test_interval_mul() -> Result<(), CubeError> {
         let base_timestamp = "TO_TIMESTAMP('2020-01-01 00:00:00', 'yyyy-MM-dd HH24:mi:ss')";
@@ -10327,6 +12629,50 @@ limit
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_compatibility_udfs() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT \
+                current_setting('TimeZone') tz, \
+                current_setting('unknown_setting', true) missing, \
+                pg_is_in_recovery() recovery, \
+                pg_client_encoding() enc, \
+                pg_sleep(0) slept, \
+                pg_postmaster_start_time() = pg_conf_load_time() started"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+-----+---------+----------+------+-------+---------+\n\
+            | tz  | missing | recovery | enc  | slept | started |\n\
+            +-----+---------+----------+------+-------+---------+\n\
+            | GMT | NULL    | false    | UTF8 | NULL  | true    |\n\
+            +-----+---------+----------+------+-------+---------+"
+        );
+
+        assert_eq!(
+            execute_query("SELECT sleep(0) s".to_string(), DatabaseProtocol::MySQL).await?,
+            "+---+\n\
+            | s |\n\
+            +---+\n\
+            | 0 |\n\
+            +---+"
+        );
+
+        // Durations which can't be represented are rejected instead of a panic
+        for secs in ["'Infinity'::float8", "'NaN'::float8", "-'Infinity'::float8"] {
+            assert!(execute_query(
+                format!("SELECT pg_sleep({}) slept", secs),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await
+            .is_err());
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_quote_ident() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -10451,6 +12797,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -10492,6 +12839,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -10562,6 +12910,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -10604,6 +12953,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             );
         }
@@ -10679,6 +13029,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -12003,6 +14354,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -12050,6 +14402,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -12175,6 +14528,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -12223,12 +14577,63 @@ limit
                     }
                 ]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_relative_date_range_filter() {
+        init_logger();
+
+        for (filter, date_range) in [
+            (
+                "order_date >= now() + INTERVAL '-30 day'",
+                "from 30 days ago to now",
+            ),
+            (
+                "order_date > now() - INTERVAL '1 month'",
+                "from 1 month ago to now",
+            ),
+            (
+                "order_date < now() + INTERVAL '2 hour'",
+                "from now to 2 hours from now",
+            ),
+        ] {
+            let logical_plan = convert_select_to_query_plan(
+                format!(
+                    "SELECT COUNT(*) FROM KibanaSampleDataEcommerce WHERE {}",
+                    filter
+                ),
+                DatabaseProtocol::PostgreSQL,
+            )
+            .await
+            .as_logical_plan();
+
+            assert_eq!(
+                logical_plan.find_cube_scan().request,
+                V1LoadRequestQuery {
+                    measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string()]),
+                    dimensions: Some(vec![]),
+                    segments: Some(vec![]),
+                    time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
+                        dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                        granularity: None,
+                        date_range: Some(json!(date_range)),
+                    }]),
+                    order: None,
+                    limit: None,
+                    offset: None,
+                    filters: None,
+                    ungrouped: None,
+                    ..Default::default()
+                }
+            )
+        }
+    }
+
     #[tokio::test]
     async fn metabase_interval_date_range_filter() {
         let logical_plan = convert_select_to_query_plan(
@@ -12269,6 +14674,7 @@ limit
                     and: None,
                 },]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -12321,6 +14727,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -12353,6 +14760,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -12381,6 +14789,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -12410,6 +14819,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -12487,6 +14897,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             );
         }
@@ -12525,6 +14936,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -12587,6 +14999,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             );
         }
@@ -12625,6 +15038,44 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_date_trunc_between_to_date_range() {
+        init_logger();
+
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT SUM(count) FROM KibanaSampleDataEcommerce
+            WHERE date_trunc('month', order_date) BETWEEN to_date('2020-02-10', 'yyyy-MM-dd') AND to_date('2020-04-01', 'yyyy-MM-dd')"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await
+        .as_logical_plan();
+
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string()]),
+                dimensions: Some(vec![]),
+                segments: Some(vec![]),
+                time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
+                    dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                    granularity: None,
+                    date_range: Some(json!(vec![
+                        "2020-03-01T00:00:00.000Z".to_string(),
+                        "2020-04-30T23:59:59.999Z".to_string(),
+                    ])),
+                }]),
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -12655,6 +15106,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -12695,6 +15147,7 @@ limit
                     and: None,
                 },]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -12742,6 +15195,7 @@ limit
                     and: None,
                 },]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
     }
@@ -12791,6 +15245,7 @@ limit
                     }
                 ]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -12842,6 +15297,7 @@ limit
                     and: None,
                 },]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
     }
@@ -12878,6 +15334,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -12975,6 +15432,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 }
             )
         }
@@ -13017,6 +15475,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 }
             )
         }
@@ -13063,6 +15522,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -13109,6 +15569,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -13155,6 +15616,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 }
             )
         }
@@ -13185,6 +15647,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -13220,6 +15683,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -13268,6 +15732,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -13299,6 +15764,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -13441,9 +15907,31 @@ limit
     async fn test_superset_pagination() {
         init_logger();
 
-        // At first, Superset gets the total count (no more than 50k)
+        // At first, Superset gets the total count (no more than 50k)
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT COUNT(*) AS rowcount FROM (SELECT order_date as order_date FROM public.\"KibanaSampleDataEcommerce\" GROUP BY order_date LIMIT 50000) AS rowcount_qry".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        ).await.as_logical_plan();
+
+        let cube_scan = logical_plan.find_cube_scan();
+        assert_eq!(
+            cube_scan.request,
+            V1LoadRequestQuery {
+                measures: Some(vec![]),
+                dimensions: Some(vec!["KibanaSampleDataEcommerce.order_date".to_string()]),
+                segments: Some(vec![]),
+                time_dimensions: None,
+                order: None,
+                limit: Some(50000),
+                offset: None,
+                filters: None,
+                ungrouped: None,
+                ..Default::default()
+            }
+        );
+
         let logical_plan = convert_select_to_query_plan(
-            "SELECT COUNT(*) AS rowcount FROM (SELECT order_date as order_date FROM public.\"KibanaSampleDataEcommerce\" GROUP BY order_date LIMIT 50000) AS rowcount_qry".to_string(),
+            "SELECT order_date AS order_date FROM public.\"KibanaSampleDataEcommerce\" GROUP BY order_date LIMIT 200 OFFSET 200".to_string(),
             DatabaseProtocol::PostgreSQL,
         ).await.as_logical_plan();
 
@@ -13456,15 +15944,16 @@ limit
                 segments: Some(vec![]),
                 time_dimensions: None,
                 order: None,
-                limit: Some(50000),
-                offset: None,
+                limit: Some(200),
+                offset: Some(200),
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
         let logical_plan = convert_select_to_query_plan(
-            "SELECT order_date AS order_date FROM public.\"KibanaSampleDataEcommerce\" GROUP BY order_date LIMIT 200 OFFSET 200".to_string(),
+            "SELECT order_date AS order_date FROM public.\"KibanaSampleDataEcommerce\" GROUP BY order_date OFFSET 200 ROWS FETCH FIRST 100 ROWS ONLY".to_string(),
             DatabaseProtocol::PostgreSQL,
         ).await.as_logical_plan();
 
@@ -13477,10 +15966,11 @@ limit
                 segments: Some(vec![]),
                 time_dimensions: None,
                 order: None,
-                limit: Some(200),
+                limit: Some(100),
                 offset: Some(200),
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -13626,6 +16116,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 }
             );
         }
@@ -13672,6 +16163,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -13710,6 +16202,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -13753,6 +16246,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -13818,6 +16312,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }),
             true
         );
@@ -13837,6 +16332,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }),
             true
         );
@@ -13900,6 +16396,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }),
             true
         );
@@ -13922,6 +16419,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }),
             true
         );
@@ -13968,6 +16466,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -14033,6 +16532,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14120,6 +16620,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 }
             )
         }
@@ -14163,6 +16664,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14205,6 +16707,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14251,6 +16754,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14307,6 +16811,7 @@ limit
                     },
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14352,6 +16857,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14397,6 +16903,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14446,6 +16953,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14505,6 +17013,7 @@ limit
                     },
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14547,6 +17056,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14595,6 +17105,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14653,6 +17164,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14689,6 +17201,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -14723,6 +17236,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -14757,6 +17271,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -14821,6 +17336,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             },
         );
     }
@@ -14869,6 +17385,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -14923,6 +17440,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -15077,6 +17595,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -15124,6 +17643,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -15182,6 +17702,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -15242,6 +17763,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -15302,12 +17824,70 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_text_search_filters() -> Result<(), CubeError> {
+        init_logger();
+
+        let query_plan = convert_select_to_query_plan(
+            r#"
+            SELECT customer_gender
+            FROM KibanaSampleDataEcommerce
+            WHERE
+                strpos(customer_gender, 'el') = 0 AND
+                strpos(customer_gender, 'fe') = 1 AND
+                starts_with(lower(customer_gender), 'f') AND
+                NOT ends_with(lower(customer_gender), 'le')
+            ;"#
+            .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await;
+
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request.filters,
+            Some(vec![
+                V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                    operator: Some("notContains".to_string()),
+                    values: Some(vec!["el".to_string()]),
+                    or: None,
+                    and: None,
+                },
+                V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                    operator: Some("startsWith".to_string()),
+                    values: Some(vec!["fe".to_string()]),
+                    or: None,
+                    and: None,
+                },
+                V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                    operator: Some("startsWith".to_string()),
+                    values: Some(vec!["f".to_string()]),
+                    or: None,
+                    and: None,
+                },
+                V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                    operator: Some("notEndsWith".to_string()),
+                    values: Some(vec!["le".to_string()]),
+                    or: None,
+                    and: None,
+                },
+            ])
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_sigma_str_not_starts_with() -> Result<(), CubeError> {
         if !Rewriter::sql_push_down_enabled() {
@@ -15362,6 +17942,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -15422,6 +18003,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -15482,6 +18064,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         );
 
@@ -15585,6 +18168,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -15650,6 +18234,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -15704,6 +18289,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             )
         }
@@ -15741,6 +18327,7 @@ limit
                     offset: None,
                     filters: None,
                     ungrouped: None,
+                    ..Default::default()
                 }
             )
         }
@@ -15781,6 +18368,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -15836,6 +18424,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -15884,6 +18473,7 @@ limit
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -15936,6 +18526,7 @@ limit
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -15993,6 +18584,7 @@ limit
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16045,6 +18637,7 @@ limit
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16102,6 +18695,7 @@ limit
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16165,6 +18759,7 @@ limit
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16284,6 +18879,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }),
             true
         );
@@ -16299,6 +18895,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }),
             true
         )
@@ -16346,6 +18943,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }),
             true
         );
@@ -16361,6 +18959,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }),
             true
         )
@@ -16404,6 +19003,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -16456,6 +19056,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -16519,6 +19120,7 @@ limit
                     and: None
                 },]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16577,6 +19179,7 @@ limit
                     and: None
                 },]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16627,6 +19230,7 @@ limit
                     and: None
                 },]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16658,6 +19262,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16689,6 +19294,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16745,6 +19351,7 @@ limit
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -16796,6 +19403,7 @@ limit
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -16847,6 +19455,7 @@ limit
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16924,6 +19533,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -16984,6 +19594,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17018,6 +19629,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -17059,6 +19671,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -17099,6 +19712,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17144,6 +19758,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -17188,6 +19803,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17228,6 +19844,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17269,6 +19886,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17332,6 +19950,7 @@ limit
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17375,6 +19994,7 @@ limit
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17413,6 +20033,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17450,6 +20071,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17487,6 +20109,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17531,6 +20154,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17575,6 +20199,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17611,6 +20236,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -17645,6 +20271,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -17694,6 +20321,7 @@ limit
                     }
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17729,6 +20357,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17769,6 +20398,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -17804,6 +20434,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17848,6 +20479,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17892,6 +20524,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
 
@@ -17933,6 +20566,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -17987,6 +20621,7 @@ limit
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18023,6 +20658,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18067,6 +20703,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18113,6 +20750,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18163,6 +20801,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18203,6 +20842,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18248,6 +20888,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18303,6 +20944,7 @@ limit
                     },
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18420,6 +21062,7 @@ limit
                     },
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18462,6 +21105,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18503,6 +21147,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18545,6 +21190,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18636,6 +21282,7 @@ limit
                         and: None
                     }]),
                     ungrouped: Some(true),
+                    ..Default::default()
                 }
             );
         }
@@ -18674,6 +21321,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18715,6 +21363,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -18756,6 +21405,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: Some(true),
+                ..Default::default()
             }
         )
     }
@@ -18794,6 +21444,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18862,6 +21513,7 @@ limit
             offset: None,
             filters: None,
             ungrouped: Some(true),
+            ..Default::default()
         }))
     }
 
@@ -18942,6 +21594,7 @@ limit
                     and: None
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -18983,6 +21636,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -19030,6 +21684,133 @@ limit
         );
     }
 
+    #[tokio::test]
+    async fn test_wrapper_sql_limits() {
+        if !Rewriter::sql_push_down_enabled() {
+            return;
+        }
+        init_logger();
+
+        let query = "SELECT COALESCE(customer_gender, 'N/A'), COALESCE(notes, 'none'), AVG(avgPrice) mp FROM KibanaSampleDataEcommerce a GROUP BY 1, 2";
+
+        let mut config = ConfigObjImpl::default();
+        config.wrapper_max_params = 1;
+        let result = convert_sql_to_cube_query(
+            &query.to_string(),
+            get_test_tenant_ctx(),
+            get_test_session_with_config(DatabaseProtocol::PostgreSQL, Arc::new(config.clone()))
+                .await,
+        )
+        .await;
+        // Literals stay bound, they are never inlined into SQL
+        match result {
+            Err(CompilationError::User(message, _)) => {
+                assert!(message.contains("CUBESQL_WRAPPER_MAX_PARAMS"))
+            }
+            other => panic!("Expected params limit error, actual: {:?}", other),
+        }
+
+        config.wrapper_max_params = 0;
+        config.wrapper_max_sql_length = 64;
+        let result = convert_sql_to_cube_query(
+            &query.to_string(),
+            get_test_tenant_ctx(),
+            get_test_session_with_config(DatabaseProtocol::PostgreSQL, Arc::new(config)).await,
+        )
+        .await;
+        match result {
+            Err(CompilationError::User(message, _)) => {
+                assert!(message.contains("CUBESQL_WRAPPER_MAX_SQL_LENGTH"))
+            }
+            other => panic!("Expected SQL size error, actual: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tablesample_wrapper() {
+        init_logger();
+
+        let query = "SELECT customer_gender FROM KibanaSampleDataEcommerce TABLESAMPLE (10 ROWS)";
+        if !Rewriter::sql_push_down_enabled() {
+            // Sampling would load the whole cube
+            let result = convert_sql_to_cube_query(
+                &query.to_string(),
+                get_test_tenant_ctx(),
+                get_test_session(DatabaseProtocol::PostgreSQL).await,
+            )
+            .await;
+            match result {
+                Err(CompilationError::Unsupported(message, _)) => {
+                    assert!(message.contains("CUBESQL_SQL_PUSH_DOWN"))
+                }
+                other => panic!("Expected TABLESAMPLE to be rejected, actual: {:?}", other),
+            }
+
+            return;
+        }
+
+        let query_plan =
+            convert_select_to_query_plan(query.to_string(), DatabaseProtocol::PostgreSQL).await;
+
+        let logical_plan = query_plan.as_logical_plan();
+        let sql = logical_plan
+            .find_cube_scan_wrapper()
+            .wrapped_sql
+            .unwrap()
+            .sql;
+        assert!(sql.contains("RANDOM()"));
+        assert!(sql.contains("10"));
+    }
+
+    #[tokio::test]
+    async fn test_pushdown_hints() {
+        init_logger();
+
+        let query_plan = convert_select_to_query_plan(
+            "SELECT /*+ cube_pushdown */ COALESCE(customer_gender, 'N/A') FROM KibanaSampleDataEcommerce"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await;
+        assert!(query_plan
+            .as_logical_plan()
+            .find_cube_scan_wrapper()
+            .wrapped_sql
+            .unwrap()
+            .sql
+            .contains("COALESCE"));
+
+        let query_plan = convert_select_to_query_plan(
+            "SELECT /*+ cube_no_pushdown */ COALESCE(customer_gender, 'N/A') FROM KibanaSampleDataEcommerce"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await;
+        assert!(!query_plan
+            .as_logical_plan()
+            .display_indent()
+            .to_string()
+            .contains("CubeScanWrapper"));
+    }
+
+    #[tokio::test]
+    async fn test_explain_source_requires_pushdown() {
+        init_logger();
+
+        let err = convert_sql_to_cube_query(
+            &"EXPLAIN (SOURCE) SELECT /*+ cube_no_pushdown */ customer_gender FROM KibanaSampleDataEcommerce"
+                .to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.message(),
+            "EXPLAIN (SOURCE) is supported only for queries pushed down to the data source"
+        );
+    }
+
     #[tokio::test]
     async fn test_case_wrapper() {
         if !Rewriter::sql_push_down_enabled() {
@@ -19059,6 +21840,30 @@ limit
         );
     }
 
+    #[tokio::test]
+    async fn test_bucket_wrapper() {
+        if !Rewriter::sql_push_down_enabled() {
+            return;
+        }
+        init_logger();
+
+        let query_plan = convert_select_to_query_plan(
+            "SELECT bucket(taxful_total_price, 50, 100) AS tier, AVG(avgPrice) mp FROM KibanaSampleDataEcommerce a GROUP BY 1"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await;
+
+        let logical_plan = query_plan.as_logical_plan();
+        let sql = logical_plan
+            .find_cube_scan_wrapper()
+            .wrapped_sql
+            .unwrap()
+            .sql;
+        assert!(sql.contains("CASE WHEN"));
+        assert!(sql.contains("GROUP BY"));
+    }
+
     #[tokio::test]
     async fn test_case_wrapper_alias_with_order() {
         if !Rewriter::sql_push_down_enabled() {
@@ -19721,6 +22526,7 @@ limit
                     },
                 ]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -19768,6 +22574,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -19806,10 +22613,62 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
 
+    #[tokio::test]
+    async fn test_date_trunc_at_time_zone() {
+        init_logger();
+
+        let request = |sql: &'static str| async move {
+            convert_select_to_query_plan(sql.to_string(), DatabaseProtocol::PostgreSQL)
+                .await
+                .as_logical_plan()
+                .find_cube_scan()
+                .request
+        };
+        let expected = |timezone: Option<&str>| V1LoadRequestQuery {
+            measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string()]),
+            dimensions: Some(vec![]),
+            segments: Some(vec![]),
+            time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
+                dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                granularity: Some("day".to_string()),
+                date_range: None,
+            }]),
+            order: None,
+            limit: None,
+            offset: None,
+            filters: None,
+            ungrouped: None,
+            timezone: timezone.map(|timezone| timezone.to_string()),
+        };
+
+        assert_eq!(
+            request(
+                "SELECT date_trunc('day', order_date AT TIME ZONE 'UTC') AS d, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1"
+            )
+            .await,
+            expected(None)
+        );
+        assert_eq!(
+            request(
+                "SELECT date_trunc('day', timezone('America/New_York', order_date)) AS d, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1"
+            )
+            .await,
+            expected(Some("America/New_York"))
+        );
+        assert_eq!(
+            request(
+                "SELECT date_trunc('day', order_date AT TIME ZONE 'UTC' AT TIME ZONE 'America/New_York') AS d, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1"
+            )
+            .await,
+            expected(Some("America/New_York"))
+        );
+    }
+
     #[tokio::test]
     async fn test_thoughtspot_pg_extract_month_of_quarter() {
         init_logger();
@@ -19847,6 +22706,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -19888,6 +22748,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -19929,6 +22790,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -19970,6 +22832,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -20018,6 +22881,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -20067,6 +22931,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -20130,6 +22995,7 @@ limit
                     and: None,
                 }]),
                 ungrouped: None,
+                ..Default::default()
             }
         )
     }
@@ -20185,6 +23051,7 @@ limit
                 offset: None,
                 filters: None,
                 ungrouped: None,
+                ..Default::default()
             }
         );
     }
@@ -20703,4 +23570,124 @@ limit
             .sql
             .contains("DATE("));
     }
+
+    #[tokio::test]
+    async fn test_wrapper_member_aliases_of_bi_tools() {
+        if !Rewriter::sql_push_down_enabled() {
+            return;
+        }
+        init_logger();
+
+        let queries = [
+            // Tableau quotes calculations with long aliases
+            "SELECT CONCAT(DATE(\"KibanaSampleDataEcommerce\".\"order_date\"), '-') AS \"Calculation_1234567890123456789:Order Date (Day) Label:nk\"
+            FROM \"public\".\"KibanaSampleDataEcommerce\" \"KibanaSampleDataEcommerce\"
+            GROUP BY 1",
+            // Power BI wraps the query and aliases columns as C1, C2...
+            "SELECT \"_\".\"d\" AS \"C1\"
+            FROM (
+                SELECT CONCAT(DATE(order_date), '-') AS \"d\"
+                FROM KibanaSampleDataEcommerce AS k
+                GROUP BY 1
+            ) \"_\"",
+        ];
+        for query in queries {
+            let logical_plan =
+                convert_select_to_query_plan(query.to_string(), DatabaseProtocol::PostgreSQL)
+                    .await
+                    .as_logical_plan();
+
+            let wrapper = logical_plan.find_cube_scan_wrapper();
+            let sql = wrapper.wrapped_sql.as_ref().unwrap().sql.clone();
+            let member_fields = wrapper.member_fields.clone().unwrap();
+            assert_eq!(member_fields.len(), logical_plan.schema().fields().len());
+            for member_field in member_fields {
+                match member_field {
+                    MemberField::Member(alias) => assert!(
+                        sql.contains(&alias),
+                        "Alias {} is not found in SQL: {}",
+                        alias,
+                        sql
+                    ),
+                    MemberField::Literal(value) => panic!("Unexpected literal: {:?}", value),
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_intersect_except_cube_scans() {
+        init_logger();
+
+        for (op, join_type) in [("INTERSECT", JoinType::Semi), ("EXCEPT", JoinType::Anti)] {
+            let logical_plan = convert_select_to_query_plan(
+                format!(
+                    "SELECT customer_gender FROM KibanaSampleDataEcommerce WHERE notes = 'a'
+                    {}
+                    SELECT customer_gender FROM KibanaSampleDataEcommerce WHERE notes = 'b'
+                    ORDER BY 1",
+                    op
+                ),
+                DatabaseProtocol::PostgreSQL,
+            )
+            .await
+            .as_logical_plan();
+
+            let join = match &logical_plan {
+                LogicalPlan::Sort(Sort { input, .. }) => match input.as_ref() {
+                    LogicalPlan::Join(join) => join.clone(),
+                    plan => panic!("Expected join, got: {:?}", plan),
+                },
+                plan => panic!("Expected sort, got: {:?}", plan),
+            };
+            assert_eq!(join.join_type, join_type);
+
+            let cube_scans = logical_plan
+                .find_cube_scans()
+                .iter()
+                .map(|cube| cube.request.dimensions.clone())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                cube_scans,
+                vec![Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]); 2]
+            );
+        }
+
+        let query = convert_sql_to_cube_query(
+            &"SELECT customer_gender FROM KibanaSampleDataEcommerce INTERSECT ALL SELECT customer_gender FROM KibanaSampleDataEcommerce".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await;
+        assert!(query.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_union_coerce_cube_scans() {
+        init_logger();
+
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT COUNT(*) AS value FROM KibanaSampleDataEcommerce
+            UNION ALL
+            SELECT AVG(avgPrice) AS value FROM KibanaSampleDataEcommerce"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await
+        .as_logical_plan();
+
+        assert_eq!(
+            logical_plan.schema().field(0).data_type(),
+            &DataType::Float64
+        );
+        assert_eq!(logical_plan.find_cube_scans().len(), 2);
+
+        let query = convert_sql_to_cube_query(
+            &"SELECT customer_gender FROM KibanaSampleDataEcommerce INTERSECT SELECT COUNT(*) FROM KibanaSampleDataEcommerce".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL).await,
+        )
+        .await;
+        assert!(query.is_err());
+    }
 }
@@ -2,14 +2,18 @@ use std::collections::HashMap;
 
 use regex::Regex;
 use sqlparser::{
-    ast::Statement,
+    ast::{Ident, SetExpr, Statement, TableFactor, TableWithJoins},
     dialect::{Dialect, PostgreSqlDialect},
-    parser::Parser,
+    parser::{Parser, ParserError},
+    tokenizer::{Token, Tokenizer},
 };
 
 use crate::{
-    compile::{qtrace::Qtrace, CompilationError},
-    sql::session::DatabaseProtocol,
+    compile::{qtrace::Qtrace, rewrite::rewriter::Rewriter, CompilationError},
+    sql::{
+        session::DatabaseProtocol,
+        statement::{GroupByAllReplacer, TableSampleReplacer},
+    },
 };
 
 use super::CompilationResult;
@@ -41,6 +45,306 @@ impl Dialect for MySqlDialectWithBackTicks {
 
 lazy_static! {
     static ref SIGMA_WORKAROUND: Regex = Regex::new(r#"(?s)^\s*with\s+nsp\sas\s\(.*nspname\s=\s.*\),\s+tbl\sas\s\(.*relname\s=\s.*\).*select\s+attname.*from\spg_attribute.*$"#).unwrap();
+    static ref PUSHDOWN_HINT: Regex =
+        Regex::new(r#"(?i)/\*\+\s*(?P<hint>cube_pushdown|cube_no_pushdown)\s*\*/"#).unwrap();
+    static ref GROUP_BY_ALL: Regex = Regex::new(r#"(?i)\bGROUP\s+BY\s+ALL\b"#).unwrap();
+    static ref EXPLAIN_OPTION: Regex = Regex::new(
+        r#"(?is)^\s*EXPLAIN\s*\(\s*(?P<option>SOURCE|FORMAT\s+JSON)\s*\)\s*(?P<query>.*?)\s*;?\s*$"#
+    )
+    .unwrap();
+}
+
+/// Alias of the subquery which marks `EXPLAIN (SOURCE)` statements after the rewrite
+pub const EXPLAIN_SOURCE_ALIAS: &str = "__cube_explain_source";
+/// Alias of the subquery which marks `EXPLAIN (FORMAT JSON)` statements after the rewrite
+pub const EXPLAIN_JSON_ALIAS: &str = "__cube_explain_json";
+
+/// Identifier which marks `GROUP BY ALL` after the rewrite, it's expanded by GroupByAllReplacer
+pub const GROUP_BY_ALL_MARKER: &str = "__cube_group_by_all";
+
+/// Sampling clause of a table, it's applied to the parsed statements by TableSampleReplacer
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableSample {
+    /// `TABLESAMPLE BERNOULLI | SYSTEM (<percent>)` keeps every row with the given probability
+    Fraction(f64),
+    /// `TABLESAMPLE (<n> ROWS)` takes n random rows
+    Rows(u64),
+}
+
+fn is_word(token: Option<&Token>, value: &str) -> bool {
+    matches!(token, Some(Token::Word(word)) if word.quote_style.is_none() && word.value.eq_ignore_ascii_case(value))
+}
+
+/// Sampling is not supported by the parser: TABLESAMPLE clauses are removed from tokens,
+/// every clause is returned with the alias or name of the table which it follows
+fn extract_tablesamples(
+    tokens: Vec<Token>,
+) -> CompilationResult<(Vec<Token>, Vec<(Ident, TableSample)>)> {
+    // Index of the next token which is not whitespace
+    let next = |from: usize| {
+        (from..tokens.len())
+            .find(|idx| !matches!(tokens[*idx], Token::Whitespace(_)))
+            .unwrap_or(tokens.len())
+    };
+    let invalid = || CompilationError::user("Invalid TABLESAMPLE clause".to_string());
+
+    let mut result = Vec::with_capacity(tokens.len());
+    let mut samples = vec![];
+    let mut idx = 0;
+    while idx < tokens.len() {
+        let mut pos = next(idx + 1);
+        let method = is_word(tokens.get(pos), "BERNOULLI") || is_word(tokens.get(pos), "SYSTEM");
+        if method {
+            pos = next(pos + 1);
+        }
+        // Column or alias which is named tablesample
+        if !is_word(tokens.get(idx), "TABLESAMPLE") || tokens.get(pos) != Some(&Token::LParen) {
+            result.push(tokens[idx].clone());
+            idx += 1;
+            continue;
+        }
+
+        let table = match result
+            .iter()
+            .rev()
+            .find(|token| !matches!(token, Token::Whitespace(_)))
+        {
+            Some(Token::Word(word)) => Ident {
+                value: word.value.clone(),
+                quote_style: word.quote_style,
+            },
+            _ => {
+                return Err(CompilationError::user(
+                    "TABLESAMPLE must follow a table name".to_string(),
+                ))
+            }
+        };
+
+        pos = next(pos + 1);
+        let size = match tokens.get(pos) {
+            Some(Token::Number(size, _)) => size.clone(),
+            _ => return Err(invalid()),
+        };
+        pos = next(pos + 1);
+        let rows = is_word(tokens.get(pos), "ROWS");
+        if rows {
+            pos = next(pos + 1);
+        }
+        if tokens.get(pos) != Some(&Token::RParen) {
+            return Err(invalid());
+        }
+        if is_word(tokens.get(next(pos + 1)), "REPEATABLE") {
+            return Err(CompilationError::unsupported(
+                "TABLESAMPLE with REPEATABLE is not supported".to_string(),
+            ));
+        }
+
+        let sample = if rows {
+            match size.parse::<u64>() {
+                Ok(rows) if !method => TableSample::Rows(rows),
+                _ => {
+                    return Err(CompilationError::user(format!(
+                        "Invalid TABLESAMPLE row count: {}",
+                        size
+                    )))
+                }
+            }
+        } else {
+            match size.parse::<f64>() {
+                Ok(percent) if (0.0..=100.0).contains(&percent) => {
+                    TableSample::Fraction(percent / 100.0)
+                }
+                _ => {
+                    return Err(CompilationError::user(format!(
+                        "TABLESAMPLE percentage must be between 0 and 100, got {}",
+                        size
+                    )))
+                }
+            }
+        };
+        samples.push((table, sample));
+        idx = pos + 1;
+    }
+
+    Ok((result, samples))
+}
+
+/// Same as Parser::parse_sql, but for tokens
+fn parse_tokens(dialect: &dyn Dialect, tokens: Vec<Token>) -> Result<Vec<Statement>, ParserError> {
+    let mut parser = Parser::new(tokens, dialect);
+    let mut statements = vec![];
+    let mut expecting_statement_delimiter = false;
+    loop {
+        while parser.consume_token(&Token::SemiColon) {
+            expecting_statement_delimiter = false;
+        }
+        if parser.peek_token() == Token::EOF {
+            break;
+        }
+        if expecting_statement_delimiter {
+            return parser.expected("end of statement", parser.peek_token());
+        }
+
+        statements.push(parser.parse_statement()?);
+        expecting_statement_delimiter = true;
+    }
+
+    Ok(statements)
+}
+
+/// TABLESAMPLE is replaced with a subquery which filters or orders rows by RANDOM(). It's
+/// pushed down to the data source as wrapped SQL, without push down the whole table would be
+/// loaded to sample it, so it's rejected.
+fn parse_statements(dialect: &dyn Dialect, query: &str) -> CompilationResult<Vec<Statement>> {
+    let unable_to_parse =
+        |err: ParserError| CompilationError::user(format!("Unable to parse: {:?}", err));
+    if !query.to_lowercase().contains("tablesample") {
+        return Parser::parse_sql(dialect, query).map_err(unable_to_parse);
+    }
+
+    let tokens = match Tokenizer::new(dialect, query).tokenize() {
+        Ok(tokens) => tokens,
+        // Error is reported by the parser
+        Err(_) => return Parser::parse_sql(dialect, query).map_err(unable_to_parse),
+    };
+    let (tokens, samples) = extract_tablesamples(tokens)?;
+    if samples.is_empty() {
+        return Parser::parse_sql(dialect, query).map_err(unable_to_parse);
+    }
+    if !Rewriter::sql_push_down_enabled() {
+        return Err(CompilationError::unsupported(
+            "TABLESAMPLE requires SQL push down (CUBESQL_SQL_PUSH_DOWN=true), \
+            otherwise the whole table would be loaded to sample it"
+                .to_string(),
+        ));
+    }
+
+    let statements = parse_tokens(dialect, tokens).map_err(unable_to_parse)?;
+    TableSampleReplacer::new(samples).replace(&statements)
+}
+
+/// `GROUP BY ALL` is not supported by the parser, ALL is replaced by a marker identifier
+fn rewrite_group_by_all(query: String) -> String {
+    if !GROUP_BY_ALL.is_match(&query) {
+        return query;
+    }
+
+    GROUP_BY_ALL
+        .replace_all(&query, format!("GROUP BY {}", GROUP_BY_ALL_MARKER).as_str())
+        .to_string()
+}
+
+/// EXPLAIN options are not supported by the parser, `EXPLAIN (SOURCE) <query>` and
+/// `EXPLAIN (FORMAT JSON) <query>` are rewritten to EXPLAIN of a subquery with a well-known
+/// alias, which is recognized by the planner.
+fn rewrite_explain_source(query: String) -> String {
+    match EXPLAIN_OPTION.captures(&query) {
+        Some(caps) => {
+            let alias = if caps["option"].eq_ignore_ascii_case("source") {
+                EXPLAIN_SOURCE_ALIAS
+            } else {
+                EXPLAIN_JSON_ALIAS
+            };
+
+            format!("EXPLAIN SELECT * FROM ({}) AS {}", &caps["query"], alias)
+        }
+        None => query,
+    }
+}
+
+/// Returns the query of `EXPLAIN (SOURCE)` from the statement under EXPLAIN
+pub fn explain_source_query(statement: &Statement) -> Option<&sqlparser::ast::Query> {
+    explain_option_query(statement, EXPLAIN_SOURCE_ALIAS)
+}
+
+/// Returns the query of `EXPLAIN (FORMAT JSON)` from the statement under EXPLAIN
+pub fn explain_json_query(statement: &Statement) -> Option<&sqlparser::ast::Query> {
+    explain_option_query(statement, EXPLAIN_JSON_ALIAS)
+}
+
+fn explain_option_query<'a>(
+    statement: &'a Statement,
+    option_alias: &str,
+) -> Option<&'a sqlparser::ast::Query> {
+    let query = match statement {
+        Statement::Query(query) => query,
+        _ => return None,
+    };
+    let select = match &query.body {
+        SetExpr::Select(select) => select,
+        _ => return None,
+    };
+
+    match select.from.as_slice() {
+        [TableWithJoins {
+            relation:
+                TableFactor::Derived {
+                    subquery,
+                    alias: Some(alias),
+                    ..
+                },
+            joins,
+        }] if joins.is_empty() && alias.name.value == option_alias => Some(subquery),
+        _ => None,
+    }
+}
+
+/// Overrides the SQL push down decision for a statement, useful as a workaround
+/// when the wrapper is (or isn't) chosen by mistake.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PushdownHint {
+    /// `/*+ cube_pushdown */` enables wrapper rules even if push down is disabled
+    Force,
+    /// `/*+ cube_no_pushdown */` disables wrapper rules
+    Forbid,
+}
+
+/// Comments are dropped by the parser, so hints are read from the query text.
+/// The first hint wins when there are several of them.
+pub fn parse_pushdown_hint(query: &str) -> Option<PushdownHint> {
+    PUSHDOWN_HINT.captures(query).map(|caps| {
+        if caps["hint"].eq_ignore_ascii_case("cube_pushdown") {
+            PushdownHint::Force
+        } else {
+            PushdownHint::Forbid
+        }
+    })
+}
+
+/// `SELECT ... EMIT CHANGES` subscribes to the query, the query without the clause is returned.
+/// The clause is found in tokens, so it's not matched in comments or string literals.
+pub fn parse_emit_changes(query: &str) -> Option<String> {
+    if !query.to_lowercase().contains("emit") {
+        return None;
+    }
+
+    let query = query.trim_end();
+    let tokens = Tokenizer::new(&PostgreSqlDialect {}, query)
+        .tokenize()
+        .ok()?;
+    let mut significant = (0..tokens.len())
+        .filter(|idx| !matches!(tokens[*idx], Token::Whitespace(_)))
+        .collect::<Vec<_>>();
+    while matches!(significant.last(), Some(idx) if tokens[*idx] == Token::SemiColon) {
+        significant.pop();
+    }
+    let emit = match significant.as_slice() {
+        [.., emit, changes]
+            if is_word(tokens.get(*emit), "EMIT") && is_word(tokens.get(*changes), "CHANGES") =>
+        {
+            *emit
+        }
+        _ => return None,
+    };
+
+    // Only words, whitespace, comments and semicolons are printed back, they are kept as written
+    let clause = tokens[emit..]
+        .iter()
+        .map(|token| token.to_string())
+        .collect::<String>();
+    query
+        .strip_suffix(clause.as_str())
+        .map(|query| query.trim_end().to_string())
 }
 
 pub fn parse_sql_to_statements(
@@ -267,19 +571,33 @@ pub fn parse_sql_to_statements(
         "select NULL, NULL AS NULL2, NULL AS NULL3",
     );
 
+    let query = rewrite_explain_source(query);
+    let query = rewrite_group_by_all(query);
+
     if let Some(qtrace) = qtrace {
         qtrace.set_replaced_query(&query)
     }
 
     let parse_result = match protocol {
-        DatabaseProtocol::MySQL => Parser::parse_sql(&MySqlDialectWithBackTicks {}, query.as_str()),
-        DatabaseProtocol::PostgreSQL => Parser::parse_sql(&PostgreSqlDialect {}, query.as_str()),
+        DatabaseProtocol::MySQL => parse_statements(&MySqlDialectWithBackTicks {}, query.as_str()),
+        DatabaseProtocol::PostgreSQL => parse_statements(&PostgreSqlDialect {}, query.as_str()),
     };
 
-    parse_result.map_err(|err| {
-        CompilationError::user(format!("Unable to parse: {:?}", err))
-            .with_meta(Some(HashMap::from([("query".to_string(), original_query)])))
-    })
+    let statements = parse_result.map_err(|err| {
+        err.with_meta(Some(HashMap::from([(
+            "query".to_string(),
+            original_query.clone(),
+        )])))
+    })?;
+    if !query.contains(GROUP_BY_ALL_MARKER) {
+        return Ok(statements);
+    }
+
+    statements
+        .iter()
+        .map(|stmt| GroupByAllReplacer::new().replace(stmt))
+        .collect::<CompilationResult<Vec<_>>>()
+        .map_err(|err| err.with_meta(Some(HashMap::from([("query".to_string(), original_query)]))))
 }
 
 pub fn parse_sql_to_statement(
@@ -314,6 +632,136 @@ pub fn parse_sql_to_statement(
 mod tests {
     use super::*;
 
+    fn sample(query: &str) -> CompilationResult<String> {
+        let tokens = Tokenizer::new(&PostgreSqlDialect {}, query)
+            .tokenize()
+            .unwrap();
+        let (tokens, samples) = extract_tablesamples(tokens)?;
+        let statements = parse_tokens(&PostgreSqlDialect {}, tokens).unwrap();
+
+        Ok(TableSampleReplacer::new(samples)
+            .replace(&statements)?
+            .iter()
+            .map(|stmt| stmt.to_string())
+            .collect::<Vec<_>>()
+            .join("; "))
+    }
+
+    #[test]
+    fn test_tablesample_rewrite() {
+        assert_eq!(
+            sample("SELECT * FROM KibanaSampleDataEcommerce TABLESAMPLE BERNOULLI (10) WHERE 1 = 1")
+                .unwrap(),
+            "SELECT * FROM (SELECT * FROM KibanaSampleDataEcommerce WHERE RANDOM() < 0.1) AS KibanaSampleDataEcommerce WHERE 1 = 1"
+        );
+        assert_eq!(
+            sample("SELECT k.customer_gender FROM db.KibanaSampleDataEcommerce AS k TABLESAMPLE (100 ROWS)")
+                .unwrap(),
+            "SELECT k.customer_gender FROM (SELECT * FROM db.KibanaSampleDataEcommerce ORDER BY RANDOM() LIMIT 100) AS k"
+        );
+        assert_eq!(
+            sample("SELECT * FROM a TABLESAMPLE SYSTEM (50) JOIN b ON a.id = b.id").unwrap(),
+            "SELECT * FROM (SELECT * FROM a WHERE RANDOM() < 0.5) AS a JOIN b ON a.id = b.id"
+        );
+
+        // String literals, comments and identifiers are not clauses
+        let tokens = Tokenizer::new(
+            &PostgreSqlDialect {},
+            "SELECT 'FROM t TABLESAMPLE (10)', tablesample FROM t -- TABLESAMPLE (10)\n",
+        )
+        .tokenize()
+        .unwrap();
+        let (result, samples) = extract_tablesamples(tokens.clone()).unwrap();
+        assert_eq!(result, tokens);
+        assert!(samples.is_empty());
+
+        assert!(sample("SELECT * FROM t TABLESAMPLE SYSTEM (150)").is_err());
+        assert!(sample("SELECT * FROM t TABLESAMPLE BERNOULLI (10 ROWS)").is_err());
+        assert!(sample("SELECT * FROM t TABLESAMPLE (10) REPEATABLE (42)").is_err());
+        assert!(sample("SELECT * FROM (SELECT 1) AS t TABLESAMPLE (10 ROWS)").is_err());
+    }
+
+    #[test]
+    fn test_explain_source_rewrite() {
+        let query = rewrite_explain_source(
+            "explain (source)\nSELECT customer_gender FROM KibanaSampleDataEcommerce;".to_string(),
+        );
+        assert_eq!(
+            query,
+            "EXPLAIN SELECT * FROM (SELECT customer_gender FROM KibanaSampleDataEcommerce) AS __cube_explain_source"
+        );
+
+        let stmt = parse_sql_to_statement(&query, DatabaseProtocol::PostgreSQL, &mut None).unwrap();
+        match stmt {
+            Statement::Explain { statement, .. } => assert_eq!(
+                explain_source_query(&statement).unwrap().to_string(),
+                "SELECT customer_gender FROM KibanaSampleDataEcommerce"
+            ),
+            other => panic!("Unexpected statement: {:?}", other),
+        }
+
+        assert_eq!(
+            rewrite_explain_source("EXPLAIN SELECT 1".to_string()),
+            "EXPLAIN SELECT 1"
+        );
+
+        let query = rewrite_explain_source(
+            "EXPLAIN (FORMAT JSON) SELECT customer_gender FROM KibanaSampleDataEcommerce"
+                .to_string(),
+        );
+        let stmt = parse_sql_to_statement(&query, DatabaseProtocol::PostgreSQL, &mut None).unwrap();
+        match stmt {
+            Statement::Explain { statement, .. } => {
+                assert!(explain_source_query(&statement).is_none());
+                assert_eq!(
+                    explain_json_query(&statement).unwrap().to_string(),
+                    "SELECT customer_gender FROM KibanaSampleDataEcommerce"
+                );
+            }
+            other => panic!("Unexpected statement: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_pushdown_hint() {
+        assert_eq!(
+            parse_pushdown_hint("SELECT /*+ cube_pushdown */ * FROM t"),
+            Some(PushdownHint::Force)
+        );
+        assert_eq!(
+            parse_pushdown_hint("SELECT /*+CUBE_NO_PUSHDOWN*/ * FROM t"),
+            Some(PushdownHint::Forbid)
+        );
+        // Regular comments are not hints
+        assert_eq!(
+            parse_pushdown_hint("SELECT /* cube_pushdown */ * FROM t"),
+            None
+        );
+        assert_eq!(parse_pushdown_hint("SELECT 'cube_pushdown'"), None);
+    }
+
+    #[test]
+    fn test_parse_emit_changes() {
+        assert_eq!(
+            parse_emit_changes(
+                "SELECT status, MEASURE(count) FROM Orders GROUP BY 1 EMIT CHANGES;"
+            ),
+            Some("SELECT status, MEASURE(count) FROM Orders GROUP BY 1".to_string())
+        );
+        assert_eq!(
+            parse_emit_changes("select *\nfrom Orders\nemit   changes"),
+            Some("select *\nfrom Orders".to_string())
+        );
+        assert_eq!(
+            parse_emit_changes("SELECT 1 EMIT CHANGES -- subscribe\n"),
+            Some("SELECT 1".to_string())
+        );
+        assert_eq!(parse_emit_changes("SELECT 'EMIT CHANGES' AS clause"), None);
+        assert_eq!(parse_emit_changes("SELECT 1 -- EMIT CHANGES"), None);
+        assert_eq!(parse_emit_changes("SELECT 1 /* EMIT CHANGES */"), None);
+        assert_eq!(parse_emit_changes("SELECT \"EMIT\" \"CHANGES\""), None);
+    }
+
     #[test]
     fn test_no_statements_mysql() {
         let result = parse_sql_to_statement(
@@ -7,8 +7,13 @@ use serde::Serialize;
 use sqlparser::ast::Statement;
 use uuid::Uuid;
 
+use crate::{
+    sql::SessionState,
+    transport::{TransportTrace, TransportTraceCall},
+};
+
 use super::{
-    find_cube_scans_deep_search,
+    find_cube_scan_wrappers, find_cube_scans_deep_search,
     rewrite::{
         analysis::{LogicalPlanAnalysis, LogicalPlanData},
         rewriter::IterInfo,
@@ -25,7 +30,14 @@ pub struct Qtrace {
     original_query: String,
     replaced_query: Option<String>,
     statements: Vec<QtraceStatement>,
+    transport: Vec<TransportTraceCall>,
     error_message: Option<String>,
+    // Written to the debug directory, CUBESQL_DEBUG_QTRACE
+    #[serde(skip)]
+    save_to_file: bool,
+    // Kept for `SHOW LAST QUERY TRACE` of the session, `SET cubesql_trace_next_query = on`
+    #[serde(skip)]
+    transport_trace: Option<TransportTrace>,
 }
 
 impl Qtrace {
@@ -39,14 +51,42 @@ impl Qtrace {
         if !Self::is_enabled() {
             return None;
         }
-        Some(Self {
+        Some(Self::make(original_query, true, None))
+    }
+
+    /// Trace of the query which is requested either by CUBESQL_DEBUG_QTRACE or by the session
+    pub fn for_session(original_query: &str, state: &SessionState) -> Option<Self> {
+        let save_to_file = Self::is_enabled();
+        let transport_trace = if state.take_trace_next_query() {
+            let trace = TransportTrace::default();
+            state.set_transport_trace(Some(trace.clone()));
+            Some(trace)
+        } else {
+            None
+        };
+
+        if !save_to_file && transport_trace.is_none() {
+            return None;
+        }
+        Some(Self::make(original_query, save_to_file, transport_trace))
+    }
+
+    fn make(
+        original_query: &str,
+        save_to_file: bool,
+        transport_trace: Option<TransportTrace>,
+    ) -> Self {
+        Self {
             version: Self::version(),
             uuid: Uuid::new_v4(),
             original_query: original_query.to_string(),
             replaced_query: None,
             statements: vec![],
+            transport: vec![],
             error_message: None,
-        })
+            save_to_file,
+            transport_trace,
+        }
     }
 
     pub fn is_enabled() -> bool {
@@ -85,19 +125,26 @@ impl Qtrace {
         self.statement(|stmt| stmt.set_optimized_plan(plan));
     }
 
+    // E-graphs are too big to be kept in the session, they are only saved to the debug directory
     pub fn set_original_graph(
         &mut self,
         egraph: &EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>,
     ) {
-        self.statement(|stmt| stmt.set_original_graph(egraph));
+        if self.save_to_file {
+            self.statement(|stmt| stmt.set_original_graph(egraph));
+        }
     }
 
     pub fn set_egraph_iterations(&mut self, iterations: Vec<QtraceEgraphIteration>) {
-        self.statement(|stmt| stmt.set_egraph_iterations(iterations));
+        if self.save_to_file {
+            self.statement(|stmt| stmt.set_egraph_iterations(iterations));
+        }
     }
 
     pub fn set_best_graph(&mut self, nodes: &Vec<LogicalPlanLanguage>) {
-        self.statement(|stmt| stmt.set_best_graph(nodes));
+        if self.save_to_file {
+            self.statement(|stmt| stmt.set_best_graph(nodes));
+        }
     }
 
     pub fn set_best_plan_and_cube_scans(&mut self, plan: &LogicalPlan) {
@@ -112,6 +159,23 @@ impl Qtrace {
         self.error_message = Some(error_message.to_string());
     }
 
+    /// Completes the trace of the query: it's kept in the session and/or saved to the debug directory
+    pub fn finish(mut self, state: &SessionState) {
+        if let Some(transport_trace) = self.transport_trace.take() {
+            self.transport = transport_trace.calls();
+            state.set_transport_trace(None);
+
+            match serde_json::to_string_pretty(&self) {
+                Ok(json_string) => state.set_last_query_trace(Some(json_string)),
+                Err(error) => log::error!("Unable to serialize qtrace to json: {}", error),
+            }
+        }
+
+        if self.save_to_file {
+            self.save_json();
+        }
+    }
+
     pub fn save_json(&self) {
         let debug_dir_name = Self::debug_dir_name();
         match fs::metadata(debug_dir_name) {
@@ -153,6 +217,7 @@ impl Qtrace {
 #[serde(rename_all = "camelCase")]
 pub struct QtraceStatement {
     parsed_statement: String,
+    ast: String,
     visitor_replaced_statement: Option<String>,
     df_plan: Option<String>,
     optimized_plan: Option<String>,
@@ -161,6 +226,8 @@ pub struct QtraceStatement {
     best_graph: Vec<QtraceEclass>,
     best_plan: Option<String>,
     cube_scans: Option<Vec<V1LoadRequestQuery>>,
+    wrapped_sql: Option<Vec<String>>,
+    schema: Option<Vec<(String, String)>>,
     error_message: Option<String>,
 }
 
@@ -168,6 +235,7 @@ impl QtraceStatement {
     pub fn new(statement: &Statement) -> Self {
         Self {
             parsed_statement: statement.to_string(),
+            ast: format!("{:?}", statement),
             visitor_replaced_statement: None,
             df_plan: None,
             optimized_plan: None,
@@ -176,6 +244,8 @@ impl QtraceStatement {
             best_graph: vec![],
             best_plan: None,
             cube_scans: None,
+            wrapped_sql: None,
+            schema: None,
             error_message: None,
         }
     }
@@ -222,6 +292,19 @@ impl QtraceStatement {
                 .map(|node| node.request)
                 .collect(),
         );
+        self.wrapped_sql = Some(
+            find_cube_scan_wrappers(plan)
+                .into_iter()
+                .filter_map(|node| node.wrapped_sql.map(|sql| sql.sql))
+                .collect(),
+        );
+        self.schema = Some(
+            plan.schema()
+                .fields()
+                .iter()
+                .map(|field| (field.name().clone(), field.data_type().to_string()))
+                .collect(),
+        );
     }
 
     pub fn set_error_message(&mut self, error_message: &str) {
@@ -2,7 +2,11 @@ use crate::{
     compile::{
         engine::{
             df::{
-                scan::{CubeScanNode, CubeScanOptions, MemberField, WrappedSelectNode},
+                scan::{
+                    CubeScanNode, CubeScanOptions, MemberField, QueryLimitSettings,
+                    WrappedSelectNode,
+                },
+                set_operations::coerce_set_inputs,
                 wrapper::CubeScanWrapperNode,
             },
             provider::CubeContext,
@@ -42,7 +46,7 @@ use datafusion::{
         build_join_schema, build_table_udf_schema, exprlist_to_fields, normalize_cols,
         plan::{Aggregate, Extension, Filter, Join, Projection, Sort, TableUDFs, Window},
         replace_col_to_expr, CrossJoin, DFField, DFSchema, DFSchemaRef, Distinct, EmptyRelation,
-        Expr, Like, Limit, LogicalPlan, LogicalPlanBuilder, TableScan, Union,
+        Expr, JoinType, Like, Limit, LogicalPlan, LogicalPlanBuilder, TableScan, Union,
     },
     physical_plan::planner::DefaultPhysicalPlanner,
     scalar::ScalarValue,
@@ -1009,7 +1013,13 @@ impl LanguageToLogicalPlanConverter {
                 let left = self.to_logical_plan(params[0]);
                 let right = self.to_logical_plan(params[1]);
 
-                if self.is_cube_scan_node(params[0]) && self.is_cube_scan_node(params[1]) {
+                let join_type = match_data_node!(node_by_id, params[4], JoinJoinType);
+                // Semi and anti joins of cubes are INTERSECT and EXCEPT, performed by DataFusion
+                let is_set_operation = matches!(join_type, JoinType::Semi | JoinType::Anti);
+                if !is_set_operation
+                    && self.is_cube_scan_node(params[0])
+                    && self.is_cube_scan_node(params[1])
+                {
                     if left_on.iter().any(|c| c.name == "__cubeJoinField")
                         || right_on.iter().any(|c| c.name == "__cubeJoinField")
                     {
@@ -1029,7 +1039,6 @@ impl LanguageToLogicalPlanConverter {
                 let left = Arc::new(left?);
                 let right = Arc::new(right?);
 
-                let join_type = match_data_node!(node_by_id, params[4], JoinJoinType);
                 let join_constraint = match_data_node!(node_by_id, params[5], JoinJoinConstraint);
                 let schema = Arc::new(build_join_schema(
                     left.schema(),
@@ -1242,14 +1251,7 @@ impl LanguageToLogicalPlanConverter {
                                     query_time_dimensions.push(V1LoadRequestQueryTimeDimension {
                                         dimension: dimension.to_string(),
                                         granularity: granularity.clone(),
-                                        date_range: date_range.map(|date_range| {
-                                            serde_json::Value::Array(
-                                                date_range
-                                                    .into_iter()
-                                                    .map(|d| serde_json::Value::String(d))
-                                                    .collect(),
-                                            )
-                                        }),
+                                        date_range: date_range.map(date_range_to_json),
                                     });
                                     if let Some(granularity) = &granularity {
                                         fields.push((
@@ -1508,7 +1510,8 @@ impl LanguageToLogicalPlanConverter {
                                                     if td.dimension == member
                                                         && td.date_range.is_none()
                                                     {
-                                                        td.date_range = Some(json!(values));
+                                                        td.date_range =
+                                                            Some(date_range_to_json(values));
                                                         Some(td)
                                                     } else {
                                                         None
@@ -1518,7 +1521,7 @@ impl LanguageToLogicalPlanConverter {
                                                 let dimension = V1LoadRequestQueryTimeDimension {
                                                     dimension: member.to_string(),
                                                     granularity: None,
-                                                    date_range: Some(json!(values)),
+                                                    date_range: Some(date_range_to_json(values)),
                                                 };
                                                 query_time_dimensions.push(dimension);
                                             }
@@ -1617,20 +1620,40 @@ impl LanguageToLogicalPlanConverter {
                         } else {
                             None
                         };
-                        let cube_scan_query_limit = env::var("CUBEJS_DB_QUERY_LIMIT")
-                            .map(|v| v.parse::<usize>().unwrap())
-                            .unwrap_or(50000);
-                        let fail_on_max_limit_hit = env::var("CUBESQL_FAIL_ON_MAX_LIMIT_HIT")
-                            .map(|v| v.to_lowercase() == "true")
-                            .unwrap_or(false);
+                        let query_limits = QueryLimitSettings::from_env();
+                        // Limit of the session for cubes of this node, e.g. a larger one for the
+                        // probe side of a join, it's checked regardless of CUBESQL_FAIL_ON_MAX_LIMIT_HIT.
+                        // Sessions can't raise it above the max limit of the server
+                        let max_records_override = self
+                            .cube_context
+                            .session_state
+                            .max_records_overrides()
+                            .for_cubes(
+                                &alias_to_cube
+                                    .iter()
+                                    .map(|(_, cube)| cube.clone())
+                                    .collect::<Vec<_>>(),
+                                query_limits.max_limit() as usize,
+                            );
+                        let cube_scan_query_limit =
+                            max_records_override.unwrap_or(query_limits.query_limit as usize);
+                        // Honored and paginated queries can return more rows than the query limit
+                        let cube_scan_max_limit =
+                            max_records_override.unwrap_or(query_limits.max_limit() as usize);
+                        let fail_on_max_limit_hit = max_records_override.is_some()
+                            || env::var("CUBESQL_FAIL_ON_MAX_LIMIT_HIT")
+                                .map(|v| v.to_lowercase() == "true")
+                                .unwrap_or(false);
                         let mut limit_was_changed = false;
+                        let mut max_limit = cube_scan_query_limit;
                         query.limit = match match_data_node!(
                             node_by_id,
                             cube_scan_params[4],
                             CubeScanLimit
                         ) {
                             Some(n) => {
-                                if n > cube_scan_query_limit {
+                                max_limit = cube_scan_max_limit;
+                                if n > cube_scan_max_limit {
                                     limit_was_changed = true;
                                 }
                                 Some(n)
@@ -1647,7 +1670,7 @@ impl LanguageToLogicalPlanConverter {
                         .map(|n| n as i32);
 
                         let max_records = if fail_on_max_limit_hit && limit_was_changed {
-                            Some(cube_scan_query_limit)
+                            Some(max_limit)
                         } else {
                             None
                         };
@@ -1671,6 +1694,8 @@ impl LanguageToLogicalPlanConverter {
                             query.ungrouped = Some(true);
                         }
 
+                        query.timezone = self.cube_context.request_timezone.clone();
+
                         let member_fields = fields.iter().map(|(_, m)| m.clone()).collect();
 
                         Arc::new(CubeScanNode::new(
@@ -1812,6 +1837,8 @@ impl LanguageToLogicalPlanConverter {
                     .into_iter()
                     .map(|n| self.to_logical_plan(n))
                     .collect::<Result<Vec<_>, _>>()?;
+                // Rewritten inputs (e.g. CubeScans of different cubes) can have different types
+                let inputs = coerce_set_inputs(inputs, "UNION")?;
 
                 let alias = match_data_node!(node_by_id, params[1], UnionAlias);
 
@@ -1858,6 +1885,14 @@ pub fn expr_name(expr: &Expr) -> Result<String, CubeError> {
     }
 }
 
+/// Relative date range, e.g. `from 30 days ago to now`, is a single string
+pub fn date_range_to_json(date_range: Vec<String>) -> serde_json::Value {
+    match date_range.as_slice() {
+        [relative] => serde_json::Value::String(relative.to_string()),
+        _ => json!(date_range),
+    }
+}
+
 pub fn expr_relation(expr: &Expr) -> Option<&str> {
     match expr {
         Expr::Column(c) => c.relation.as_ref().map(|s| s.as_str()),
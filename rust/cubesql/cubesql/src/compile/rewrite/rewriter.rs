@@ -190,16 +190,20 @@ impl Rewriter {
         cube_context: Arc<CubeContext>,
         egraph: EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>,
     ) -> CubeRunner {
+        // TODO move config to injector
+        let mut iter_limit = env::var("CUBESQL_REWRITE_MAX_ITERATIONS")
+            .map(|v| v.parse::<usize>().unwrap())
+            .unwrap_or(300);
+        let custom_rewrites = &cube_context.sessions.server.custom_rewrites;
+        if !custom_rewrites.is_empty() {
+            iter_limit = iter_limit.min(custom_rewrites.max_iterations());
+        }
+
         CubeRunner::new(LogicalPlanAnalysis::new(
             cube_context,
             Arc::new(DefaultPhysicalPlanner::default()),
         ))
-        // TODO move config to injector
-        .with_iter_limit(
-            env::var("CUBESQL_REWRITE_MAX_ITERATIONS")
-                .map(|v| v.parse::<usize>().unwrap())
-                .unwrap_or(300),
-        )
+        .with_iter_limit(iter_limit)
         .with_node_limit(
             env::var("CUBESQL_REWRITE_MAX_NODES")
                 .map(|v| v.parse::<usize>().unwrap())
@@ -219,6 +223,7 @@ impl Rewriter {
         auth_context: AuthContextRef,
         qtrace: &mut Option<Qtrace>,
         span_id: Option<Arc<SpanId>>,
+        sql_push_down: bool,
     ) -> Result<LogicalPlan, CubeError> {
         let cube_context = self.cube_context.clone();
         let egraph = self.graph.clone();
@@ -228,7 +233,7 @@ impl Rewriter {
 
         let (plan, qtrace_egraph_iterations, qtrace_best_graph) =
             tokio::task::spawn_blocking(move || {
-                let rules = Self::rewrite_rules(cube_context.clone());
+                let rules = Self::rewrite_rules(cube_context.clone(), sql_push_down);
                 let runner = Self::rewrite_runner(cube_context.clone(), egraph);
                 let runner = runner.run(rules.iter());
                 if !IterInfo::egraph_debug_enabled() {
@@ -387,8 +392,8 @@ impl Rewriter {
 
     pub fn rewrite_rules(
         cube_context: Arc<CubeContext>,
+        sql_push_down: bool,
     ) -> Vec<Rewrite<LogicalPlanLanguage, LogicalPlanAnalysis>> {
-        let sql_push_down = Self::sql_push_down_enabled();
         let rules: Vec<Box<dyn RewriteRules>> = vec![
             Box::new(MemberRules::new(cube_context.clone(), sql_push_down)),
             Box::new(FilterRules::new(cube_context.clone())),
@@ -404,6 +409,13 @@ impl Rewriter {
         if sql_push_down {
             rewrites.extend(WrapperRules::new(cube_context.clone()).rewrite_rules());
         }
+        rewrites.extend(
+            cube_context
+                .sessions
+                .server
+                .custom_rewrites
+                .rewrite_rules(cube_context.clone()),
+        );
         if let Ok(disabled_rule_names) = env::var("CUBESQL_DISABLE_REWRITES") {
             let disabled_rule_names = disabled_rule_names
                 .split(",")
@@ -0,0 +1,165 @@
+use std::{
+    fmt,
+    sync::{Arc, RwLock as RwLockSync},
+};
+
+use egg::Rewrite;
+use log::warn;
+
+use crate::{
+    compile::{
+        engine::provider::CubeContext,
+        rewrite::{analysis::LogicalPlanAnalysis, LogicalPlanLanguage},
+    },
+    CubeError,
+};
+
+/// Rewrite rules of embedding code, e.g. a mapping of an org-specific SQL macro to a member
+/// pattern. Rules are built for every query, they are applied together with the built-in rules.
+pub trait CustomRewriteRules: fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    fn rewrite_rules(
+        &self,
+        cube_context: Arc<CubeContext>,
+    ) -> Vec<Rewrite<LogicalPlanLanguage, LogicalPlanAnalysis>>;
+}
+
+/// Custom rules which are registered through `ServerManager::custom_rewrites`. A misbehaving
+/// extension can't blow up the rewriter: rules over `max_rules` are not applied and queries
+/// with custom rules are limited to `max_iterations` of the rewriter.
+#[derive(Debug)]
+pub struct CustomRewrites {
+    extensions: RwLockSync<Vec<Arc<dyn CustomRewriteRules>>>,
+    max_rules: usize,
+    max_iterations: usize,
+}
+
+impl CustomRewrites {
+    pub fn new(max_rules: usize, max_iterations: usize) -> Self {
+        Self {
+            extensions: RwLockSync::new(vec![]),
+            max_rules,
+            max_iterations,
+        }
+    }
+
+    pub fn register(&self, extension: Arc<dyn CustomRewriteRules>) -> Result<(), CubeError> {
+        let mut extensions = self
+            .extensions
+            .write()
+            .expect("failed to unlock custom rewrites for writing");
+        if extensions
+            .iter()
+            .any(|registered| registered.name() == extension.name())
+        {
+            return Err(CubeError::user(format!(
+                "Custom rewrite rules '{}' are already registered",
+                extension.name()
+            )));
+        }
+
+        extensions.push(extension);
+
+        Ok(())
+    }
+
+    fn extensions(&self) -> Vec<Arc<dyn CustomRewriteRules>> {
+        self.extensions
+            .read()
+            .expect("failed to unlock custom rewrites for reading")
+            .clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extensions().is_empty()
+    }
+
+    pub fn max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+
+    /// Rules of the extensions in the order of registration, an extension which doesn't fit
+    /// into CUBESQL_CUSTOM_REWRITE_MAX_RULES is skipped as a whole
+    pub fn rewrite_rules(
+        &self,
+        cube_context: Arc<CubeContext>,
+    ) -> Vec<Rewrite<LogicalPlanLanguage, LogicalPlanAnalysis>> {
+        let mut rewrites = Vec::new();
+        for extension in self.extensions() {
+            let rules = extension.rewrite_rules(cube_context.clone());
+            if rewrites.len() + rules.len() > self.max_rules {
+                warn!(
+                    "Custom rewrite rules '{}' are not applied: {} rules exceed the limit of {} rules (CUBESQL_CUSTOM_REWRITE_MAX_RULES)",
+                    extension.name(),
+                    rewrites.len() + rules.len(),
+                    self.max_rules
+                );
+                continue;
+            }
+
+            rewrites.extend(rules);
+        }
+
+        rewrites
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::{rewrite::rewrite, test::rewrite_engine::cube_context};
+
+    #[derive(Debug)]
+    struct TestRules {
+        name: String,
+        rules: usize,
+    }
+
+    impl CustomRewriteRules for TestRules {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn rewrite_rules(
+            &self,
+            _cube_context: Arc<CubeContext>,
+        ) -> Vec<Rewrite<LogicalPlanLanguage, LogicalPlanAnalysis>> {
+            (0..self.rules)
+                .map(|i| {
+                    rewrite(
+                        &format!("{}-{}", self.name, i),
+                        "?expr".to_string(),
+                        "?expr".to_string(),
+                    )
+                })
+                .collect()
+        }
+    }
+
+    fn rules(name: &str, rules: usize) -> Arc<dyn CustomRewriteRules> {
+        Arc::new(TestRules {
+            name: name.to_string(),
+            rules,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_custom_rewrites() {
+        let custom_rewrites = CustomRewrites::new(4, 50);
+        assert!(custom_rewrites.is_empty());
+
+        custom_rewrites.register(rules("macros", 3)).unwrap();
+        assert!(custom_rewrites.register(rules("macros", 1)).is_err());
+        // Doesn't fit into the limit
+        custom_rewrites.register(rules("aliases", 2)).unwrap();
+        custom_rewrites.register(rules("members", 1)).unwrap();
+
+        let names = custom_rewrites
+            .rewrite_rules(Arc::new(cube_context().await))
+            .iter()
+            .map(|rule| rule.name.as_str().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["macros-0", "macros-1", "macros-2", "members-0"]);
+    }
+}
@@ -20,8 +20,8 @@ use crate::{
             ChangeUserMemberValue, ColumnExprColumn, CubeScanAliasToCube, CubeScanLimit,
             FilterMemberMember, FilterMemberOp, FilterMemberValues, FilterReplacerAliasToCube,
             FilterReplacerAliases, InListExprNegated, LikeExprEscapeChar, LikeExprNegated,
-            LimitFetch, LimitSkip, LiteralExprValue, LogicalPlanLanguage, SegmentMemberMember,
-            TimeDimensionDateRange, TimeDimensionDateRangeReplacerDateRange,
+            LimitFetch, LimitSkip, LiteralExprValue, LogicalPlanLanguage, ScalarUDFExprFun,
+            SegmentMemberMember, TimeDimensionDateRange, TimeDimensionDateRangeReplacerDateRange,
             TimeDimensionDateRangeReplacerMember, TimeDimensionGranularity, TimeDimensionName,
         },
     },
@@ -45,7 +45,7 @@ use datafusion::{
     logical_plan::{Column, Expr, Operator},
     scalar::ScalarValue,
 };
-use egg::{EGraph, Rewrite, Subst, Var};
+use egg::{EGraph, Id, Rewrite, Subst, Var};
 use std::{fmt::Display, ops::Index, sync::Arc};
 
 pub struct FilterRules {
@@ -54,7 +54,7 @@ pub struct FilterRules {
 
 impl RewriteRules for FilterRules {
     fn rewrite_rules(&self) -> Vec<Rewrite<LogicalPlanLanguage, LogicalPlanAnalysis>> {
-        vec![
+        let mut rules = vec![
             transforming_rewrite(
                 "push-down-filter",
                 filter(
@@ -640,6 +640,47 @@ impl RewriteRules for FilterRules {
                     "?filter_aliases",
                 ),
             ),
+            rewrite(
+                "filter-replacer-between-date-trunc",
+                filter_replacer(
+                    between_expr(
+                        fun_expr(
+                            "DateTrunc",
+                            vec![literal_expr("?granularity"), column_expr("?column")],
+                        ),
+                        "BetweenExprNegated:false",
+                        literal_expr("?low"),
+                        literal_expr("?high"),
+                    ),
+                    "?alias_to_cube",
+                    "?members",
+                    "?filter_aliases",
+                ),
+                filter_replacer(
+                    binary_expr(
+                        binary_expr(
+                            fun_expr(
+                                "DateTrunc",
+                                vec![literal_expr("?granularity"), column_expr("?column")],
+                            ),
+                            ">=",
+                            literal_expr("?low"),
+                        ),
+                        "AND",
+                        binary_expr(
+                            fun_expr(
+                                "DateTrunc",
+                                vec![literal_expr("?granularity"), column_expr("?column")],
+                            ),
+                            "<=",
+                            literal_expr("?high"),
+                        ),
+                    ),
+                    "?alias_to_cube",
+                    "?members",
+                    "?filter_aliases",
+                ),
+            ),
             transforming_rewrite(
                 "filter-replacer-between-numbers",
                 filter_replacer(
@@ -2610,7 +2651,11 @@ impl RewriteRules for FilterRules {
                     "?output_date_range",
                 ),
             ),
-        ]
+        ];
+
+        self.text_search_rules(&mut rules);
+
+        rules
     }
 }
 
@@ -2619,6 +2664,121 @@ impl FilterRules {
         Self { cube_context }
     }
 
+    /// Text search functions which are compared with a position: `strpos(col, 'x') > 0`,
+    /// `position('x' in col) = 1`, and `starts_with`/`ends_with` over `lower(col)`.
+    /// Cube text filters are case-insensitive, so `lower(col)` is matched as the column itself.
+    fn text_search_rules(
+        &self,
+        rules: &mut Vec<Rewrite<LogicalPlanLanguage, LogicalPlanAnalysis>>,
+    ) {
+        let columns = vec![
+            ("column", column_expr("?column")),
+            ("lower", fun_expr("Lower", vec![column_expr("?column")])),
+        ];
+        let comparisons = vec![
+            (">", 0, "contains"),
+            ("=", 0, "notContains"),
+            ("<=", 0, "notContains"),
+            ("=", 1, "startsWith"),
+            ("!=", 1, "notStartsWith"),
+        ];
+
+        for (column_name, column) in columns.iter() {
+            let positions = vec![
+                (
+                    "strpos",
+                    fun_expr("Strpos", vec![column.clone(), literal_expr("?literal")]),
+                ),
+                (
+                    "position",
+                    udf_expr("position", vec![literal_expr("?literal"), column.clone()]),
+                ),
+            ];
+
+            for (position_name, position) in positions.iter() {
+                for (op, value, filter_op) in comparisons.iter().cloned() {
+                    // `strpos() > 0` is rewritten to LIKE, sigma rules cover position over
+                    // lower(column) except `= 0`
+                    if (*position_name == "strpos" && op == ">")
+                        || (*position_name == "position" && *column_name == "lower" && op != "=")
+                    {
+                        continue;
+                    }
+
+                    rules.push(transforming_rewrite(
+                        &format!(
+                            "filter-{}-{}-{}-{}",
+                            position_name, column_name, filter_op, op
+                        ),
+                        filter_replacer(
+                            binary_expr(position.clone(), op, literal_int(value)),
+                            "?alias_to_cube",
+                            "?members",
+                            "?filter_aliases",
+                        ),
+                        filter_member("?filter_member", "?filter_op", "?filter_values"),
+                        self.transform_filter_prefix(
+                            "?column",
+                            filter_op,
+                            "?literal",
+                            None,
+                            "?alias_to_cube",
+                            "?members",
+                            "?filter_member",
+                            "?filter_op",
+                            "?filter_values",
+                            "?filter_aliases",
+                        ),
+                    ));
+                }
+            }
+
+            if *column_name == "column" {
+                continue;
+            }
+
+            let prefixes = vec![
+                (
+                    "starts-with",
+                    fun_expr("StartsWith", vec![column.clone(), literal_expr("?literal")]),
+                    "startsWith",
+                    "notStartsWith",
+                ),
+                (
+                    "ends-with",
+                    udf_expr("ends_with", vec![column.clone(), literal_expr("?literal")]),
+                    "endsWith",
+                    "notEndsWith",
+                ),
+            ];
+
+            for (prefix_name, prefix, filter_op, not_filter_op) in prefixes.iter() {
+                for (expr, filter_op) in vec![
+                    (prefix.clone(), *filter_op),
+                    (not_expr(prefix.clone()), *not_filter_op),
+                ] {
+                    rules.push(transforming_rewrite(
+                        &format!("filter-{}-{}-{}", prefix_name, column_name, filter_op),
+                        filter_replacer(expr, "?alias_to_cube", "?members", "?filter_aliases"),
+                        filter_member("?filter_member", "?filter_op", "?filter_values"),
+                        self.transform_filter_prefix(
+                            "?column",
+                            filter_op,
+                            "?literal",
+                            None,
+                            "?alias_to_cube",
+                            "?members",
+                            "?filter_member",
+                            "?filter_op",
+                            "?filter_values",
+                            "?filter_aliases",
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
     fn push_down_filter(
         &self,
         alias_to_cube_var: &'static str,
@@ -2749,6 +2909,13 @@ impl FilterRules {
         let filter_values_var = filter_values_var.parse().unwrap();
         let filter_aliases_var = filter_aliases_var.parse().unwrap();
         let meta_context = self.cube_context.meta.clone();
+        let relative_date_ranges = self
+            .cube_context
+            .sessions
+            .server
+            .config_obj
+            .features()
+            .is_enabled("relative_date_ranges", &self.cube_context.session_state);
         move |egraph, subst| {
             for expr_op in var_iter!(egraph[subst[op_var]], BinaryExprOp) {
                 if let Some(ConstantFolding::Scalar(literal)) =
@@ -2802,6 +2969,43 @@ impl FilterRules {
                                         },
                                     };
 
+                                    // `now()` and `current_date` are folded into literals at
+                                    // plan time, Cube evaluates relative ranges at query time
+                                    if let (MemberType::Time, true) =
+                                        (&member_type, relative_date_ranges)
+                                    {
+                                        if let Some(date_range) = Self::relative_date_range(
+                                            egraph,
+                                            subst[constant_var],
+                                            expr_op,
+                                        ) {
+                                            subst.insert(
+                                                filter_member_var,
+                                                egraph.add(
+                                                    LogicalPlanLanguage::FilterMemberMember(
+                                                        FilterMemberMember(member_name.to_string()),
+                                                    ),
+                                                ),
+                                            );
+                                            subst.insert(
+                                                filter_op_var,
+                                                egraph.add(LogicalPlanLanguage::FilterMemberOp(
+                                                    FilterMemberOp("inDateRange".to_string()),
+                                                )),
+                                            );
+                                            subst.insert(
+                                                filter_values_var,
+                                                egraph.add(
+                                                    LogicalPlanLanguage::FilterMemberValues(
+                                                        FilterMemberValues(vec![date_range]),
+                                                    ),
+                                                ),
+                                            );
+
+                                            return true;
+                                        }
+                                    }
+
                                     let op = match literal {
                                         ScalarValue::Utf8(Some(value)) => match op {
                                             "contains" => {
@@ -3563,6 +3767,68 @@ impl FilterRules {
         }
     }
 
+    /// Cube relative date range for a comparison with `now()` or `current_date` shifted by an
+    /// interval, e.g. `>= now() - interval '30 days'` is `from 30 days ago to now`
+    fn relative_date_range(
+        egraph: &EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>,
+        id: Id,
+        expr_op: &Operator,
+    ) -> Option<String> {
+        let is_current_time = |id: Id| {
+            egraph[id].nodes.iter().any(|node| match node {
+                LogicalPlanLanguage::ScalarUDFExpr([fun, _]) => {
+                    var_iter!(egraph[*fun], ScalarUDFExprFun)
+                        .any(|fun| fun == "eval_now" || fun == "eval_current_date")
+                }
+                _ => false,
+            })
+        };
+
+        for node in egraph[id].nodes.iter() {
+            let (left, op, right) = match node {
+                LogicalPlanLanguage::BinaryExpr([left, op, right]) => (*left, *op, *right),
+                _ => continue,
+            };
+            if !is_current_time(left) {
+                continue;
+            }
+
+            let (amount, unit) = match &egraph[right].data.constant {
+                Some(ConstantFolding::Scalar(interval)) => match interval_to_amount(interval) {
+                    Some(amount) => amount,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            for op in var_iter!(egraph[op], BinaryExprOp) {
+                let amount = match op {
+                    Operator::Minus => -amount,
+                    Operator::Plus => amount,
+                    _ => continue,
+                };
+                let period = format!(
+                    "{} {}{}",
+                    amount.abs(),
+                    unit,
+                    if amount.abs() == 1 { "" } else { "s" }
+                );
+
+                // Only the bound in the past (future) is relative, the other one is now
+                match expr_op {
+                    Operator::Gt | Operator::GtEq if amount < 0 => {
+                        return Some(format!("from {} ago to now", period))
+                    }
+                    Operator::Lt | Operator::LtEq if amount > 0 => {
+                        return Some(format!("from now to {} from now", period))
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        None
+    }
+
     fn scalar_to_native_datetime(literal: &ScalarValue) -> Option<NaiveDateTime> {
         match literal {
             ScalarValue::TimestampNanosecond(_, _)
@@ -3929,11 +4195,11 @@ impl FilterRules {
         let date_sub_interval_var = var!(date_sub_interval_var);
         move |egraph, subst| {
             for op in var_iter!(egraph[subst[op_var]], BinaryExprOp) {
+                // The boundary of the next period belongs to the next period:
+                // `date_trunc('month', d) <= '2020-04-01'` is `d < '2020-05-01'`
                 let new_op = match op {
-                    Operator::GtEq => Operator::GtEq,
-                    Operator::Gt => Operator::Gt,
-                    Operator::LtEq => Operator::LtEq,
-                    Operator::Lt => Operator::Lt,
+                    Operator::GtEq | Operator::Gt => Operator::GtEq,
+                    Operator::LtEq | Operator::Lt => Operator::Lt,
                     _ => continue,
                 };
 
@@ -4055,24 +4321,27 @@ impl FilterRules {
                 }
             }
 
+            // Half-open ranges are usually written with plain dates, e.g.
+            // `order_date >= '2024-01-01' AND order_date < '2024-04-01'`, the upper bound must be
+            // excluded from the resulting inclusive date range too
             fn increment_iso_timestamp_time(date_var: &String) -> String {
-                let timestamp = NaiveDateTime::parse_from_str(date_var, "%Y-%m-%dT%H:%M:%S.%fZ");
+                let timestamp = utils::parse_date_str(date_var);
                 let value = match timestamp {
-                    Ok(val) => format_iso_timestamp(
+                    Some(val) => format_iso_timestamp(
                         val.checked_add_signed(Duration::milliseconds(1)).unwrap(),
                     ),
-                    Err(_) => date_var.clone(),
+                    None => date_var.clone(),
                 };
                 return value;
             }
 
             fn decrement_iso_timestamp_time(date_var: &String) -> String {
-                let timestamp = NaiveDateTime::parse_from_str(date_var, "%Y-%m-%dT%H:%M:%S.%fZ");
+                let timestamp = utils::parse_date_str(date_var);
                 let value = match timestamp {
-                    Ok(val) => format_iso_timestamp(
+                    Some(val) => format_iso_timestamp(
                         val.checked_sub_signed(Duration::milliseconds(1)).unwrap(),
                     ),
-                    Err(_) => date_var.clone(),
+                    None => date_var.clone(),
                 };
                 return value;
             }
@@ -4629,3 +4898,39 @@ fn format_iso_timestamp(dt: NaiveDateTime) -> String {
     )
     .to_string()
 }
+
+/// Amount and unit of an interval with a single non-zero part, e.g. `30 day` or `3 hour`
+fn interval_to_amount(interval: &ScalarValue) -> Option<(i64, &'static str)> {
+    let (months, days, nanos) = match interval {
+        ScalarValue::IntervalYearMonth(Some(months)) => (*months as i64, 0, 0),
+        ScalarValue::IntervalDayTime(Some(value)) => (
+            0,
+            (value >> 32) as i32 as i64,
+            (*value as i32) as i64 * 1_000_000,
+        ),
+        ScalarValue::IntervalMonthDayNano(Some(value)) => (
+            (value >> 96) as i32 as i64,
+            (value >> 64) as i32 as i64,
+            *value as i64,
+        ),
+        _ => return None,
+    };
+
+    match (months, days, nanos) {
+        (0, 0, 0) => None,
+        (months, 0, 0) if months % 12 == 0 => Some((months / 12, "year")),
+        (months, 0, 0) => Some((months, "month")),
+        (0, days, 0) => Some((days, "day")),
+        (0, 0, nanos) if nanos % 1_000_000_000 == 0 => {
+            let seconds = nanos / 1_000_000_000;
+            if seconds % 3600 == 0 {
+                Some((seconds / 3600, "hour"))
+            } else if seconds % 60 == 0 {
+                Some((seconds / 60, "minute"))
+            } else {
+                Some((seconds, "second"))
+            }
+        }
+        _ => None,
+    }
+}
@@ -15,16 +15,17 @@ use crate::{
             rules::{replacer_push_down_node, replacer_push_down_node_substitute_rules, utils},
             segment_expr, table_scan, time_dimension_expr, transforming_chain_rewrite,
             transforming_rewrite, udaf_expr, udf_expr, virtual_field_expr,
-            AggregateFunctionExprDistinct, AggregateFunctionExprFun, AliasExprAlias,
-            AllMembersAlias, AllMembersCube, BinaryExprOp, CastExprDataType, ChangeUserCube,
-            ColumnExprColumn, CubeScanAliasToCube, CubeScanCanPushdownJoin, CubeScanLimit,
-            CubeScanOffset, CubeScanUngrouped, DimensionName, JoinLeftOn, JoinRightOn,
-            LikeExprEscapeChar, LikeExprLikeType, LikeExprNegated, LikeType, LimitFetch, LimitSkip,
-            LiteralExprValue, LiteralMemberRelation, LiteralMemberValue, LogicalPlanLanguage,
-            MeasureName, MemberErrorAliasToCube, MemberErrorError, MemberErrorPriority,
-            MemberPushdownReplacerAliasToCube, MemberReplacerAliasToCube, ProjectionAlias,
-            SegmentName, TableScanSourceTableName, TableScanTableName, TimeDimensionDateRange,
-            TimeDimensionGranularity, TimeDimensionName, VirtualFieldCube, VirtualFieldName,
+            AggregateFunctionExprDistinct, AggregateFunctionExprFun, AggregateUDFExprFun,
+            AliasExprAlias, AllMembersAlias, AllMembersCube, BinaryExprOp, CastExprDataType,
+            ChangeUserCube, ColumnExprColumn, CubeScanAliasToCube, CubeScanCanPushdownJoin,
+            CubeScanLimit, CubeScanOffset, CubeScanUngrouped, DimensionName, JoinLeftOn,
+            JoinRightOn, LikeExprEscapeChar, LikeExprLikeType, LikeExprNegated, LikeType,
+            LimitFetch, LimitSkip, LiteralExprValue, LiteralMemberRelation, LiteralMemberValue,
+            LogicalPlanLanguage, MeasureName, MemberErrorAliasToCube, MemberErrorError,
+            MemberErrorPriority, MemberPushdownReplacerAliasToCube, MemberReplacerAliasToCube,
+            ProjectionAlias, SegmentName, TableScanSourceTableName, TableScanTableName,
+            TimeDimensionDateRange, TimeDimensionGranularity, TimeDimensionName, VirtualFieldCube,
+            VirtualFieldName,
         },
     },
     transport::{V1CubeMetaDimensionExt, V1CubeMetaExt, V1CubeMetaMeasureExt},
@@ -1037,6 +1038,26 @@ impl MemberRules {
             None,
             Some("?column"),
         ));
+        // BI tools wrap dimensions into ANY_VALUE() to satisfy GROUP BY
+        rules.push(transforming_chain_rewrite(
+            "member-pushdown-replacer-udaf-dimension-selection",
+            member_pushdown_replacer(
+                "?aggr_expr",
+                dimension_expr("?name", "?old_alias"),
+                "?member_pushdown_replacer_alias_to_cube",
+            ),
+            vec![(
+                "?aggr_expr",
+                udaf_expr("?fun_name", vec![column_expr("?column")]),
+            )],
+            dimension_expr("?name", "?output_column"),
+            self.pushdown_dimension_selection(
+                "?member_pushdown_replacer_alias_to_cube",
+                "?fun_name",
+                "?aggr_expr",
+                "?output_column",
+            ),
+        ));
         rules.push(pushdown_measure_rewrite(
             "member-pushdown-replacer-udaf-fun-on-dimension",
             udaf_expr("?fun_name", vec![column_expr("?column")]),
@@ -1642,6 +1663,20 @@ impl MemberRules {
                                         Some(g) => g,
                                         None => continue,
                                     };
+                                // Non-standard granularity should be declared on the dimension
+                                let granularity_value = if utils::granularity_str_to_int_order(
+                                    &granularity_value,
+                                    Some(false),
+                                )
+                                .is_some()
+                                {
+                                    granularity_value
+                                } else {
+                                    match time_dimension.find_granularity(&granularity_value) {
+                                        Some(custom) => custom.name.to_string(),
+                                        None => continue,
+                                    }
+                                };
 
                                 subst.insert(
                                     time_dimension_name_var,
@@ -2045,6 +2080,49 @@ impl MemberRules {
         }
     }
 
+    fn pushdown_dimension_selection(
+        &self,
+        member_pushdown_replacer_alias_to_cube_var: &'static str,
+        fun_var: &'static str,
+        original_expr_var: &'static str,
+        output_column_var: &'static str,
+    ) -> impl Fn(&mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>, &mut Subst) -> bool {
+        let member_pushdown_replacer_alias_to_cube_var =
+            var!(member_pushdown_replacer_alias_to_cube_var);
+        let fun_var = var!(fun_var);
+        let original_expr_var = var!(original_expr_var);
+        let output_column_var = var!(output_column_var);
+        move |egraph, subst| {
+            // Every row of a group has the same value of a grouped dimension, so picking
+            // any of them is the same as selecting the dimension itself
+            if !var_iter!(egraph[subst[fun_var]], AggregateUDFExprFun).any(|fun| {
+                ["any_value", "first", "last"]
+                    .iter()
+                    .any(|name| fun.eq_ignore_ascii_case(name))
+            }) {
+                return false;
+            }
+
+            if let Some(alias) = original_expr_name(egraph, subst[original_expr_var]) {
+                for alias_to_cube in var_iter!(
+                    egraph[subst[member_pushdown_replacer_alias_to_cube_var]],
+                    MemberPushdownReplacerAliasToCube
+                )
+                .cloned()
+                {
+                    // alias_to_cube at this point is already filtered to a single cube
+                    let cube_alias = alias_to_cube.iter().next().unwrap().0 .1.to_string();
+                    let alias_expr = Self::add_alias_column(egraph, alias, Some(cube_alias));
+                    subst.insert(output_column_var, alias_expr);
+
+                    return true;
+                }
+            }
+
+            false
+        }
+    }
+
     fn transform_measure(
         &self,
         alias_to_cube_var: &'static str,
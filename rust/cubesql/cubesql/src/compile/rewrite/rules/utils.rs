@@ -1,6 +1,6 @@
 use std::cmp::{max, min};
 
-use chrono::{Datelike, NaiveDateTime, Timelike};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
 use datafusion::{physical_plan::aggregates::AggregateFunction, scalar::ScalarValue};
 
 pub fn parse_granularity_string(granularity: &str, to_normalize: bool) -> Option<String> {
@@ -182,3 +182,54 @@ pub fn is_literal_date_trunced(ns: i64, granularity: &str) -> Option<bool> {
         _ => return None,
     })
 }
+
+/// Parse date filter value which came from SQL as a string literal, e.g. '2024-01-01',
+/// '2024-01-01 10:00:00' or '2024-01-01T10:00:00.000Z'
+pub fn parse_date_str(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim().trim_end_matches('Z');
+
+    for format in &[
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(dt);
+        }
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.and_hms(0, 0, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_parse_date_str() {
+        let start_of_april = NaiveDate::from_ymd(2024, 4, 1).and_hms(0, 0, 0);
+        for value in &[
+            "2024-04-01",
+            "2024-04-01 00:00",
+            "2024-04-01 00:00:00",
+            "2024-04-01T00:00:00.000Z",
+        ] {
+            assert_eq!(parse_date_str(value), Some(start_of_april));
+        }
+        assert_eq!(
+            parse_date_str("2024-04-01 10:30:15.250"),
+            Some(NaiveDate::from_ymd(2024, 4, 1).and_hms_milli(10, 30, 15, 250))
+        );
+        assert_eq!(parse_date_str("April 1st"), None);
+
+        // The exclusive end of `d < '2024-04-01'` is the last millisecond of March
+        assert_eq!(
+            parse_date_str("2024-04-01").map(|date| date - Duration::milliseconds(1)),
+            Some(NaiveDate::from_ymd(2024, 3, 31).and_hms_milli(23, 59, 59, 999))
+        );
+    }
+}
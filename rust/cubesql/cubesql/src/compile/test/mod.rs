@@ -1,9 +1,12 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock as RwLockSync},
+};
 
 use async_trait::async_trait;
 use cubeclient::models::{
-    V1CubeMeta, V1CubeMetaDimension, V1CubeMetaJoin, V1CubeMetaMeasure, V1CubeMetaSegment,
-    V1LoadRequestQuery, V1LoadResponse,
+    V1CubeMeta, V1CubeMetaDimension, V1CubeMetaDimensionGranularity, V1CubeMetaJoin,
+    V1CubeMetaMeasure, V1CubeMetaSegment, V1LoadRequestQuery, V1LoadResponse,
 };
 use datafusion::arrow::datatypes::SchemaRef;
 
@@ -16,7 +19,7 @@ use crate::{
     },
     transport::{
         CubeStreamReceiver, LoadRequestMeta, SpanId, SqlGenerator, SqlResponse, SqlTemplates,
-        TransportService,
+        SqlView, TransportService,
     },
     CubeError,
 };
@@ -34,26 +37,37 @@ pub fn get_test_meta() -> Vec<V1CubeMeta> {
                 V1CubeMetaDimension {
                     name: "KibanaSampleDataEcommerce.order_date".to_string(),
                     _type: "time".to_string(),
+                    granularities: Some(vec![V1CubeMetaDimensionGranularity {
+                        name: "fiscal_year".to_string(),
+                        title: Some("Fiscal Year".to_string()),
+                        interval: Some("1 year".to_string()),
+                        offset: Some("3 months".to_string()),
+                    }]),
                 },
                 V1CubeMetaDimension {
                     name: "KibanaSampleDataEcommerce.last_mod".to_string(),
                     _type: "time".to_string(),
+                    granularities: None,
                 },
                 V1CubeMetaDimension {
                     name: "KibanaSampleDataEcommerce.customer_gender".to_string(),
                     _type: "string".to_string(),
+                    granularities: None,
                 },
                 V1CubeMetaDimension {
                     name: "KibanaSampleDataEcommerce.notes".to_string(),
                     _type: "string".to_string(),
+                    granularities: None,
                 },
                 V1CubeMetaDimension {
                     name: "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
                     _type: "number".to_string(),
+                    granularities: None,
                 },
                 V1CubeMetaDimension {
                     name: "KibanaSampleDataEcommerce.has_subscription".to_string(),
                     _type: "boolean".to_string(),
+                    granularities: None,
                 },
             ],
             measures: vec![
@@ -104,6 +118,7 @@ pub fn get_test_meta() -> Vec<V1CubeMeta> {
                 name: "Logs".to_string(),
                 relationship: "belongsTo".to_string(),
             }]),
+            folder: None,
         },
         V1CubeMeta {
             name: "Logs".to_string(),
@@ -112,14 +127,17 @@ pub fn get_test_meta() -> Vec<V1CubeMeta> {
                 V1CubeMetaDimension {
                     name: "Logs.id".to_string(),
                     _type: "number".to_string(),
+                    granularities: None,
                 },
                 V1CubeMetaDimension {
                     name: "Logs.read".to_string(),
                     _type: "boolean".to_string(),
+                    granularities: None,
                 },
                 V1CubeMetaDimension {
                     name: "Logs.content".to_string(),
                     _type: "string".to_string(),
+                    granularities: None,
                 },
             ],
             measures: vec![
@@ -141,6 +159,7 @@ pub fn get_test_meta() -> Vec<V1CubeMeta> {
                 name: "NumberCube".to_string(),
                 relationship: "belongsTo".to_string(),
             }]),
+            folder: None,
         },
         V1CubeMeta {
             name: "NumberCube".to_string(),
@@ -154,6 +173,7 @@ pub fn get_test_meta() -> Vec<V1CubeMeta> {
             }],
             segments: vec![],
             joins: None,
+            folder: None,
         },
         V1CubeMeta {
             name: "WideCube".to_string(),
@@ -162,6 +182,7 @@ pub fn get_test_meta() -> Vec<V1CubeMeta> {
                 .map(|i| V1CubeMetaDimension {
                     name: format!("WideCube.dim{}", i),
                     _type: "number".to_string(),
+                    granularities: None,
                 })
                 .collect(),
             measures: (0..100)
@@ -209,6 +230,7 @@ pub fn get_test_meta() -> Vec<V1CubeMeta> {
                 .collect(),
             segments: Vec::new(),
             joins: Some(Vec::new()),
+            folder: None,
         },
     ]
 }
@@ -226,6 +248,7 @@ pub fn get_string_cube_meta() -> Vec<V1CubeMeta> {
         }],
         segments: vec![],
         joins: None,
+        folder: None,
     }]
 }
 
@@ -278,6 +301,7 @@ pub fn get_test_tenant_ctx_customized(custom_templates: Vec<(String, String)>) -
                     // ("functions/DATEADD".to_string(), "DATEADD({{ date_part }}, {{ interval }}, {{ args[2] }})".to_string()),
                     ("functions/CONCAT".to_string(), "CONCAT({{ args_concat }})".to_string()),
                     ("functions/DATE".to_string(), "DATE({{ args_concat }})".to_string()),
+                    ("functions/RANDOM".to_string(), "RANDOM()".to_string()),
                     ("expressions/extract".to_string(), "EXTRACT({{ date_part }} FROM {{ expr }})".to_string()),
                     (
                         "statements/select".to_string(),
@@ -400,13 +424,15 @@ pub fn get_test_auth() -> Arc<dyn SqlAuthService> {
 
 pub fn get_test_transport() -> Arc<dyn TransportService> {
     #[derive(Debug)]
-    struct TestConnectionTransport {}
+    struct TestConnectionTransport {
+        views: RwLockSync<Vec<SqlView>>,
+    }
 
     #[async_trait]
     impl TransportService for TestConnectionTransport {
         // Load meta information about cubes
         async fn meta(&self, _ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
-            panic!("It's a fake transport");
+            Ok(get_test_tenant_ctx())
         }
 
         async fn sql(
@@ -477,7 +503,46 @@ pub fn get_test_transport() -> Arc<dyn TransportService> {
             );
             Ok(())
         }
+
+        async fn views(&self, _ctx: AuthContextRef) -> Result<Vec<SqlView>, CubeError> {
+            Ok(self.views.read().unwrap().clone())
+        }
+
+        async fn save_view(
+            &self,
+            _ctx: AuthContextRef,
+            view: SqlView,
+            replace: bool,
+        ) -> Result<(), CubeError> {
+            let mut views = self.views.write().unwrap();
+            if let Some(existing) = views
+                .iter_mut()
+                .find(|v| v.name.eq_ignore_ascii_case(&view.name))
+            {
+                if !replace {
+                    return Err(CubeError::user(format!(
+                        "View '{}' already exists",
+                        view.name
+                    )));
+                }
+                *existing = view;
+            } else {
+                views.push(view);
+            }
+
+            Ok(())
+        }
+
+        async fn drop_view(&self, _ctx: AuthContextRef, name: String) -> Result<bool, CubeError> {
+            let mut views = self.views.write().unwrap();
+            let len = views.len();
+            views.retain(|v| !v.name.eq_ignore_ascii_case(&name));
+
+            Ok(views.len() != len)
+        }
     }
 
-    Arc::new(TestConnectionTransport {})
+    Arc::new(TestConnectionTransport {
+        views: RwLockSync::new(vec![]),
+    })
 }
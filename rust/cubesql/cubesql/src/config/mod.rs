@@ -2,15 +2,21 @@ pub mod injection;
 pub mod processing_loop;
 
 use crate::{
+    compile::engine::df::{result_processor::ResultProcessorConfig, scan::ResponseDateFormats},
     config::{
         injection::{DIService, Injector},
         processing_loop::ProcessingLoop,
     },
     sql::{
-        MySqlServer, PostgresServer, ServerManager, SessionManager, SqlAuthDefaultImpl,
-        SqlAuthService,
+        listen_address, BindValuesLogPolicy, DatabaseMapping, FeatureFlags, MySqlServer,
+        PostgresServer, ServerManager, ServerVersion, SessionManager, SocketOptions,
+        SqlAuthDefaultImpl, SqlAuthService,
+    },
+    transport::{
+        FederatedTransport, HttpClientOptions, HttpTransport, LocalSqlViewStore,
+        MetaSnapshotTransport, MetaStalePolicy, RecordingTransport, SchemaMount, ShadowTransport,
+        TransportLimits, TransportRecordMode, TransportService, TransportTimeouts,
     },
-    transport::{HttpTransport, TransportService},
     CubeError,
 };
 use futures::future::join_all;
@@ -108,6 +114,9 @@ impl CubeServices {
 pub struct Config {
     config_obj: Arc<ConfigObjImpl>,
     injector: Arc<Injector>,
+    // Services of the embedding application, they replace the built-in ones
+    transport: Option<Arc<dyn TransportService>>,
+    auth: Option<Arc<dyn SqlAuthService>>,
 }
 
 pub trait ConfigObj: DIService + Debug {
@@ -122,6 +131,99 @@ pub trait ConfigObj: DIService + Debug {
     fn disable_strict_agg_type_match(&self) -> bool;
 
     fn auth_expire_secs(&self) -> u64;
+
+    fn bind_values_log_policy(&self) -> BindValuesLogPolicy;
+
+    fn client_write_timeout(&self) -> u64;
+
+    fn idle_in_transaction_timeout(&self) -> u64;
+
+    fn portal_idle_timeout(&self) -> u64;
+
+    fn meta_snapshot_path(&self) -> &Option<String>;
+
+    fn mysql_socket_options(&self) -> &SocketOptions;
+
+    fn postgres_socket_options(&self) -> &SocketOptions;
+
+    fn transport_max_concurrent_loads(&self) -> usize;
+
+    fn transport_http_client(&self) -> &HttpClientOptions;
+
+    fn transport_timeouts(&self) -> &TransportTimeouts;
+
+    fn transport_limits(&self) -> &TransportLimits;
+
+    fn views_path(&self) -> &Option<String>;
+
+    fn transform_max_concurrency(&self) -> usize;
+
+    fn wrapper_max_sql_length(&self) -> usize;
+
+    fn last_result_max_size(&self) -> usize;
+
+    fn wrapper_max_params(&self) -> usize;
+
+    fn auth_warm_up(&self) -> bool;
+
+    fn server_version(&self) -> &ServerVersion;
+
+    fn transport_record_mode(&self) -> Option<TransportRecordMode>;
+
+    fn transport_record_path(&self) -> &String;
+
+    fn databases(&self) -> &DatabaseMapping;
+
+    fn shadow_base_path(&self) -> &Option<String>;
+
+    fn shadow_max_concurrent_loads(&self) -> usize;
+
+    fn response_date_formats(&self) -> &ResponseDateFormats;
+
+    fn stream_mode(&self) -> bool;
+
+    fn shared_scan_max_rows(&self) -> usize;
+
+    fn join_broadcast_max_rows(&self) -> usize;
+
+    fn dictionary_encoding_max_ratio(&self) -> f64;
+
+    fn data_source_collation(&self) -> &Option<String>;
+
+    fn schema_mounts(&self) -> &Vec<SchemaMount>;
+
+    fn folder_schemas(&self) -> bool;
+
+    fn planning_failure_cache_ttl_secs(&self) -> u64;
+
+    fn planning_failure_cache_max_entries(&self) -> usize;
+
+    fn features(&self) -> &FeatureFlags;
+
+    fn result_processors(&self) -> &Vec<ResultProcessorConfig>;
+
+    fn subscription_poll_interval_secs(&self) -> u64;
+
+    fn meta_stale_threshold_secs(&self) -> u64;
+
+    fn meta_stale_policy(&self) -> MetaStalePolicy;
+
+    fn memory_high_watermark_mb(&self) -> u64;
+
+    fn memory_low_watermark_mb(&self) -> u64;
+
+    fn memory_relief_timeout_secs(&self) -> u64;
+
+    fn replica_routing_min_limit(&self) -> i32;
+
+    fn custom_rewrite_max_rules(&self) -> usize;
+
+    fn custom_rewrite_max_iterations(&self) -> usize;
+
+    /// Memory (in MB) of sorts executed by SQL API, larger sorts spill to disk. 0 means unlimited
+    fn sort_memory_limit_mb(&self) -> usize;
+
+    fn sort_spill_path(&self) -> &Option<String>;
 }
 
 #[derive(Debug, Clone)]
@@ -133,6 +235,52 @@ pub struct ConfigObjImpl {
     pub auth_expire_secs: u64,
     pub timezone: Option<String>,
     pub disable_strict_agg_type_match: bool,
+    pub bind_values_log_policy: BindValuesLogPolicy,
+    pub client_write_timeout: u64,
+    pub idle_in_transaction_timeout: u64,
+    pub portal_idle_timeout: u64,
+    pub meta_snapshot_path: Option<String>,
+    pub mysql_socket_options: SocketOptions,
+    pub postgres_socket_options: SocketOptions,
+    pub transport_max_concurrent_loads: usize,
+    pub transport_http_client: HttpClientOptions,
+    pub transport_timeouts: TransportTimeouts,
+    pub transport_limits: TransportLimits,
+    pub views_path: Option<String>,
+    pub transform_max_concurrency: usize,
+    pub wrapper_max_sql_length: usize,
+    pub last_result_max_size: usize,
+    pub wrapper_max_params: usize,
+    pub auth_warm_up: bool,
+    pub server_version: ServerVersion,
+    pub transport_record_mode: Option<TransportRecordMode>,
+    pub transport_record_path: String,
+    pub databases: DatabaseMapping,
+    pub shadow_base_path: Option<String>,
+    pub shadow_max_concurrent_loads: usize,
+    pub response_date_formats: ResponseDateFormats,
+    pub stream_mode: bool,
+    pub shared_scan_max_rows: usize,
+    pub join_broadcast_max_rows: usize,
+    pub dictionary_encoding_max_ratio: f64,
+    pub data_source_collation: Option<String>,
+    pub schema_mounts: Vec<SchemaMount>,
+    pub folder_schemas: bool,
+    pub planning_failure_cache_ttl_secs: u64,
+    pub planning_failure_cache_max_entries: usize,
+    pub features: FeatureFlags,
+    pub result_processors: Vec<ResultProcessorConfig>,
+    pub subscription_poll_interval_secs: u64,
+    pub meta_stale_threshold_secs: u64,
+    pub meta_stale_policy: MetaStalePolicy,
+    pub memory_high_watermark_mb: u64,
+    pub memory_low_watermark_mb: u64,
+    pub memory_relief_timeout_secs: u64,
+    pub replica_routing_min_limit: i32,
+    pub custom_rewrite_max_rules: usize,
+    pub custom_rewrite_max_iterations: usize,
+    pub sort_memory_limit_mb: usize,
+    pub sort_spill_path: Option<String>,
 }
 
 impl ConfigObjImpl {
@@ -141,15 +289,19 @@ impl ConfigObjImpl {
             .ok()
             .map(|v| v.parse::<u64>().unwrap())
             .unwrap_or(120);
+        // `::` binds a dual-stack listener, e.g. in IPv6-only clusters
+        let bind_host = env::var("CUBESQL_BIND_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
         Self {
             bind_address: env::var("CUBESQL_BIND_ADDR").ok().or_else(|| {
                 env::var("CUBESQL_PORT")
                     .ok()
-                    .map(|v| format!("0.0.0.0:{}", v.parse::<u16>().unwrap()))
+                    .map(|v| listen_address(&bind_host, v.parse::<u16>().unwrap()))
+            }),
+            postgres_bind_address: env::var("CUBESQL_PG_BIND_ADDR").ok().or_else(|| {
+                env::var("CUBESQL_PG_PORT")
+                    .ok()
+                    .map(|port| listen_address(&bind_host, port.parse::<u16>().unwrap()))
             }),
-            postgres_bind_address: env::var("CUBESQL_PG_PORT")
-                .ok()
-                .map(|port| format!("0.0.0.0:{}", port.parse::<u16>().unwrap())),
             nonce: None,
             query_timeout,
             timezone: Some("UTC".to_string()),
@@ -158,6 +310,61 @@ impl ConfigObjImpl {
                 false,
             ),
             auth_expire_secs: env_parse("CUBESQL_AUTH_EXPIRE_SECS", 300),
+            bind_values_log_policy: env_parse("CUBESQL_LOG_BIND_VALUES", BindValuesLogPolicy::None),
+            client_write_timeout: env_parse("CUBESQL_CLIENT_WRITE_TIMEOUT", 60),
+            idle_in_transaction_timeout: env_parse("CUBESQL_PG_IDLE_IN_TRANSACTION_TIMEOUT", 0),
+            portal_idle_timeout: env_parse("CUBESQL_PG_PORTAL_IDLE_TIMEOUT", 0),
+            meta_snapshot_path: env::var("CUBESQL_META_SNAPSHOT").ok(),
+            mysql_socket_options: SocketOptions::from_env("MYSQL"),
+            postgres_socket_options: SocketOptions::from_env("PG"),
+            transport_max_concurrent_loads: env_parse("CUBESQL_TRANSPORT_MAX_CONCURRENT_LOADS", 0),
+            transport_http_client: HttpClientOptions::from_env(),
+            transport_timeouts: TransportTimeouts::from_env(),
+            transport_limits: TransportLimits::from_env(),
+            views_path: env::var("CUBESQL_VIEWS_PATH").ok(),
+            transform_max_concurrency: env_parse(
+                "CUBESQL_TRANSFORM_MAX_CONCURRENCY",
+                std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(4),
+            ),
+            wrapper_max_sql_length: env_parse("CUBESQL_WRAPPER_MAX_SQL_LENGTH", 16 * 1024 * 1024),
+            last_result_max_size: env_parse("CUBESQL_LAST_RESULT_MAX_SIZE", 0),
+            wrapper_max_params: env_parse("CUBESQL_WRAPPER_MAX_PARAMS", 65535),
+            auth_warm_up: env_parse("CUBESQL_AUTH_WARM_UP", false),
+            server_version: ServerVersion::from_env(),
+            transport_record_mode: env_optparse("CUBESQL_TRANSPORT_RECORD_MODE"),
+            transport_record_path: env::var("CUBESQL_TRANSPORT_RECORD_PATH")
+                .unwrap_or_else(|_| "cubesql-recording".to_string()),
+            databases: DatabaseMapping::from_env(),
+            shadow_base_path: env::var("CUBESQL_SHADOW_BASE_PATH").ok(),
+            shadow_max_concurrent_loads: env_parse("CUBESQL_SHADOW_MAX_CONCURRENT_LOADS", 4),
+            response_date_formats: ResponseDateFormats::from_env(),
+            stream_mode: env_parse("CUBESQL_STREAM_MODE", false),
+            shared_scan_max_rows: env_parse("CUBESQL_SHARED_SCAN_MAX_ROWS", 50000),
+            join_broadcast_max_rows: env_parse("CUBESQL_JOIN_BROADCAST_MAX_ROWS", 10000),
+            dictionary_encoding_max_ratio: env_parse("CUBESQL_DICTIONARY_ENCODING_MAX_RATIO", 0.0),
+            data_source_collation: env::var("CUBESQL_DATA_SOURCE_COLLATION").ok(),
+            schema_mounts: SchemaMount::from_env(),
+            folder_schemas: env_parse("CUBESQL_FOLDER_SCHEMAS", false),
+            planning_failure_cache_ttl_secs: env_parse("CUBESQL_PLANNING_FAILURE_CACHE_TTL", 30),
+            planning_failure_cache_max_entries: env_parse(
+                "CUBESQL_PLANNING_FAILURE_CACHE_MAX_ENTRIES",
+                1000,
+            ),
+            features: FeatureFlags::from_env(),
+            result_processors: ResultProcessorConfig::from_env(),
+            subscription_poll_interval_secs: env_parse("CUBESQL_SUBSCRIPTION_POLL_INTERVAL", 10),
+            meta_stale_threshold_secs: env_parse("CUBESQL_META_STALE_THRESHOLD", 0),
+            meta_stale_policy: env_parse("CUBESQL_META_STALE_POLICY", MetaStalePolicy::Reject),
+            memory_high_watermark_mb: env_parse("CUBESQL_MEMORY_HIGH_WATERMARK_MB", 0),
+            memory_low_watermark_mb: env_parse("CUBESQL_MEMORY_LOW_WATERMARK_MB", 0),
+            memory_relief_timeout_secs: env_parse("CUBESQL_MEMORY_RELIEF_TIMEOUT", 30),
+            replica_routing_min_limit: env_parse("CUBESQL_REPLICA_ROUTING_MIN_LIMIT", 0),
+            custom_rewrite_max_rules: env_parse("CUBESQL_CUSTOM_REWRITE_MAX_RULES", 256),
+            custom_rewrite_max_iterations: env_parse("CUBESQL_CUSTOM_REWRITE_MAX_ITERATIONS", 200),
+            sort_memory_limit_mb: env_parse("CUBESQL_SORT_MEMORY_LIMIT_MB", 0),
+            sort_spill_path: env::var("CUBESQL_SORT_SPILL_PATH").ok(),
         }
     }
 }
@@ -188,6 +395,190 @@ impl ConfigObj for ConfigObjImpl {
     fn auth_expire_secs(&self) -> u64 {
         self.auth_expire_secs
     }
+
+    fn bind_values_log_policy(&self) -> BindValuesLogPolicy {
+        self.bind_values_log_policy
+    }
+
+    fn client_write_timeout(&self) -> u64 {
+        self.client_write_timeout
+    }
+
+    fn idle_in_transaction_timeout(&self) -> u64 {
+        self.idle_in_transaction_timeout
+    }
+
+    fn portal_idle_timeout(&self) -> u64 {
+        self.portal_idle_timeout
+    }
+
+    fn meta_snapshot_path(&self) -> &Option<String> {
+        &self.meta_snapshot_path
+    }
+
+    fn mysql_socket_options(&self) -> &SocketOptions {
+        &self.mysql_socket_options
+    }
+
+    fn postgres_socket_options(&self) -> &SocketOptions {
+        &self.postgres_socket_options
+    }
+
+    fn transport_max_concurrent_loads(&self) -> usize {
+        self.transport_max_concurrent_loads
+    }
+
+    fn transport_http_client(&self) -> &HttpClientOptions {
+        &self.transport_http_client
+    }
+
+    fn transport_timeouts(&self) -> &TransportTimeouts {
+        &self.transport_timeouts
+    }
+
+    fn transport_limits(&self) -> &TransportLimits {
+        &self.transport_limits
+    }
+
+    fn views_path(&self) -> &Option<String> {
+        &self.views_path
+    }
+
+    fn transform_max_concurrency(&self) -> usize {
+        self.transform_max_concurrency
+    }
+
+    fn wrapper_max_sql_length(&self) -> usize {
+        self.wrapper_max_sql_length
+    }
+
+    fn last_result_max_size(&self) -> usize {
+        self.last_result_max_size
+    }
+
+    fn wrapper_max_params(&self) -> usize {
+        self.wrapper_max_params
+    }
+
+    fn auth_warm_up(&self) -> bool {
+        self.auth_warm_up
+    }
+
+    fn server_version(&self) -> &ServerVersion {
+        &self.server_version
+    }
+
+    fn transport_record_mode(&self) -> Option<TransportRecordMode> {
+        self.transport_record_mode
+    }
+
+    fn transport_record_path(&self) -> &String {
+        &self.transport_record_path
+    }
+
+    fn databases(&self) -> &DatabaseMapping {
+        &self.databases
+    }
+
+    fn shadow_base_path(&self) -> &Option<String> {
+        &self.shadow_base_path
+    }
+
+    fn shadow_max_concurrent_loads(&self) -> usize {
+        self.shadow_max_concurrent_loads
+    }
+
+    fn response_date_formats(&self) -> &ResponseDateFormats {
+        &self.response_date_formats
+    }
+
+    fn stream_mode(&self) -> bool {
+        self.stream_mode
+    }
+
+    fn shared_scan_max_rows(&self) -> usize {
+        self.shared_scan_max_rows
+    }
+
+    fn join_broadcast_max_rows(&self) -> usize {
+        self.join_broadcast_max_rows
+    }
+
+    fn dictionary_encoding_max_ratio(&self) -> f64 {
+        self.dictionary_encoding_max_ratio
+    }
+
+    fn data_source_collation(&self) -> &Option<String> {
+        &self.data_source_collation
+    }
+
+    fn schema_mounts(&self) -> &Vec<SchemaMount> {
+        &self.schema_mounts
+    }
+
+    fn folder_schemas(&self) -> bool {
+        self.folder_schemas
+    }
+
+    fn planning_failure_cache_ttl_secs(&self) -> u64 {
+        self.planning_failure_cache_ttl_secs
+    }
+
+    fn planning_failure_cache_max_entries(&self) -> usize {
+        self.planning_failure_cache_max_entries
+    }
+
+    fn features(&self) -> &FeatureFlags {
+        &self.features
+    }
+
+    fn result_processors(&self) -> &Vec<ResultProcessorConfig> {
+        &self.result_processors
+    }
+
+    fn subscription_poll_interval_secs(&self) -> u64 {
+        self.subscription_poll_interval_secs
+    }
+
+    fn meta_stale_threshold_secs(&self) -> u64 {
+        self.meta_stale_threshold_secs
+    }
+
+    fn meta_stale_policy(&self) -> MetaStalePolicy {
+        self.meta_stale_policy
+    }
+
+    fn memory_high_watermark_mb(&self) -> u64 {
+        self.memory_high_watermark_mb
+    }
+
+    fn memory_low_watermark_mb(&self) -> u64 {
+        self.memory_low_watermark_mb
+    }
+
+    fn memory_relief_timeout_secs(&self) -> u64 {
+        self.memory_relief_timeout_secs
+    }
+
+    fn replica_routing_min_limit(&self) -> i32 {
+        self.replica_routing_min_limit
+    }
+
+    fn custom_rewrite_max_rules(&self) -> usize {
+        self.custom_rewrite_max_rules
+    }
+
+    fn custom_rewrite_max_iterations(&self) -> usize {
+        self.custom_rewrite_max_iterations
+    }
+
+    fn sort_memory_limit_mb(&self) -> usize {
+        self.sort_memory_limit_mb
+    }
+
+    fn sort_spill_path(&self) -> &Option<String> {
+        &self.sort_spill_path
+    }
 }
 
 lazy_static! {
@@ -200,6 +591,8 @@ impl Config {
         Config {
             injector: Injector::new(),
             config_obj: Arc::new(ConfigObjImpl::default()),
+            transport: None,
+            auth: None,
         }
     }
 
@@ -216,7 +609,55 @@ impl Config {
                 auth_expire_secs: 60,
                 timezone,
                 disable_strict_agg_type_match: false,
+                bind_values_log_policy: BindValuesLogPolicy::None,
+                client_write_timeout: 15,
+                idle_in_transaction_timeout: 0,
+                portal_idle_timeout: 0,
+                meta_snapshot_path: None,
+                mysql_socket_options: SocketOptions::default(),
+                postgres_socket_options: SocketOptions::default(),
+                transport_max_concurrent_loads: 0,
+                transport_http_client: HttpClientOptions::default(),
+                transport_timeouts: TransportTimeouts::default(),
+                transport_limits: TransportLimits::default(),
+                views_path: None,
+                transform_max_concurrency: 2,
+                wrapper_max_sql_length: 16 * 1024 * 1024,
+                last_result_max_size: 0,
+                wrapper_max_params: 65535,
+                auth_warm_up: false,
+                server_version: ServerVersion::default(),
+                transport_record_mode: None,
+                transport_record_path: "cubesql-recording".to_string(),
+                databases: DatabaseMapping::default(),
+                shadow_base_path: None,
+                shadow_max_concurrent_loads: 4,
+                response_date_formats: ResponseDateFormats::default(),
+                stream_mode: false,
+                shared_scan_max_rows: 50000,
+                join_broadcast_max_rows: 10000,
+                dictionary_encoding_max_ratio: 0.0,
+                data_source_collation: None,
+                schema_mounts: vec![],
+                folder_schemas: false,
+                planning_failure_cache_ttl_secs: 0,
+                planning_failure_cache_max_entries: 1000,
+                features: FeatureFlags::default(),
+                result_processors: vec![],
+                subscription_poll_interval_secs: 1,
+                meta_stale_threshold_secs: 0,
+                meta_stale_policy: MetaStalePolicy::Reject,
+                memory_high_watermark_mb: 0,
+                memory_low_watermark_mb: 0,
+                memory_relief_timeout_secs: 30,
+                replica_routing_min_limit: 0,
+                custom_rewrite_max_rules: 256,
+                custom_rewrite_max_iterations: 200,
+                sort_memory_limit_mb: 0,
+                sort_spill_path: None,
             }),
+            transport: None,
+            auth: None,
         }
     }
 
@@ -228,9 +669,23 @@ impl Config {
         Self {
             injector: self.injector.clone(),
             config_obj: Arc::new(update_config(new_config)),
+            transport: self.transport.clone(),
+            auth: self.auth.clone(),
         }
     }
 
+    /// Serves the SQL API from a custom transport instead of Cube API, see [crate::ext]
+    pub fn with_transport(mut self, transport: Arc<dyn TransportService>) -> Config {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Authenticates connections by the embedding application, see [crate::ext]
+    pub fn with_auth_service(mut self, auth: Arc<dyn SqlAuthService>) -> Config {
+        self.auth = Some(auth);
+        self
+    }
+
     pub fn config_obj(&self) -> Arc<dyn ConfigObj> {
         self.config_obj.clone()
     }
@@ -239,24 +694,88 @@ impl Config {
         self.injector.clone()
     }
 
-    pub async fn configure_injector(&self) {
+    pub async fn configure_injector(&self) -> Result<(), CubeError> {
         let config_obj_to_register = self.config_obj.clone();
         self.injector
             .register_typed::<dyn ConfigObj, _, _, _>(async move |_| config_obj_to_register)
             .await;
 
+        // Files and clients are loaded at startup, a broken setting isn't reported as a panic of
+        // the first connection
+        let transport: Arc<dyn TransportService> =
+            if self.config_obj.transport_record_mode() == Some(TransportRecordMode::Replay) {
+                Arc::new(RecordingTransport::replay(
+                    self.config_obj.transport_record_path(),
+                )?)
+            } else if let Some(snapshot_path) = self.config_obj.meta_snapshot_path() {
+                Arc::new(MetaSnapshotTransport::try_from_file(snapshot_path)?)
+            } else {
+                let transport = HttpTransport::try_new(self.config_obj.transport_http_client())?
+                    .with_timeouts(self.config_obj.transport_timeouts().clone())
+                    .with_limits(self.config_obj.transport_limits().clone())
+                    .with_folder_schemas(self.config_obj.folder_schemas());
+                Arc::new(match self.config_obj.views_path() {
+                    Some(path) => transport.with_view_store(LocalSqlViewStore::new(path)),
+                    None => transport,
+                })
+            };
         self.injector
-            .register_typed::<dyn TransportService, _, _, _>(async move |_| {
-                Arc::new(HttpTransport::new())
-            })
+            .register_typed::<dyn TransportService, _, _, _>(async move |_| transport)
             .await;
 
+        let shadow_transport = match self.config_obj.shadow_base_path() {
+            Some(_) => Some(Arc::new(
+                HttpTransport::try_new(self.config_obj.transport_http_client())?
+                    .with_timeouts(self.config_obj.transport_timeouts().clone())
+                    .with_limits(self.config_obj.transport_limits().clone()),
+            )),
+            None => None,
+        };
+
+        let custom_transport = self.transport.clone();
+        let custom_auth = self.auth.clone();
         self.injector
             .register_typed::<ServerManager, _, _, _>(async move |i| {
                 let config = i.get_service_typed::<dyn ConfigObj>().await;
+                let transport = match custom_transport {
+                    Some(transport) => transport,
+                    None => i.get_service_typed::<dyn TransportService>().await,
+                };
+                let transport: Arc<dyn TransportService> = if config.schema_mounts().is_empty() {
+                    transport
+                } else {
+                    Arc::new(FederatedTransport::new(
+                        transport,
+                        config.schema_mounts().clone(),
+                    ))
+                };
+                // Only connections are recorded, not internal requests such as export-meta
+                let transport: Arc<dyn TransportService> = match config.transport_record_mode() {
+                    Some(TransportRecordMode::Record) => Arc::new(RecordingTransport::record(
+                        transport,
+                        config.transport_record_path(),
+                    )),
+                    _ => transport,
+                };
+                let transport: Arc<dyn TransportService> =
+                    match (config.shadow_base_path(), shadow_transport) {
+                        (Some(base_path), Some(shadow_transport)) => {
+                            Arc::new(ShadowTransport::new(
+                                transport,
+                                shadow_transport,
+                                base_path.clone(),
+                                config.shadow_max_concurrent_loads(),
+                            ))
+                        }
+                        _ => transport,
+                    };
+                let auth = match custom_auth {
+                    Some(auth) => auth,
+                    None => i.get_service_typed().await,
+                };
                 Arc::new(ServerManager::new(
-                    i.get_service_typed().await,
-                    i.get_service_typed().await,
+                    auth,
+                    transport,
                     config.nonce().clone(),
                     config.clone(),
                 ))
@@ -298,6 +817,8 @@ impl Config {
                 })
                 .await;
         }
+
+        Ok(())
     }
 
     pub async fn cube_services(&self) -> CubeServices {
@@ -306,12 +827,12 @@ impl Config {
         }
     }
 
-    pub async fn configure(&self) {
+    pub async fn configure(&self) -> Result<(), CubeError> {
         if let Some(timezone) = &self.config_obj.timezone {
             env::set_var("TZ", timezone.as_str());
         }
 
-        self.configure_injector().await;
+        self.configure_injector().await
     }
 }
 
@@ -323,7 +844,7 @@ where
     env_optparse(name).unwrap_or(default)
 }
 
-fn env_optparse<T>(name: &str) -> Option<T>
+pub fn env_optparse<T>(name: &str) -> Option<T>
 where
     T: FromStr,
     T::Err: Display,
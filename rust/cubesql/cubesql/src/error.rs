@@ -62,6 +62,20 @@ impl CubeError {
 }
 
 impl CubeError {
+    pub fn with_meta(mut self, meta: Option<HashMap<String, String>>) -> Self {
+        self.cause = match self.cause {
+            CubeErrorCauseType::User(_) => CubeErrorCauseType::User(meta),
+            CubeErrorCauseType::Internal(_) => CubeErrorCauseType::Internal(meta),
+        };
+        self
+    }
+
+    pub fn meta(&self) -> Option<&HashMap<String, String>> {
+        match &self.cause {
+            CubeErrorCauseType::User(meta) | CubeErrorCauseType::Internal(meta) => meta.as_ref(),
+        }
+    }
+
     pub fn backtrace(&self) -> Option<&Backtrace> {
         self.backtrace.as_ref()
     }
@@ -119,6 +133,9 @@ impl From<crate::compile::CompilationError> for CubeError {
             | crate::compile::CompilationError::Fatal(_, meta) => {
                 CubeErrorCauseType::Internal(meta.clone())
             }
+            crate::compile::CompilationError::MemberNotFound(_, meta) => {
+                CubeErrorCauseType::User(meta.clone())
+            }
         };
         let mut err = CubeError::internal_with_bt(v.to_string(), v.to_backtrace());
         err.cause = cause;
@@ -183,15 +200,49 @@ impl From<tokio::sync::broadcast::error::RecvError> for CubeError {
     }
 }
 
+/// Errors which are raised while executing a plan are passed through DataFusion as external ones
+fn unwrap_external_error(
+    error: &(dyn std::error::Error + Send + Sync + 'static),
+) -> Option<&CubeError> {
+    if let Some(error) = error.downcast_ref::<CubeError>() {
+        return Some(error);
+    }
+
+    match error.downcast_ref::<datafusion::error::DataFusionError>() {
+        Some(datafusion::error::DataFusionError::External(e)) => unwrap_external_error(e.as_ref()),
+        Some(datafusion::error::DataFusionError::ArrowError(
+            arrow::error::ArrowError::ExternalError(e),
+        )) => unwrap_external_error(e.as_ref()),
+        _ => match error.downcast_ref::<arrow::error::ArrowError>() {
+            Some(arrow::error::ArrowError::ExternalError(e)) => unwrap_external_error(e.as_ref()),
+            _ => None,
+        },
+    }
+}
+
 impl From<datafusion::error::DataFusionError> for CubeError {
     fn from(v: datafusion::error::DataFusionError) -> Self {
-        CubeError::internal(v.to_string())
+        match unwrap_external_error(&v) {
+            Some(e) => CubeError {
+                message: e.message.clone(),
+                cause: e.cause.clone(),
+                backtrace: None,
+            },
+            None => CubeError::internal(v.to_string()),
+        }
     }
 }
 
 impl From<arrow::error::ArrowError> for CubeError {
     fn from(v: arrow::error::ArrowError) -> Self {
-        CubeError::internal(v.to_string())
+        match unwrap_external_error(&v) {
+            Some(e) => CubeError {
+                message: e.message.clone(),
+                cause: e.cause.clone(),
+                backtrace: None,
+            },
+            None => CubeError::internal(v.to_string()),
+        }
     }
 }
 
@@ -0,0 +1,36 @@
+//! API for embedding cubesql with a custom transport, e.g. a backend which isn't Cube API.
+//!
+//! Items of this module follow semver of the crate: they are not changed or removed in patch
+//! and minor releases, new trait methods get default implementations. Other modules are
+//! internal and can change in any release.
+//!
+//! A transport implements [TransportService] and is passed to [Config::with_transport],
+//! connections are authenticated by [SqlAuthService] passed to [Config::with_auth_service]:
+//!
+//! ```ignore
+//! let config = Config::default()
+//!     .with_transport(Arc::new(MyTransport::new()))
+//!     .with_auth_service(Arc::new(MyAuth));
+//! config.configure().await?;
+//! config.cube_services().await.wait_processing_loops().await?;
+//! ```
+//!
+//! See `examples/custom_transport.rs` for a complete implementation.
+
+pub use async_trait::async_trait;
+pub use cubeclient::models::{
+    V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure, V1CubeMetaSegment, V1LoadRequestQuery,
+    V1LoadResponse, V1LoadResult, V1LoadResultAnnotation,
+};
+pub use datafusion::arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
+
+pub use crate::{
+    compile::{
+        engine::df::{scan::MemberField, wrapper::SqlQuery},
+        MetaContext,
+    },
+    config::Config,
+    sql::{AuthContext, AuthContextRef, AuthenticateResponse, SqlAuthService},
+    transport::{CubeStreamReceiver, LoadRequestMeta, SpanId, SqlResponse, TransportService},
+    CubeError,
+};
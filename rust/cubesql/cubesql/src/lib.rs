@@ -22,6 +22,7 @@ extern crate core;
 pub mod compile;
 pub mod config;
 pub mod error;
+pub mod ext;
 pub mod sql;
 pub mod telemetry;
 pub mod transport;
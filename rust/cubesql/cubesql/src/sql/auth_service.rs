@@ -2,12 +2,28 @@ use std::{any::Any, env, fmt::Debug, sync::Arc};
 
 use async_trait::async_trait;
 
-use crate::CubeError;
+use crate::{transport::QueryPriority, CubeError};
 
 // We cannot use generic here. It's why there is this trait
 // Any type will allow us to split (with downcast) auth context into HTTP (standalone) or Native
 pub trait AuthContext: Debug + Send + Sync {
     fn as_any(&self) -> &dyn Any;
+
+    /// Priority of loads declared by the security context, `SET cubesql_query_priority` wins
+    fn query_priority(&self) -> Option<QueryPriority> {
+        None
+    }
+
+    /// Features enabled or disabled for the security context, they win over `CUBESQL_FEATURES`
+    fn feature_overrides(&self) -> Option<Vec<(String, bool)>> {
+        None
+    }
+
+    /// Attributes of the security context which can be inspected by `cube_security_context()`,
+    /// nothing is exposed by default
+    fn security_context(&self) -> Option<serde_json::Value> {
+        None
+    }
 }
 
 pub type AuthContextRef = Arc<dyn AuthContext>;
@@ -38,6 +54,59 @@ pub trait SqlAuthService: Send + Sync + Debug {
         user: Option<String>,
         password: Option<String>,
     ) -> Result<AuthenticateResponse, CubeError>;
+
+    /// Called in the background after the connection is authenticated, e.g. to pre-fetch data
+    /// for the security context. Errors are logged, they don't affect the connection.
+    async fn post_auth(&self, _ctx: AuthContextRef) -> Result<(), CubeError> {
+        Ok(())
+    }
+
+    /// Security context acceptor for GSSAPI (Kerberos) authentication of Postgres connections.
+    /// New one is requested for every connection, password authentication is used for None.
+    /// cubesql doesn't ship an acceptor: Kerberos (or SSPI) bindings must be provided by
+    /// the embedding auth service.
+    async fn gss_authenticator(&self) -> Option<Box<dyn GssAuthenticator>> {
+        None
+    }
+
+    /// Maps the authenticated Kerberos principal to the requested user. By default, the realm
+    /// is stripped and the rest must match the user name.
+    async fn authenticate_principal(
+        &self,
+        principal: String,
+        user: String,
+    ) -> Result<AuthenticateResponse, CubeError> {
+        let name = principal.split('@').next().unwrap_or_default();
+        if name != user {
+            return Err(CubeError::user(format!(
+                "Kerberos principal \"{}\" is not allowed to connect as \"{}\"",
+                principal, user
+            )));
+        }
+
+        let mut response = self.authenticate(Some(user), None).await?;
+        response.skip_password_check = true;
+
+        Ok(response)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GssStep {
+    /// Token should be sent to the client, negotiation continues
+    Continue(Vec<u8>),
+    /// Security context is established for the principal, the last token (if any) is sent to
+    /// the client
+    Complete {
+        principal: String,
+        token: Option<Vec<u8>>,
+    },
+}
+
+/// Server side of a GSSAPI security context, tokens are exchanged until it's established
+#[async_trait]
+pub trait GssAuthenticator: Send + Sync + Debug {
+    async fn step(&mut self, token: Vec<u8>) -> Result<GssStep, CubeError>;
 }
 
 #[derive(Debug)]
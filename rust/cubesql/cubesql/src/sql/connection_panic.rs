@@ -0,0 +1,118 @@
+use futures::{future::poll_fn, FutureExt};
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::{Cell, RefCell},
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    sync::Once,
+};
+
+use crate::CubeError;
+
+static INSTALL_HOOK: Once = Once::new();
+
+thread_local! {
+    // Backtrace of the last panic on the thread, the stack is unwound before the panic is caught
+    static PANIC_BACKTRACE: RefCell<Option<String>> = RefCell::new(None);
+    // Connection handler is being polled on the thread
+    static IN_CONNECTION: Cell<bool> = Cell::new(false);
+}
+
+/// Panic of a connection handler which was caught instead of dropping the connection silently
+#[derive(Debug)]
+pub struct ConnectionPanic {
+    pub reason: String,
+    pub backtrace: Option<String>,
+}
+
+impl ConnectionPanic {
+    fn new(payload: Box<dyn Any + Send>) -> Self {
+        Self {
+            reason: CubeError::panic(payload).message,
+            backtrace: PANIC_BACKTRACE.with(|backtrace| backtrace.borrow_mut().take()),
+        }
+    }
+}
+
+/// Keeps the backtrace of panics of connection handlers, the previous hook (which prints
+/// the panic) is still called. Panics of other code don't capture backtraces.
+fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if IN_CONNECTION.with(|in_connection| in_connection.get()) {
+                let backtrace = Backtrace::force_capture().to_string();
+                PANIC_BACKTRACE.with(|cell| *cell.borrow_mut() = Some(backtrace));
+            }
+
+            previous(info);
+        }));
+    });
+}
+
+/// Resets the flag of the thread when the poll is finished or unwound
+struct ConnectionPollGuard;
+
+impl ConnectionPollGuard {
+    fn enter() -> Self {
+        IN_CONNECTION.with(|in_connection| in_connection.set(true));
+        Self
+    }
+}
+
+impl Drop for ConnectionPollGuard {
+    fn drop(&mut self) {
+        IN_CONNECTION.with(|in_connection| in_connection.set(false));
+    }
+}
+
+/// Runs the handler of a connection and catches its panic
+pub async fn catch_connection_panic<F: Future>(handler: F) -> Result<F::Output, ConnectionPanic> {
+    install_hook();
+
+    let mut handler = Box::pin(handler);
+    // Task can be moved between threads, so the flag is set for every poll
+    let handler = poll_fn(move |cx| {
+        let _guard = ConnectionPollGuard::enter();
+        handler.as_mut().poll(cx)
+    });
+
+    AssertUnwindSafe(handler)
+        .catch_unwind()
+        .await
+        .map_err(ConnectionPanic::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_catch_connection_panic() {
+        assert_eq!(catch_connection_panic(async { 1 }).await.unwrap(), 1);
+
+        let panic = catch_connection_panic(async {
+            let statements: Vec<u32> = vec![];
+            statements[0]
+        })
+        .await
+        .unwrap_err();
+        assert!(
+            panic.reason.contains("index out of bounds"),
+            "{}",
+            panic.reason
+        );
+        assert!(panic.backtrace.is_some());
+
+        // Panics outside of connection handlers are not captured
+        let result = panic::catch_unwind(|| {
+            let statements: Vec<u32> = vec![];
+            statements[0]
+        });
+        assert!(result.is_err());
+        assert!(PANIC_BACKTRACE
+            .with(|backtrace| backtrace.borrow_mut().take())
+            .is_none());
+    }
+}
@@ -208,5 +208,61 @@ pub fn defaults() -> DatabaseVariables {
       None,
   ),
 );
+    variables.insert(
+        "cubesql_query_priority".to_string(),
+        DatabaseVariable::system(
+            "cubesql_query_priority".to_string(),
+            ScalarValue::Utf8(None),
+            None,
+        ),
+    );
+    variables.insert(
+        "cubesql_shadow".to_string(),
+        DatabaseVariable::system(
+            "cubesql_shadow".to_string(),
+            ScalarValue::Boolean(Some(false)),
+            None,
+        ),
+    );
+    variables.insert(
+        "cubesql_planning_failure_cache".to_string(),
+        DatabaseVariable::system(
+            "cubesql_planning_failure_cache".to_string(),
+            ScalarValue::Boolean(Some(true)),
+            None,
+        ),
+    );
+    variables.insert(
+        "cube_scan_schema_cache".to_string(),
+        DatabaseVariable::system(
+            "cube_scan_schema_cache".to_string(),
+            ScalarValue::Boolean(Some(true)),
+            None,
+        ),
+    );
+    variables.insert(
+        "fiscal_year_start_month".to_string(),
+        DatabaseVariable::system(
+            "fiscal_year_start_month".to_string(),
+            ScalarValue::Int64(Some(1)),
+            None,
+        ),
+    );
+    variables.insert(
+        "cubesql_join_strategy".to_string(),
+        DatabaseVariable::system(
+            "cubesql_join_strategy".to_string(),
+            ScalarValue::Utf8(Some("auto".to_string())),
+            None,
+        ),
+    );
+    variables.insert(
+        "cubesql_max_records".to_string(),
+        DatabaseVariable::system(
+            "cubesql_max_records".to_string(),
+            ScalarValue::Utf8(Some("".to_string())),
+            None,
+        ),
+    );
     variables
 }
@@ -105,5 +105,95 @@ pub fn defaults() -> DatabaseVariables {
         ),
     );
 
+    variables.insert(
+        "cubesql_query_priority".to_string(),
+        DatabaseVariable::system(
+            "cubesql_query_priority".to_string(),
+            ScalarValue::Utf8(None),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "cubesql_shadow".to_string(),
+        DatabaseVariable::system(
+            "cubesql_shadow".to_string(),
+            ScalarValue::Boolean(Some(false)),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "cubesql_result_checksum".to_string(),
+        DatabaseVariable::system(
+            "cubesql_result_checksum".to_string(),
+            ScalarValue::Boolean(Some(false)),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "cubesql_planning_failure_cache".to_string(),
+        DatabaseVariable::system(
+            "cubesql_planning_failure_cache".to_string(),
+            ScalarValue::Boolean(Some(true)),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "cube_scan_schema_cache".to_string(),
+        DatabaseVariable::system(
+            "cube_scan_schema_cache".to_string(),
+            ScalarValue::Boolean(Some(true)),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "fiscal_year_start_month".to_string(),
+        DatabaseVariable::system(
+            "fiscal_year_start_month".to_string(),
+            ScalarValue::Int64(Some(1)),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "statement_timeout".to_string(),
+        DatabaseVariable::system(
+            "statement_timeout".to_string(),
+            ScalarValue::Utf8(Some("0".to_string())),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "cubesql_join_strategy".to_string(),
+        DatabaseVariable::system(
+            "cubesql_join_strategy".to_string(),
+            ScalarValue::Utf8(Some("auto".to_string())),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "cubesql_max_records".to_string(),
+        DatabaseVariable::system(
+            "cubesql_max_records".to_string(),
+            ScalarValue::Utf8(Some("".to_string())),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "cubesql_trace_next_query".to_string(),
+        DatabaseVariable::system(
+            "cubesql_trace_next_query".to_string(),
+            ScalarValue::Boolean(Some(false)),
+            None,
+        ),
+    );
+
     variables
 }
@@ -0,0 +1,137 @@
+use std::env;
+
+/// Databases which always exist on MySQL servers, clients switch to them during introspection
+pub const MYSQL_SYSTEM_DATABASES: [&str; 4] =
+    ["information_schema", "mysql", "performance_schema", "sys"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MappedDatabase {
+    pub name: String,
+    /// Cube API base path used for the database, None means the one from the auth context
+    pub base_path: Option<String>,
+}
+
+/// Databases exposed to MySQL clients via USE/SHOW DATABASES. Every database can point to
+/// its own Cube API deployment, so one endpoint can serve staging and production models.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseMapping {
+    pub databases: Vec<MappedDatabase>,
+    /// USE accepts only mapped and system databases. Without CUBESQL_DATABASES any name is
+    /// accepted, as clients can connect with an arbitrary database.
+    pub strict: bool,
+}
+
+impl Default for DatabaseMapping {
+    fn default() -> Self {
+        Self {
+            databases: vec![MappedDatabase {
+                name: "db".to_string(),
+                base_path: None,
+            }],
+            strict: false,
+        }
+    }
+}
+
+impl DatabaseMapping {
+    /// CUBESQL_DATABASES is a comma separated list of name or name=base_path entries
+    pub fn from_env() -> Self {
+        env::var("CUBESQL_DATABASES")
+            .ok()
+            .map(|v| Self::parse(&v))
+            .filter(|mapping| !mapping.databases.is_empty())
+            .unwrap_or_default()
+    }
+
+    fn parse(value: &str) -> Self {
+        let databases = value
+            .split(',')
+            .filter_map(|entry| {
+                let (name, base_path) = match entry.split_once('=') {
+                    Some((name, base_path)) => (name.trim(), Some(base_path.trim())),
+                    None => (entry.trim(), None),
+                };
+                if name.is_empty() {
+                    return None;
+                }
+
+                Some(MappedDatabase {
+                    name: name.to_string(),
+                    base_path: base_path.filter(|p| !p.is_empty()).map(|p| p.to_string()),
+                })
+            })
+            .collect();
+
+        Self {
+            databases,
+            strict: true,
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<&MappedDatabase> {
+        self.databases
+            .iter()
+            .find(|db| db.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Names for SHOW DATABASES, system databases are listed after the mapped ones
+    pub fn names(&self) -> Vec<String> {
+        self.databases
+            .iter()
+            .map(|db| db.name.clone())
+            .chain(MYSQL_SYSTEM_DATABASES.iter().map(|db| db.to_string()))
+            .collect()
+    }
+
+    pub fn is_system_database(name: &str) -> bool {
+        MYSQL_SYSTEM_DATABASES
+            .iter()
+            .any(|db| db.eq_ignore_ascii_case(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_mapping_parse() {
+        let mapping = DatabaseMapping::parse(
+            "staging = http://staging:4000/cubejs-api, prod=http://prod:4000/cubejs-api,,local",
+        );
+
+        assert_eq!(
+            mapping.databases,
+            vec![
+                MappedDatabase {
+                    name: "staging".to_string(),
+                    base_path: Some("http://staging:4000/cubejs-api".to_string()),
+                },
+                MappedDatabase {
+                    name: "prod".to_string(),
+                    base_path: Some("http://prod:4000/cubejs-api".to_string()),
+                },
+                MappedDatabase {
+                    name: "local".to_string(),
+                    base_path: None,
+                },
+            ]
+        );
+        assert_eq!(mapping.find("PROD").unwrap().name, "prod");
+        assert!(mapping.find("db").is_none());
+        assert!(mapping.strict);
+        assert!(!DatabaseMapping::default().strict);
+        assert_eq!(
+            mapping.names(),
+            vec![
+                "staging",
+                "prod",
+                "local",
+                "information_schema",
+                "mysql",
+                "performance_schema",
+                "sys"
+            ]
+        );
+    }
+}
@@ -10,11 +10,13 @@ use chrono_tz::Tz;
 use comfy_table::{Cell, Table};
 use datafusion::arrow::{
     array::{
-        Array, ArrayRef, BooleanArray, Date32Array, Date64Array, DecimalArray, Float16Array,
-        Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array,
-        IntervalDayTimeArray, IntervalMonthDayNanoArray, IntervalYearMonthArray, LargeStringArray,
-        ListArray, StringArray, TimestampMicrosecondArray, TimestampMillisecondArray,
-        TimestampNanosecondArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array,
+        Array, ArrayRef, BinaryArray, BooleanArray, Date32Array, Date64Array, DecimalArray,
+        Float16Array, Float32Array, Float64Array, Int16Array, Int32Array, Int32DictionaryArray,
+        Int64Array, Int8Array, IntervalDayTimeArray, IntervalMonthDayNanoArray,
+        IntervalYearMonthArray, LargeBinaryArray, LargeStringArray, ListArray, StringArray,
+        Time32MillisecondArray, Time32SecondArray, Time64MicrosecondArray, Time64NanosecondArray,
+        TimestampMicrosecondArray, TimestampMillisecondArray, TimestampNanosecondArray,
+        UInt16Array, UInt32Array, UInt64Array, UInt8Array,
     },
     datatypes::{DataType, IntervalUnit, Schema, TimeUnit},
     record_batch::RecordBatch,
@@ -101,6 +103,7 @@ pub enum TableValue {
     Date(NaiveDate),
     Timestamp(TimestampValue),
     Interval(IntervalValue),
+    Bytes(Vec<u8>),
 }
 
 impl ToString for TableValue {
@@ -119,6 +122,10 @@ impl ToString for TableValue {
             TableValue::Decimal128(v) => v.to_string(),
             TableValue::Interval(v) => v.to_string(),
             TableValue::List(v) => v.to_string(),
+            TableValue::Bytes(v) => v.iter().fold("\\x".to_string(), |mut hex, byte| {
+                hex.push_str(&format!("{:02x}", byte));
+                hex
+            }),
         }
     }
 }
@@ -373,13 +380,28 @@ macro_rules! convert_array {
     }};
 }
 
+macro_rules! convert_time_array {
+    ($ARRAY:expr, $NUM_ROWS:expr, $ROWS:expr, $ARRAY_TYPE: ident) => {{
+        let a = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        for i in 0..$NUM_ROWS {
+            $ROWS[i].push(match a.value_as_time(i) {
+                Some(time) if !a.is_null(i) => TableValue::String(time.to_string()),
+                _ => TableValue::Null,
+            });
+        }
+    }};
+}
+
 pub fn arrow_to_column_type(arrow_type: DataType) -> Result<ColumnType, CubeError> {
     match arrow_type {
-        DataType::Binary => Ok(ColumnType::Blob),
+        DataType::Binary | DataType::LargeBinary => Ok(ColumnType::Blob),
         DataType::Utf8 | DataType::LargeUtf8 => Ok(ColumnType::String),
+        DataType::Dictionary(_, value_type) => arrow_to_column_type(*value_type),
         DataType::Date32 => Ok(ColumnType::Date(false)),
         DataType::Date64 => Ok(ColumnType::Date(true)),
         DataType::Timestamp(_, _) => Ok(ColumnType::String),
+        // Time of day is returned as text
+        DataType::Time32(_) | DataType::Time64(_) => Ok(ColumnType::String),
         DataType::Interval(unit) => Ok(ColumnType::Interval(unit)),
         DataType::Float16 | DataType::Float32 | DataType::Float64 => Ok(ColumnType::Double),
         DataType::Boolean => Ok(ColumnType::Boolean),
@@ -451,6 +473,26 @@ pub fn batch_to_dataframe(
                         });
                     }
                 }
+                DataType::Binary => {
+                    let a = array.as_any().downcast_ref::<BinaryArray>().unwrap();
+                    for i in 0..num_rows {
+                        rows[i].push(if a.is_null(i) {
+                            TableValue::Null
+                        } else {
+                            TableValue::Bytes(a.value(i).to_vec())
+                        });
+                    }
+                }
+                DataType::LargeBinary => {
+                    let a = array.as_any().downcast_ref::<LargeBinaryArray>().unwrap();
+                    for i in 0..num_rows {
+                        rows[i].push(if a.is_null(i) {
+                            TableValue::Null
+                        } else {
+                            TableValue::Bytes(a.value(i).to_vec())
+                        });
+                    }
+                }
                 DataType::Date32 => {
                     let a = array.as_any().downcast_ref::<Date32Array>().unwrap();
                     for i in 0..num_rows {
@@ -475,6 +517,18 @@ pub fn batch_to_dataframe(
                         });
                     }
                 }
+                DataType::Time32(TimeUnit::Second) => {
+                    convert_time_array!(array, num_rows, rows, Time32SecondArray)
+                }
+                DataType::Time32(TimeUnit::Millisecond) => {
+                    convert_time_array!(array, num_rows, rows, Time32MillisecondArray)
+                }
+                DataType::Time64(TimeUnit::Microsecond) => {
+                    convert_time_array!(array, num_rows, rows, Time64MicrosecondArray)
+                }
+                DataType::Time64(TimeUnit::Nanosecond) => {
+                    convert_time_array!(array, num_rows, rows, Time64NanosecondArray)
+                }
                 DataType::Timestamp(TimeUnit::Millisecond, tz) => {
                     let a = array
                         .as_any()
@@ -546,7 +600,7 @@ pub fn batch_to_dataframe(
                                 hours,
                                 mins,
                                 secs,
-                                milliseconds_part % 1000,
+                                (milliseconds_part % 1000) * 1000,
                             )));
                         }
                     }
@@ -599,7 +653,7 @@ pub fn batch_to_dataframe(
                                 hours as i32,
                                 mins as i32,
                                 secs as i32,
-                                (nanoseconds_part % 1000000000) as i32,
+                                ((nanoseconds_part % 1000000000) / 1000) as i32,
                             )));
                         }
                     }
@@ -625,6 +679,24 @@ pub fn batch_to_dataframe(
                         });
                     }
                 }
+                DataType::Dictionary(key_type, value_type)
+                    if **key_type == DataType::Int32 && **value_type == DataType::Utf8 =>
+                {
+                    let a = array
+                        .as_any()
+                        .downcast_ref::<Int32DictionaryArray>()
+                        .unwrap();
+                    let keys = a.keys();
+                    let values = a.values();
+                    let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+                    for i in 0..num_rows {
+                        rows[i].push(if a.is_null(i) {
+                            TableValue::Null
+                        } else {
+                            TableValue::String(values.value(keys.value(i) as usize).to_string())
+                        });
+                    }
+                }
                 x => panic!("Unsupported data type: {:?}", x),
             }
         }
@@ -637,6 +709,8 @@ pub fn batch_to_dataframe(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use datafusion::arrow::datatypes::Field;
+    use std::sync::Arc;
 
     #[test]
     fn test_dataframe_print() {
@@ -658,4 +732,32 @@ mod tests {
             +------------+"
         );
     }
+
+    #[test]
+    fn test_batch_to_dataframe_time() {
+        let schema = Schema::new(vec![Field::new(
+            "time",
+            DataType::Time64(TimeUnit::Microsecond),
+            true,
+        )]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema.clone()),
+            vec![Arc::new(Time64MicrosecondArray::from(vec![
+                Some(49530125000),
+                None,
+            ]))],
+        )
+        .unwrap();
+
+        let frame = batch_to_dataframe(&schema, &vec![batch]).unwrap();
+        assert_eq!(frame.get_columns()[0].get_type(), ColumnType::String);
+        assert_eq!(
+            frame
+                .get_rows()
+                .iter()
+                .map(|row| format!("{:?}", row.values()))
+                .collect::<Vec<_>>(),
+            vec![r#"[String("13:45:30.125")]"#, "[Null]"]
+        );
+    }
 }
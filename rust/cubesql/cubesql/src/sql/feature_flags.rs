@@ -0,0 +1,277 @@
+use log::warn;
+use std::{
+    collections::hash_map::DefaultHasher,
+    env, fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use crate::sql::SessionState;
+
+/// Features which are rolled out gradually: name, enabled by default and description.
+/// Bigger planner changes should be guarded by a feature until they are proven by real traffic.
+pub const FEATURES: &[(&str, bool, &str)] = &[
+    (
+        "relative_date_ranges",
+        true,
+        "Comparisons of time dimensions with now() shifted by an interval are pushed down as relative date ranges",
+    ),
+    (
+        "scalar_subquery_inlining",
+        true,
+        "Uncorrelated scalar subqueries over cubes are evaluated once and inlined as literals",
+    ),
+    (
+        "collated_local_sort",
+        false,
+        "Strings sorted by SQL API are ordered by the session lc_collate instead of bytes",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeatureRollout {
+    Enabled,
+    Disabled,
+    /// Enabled for the percentage of connections
+    Percentage(u8),
+}
+
+impl FromStr for FeatureRollout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "on" | "true" | "enabled" => Ok(FeatureRollout::Enabled),
+            "off" | "false" | "disabled" => Ok(FeatureRollout::Disabled),
+            value => value
+                .strip_suffix('%')
+                .and_then(|percentage| percentage.trim().parse::<u8>().ok())
+                .filter(|percentage| *percentage <= 100)
+                .map(FeatureRollout::Percentage)
+                .ok_or_else(|| format!("expected on, off or a percentage, got '{}'", value)),
+        }
+    }
+}
+
+impl fmt::Display for FeatureRollout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeatureRollout::Enabled => write!(f, "on"),
+            FeatureRollout::Disabled => write!(f, "off"),
+            FeatureRollout::Percentage(percentage) => write!(f, "{}%", percentage),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionFeature {
+    pub name: &'static str,
+    pub enabled: bool,
+    /// Rollout from the config or `security_context` if it's overridden by the auth context
+    pub rollout: String,
+    pub description: &'static str,
+}
+
+/// Rollout of the features, consulted by the planner and the protocol shims for every session
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FeatureFlags {
+    rollouts: Vec<(String, FeatureRollout)>,
+}
+
+impl FeatureFlags {
+    /// CUBESQL_FEATURES is a comma separated list of name=on|off|<percentage>% entries
+    pub fn from_env() -> Self {
+        env::var("CUBESQL_FEATURES")
+            .ok()
+            .map(|v| Self::parse(&v))
+            .unwrap_or_default()
+    }
+
+    fn parse(value: &str) -> Self {
+        let rollouts = value
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .filter_map(|entry| {
+                let (name, rollout) = match entry.split_once('=') {
+                    Some((name, rollout)) => (name.trim().to_lowercase(), rollout),
+                    None => (entry.trim().to_lowercase(), "on"),
+                };
+                if !FEATURES.iter().any(|(feature, _, _)| *feature == name) {
+                    warn!("Ignoring unknown feature in CUBESQL_FEATURES: {}", name);
+                    return None;
+                }
+
+                match rollout.parse::<FeatureRollout>() {
+                    Ok(rollout) => Some((name, rollout)),
+                    Err(err) => {
+                        warn!("Ignoring feature {} in CUBESQL_FEATURES: {}", name, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Self { rollouts }
+    }
+
+    pub fn with_rollout(mut self, name: &str, rollout: FeatureRollout) -> Self {
+        self.rollouts.retain(|(feature, _)| feature != name);
+        self.rollouts.push((name.to_string(), rollout));
+        self
+    }
+
+    pub fn rollout(&self, name: &str) -> FeatureRollout {
+        if let Some((_, rollout)) = self.rollouts.iter().find(|(feature, _)| feature == name) {
+            return *rollout;
+        }
+
+        match FEATURES.iter().find(|(feature, _, _)| *feature == name) {
+            Some((_, true, _)) => FeatureRollout::Enabled,
+            _ => FeatureRollout::Disabled,
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str, state: &SessionState) -> bool {
+        self.session_feature_enabled(name, state).0
+    }
+
+    /// Features with their state for the session, e.g. for `SHOW cubesql_features`
+    pub fn session_features(&self, state: &SessionState) -> Vec<SessionFeature> {
+        FEATURES
+            .iter()
+            .map(|&(name, _, description)| {
+                let (enabled, rollout) = self.session_feature_enabled(name, state);
+
+                SessionFeature {
+                    name,
+                    enabled,
+                    rollout,
+                    description,
+                }
+            })
+            .collect()
+    }
+
+    fn session_feature_enabled(&self, name: &str, state: &SessionState) -> (bool, String) {
+        let overridden = state
+            .auth_context()
+            .and_then(|ctx| ctx.feature_overrides())
+            .and_then(|overrides| {
+                overrides
+                    .into_iter()
+                    .find(|(feature, _)| feature.eq_ignore_ascii_case(name))
+            });
+        if let Some((_, enabled)) = overridden {
+            return (enabled, "security_context".to_string());
+        }
+
+        let rollout = self.rollout(name);
+        let enabled = match rollout {
+            FeatureRollout::Enabled => true,
+            FeatureRollout::Disabled => false,
+            FeatureRollout::Percentage(percentage) => {
+                Self::bucket(name, state.connection_id) < percentage
+            }
+        };
+
+        (enabled, rollout.to_string())
+    }
+
+    /// Stable bucket (0..100) of the connection, every feature splits connections differently
+    fn bucket(name: &str, connection_id: u32) -> u8 {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        connection_id.hash(&mut hasher);
+
+        (hasher.finish() % 100) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::{AuthContext, DatabaseProtocol};
+    use std::{any::Any, sync::Arc, time::Duration};
+
+    #[derive(Debug)]
+    struct OverridingAuthContext;
+
+    impl AuthContext for OverridingAuthContext {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn feature_overrides(&self) -> Option<Vec<(String, bool)>> {
+            Some(vec![("relative_date_ranges".to_string(), false)])
+        }
+    }
+
+    fn session_state(connection_id: u32) -> SessionState {
+        SessionState::new(
+            connection_id,
+            "127.0.0.1".to_string(),
+            1234,
+            DatabaseProtocol::PostgreSQL,
+            None,
+            Duration::from_secs(60),
+        )
+    }
+
+    #[test]
+    fn test_feature_flags_parse() {
+        let flags =
+            FeatureFlags::parse("relative_date_ranges=off, scalar_subquery_inlining=10%, foo=on");
+        assert_eq!(
+            flags.rollout("relative_date_ranges"),
+            FeatureRollout::Disabled
+        );
+        assert_eq!(
+            flags.rollout("scalar_subquery_inlining"),
+            FeatureRollout::Percentage(10)
+        );
+        assert_eq!(flags.rollout("foo"), FeatureRollout::Disabled);
+
+        assert_eq!(
+            FeatureFlags::default().rollout("relative_date_ranges"),
+            FeatureRollout::Enabled
+        );
+        assert!("110%".parse::<FeatureRollout>().is_err());
+    }
+
+    #[test]
+    fn test_feature_flags_percentage() {
+        let flags = FeatureFlags::default()
+            .with_rollout("scalar_subquery_inlining", FeatureRollout::Percentage(10));
+
+        let enabled = (0..1000)
+            .filter(|id| flags.is_enabled("scalar_subquery_inlining", &session_state(*id)))
+            .count();
+        assert!(enabled > 50 && enabled < 150, "enabled for {}", enabled);
+
+        // The same connection always gets the same answer
+        let state = session_state(42);
+        assert_eq!(
+            flags.is_enabled("scalar_subquery_inlining", &state),
+            flags.is_enabled("scalar_subquery_inlining", &state)
+        );
+    }
+
+    #[test]
+    fn test_feature_flags_auth_context_override() {
+        let state = session_state(1);
+        state.set_auth_context(Some(Arc::new(OverridingAuthContext)));
+
+        let features = FeatureFlags::default().session_features(&state);
+        assert_eq!(
+            features
+                .iter()
+                .map(|f| (f.name, f.enabled, f.rollout.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("relative_date_ranges", false, "security_context"),
+                ("scalar_subquery_inlining", true, "on"),
+                ("collated_local_sort", false, "off"),
+            ]
+        );
+    }
+}
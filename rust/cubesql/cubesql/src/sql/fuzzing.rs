@@ -0,0 +1,61 @@
+//! Helpers of protocol fuzzing: valid client messages are mutated and sent to a connection
+//! handler, it must not panic or hang and must send only complete messages back.
+
+/// Deterministic xorshift generator, failures must be reproducible by the seed
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+/// Produces malformed variants of the messages: truncated, with bit flips, with corrupted
+/// length or counts, with garbage appended
+pub fn mutate(rng: &mut Rng, messages: &[u8]) -> Vec<u8> {
+    let mut result = messages.to_vec();
+    if result.is_empty() {
+        return rng.bytes(rng.below(64));
+    }
+
+    match rng.below(6) {
+        0 => result.truncate(rng.below(messages.len())),
+        1 => {
+            for _ in 0..=rng.below(4) {
+                let idx = rng.below(result.len());
+                result[idx] ^= 1u8 << rng.below(8);
+            }
+        }
+        2 => {
+            // Length or count fields with extreme values
+            let idx = rng.below(result.len());
+            let value = [0x00, 0x7f, 0x80, 0xff][rng.below(4)];
+            for byte in result.iter_mut().skip(idx).take(4) {
+                *byte = value;
+            }
+        }
+        3 => result.extend(rng.bytes(rng.below(32))),
+        4 => {
+            let idx = rng.below(result.len());
+            result.remove(idx);
+        }
+        _ => result = rng.bytes(rng.below(64)),
+    }
+
+    result
+}
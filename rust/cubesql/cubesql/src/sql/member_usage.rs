@@ -0,0 +1,274 @@
+use chrono::{DateTime, Utc};
+use cubeclient::models::{V1LoadRequestQuery, V1LoadRequestQueryFilterItem};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock as RwLockSync,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemberUsageKind {
+    Measure,
+    Dimension,
+    TimeDimension,
+    Segment,
+    /// Member was referenced only by filters
+    Filter,
+}
+
+impl MemberUsageKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MemberUsageKind::Measure => "measure",
+            MemberUsageKind::Dimension => "dimension",
+            MemberUsageKind::TimeDimension => "time_dimension",
+            MemberUsageKind::Segment => "segment",
+            MemberUsageKind::Filter => "filter",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MemberUsage {
+    pub cube: String,
+    pub kind: MemberUsageKind,
+    /// Number of executed queries which referenced the member
+    pub queries: u64,
+    /// Number of executed queries which filtered by the member
+    pub filters: u64,
+    pub last_used: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberUsageMetrics {
+    pub queries: u64,
+    pub members: usize,
+    pub cubes: usize,
+}
+
+/// Server-wide statistics of cube members referenced by executed queries. Data teams use it
+/// (via `cubesql.member_usage_stats`) to find unused dimensions and hot measures.
+#[derive(Debug)]
+pub struct MemberUsageStats {
+    members: RwLockSync<HashMap<String, MemberUsage>>,
+    queries: AtomicU64,
+}
+
+impl MemberUsageStats {
+    pub fn new() -> Self {
+        Self {
+            members: RwLockSync::new(HashMap::new()),
+            queries: AtomicU64::new(0),
+        }
+    }
+
+    /// Record members of load request which is going to be sent to Cube
+    pub fn record_request(&self, request: &V1LoadRequestQuery) {
+        let mut used: Vec<(String, MemberUsageKind)> = vec![];
+        let mut push_all = |members: &Option<Vec<String>>, kind: MemberUsageKind| {
+            for member in members.iter().flatten() {
+                used.push((member.clone(), kind.clone()));
+            }
+        };
+
+        push_all(&request.measures, MemberUsageKind::Measure);
+        push_all(&request.dimensions, MemberUsageKind::Dimension);
+        push_all(&request.segments, MemberUsageKind::Segment);
+        for time_dimension in request.time_dimensions.iter().flatten() {
+            used.push((
+                time_dimension.dimension.clone(),
+                MemberUsageKind::TimeDimension,
+            ));
+        }
+
+        let mut filtered = HashSet::new();
+        for filter in request.filters.iter().flatten() {
+            collect_filter_members(filter, &mut filtered);
+        }
+
+        self.record(used, filtered);
+    }
+
+    fn record(&self, used: Vec<(String, MemberUsageKind)>, filtered: HashSet<String>) {
+        if used.is_empty() && filtered.is_empty() {
+            return;
+        }
+
+        self.queries.fetch_add(1, Ordering::Relaxed);
+
+        let now = Utc::now();
+        let mut members = self
+            .members
+            .write()
+            .expect("failed to unlock member usage stats for writing");
+
+        let mut counted = HashSet::new();
+        let filter_only = filtered
+            .iter()
+            .filter(|member| !used.iter().any(|(m, _)| m == *member))
+            .map(|member| (member.clone(), MemberUsageKind::Filter))
+            .collect::<Vec<_>>();
+
+        for (member, kind) in used.into_iter().chain(filter_only) {
+            // The same member can be used twice by one query, e.g. as dimension & time dimension
+            if !counted.insert(member.clone()) {
+                continue;
+            }
+
+            let usage = members
+                .entry(member.clone())
+                .or_insert_with(|| MemberUsage {
+                    cube: member.split('.').next().unwrap_or_default().to_string(),
+                    kind: kind.clone(),
+                    queries: 0,
+                    filters: 0,
+                    last_used: now,
+                });
+            // Filter-only usage doesn't tell the real type of the member
+            if usage.kind == MemberUsageKind::Filter {
+                usage.kind = kind;
+            }
+            usage.queries += 1;
+            if filtered.contains(&member) {
+                usage.filters += 1;
+            }
+            usage.last_used = now;
+        }
+    }
+
+    /// Copy of the collected statistics, sorted by member name
+    pub fn snapshot(&self) -> Vec<(String, MemberUsage)> {
+        let members = self
+            .members
+            .read()
+            .expect("failed to unlock member usage stats for reading");
+
+        let mut result = members
+            .iter()
+            .map(|(member, usage)| (member.clone(), usage.clone()))
+            .collect::<Vec<_>>();
+        result.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        result
+    }
+
+    pub fn metrics(&self) -> MemberUsageMetrics {
+        let members = self
+            .members
+            .read()
+            .expect("failed to unlock member usage stats for reading");
+
+        MemberUsageMetrics {
+            queries: self.queries.load(Ordering::Relaxed),
+            members: members.len(),
+            cubes: members
+                .values()
+                .map(|usage| usage.cube.as_str())
+                .collect::<HashSet<_>>()
+                .len(),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.members
+            .write()
+            .expect("failed to unlock member usage stats for writing")
+            .clear();
+        self.queries.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Members which are referenced by the filter, including nested logical filters
+pub fn collect_filter_members(
+    filter: &V1LoadRequestQueryFilterItem,
+    members: &mut HashSet<String>,
+) {
+    if let Some(member) = &filter.member {
+        members.insert(member.clone());
+    }
+
+    for nested in filter.or.iter().chain(filter.and.iter()).flatten() {
+        if let Ok(nested) = serde_json::from_value::<V1LoadRequestQueryFilterItem>(nested.clone()) {
+            collect_filter_members(&nested, members);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cubeclient::models::V1LoadRequestQueryTimeDimension;
+    use serde_json::json;
+
+    #[test]
+    fn test_member_usage_stats() {
+        let stats = MemberUsageStats::new();
+
+        stats.record_request(&V1LoadRequestQuery {
+            measures: Some(vec!["Orders.count".to_string()]),
+            dimensions: Some(vec!["Orders.createdAt".to_string()]),
+            segments: None,
+            time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
+                dimension: "Orders.createdAt".to_string(),
+                granularity: Some("day".to_string()),
+                date_range: None,
+            }]),
+            order: None,
+            limit: None,
+            offset: None,
+            filters: Some(vec![V1LoadRequestQueryFilterItem {
+                member: None,
+                operator: None,
+                values: None,
+                or: Some(vec![
+                    json!({ "member": "Orders.status", "operator": "set" }),
+                    json!({ "member": "Orders.count", "operator": "gt", "values": ["1"] }),
+                ]),
+                and: None,
+            }]),
+            ungrouped: None,
+            ..Default::default()
+        });
+        stats.record_request(&V1LoadRequestQuery {
+            measures: Some(vec!["Orders.count".to_string()]),
+            ..V1LoadRequestQuery::new()
+        });
+        // Queries without members (e.g. count(*) over cube) are not counted
+        stats.record_request(&V1LoadRequestQuery::new());
+
+        let snapshot = stats.snapshot();
+        let members = snapshot
+            .iter()
+            .map(|(member, usage)| {
+                (
+                    member.as_str(),
+                    usage.cube.as_str(),
+                    usage.kind.as_str(),
+                    usage.queries,
+                    usage.filters,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            members,
+            vec![
+                ("Orders.count", "Orders", "measure", 2, 1),
+                ("Orders.createdAt", "Orders", "dimension", 1, 0),
+                ("Orders.status", "Orders", "filter", 1, 1),
+            ]
+        );
+        assert_eq!(
+            stats.metrics(),
+            MemberUsageMetrics {
+                queries: 2,
+                members: 3,
+                cubes: 1,
+            }
+        );
+
+        stats.reset();
+        assert!(stats.snapshot().is_empty());
+    }
+}
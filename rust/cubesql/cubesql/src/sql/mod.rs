@@ -1,23 +1,57 @@
 pub(crate) mod auth_service;
+pub(crate) mod connection_panic;
 pub(crate) mod database_variables;
+pub(crate) mod databases;
 pub(crate) mod dataframe;
+pub(crate) mod feature_flags;
+#[cfg(test)]
+pub(crate) mod fuzzing;
+pub(crate) mod member_usage;
 pub(crate) mod mysql;
+pub(crate) mod planning_failures;
 pub(crate) mod postgres;
+pub(crate) mod prepared_statements;
+pub(crate) mod proxy_protocol;
 pub(crate) mod server_manager;
+pub(crate) mod server_version;
 pub(crate) mod service;
 pub(crate) mod session;
 pub(crate) mod session_manager;
+pub(crate) mod socket;
 pub(crate) mod statement;
+pub(crate) mod statement_classes;
+pub(crate) mod statement_schemas;
 pub(crate) mod types;
+pub(crate) mod unsupported_queries;
 
 pub use auth_service::{
-    AuthContext, AuthContextRef, AuthenticateResponse, HttpAuthContext, SqlAuthDefaultImpl,
-    SqlAuthService,
+    AuthContext, AuthContextRef, AuthenticateResponse, GssAuthenticator, GssStep, HttpAuthContext,
+    SqlAuthDefaultImpl, SqlAuthService,
+};
+pub use connection_panic::{catch_connection_panic, ConnectionPanic};
+pub use databases::{DatabaseMapping, MappedDatabase};
+pub use feature_flags::{FeatureFlags, FeatureRollout, SessionFeature, FEATURES};
+pub use member_usage::{
+    collect_filter_members, MemberUsage, MemberUsageKind, MemberUsageMetrics, MemberUsageStats,
 };
 pub use mysql::*;
+pub use planning_failures::PlanningFailures;
 pub use postgres::*;
+pub use prepared_statements::{PreparedStatementUsage, PreparedStatements};
+pub use proxy_protocol::TrustedSources;
 pub use server_manager::ServerManager;
+pub use server_version::ServerVersion;
 pub use service::*;
-pub use session::{Session, SessionProcessList, SessionProperties, SessionState};
+pub use session::{
+    DatabaseProtocol, Session, SessionEndReason, SessionProcessList, SessionProperties,
+    SessionState,
+};
 pub use session_manager::SessionManager;
+pub use socket::{bind_listener, listen_address, SocketOptions};
+pub use statement::BindValuesLogPolicy;
+pub use statement_classes::{StatementClass, StatementClassStats, StatementClassUsage};
+pub use statement_schemas::{StatementSchemas, StatementSchemasKey, StatementSchemasStats};
 pub use types::{ColumnFlags, ColumnType, StatusFlags};
+pub use unsupported_queries::{
+    UnsupportedQuery, UnsupportedQueryCategory, UnsupportedQueryMetrics, UnsupportedQueryStats,
+};
@@ -1,12 +1,19 @@
-use std::{collections::HashMap, io};
+use std::io;
 
-use std::{sync::Arc, time::SystemTime};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
 
 use async_trait::async_trait;
 
 use datafusion::prelude::DataFrame as DFDataFrame;
 
-use log::{debug, error, trace};
+use log::{debug, error, info, trace, warn};
 
 //use msql_srv::*;
 use msql_srv::{
@@ -15,12 +22,18 @@ use msql_srv::{
 };
 
 use tokio::{
-    net::TcpListener,
+    io::AsyncWriteExt,
+    net::TcpStream,
     sync::{watch, RwLock},
 };
 
 use crate::{
-    compile::{convert_sql_to_cube_query, parser::parse_sql_to_statement},
+    compile::{
+        convert_sql_to_cube_query,
+        engine::df::{last_result::LastResultBuffer, scan_schemas::ScanSchemaCache},
+        parser::parse_sql_to_statement,
+        MemberNotFound,
+    },
     config::processing_loop::ProcessingLoop,
     telemetry::{ContextLogger, SessionLogger},
     CubeErrorCauseType,
@@ -28,11 +41,12 @@ use crate::{
 
 use crate::{
     sql::{
+        bind_listener, catch_connection_panic,
         dataframe::{self, batch_to_dataframe},
         session::DatabaseProtocol,
         statement::{MySQLStatementParamsFinder, MysqlStatementParamsBinder},
-        AuthContextRef, ColumnFlags, ColumnType, QueryResponse, Session, SessionManager,
-        StatusFlags,
+        AuthContextRef, ColumnFlags, ColumnType, PreparedStatements, QueryResponse, Session,
+        SessionEndReason, SessionManager, StatementSchemasKey, StatusFlags,
     },
     CubeError,
 };
@@ -42,16 +56,16 @@ use sqlparser::ast;
 use tokio::sync::oneshot;
 
 #[derive(Debug)]
-struct PreparedStatements {
+struct MySqlPreparedStatements {
     id: u32,
-    statements: HashMap<u32, ast::Statement>,
+    statements: PreparedStatements<u32, (ast::Statement, Arc<ScanSchemaCache>)>,
 }
 
-impl PreparedStatements {
+impl MySqlPreparedStatements {
     pub fn new() -> Self {
         Self {
             id: 1,
-            statements: HashMap::new(),
+            statements: PreparedStatements::new(),
         }
     }
 }
@@ -59,13 +73,39 @@ impl PreparedStatements {
 #[derive(Debug)]
 struct MySqlConnection {
     // Prepared statements
-    statements: Arc<RwLock<PreparedStatements>>,
+    statements: Arc<RwLock<MySqlPreparedStatements>>,
     // Shared
     session: Arc<Session>,
     logger: Arc<dyn ContextLogger>,
+    // Set by the first command, msql_srv dispatches commands only after the password check
+    authenticated: bool,
+    // Sequence id of the ERR packet which is sent when the handler panics
+    error_sequence_id: Arc<AtomicU8>,
 }
 
 impl MySqlConnection {
+    fn new(session: Arc<Session>, logger: Arc<dyn ContextLogger>) -> Self {
+        Self {
+            statements: Arc::new(RwLock::new(MySqlPreparedStatements::new())),
+            session,
+            logger,
+            authenticated: false,
+            // Server greeting is packet 0, handshake response of the client is packet 1
+            error_sequence_id: Arc::new(AtomicU8::new(2)),
+        }
+    }
+
+    /// msql_srv checks the password after on_auth and has no hook after the check, so the
+    /// session is warmed up by the first command of the connection
+    fn on_command(&mut self) {
+        if !self.authenticated {
+            self.authenticated = true;
+            self.session.warm_up();
+            // Command of the client is packet 0, the response starts with packet 1
+            self.error_sequence_id.store(1, Ordering::SeqCst);
+        }
+    }
+
     // This method write response back to client after execution
     async fn handle_query<'a, W: io::Write + Send>(
         &'a mut self,
@@ -88,7 +128,15 @@ impl MySqlConnection {
                     trace!("Backtrace: not found");
                 }
 
-                results.error(ErrorKind::ER_INTERNAL_ERROR, e.message.as_bytes())?;
+                let kind = match &e.cause {
+                    CubeErrorCauseType::User(Some(meta))
+                        if meta.contains_key(MemberNotFound::META_KEY) =>
+                    {
+                        ErrorKind::ER_BAD_FIELD_ERROR
+                    }
+                    _ => ErrorKind::ER_INTERNAL_ERROR,
+                };
+                results.error(kind, e.message.as_bytes())?;
 
                 Ok(())
             }
@@ -114,6 +162,7 @@ impl MySqlConnection {
                     for (_i, value) in row.values().iter().enumerate() {
                         match value {
                             dataframe::TableValue::String(s) => rw.write_col(s)?,
+                            dataframe::TableValue::Bytes(s) => rw.write_col(s.as_slice())?,
                             dataframe::TableValue::Timestamp(s) => rw.write_col(s.to_string())?,
                             dataframe::TableValue::Boolean(s) => {
                                 rw.write_col(if *s == true { 1_u8 } else { 0_u8 })?
@@ -123,6 +172,7 @@ impl MySqlConnection {
                             dataframe::TableValue::Int16(s) => rw.write_col(s)?,
                             dataframe::TableValue::Int32(s) => rw.write_col(s)?,
                             dataframe::TableValue::Int64(s) => rw.write_col(s)?,
+                            dataframe::TableValue::Interval(s) => rw.write_col(s.to_string())?,
                             dataframe::TableValue::Null => rw.write_col(Option::<String>::None)?,
                             dt => unimplemented!("Not supported type for MySQL: {:?}", dt),
                         }
@@ -148,6 +198,11 @@ impl MySqlConnection {
         let query_lower = query_lower.replace("db.`", "");
         let query_lower = query_lower.replace("`", "");
 
+        // Warnings are kept until the next statement, SHOW WARNINGS reads them
+        if !query_lower.starts_with("show warnings") {
+            self.session.state.take_warnings();
+        }
+
         let ignore = match query_lower.as_str() {
             "rollback" => true,
             "commit" => true,
@@ -222,6 +277,15 @@ impl MySqlConnection {
                     let batches = df.collect().await?;
                     let response = batch_to_dataframe(&df.schema().into(), &batches)?;
 
+                    let mut last_result = LastResultBuffer::new(
+                        ctx.sessions.server.config_obj.last_result_max_size(),
+                    );
+                    for batch in batches.iter() {
+                        last_result.push(batch);
+                    }
+                    ctx.session_state
+                        .set_last_result(last_result.finish(Arc::new(df.schema().into())));
+
                     return Ok(QueryResponse::ResultSet(status, Box::new(response)))
                 }
             }
@@ -250,7 +314,12 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
     type Error = io::Error;
 
     fn server_version(&self) -> &str {
-        "8.0.25"
+        self.session
+            .server
+            .config_obj
+            .server_version()
+            .mysql
+            .as_str()
     }
 
     fn connection_id(&self) -> u32 {
@@ -263,46 +332,56 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
         info: StatementMetaWriter<'a, W>,
     ) -> Result<(), Self::Error> {
         debug!("[mysql] on_execute: {}", input);
+        self.on_command();
 
         let mut statement =
             match parse_sql_to_statement(&input.to_string(), DatabaseProtocol::MySQL, &mut None) {
                 Ok(s) => s,
                 Err(e) => {
+                    self.session.report_unsupported_query(&e);
                     info.error(ErrorKind::ER_PARSE_ERROR, e.to_string().as_bytes())?;
                     return Ok(());
                 }
             };
 
         let stmt_prepare = MySQLStatementParamsFinder::new();
-        let paramaters: Vec<Column> = stmt_prepare
+        let found_parameters = stmt_prepare
             .find(&mut statement)
-            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?
-            .into_iter()
-            .map(|p| p.into())
-            .collect();
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+        let scan_schemas = if self.session.state.scan_schema_cache_enabled() {
+            self.session
+                .state
+                .statement_schemas
+                .get_or_insert(StatementSchemasKey::new(
+                    &statement,
+                    found_parameters.iter().map(|p| p.coltype.clone()).collect(),
+                ))
+        } else {
+            Arc::new(ScanSchemaCache::new())
+        };
+        let paramaters: Vec<Column> = found_parameters.into_iter().map(|p| p.into()).collect();
 
         let mut state = self.statements.write().await;
-        if state.statements.len()
-            >= self
-                .session
+        state.id = state.id + 1;
+
+        let next_id = state.id;
+        let evicted = state.statements.insert(
+            next_id,
+            (statement, scan_schemas),
+            self.session
                 .server
                 .configuration
-                .connection_max_prepared_statements
-        {
-            let message = format!(
-                "Unable to allocate new prepared statement, max allocation reached, actual: {}, max: {}",
-                state.statements.len(),
-                self.session.server.configuration.connection_max_prepared_statements
-            );
-            info.error(ErrorKind::ER_INTERNAL_ERROR, message.as_bytes())
-        } else {
-            state.id = state.id + 1;
-
-            let next_id = state.id;
-            state.statements.insert(next_id, statement);
+                .connection_max_prepared_statements,
+        );
 
-            info.reply(state.id, &paramaters, &[])
+        for id in evicted {
+            debug!(
+                "[mysql] Prepared statement {} was deallocated (connection_id: {}): max allocation reached",
+                id, self.session.state.connection_id
+            );
         }
+
+        info.reply(next_id, &paramaters, &[])
     }
 
     async fn on_execute<'a>(
@@ -312,10 +391,11 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
         results: QueryResultWriter<'a, W>,
     ) -> Result<(), Self::Error> {
         debug!("[mysql] on_execute: {}", id);
+        self.on_command();
 
-        let mut statement = {
-            let state = self.statements.read().await;
-            let possible_statement = state.statements.get(&id);
+        let (mut statement, scan_schemas) = {
+            let mut state = self.statements.write().await;
+            let possible_statement = state.statements.get_for_execution(&id);
 
             if possible_statement.is_none() {
                 return results.error(ErrorKind::ER_INTERNAL_ERROR, b"Unknown statement");
@@ -358,16 +438,38 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
             values_to_bind.push(bind_value);
         }
 
+        if let Some(params) = self
+            .session
+            .server
+            .config_obj
+            .bind_values_log_policy()
+            .format(&values_to_bind)
+        {
+            info!(
+                "[mysql] Execute statement {} (connection_id: {}): params={}",
+                id, self.session.state.connection_id, params
+            );
+        }
+
         let binder = MysqlStatementParamsBinder::new(values_to_bind);
         binder
             .bind(&mut statement)
             .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
 
-        self.handle_query(statement.to_string().as_str(), results)
-            .await
+        if self.session.state.scan_schema_cache_enabled() {
+            self.session.state.set_scan_schemas(Some(scan_schemas));
+        }
+        let result = self
+            .handle_query(statement.to_string().as_str(), results)
+            .await;
+        self.session.state.set_scan_schemas(None);
+
+        result
     }
 
-    /// On close will be called when client sends COM_STMT_CLOSE
+    /// On close will be called when client sends COM_STMT_CLOSE. COM_STMT_RESET doesn't reach the
+    /// shim: msql-srv clears long data of the statement and replies OK, the statement stays
+    /// prepared with its usage stats.
     async fn on_close<'a>(&'a mut self, id: u32)
     where
         W: 'async_trait,
@@ -388,6 +490,7 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
         results: QueryResultWriter<'a, W>,
     ) -> Result<(), Self::Error> {
         debug!("[mysql] on_query: {}", query);
+        self.on_command();
 
         self.handle_query(query, results).await
     }
@@ -419,6 +522,8 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
         let passwd = auth_response.password.map(|p| p.as_bytes().to_vec());
 
         self.session.state.set_user(user.clone());
+        // Session is not warmed up here: the password is checked by the protocol library
+        // after on_auth, warming up an unauthenticated connection is not safe. See on_command.
         self.session
             .state
             .set_auth_context(Some(auth_response.context));
@@ -446,6 +551,7 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
         writter: InitWriter<'a, W>,
     ) -> Result<(), Self::Error> {
         debug!("[mysql] on_init: USE {}", database);
+        self.on_command();
 
         if self
             .execute_query(&format!("USE {}", database))
@@ -474,7 +580,7 @@ crate::di_service!(MySqlServer, []);
 #[async_trait]
 impl ProcessingLoop for MySqlServer {
     async fn processing_loop(&self) -> Result<(), CubeError> {
-        let listener = TcpListener::bind(self.address.clone()).await?;
+        let listener = bind_listener(&self.address).await?;
 
         println!("🔗 Cube SQL is listening on {}", self.address);
 
@@ -501,51 +607,108 @@ impl ProcessingLoop for MySqlServer {
                 }
             };
 
-            let (client_addr, client_port) = match socket.peer_addr() {
-                Ok(peer_addr) => (peer_addr.ip().to_string(), peer_addr.port()),
+            if let Err(err) = self
+                .session_manager
+                .server
+                .config_obj
+                .mysql_socket_options()
+                .apply(&socket)
+            {
+                warn!("[mysql] Unable to set socket options: {}", err);
+            }
+
+            let peer_addr = match socket.peer_addr() {
+                Ok(peer_addr) => peer_addr,
                 Err(e) => {
                     error!(
                         "[mysql] Error while calling peer_addr() on TcpStream: {}",
                         e
                     );
 
-                    ("127.0.0.1".to_string(), 0000_u16)
+                    SocketAddr::from(([127, 0, 0, 1], 0000_u16))
                 }
             };
 
-            let session = self
-                .session_manager
-                .create_session(DatabaseProtocol::MySQL, client_addr, client_port)
-                .await;
+            let session_manager = self.session_manager.clone();
+            tokio::spawn(async move {
+                let mut socket = socket;
+                // PROXY protocol header is read outside of the accept loop, slow peers can't block it
+                let client_addr = match session_manager
+                    .server
+                    .config_obj
+                    .mysql_socket_options()
+                    .client_addr(&mut socket, peer_addr)
+                    .await
+                {
+                    Ok(client_addr) => client_addr,
+                    Err(e) => {
+                        error!(
+                            "[mysql] Unable to read PROXY protocol header from {}: {}",
+                            peer_addr, e
+                        );
+
+                        return;
+                    }
+                };
 
-            let logger = Arc::new(SessionLogger::new(session.state.clone()));
+                let session = session_manager
+                    .create_session(
+                        DatabaseProtocol::MySQL,
+                        client_addr.ip().to_string(),
+                        client_addr.port(),
+                    )
+                    .await;
 
-            let (mut tx, rx) = oneshot::channel::<()>();
+                let logger = Arc::new(SessionLogger::new(session.state.clone()));
 
-            let connection_id = session.state.connection_id;
-            let session_manager = self.session_manager.clone();
-            tokio::spawn(async move {
-                tx.closed().await;
+                let (mut tx, rx) = oneshot::channel::<()>();
 
-                trace!("[mysql] Removing connection {}", connection_id);
+                let connection_id = session.state.connection_id;
+                tokio::spawn(async move {
+                    tx.closed().await;
 
-                session_manager.drop_session(connection_id).await;
-            });
+                    trace!("[mysql] Removing connection {}", connection_id);
 
-            tokio::spawn(async move {
-                let handler = AsyncMysqlIntermediary::run_on(
-                    MySqlConnection {
-                        session,
-                        statements: Arc::new(RwLock::new(PreparedStatements::new())),
-                        logger: logger.clone(),
-                    },
-                    socket,
-                );
-                if let Err(e) = handler.await {
-                    logger.error(
-                        format!("Error during processing MySQL connection: {}", e).as_str(),
-                        None,
-                    );
+                    session_manager.drop_session(connection_id).await;
+                });
+
+                // Handler owns the socket, the clone is used to deliver the error if it panics
+                let (socket, mut error_socket) = match clone_socket(socket) {
+                    Ok(sockets) => sockets,
+                    Err(e) => {
+                        error!("[mysql] Unable to clone socket: {}", e);
+
+                        return;
+                    }
+                };
+
+                let connection = MySqlConnection::new(session.clone(), logger.clone());
+                let error_sequence_id = connection.error_sequence_id.clone();
+                let handler = AsyncMysqlIntermediary::run_on(connection, socket);
+                match catch_connection_panic(handler).await {
+                    // COM_QUIT and closed sockets are not distinguished by msql_srv
+                    Ok(Ok(())) => session.end(SessionEndReason::Closed).await,
+                    Ok(Err(e)) => {
+                        session.end(SessionEndReason::Error).await;
+                        logger.error(
+                            format!("Error during processing MySQL connection: {}", e).as_str(),
+                            None,
+                        );
+                    }
+                    Err(panic) => {
+                        session.report_panic(&panic);
+                        session.end(SessionEndReason::Panic).await;
+
+                        let packet = error_packet(
+                            ErrorKind::ER_UNKNOWN_ERROR,
+                            &panic.reason,
+                            error_sequence_id.load(Ordering::SeqCst),
+                        );
+                        if let Err(e) = error_socket.write_all(&packet).await {
+                            trace!("[mysql] Unable to deliver panic to the client: {}", e);
+                        }
+                        let _ = error_socket.shutdown().await;
+                    }
                 }
 
                 // Handler can finish with panic, it's why we are using additional channel to drop session by moving it here
@@ -560,6 +723,30 @@ impl ProcessingLoop for MySqlServer {
     }
 }
 
+fn clone_socket(socket: TcpStream) -> io::Result<(TcpStream, TcpStream)> {
+    let socket = socket.into_std()?;
+    let clone = socket.try_clone()?;
+
+    Ok((TcpStream::from_std(socket)?, TcpStream::from_std(clone)?))
+}
+
+/// ERR packet, it's sent as the response to the handshake or the command which was processed
+/// when the handler panicked. msql_srv writes the response to the socket only after the shim
+/// returns, so the client hasn't got any packet of the response and the sequence id follows
+/// the last packet of the client.
+fn error_packet(kind: ErrorKind, message: &str, sequence_id: u8) -> Vec<u8> {
+    let mut payload = vec![0xFF];
+    payload.extend_from_slice(&(kind as u16).to_le_bytes());
+    payload.extend_from_slice(b"#HY000");
+    payload.extend_from_slice(message.as_bytes());
+
+    let mut packet = (payload.len() as u32).to_le_bytes()[..3].to_vec();
+    packet.push(sequence_id);
+    packet.extend(payload);
+
+    packet
+}
+
 impl MySqlServer {
     pub fn new(address: String, session_manager: Arc<SessionManager>) -> Arc<Self> {
         let (close_socket_tx, close_socket_rx) = watch::channel(false);
@@ -571,3 +758,263 @@ impl MySqlServer {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compile::test::{get_test_session, get_test_session_with_config, get_test_transport},
+        config::ConfigObjImpl,
+        sql::{
+            fuzzing::{mutate, Rng},
+            AuthenticateResponse, HttpAuthContext, ServerManager, SqlAuthService,
+        },
+        telemetry::SessionLogger,
+    };
+    use std::{sync::atomic::AtomicUsize, time::Duration};
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    fn packet(seq: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = (payload.len() as u32).to_le_bytes()[..3].to_vec();
+        packet.push(seq);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn handshake_response() -> Vec<u8> {
+        // CLIENT_PROTOCOL_41 | CLIENT_SECURE_CONNECTION | CLIENT_PLUGIN_AUTH
+        let mut payload = (0x0000_0200u32 | 0x0000_8000 | 0x0008_0000)
+            .to_le_bytes()
+            .to_vec();
+        payload.extend_from_slice(&(16u32 * 1024 * 1024).to_le_bytes());
+        // utf8_general_ci
+        payload.push(33);
+        payload.extend_from_slice(&[0; 23]);
+        payload.extend_from_slice(b"test\0");
+        // Empty auth response
+        payload.push(0);
+        payload.extend_from_slice(b"mysql_native_password\0");
+        packet(1, &payload)
+    }
+
+    fn command(command: u8, body: &[u8]) -> Vec<u8> {
+        packet(0, &[&[command][..], body].concat())
+    }
+
+    /// Packets sent by the server must be complete, ERR packets must have the code and SQL state
+    fn assert_server_packets(mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            assert!(bytes.len() >= 4, "truncated packet header");
+            let length = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) as usize;
+            assert!(bytes.len() >= length + 4, "truncated packet");
+            let payload = &bytes[4..length + 4];
+            if payload.first() == Some(&0xFF) {
+                assert!(payload.len() >= 9, "truncated ERR packet");
+                assert_eq!(payload[3], b'#', "ERR packet without SQL state");
+            }
+            bytes = &bytes[length + 4..];
+        }
+    }
+
+    /// Malformed and truncated handshakes and commands, the handler must close the connection
+    /// without a panic
+    #[tokio::test]
+    async fn test_fuzz_packets() {
+        // Statement 1 with a LONGLONG parameter
+        let execute = [
+            vec![1, 0, 0, 0, 0, 1, 0, 0, 0],
+            vec![0, 1, 0x08, 0],
+            42u64.to_le_bytes().to_vec(),
+        ]
+        .concat();
+        let corpus = vec![
+            handshake_response(),
+            [handshake_response(), command(0x03, b"SELECT 1")].concat(),
+            [
+                handshake_response(),
+                command(0x16, b"SELECT ?"),
+                command(0x17, &execute),
+                command(0x19, &[1, 0, 0, 0]),
+            ]
+            .concat(),
+            [handshake_response(), command(0x0e, &[]), command(0x01, &[])].concat(),
+        ];
+        let mut rng = Rng::new(0xc0ffee);
+
+        for _ in 0..200 {
+            let input = mutate(&mut rng, &corpus[rng.below(corpus.len())]);
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap())
+                .await
+                .unwrap();
+            let (socket, _) = listener.accept().await.unwrap();
+            let session = get_test_session(DatabaseProtocol::MySQL).await;
+            let logger = Arc::new(SessionLogger::new(session.state.clone()));
+            let handler = tokio::spawn(AsyncMysqlIntermediary::run_on(
+                MySqlConnection::new(session, logger),
+                socket,
+            ));
+
+            client.write_all(&input).await.unwrap();
+            client.shutdown().await.unwrap();
+
+            let mut output = vec![];
+            let read =
+                tokio::time::timeout(Duration::from_secs(10), client.read_to_end(&mut output))
+                    .await
+                    .expect("connection must be closed after the client is gone");
+            let result = handler.await;
+            assert!(
+                !matches!(&result, Err(err) if err.is_panic()),
+                "handler panicked on {:?}",
+                input
+            );
+            // Connection can be reset when the handler doesn't read the rest of the input
+            if read.is_ok() {
+                assert_server_packets(&output);
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct PostAuthCounter {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl SqlAuthService for PostAuthCounter {
+        async fn authenticate(
+            &self,
+            _user: Option<String>,
+            password: Option<String>,
+        ) -> Result<AuthenticateResponse, CubeError> {
+            Ok(AuthenticateResponse {
+                context: Arc::new(HttpAuthContext {
+                    access_token: "fake".to_string(),
+                    base_path: "fake".to_string(),
+                }),
+                password,
+                skip_password_check: false,
+            })
+        }
+
+        async fn post_auth(&self, _ctx: AuthContextRef) -> Result<(), CubeError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_post_auth() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let server = Arc::new(ServerManager::new(
+            Arc::new(PostAuthCounter {
+                calls: calls.clone(),
+            }),
+            get_test_transport(),
+            None,
+            Arc::new(ConfigObjImpl::default()),
+        ));
+        let session = SessionManager::new(server)
+            .create_session(DatabaseProtocol::MySQL, "127.0.0.1".to_string(), 1234)
+            .await;
+        let logger = Arc::new(SessionLogger::new(session.state.clone()));
+        let mut connection = MySqlConnection::new(session.clone(), logger);
+
+        // Password is not checked yet
+        AsyncMysqlShim::<Vec<u8>>::on_auth(&mut connection, b"test".to_vec())
+            .await
+            .unwrap();
+        assert!(session.state.auth_context().is_some());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        connection.on_command();
+        connection.on_command();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while calls.load(Ordering::SeqCst) == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("post auth hook must be called after the first command");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_error_packet_sequence() {
+        let session = get_test_session(DatabaseProtocol::MySQL).await;
+        let logger = Arc::new(SessionLogger::new(session.state.clone()));
+        let mut connection = MySqlConnection::new(session, logger);
+        let sequence_id = connection.error_sequence_id.clone();
+
+        // Panic during the handshake is reported after the handshake response
+        assert_eq!(sequence_id.load(Ordering::SeqCst), 2);
+        connection.on_command();
+        assert_eq!(sequence_id.load(Ordering::SeqCst), 1);
+
+        let packet = error_packet(ErrorKind::ER_UNKNOWN_ERROR, "panic", 1);
+        assert_eq!(&packet[..4], &[14, 0, 0, 1]);
+        assert_server_packets(&packet);
+    }
+
+    async fn query_value(
+        connection: &mut MySqlConnection,
+        query: &str,
+    ) -> Result<String, CubeError> {
+        match connection.execute_query(query).await? {
+            QueryResponse::ResultSet(_, frame) => Ok(frame.get_rows()[0].values()[0].to_string()),
+            _ => panic!("Expected result set"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cube_last_result() -> Result<(), CubeError> {
+        let config = ConfigObjImpl {
+            last_result_max_size: 1024 * 1024,
+            ..ConfigObjImpl::default()
+        };
+        let session = get_test_session_with_config(DatabaseProtocol::MySQL, Arc::new(config)).await;
+        let logger = Arc::new(SessionLogger::new(session.state.clone()));
+        let mut connection = MySqlConnection::new(session, logger);
+
+        assert!(
+            query_value(&mut connection, "SELECT COUNT(*) FROM cube_last_result()")
+                .await
+                .is_err()
+        );
+        query_value(
+            &mut connection,
+            "SELECT 1 AS n UNION ALL SELECT 2 AS n UNION ALL SELECT 3 AS n",
+        )
+        .await?;
+        assert_eq!(
+            query_value(
+                &mut connection,
+                "SELECT SUM(n) AS total FROM cube_last_result() WHERE n > 1"
+            )
+            .await?,
+            "5"
+        );
+        // The previous statement is the aggregation
+        assert_eq!(
+            query_value(&mut connection, "SELECT COUNT(*) FROM cube_last_result()").await?,
+            "1"
+        );
+
+        // Results are not retained by default
+        let session = get_test_session(DatabaseProtocol::MySQL).await;
+        let logger = Arc::new(SessionLogger::new(session.state.clone()));
+        let mut connection = MySqlConnection::new(session, logger);
+        query_value(&mut connection, "SELECT 1 AS n").await?;
+        assert!(
+            query_value(&mut connection, "SELECT COUNT(*) FROM cube_last_result()")
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use sha1_smol::Sha1;
+use sqlparser::ast;
+
+use crate::{
+    compile::{CompilationError, MetaContext},
+    sql::SessionState,
+};
+
+struct PlanningFailure {
+    error: CompilationError,
+    // Failure is valid only for the data model it was produced with
+    meta: Weak<MetaContext>,
+    expires_at: Instant,
+}
+
+/// Failures of planning which don't depend on the state of Cube (unsupported constructs, unknown
+/// members, rewrites which can't be found) are cached for a short ttl, so clients which retry
+/// the same query fail fast instead of paying for the rewrite again.
+pub struct PlanningFailures {
+    ttl: Duration,
+    max_entries: usize,
+    failures: Mutex<HashMap<String, PlanningFailure>>,
+}
+
+impl fmt::Debug for PlanningFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlanningFailures")
+            .field("ttl", &self.ttl)
+            .field("max_entries", &self.max_entries)
+            .field("failures", &self.len())
+            .finish()
+    }
+}
+
+impl PlanningFailures {
+    /// Zero ttl disables the cache
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero() && self.max_entries > 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.failures.lock().unwrap().len()
+    }
+
+    /// Statement together with the session state which affects its planning
+    pub fn fingerprint(state: &SessionState, stmt: &ast::Statement) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(
+            format!(
+                "{}\n{:?}\n{:?}\n{:?}\n{}",
+                state.protocol,
+                state.user(),
+                state.database(),
+                state.pushdown_hint(),
+                stmt
+            )
+            .as_bytes(),
+        );
+
+        hasher.digest().to_string()
+    }
+
+    pub fn get(&self, fingerprint: &str, meta: &Arc<MetaContext>) -> Option<CompilationError> {
+        let mut failures = self.failures.lock().unwrap();
+        let failure = failures.get(fingerprint)?;
+        let is_valid = failure.expires_at > Instant::now()
+            && failure
+                .meta
+                .upgrade()
+                .map(|m| Arc::ptr_eq(&m, meta))
+                .unwrap_or(false);
+        if !is_valid {
+            failures.remove(fingerprint);
+            return None;
+        }
+
+        Self::cacheable(&failure.error)
+    }
+
+    pub fn insert(&self, fingerprint: String, meta: &Arc<MetaContext>, error: &CompilationError) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let error = match Self::cacheable(error) {
+            Some(error) => error,
+            None => return,
+        };
+
+        let mut failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+        failures.retain(|_, failure| failure.expires_at > now);
+        if failures.len() >= self.max_entries && !failures.contains_key(&fingerprint) {
+            return;
+        }
+
+        failures.insert(
+            fingerprint,
+            PlanningFailure {
+                error,
+                meta: Arc::downgrade(meta),
+                expires_at: now + self.ttl,
+            },
+        );
+    }
+
+    /// Internal and fatal errors can be caused by the state of the server or Cube, they are
+    /// never cached
+    fn cacheable(error: &CompilationError) -> Option<CompilationError> {
+        match error {
+            CompilationError::User(message, meta) => {
+                Some(CompilationError::User(message.clone(), meta.clone()))
+            }
+            CompilationError::Unsupported(message, meta) => {
+                Some(CompilationError::Unsupported(message.clone(), meta.clone()))
+            }
+            CompilationError::MemberNotFound(err, meta) => {
+                Some(CompilationError::MemberNotFound(err.clone(), meta.clone()))
+            }
+            CompilationError::Internal(_, _, _) | CompilationError::Fatal(_, _) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> Arc<MetaContext> {
+        Arc::new(MetaContext::new(vec![], HashMap::new(), HashMap::new()))
+    }
+
+    #[test]
+    fn test_planning_failures() {
+        let failures = PlanningFailures::new(Duration::from_secs(60), 2);
+        let meta = meta();
+
+        failures.insert(
+            "a".to_string(),
+            &meta,
+            &CompilationError::unsupported("Unsupported query".to_string()),
+        );
+        failures.insert(
+            "b".to_string(),
+            &meta,
+            &CompilationError::internal("Transport error".to_string()),
+        );
+        assert_eq!(
+            failures.get("a", &meta),
+            Some(CompilationError::unsupported(
+                "Unsupported query".to_string()
+            ))
+        );
+        assert_eq!(failures.get("b", &meta), None);
+
+        // Failure of another data model is dropped
+        assert_eq!(failures.get("a", &self::meta()), None);
+        assert_eq!(failures.len(), 0);
+
+        // Entries over the limit are not cached
+        for key in ["a", "b", "c"] {
+            failures.insert(
+                key.to_string(),
+                &meta,
+                &CompilationError::user("Error".to_string()),
+            );
+        }
+        assert_eq!(failures.len(), 2);
+        assert!(failures.get("c", &meta).is_none());
+
+        let disabled = PlanningFailures::new(Duration::ZERO, 2);
+        disabled.insert(
+            "a".to_string(),
+            &meta,
+            &CompilationError::user("Error".to_string()),
+        );
+        assert_eq!(disabled.len(), 0);
+    }
+}
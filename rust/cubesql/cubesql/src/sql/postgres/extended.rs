@@ -1,7 +1,10 @@
 use crate::{
-    compile::QueryPlan,
+    compile::{
+        engine::df::{last_result::LastResultStream, scan_schemas::ScanSchemaCache},
+        QueryPlan,
+    },
     sql::{
-        dataframe::{batch_to_dataframe, DataFrame, TableValue},
+        dataframe::{batch_to_dataframe, DataFrame, TableValue, TimestampValue},
         statement::PostgresStatementParamsBinder,
         writer::BatchWriter,
     },
@@ -10,8 +13,9 @@ use crate::{
 use chrono::{DateTime, Utc};
 use datafusion::arrow::record_batch::RecordBatch;
 use pg_srv::{protocol, BindValue, PgTypeId, ProtocolError};
+use sha1_smol::Sha1;
 use sqlparser::ast;
-use std::{fmt, pin::Pin, sync::Arc};
+use std::{fmt, pin::Pin, sync::Arc, time::Instant};
 
 use crate::sql::shim::{ConnectionError, QueryPlanExt};
 use datafusion::{
@@ -56,6 +60,7 @@ pub enum PreparedStatement {
         /// for example BEGIN
         description: Option<protocol::RowDescription>,
         span_id: Option<Arc<SpanId>>,
+        scan_schemas: Arc<ScanSchemaCache>,
     },
 }
 
@@ -189,9 +194,47 @@ pub enum PortalFrom {
 pub enum PortalBatch {
     Description(protocol::RowDescription),
     Rows(BatchWriter),
+    Notice(protocol::NoticeResponse),
     Completion(protocol::PortalCompletion),
 }
 
+/// Row count and SHA-1 of the DataRow messages of the whole result. It's sent to the client
+/// as a notice after the last row, clients can compare it with the received data to detect
+/// truncated transfers.
+pub struct ResultChecksum {
+    rows: u64,
+    hasher: Sha1,
+}
+
+impl fmt::Debug for ResultChecksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResultChecksum")
+            .field("rows", &self.rows)
+            .finish()
+    }
+}
+
+impl ResultChecksum {
+    pub fn new() -> Self {
+        Self {
+            rows: 0,
+            hasher: Sha1::new(),
+        }
+    }
+
+    pub fn update(&mut self, writer: &BatchWriter) {
+        self.rows += writer.num_rows() as u64;
+        self.hasher.update(writer.data());
+    }
+
+    pub fn to_notice(&self) -> protocol::NoticeResponse {
+        protocol::NoticeResponse::info(
+            protocol::ErrorCode::SuccessfulCompletion,
+            format!("rows={} sha1={}", self.rows, self.hasher.digest()),
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct Portal {
     // Format which is used to return data
@@ -200,6 +243,11 @@ pub struct Portal {
     // State which holds corresponding data for each step. Option is used for dereferencing
     state: Option<PortalState>,
     span_id: Option<Arc<SpanId>>,
+    // Last execution or creation, idle portals are closed to release buffered results
+    last_used: Instant,
+    checksum: Option<ResultChecksum>,
+    // Time zone of the session, timestamptz values are written in it
+    timezone: Option<String>,
 }
 
 unsafe impl Send for Portal {}
@@ -237,6 +285,9 @@ impl Portal {
             from,
             span_id,
             state: Some(PortalState::Prepared(PreparedState { plan })),
+            last_used: Instant::now(),
+            checksum: None,
+            timezone: None,
         }
     }
 
@@ -250,9 +301,38 @@ impl Portal {
             from,
             span_id,
             state: Some(PortalState::Empty),
+            last_used: Instant::now(),
+            checksum: None,
+            timezone: None,
         }
     }
 
+    pub fn last_used(&self) -> Instant {
+        self.last_used
+    }
+
+    /// Starts checksum calculation, portal can be executed multiple times before the result is read
+    pub fn enable_checksum(&mut self) {
+        if self.checksum.is_none() {
+            self.checksum = Some(ResultChecksum::new());
+        }
+    }
+
+    pub fn set_timezone(&mut self, timezone: Option<String>) {
+        self.timezone = timezone;
+    }
+
+    fn update_checksum(&mut self, writer: &BatchWriter) {
+        if let Some(checksum) = &mut self.checksum {
+            checksum.update(writer);
+        }
+    }
+
+    /// Notice with the checksum when the result is read completely
+    fn take_checksum_notice(&mut self) -> Option<protocol::NoticeResponse> {
+        self.checksum.take().map(|checksum| checksum.to_notice())
+    }
+
     pub fn get_description(&self) -> Result<Option<protocol::RowDescription>, ConnectionError> {
         match &self.state {
             Some(PortalState::Prepared(state)) => state.plan.to_row_description(self.format),
@@ -304,6 +384,7 @@ impl Portal {
             } else {
                 let writer = self.dataframe_to_writer(frame_state.batch)?;
                 let num_rows = writer.num_rows() as u32;
+                self.update_checksum(&writer);
 
                 if let Some(description) = &frame_state.description {
                     yield Ok(PortalBatch::Description(description.clone()));
@@ -315,6 +396,10 @@ impl Portal {
                     description: frame_state.description,
                 }));
 
+                if let Some(notice) = self.take_checksum_notice() {
+                    yield Ok(PortalBatch::Notice(notice));
+                }
+
                 return yield Ok(PortalBatch::Completion(self.new_portal_completion(num_rows, false)));
             }
         }
@@ -353,10 +438,17 @@ impl Portal {
                     TableValue::Float32(v) => writer.write_value(v)?,
                     TableValue::Float64(v) => writer.write_value(v)?,
                     TableValue::List(v) => writer.write_value(v)?,
-                    TableValue::Timestamp(v) => writer.write_value(v)?,
+                    TableValue::Timestamp(v) => match (&self.timezone, v.tz_ref()) {
+                        (Some(timezone), Some(_)) => writer.write_value(TimestampValue::new(
+                            v.get_time_stamp(),
+                            Some(timezone.clone()),
+                        ))?,
+                        _ => writer.write_value(v)?,
+                    },
                     TableValue::Date(v) => writer.write_value(v)?,
                     TableValue::Decimal128(v) => writer.write_value(v)?,
                     TableValue::Interval(v) => writer.write_value(v)?,
+                    TableValue::Bytes(v) => writer.write_value(v)?,
                 };
             }
 
@@ -411,6 +503,7 @@ impl Portal {
                 let (usused_batch, batch_writer) = self.iterate_stream_batch(unused_batch, max_rows, &mut left)?;
                 stream_state.unused = usused_batch;
                 num_of_rows = batch_writer.num_rows() as u32;
+                self.update_checksum(&batch_writer);
 
                 yield Ok(PortalBatch::Rows(batch_writer));
             }
@@ -428,6 +521,10 @@ impl Portal {
                             description: stream_state.description,
                         }));
 
+                        if let Some(notice) = self.take_checksum_notice() {
+                            yield Ok(PortalBatch::Notice(notice));
+                        }
+
                         return yield Ok(PortalBatch::Completion(self.new_portal_completion(num_of_rows, false)));
                     }
                     Some(res) => match res {
@@ -435,6 +532,7 @@ impl Portal {
                             let (unused_batch, writer) = self.iterate_stream_batch(batch, max_rows, &mut left)?;
 
                             num_of_rows += writer.num_rows() as u32;
+                            self.update_checksum(&writer);
 
                             yield Ok(PortalBatch::Rows(writer));
 
@@ -459,6 +557,8 @@ impl Portal {
         max_rows: usize,
     ) -> impl Stream<Item = Result<PortalBatch, ConnectionError>> + 'a {
         stream! {
+            self.last_used = Instant::now();
+
             let state = self
                 .state
                 .take()
@@ -500,7 +600,12 @@ impl Portal {
                             };
                             match safe_stream.await {
                                 Ok(sendable_batch) => {
-                                    let stream = self.hand_execution_stream_state(InExecutionStreamState::new(sendable_batch?, description), max_rows);
+                                    let sendable_batch: SendableRecordBatchStream = Box::pin(LastResultStream::new(
+                                        sendable_batch?,
+                                        ctx.session_state.clone(),
+                                        ctx.sessions.server.config_obj.last_result_max_size(),
+                                    ));
+                                    let stream = self.hand_execution_stream_state(InExecutionStreamState::new(sendable_batch, description), max_rows);
                                     for await value in stream {
                                         yield value;
                                     }
@@ -654,6 +759,75 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_portal_last_used() -> Result<(), ConnectionError> {
+        let created = Instant::now() - std::time::Duration::from_secs(600);
+        let mut p = Portal {
+            format: Format::Binary,
+            from: PortalFrom::Extended,
+            state: Some(PortalState::InExecutionFrame(InExecutionFrameState::new(
+                generate_testing_data_frame(3),
+                None,
+            ))),
+            span_id: None,
+            last_used: created,
+            checksum: None,
+            timezone: None,
+        };
+        assert_eq!(p.last_used(), created);
+
+        {
+            let mut portal = Pin::new(&mut p);
+            let stream = portal.execute(10);
+            pin_mut!(stream);
+            stream.next().await.unwrap()?;
+        }
+
+        assert!(p.last_used() > created);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_portal_result_checksum() -> Result<(), ConnectionError> {
+        let mut p = Portal {
+            format: Format::Text,
+            from: PortalFrom::Simple,
+            state: Some(PortalState::InExecutionFrame(InExecutionFrameState::new(
+                generate_testing_data_frame(3),
+                None,
+            ))),
+            span_id: None,
+            last_used: Instant::now(),
+            checksum: None,
+            timezone: None,
+        };
+        p.enable_checksum();
+
+        let mut portal = Pin::new(&mut p);
+        let stream = portal.execute(0);
+        pin_mut!(stream);
+
+        let expected_sha1 = match stream.next().await.unwrap()? {
+            PortalBatch::Rows(writer) => Sha1::from(writer.data()).digest().to_string(),
+            _ => panic!("must be rows here"),
+        };
+
+        match stream.next().await.unwrap()? {
+            PortalBatch::Notice(notice) => {
+                assert_eq!(notice.message, format!("rows=3 sha1={}", expected_sha1))
+            }
+            _ => panic!("must be Notice here"),
+        }
+
+        match stream.next().await.unwrap()? {
+            PortalBatch::Completion(_) => (),
+            _ => panic!("must be Completion here"),
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_portal_legacy_dataframe_limited_more() -> Result<(), ConnectionError> {
         let mut p = Portal {
@@ -664,6 +838,9 @@ mod tests {
                 None,
             ))),
             span_id: None,
+            last_used: Instant::now(),
+            checksum: None,
+            timezone: None,
         };
 
         let mut portal = Pin::new(&mut p);
@@ -697,6 +874,9 @@ mod tests {
                 None,
             ))),
             span_id: None,
+            last_used: Instant::now(),
+            checksum: None,
+            timezone: None,
         };
 
         let mut portal = Pin::new(&mut p);
@@ -725,6 +905,9 @@ mod tests {
                 Some(protocol::RowDescription::new(vec![])),
             ))),
             span_id: None,
+            last_used: Instant::now(),
+            checksum: None,
+            timezone: None,
         };
 
         let mut portal = Pin::new(&mut p);
@@ -760,6 +943,9 @@ mod tests {
                 Some(protocol::RowDescription::new(vec![])),
             ))),
             span_id: None,
+            last_used: Instant::now(),
+            checksum: None,
+            timezone: None,
         };
 
         execute_portal_single_batch(&mut portal, 1, 1).await?;
@@ -783,6 +969,9 @@ mod tests {
                 Some(protocol::RowDescription::new(vec![])),
             ))),
             span_id: None,
+            last_used: Instant::now(),
+            checksum: None,
+            timezone: None,
         };
 
         // use 1 batch
@@ -14,12 +14,16 @@ pub fn df_type_to_pg_tid(dt: &DataType) -> Result<PgTypeId, ProtocolError> {
         DataType::Float64 => Ok(PgTypeId::FLOAT8),
         DataType::Decimal(_, _) => Ok(PgTypeId::NUMERIC),
         DataType::Utf8 | DataType::LargeUtf8 => Ok(PgTypeId::TEXT),
+        // Time of day is written as text, see `batch_to_dataframe`
+        DataType::Time32(_) | DataType::Time64(_) => Ok(PgTypeId::TEXT),
+        DataType::Binary | DataType::LargeBinary => Ok(PgTypeId::BYTEA),
         DataType::Date32 | DataType::Date64 => Ok(PgTypeId::DATE),
         DataType::Interval(_) => Ok(PgTypeId::INTERVAL),
         DataType::Timestamp(_, tz) => match tz {
             None => Ok(PgTypeId::TIMESTAMP),
             Some(_) => Ok(PgTypeId::TIMESTAMPTZ),
         },
+        DataType::Dictionary(_, value_type) => df_type_to_pg_tid(value_type),
         DataType::Null => Ok(PgTypeId::BOOL),
         DataType::List(field) => match field.data_type() {
             DataType::Boolean => Ok(PgTypeId::ARRAYBOOL),
@@ -1,14 +1,11 @@
 use async_trait::async_trait;
-use log::{error, trace};
-use std::sync::Arc;
-use tokio::{
-    net::TcpListener,
-    sync::{oneshot, watch, RwLock},
-};
+use log::{error, trace, warn};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::sync::{oneshot, watch, RwLock};
 
 use crate::{
     config::processing_loop::ProcessingLoop,
-    sql::{session::DatabaseProtocol, SessionManager},
+    sql::{bind_listener, session::DatabaseProtocol, SessionManager},
     telemetry::{ContextLogger, SessionLogger},
     CubeError,
 };
@@ -29,7 +26,7 @@ crate::di_service!(PostgresServer, []);
 #[async_trait]
 impl ProcessingLoop for PostgresServer {
     async fn processing_loop(&self) -> Result<(), CubeError> {
-        let listener = TcpListener::bind(self.address.clone()).await?;
+        let listener = bind_listener(&self.address).await?;
 
         println!("🔗 Cube SQL (pg) is listening on {}", self.address);
 
@@ -56,36 +53,69 @@ impl ProcessingLoop for PostgresServer {
                 }
             };
 
-            let (client_addr, client_port) = match socket.peer_addr() {
-                Ok(peer_addr) => (peer_addr.ip().to_string(), peer_addr.port()),
+            if let Err(err) = self
+                .session_manager
+                .server
+                .config_obj
+                .postgres_socket_options()
+                .apply(&socket)
+            {
+                warn!("[pg] Unable to set socket options: {}", err);
+            }
+
+            let peer_addr = match socket.peer_addr() {
+                Ok(peer_addr) => peer_addr,
                 Err(e) => {
                     error!("[pg] Error while calling peer_addr() on TcpStream: {}", e);
 
-                    ("127.0.0.1".to_string(), 0000_u16)
+                    SocketAddr::from(([127, 0, 0, 1], 0000_u16))
                 }
             };
 
-            let session = self
-                .session_manager
-                .create_session(DatabaseProtocol::PostgreSQL, client_addr, client_port)
-                .await;
-            let logger = Arc::new(SessionLogger::new(session.state.clone()));
+            let session_manager = self.session_manager.clone();
+            tokio::spawn(async move {
+                let mut socket = socket;
+                // PROXY protocol header is read outside of the accept loop, slow peers can't block it
+                let client_addr = match session_manager
+                    .server
+                    .config_obj
+                    .postgres_socket_options()
+                    .client_addr(&mut socket, peer_addr)
+                    .await
+                {
+                    Ok(client_addr) => client_addr,
+                    Err(e) => {
+                        error!(
+                            "[pg] Unable to read PROXY protocol header from {}: {}",
+                            peer_addr, e
+                        );
+
+                        return;
+                    }
+                };
 
-            trace!("[pg] New connection {}", session.state.connection_id);
+                let session = session_manager
+                    .create_session(
+                        DatabaseProtocol::PostgreSQL,
+                        client_addr.ip().to_string(),
+                        client_addr.port(),
+                    )
+                    .await;
+                let logger = Arc::new(SessionLogger::new(session.state.clone()));
 
-            let (mut tx, rx) = oneshot::channel::<()>();
+                trace!("[pg] New connection {}", session.state.connection_id);
 
-            let connection_id = session.state.connection_id;
-            let session_manager = self.session_manager.clone();
-            tokio::spawn(async move {
-                tx.closed().await;
+                let (mut tx, rx) = oneshot::channel::<()>();
 
-                trace!("[pg] Removing connection {}", connection_id);
+                let connection_id = session.state.connection_id;
+                tokio::spawn(async move {
+                    tx.closed().await;
 
-                session_manager.drop_session(connection_id).await;
-            });
+                    trace!("[pg] Removing connection {}", connection_id);
+
+                    session_manager.drop_session(connection_id).await;
+                });
 
-            tokio::spawn(async move {
                 let handler = AsyncPostgresShim::run_on(socket, session.clone(), logger.clone());
                 if let Err(e) = handler.await {
                     logger.error(
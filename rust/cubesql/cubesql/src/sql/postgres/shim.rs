@@ -1,32 +1,43 @@
 use std::{
-    backtrace::Backtrace, collections::HashMap, io::ErrorKind, pin::Pin, sync::Arc,
-    time::SystemTime,
+    backtrace::Backtrace,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    io::ErrorKind,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
 use super::extended::PreparedStatement;
 use crate::{
     compile::{
         convert_statement_to_cube_query,
-        parser::{parse_sql_to_statement, parse_sql_to_statements},
+        engine::df::{scan::MaxRecordsExceeded, scan_schemas::ScanSchemaCache},
+        parser::{parse_emit_changes, parse_pushdown_hint, parse_sql_to_statements},
         qtrace::Qtrace,
         CompilationError, MetaContext, QueryPlan,
     },
     sql::{
+        catch_connection_panic,
+        dataframe::{batch_to_dataframe, DataFrame},
         df_type_to_pg_tid,
         extended::{Cursor, Portal, PortalBatch, PortalFrom},
         session::DatabaseProtocol,
         statement::{PostgresStatementParamsFinder, StatementPlaceholderReplacer},
         types::CommandCompletion,
-        AuthContextRef, Session, StatusFlags,
+        AuthContextRef, ColumnType, GssAuthenticator, GssStep, Session, SessionEndReason,
+        StatementSchemasKey, StatusFlags,
     },
     telemetry::ContextLogger,
-    transport::SpanId,
+    transport::{MemoryPressure, SpanId},
     CubeError,
 };
+use datafusion::prelude::DataFrame as DFDataFrame;
 use futures::{pin_mut, FutureExt, StreamExt};
-use log::{debug, error, trace};
+use log::{debug, error, info, trace, warn};
 use pg_srv::{
-    buffer, protocol,
+    buffer::{self, WriteBuffer, WriteOptions},
+    protocol,
     protocol::{ErrorCode, ErrorResponse, Format, InitialMessage, PortalCompletion},
     PgType, PgTypeId, ProtocolError,
 };
@@ -37,12 +48,16 @@ use uuid::Uuid;
 
 pub struct AsyncPostgresShim {
     socket: TcpStream,
+    // Outgoing messages, written to the socket with flow control
+    write_buffer: WriteBuffer,
     // Extended query
     cursors: HashMap<String, Cursor>,
     portals: HashMap<String, Portal>,
     // Shared
     session: Arc<Session>,
     logger: Arc<dyn ContextLogger>,
+    // Set when the connection is closed on purpose, other closes are told apart by the error
+    end_reason: Option<SessionEndReason>,
 }
 
 #[derive(PartialEq, Eq)]
@@ -124,7 +139,30 @@ impl ConnectionError {
     pub fn to_error_response(self) -> protocol::ErrorResponse {
         match self {
             ConnectionError::Cube(e, _) => {
-                protocol::ErrorResponse::error(protocol::ErrorCode::InternalError, e.to_string())
+                match e
+                    .meta()
+                    .and_then(|meta| meta.get(MaxRecordsExceeded::META_KEY))
+                {
+                    Some(detail) => protocol::ErrorResponse::error(
+                        protocol::ErrorCode::ConfigurationLimitExceeded,
+                        e.to_string(),
+                    )
+                    .with_detail(Some(detail.clone())),
+                    None if e
+                        .meta()
+                        .map(|meta| meta.contains_key(MemoryPressure::META_KEY))
+                        .unwrap_or(false) =>
+                    {
+                        protocol::ErrorResponse::error(
+                            protocol::ErrorCode::InsufficientResources,
+                            e.to_string(),
+                        )
+                    }
+                    None => protocol::ErrorResponse::error(
+                        protocol::ErrorCode::InternalError,
+                        e.to_string(),
+                    ),
+                }
             }
             ConnectionError::CompilationError(e, _) => {
                 fn to_error_response(e: CompilationError) -> protocol::ErrorResponse {
@@ -133,6 +171,14 @@ impl ConnectionError {
                             protocol::ErrorCode::InternalError,
                             e.to_string(),
                         ),
+                        CompilationError::User(_, Some(ref meta))
+                            if meta.contains_key(MemoryPressure::META_KEY) =>
+                        {
+                            protocol::ErrorResponse::error(
+                                protocol::ErrorCode::InsufficientResources,
+                                e.to_string(),
+                            )
+                        }
                         CompilationError::User(_, _) => protocol::ErrorResponse::error(
                             protocol::ErrorCode::InvalidSqlStatement,
                             e.to_string(),
@@ -145,6 +191,10 @@ impl ConnectionError {
                             protocol::ErrorCode::InternalError,
                             e.to_string(),
                         ),
+                        CompilationError::MemberNotFound(_, _) => protocol::ErrorResponse::error(
+                            protocol::ErrorCode::UndefinedColumn,
+                            e.to_string(),
+                        ),
                     }
                 }
 
@@ -223,30 +273,81 @@ impl From<ErrorResponse> for ConnectionError {
     }
 }
 
+/// Socket was closed or reset by the client, there is nobody to deliver an error to
+fn is_disconnect(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::BrokenPipe
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+    )
+}
+
 impl AsyncPostgresShim {
     pub async fn run_on(
         socket: TcpStream,
         session: Arc<Session>,
         logger: Arc<dyn ContextLogger>,
     ) -> Result<(), ConnectionError> {
+        let client_write_timeout = session.server.config_obj.client_write_timeout();
         let mut shim = Self {
             socket,
+            write_buffer: WriteBuffer::new(WriteOptions {
+                timeout: if client_write_timeout > 0 {
+                    Some(Duration::from_secs(client_write_timeout))
+                } else {
+                    None
+                },
+                ..WriteOptions::default()
+            }),
             cursors: HashMap::new(),
             portals: HashMap::new(),
             session,
             logger,
+            end_reason: None,
         };
 
-        match shim.run().await {
+        let result = match catch_connection_panic(shim.run()).await {
+            Ok(result) => result,
+            Err(panic) => {
+                shim.session.report_panic(&panic);
+                shim.session.end(SessionEndReason::Panic).await;
+
+                // Response which was being written is incomplete, the client must not get a part
+                // of it before the error. FATAL severity tells the client that the connection is
+                // closed by the server.
+                shim.write_buffer.clear();
+                shim.write(protocol::ErrorResponse::fatal(
+                    protocol::ErrorCode::InternalError,
+                    panic.reason,
+                ))
+                .await?;
+                shim.socket.shutdown().await?;
+                return Ok(());
+            }
+        };
+
+        shim.session.end(shim.end_reason(&result)).await;
+
+        match result {
             Err(e) => {
                 if let ConnectionError::Protocol(ProtocolError::IO { source, .. }, _) = &e {
-                    if source.kind() == ErrorKind::BrokenPipe
-                        || source.kind() == ErrorKind::UnexpectedEof
-                    {
+                    if is_disconnect(source.kind()) {
                         trace!("Error during processing PostgreSQL connection: {}", e);
 
                         return Ok(());
                     }
+
+                    // Client doesn't read from the socket, there is no way to deliver an error
+                    if source.kind() == ErrorKind::TimedOut {
+                        warn!(
+                            "Terminating PostgreSQL connection (connection_id: {}): {}",
+                            shim.session.state.connection_id, source
+                        );
+
+                        return Ok(());
+                    }
                 } else if let ConnectionError::CompilationError(CompilationError::Fatal(_, _), _) =
                     &e
                 {
@@ -264,6 +365,24 @@ impl AsyncPostgresShim {
         }
     }
 
+    /// Reason of the session end by the result of the connection handler
+    fn end_reason(&self, result: &Result<(), ConnectionError>) -> SessionEndReason {
+        match result {
+            // Handler returns without an explicit reason only before the startup is finished
+            Ok(()) => self.end_reason.unwrap_or(SessionEndReason::Startup),
+            Err(ConnectionError::Protocol(ProtocolError::IO { source, .. }, _)) => {
+                if is_disconnect(source.kind()) {
+                    SessionEndReason::Disconnect
+                } else if source.kind() == ErrorKind::TimedOut {
+                    SessionEndReason::Timeout
+                } else {
+                    SessionEndReason::Error
+                }
+            }
+            Err(_) => SessionEndReason::Error,
+        }
+    }
+
     pub async fn run(&mut self) -> Result<(), ConnectionError> {
         let initial_parameters = match self.process_initial_message().await? {
             StartupState::Success(parameters) => parameters,
@@ -274,31 +393,64 @@ impl AsyncPostgresShim {
             StartupState::Denied | StartupState::CancelRequest => return Ok(()),
         };
 
-        match buffer::read_message(&mut self.socket).await? {
-            protocol::FrontendMessage::PasswordMessage(password_message) => {
-                if !self
-                    .authenticate(password_message, initial_parameters)
-                    .await?
-                {
-                    return Ok(());
+        let authenticated = match self.session.server.auth.gss_authenticator().await {
+            Some(gss) => self.authenticate_gss(gss, initial_parameters).await?,
+            None => {
+                self.write(protocol::Authentication::new(
+                    protocol::AuthenticationRequest::CleartextPassword,
+                ))
+                .await?;
+
+                match buffer::read_message(&mut self.socket).await? {
+                    protocol::FrontendMessage::PasswordMessage(password_message) => {
+                        self.authenticate(password_message, initial_parameters)
+                            .await?
+                    }
+                    _ => return Ok(()),
                 }
             }
-            _ => return Ok(()),
+        };
+        if !authenticated {
+            return Ok(());
         }
 
         self.ready().await?;
 
+        self.process_messages().await
+    }
+
+    /// Main loop of the connection after the startup
+    async fn process_messages(&mut self) -> Result<(), ConnectionError> {
         // When an error is detected while processing any extended-query message, the backend issues ErrorResponse,
         // then reads and discards messages until a Sync is reached, then issues ReadyForQuery and returns to normal message processing.
-        let mut tracked_error: Option<ConnectionError> = None;
+        // The error is sent right away, clients which use Flush instead of Sync wait for it.
+        let mut ignore_till_sync = false;
 
         loop {
             let mut doing_extended_query_message = false;
 
-            let result = match buffer::read_message(&mut self.socket).await? {
+            let message = match self.read_message().await? {
+                Some(message) => message,
+                None => return Ok(()),
+            };
+
+            let result = match message {
+                protocol::FrontendMessage::Sync => {
+                    ignore_till_sync = false;
+                    self.write_ready().await?;
+
+                    continue;
+                }
+                protocol::FrontendMessage::Flush => self.flush().await,
+                protocol::FrontendMessage::Terminate => {
+                    self.end_reason = Some(SessionEndReason::Terminate);
+
+                    return Ok(());
+                }
+                _ if ignore_till_sync => continue,
                 protocol::FrontendMessage::Query(body) => {
                     let span_id = Self::new_span_id(body.query.clone());
-                    let mut qtrace = Qtrace::new(&body.query);
+                    let mut qtrace = Qtrace::for_session(&body.query, &self.session.state);
                     if let Some(qtrace) = &qtrace {
                         debug!("Assigned query UUID: {}", qtrace.uuid())
                     }
@@ -306,131 +458,102 @@ impl AsyncPostgresShim {
                         .process_query(body.query, &mut qtrace, span_id.clone())
                         .await
                         .map_err(|e| e.with_span_id(span_id));
-                    if let Some(qtrace) = &qtrace {
-                        qtrace.save_json()
+                    if let Some(qtrace) = qtrace {
+                        qtrace.finish(&self.session.state)
                     }
                     result
                 }
-                protocol::FrontendMessage::Flush => self.flush().await,
-                protocol::FrontendMessage::Terminate => return Ok(()),
                 // Extended
                 protocol::FrontendMessage::Parse(body) => {
-                    if tracked_error.is_none() {
-                        doing_extended_query_message = true;
-                        let mut qtrace = Qtrace::new(&body.query);
-                        let span_id = Self::new_span_id(body.query.clone());
-                        if let Some(qtrace) = &qtrace {
-                            debug!("Assigned query UUID: {}", qtrace.uuid())
-                        }
-                        if let Some(auth_context) = self.session.state.auth_context() {
-                            self.session
-                                .session_manager
-                                .server
-                                .transport
-                                .log_load_state(
-                                    span_id.clone(),
-                                    auth_context,
-                                    self.session.state.get_load_request_meta(),
-                                    "Load Request".to_string(),
-                                    serde_json::json!({
-                                        "query": span_id.as_ref().unwrap().query_key.clone(),
-                                    }),
-                                )
-                                .await?;
-                        }
-                        let result = self
-                            .parse(body, &mut qtrace, span_id.clone())
-                            .await
-                            .map_err(|e| e.with_span_id(span_id));
-                        if let Err(err) = &result {
-                            if let Some(qtrace) = &mut qtrace {
-                                qtrace.set_query_error_message(&err.to_string())
-                            }
-                        };
-                        if let Some(qtrace) = &qtrace {
-                            qtrace.save_json()
+                    doing_extended_query_message = true;
+                    let mut qtrace = Qtrace::for_session(&body.query, &self.session.state);
+                    let span_id = Self::new_span_id(body.query.clone());
+                    if let Some(qtrace) = &qtrace {
+                        debug!("Assigned query UUID: {}", qtrace.uuid())
+                    }
+                    if let Some(auth_context) = self.session.state.auth_context() {
+                        self.session
+                            .session_manager
+                            .server
+                            .transport
+                            .log_load_state(
+                                span_id.clone(),
+                                auth_context,
+                                self.session.state.get_load_request_meta(),
+                                "Load Request".to_string(),
+                                serde_json::json!({
+                                    "query": span_id.as_ref().unwrap().query_key.clone(),
+                                }),
+                            )
+                            .await?;
+                    }
+                    let result = self
+                        .parse(body, &mut qtrace, span_id.clone())
+                        .await
+                        .map_err(|e| e.with_span_id(span_id));
+                    if let Err(err) = &result {
+                        if let Some(qtrace) = &mut qtrace {
+                            qtrace.set_query_error_message(&err.to_string())
                         }
-                        result
-                    } else {
-                        continue;
+                    };
+                    if let Some(qtrace) = qtrace {
+                        qtrace.finish(&self.session.state)
                     }
+                    result
                 }
                 protocol::FrontendMessage::Bind(body) => {
-                    if tracked_error.is_none() {
-                        doing_extended_query_message = true;
-                        let span_id = {
-                            let statements_guard = self.session.state.statements.read().await;
-                            statements_guard
-                                .get(&body.statement)
-                                .and_then(|s| s.span_id())
-                        };
-                        self.bind(body, span_id).await
-                    } else {
-                        continue;
-                    }
+                    doing_extended_query_message = true;
+                    let span_id = {
+                        let statements_guard = self.session.state.statements.read().await;
+                        statements_guard
+                            .get(&body.statement)
+                            .and_then(|s| s.span_id())
+                    };
+                    self.bind(body, span_id).await
                 }
                 protocol::FrontendMessage::Execute(body) => {
-                    if tracked_error.is_none() {
-                        doing_extended_query_message = true;
-                        let span_id = if let Some(portal) = self.portals.get(&body.portal) {
-                            portal.span_id()
-                        } else {
-                            None
-                        };
-                        let result = self
-                            .execute(body)
-                            .await
-                            .map_err(|e| e.with_span_id(span_id.clone()));
-                        if result.is_ok() {
-                            if let Some(auth_context) = self.session.state.auth_context() {
-                                if let Some(span_id) = span_id {
-                                    self.session
-                                        .session_manager
-                                        .server
-                                        .transport
-                                        .log_load_state(
-                                            Some(span_id.clone()),
-                                            auth_context,
-                                            self.session.state.get_load_request_meta(),
-                                            "Load Request Success".to_string(),
-                                            serde_json::json!({
-                                                "query": span_id.query_key.clone(),
-                                                "apiType": "sql",
-                                                "duration": span_id.duration(),
-                                                "isDataQuery": span_id.is_data_query().await
-                                            }),
-                                        )
-                                        .await?;
-                                }
+                    doing_extended_query_message = true;
+                    let span_id = if let Some(portal) = self.portals.get(&body.portal) {
+                        portal.span_id()
+                    } else {
+                        None
+                    };
+                    let result = self
+                        .execute(body)
+                        .await
+                        .map_err(|e| e.with_span_id(span_id.clone()));
+                    if result.is_ok() {
+                        if let Some(auth_context) = self.session.state.auth_context() {
+                            if let Some(span_id) = span_id {
+                                self.session
+                                    .session_manager
+                                    .server
+                                    .transport
+                                    .log_load_state(
+                                        Some(span_id.clone()),
+                                        auth_context,
+                                        self.session.state.get_load_request_meta(),
+                                        "Load Request Success".to_string(),
+                                        serde_json::json!({
+                                            "query": span_id.query_key.clone(),
+                                            "apiType": "sql",
+                                            "duration": span_id.duration(),
+                                            "isDataQuery": span_id.is_data_query().await
+                                        }),
+                                    )
+                                    .await?;
                             }
                         }
-                        result
-                    } else {
-                        continue;
                     }
+                    result
                 }
                 protocol::FrontendMessage::Close(body) => {
-                    if tracked_error.is_none() {
-                        self.close(body).await
-                    } else {
-                        continue;
-                    }
+                    doing_extended_query_message = true;
+                    self.close(body).await
                 }
                 protocol::FrontendMessage::Describe(body) => {
-                    if tracked_error.is_none() {
-                        self.describe(body).await
-                    } else {
-                        continue;
-                    }
-                }
-                protocol::FrontendMessage::Sync => {
-                    if let Some(err) = tracked_error.take() {
-                        self.handle_connection_error(err).await?;
-                    };
-
-                    self.write_ready().await?;
-
-                    continue;
+                    doing_extended_query_message = true;
+                    self.describe(body).await
                 }
                 command_id => {
                     return Err(ConnectionError::Protocol(
@@ -444,11 +567,8 @@ impl AsyncPostgresShim {
                 }
             };
             if let Err(err) = result {
-                if doing_extended_query_message {
-                    tracked_error = Some(err);
-                } else {
-                    self.handle_connection_error(err).await?;
-                }
+                ignore_till_sync = doing_extended_query_message;
+                self.handle_connection_error(err).await?;
             }
         }
     }
@@ -469,11 +589,12 @@ impl AsyncPostgresShim {
                 CompilationError::Unsupported(msg, meta)
                 | CompilationError::User(msg, meta)
                 | CompilationError::Internal(msg, _, meta) => (msg.clone(), meta.clone()),
+                CompilationError::MemberNotFound(err, meta) => (err.to_string(), meta.clone()),
                 CompilationError::Fatal(_, _) => return Err(err),
             },
             ConnectionError::Protocol(ProtocolError::IO { source, .. }, _) => match source.kind() {
                 // Propagate unrecoverable errors to top level - run_on
-                ErrorKind::UnexpectedEof | ErrorKind::BrokenPipe => return Err(err),
+                kind if is_disconnect(kind) || kind == ErrorKind::TimedOut => return Err(err),
                 _ => (
                     format!("Error during processing PostgreSQL message: {}", err),
                     None,
@@ -536,7 +657,11 @@ impl AsyncPostgresShim {
         &mut self,
         message: Vec<Message>,
     ) -> Result<(), ConnectionError> {
-        buffer::write_messages(&mut self.socket, message).await?;
+        for message in message {
+            self.write_buffer.push(message)?;
+        }
+
+        self.write_buffer.flush(&mut self.socket).await?;
 
         Ok(())
     }
@@ -546,24 +671,128 @@ impl AsyncPostgresShim {
         completion: PortalCompletion,
     ) -> Result<(), ConnectionError> {
         match completion {
-            PortalCompletion::Complete(c) => buffer::write_message(&mut self.socket, c).await?,
-            PortalCompletion::Suspended(s) => buffer::write_message(&mut self.socket, s).await?,
+            PortalCompletion::Complete(c) => self.write(c).await,
+            PortalCompletion::Suspended(s) => self.write(s).await,
         }
+    }
 
-        Ok(())
+    /// Waits for the next message from the client. Meanwhile, portals which were not used for
+    /// portal_idle_timeout are closed, and the connection is terminated (None is returned)
+    /// when a transaction stays idle for longer than idle_in_transaction_timeout.
+    async fn read_message(&mut self) -> Result<Option<protocol::FrontendMessage>, ConnectionError> {
+        let idle_in_transaction_timeout =
+            self.session.server.config_obj.idle_in_transaction_timeout();
+        let portal_idle_timeout = self.session.server.config_obj.portal_idle_timeout();
+        let idle_since = Instant::now();
+
+        loop {
+            let transaction_deadline =
+                if idle_in_transaction_timeout > 0 && self.session.state.is_in_transaction() {
+                    Some(idle_since + Duration::from_secs(idle_in_transaction_timeout))
+                } else {
+                    None
+                };
+            let portal_deadline = if portal_idle_timeout > 0 {
+                self.portals
+                    .values()
+                    .map(|portal| portal.last_used() + Duration::from_secs(portal_idle_timeout))
+                    .min()
+            } else {
+                None
+            };
+
+            let deadline = match transaction_deadline
+                .into_iter()
+                .chain(portal_deadline)
+                .min()
+            {
+                Some(deadline) => deadline,
+                None => return Ok(Some(buffer::read_message(&mut self.socket).await?)),
+            };
+
+            let mut peek_buf = [0; 1];
+            let readable = tokio::select! {
+                // Unlike reading of the message, peek is cancellation safe
+                _ = self.socket.peek(&mut peek_buf) => true,
+                _ = tokio::time::sleep_until(deadline.into()) => false,
+            };
+            if readable {
+                return Ok(Some(buffer::read_message(&mut self.socket).await?));
+            }
+
+            if transaction_deadline.map_or(false, |deadline| deadline <= Instant::now()) {
+                warn!(
+                    "Terminating PostgreSQL connection (connection_id: {}): idle in transaction for more than {} seconds",
+                    self.session.state.connection_id, idle_in_transaction_timeout
+                );
+
+                self.end_reason = Some(SessionEndReason::Timeout);
+                self.write(protocol::ErrorResponse::idle_in_transaction_session_timeout())
+                    .await?;
+
+                return Ok(None);
+            }
+
+            if portal_idle_timeout > 0 {
+                self.close_idle_portals(Duration::from_secs(portal_idle_timeout));
+            }
+        }
+    }
+
+    /// Releases buffered results and upstream streams of portals which were not fetched for a while
+    fn close_idle_portals(&mut self, timeout: Duration) {
+        let expired = self
+            .portals
+            .iter()
+            .filter(|(_, portal)| portal.last_used().elapsed() >= timeout)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        for name in expired {
+            self.portals.remove(&name);
+            // FETCH reopens a missing portal of the cursor from the start, close the cursor as well
+            self.cursors.remove(&name);
+
+            debug!(
+                "[pg] Portal \"{}\" was closed (connection_id: {}): idle for more than {} seconds",
+                name,
+                self.session.state.connection_id,
+                timeout.as_secs()
+            );
+        }
     }
 
     pub async fn write<Message: protocol::Serialize>(
         &mut self,
         message: Message,
     ) -> Result<(), ConnectionError> {
-        buffer::write_message(&mut self.socket, message).await?;
+        self.write_buffer.push(message)?;
+        self.write_buffer.flush(&mut self.socket).await?;
+
+        Ok(())
+    }
+
+    /// Buffer rows without waiting for the client, buffer is written to the socket only when it's full
+    async fn write_rows<Message: protocol::Serialize>(
+        write_buffer: &mut WriteBuffer,
+        socket: &mut TcpStream,
+        rows: Message,
+    ) -> Result<(), ConnectionError> {
+        write_buffer.push_direct(rows);
+        if write_buffer.is_full() {
+            write_buffer.flush(socket).await?;
+        }
 
         Ok(())
     }
 
     pub async fn process_initial_message(&mut self) -> Result<StartupState, ConnectionError> {
-        let mut buffer = buffer::read_contents(&mut self.socket, 0).await?;
+        let mut buffer = buffer::read_contents_with_limit(
+            &mut self.socket,
+            0,
+            buffer::MAX_STARTUP_PACKET_LENGTH,
+        )
+        .await?;
 
         let initial_message = protocol::InitialMessage::from(&mut buffer).await?;
         match initial_message {
@@ -617,7 +846,7 @@ impl AsyncPostgresShim {
                     startup_message.major, startup_message.minor,
                 ),
             );
-            buffer::write_message(&mut self.socket, error_response).await?;
+            self.write(error_response).await?;
             return Ok(StartupState::Denied);
         }
 
@@ -628,15 +857,10 @@ impl AsyncPostgresShim {
                 protocol::ErrorCode::InvalidAuthorizationSpecification,
                 "no PostgreSQL user name specified in startup packet".to_string(),
             );
-            buffer::write_message(&mut self.socket, error_response).await?;
+            self.write(error_response).await?;
             return Ok(StartupState::Denied);
         }
 
-        self.write(protocol::Authentication::new(
-            protocol::AuthenticationRequest::CleartextPassword,
-        ))
-        .await?;
-
         Ok(StartupState::Success(parameters))
     }
 
@@ -675,11 +899,105 @@ impl AsyncPostgresShim {
                 protocol::ErrorCode::InvalidPassword,
                 format!("password authentication failed for user \"{}\"", &user),
             );
-            buffer::write_message(&mut self.socket, error_response).await?;
+            self.write(error_response).await?;
 
             return Ok(false);
         }
 
+        self.complete_authentication(user, parameters, auth_context)
+            .await?;
+
+        Ok(true)
+    }
+
+    /// GSSAPI tokens are exchanged until the security context is established by the auth
+    /// service, then the principal is mapped to the requested user
+    pub async fn authenticate_gss(
+        &mut self,
+        mut gss: Box<dyn GssAuthenticator>,
+        parameters: HashMap<String, String>,
+    ) -> Result<bool, ConnectionError> {
+        // Kerberos needs one or two round trips, it protects from a looping client
+        const MAX_GSS_ROUNDS: usize = 16;
+
+        let user = parameters.get("user").unwrap().clone();
+        self.write(protocol::Authentication::new(
+            protocol::AuthenticationRequest::GSS,
+        ))
+        .await?;
+
+        let mut principal = None;
+        for _ in 0..MAX_GSS_ROUNDS {
+            let response = buffer::read_gss_response(&mut self.socket).await?;
+            match gss.step(response.data).await {
+                Ok(GssStep::Continue(token)) => {
+                    self.write(protocol::Authentication::new(
+                        protocol::AuthenticationRequest::GSSContinue(token),
+                    ))
+                    .await?;
+                }
+                Ok(GssStep::Complete {
+                    principal: authenticated,
+                    token,
+                }) => {
+                    if let Some(token) = token.filter(|t| !t.is_empty()) {
+                        self.write(protocol::Authentication::new(
+                            protocol::AuthenticationRequest::GSSContinue(token),
+                        ))
+                        .await?;
+                    }
+
+                    principal = Some(authenticated);
+                    break;
+                }
+                Err(err) => {
+                    debug!("GSSAPI negotiation failed for user \"{}\": {}", user, err);
+                    break;
+                }
+            }
+        }
+
+        let auth_context = match principal {
+            Some(principal) => {
+                match self
+                    .session
+                    .server
+                    .auth
+                    .authenticate_principal(principal, user.clone())
+                    .await
+                {
+                    Ok(response) => Some(response.context),
+                    Err(err) => {
+                        debug!("Kerberos principal is rejected: {}", err);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        if auth_context.is_none() {
+            let error_response = protocol::ErrorResponse::fatal(
+                protocol::ErrorCode::InvalidAuthorizationSpecification,
+                format!("GSSAPI authentication failed for user \"{}\"", &user),
+            );
+            self.write(error_response).await?;
+
+            return Ok(false);
+        }
+
+        self.complete_authentication(user, parameters, auth_context)
+            .await?;
+
+        Ok(true)
+    }
+
+    async fn complete_authentication(
+        &mut self,
+        user: String,
+        parameters: HashMap<String, String>,
+        auth_context: Option<AuthContextRef>,
+    ) -> Result<(), ConnectionError> {
         let database = parameters
             .get("database")
             .map(|v| v.clone())
@@ -687,35 +1005,43 @@ impl AsyncPostgresShim {
         self.session.state.set_database(Some(database));
         self.session.state.set_user(Some(user));
         self.session.state.set_auth_context(auth_context);
+        self.session.warm_up();
 
         self.write(protocol::Authentication::new(
             protocol::AuthenticationRequest::Ok,
         ))
         .await?;
 
-        Ok(true)
+        Ok(())
     }
 
     pub async fn ready(&mut self) -> Result<(), ConnectionError> {
-        let params = vec![
-            protocol::ParameterStatus::new(
+        let server_version = self.session.server.config_obj.server_version().clone();
+        let mut params = vec![
+            (
                 "server_version".to_string(),
-                "14.2 (Cube SQL)".to_string(),
+                format!("{} (Cube SQL)", server_version.postgres_version()),
             ),
-            protocol::ParameterStatus::new("server_encoding".to_string(), "UTF8".to_string()),
-            protocol::ParameterStatus::new("client_encoding".to_string(), "UTF8".to_string()),
-            protocol::ParameterStatus::new("DateStyle".to_string(), "ISO".to_string()),
+            ("server_encoding".to_string(), "UTF8".to_string()),
+            ("client_encoding".to_string(), "UTF8".to_string()),
+            ("DateStyle".to_string(), "ISO".to_string()),
             // Reports whether PostgreSQL was built with support for 64-bit-integer dates and times.
-            protocol::ParameterStatus::new("integer_datetimes".to_string(), "on".to_string()),
-            protocol::ParameterStatus::new("TimeZone".to_string(), "Etc/UTC".to_string()),
-            protocol::ParameterStatus::new("IntervalStyle".to_string(), "postgres".to_string()),
+            ("integer_datetimes".to_string(), "on".to_string()),
+            ("TimeZone".to_string(), "Etc/UTC".to_string()),
+            ("IntervalStyle".to_string(), "postgres".to_string()),
             // Some drivers rely on it, for example, SQLAlchemy
             // https://github.com/sqlalchemy/sqlalchemy/blob/6104c163eb58e35e46b0bb6a237e824ec1ee1d15/lib/sqlalchemy/dialects/postgresql/base.py#L2994
-            protocol::ParameterStatus::new(
-                "standard_conforming_strings".to_string(),
-                "on".to_string(),
-            ),
+            ("standard_conforming_strings".to_string(), "on".to_string()),
         ];
+        // Configured parameters override the defaults
+        for (name, value) in server_version.postgres_parameters {
+            params.retain(|(n, _)| !n.eq_ignore_ascii_case(&name));
+            params.push((name, value));
+        }
+        let params = params
+            .into_iter()
+            .map(|(name, value)| protocol::ParameterStatus::new(name, value))
+            .collect::<Vec<_>>();
 
         self.write_multi(params).await?;
         self.write(protocol::BackendKeyData::new(
@@ -743,7 +1069,8 @@ impl AsyncPostgresShim {
     }
 
     pub async fn flush(&mut self) -> Result<(), ConnectionError> {
-        // TODO: flush network buffers here once buffering has been implemented
+        self.write_buffer.flush(&mut self.socket).await?;
+
         Ok(())
     }
 
@@ -759,12 +1086,11 @@ impl AsyncPostgresShim {
                 }
             }
         } else {
-            self.write(protocol::ErrorResponse::new(
-                protocol::ErrorSeverity::Error,
-                protocol::ErrorCode::InvalidCursorName,
-                "missing cursor".to_string(),
-            ))
-            .await
+            Err(ErrorResponse::error(
+                ErrorCode::InvalidCursorName,
+                format!(r#"portal "{}" does not exist"#, name),
+            )
+            .into())
         }
     }
 
@@ -772,16 +1098,11 @@ impl AsyncPostgresShim {
         let session = self.session.clone();
         let statements_guard = session.state.statements.read().await;
         match statements_guard.get(&name) {
-            None => {
-                self.write(protocol::ErrorResponse::new(
-                    protocol::ErrorSeverity::Error,
-                    protocol::ErrorCode::InvalidSqlStatement,
-                    "missing statement".to_string(),
-                ))
-                .await?;
-
-                return Ok(());
-            }
+            None => Err(ErrorResponse::error(
+                ErrorCode::InvalidSqlStatement,
+                format!(r#"prepared statement "{}" does not exist"#, name),
+            )
+            .into()),
             Some(statement) => match statement {
                 PreparedStatement::Empty { .. } => {
                     self.write(protocol::ParameterDescription::new(vec![]))
@@ -845,6 +1166,10 @@ impl AsyncPostgresShim {
                     .state
                     .begin_query(format!("portal #{}", execute.portal));
 
+                if self.session.state.result_checksum() {
+                    portal.enable_checksum();
+                }
+
                 let mut portal = Pin::new(portal);
                 let stream = portal.execute(execute.max_rows as usize);
                 pin_mut!(stream);
@@ -875,15 +1200,21 @@ impl AsyncPostgresShim {
                             }
 
                             match chunk {
-                                PortalBatch::Rows(writer) if writer.has_data() => buffer::write_direct(&mut self.socket, writer).await?,
+                                PortalBatch::Rows(writer) if writer.has_data() => Self::write_rows(&mut self.write_buffer, &mut self.socket, writer).await?,
+                                PortalBatch::Notice(notice) => self.write_buffer.push(notice)?,
                                 PortalBatch::Completion(completion) => {
                                     self.session.state.end_query();
 
+                                    for warning in self.session.state.take_warnings() {
+                                        self.write_buffer.push(protocol::NoticeResponse::warning(ErrorCode::Warning, warning))?;
+                                    }
+
                                     // TODO:
                                     match completion {
-                                        PortalCompletion::Complete(c) => buffer::write_message(&mut self.socket, c).await?,
-                                        PortalCompletion::Suspended(s) => buffer::write_message(&mut self.socket, s).await?,
+                                        PortalCompletion::Complete(c) => self.write_buffer.push(c)?,
+                                        PortalCompletion::Suspended(s) => self.write_buffer.push(s)?,
                                     }
+                                    self.write_buffer.flush(&mut self.socket).await?;
 
                                     return Ok(());
                                 },
@@ -909,7 +1240,21 @@ impl AsyncPostgresShim {
         body: protocol::Bind,
         span_id: Option<Arc<SpanId>>,
     ) -> Result<(), ConnectionError> {
-        if self.portals.len() >= self.session.server.configuration.connection_max_portals {
+        // Unnamed portal is replaced by the next Bind, named one lives until it's closed
+        if !body.portal.is_empty() && self.portals.contains_key(&body.portal) {
+            return Err(ConnectionError::Protocol(
+                ErrorResponse::error(
+                    ErrorCode::DuplicateCursor,
+                    format!(r#"portal "{}" already exists"#, body.portal),
+                )
+                .into(),
+                span_id,
+            ));
+        }
+
+        if !self.portals.contains_key(&body.portal)
+            && self.portals.len() >= self.session.server.configuration.connection_max_portals
+        {
             return Err(ConnectionError::Protocol(
                 protocol::ErrorResponse::error(
                     protocol::ErrorCode::ConfigurationLimitExceeded,
@@ -923,13 +1268,15 @@ impl AsyncPostgresShim {
             ));
         }
 
-        let statements_guard = self.session.state.statements.read().await;
-        let source_statement = statements_guard.get(&body.statement).ok_or_else(|| {
-            ErrorResponse::error(
-                ErrorCode::InvalidSqlStatement,
-                format!(r#"Unknown statement: {}"#, body.statement),
-            )
-        })?;
+        let mut statements_guard = self.session.state.statements.write().await;
+        let source_statement = statements_guard
+            .get_for_execution(&body.statement)
+            .ok_or_else(|| {
+                ErrorResponse::error(
+                    ErrorCode::InvalidSqlStatement,
+                    format!(r#"Unknown statement: {}"#, body.statement),
+                )
+            })?;
 
         let format = body.result_formats.first().unwrap_or(&Format::Text).clone();
         let portal = match source_statement {
@@ -938,9 +1285,30 @@ impl AsyncPostgresShim {
 
                 Portal::new_empty(format, PortalFrom::Extended, span_id)
             }
-            PreparedStatement::Query { parameters, .. } => {
-                let prepared_statement =
-                    source_statement.bind(body.to_bind_values(&parameters)?)?;
+            PreparedStatement::Query {
+                parameters,
+                scan_schemas,
+                ..
+            } => {
+                let values = body.to_bind_values(&parameters)?;
+                if let Some(params) = self
+                    .session
+                    .server
+                    .config_obj
+                    .bind_values_log_policy()
+                    .format(&values)
+                {
+                    info!(
+                        "[pg] Bind statement \"{}\" to portal \"{}\" (connection_id: {}): params={}",
+                        body.statement,
+                        body.portal,
+                        self.session.state.connection_id,
+                        params
+                    );
+                }
+
+                let prepared_statement = source_statement.bind(values)?;
+                let scan_schemas = scan_schemas.clone();
                 drop(statements_guard);
 
                 let meta = self
@@ -950,6 +1318,9 @@ impl AsyncPostgresShim {
                     .meta(self.auth_context()?)
                     .await?;
 
+                if self.session.state.scan_schema_cache_enabled() {
+                    self.session.state.set_scan_schemas(Some(scan_schemas));
+                }
                 let plan = convert_statement_to_cube_query(
                     &prepared_statement,
                     meta,
@@ -957,7 +1328,9 @@ impl AsyncPostgresShim {
                     &mut None,
                     span_id.clone(),
                 )
-                .await?;
+                .await;
+                self.session.state.set_scan_schemas(None);
+                let plan = plan?;
 
                 Portal::new(plan, format, PortalFrom::Extended, span_id)
             }
@@ -975,23 +1348,44 @@ impl AsyncPostgresShim {
         qtrace: &mut Option<Qtrace>,
         span_id: Option<Arc<SpanId>>,
     ) -> Result<(), ConnectionError> {
-        if parse.query.trim() == "" {
-            let mut statements_guard = self.session.state.statements.write().await;
-            statements_guard.insert(
-                parse.name,
-                PreparedStatement::Empty {
-                    from_sql: false,
-                    created: chrono::offset::Utc::now(),
-                    span_id: span_id.clone(),
+        self.session
+            .state
+            .set_pushdown_hint(parse_pushdown_hint(&parse.query));
+        // Comments and semicolons alone are an empty query as well
+        let mut statements =
+            parse_sql_to_statements(&parse.query, DatabaseProtocol::PostgreSQL, qtrace).map_err(
+                |err| {
+                    self.session.report_unsupported_query(&err);
+                    err
                 },
-            );
-        } else {
-            let query = parse_sql_to_statement(&parse.query, DatabaseProtocol::PostgreSQL, qtrace)?;
-            if let Some(qtrace) = qtrace {
-                qtrace.push_statement(&query);
+            )?;
+        match statements.len() {
+            0 => {
+                self.insert_prepared_statement(
+                    parse.name,
+                    PreparedStatement::Empty {
+                        from_sql: false,
+                        created: chrono::offset::Utc::now(),
+                        span_id: span_id.clone(),
+                    },
+                )
+                .await;
+            }
+            1 => {
+                let query = statements.remove(0);
+                if let Some(qtrace) = qtrace {
+                    qtrace.push_statement(&query);
+                }
+                self.prepare_statement(parse.name, query, false, qtrace, span_id.clone())
+                    .await?;
+            }
+            _ => {
+                return Err(ErrorResponse::error(
+                    ErrorCode::SyntaxError,
+                    "cannot insert multiple commands into a prepared statement".to_string(),
+                )
+                .into())
             }
-            self.prepare_statement(parse.name, query, false, qtrace, span_id.clone())
-                .await?;
         }
 
         self.write(protocol::ParseComplete::new()).await?;
@@ -1001,38 +1395,21 @@ impl AsyncPostgresShim {
 
     pub async fn prepare_statement(
         &mut self,
-        name: String,
-        query: Statement,
-        from_sql: bool,
-        qtrace: &mut Option<Qtrace>,
-        span_id: Option<Arc<SpanId>>,
-    ) -> Result<(), ConnectionError> {
-        let prepared_statements_count = self.session.state.statements.read().await.len();
-        if prepared_statements_count
-            >= self
-                .session
-                .server
-                .configuration
-                .connection_max_prepared_statements
-        {
-            return Err(ConnectionError::Protocol(
-                protocol::ErrorResponse::error(
-                    protocol::ErrorCode::ConfigurationLimitExceeded,
-                    format!(
-                        "Unable to allocate a new prepared statement: max allocation reached, actual: {}, max: {}",
-                        prepared_statements_count,
-                        self.session.server.configuration.connection_max_prepared_statements),
-                )
-                    .into(),
-                span_id.clone(),
-            ));
-        }
-
+        name: String,
+        query: Statement,
+        from_sql: bool,
+        qtrace: &mut Option<Qtrace>,
+        span_id: Option<Arc<SpanId>>,
+    ) -> Result<(), ConnectionError> {
         let stmt_finder = PostgresStatementParamsFinder::new();
-        let parameters: Vec<PgTypeId> = stmt_finder
+        let parameter_types: Vec<ColumnType> = stmt_finder
             .find(&query)?
             .into_iter()
-            .map(|param| param.coltype.to_pg_tid())
+            .map(|param| param.coltype)
+            .collect();
+        let parameters: Vec<PgTypeId> = parameter_types
+            .iter()
+            .map(|coltype| coltype.to_pg_tid())
             .collect();
 
         let meta = self
@@ -1042,6 +1419,16 @@ impl AsyncPostgresShim {
             .meta(self.auth_context()?)
             .await?;
 
+        // Bind values are not known yet, the key is built from markers and their types
+        let scan_schemas = if self.session.state.scan_schema_cache_enabled() {
+            self.session
+                .state
+                .statement_schemas
+                .get_or_insert(StatementSchemasKey::new(&query, parameter_types))
+        } else {
+            Arc::new(ScanSchemaCache::new())
+        };
+
         let stmt_replacer = StatementPlaceholderReplacer::new();
         let hacked_query = stmt_replacer.replace(&query)?;
 
@@ -1071,17 +1458,33 @@ impl AsyncPostgresShim {
             parameters: protocol::ParameterDescription::new(parameters),
             description,
             span_id,
+            scan_schemas,
         };
-        self.session
-            .state
-            .statements
-            .write()
-            .await
-            .insert(name, pstmt);
+        self.insert_prepared_statement(name, pstmt).await;
 
         Ok(())
     }
 
+    /// Allocate prepared statement, when limit per connection is reached least recently used
+    /// statements are deallocated
+    async fn insert_prepared_statement(&self, name: String, statement: PreparedStatement) {
+        let evicted = self.session.state.statements.write().await.insert(
+            name,
+            statement,
+            self.session
+                .server
+                .configuration
+                .connection_max_prepared_statements,
+        );
+
+        for name in evicted {
+            debug!(
+                "[pg] Prepared statement \"{}\" was deallocated (connection_id: {}): max allocation reached",
+                name, self.session.state.connection_id
+            );
+        }
+    }
+
     pub fn end_transaction(&mut self) -> Result<bool, ConnectionError> {
         if let Some(_) = self.session.state.end_transaction() {
             // Portals + Cursors which we want to remove
@@ -1303,6 +1706,11 @@ impl AsyncPostgresShim {
                     )
                 })?;
 
+                // Clients which fetch by small batches often abandon the cursor early, so
+                // scans load rows by pages starting from the fetch size
+                self.session
+                    .state
+                    .set_cursor_fetch_size(Some(limit).filter(|limit| *limit > 0));
                 let plan = convert_statement_to_cube_query(
                     &cursor.query,
                     meta,
@@ -1310,7 +1718,9 @@ impl AsyncPostgresShim {
                     qtrace,
                     span_id.clone(),
                 )
-                .await?;
+                .await;
+                self.session.state.set_cursor_fetch_size(None);
+                let plan = plan?;
 
                 let mut portal =
                     Portal::new(plan, cursor.format, PortalFrom::Fetch, span_id.clone());
@@ -1326,12 +1736,7 @@ impl AsyncPostgresShim {
                 sensitive,
                 hold,
             } => {
-                // TODO: move envs to config
-                let stream_mode = std::env::var("CUBESQL_STREAM_MODE")
-                    .ok()
-                    .map(|v| v.parse::<bool>().unwrap())
-                    .unwrap_or(false);
-                if stream_mode {
+                if self.session.server.config_obj.stream_mode() {
                     return Err(ConnectionError::Protocol(
                         protocol::ErrorResponse::error(
                             protocol::ErrorCode::FeatureNotSupported,
@@ -1430,9 +1835,24 @@ impl AsyncPostgresShim {
                 .await?;
             }
             Statement::Discard { object_type } => {
-                self.session.state.clear_extended().await;
-                self.portals = HashMap::new();
-                self.cursors = HashMap::new();
+                // Only ALL owns state in cubesql: there are no cached plans, sequences
+                // or temporary tables to discard
+                if let ast::DiscardObject::ALL = object_type {
+                    if self.session.state.is_in_transaction() {
+                        return Err(ConnectionError::Protocol(
+                            protocol::ErrorResponse::error(
+                                protocol::ErrorCode::ActiveSqlTransaction,
+                                "DISCARD ALL cannot run inside a transaction block".to_string(),
+                            )
+                            .into(),
+                            span_id.clone(),
+                        ));
+                    }
+
+                    self.session.state.discard_all().await;
+                    self.portals = HashMap::new();
+                    self.cursors = HashMap::new();
+                }
 
                 let plan = QueryPlan::MetaOk(
                     StatusFlags::empty(),
@@ -1588,6 +2008,11 @@ impl AsyncPostgresShim {
         max_rows: usize,
         cancel: CancellationToken,
     ) -> Result<(), ConnectionError> {
+        if self.session.state.result_checksum() {
+            portal.enable_checksum();
+        }
+        portal.set_timezone(self.session.state.timezone());
+
         let mut portal = Pin::new(portal);
         let stream = portal.execute(max_rows);
         pin_mut!(stream);
@@ -1612,14 +2037,189 @@ impl AsyncPostgresShim {
                         },
                         PortalBatch::Rows(writer) => {
                             if writer.has_data() {
-                                buffer::write_direct(&mut self.socket, writer).await?
+                                Self::write_rows(&mut self.write_buffer, &mut self.socket, writer).await?
                             }
                         }
-                        PortalBatch::Completion(completion) => return self.write_completion(completion).await,
+                        PortalBatch::Notice(notice) => self.write(notice).await?,
+                        PortalBatch::Completion(completion) => {
+                            for warning in self.session.state.take_warnings() {
+                                self.write(protocol::NoticeResponse::warning(ErrorCode::Warning, warning)).await?;
+                            }
+
+                            return self.write_completion(completion).await
+                        },
+                    }
+                }
+            }
+        }
+    }
+
+    /// `SELECT ... EMIT CHANGES` keeps the query open: it's run again every poll interval and
+    /// rows which were not in the previous result (e.g. after Cube refreshed the data) are pushed
+    /// to the client as additional rows of the same result. Rows which disappeared are not
+    /// retracted. The subscription ends when the query is cancelled, the client sends another
+    /// message or disconnects.
+    async fn execute_subscription(
+        &mut self,
+        query: &str,
+        span_id: Option<Arc<SpanId>>,
+    ) -> Result<(), ConnectionError> {
+        let statements =
+            parse_sql_to_statements(&query.to_string(), DatabaseProtocol::PostgreSQL, &mut None)?;
+        let statement = match statements.as_slice() {
+            [statement @ Statement::Query(_)] => statement.clone(),
+            _ => {
+                return Err(protocol::ErrorResponse::error(
+                    protocol::ErrorCode::FeatureNotSupported,
+                    "EMIT CHANGES is supported only for a single SELECT statement".to_string(),
+                )
+                .into())
+            }
+        };
+
+        let meta = self
+            .session
+            .server
+            .transport
+            .meta(self.auth_context()?)
+            .await?;
+        let plan = convert_statement_to_cube_query(
+            &statement,
+            meta,
+            self.session.clone(),
+            &mut None,
+            span_id.clone(),
+        )
+        .await?;
+        let df = match plan {
+            QueryPlan::DataFusionSelect(_, plan, ctx) => DFDataFrame::new(ctx.state, &plan),
+            QueryPlan::MetaOk(_, _) | QueryPlan::MetaTabular(_, _) => {
+                return Err(protocol::ErrorResponse::error(
+                    protocol::ErrorCode::FeatureNotSupported,
+                    "EMIT CHANGES is not supported for this query".to_string(),
+                )
+                .into())
+            }
+        };
+        let poll_interval = Duration::from_secs(
+            self.session
+                .server
+                .config_obj
+                .subscription_poll_interval_secs()
+                .max(1),
+        );
+
+        let cancel = self.session.state.begin_query(query.to_string());
+        let result = self
+            .poll_subscription(df, poll_interval, cancel, span_id)
+            .await;
+        self.session.state.end_query();
+
+        result
+    }
+
+    async fn poll_subscription(
+        &mut self,
+        df: DFDataFrame,
+        poll_interval: Duration,
+        cancel: CancellationToken,
+        span_id: Option<Arc<SpanId>>,
+    ) -> Result<(), ConnectionError> {
+        self.write_subscription_frame(
+            batch_to_dataframe(&df.schema().into(), &vec![])?,
+            true,
+            span_id.clone(),
+        )
+        .await?;
+
+        // Hashes of the rows of the previous result, it's as large as the result at most
+        let mut previous = HashSet::new();
+
+        loop {
+            let mut current = HashSet::with_capacity(previous.len());
+            // The result is streamed batch by batch, it's never collected
+            let mut stream = df.execute_stream().await?;
+            loop {
+                let batch = tokio::select! {
+                    _ = cancel.cancelled() => {
+                        return self.write(protocol::ErrorResponse::query_canceled()).await
                     }
+                    batch = stream.next() => match batch {
+                        Some(batch) => batch?,
+                        None => break,
+                    },
+                };
+
+                let frame = batch_to_dataframe(&batch.schema(), &vec![batch])?;
+                let columns = frame.get_columns().clone();
+                let rows = frame
+                    .to_rows()
+                    .into_iter()
+                    .filter(|row| {
+                        let mut hasher = DefaultHasher::new();
+                        format!("{:?}", row.values()).hash(&mut hasher);
+                        let hash = hasher.finish();
+
+                        current.insert(hash) && !previous.contains(&hash)
+                    })
+                    .collect::<Vec<_>>();
+                if !rows.is_empty() {
+                    self.write_subscription_frame(
+                        DataFrame::new(columns, rows),
+                        false,
+                        span_id.clone(),
+                    )
+                    .await?;
+                }
+            }
+            self.flush().await?;
+            previous = current;
+
+            let mut peek_buf = [0; 1];
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = tokio::time::sleep(poll_interval) => (),
+                // Unlike reading of the message, peek is cancellation safe. The message is
+                // processed by the main loop after the subscription ends.
+                read = self.socket.peek(&mut peek_buf) => match read? {
+                    0 => return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into()),
+                    _ => break,
+                },
+            }
+        }
+
+        self.write(protocol::ErrorResponse::query_canceled()).await
+    }
+
+    async fn write_subscription_frame(
+        &mut self,
+        frame: DataFrame,
+        describe: bool,
+        span_id: Option<Arc<SpanId>>,
+    ) -> Result<(), ConnectionError> {
+        let mut portal = Portal::new(
+            QueryPlan::MetaTabular(StatusFlags::empty(), Box::new(frame)),
+            Format::Text,
+            PortalFrom::Simple,
+            span_id,
+        );
+        let mut portal = Pin::new(&mut portal);
+        let stream = portal.execute(0);
+        pin_mut!(stream);
+
+        while let Some(chunk) = stream.next().await {
+            match chunk? {
+                PortalBatch::Description(description) if describe => {
+                    self.write(description).await?
                 }
+                PortalBatch::Rows(writer) if writer.has_data() => {
+                    Self::write_rows(&mut self.write_buffer, &mut self.socket, writer).await?
+                }
+                _ => (),
             }
         }
+
+        Ok(())
     }
 
     /// Pipeline of Execution
@@ -1634,6 +2234,14 @@ impl AsyncPostgresShim {
         qtrace: &mut Option<Qtrace>,
         span_id: Option<Arc<SpanId>>,
     ) -> Result<(), ConnectionError> {
+        if query.trim().is_empty() {
+            return self.write(protocol::EmptyQueryResponse::new()).await;
+        }
+
+        if let Some(query) = parse_emit_changes(query) {
+            return self.execute_subscription(&query, span_id).await;
+        }
+
         let meta = self
             .session
             .server
@@ -1641,11 +2249,18 @@ impl AsyncPostgresShim {
             .meta(self.auth_context()?)
             .await?;
 
+        self.session
+            .state
+            .set_pushdown_hint(parse_pushdown_hint(query));
         let statements =
-            parse_sql_to_statements(&query.to_string(), DatabaseProtocol::PostgreSQL, qtrace)?;
+            parse_sql_to_statements(&query.to_string(), DatabaseProtocol::PostgreSQL, qtrace)
+                .map_err(|err| {
+                    self.session.report_unsupported_query(&err);
+                    err
+                })?;
 
         if statements.len() == 0 {
-            self.write(protocol::EmptyQuery::new()).await?;
+            self.write(protocol::EmptyQueryResponse::new()).await?;
         } else {
             for statement in statements {
                 if let Some(qtrace) = qtrace {
@@ -1751,3 +2366,639 @@ impl AsyncPostgresShim {
             .ok_or(CubeError::internal("must be auth".to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compile::test::{get_test_session, get_test_session_with_config, get_test_transport},
+        config::ConfigObjImpl,
+        sql::{
+            fuzzing::{mutate, Rng},
+            AuthenticateResponse, HttpAuthContext, ServerManager, SessionManager, SqlAuthService,
+        },
+        telemetry::SessionLogger,
+    };
+    use async_trait::async_trait;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    fn message(tag: u8, body: Vec<u8>) -> Vec<u8> {
+        let mut message = vec![tag];
+        message.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        message.extend(body);
+        message
+    }
+
+    fn cstr(values: &[&str]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.bytes().chain(std::iter::once(0)))
+            .collect()
+    }
+
+    fn query(query: &str) -> Vec<u8> {
+        message(b'Q', cstr(&[query]))
+    }
+
+    fn parse(name: &str, query: &str) -> Vec<u8> {
+        let mut body = cstr(&[name, query]);
+        // No parameter types
+        body.extend_from_slice(&0_i16.to_be_bytes());
+        message(b'P', body)
+    }
+
+    fn bind(portal: &str, statement: &str) -> Vec<u8> {
+        let mut body = cstr(&[portal, statement]);
+        // No parameter formats, parameters and result formats
+        body.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        message(b'B', body)
+    }
+
+    fn describe_portal(name: &str) -> Vec<u8> {
+        let mut body = vec![b'P'];
+        body.extend(cstr(&[name]));
+        message(b'D', body)
+    }
+
+    fn execute(portal: &str) -> Vec<u8> {
+        let mut body = cstr(&[portal]);
+        body.extend_from_slice(&0_i32.to_be_bytes());
+        message(b'E', body)
+    }
+
+    fn flush() -> Vec<u8> {
+        message(b'H', vec![])
+    }
+
+    fn sync() -> Vec<u8> {
+        message(b'S', vec![])
+    }
+
+    /// Client socket and the handler of a connection which has passed the startup
+    async fn accept() -> (TcpStream, AsyncPostgresShim) {
+        accept_session(get_test_session(DatabaseProtocol::PostgreSQL).await).await
+    }
+
+    async fn accept_session(session: Arc<Session>) -> (TcpStream, AsyncPostgresShim) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+
+        let shim = AsyncPostgresShim {
+            socket,
+            write_buffer: WriteBuffer::new(WriteOptions::default()),
+            cursors: HashMap::new(),
+            portals: HashMap::new(),
+            logger: Arc::new(SessionLogger::new(session.state.clone())),
+            session,
+            end_reason: None,
+        };
+
+        (client, shim)
+    }
+
+    /// Client socket of a connection which has passed the startup
+    async fn connect() -> TcpStream {
+        let (client, mut shim) = accept().await;
+        tokio::spawn(async move { shim.process_messages().await });
+
+        client
+    }
+
+    /// Sends messages and returns tags of the expected number of backend messages
+    async fn exchange(client: &mut TcpStream, messages: Vec<Vec<u8>>, expected: usize) -> String {
+        client.write_all(&messages.concat()).await.unwrap();
+
+        let mut tags = String::new();
+        for _ in 0..expected {
+            let tag = tokio::time::timeout(Duration::from_secs(5), client.read_u8())
+                .await
+                .expect("backend message was not delivered")
+                .unwrap();
+            let length = client.read_i32().await.unwrap() as usize;
+            let mut body = vec![0; length - 4];
+            client.read_exact(&mut body).await.unwrap();
+
+            tags.push(tag as char);
+        }
+
+        tags
+    }
+
+    #[tokio::test]
+    async fn test_session_end() {
+        let (mut client, mut shim) = accept().await;
+        let session = shim.session.clone();
+        let cancel = session.state.begin_query("SELECT 1".to_string());
+        let handler = tokio::spawn(async move {
+            let result = shim.process_messages().await;
+            shim.end_reason(&result)
+        });
+
+        client.write_all(&message(b'X', vec![])).await.unwrap();
+        let reason = handler.await.unwrap();
+        assert_eq!(reason, SessionEndReason::Terminate);
+
+        // In-flight query of the closed connection is cancelled
+        session.end(reason).await;
+        assert!(cancel.is_cancelled());
+
+        // Socket is closed without Terminate
+        let (client, mut shim) = accept().await;
+        let handler = tokio::spawn(async move {
+            let result = shim.process_messages().await;
+            shim.end_reason(&result)
+        });
+
+        drop(client);
+        assert_eq!(handler.await.unwrap(), SessionEndReason::Disconnect);
+    }
+
+    #[tokio::test]
+    async fn test_empty_query() {
+        let mut client = connect().await;
+
+        assert_eq!(exchange(&mut client, vec![query("")], 2).await, "IZ");
+        assert_eq!(exchange(&mut client, vec![query("  ")], 2).await, "IZ");
+
+        // ParseComplete, BindComplete, NoData, EmptyQueryResponse, ReadyForQuery
+        for empty in ["", ";", "-- comment"] {
+            assert_eq!(
+                exchange(
+                    &mut client,
+                    vec![
+                        parse("", empty),
+                        bind("", ""),
+                        describe_portal(""),
+                        execute(""),
+                        sync()
+                    ],
+                    5
+                )
+                .await,
+                "12nIZ"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush() {
+        let mut client = connect().await;
+
+        // Responses are delivered without Sync
+        assert_eq!(
+            exchange(&mut client, vec![parse("s", ""), flush()], 1).await,
+            "1"
+        );
+        assert_eq!(
+            exchange(&mut client, vec![bind("p", "s"), flush()], 1).await,
+            "2"
+        );
+
+        // Error is delivered on Flush, the rest is discarded until Sync
+        assert_eq!(
+            exchange(&mut client, vec![bind("", "unknown"), flush()], 1).await,
+            "E"
+        );
+        assert_eq!(
+            exchange(
+                &mut client,
+                vec![execute("p"), flush(), query(""), sync()],
+                1
+            )
+            .await,
+            "Z"
+        );
+
+        // Back to normal processing
+        assert_eq!(
+            exchange(&mut client, vec![execute("p"), sync()], 2).await,
+            "IZ"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_named_portals() {
+        let mut client = connect().await;
+
+        assert_eq!(
+            exchange(
+                &mut client,
+                vec![parse("s", ""), bind("p", "s"), bind("p", "s"), sync()],
+                4
+            )
+            .await,
+            "12EZ"
+        );
+
+        // Unnamed portal is replaced, named portal is executed without Describe
+        assert_eq!(
+            exchange(
+                &mut client,
+                vec![bind("", "s"), bind("", "s"), execute("p"), sync()],
+                4
+            )
+            .await,
+            "22IZ"
+        );
+
+        assert_eq!(
+            exchange(
+                &mut client,
+                vec![describe_portal("unknown"), execute("p"), sync()],
+                2
+            )
+            .await,
+            "EZ"
+        );
+    }
+
+    /// Backend messages must be complete, ErrorResponse must have its fields
+    fn assert_backend_messages(mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            assert!(bytes.len() >= 5, "truncated backend message header");
+            let length = i32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
+            assert!(
+                length >= 4 && bytes.len() > length,
+                "truncated backend message"
+            );
+            if bytes[0] == b'E' {
+                assert_error_fields(&bytes[5..length + 1]);
+            }
+            bytes = &bytes[length + 1..];
+        }
+    }
+
+    fn assert_error_fields(mut body: &[u8]) {
+        let mut fields = HashMap::new();
+        loop {
+            let (&field, rest) = body
+                .split_first()
+                .expect("ErrorResponse must be terminated");
+            if field == 0 {
+                assert!(
+                    rest.is_empty(),
+                    "ErrorResponse has data after the terminator"
+                );
+                break;
+            }
+            let end = rest
+                .iter()
+                .position(|byte| *byte == 0)
+                .expect("field must be a C string");
+            fields.insert(field, String::from_utf8(rest[..end].to_vec()).unwrap());
+            body = &rest[end + 1..];
+        }
+
+        assert!(matches!(
+            fields.get(&b'S').map(|s| s.as_str()),
+            Some("ERROR") | Some("FATAL")
+        ));
+        assert_eq!(fields.get(&b'V'), fields.get(&b'S'));
+        assert_eq!(fields.get(&b'C').map(|code| code.len()), Some(5));
+        assert!(fields.contains_key(&b'M'));
+    }
+
+    /// Malformed and truncated messages of a connection which has passed the startup. The shim
+    /// peeks into its TcpStream for idle timeouts, so it's driven through a loopback socket
+    /// instead of an in-memory duplex stream.
+    #[tokio::test]
+    async fn test_fuzz_messages() {
+        let corpus = vec![
+            query("SELECT 1"),
+            [
+                parse("s1", "SELECT 1"),
+                bind("p1", "s1"),
+                describe_portal("p1"),
+                execute("p1"),
+                sync(),
+            ]
+            .concat(),
+            [
+                parse("", "SELECT 1"),
+                flush(),
+                bind("", ""),
+                execute(""),
+                sync(),
+            ]
+            .concat(),
+            message(b'C', [vec![b'S'], cstr(&["s1"])].concat()),
+            message(b'X', vec![]),
+        ];
+        let mut rng = Rng::new(0x5eed);
+
+        for _ in 0..200 {
+            let input = mutate(&mut rng, &corpus[rng.below(corpus.len())]);
+            let (mut client, mut shim) = accept().await;
+            let handler = tokio::spawn(async move { shim.process_messages().await });
+
+            client.write_all(&input).await.unwrap();
+            client.shutdown().await.unwrap();
+
+            let mut output = vec![];
+            let read =
+                tokio::time::timeout(Duration::from_secs(10), client.read_to_end(&mut output))
+                    .await
+                    .expect("connection must be closed after the client is gone");
+            let result = handler.await;
+            assert!(
+                !matches!(&result, Err(err) if err.is_panic()),
+                "handler panicked on {:?}",
+                input
+            );
+            // Connection can be reset when the handler doesn't read the rest of the input
+            if read.is_ok() {
+                assert_backend_messages(&output);
+            }
+        }
+    }
+
+    /// Scripted server side of a Kerberos exchange: one round trip, then the context is
+    /// established with the last token for the client
+    #[derive(Debug)]
+    struct TestGssAuthenticator {
+        principal: String,
+        round: usize,
+    }
+
+    #[async_trait]
+    impl GssAuthenticator for TestGssAuthenticator {
+        async fn step(&mut self, token: Vec<u8>) -> Result<GssStep, CubeError> {
+            self.round += 1;
+            match (self.round, token.as_slice()) {
+                (1, b"client-1") => Ok(GssStep::Continue(b"server-1".to_vec())),
+                (2, b"client-2") => Ok(GssStep::Complete {
+                    principal: self.principal.clone(),
+                    token: Some(b"server-2".to_vec()),
+                }),
+                _ => Err(CubeError::user("Unexpected GSSAPI token".to_string())),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct TestGssAuth {
+        principal: String,
+    }
+
+    #[async_trait]
+    impl SqlAuthService for TestGssAuth {
+        async fn authenticate(
+            &self,
+            _user: Option<String>,
+            password: Option<String>,
+        ) -> Result<AuthenticateResponse, CubeError> {
+            Ok(AuthenticateResponse {
+                context: Arc::new(HttpAuthContext {
+                    access_token: "fake".to_string(),
+                    base_path: "fake".to_string(),
+                }),
+                password,
+                skip_password_check: false,
+            })
+        }
+
+        async fn gss_authenticator(&self) -> Option<Box<dyn GssAuthenticator>> {
+            Some(Box::new(TestGssAuthenticator {
+                principal: self.principal.clone(),
+                round: 0,
+            }))
+        }
+    }
+
+    async fn gss_shim(principal: &str) -> (TcpStream, AsyncPostgresShim) {
+        let server = Arc::new(ServerManager::new(
+            Arc::new(TestGssAuth {
+                principal: principal.to_string(),
+            }),
+            get_test_transport(),
+            None,
+            Arc::new(ConfigObjImpl::default()),
+        ));
+        let session = SessionManager::new(server)
+            .create_session(DatabaseProtocol::PostgreSQL, "127.0.0.1".to_string(), 1234)
+            .await;
+
+        accept_session(session).await
+    }
+
+    /// Tag and body of the next backend message
+    async fn read_backend_message(client: &mut TcpStream) -> (u8, Vec<u8>) {
+        let tag = tokio::time::timeout(Duration::from_secs(5), client.read_u8())
+            .await
+            .expect("backend message was not delivered")
+            .unwrap();
+        let length = client.read_i32().await.unwrap() as usize;
+        let mut body = vec![0; length - 4];
+        client.read_exact(&mut body).await.unwrap();
+
+        (tag, body)
+    }
+
+    fn authentication(code: u32, data: &[u8]) -> (u8, Vec<u8>) {
+        let mut body = code.to_be_bytes().to_vec();
+        body.extend_from_slice(data);
+        (b'R', body)
+    }
+
+    async fn authenticate_gss(shim: &mut AsyncPostgresShim) -> Result<bool, ConnectionError> {
+        let gss = shim.session.server.auth.gss_authenticator().await.unwrap();
+        let parameters = HashMap::from([("user".to_string(), "ovr".to_string())]);
+
+        shim.authenticate_gss(gss, parameters).await
+    }
+
+    #[tokio::test]
+    async fn test_gss_authentication() {
+        let (mut client, mut shim) = gss_shim("ovr@EXAMPLE.COM").await;
+        let handler = tokio::spawn(async move {
+            let result = authenticate_gss(&mut shim).await;
+            (result, shim)
+        });
+
+        assert_eq!(
+            read_backend_message(&mut client).await,
+            authentication(7, b"")
+        );
+        client
+            .write_all(&message(b'p', b"client-1".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(
+            read_backend_message(&mut client).await,
+            authentication(8, b"server-1")
+        );
+        client
+            .write_all(&message(b'p', b"client-2".to_vec()))
+            .await
+            .unwrap();
+        // Last token of the established context is delivered before AuthenticationOk
+        assert_eq!(
+            read_backend_message(&mut client).await,
+            authentication(8, b"server-2")
+        );
+        assert_eq!(
+            read_backend_message(&mut client).await,
+            authentication(0, b"")
+        );
+
+        let (result, shim) = handler.await.unwrap();
+        assert!(result.unwrap());
+        assert_eq!(shim.session.state.user(), Some("ovr".to_string()));
+        assert!(shim.session.state.auth_context().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_gss_authentication_rejected() {
+        // Principal doesn't match the requested user
+        let (mut client, mut shim) = gss_shim("admin@EXAMPLE.COM").await;
+        let handler = tokio::spawn(async move { authenticate_gss(&mut shim).await });
+
+        assert_eq!(
+            read_backend_message(&mut client).await,
+            authentication(7, b"")
+        );
+        client
+            .write_all(&message(b'p', b"client-1".to_vec()))
+            .await
+            .unwrap();
+        read_backend_message(&mut client).await;
+        client
+            .write_all(&message(b'p', b"client-2".to_vec()))
+            .await
+            .unwrap();
+        read_backend_message(&mut client).await;
+
+        let (tag, body) = read_backend_message(&mut client).await;
+        assert_eq!(tag, b'E');
+        assert_error_fields(&body);
+        assert!(!handler.await.unwrap().unwrap());
+
+        // Invalid token fails the negotiation
+        let (mut client, mut shim) = gss_shim("ovr@EXAMPLE.COM").await;
+        let handler = tokio::spawn(async move { authenticate_gss(&mut shim).await });
+
+        read_backend_message(&mut client).await;
+        client
+            .write_all(&message(b'p', b"unknown".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(read_backend_message(&mut client).await.0, b'E');
+        assert!(!handler.await.unwrap().unwrap());
+    }
+
+    /// First values of the rows returned by a simple query, None if it has failed
+    async fn query_values(client: &mut TcpStream, sql: &str) -> Option<Vec<String>> {
+        client.write_all(&query(sql)).await.unwrap();
+
+        let mut values = Some(vec![]);
+        loop {
+            match read_backend_message(client).await {
+                (b'D', body) => {
+                    let length = i32::from_be_bytes([body[2], body[3], body[4], body[5]]) as usize;
+                    if let Some(values) = &mut values {
+                        values.push(String::from_utf8(body[6..6 + length].to_vec()).unwrap());
+                    }
+                }
+                (b'E', _) => values = None,
+                (b'Z', _) => return values,
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cube_last_result() {
+        let config = ConfigObjImpl {
+            last_result_max_size: 1024 * 1024,
+            ..ConfigObjImpl::default()
+        };
+        let session =
+            get_test_session_with_config(DatabaseProtocol::PostgreSQL, Arc::new(config)).await;
+        let (mut client, mut shim) = accept_session(session).await;
+        tokio::spawn(async move { shim.process_messages().await });
+
+        assert_eq!(
+            query_values(&mut client, "SELECT COUNT(*) FROM cube_last_result()").await,
+            None
+        );
+        query_values(
+            &mut client,
+            "SELECT 1 AS n UNION ALL SELECT 2 AS n UNION ALL SELECT 3 AS n",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            query_values(
+                &mut client,
+                "SELECT SUM(n) AS total FROM cube_last_result() WHERE n > 1"
+            )
+            .await,
+            Some(vec!["5".to_string()])
+        );
+        // The previous statement is the aggregation
+        assert_eq!(
+            query_values(&mut client, "SELECT COUNT(*) FROM cube_last_result()").await,
+            Some(vec!["1".to_string()])
+        );
+
+        // Results are not retained by default
+        let mut client = connect().await;
+        query_values(&mut client, "SELECT 1 AS n").await.unwrap();
+        assert_eq!(
+            query_values(&mut client, "SELECT COUNT(*) FROM cube_last_result()").await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscription() {
+        let config = ConfigObjImpl {
+            subscription_poll_interval_secs: 1,
+            ..ConfigObjImpl::default()
+        };
+        let session =
+            get_test_session_with_config(DatabaseProtocol::PostgreSQL, Arc::new(config)).await;
+        let (mut client, mut shim) = accept_session(session).await;
+        let handler = tokio::spawn(async move {
+            let result = shim.process_messages().await;
+            shim.end_reason(&result)
+        });
+
+        // RowDescription and the row of the first poll
+        assert_eq!(
+            exchange(&mut client, vec![query("SELECT 1 AS n EMIT CHANGES;")], 2).await,
+            "TD"
+        );
+        // The row of the next polls is not sent again. Another message ends the subscription
+        // and is processed after it.
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(
+            exchange(&mut client, vec![query("SELECT 2 AS n")], 6).await,
+            "EZTDCZ"
+        );
+
+        // The clause in a comment is not a subscription
+        assert_eq!(
+            exchange(&mut client, vec![query("SELECT 1 AS n -- EMIT CHANGES")], 4).await,
+            "TDCZ"
+        );
+
+        // Disconnect of the client is noticed between polls
+        assert_eq!(
+            exchange(&mut client, vec![query("SELECT 1 AS n EMIT CHANGES")], 2).await,
+            "TD"
+        );
+        drop(client);
+        let reason = tokio::time::timeout(Duration::from_secs(5), handler)
+            .await
+            .expect("subscription was not ended by the disconnect")
+            .unwrap();
+        assert_eq!(reason, SessionEndReason::Disconnect);
+    }
+}
@@ -34,11 +34,26 @@ fn pg_base_date_epoch() -> NaiveDateTime {
         .unwrap()
 }
 
+/// Offset of timestamptz in PostgreSQL format: `+00`, `-05`, `+05:30`
+fn format_utc_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    if minutes % 60 == 0 {
+        format!("{}{:02}", sign, minutes / 60)
+    } else {
+        format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+    }
+}
+
 impl ToProtocolValue for TimestampValue {
     fn to_text(&self, buf: &mut BytesMut) -> Result<(), ProtocolError> {
-        let ndt = match self.tz_ref() {
-            None => self.to_naive_datetime(),
-            Some(_) => self.to_fixed_datetime()?.naive_utc(),
+        // Values with a time zone are written as the local time in it, with the offset
+        let (ndt, offset) = match self.tz_ref() {
+            None => (self.to_naive_datetime(), None),
+            Some(_) => {
+                let dt = self.to_fixed_datetime()?;
+                (dt.naive_local(), Some(dt.offset().fix().local_minus_utc()))
+            }
         };
 
         // 2022-04-25 15:36:49.39705+00
@@ -62,9 +77,9 @@ impl ToProtocolValue for TimestampValue {
             )
             .to_string();
 
-        match self.tz_ref() {
+        match offset {
             None => as_str.to_text(buf),
-            Some(_) => (as_str + &"+00").to_text(buf),
+            Some(offset) => (as_str + &format_utc_offset(offset)).to_text(buf),
         }
     }
 
@@ -126,6 +141,20 @@ impl ToProtocolValue for Decimal128Value {
     }
 }
 
+/// Elements of text arrays are quoted when they can't be read back as is, e.g. `{"a,b",NULL}`
+fn quote_array_element(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value.eq_ignore_ascii_case("null")
+        || value
+            .chars()
+            .any(|c| matches!(c, '{' | '}' | ',' | '"' | '\\') || c.is_whitespace());
+    if !needs_quotes {
+        return value.to_string();
+    }
+
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 impl ToProtocolValue for ListValue {
     fn to_text(&self, buf: &mut BytesMut) -> Result<(), ProtocolError> {
         let mut values: Vec<String> = Vec::with_capacity(self.v.len());
@@ -136,7 +165,7 @@ impl ToProtocolValue for ListValue {
 
                 for i in 0..$ARRAY.len() {
                     if self.v.is_null(i) {
-                        $BUFF.push("null".to_string());
+                        $BUFF.push("NULL".to_string());
                     } else {
                         $BUFF.push(arr.value(i).to_string());
                     }
@@ -159,7 +188,17 @@ impl ToProtocolValue for ListValue {
             DataType::UInt32 => write_native_array_to_buffer!(self.v, values, UInt32Array),
             DataType::UInt64 => write_native_array_to_buffer!(self.v, values, UInt64Array),
             DataType::Boolean => write_native_array_to_buffer!(self.v, values, BooleanArray),
-            DataType::Utf8 => write_native_array_to_buffer!(self.v, values, StringArray),
+            DataType::Utf8 => {
+                let arr = self.v.as_any().downcast_ref::<StringArray>().unwrap();
+
+                for i in 0..self.v.len() {
+                    if self.v.is_null(i) {
+                        values.push("NULL".to_string());
+                    } else {
+                        values.push(quote_array_element(arr.value(i)));
+                    }
+                }
+            }
             dt => {
                 return Err(protocol::ErrorResponse::error(
                     protocol::ErrorCode::InternalError,
@@ -230,7 +269,7 @@ impl ToProtocolValue for ListValue {
 
                 for i in 0..self.v.len() {
                     if self.v.is_null(i) {
-                        "null".to_string().to_binary(&mut column_data)?
+                        Option::<String>::None.to_binary(&mut column_data)?
                     } else {
                         arr.value(i).to_string().to_binary(&mut column_data)?
                     }
@@ -308,6 +347,11 @@ impl BatchWriter {
     pub fn has_data(&self) -> bool {
         self.rows > 0
     }
+
+    /// Encoded DataRow messages
+    pub fn data(&self) -> &[u8] {
+        &self.data[..]
+    }
 }
 
 impl<'a> Serialize for BatchWriter {
@@ -329,7 +373,7 @@ mod tests {
         writer::{BatchWriter, ToProtocolValue},
     };
     use bytes::BytesMut;
-    use datafusion::arrow::array::{ArrayRef, Int64Builder};
+    use datafusion::arrow::array::{ArrayRef, Int64Builder, StringArray};
     use pg_srv::{buffer, protocol::Format};
     use std::{io::Cursor, sync::Arc};
 
@@ -356,6 +400,28 @@ mod tests {
                 48, 49, 46, 54, 53, 48, 56, 57, 48, 43, 48, 48,
             ],
         );
+        assert_text_encode(
+            TimestampValue::new(
+                1_650_890_322_000_000_000,
+                Some("America/New_York".to_string()),
+            ),
+            &[&[0, 0, 0, 29][..], &b"2022-04-25 08:38:42.000000-04"[..]].concat(),
+        );
+        assert_eq!(format_utc_offset(19800), "+05:30");
+        assert_text_encode(
+            ListValue::new(Arc::new(StringArray::from(vec![
+                Some("red"),
+                None,
+                Some("dark blue"),
+                Some("a,\"b\""),
+                Some("NULL"),
+            ])) as ArrayRef),
+            &[
+                &[0, 0, 0, 39][..],
+                &br#"{red,NULL,"dark blue","a,\"b\"","NULL"}"#[..],
+            ]
+            .concat(),
+        );
 
         Ok(())
     }
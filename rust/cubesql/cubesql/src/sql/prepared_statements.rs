@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use std::{collections::HashMap, hash::Hash};
+
+#[derive(Debug, Clone)]
+pub struct PreparedStatementUsage {
+    /// How many times statement was executed (bound to a portal for Postgres)
+    pub executions: u64,
+    pub last_used: DateTime<Utc>,
+}
+
+impl PreparedStatementUsage {
+    fn new() -> Self {
+        Self {
+            executions: 0,
+            last_used: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PreparedStatementEntry<V> {
+    statement: V,
+    usage: PreparedStatementUsage,
+    // Monotonic counter, timestamps are not precise enough to order statements used in a row
+    last_used_tick: u64,
+}
+
+/// Prepared statements allocated by the connection, it's shared between MySQL & Postgres protocols.
+/// When limit of statements is reached, least recently used statements are evicted.
+#[derive(Debug)]
+pub struct PreparedStatements<K, V> {
+    statements: HashMap<K, PreparedStatementEntry<V>>,
+    tick: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> PreparedStatements<K, V> {
+    pub fn new() -> Self {
+        Self {
+            statements: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.statements.contains_key(key)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.statements.get(key).map(|entry| &entry.statement)
+    }
+
+    pub fn usage(&self, key: &K) -> Option<&PreparedStatementUsage> {
+        self.statements.get(key).map(|entry| &entry.usage)
+    }
+
+    /// Get statement for execution, it updates usage statistics of the statement
+    pub fn get_for_execution(&mut self, key: &K) -> Option<&V> {
+        self.tick += 1;
+
+        let entry = self.statements.get_mut(key)?;
+        entry.usage.executions += 1;
+        entry.usage.last_used = Utc::now();
+        entry.last_used_tick = self.tick;
+
+        Some(&entry.statement)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V, &PreparedStatementUsage)> {
+        self.statements
+            .iter()
+            .map(|(key, entry)| (key, &entry.statement, &entry.usage))
+    }
+
+    /// Insert (or replace) statement, least recently used statements are evicted to keep the number
+    /// of statements under the limit. Returns keys of evicted statements.
+    pub fn insert(&mut self, key: K, statement: V, limit: usize) -> Vec<K> {
+        let mut evicted = vec![];
+
+        if !self.statements.contains_key(&key) {
+            while !self.statements.is_empty() && self.statements.len() >= limit {
+                let lru_key = self
+                    .statements
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used_tick)
+                    .map(|(key, _)| key.clone())
+                    .unwrap();
+
+                self.statements.remove(&lru_key);
+                evicted.push(lru_key);
+            }
+        }
+
+        self.tick += 1;
+        self.statements.insert(
+            key,
+            PreparedStatementEntry {
+                statement,
+                usage: PreparedStatementUsage::new(),
+                last_used_tick: self.tick,
+            },
+        );
+
+        evicted
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.statements.remove(key).map(|entry| entry.statement)
+    }
+
+    pub fn clear(&mut self) {
+        self.statements.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepared_statements_lru_eviction() {
+        let mut statements = PreparedStatements::new();
+
+        assert!(statements.insert("s1", 1, 2).is_empty());
+        assert!(statements.insert("s2", 2, 2).is_empty());
+
+        assert_eq!(statements.get_for_execution(&"s1"), Some(&1));
+        assert_eq!(statements.get_for_execution(&"s1"), Some(&1));
+        assert_eq!(statements.usage(&"s1").unwrap().executions, 2);
+        assert_eq!(statements.usage(&"s2").unwrap().executions, 0);
+
+        // s2 was never executed after s1 usage
+        assert_eq!(statements.insert("s3", 3, 2), vec!["s2"]);
+        assert!(statements.contains_key(&"s1"));
+        assert!(statements.contains_key(&"s3"));
+
+        // Replacing existing statement doesn't evict anything
+        assert!(statements.insert("s3", 4, 2).is_empty());
+        assert_eq!(statements.get(&"s3"), Some(&4));
+        assert_eq!(statements.len(), 2);
+
+        statements.clear();
+        assert!(statements.is_empty());
+    }
+}
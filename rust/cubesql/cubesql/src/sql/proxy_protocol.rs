@@ -0,0 +1,246 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::CubeError;
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+// PROXY TCP6 with max addresses and ports, including CRLF
+const V1_MAX_LENGTH: usize = 107;
+
+/// Addresses (or CIDR networks) of load balancers which are allowed to send a PROXY protocol
+/// header, e.g. `10.0.0.0/8, 192.168.1.10`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrustedSources(Vec<(IpAddr, u8)>);
+
+impl TrustedSources {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            ip => ip,
+        };
+
+        self.0.iter().any(|(network, prefix)| match (network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                u32::from(*network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+                u128::from(*network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        })
+    }
+}
+
+impl FromStr for TrustedSources {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sources = vec![];
+        for source in s.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (ip, prefix) = match source.split_once('/') {
+                Some((ip, prefix)) => (ip, Some(prefix)),
+                None => (source, None),
+            };
+            let ip = ip
+                .parse::<IpAddr>()
+                .map_err(|e| format!("invalid address '{}': {}", source, e))?;
+            let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                Some(prefix) => prefix
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|prefix| *prefix <= max_prefix)
+                    .ok_or_else(|| format!("invalid prefix length of '{}'", source))?,
+                None => max_prefix,
+            };
+
+            sources.push((ip, prefix));
+        }
+
+        Ok(Self(sources))
+    }
+}
+
+/// Reads the PROXY protocol (v1 or v2) header from the start of the stream and returns the
+/// address of the client. None is returned for health checks of the proxy (LOCAL, UNKNOWN) and
+/// for non TCP connections, the address of the socket must be used for them.
+pub async fn read_proxy_header<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> Result<Option<SocketAddr>, CubeError> {
+    // Both v2 signature and the shortest v1 header (PROXY UNKNOWN\r\n) are at least 12 bytes
+    let mut signature = [0_u8; 12];
+    stream.read_exact(&mut signature).await?;
+
+    if signature == V2_SIGNATURE {
+        read_v2_header(stream).await
+    } else if signature.starts_with(b"PROXY ") {
+        read_v1_header(stream, signature.to_vec()).await
+    } else {
+        Err(CubeError::user(
+            "Connection doesn't start with PROXY protocol header".to_string(),
+        ))
+    }
+}
+
+async fn read_v1_header<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    mut line: Vec<u8>,
+) -> Result<Option<SocketAddr>, CubeError> {
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(CubeError::user(
+                "PROXY protocol header is too long".to_string(),
+            ));
+        }
+
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = String::from_utf8(line)?;
+    let invalid = || {
+        CubeError::user(format!(
+            "Invalid PROXY protocol header: {}",
+            line.trim_end()
+        ))
+    };
+    let parts = line.trim_end().split(' ').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _, source_port, _] => {
+            let ip = source.parse::<IpAddr>().map_err(|_| invalid())?;
+            let port = source_port.parse::<u16>().map_err(|_| invalid())?;
+
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+async fn read_v2_header<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> Result<Option<SocketAddr>, CubeError> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let length = stream.read_u16().await? as usize;
+
+    let mut addresses = vec![0_u8; length];
+    stream.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(CubeError::user(format!(
+            "Unsupported PROXY protocol version: {}",
+            version_command >> 4
+        )));
+    }
+
+    match version_command & 0x0F {
+        // LOCAL
+        0x00 => return Ok(None),
+        // PROXY
+        0x01 => {}
+        command => {
+            return Err(CubeError::user(format!(
+                "Unsupported PROXY protocol command: {}",
+                command
+            )))
+        }
+    }
+
+    let too_short = || CubeError::user("PROXY protocol addresses are truncated".to_string());
+    match family {
+        // TCP over IPv4: source, destination addresses and ports
+        0x11 => {
+            let addresses = addresses.get(..12).ok_or_else(too_short)?;
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        // TCP over IPv6
+        0x21 => {
+            let addresses = addresses.get(..36).ok_or_else(too_short)?;
+            let mut ip = [0_u8; 16];
+            ip.copy_from_slice(&addresses[..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+
+            Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port)))
+        }
+        // UNSPEC, UDP and unix sockets
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trusted_sources() {
+        let sources = "10.0.0.0/8, 192.168.1.10,fd00::/8"
+            .parse::<TrustedSources>()
+            .unwrap();
+
+        assert!(sources.contains("10.1.2.3".parse().unwrap()));
+        assert!(sources.contains("192.168.1.10".parse().unwrap()));
+        assert!(sources.contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(sources.contains("fd12::1".parse().unwrap()));
+        assert!(!sources.contains("192.168.1.11".parse().unwrap()));
+        assert!(!sources.contains("11.0.0.1".parse().unwrap()));
+
+        assert!("0.0.0.0/0"
+            .parse::<TrustedSources>()
+            .unwrap()
+            .contains("8.8.8.8".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<TrustedSources>().is_err());
+        assert!("localhost".parse::<TrustedSources>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_proxy_header_v1() -> Result<(), CubeError> {
+        let mut stream: &[u8] = b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 5432\r\nQ";
+        assert_eq!(
+            read_proxy_header(&mut stream).await?,
+            Some("203.0.113.7:56324".parse().unwrap())
+        );
+        // Data after the header is not consumed
+        assert_eq!(stream, b"Q");
+
+        let mut stream: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_proxy_header(&mut stream).await?, None);
+
+        let mut stream: &[u8] = b"PROXY TCP4 nope 10.0.0.1 1 2\r\n";
+        assert!(read_proxy_header(&mut stream).await.is_err());
+
+        let mut stream: &[u8] = b"\x00\x00\x00\x08\x04\xd2\x16\x2f\x00\x00\x00\x00";
+        assert!(read_proxy_header(&mut stream).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_proxy_header_v2() -> Result<(), CubeError> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11, 0x00, 0x0C]);
+        header.extend_from_slice(&[203, 0, 113, 7, 10, 0, 0, 1]);
+        header.extend_from_slice(&[0xDC, 0x04, 0x15, 0x38]);
+        header.push(b'Q');
+
+        let mut stream = header.as_slice();
+        assert_eq!(
+            read_proxy_header(&mut stream).await?,
+            Some("203.0.113.7:56324".parse().unwrap())
+        );
+        assert_eq!(stream, b"Q");
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+        assert_eq!(read_proxy_header(&mut header.as_slice()).await?, None);
+
+        Ok(())
+    }
+}
@@ -1,16 +1,31 @@
 use crate::{
+    compile::{
+        engine::df::{
+            result_processor::ResultProcessors, runtime::create_runtime_env_or_default,
+            scan::ResponseDateFormats, transform_pool::TransformPool,
+        },
+        rewrite::rules::custom::CustomRewrites,
+    },
     config::ConfigObj,
     sql::{
         database_variables::{
             mysql_default_global_variables, postgres_default_global_variables,
             DatabaseVariablesToUpdate,
         },
-        SqlAuthService,
+        MemberUsageStats, PlanningFailures, SqlAuthService, StatementClassStats,
+        UnsupportedQueryStats,
     },
-    transport::TransportService,
+    transport::{LoadLimiter, MemoryPressure, ParsedSqlViews, TransportService},
     CubeError,
 };
-use std::sync::{Arc, RwLock as RwLockSync, RwLockReadGuard, RwLockWriteGuard};
+use datafusion::execution::runtime_env::RuntimeEnv;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock as RwLockSync, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::Duration,
+};
 
 use super::{database_variables::DatabaseVariables, session::DatabaseProtocol};
 
@@ -44,6 +59,24 @@ pub struct ServerManager {
     pub configuration: ServerConfiguration,
     pub nonce: Option<Vec<u8>>,
     pub config_obj: Arc<dyn ConfigObj>,
+    pub member_usage: Arc<MemberUsageStats>,
+    pub unsupported_queries: Arc<UnsupportedQueryStats>,
+    pub statement_classes: Arc<StatementClassStats>,
+    pub load_limiter: Arc<LoadLimiter>,
+    pub memory_pressure: Arc<MemoryPressure>,
+    pub transform_pool: Arc<TransformPool>,
+    pub date_formats: Arc<ResponseDateFormats>,
+    // Processors of CubeScan results, embedding code can register own processors
+    pub result_processors: Arc<ResultProcessors>,
+    pub planning_failures: Arc<PlanningFailures>,
+    // Rewrite rules of embedding code, they are applied with the built-in rules
+    pub custom_rewrites: Arc<CustomRewrites>,
+    // Runtime of DataFusion plans, memory of sorts is limited by it
+    pub runtime_env: Arc<RuntimeEnv>,
+    // Views are parsed once, not on every query
+    pub parsed_views: ParsedSqlViews,
+    // Connections which were closed because of a panic of the handler
+    connection_panics: AtomicU64,
     postgres_variables: RwLockSync<DatabaseVariables>,
     mysql_variables: RwLockSync<DatabaseVariables>,
 }
@@ -61,13 +94,49 @@ impl ServerManager {
             auth,
             transport,
             nonce,
+            load_limiter: Arc::new(LoadLimiter::new(
+                config_obj.transport_max_concurrent_loads(),
+            )),
+            memory_pressure: Arc::new(MemoryPressure::new(
+                config_obj.memory_high_watermark_mb() * 1024 * 1024,
+                config_obj.memory_low_watermark_mb() * 1024 * 1024,
+                Duration::from_secs(config_obj.memory_relief_timeout_secs()),
+            )),
+            transform_pool: Arc::new(TransformPool::new(config_obj.transform_max_concurrency())),
+            date_formats: Arc::new(config_obj.response_date_formats().clone()),
+            result_processors: Arc::new(ResultProcessors::new(config_obj.result_processors())),
+            planning_failures: Arc::new(PlanningFailures::new(
+                Duration::from_secs(config_obj.planning_failure_cache_ttl_secs()),
+                config_obj.planning_failure_cache_max_entries(),
+            )),
+            custom_rewrites: Arc::new(CustomRewrites::new(
+                config_obj.custom_rewrite_max_rules(),
+                config_obj.custom_rewrite_max_iterations(),
+            )),
+            runtime_env: create_runtime_env_or_default(
+                config_obj.sort_memory_limit_mb() * 1024 * 1024,
+                config_obj.sort_spill_path().as_deref(),
+            ),
+            parsed_views: ParsedSqlViews::new(),
             config_obj,
             configuration: ServerConfiguration::default(),
+            member_usage: Arc::new(MemberUsageStats::new()),
+            unsupported_queries: Arc::new(UnsupportedQueryStats::new()),
+            statement_classes: Arc::new(StatementClassStats::new()),
+            connection_panics: AtomicU64::new(0),
             postgres_variables: RwLockSync::new(postgres_default_global_variables()),
             mysql_variables: RwLockSync::new(mysql_default_global_variables()),
         }
     }
 
+    pub fn record_connection_panic(&self) {
+        self.connection_panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_panics(&self) -> u64 {
+        self.connection_panics.load(Ordering::Relaxed)
+    }
+
     pub fn read_variables(
         &self,
         protocol: DatabaseProtocol,
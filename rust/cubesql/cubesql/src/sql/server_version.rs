@@ -0,0 +1,155 @@
+use std::env;
+
+/// Versions reported to clients during the handshake and by version()/current_setting().
+/// Some BI tools enable features depending on the reported version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerVersion {
+    pub mysql: String,
+    /// None keeps the versions which were reported before it was configurable
+    pub postgres: Option<String>,
+    /// Additional (or overridden) parameters which are sent to Postgres clients on startup
+    pub postgres_parameters: Vec<(String, String)>,
+}
+
+impl Default for ServerVersion {
+    fn default() -> Self {
+        Self {
+            mysql: "8.0.25".to_string(),
+            postgres: None,
+            postgres_parameters: vec![],
+        }
+    }
+}
+
+impl ServerVersion {
+    /// CUBESQL_PG_PARAMETER_STATUS is a comma separated list of name=value pairs
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            mysql: env::var("CUBESQL_MYSQL_SERVER_VERSION").unwrap_or(default.mysql),
+            postgres: env::var("CUBESQL_PG_SERVER_VERSION")
+                .ok()
+                .or(default.postgres),
+            postgres_parameters: env::var("CUBESQL_PG_PARAMETER_STATUS")
+                .map(|v| Self::parse_parameters(&v))
+                .unwrap_or(default.postgres_parameters),
+        }
+    }
+
+    fn parse_parameters(value: &str) -> Vec<(String, String)> {
+        value
+            .split(',')
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let name = name.trim();
+                if name.is_empty() {
+                    return None;
+                }
+
+                Some((name.to_string(), value.trim().to_string()))
+            })
+            .collect()
+    }
+
+    /// server_version parameter
+    pub fn postgres_version(&self) -> &str {
+        self.postgres.as_deref().unwrap_or("14.2")
+    }
+
+    /// Result of version(), by default it reports 14.1 as before
+    pub fn postgres_version_string(&self) -> String {
+        format!(
+            "PostgreSQL {} on x86_64-cubesql",
+            self.postgres.as_deref().unwrap_or("14.1")
+        )
+    }
+
+    /// server_version_num format, for example 140002 for 14.2 and 90624 for 9.6.24
+    pub fn postgres_version_num(&self) -> String {
+        let parts = self
+            .postgres_version()
+            .split(|c: char| !c.is_ascii_digit())
+            .take_while(|p| !p.is_empty())
+            .map(|p| p.parse::<u32>().unwrap_or(0))
+            .collect::<Vec<_>>();
+        let part = |i: usize| parts.get(i).cloned().unwrap_or(0);
+
+        let num = if part(0) >= 10 {
+            part(0) * 10000 + part(1)
+        } else {
+            part(0) * 10000 + part(1) * 100 + part(2)
+        };
+
+        num.to_string()
+    }
+
+    pub fn postgres_parameter(&self, name: &str) -> Option<&String> {
+        self.postgres_parameters
+            .iter()
+            .rev()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_postgres_version_num() {
+        let version = |v: &str| ServerVersion {
+            postgres: Some(v.to_string()),
+            ..ServerVersion::default()
+        };
+
+        assert_eq!(ServerVersion::default().postgres_version_num(), "140002");
+        assert_eq!(version("14.2").postgres_version_num(), "140002");
+        assert_eq!(version("16.1 (Debian)").postgres_version_num(), "160001");
+        assert_eq!(version("9.6.24").postgres_version_num(), "90624");
+        assert_eq!(version("13").postgres_version_num(), "130000");
+    }
+
+    #[test]
+    fn test_postgres_version_string() {
+        assert_eq!(
+            ServerVersion::default().postgres_version_string(),
+            "PostgreSQL 14.1 on x86_64-cubesql"
+        );
+        assert_eq!(ServerVersion::default().postgres_version(), "14.2");
+
+        let version = ServerVersion {
+            postgres: Some("16.1".to_string()),
+            ..ServerVersion::default()
+        };
+        assert_eq!(
+            version.postgres_version_string(),
+            "PostgreSQL 16.1 on x86_64-cubesql"
+        );
+        assert_eq!(version.postgres_version(), "16.1");
+    }
+
+    #[test]
+    fn test_postgres_parameters() {
+        let version = ServerVersion {
+            postgres_parameters: ServerVersion::parse_parameters(
+                "is_superuser=on, application_name = cube,invalid",
+            ),
+            ..ServerVersion::default()
+        };
+
+        assert_eq!(
+            version.postgres_parameters,
+            vec![
+                ("is_superuser".to_string(), "on".to_string()),
+                ("application_name".to_string(), "cube".to_string()),
+            ]
+        );
+        assert_eq!(
+            version.postgres_parameter("IS_SUPERUSER"),
+            Some(&"on".to_string())
+        );
+        assert_eq!(version.postgres_parameter("server_encoding"), None);
+    }
+}
@@ -1,28 +1,40 @@
+use chrono_tz::Tz;
 use datafusion::scalar::ScalarValue;
-use log::trace;
+use log::{error, trace, warn};
 use rand::Rng;
 use std::{
-    collections::HashMap,
     sync::{Arc, RwLock as RwLockSync},
     time::{Duration, SystemTime},
 };
 use tokio_util::sync::CancellationToken;
 
 use crate::{
+    compile::{
+        engine::df::{
+            join_strategy::JoinStrategy, last_result::LastResult, scan::MaxRecordsOverrides,
+            scan_schemas::ScanSchemaCache,
+        },
+        parser::PushdownHint,
+        CompilationError,
+    },
     sql::{
         database_variables::{
             mysql_default_session_variables, postgres_default_session_variables, DatabaseVariable,
             DatabaseVariablesToUpdate,
         },
         extended::PreparedStatement,
+        ConnectionPanic, PreparedStatements, StatementSchemas,
+    },
+    telemetry::SessionLogger,
+    transport::{
+        CubeRequest, LastCubeRequest, LoadRequestMeta, QueryPriority, QueryWarnings, TransportTrace,
     },
-    transport::LoadRequestMeta,
     RWLockAsync,
 };
 
 use super::{
     database_variables::DatabaseVariables, server_manager::ServerManager,
-    session_manager::SessionManager, AuthContextRef,
+    session_manager::SessionManager, AuthContextRef, HttpAuthContext, StatementClass,
 };
 
 extern crate lazy_static;
@@ -42,15 +54,55 @@ impl DatabaseProtocol {
     }
 }
 
+/// Why the connection of the session was closed, it's reported by the session-end telemetry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEndReason {
+    /// Client sent Terminate
+    Terminate,
+    /// Socket was closed or reset by the client without Terminate
+    Disconnect,
+    /// Connection was closed by the protocol library, it doesn't tell if it was graceful
+    Closed,
+    /// Startup didn't finish: cancel requests, refused SSL or failed authentication
+    Startup,
+    /// Idle in transaction or the client doesn't read results
+    Timeout,
+    Error,
+    Panic,
+}
+
+impl SessionEndReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionEndReason::Terminate => "terminate",
+            SessionEndReason::Disconnect => "disconnect",
+            SessionEndReason::Closed => "closed",
+            SessionEndReason::Startup => "startup",
+            SessionEndReason::Timeout => "timeout",
+            SessionEndReason::Error => "error",
+            SessionEndReason::Panic => "panic",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SessionProperties {
     user: Option<String>,
+    // Authenticated user, it's kept when the user is switched by `SET user`
+    session_user: Option<String>,
     database: Option<String>,
+    // Cube API base path of the selected database, see DatabaseMapping
+    base_path: Option<String>,
 }
 
 impl SessionProperties {
     pub fn new(user: Option<String>, database: Option<String>) -> Self {
-        Self { user, database }
+        Self {
+            session_user: user.clone(),
+            user,
+            database,
+            base_path: None,
+        }
     }
 }
 
@@ -101,7 +153,41 @@ pub struct SessionState {
     query: RwLockSync<QueryState>,
 
     // Extended Query
-    pub statements: RWLockAsync<HashMap<String, PreparedStatement>>,
+    pub statements: RWLockAsync<PreparedStatements<String, PreparedStatement>>,
+
+    // Warnings of the last query, e.g. the result was truncated by the query limit
+    warnings: QueryWarnings,
+
+    // The last request which was sent to Cube, see `SHOW LAST CUBE REQUEST`
+    last_cube_request: LastCubeRequest,
+
+    // Hint from the query text which is planned right now, comments are lost after parsing
+    pushdown_hint: RwLockSync<Option<PushdownHint>>,
+
+    // Scan schemas of the prepared statement which is executed right now
+    scan_schemas: RwLockSync<Option<Arc<ScanSchemaCache>>>,
+
+    // Fetch size of the cursor which is opened right now
+    cursor_fetch_size: RwLockSync<Option<usize>>,
+
+    // Fingerprint of the last planned statement, it's reported if the connection panics
+    last_statement_fingerprint: RwLockSync<Option<String>>,
+
+    // Class of the last planned statement, telemetry events of the session are tagged by it
+    last_statement_class: RwLockSync<Option<StatementClass>>,
+
+    // Result of the previous statement which returned rows, it's scanned by `cube_last_result()`
+    last_result: RwLockSync<Option<Arc<LastResult>>>,
+
+    // Scan schemas of prepared statements, they are keyed by parameter types and shared by bind
+    // values
+    pub statement_schemas: StatementSchemas,
+
+    // Calls to the transport of the query which is traced right now
+    transport_trace: RwLockSync<Option<TransportTrace>>,
+
+    // Trace of the last traced query, it's returned by `SHOW LAST QUERY TRACE`
+    last_query_trace: RwLockSync<Option<String>>,
 
     auth_context_expiration: Duration,
 }
@@ -128,7 +214,18 @@ impl SessionState {
             auth_context: RwLockSync::new((auth_context, SystemTime::now())),
             transaction: RwLockSync::new(TransactionState::None),
             query: RwLockSync::new(QueryState::None),
-            statements: RWLockAsync::new(HashMap::new()),
+            statements: RWLockAsync::new(PreparedStatements::new()),
+            warnings: QueryWarnings::default(),
+            last_cube_request: LastCubeRequest::default(),
+            pushdown_hint: RwLockSync::new(None),
+            scan_schemas: RwLockSync::new(None),
+            cursor_fetch_size: RwLockSync::new(None),
+            last_statement_fingerprint: RwLockSync::new(None),
+            last_statement_class: RwLockSync::new(None),
+            last_result: RwLockSync::new(None),
+            statement_schemas: StatementSchemas::new(),
+            transport_trace: RwLockSync::new(None),
+            last_query_trace: RwLockSync::new(None),
             auth_context_expiration,
         }
     }
@@ -217,6 +314,9 @@ impl SessionState {
             trace!("Unable to begin new query while previous is still active.")
         };
 
+        // Warnings of the previous query are not related to the new one
+        self.warnings.take();
+
         let cancel = CancellationToken::new();
 
         *guard = QueryState::Active {
@@ -248,9 +348,22 @@ impl SessionState {
         self.clear_prepared_statements().await;
     }
 
+    /// Returns the session to the state of a new connection, it's used by DISCARD ALL which
+    /// poolers issue between clients. User, database and security context are kept.
+    pub async fn discard_all(&self) {
+        self.clear_extended().await;
+        self.warnings.take();
+
+        let mut guard = self
+            .variables
+            .write()
+            .expect("failed to unlock variables for discarding");
+        *guard = None;
+    }
+
     pub async fn clear_prepared_statements(&self) {
         let mut statements_guard = self.statements.write().await;
-        *statements_guard = HashMap::new();
+        statements_guard.clear();
     }
 
     pub fn user(&self) -> Option<String> {
@@ -266,9 +379,30 @@ impl SessionState {
             .properties
             .write()
             .expect("failed to unlock properties for writting user");
+        if guard.session_user.is_none() {
+            guard.session_user = user.clone();
+        }
         guard.user = user;
     }
 
+    /// User which was authenticated by the connection
+    pub fn session_user(&self) -> Option<String> {
+        let guard = self
+            .properties
+            .read()
+            .expect("failed to unlock properties for reading session_user");
+        guard.session_user.clone()
+    }
+
+    /// Current user was switched from the authenticated one by `SET user`
+    pub fn is_user_switched(&self) -> bool {
+        let guard = self
+            .properties
+            .read()
+            .expect("failed to unlock properties for reading user");
+        guard.user != guard.session_user
+    }
+
     pub fn database(&self) -> Option<String> {
         let guard = self
             .properties
@@ -285,6 +419,15 @@ impl SessionState {
         guard.database = database;
     }
 
+    /// Routes transport requests of the session to another Cube API deployment
+    pub fn set_base_path(&self, base_path: Option<String>) {
+        let mut guard = self
+            .properties
+            .write()
+            .expect("failed to unlock properties for writting base_path");
+        guard.base_path = base_path;
+    }
+
     pub fn is_auth_context_expired(&self) -> bool {
         let guard = self
             .auth_context
@@ -297,11 +440,34 @@ impl SessionState {
     }
 
     pub fn auth_context(&self) -> Option<AuthContextRef> {
-        let guard = self
-            .auth_context
-            .read()
-            .expect("failed to unlock auth_context for reading");
-        guard.0.clone()
+        let auth_context = {
+            let guard = self
+                .auth_context
+                .read()
+                .expect("failed to unlock auth_context for reading");
+            guard.0.clone()
+        };
+
+        let base_path = {
+            let guard = self
+                .properties
+                .read()
+                .expect("failed to unlock properties for reading base_path");
+            guard.base_path.clone()
+        };
+
+        match (auth_context, base_path) {
+            (Some(auth_context), Some(base_path)) => {
+                match auth_context.as_any().downcast_ref::<HttpAuthContext>() {
+                    Some(http_ctx) => Some(Arc::new(HttpAuthContext {
+                        access_token: http_ctx.access_token.clone(),
+                        base_path,
+                    })),
+                    None => Some(auth_context),
+                }
+            }
+            (auth_context, _) => auth_context,
+        }
     }
 
     pub fn set_auth_context(&self, auth_context: Option<AuthContextRef>) {
@@ -376,11 +542,329 @@ impl SessionState {
             None
         };
 
-        LoadRequestMeta::new(
+        let mut meta = LoadRequestMeta::new(
             self.protocol.to_string(),
             "sql".to_string(),
             application_name,
-        )
+        );
+        meta.set_priority(self.query_priority());
+        meta.set_warnings(self.warnings.clone());
+        meta.set_last_cube_request(self.last_cube_request.clone());
+        meta.set_shadow(self.shadow_mode());
+        meta.set_trace(self.transport_trace());
+        meta.set_statement_timeout(self.statement_timeout());
+
+        meta
+    }
+
+    pub fn pushdown_hint(&self) -> Option<PushdownHint> {
+        let guard = self
+            .pushdown_hint
+            .read()
+            .expect("failed to unlock pushdown_hint for reading");
+        *guard
+    }
+
+    /// Called by the shims for every query text, before its statements are planned
+    pub fn set_pushdown_hint(&self, hint: Option<PushdownHint>) {
+        let mut guard = self
+            .pushdown_hint
+            .write()
+            .expect("failed to unlock pushdown_hint for writting");
+        *guard = hint;
+    }
+
+    pub fn scan_schemas(&self) -> Option<Arc<ScanSchemaCache>> {
+        let guard = self
+            .scan_schemas
+            .read()
+            .expect("failed to unlock scan_schemas for reading");
+        guard.clone()
+    }
+
+    /// Called by the shims around planning of a prepared statement execution
+    pub fn set_scan_schemas(&self, scan_schemas: Option<Arc<ScanSchemaCache>>) {
+        let mut guard = self
+            .scan_schemas
+            .write()
+            .expect("failed to unlock scan_schemas for writting");
+        *guard = scan_schemas;
+    }
+
+    pub fn cursor_fetch_size(&self) -> Option<usize> {
+        let guard = self
+            .cursor_fetch_size
+            .read()
+            .expect("failed to unlock cursor_fetch_size for reading");
+        *guard
+    }
+
+    /// Called by the shims around planning of the first FETCH from a cursor
+    pub fn set_cursor_fetch_size(&self, fetch_size: Option<usize>) {
+        let mut guard = self
+            .cursor_fetch_size
+            .write()
+            .expect("failed to unlock cursor_fetch_size for writting");
+        *guard = fetch_size;
+    }
+
+    pub fn last_statement_fingerprint(&self) -> Option<String> {
+        let guard = self
+            .last_statement_fingerprint
+            .read()
+            .expect("failed to unlock last_statement_fingerprint for reading");
+        guard.clone()
+    }
+
+    pub fn set_last_statement_fingerprint(&self, fingerprint: Option<String>) {
+        let mut guard = self
+            .last_statement_fingerprint
+            .write()
+            .expect("failed to unlock last_statement_fingerprint for writting");
+        *guard = fingerprint;
+    }
+
+    pub fn last_statement_class(&self) -> Option<StatementClass> {
+        let guard = self
+            .last_statement_class
+            .read()
+            .expect("failed to unlock last_statement_class for reading");
+        *guard
+    }
+
+    pub fn set_last_statement_class(&self, class: Option<StatementClass>) {
+        let mut guard = self
+            .last_statement_class
+            .write()
+            .expect("failed to unlock last_statement_class for writting");
+        *guard = class;
+    }
+
+    pub fn last_result(&self) -> Option<Arc<LastResult>> {
+        let guard = self
+            .last_result
+            .read()
+            .expect("failed to unlock last_result for reading");
+        guard.clone()
+    }
+
+    pub fn set_last_result(&self, result: Option<Arc<LastResult>>) {
+        let mut guard = self
+            .last_result
+            .write()
+            .expect("failed to unlock last_result for writting");
+        *guard = result;
+    }
+
+    pub fn transport_trace(&self) -> Option<TransportTrace> {
+        let guard = self
+            .transport_trace
+            .read()
+            .expect("failed to unlock transport_trace for reading");
+        guard.clone()
+    }
+
+    pub fn set_transport_trace(&self, trace: Option<TransportTrace>) {
+        let mut guard = self
+            .transport_trace
+            .write()
+            .expect("failed to unlock transport_trace for writting");
+        *guard = trace;
+    }
+
+    pub fn last_query_trace(&self) -> Option<String> {
+        let guard = self
+            .last_query_trace
+            .read()
+            .expect("failed to unlock last_query_trace for reading");
+        guard.clone()
+    }
+
+    pub fn set_last_query_trace(&self, trace: Option<String>) {
+        let mut guard = self
+            .last_query_trace
+            .write()
+            .expect("failed to unlock last_query_trace for writting");
+        *guard = trace;
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.list()
+    }
+
+    pub fn add_warning(&self, warning: String) {
+        self.warnings.push(warning)
+    }
+
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    pub fn last_cube_request(&self) -> Option<CubeRequest> {
+        self.last_cube_request.get()
+    }
+
+    /// Priority from `SET cubesql_query_priority`, then from the auth context
+    pub fn query_priority(&self) -> QueryPriority {
+        if let Some(var) = self.get_variable("cubesql_query_priority") {
+            if let ScalarValue::Utf8(Some(value)) = &var.value {
+                match value.parse() {
+                    Ok(priority) => return priority,
+                    Err(err) => warn!("Ignoring cubesql_query_priority: {}", err),
+                }
+            }
+        }
+
+        self.auth_context()
+            .and_then(|ctx| ctx.query_priority())
+            .unwrap_or_default()
+    }
+
+    /// Loads are shadowed to the secondary deployment after `SET cubesql_shadow = on`
+    pub fn shadow_mode(&self) -> bool {
+        self.get_bool_variable("cubesql_shadow")
+    }
+
+    /// Results are followed by a notice with row count and checksum after `SET cubesql_result_checksum = on`
+    pub fn result_checksum(&self) -> bool {
+        self.get_bool_variable("cubesql_result_checksum")
+    }
+
+    /// Cached planning failures are returned without planning, `SET cubesql_planning_failure_cache = off`
+    /// plans every query from scratch
+    pub fn planning_failure_cache(&self) -> bool {
+        self.get_bool_variable("cubesql_planning_failure_cache")
+    }
+
+    /// Prepared statements reuse scan schemas of statements with the same text and parameter
+    /// types, `SET cube_scan_schema_cache = off` converts them on every execution
+    pub fn scan_schema_cache_enabled(&self) -> bool {
+        self.get_bool_variable("cube_scan_schema_cache")
+    }
+
+    /// `SET cubesql_trace_next_query = on` traces only the next query, the variable is reset
+    pub fn take_trace_next_query(&self) -> bool {
+        if !self.get_bool_variable("cubesql_trace_next_query") {
+            return false;
+        }
+
+        self.set_variables(vec![DatabaseVariable::system(
+            "cubesql_trace_next_query".to_string(),
+            ScalarValue::Boolean(Some(false)),
+            None,
+        )]);
+
+        true
+    }
+
+    fn get_bool_variable(&self, name: &str) -> bool {
+        match self.get_variable(name).map(|v| v.value) {
+            Some(ScalarValue::Boolean(Some(value))) => value,
+            Some(ScalarValue::Utf8(Some(value))) => {
+                matches!(value.to_lowercase().as_str(), "on" | "true" | "1")
+            }
+            Some(ScalarValue::Int64(Some(value))) => value != 0,
+            _ => false,
+        }
+    }
+
+    /// First month of the fiscal year from `SET fiscal_year_start_month`, January by default
+    pub fn fiscal_year_start_month(&self) -> u32 {
+        let value = match self
+            .get_variable("fiscal_year_start_month")
+            .map(|v| v.value)
+        {
+            Some(ScalarValue::Int64(Some(value))) => Some(value),
+            Some(ScalarValue::Utf8(Some(value))) => value.trim().parse::<i64>().ok(),
+            _ => None,
+        };
+
+        match value {
+            Some(month) if (1..=12).contains(&month) => month as u32,
+            Some(month) => {
+                warn!("Ignoring fiscal_year_start_month: {} is not a month", month);
+                1
+            }
+            None => 1,
+        }
+    }
+
+    /// `SET statement_timeout`, milliseconds when the unit is omitted, 0 disables it
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        let value = match self.get_variable("statement_timeout").map(|v| v.value) {
+            Some(ScalarValue::Utf8(Some(value))) => value,
+            Some(ScalarValue::Int64(Some(value))) => value.to_string(),
+            _ => return None,
+        };
+
+        match parse_statement_timeout(&value) {
+            Some(timeout) if timeout.is_zero() => None,
+            Some(timeout) => Some(timeout),
+            None => {
+                warn!("Ignoring statement_timeout: invalid value '{}'", value);
+                None
+            }
+        }
+    }
+
+    /// Time zone from `SET TIME ZONE`, None for UTC and unknown zones
+    pub fn timezone(&self) -> Option<String> {
+        match self.get_variable("timezone").map(|v| v.value) {
+            Some(ScalarValue::Utf8(Some(timezone))) => match timezone.parse::<Tz>() {
+                Ok(Tz::UTC | Tz::GMT | Tz::Etc__UTC | Tz::Etc__GMT) => None,
+                Ok(_) => Some(timezone),
+                Err(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn lc_collate(&self) -> Option<String> {
+        match self.get_variable("lc_collate").map(|v| v.value) {
+            Some(ScalarValue::Utf8(value)) => value,
+            _ => None,
+        }
+    }
+
+    /// Strategy of joins between CubeScans from `SET cubesql_join_strategy`
+    pub fn join_strategy(&self) -> JoinStrategy {
+        match self.get_variable("cubesql_join_strategy").map(|v| v.value) {
+            Some(ScalarValue::Utf8(Some(value))) => value.parse().unwrap_or_else(|err| {
+                warn!("Ignoring cubesql_join_strategy: {}", err);
+                JoinStrategy::Auto
+            }),
+            _ => JoinStrategy::Auto,
+        }
+    }
+
+    /// Limits of rows of CubeScans from `SET cubesql_max_records`, they are clamped to the max limit
+    /// of the server when scans are planned
+    pub fn max_records_overrides(&self) -> MaxRecordsOverrides {
+        match self.get_variable("cubesql_max_records").map(|v| v.value) {
+            Some(ScalarValue::Utf8(Some(value))) => value.parse().unwrap_or_else(|err| {
+                warn!("Ignoring cubesql_max_records: {}", err);
+                MaxRecordsOverrides::default()
+            }),
+            _ => MaxRecordsOverrides::default(),
+        }
+    }
+}
+
+/// Postgres format of durations: `5000`, `500ms`, `30s`, `5min`, `1h`
+fn parse_statement_timeout(value: &str) -> Option<Duration> {
+    let value = value.trim().to_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number.parse::<u64>().ok()?;
+
+    match unit.trim() {
+        "" | "ms" => Some(Duration::from_millis(number)),
+        "s" => Some(Duration::from_secs(number)),
+        "min" => Some(Duration::from_secs(number * 60)),
+        "h" => Some(Duration::from_secs(number * 60 * 60)),
+        _ => None,
     }
 }
 
@@ -422,6 +906,94 @@ impl Session {
         }
     }
 
+    /// Runs the post-auth hook and, if CUBESQL_AUTH_WARM_UP is enabled, fetches meta for
+    /// the security context in the background. The first query of the connection finds it
+    /// in the transport cache instead of waiting for the cold start.
+    pub fn warm_up(self: &Arc<Self>) {
+        let auth_context = match self.state.auth_context() {
+            Some(auth_context) => auth_context,
+            None => return,
+        };
+        let server = self.server.clone();
+        let connection_id = self.state.connection_id;
+
+        tokio::spawn(async move {
+            let started = SystemTime::now();
+            if let Err(err) = server.auth.post_auth(auth_context.clone()).await {
+                warn!(
+                    "Post auth hook failed for connection {}: {}",
+                    connection_id, err
+                );
+            }
+
+            if server.config_obj.auth_warm_up() {
+                if let Err(err) = server.transport.meta(auth_context).await {
+                    warn!(
+                        "Unable to warm up meta for connection {}: {}",
+                        connection_id, err
+                    );
+                    return;
+                }
+            }
+
+            trace!(
+                "Connection {} warmed up in {:?}",
+                connection_id,
+                started.elapsed().unwrap_or_default()
+            );
+        });
+    }
+
+    /// Counts the error in `cubesql.unsupported_queries` and reports it to telemetry if it's
+    /// caused by missing SQL support
+    pub fn record_statement_class(self: &Arc<Self>, class: StatementClass) {
+        self.server.statement_classes.record(class);
+        self.state.set_last_statement_class(Some(class));
+    }
+
+    pub fn report_unsupported_query(self: &Arc<Self>, error: &CompilationError) {
+        if let Some((category, reason)) = self.server.unsupported_queries.record(error) {
+            SessionLogger::new(self.state.clone()).unsupported_query(category.as_str(), &reason);
+        }
+    }
+
+    /// Cleanup hooks of the closed connection. The in-flight query is cancelled, so its loads
+    /// are not awaited by anyone, and the state owned by the session is released right away
+    /// instead of when the last reference is dropped. There are no temporary tables in cubesql,
+    /// the result buffered for `cube_last_result()` is the only data kept by the session.
+    pub async fn end(self: &Arc<Self>, reason: SessionEndReason) {
+        self.state.cancel_query();
+        self.state.clear_extended().await;
+        self.state.set_last_result(None);
+
+        trace!(
+            "Session {} ended: {}",
+            self.state.connection_id,
+            reason.as_str()
+        );
+        SessionLogger::new(self.state.clone()).session_end(reason);
+    }
+
+    /// Reports the panic of the connection handler to telemetry together with the session
+    /// diagnostics
+    pub fn report_panic(self: &Arc<Self>, panic: &ConnectionPanic) {
+        self.server.record_connection_panic();
+
+        let fingerprint = self.state.last_statement_fingerprint();
+        error!(
+            "Connection {} panicked (last statement: {}): {}\n{}",
+            self.state.connection_id,
+            fingerprint.as_deref().unwrap_or("none"),
+            panic.reason,
+            panic.backtrace.as_deref().unwrap_or("Backtrace: not found")
+        );
+        SessionLogger::new(self.state.clone()).connection_panic(
+            &panic.reason,
+            fingerprint,
+            panic.backtrace.as_deref(),
+        );
+    }
+
     // For MySQL
     pub fn to_process_list(self: &Arc<Self>) -> SessionProcessList {
         SessionProcessList {
@@ -0,0 +1,270 @@
+use socket2::{Domain, SockRef, Socket, TcpKeepalive, Type};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use tokio::{
+    net::{lookup_host, TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{
+    config::env_optparse,
+    sql::proxy_protocol::{read_proxy_header, TrustedSources},
+    CubeError,
+};
+
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Address of a listener on the port, IPv6 hosts are enclosed in brackets
+pub fn listen_address(host: &str, port: u16) -> String {
+    match host
+        .trim_matches(|c| c == '[' || c == ']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) => SocketAddr::new(ip, port).to_string(),
+        Err(_) => format!("{}:{}", host, port),
+    }
+}
+
+/// Binds the listener to the first usable address of `address` (host names are resolved
+/// without blocking). The unspecified IPv6 address `[::]` accepts IPv4 connections too,
+/// regardless of the system default of IPV6_V6ONLY.
+pub async fn bind_listener(address: &str) -> io::Result<TcpListener> {
+    let mut last_err = None;
+    for addr in lookup_host(address).await? {
+        match bind_socket(addr) {
+            Ok(listener) => return Ok(listener),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unable to resolve listen address '{}'", address),
+        )
+    }))
+}
+
+fn bind_socket(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Options which are applied to every accepted connection of the listener. Long-idle BI
+/// connections through NAT/gateways are dropped silently, keepalive allows to detect it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SocketOptions {
+    pub nodelay: bool,
+    /// Idle time (in seconds) before the first keepalive probe, keepalive is disabled when None
+    pub keepalive_time: Option<u64>,
+    /// Interval (in seconds) between keepalive probes
+    pub keepalive_interval: Option<u64>,
+    pub keepalive_retries: Option<u32>,
+    /// Time (in seconds) to wait for acknowledgement of transmitted data, Linux only
+    pub user_timeout: Option<u64>,
+    /// Connections from these sources must start with a PROXY protocol header, which carries
+    /// the address of the client behind the load balancer
+    pub proxy_protocol_trusted_sources: Option<TrustedSources>,
+}
+
+impl SocketOptions {
+    /// Read options of the listener from CUBESQL_{PREFIX}_TCP_* variables, which fall back to
+    /// CUBESQL_TCP_* shared by all listeners
+    pub fn from_env(prefix: &str) -> Self {
+        fn var<T>(prefix: &str, name: &str) -> Option<T>
+        where
+            T: std::str::FromStr,
+            T::Err: std::fmt::Display,
+        {
+            env_optparse(&format!("CUBESQL_{}_TCP_{}", prefix, name))
+                .or_else(|| env_optparse(&format!("CUBESQL_TCP_{}", name)))
+        }
+
+        Self {
+            nodelay: var(prefix, "NODELAY").unwrap_or(false),
+            keepalive_time: var(prefix, "KEEPALIVE_TIME"),
+            keepalive_interval: var(prefix, "KEEPALIVE_INTERVAL"),
+            keepalive_retries: var(prefix, "KEEPALIVE_RETRIES"),
+            user_timeout: var(prefix, "USER_TIMEOUT"),
+            proxy_protocol_trusted_sources: env_optparse(&format!(
+                "CUBESQL_{}_PROXY_PROTOCOL_TRUSTED_SOURCES",
+                prefix
+            ))
+            .or_else(|| env_optparse("CUBESQL_PROXY_PROTOCOL_TRUSTED_SOURCES")),
+        }
+    }
+
+    fn keepalive(&self) -> Option<TcpKeepalive> {
+        let time = self.keepalive_time?;
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(time));
+
+        #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+        let keepalive = match self.keepalive_interval {
+            Some(interval) => keepalive.with_interval(Duration::from_secs(interval)),
+            None => keepalive,
+        };
+
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        let keepalive = match self.keepalive_retries {
+            Some(retries) => keepalive.with_retries(retries),
+            None => keepalive,
+        };
+
+        Some(keepalive)
+    }
+
+    pub fn apply(&self, socket: &TcpStream) -> io::Result<()> {
+        socket.set_nodelay(self.nodelay)?;
+
+        let sock_ref = SockRef::from(socket);
+        if let Some(keepalive) = self.keepalive() {
+            sock_ref.set_tcp_keepalive(&keepalive)?;
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(user_timeout) = self.user_timeout {
+                sock_ref.set_tcp_user_timeout(Some(Duration::from_secs(user_timeout)))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Address of the client, which is taken from the PROXY protocol header when the peer is a
+    /// trusted source
+    pub async fn client_addr(
+        &self,
+        socket: &mut TcpStream,
+        peer_addr: SocketAddr,
+    ) -> Result<SocketAddr, CubeError> {
+        // IPv4 clients of a dual-stack listener have IPv4-mapped addresses
+        let peer_addr = match peer_addr {
+            SocketAddr::V6(addr) => match addr.ip().to_ipv4_mapped() {
+                Some(ip) => SocketAddr::new(IpAddr::V4(ip), addr.port()),
+                None => peer_addr,
+            },
+            _ => peer_addr,
+        };
+
+        match &self.proxy_protocol_trusted_sources {
+            Some(sources) if sources.contains(peer_addr.ip()) => {
+                let client_addr =
+                    timeout(PROXY_HEADER_TIMEOUT, read_proxy_header(socket)).await??;
+
+                Ok(client_addr.unwrap_or(peer_addr))
+            }
+            _ => Ok(peer_addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_socket_options_apply() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let client = TcpStream::connect(listener.local_addr()?).await?;
+        let (server, _) = listener.accept().await?;
+
+        let options = SocketOptions {
+            nodelay: true,
+            keepalive_time: Some(30),
+            keepalive_interval: Some(10),
+            keepalive_retries: Some(3),
+            user_timeout: Some(60),
+            proxy_protocol_trusted_sources: None,
+        };
+        options.apply(&server)?;
+
+        assert!(server.nodelay()?);
+        assert!(SockRef::from(&server).keepalive()?);
+        // Client socket is not touched
+        assert!(!SockRef::from(&client).keepalive()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_listen_address() {
+        assert_eq!(listen_address("0.0.0.0", 5432), "0.0.0.0:5432");
+        assert_eq!(listen_address("::", 5432), "[::]:5432");
+        assert_eq!(listen_address("[fd00::1]", 3306), "[fd00::1]:3306");
+        assert_eq!(listen_address("localhost", 3306), "localhost:3306");
+    }
+
+    #[tokio::test]
+    async fn test_bind_listener() -> io::Result<()> {
+        let listener = bind_listener("127.0.0.1:0").await?;
+        let client = TcpStream::connect(listener.local_addr()?).await?;
+        let (_, peer_addr) = listener.accept().await?;
+        assert_eq!(peer_addr, client.local_addr()?);
+
+        // Sandboxes without IPv6 can't bind it
+        if let Ok(listener) = bind_listener("[::]:0").await {
+            let port = listener.local_addr()?.port();
+            TcpStream::connect(("127.0.0.1", port)).await?;
+            let (mut server, peer_addr) = listener.accept().await?;
+            assert!(peer_addr.is_ipv6());
+
+            let client_addr = SocketOptions::default()
+                .client_addr(&mut server, peer_addr)
+                .await
+                .unwrap();
+            assert_eq!(client_addr.ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+        }
+
+        assert!(bind_listener("not a host:0").await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_socket_options_client_addr() -> Result<(), CubeError> {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut client = TcpStream::connect(listener.local_addr()?).await?;
+        let (mut server, peer_addr) = listener.accept().await?;
+
+        client
+            .write_all(b"PROXY TCP4 203.0.113.7 127.0.0.1 56324 5432\r\n")
+            .await?;
+
+        let untrusted = SocketOptions {
+            proxy_protocol_trusted_sources: Some("10.0.0.0/8".parse().unwrap()),
+            ..SocketOptions::default()
+        };
+        assert_eq!(
+            untrusted.client_addr(&mut server, peer_addr).await?,
+            peer_addr
+        );
+
+        let trusted = SocketOptions {
+            proxy_protocol_trusted_sources: Some("127.0.0.1".parse().unwrap()),
+            ..SocketOptions::default()
+        };
+        assert_eq!(
+            trusted.client_addr(&mut server, peer_addr).await?,
+            "203.0.113.7:56324".parse().unwrap()
+        );
+
+        Ok(())
+    }
+}
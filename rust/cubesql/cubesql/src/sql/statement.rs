@@ -1,4 +1,11 @@
-use crate::sql::shim::ConnectionError;
+use crate::{
+    compile::{
+        parser::{TableSample, GROUP_BY_ALL_MARKER},
+        CompilationError, CompilationResult,
+    },
+    sql::shim::ConnectionError,
+};
+use chrono::NaiveDate;
 use itertools::Itertools;
 use log::trace;
 use msql_srv::Column as MysqlColumn;
@@ -6,10 +13,17 @@ use pg_srv::{
     protocol::{ErrorCode, ErrorResponse},
     BindValue, PgType,
 };
-use sqlparser::ast::{
-    self, ArrayAgg, Expr, Function, FunctionArg, FunctionArgExpr, Ident, ObjectName, Value,
+use sqlparser::{
+    ast::{self, ArrayAgg, Expr, Function, FunctionArg, FunctionArgExpr, Ident, ObjectName, Value},
+    dialect::PostgreSqlDialect,
+    parser::Parser,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    str::FromStr,
+    sync::Arc,
 };
-use std::{collections::HashMap, error::Error};
 
 use super::types::{ColumnFlags, ColumnType};
 
@@ -599,6 +613,63 @@ impl<'ast> Visitor<'ast, ConnectionError> for MySQLStatementParamsFinder {
     }
 }
 
+/// Controls how bind values of prepared statements are written to the log on execution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindValuesLogPolicy {
+    /// Bind values are not logged
+    None,
+    /// Only types of bind values are logged, values are redacted
+    TypesOnly,
+    /// Bind values are logged as is
+    Full,
+}
+
+impl FromStr for BindValuesLogPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "types-only" | "types_only" | "types" => Ok(Self::TypesOnly),
+            "full" => Ok(Self::Full),
+            _ => Err(format!(
+                "Unknown bind values log policy: '{}', expected one of: full, types-only, none",
+                s
+            )),
+        }
+    }
+}
+
+impl BindValuesLogPolicy {
+    /// Format bind values for the log, None is returned when values should not be logged
+    pub fn format(&self, values: &[BindValue]) -> Option<String> {
+        let params = match self {
+            Self::None => return None,
+            Self::TypesOnly => values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let type_name = match v {
+                        BindValue::String(_) => "String",
+                        BindValue::Int64(_) => "Int64",
+                        BindValue::Float64(_) => "Float64",
+                        BindValue::Bool(_) => "Bool",
+                        BindValue::Null => "Null",
+                    };
+                    format!("${}: {}", i + 1, type_name)
+                })
+                .join(", "),
+            Self::Full => values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| format!("${}: {:?}", i + 1, v))
+                .join(", "),
+        };
+
+        Some(format!("[{}]", params))
+    }
+}
+
 #[derive(Debug)]
 pub struct PostgresStatementParamsBinder {
     values: Vec<BindValue>,
@@ -1107,10 +1178,27 @@ impl<'a> Visitor<'a, ConnectionError> for ApproximateCountDistinctVisitor {
     }
 }
 
+/// Cube's `compareDateRange` through SQL:
+///
+/// SELECT compare_date_range, status, MEASURE(count)
+/// FROM compare_date_range(Orders, createdAt, '2024-01-01', '2024-01-31', '2023-01-01', '2023-01-31')
+/// GROUP BY 1, 2
+///
+/// The query is repeated for every period with a date range filter on the time dimension and
+/// combined by UNION ALL. `compare_date_range` column is replaced with the period label in
+/// format of Cube ("from - to").
 #[derive(Debug)]
-pub struct SensitiveDataSanitizer {}
+pub struct CompareDateRangeReplacer {}
+
+struct CompareDateRangeArgs {
+    cube: Ident,
+    time_dimension: Expr,
+    periods: Vec<(String, String)>,
+}
+
+impl CompareDateRangeReplacer {
+    const FUNCTION_NAME: &'static str = "compare_date_range";
 
-impl SensitiveDataSanitizer {
     pub fn new() -> Self {
         Self {}
     }
@@ -1122,182 +1210,1788 @@ impl SensitiveDataSanitizer {
 
         result
     }
-}
 
-impl<'ast> Visitor<'ast, ConnectionError> for SensitiveDataSanitizer {
-    fn visit_value(
-        &mut self,
-        val: &mut ast::Value,
-        _pt: PlaceholderType,
-    ) -> Result<(), ConnectionError> {
-        match val {
-            ast::Value::SingleQuotedString(str)
-            | ast::Value::DoubleQuotedString(str)
-            | ast::Value::NationalStringLiteral(str) => {
-                if vec!["false", "true"].contains(&str.as_str()) || str.len() < 4 {
-                    return Ok(());
+    fn parse_args(args: &Vec<FunctionArg>) -> Option<CompareDateRangeArgs> {
+        let exprs = args
+            .iter()
+            .map(|arg| match arg {
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Some(expr),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        if exprs.len() < 4 || exprs.len() % 2 != 0 {
+            return None;
+        }
+
+        let cube = match exprs[0] {
+            Expr::Identifier(ident) => ident.clone(),
+            Expr::Value(Value::SingleQuotedString(name)) => Ident::new(name.clone()),
+            _ => return None,
+        };
+        let time_dimension = match exprs[1] {
+            expr @ Expr::Identifier(_) | expr @ Expr::CompoundIdentifier(_) => expr.clone(),
+            _ => return None,
+        };
+        let periods = exprs[2..]
+            .chunks(2)
+            .map(|pair| match (pair[0], pair[1]) {
+                (
+                    Expr::Value(Value::SingleQuotedString(from)),
+                    Expr::Value(Value::SingleQuotedString(to)),
+                ) => Some((from.clone(), to.clone())),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(CompareDateRangeArgs {
+            cube,
+            time_dimension,
+            periods,
+        })
+    }
+
+    fn is_period_column(expr: &Expr) -> bool {
+        match expr {
+            Expr::Identifier(ident) => ident.value.to_lowercase() == Self::FUNCTION_NAME,
+            Expr::CompoundIdentifier(idents) => idents
+                .last()
+                .map(|ident| ident.value.to_lowercase() == Self::FUNCTION_NAME)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    fn timestamp_literal(value: &str) -> Expr {
+        Expr::Cast {
+            expr: Box::new(Expr::Value(Value::SingleQuotedString(value.to_string()))),
+            data_type: ast::DataType::Timestamp,
+        }
+    }
+
+    /// Periods are inclusive as in Cube: a plain date as the end of the period covers the whole day
+    fn period_end(to: &str) -> String {
+        match NaiveDate::parse_from_str(to.trim(), "%Y-%m-%d") {
+            Ok(date) => format!("{}T23:59:59.999", date.format("%Y-%m-%d")),
+            Err(_) => to.to_string(),
+        }
+    }
+
+    fn select_for_period(
+        select: &ast::Select,
+        args: &CompareDateRangeArgs,
+        alias: &Option<ast::TableAlias>,
+        (from, to): &(String, String),
+    ) -> ast::Select {
+        let mut select = select.clone();
+        let label = Expr::Value(Value::SingleQuotedString(format!("{} - {}", from, to)));
+
+        select.from[0].relation = ast::TableFactor::Table {
+            name: ObjectName(vec![args.cube.clone()]),
+            alias: alias.clone(),
+            args: vec![],
+            with_hints: vec![],
+        };
+
+        let date_range = Expr::Between {
+            expr: Box::new(args.time_dimension.clone()),
+            negated: false,
+            low: Box::new(Self::timestamp_literal(from)),
+            high: Box::new(Self::timestamp_literal(&Self::period_end(to))),
+        };
+        select.selection = Some(match select.selection.take() {
+            Some(selection) => Expr::BinaryOp {
+                left: Box::new(Expr::Nested(Box::new(selection))),
+                op: ast::BinaryOperator::And,
+                right: Box::new(date_range),
+            },
+            None => date_range,
+        });
+
+        for item in select.projection.iter_mut() {
+            match item {
+                ast::SelectItem::UnnamedExpr(expr) if Self::is_period_column(expr) => {
+                    *item = ast::SelectItem::ExprWithAlias {
+                        expr: label.clone(),
+                        alias: Ident::new(Self::FUNCTION_NAME),
+                    };
                 }
-                *str = "[REPLACED]".to_string();
+                ast::SelectItem::ExprWithAlias { expr, .. } if Self::is_period_column(expr) => {
+                    *expr = label.clone();
+                }
+                _ => (),
             }
-            _ => (),
-        };
+        }
 
-        Ok(())
+        // Period label is a constant inside of every branch, grouping by ordinals is kept
+        // as is because positions of the projection are not changed
+        let label_positions = select
+            .projection
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| match item {
+                ast::SelectItem::ExprWithAlias { expr, .. } => expr == &label,
+                _ => false,
+            })
+            .map(|(i, _)| (i + 1).to_string())
+            .collect::<Vec<_>>();
+        select.group_by.retain(|expr| match expr {
+            Expr::Value(Value::Number(position, _)) => !label_positions.contains(position),
+            expr => !Self::is_period_column(expr),
+        });
+
+        select
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{compile::parser::MySqlDialectWithBackTicks, CubeError};
-    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+    fn expand_query(query: &mut ast::Query) {
+        let select = match &query.body {
+            ast::SetExpr::Select(select) => select,
+            _ => return,
+        };
+        if select.from.len() != 1 || !select.from[0].joins.is_empty() {
+            return;
+        }
 
-    fn run_cast_replacer(input: &str, output: &str) -> Result<(), CubeError> {
-        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+        let (args, alias) = match &select.from[0].relation {
+            ast::TableFactor::Table {
+                name, args, alias, ..
+            } if name.to_string().to_lowercase() == Self::FUNCTION_NAME => {
+                match Self::parse_args(args) {
+                    Some(args) => (args, alias.clone()),
+                    None => return,
+                }
+            }
+            _ => return,
+        };
 
-        let replacer = CastReplacer::new();
-        let res = replacer.replace(&stmts[0]);
+        let body = args
+            .periods
+            .iter()
+            .map(|period| {
+                ast::SetExpr::Select(Box::new(Self::select_for_period(
+                    select, &args, &alias, period,
+                )))
+            })
+            .reduce(|left, right| ast::SetExpr::SetOperation {
+                op: ast::SetOperator::Union,
+                all: true,
+                left: Box::new(left),
+                right: Box::new(right),
+            });
+
+        if let Some(body) = body {
+            query.body = body;
+        }
+    }
+}
 
-        assert_eq!(res.to_string(), output);
+impl<'ast> Visitor<'ast, ConnectionError> for CompareDateRangeReplacer {
+    fn visit_query(&mut self, query: &mut Box<ast::Query>) -> Result<(), ConnectionError> {
+        self.visit_set_expr(&mut query.body)?;
+        if let Some(with) = query.with.as_mut() {
+            self.visit_with(with)?;
+        }
+
+        Self::expand_query(query);
 
         Ok(())
     }
+}
 
-    #[test]
-    fn test_cast_replacer() -> Result<(), CubeError> {
-        run_cast_replacer("SELECT 'pg_class'::regclass", "SELECT 1259")?;
-        run_cast_replacer("SELECT 'pg_class'::regclass::oid", "SELECT 1259")?;
-        run_cast_replacer("SELECT 64::information_schema.cardinal_number", "SELECT 64")?;
-        run_cast_replacer(
-            "SELECT NOW()::timestamptz",
-            "SELECT CAST(NOW() AS TIMESTAMP)",
-        )?;
-        run_cast_replacer(
-            "SELECT CAST(1 + 1 as Regclass);",
-            "SELECT __cube_regclass_cast(1 + 1)",
-        )?;
+/// Maps granularities which aren't supported by DATE_TRUNC / DATE_PART natively:
+/// `fiscal_year` and `fiscal_quarter` are shifted by the session `fiscal_year_start_month`,
+/// `isoweek` and MySQL `WEEK(date, 3)` are ISO weeks. Granularities declared as custom
+/// granularities of time dimensions are left as is, they are pushed down to Cube.
+#[derive(Debug)]
+pub struct GranularityReplacer {
+    fiscal_year_start_month: u32,
+    // Lower-cased names
+    custom_granularities: HashSet<String>,
+}
 
-        Ok(())
+impl GranularityReplacer {
+    pub fn new(fiscal_year_start_month: u32, custom_granularities: HashSet<String>) -> Self {
+        Self {
+            fiscal_year_start_month,
+            custom_granularities,
+        }
     }
 
-    fn run_redshift_date_part_replacer(input: &str, output: &str) -> Result<(), CubeError> {
-        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
 
-        let replacer = RedshiftDatePartReplacer::new();
-        let res = replacer.replace(&stmts[0]);
+        self.visit_statement(&mut result).unwrap();
 
-        assert_eq!(res.to_string(), output);
+        result
+    }
 
-        Ok(())
+    fn function(name: &str, args: Vec<Expr>) -> Expr {
+        Expr::Function(Function {
+            name: ObjectName(vec![Ident::new(name)]),
+            args: args
+                .into_iter()
+                .map(|arg| FunctionArg::Unnamed(FunctionArgExpr::Expr(arg)))
+                .collect(),
+            over: None,
+            distinct: false,
+            special: false,
+            approximate: false,
+        })
+    }
+
+    fn granularity(granularity: &str) -> Expr {
+        Expr::Value(Value::SingleQuotedString(granularity.to_string()))
+    }
+
+    fn shift_months(expr: Expr, op: ast::BinaryOperator, months: u32) -> Expr {
+        Expr::BinaryOp {
+            left: Box::new(expr),
+            op,
+            right: Box::new(Expr::Value(Value::Interval {
+                value: Box::new(Expr::Value(Value::SingleQuotedString(format!(
+                    "{} month",
+                    months
+                )))),
+                leading_field: None,
+                leading_precision: None,
+                last_field: None,
+                fractional_seconds_precision: None,
+            })),
+        }
     }
 
-    #[test]
-    fn test_redshift_date_part_replacer() -> Result<(), CubeError> {
-        run_redshift_date_part_replacer(
-            r#"SELECT DATEDIFF(day, DATE '1970-01-01', "ta_1"."createdAt")"#,
-            r#"SELECT DATEDIFF('day', DATE '1970-01-01', "ta_1"."createdAt")"#,
-        )?;
+    fn unnamed_args(fun: &Function) -> Option<Vec<Expr>> {
+        fun.args
+            .iter()
+            .map(|arg| match arg {
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Some(expr.clone()),
+                _ => None,
+            })
+            .collect()
+    }
 
-        run_redshift_date_part_replacer(
-            r#"SELECT DATEADD(week, '2009-01-01', '2009-12-31')"#,
-            r#"SELECT DATEADD('week', '2009-01-01', '2009-12-31')"#,
-        )?;
+    /// Fiscal year is named after the calendar year in which it ends
+    fn rewrite_fiscal(&self, fn_name: &str, granularity: &str, date: Expr) -> Option<Expr> {
+        let calendar = match granularity {
+            "fiscal_year" => "year",
+            "fiscal_quarter" => "quarter",
+            _ => return None,
+        };
+        let offset = self.fiscal_year_start_month - 1;
+        if offset == 0 {
+            return Some(Self::function(
+                fn_name,
+                vec![Self::granularity(calendar), date],
+            ));
+        }
 
-        run_redshift_date_part_replacer(
-            r#"SELECT DATEDIFF(day, DATEADD(week, '2009-01-01', '2009-12-31'), "ta_1"."createdAt")"#,
-            r#"SELECT DATEDIFF('day', DATEADD('week', '2009-01-01', '2009-12-31'), "ta_1"."createdAt")"#,
-        )?;
+        let expr = match (fn_name, calendar) {
+            ("date_trunc", _) => Self::shift_months(
+                Self::function(
+                    fn_name,
+                    vec![
+                        Self::granularity(calendar),
+                        Self::shift_months(date, ast::BinaryOperator::Minus, offset),
+                    ],
+                ),
+                ast::BinaryOperator::Plus,
+                offset,
+            ),
+            (_, "year") => Self::function(
+                fn_name,
+                vec![
+                    Self::granularity(calendar),
+                    Self::shift_months(date, ast::BinaryOperator::Plus, 12 - offset),
+                ],
+            ),
+            _ => Self::function(
+                fn_name,
+                vec![
+                    Self::granularity(calendar),
+                    Self::shift_months(date, ast::BinaryOperator::Minus, offset),
+                ],
+            ),
+        };
 
-        Ok(())
+        Some(expr)
     }
 
-    fn run_pg_binder(
-        input: &str,
-        output: &str,
-        values: Vec<BindValue>,
-    ) -> Result<(), ConnectionError> {
-        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+    fn rewrite_function(&self, fun: &Function) -> Option<Expr> {
+        let fn_name = fun.name.to_string().to_lowercase();
+        let mut args = Self::unnamed_args(fun)?;
+        match (fn_name.as_str(), args.len()) {
+            ("date_trunc" | "date_part", 2) => {
+                let granularity = match &args[0] {
+                    Expr::Value(Value::SingleQuotedString(granularity)) => {
+                        granularity.to_lowercase()
+                    }
+                    _ => return None,
+                };
+                if self.custom_granularities.contains(&granularity) {
+                    return None;
+                }
 
-        let binder = PostgresStatementParamsBinder::new(values);
-        let mut res = stmts[0].clone();
-        binder.bind(&mut res)?;
+                let date = args.remove(1);
+                match granularity.as_str() {
+                    "isoweek" | "iso_week" => Some(Self::function(
+                        &fn_name,
+                        vec![Self::granularity("week"), date],
+                    )),
+                    _ => self.rewrite_fiscal(&fn_name, &granularity, date),
+                }
+            }
+            // MySQL mode 3: weeks start on Monday, the first week has 4 or more days
+            ("week", 2) => match &args[1] {
+                Expr::Value(Value::Number(mode, _)) if mode == "3" => Some(Self::function(
+                    "date_part",
+                    vec![Self::granularity("week"), args.remove(0)],
+                )),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
 
-        assert_eq!(res.to_string(), output);
+impl<'ast> Visitor<'ast, ConnectionError> for GranularityReplacer {
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), ConnectionError> {
+        self.visit_expr_with_placeholder_type(expr, PlaceholderType::String)?;
+
+        if let Expr::Function(fun) = expr {
+            if let Some(rewritten) = self.rewrite_function(fun) {
+                *expr = rewritten;
+            }
+        }
 
         Ok(())
     }
+}
 
-    #[test]
-    fn test_pg_binder() -> Result<(), ConnectionError> {
-        run_pg_binder(
-            "SELECT $1",
-            "SELECT 'test'",
-            vec![BindValue::String("test".to_string())],
-        )?;
+/// Time zone conversions of time dimensions (`AT TIME ZONE`, `timezone()` and `convert_tz()`
+/// from UTC) are replaced with the columns, Cube converts time dimensions to the time zone of the
+/// load request. Conversions to UTC are no-ops, so the request keeps matching pre-aggregations,
+/// and chained conversions from UTC convert to the last time zone. Statements which convert to
+/// several time zones or also reference the converted time dimensions as is are kept as is.
+#[derive(Debug)]
+pub struct TimezoneReplacer {
+    // Lower-cased cube name -> lower-cased names of its time dimensions
+    cube_time_dimensions: HashMap<String, HashSet<String>>,
+    // Time dimensions of cubes referenced by the statement
+    time_dimensions: HashSet<String>,
+    // Converted columns and their target time zones
+    conversions: Vec<(String, String)>,
+    // Columns referenced without conversions
+    columns: HashSet<String>,
+    replace: bool,
+}
 
-        run_pg_binder(
-            "SELECT $1 AS t1, $2 AS t2",
-            "SELECT 'test1' AS t1, NULL AS t2",
-            vec![BindValue::String("test1".to_string()), BindValue::Null],
-        )?;
+impl TimezoneReplacer {
+    pub fn new(cube_time_dimensions: HashMap<String, HashSet<String>>) -> Self {
+        Self {
+            cube_time_dimensions,
+            time_dimensions: HashSet::new(),
+            conversions: Vec::new(),
+            columns: HashSet::new(),
+            replace: false,
+        }
+    }
 
-        run_pg_binder(
-            "SELECT $1 AS t1, $2 AS t2, $1 as b1, $2 as b2",
-            "SELECT 'test1' AS t1, NULL AS t2, 'test1' AS b1, NULL AS b2",
-            vec![BindValue::String("test1".to_string()), BindValue::Null],
-        )?;
+    /// Statement without conversions and the time zone of its load requests
+    pub fn replace(mut self, stmt: &ast::Statement) -> (ast::Statement, Option<String>) {
+        let mut result = stmt.clone();
+        self.visit_statement(&mut result).unwrap();
 
-        // binary op
-        run_pg_binder(
-            r#"
-                SELECT *
-                FROM testdata
-                WHERE fieldA = $1 AND fieldB = $2 OR (fieldC = $3 AND fieldD = $4)
-            "#,
-            "SELECT * FROM testdata WHERE fieldA = 'test' AND fieldB = 1 OR (fieldC = 2 AND fieldD = 2)",
-            vec![
-                BindValue::String("test".to_string()),
-                BindValue::Int64(1),
-                BindValue::Int64(2),
-                BindValue::Float64(2.0),
-                BindValue::Bool(true),
-            ],
-        )?;
+        let mut timezones = self
+            .conversions
+            .iter()
+            .filter(|(column, _)| self.time_dimensions.contains(column))
+            .map(|(_, timezone)| timezone)
+            .filter(|timezone| !is_utc_timezone(timezone))
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let unconverted = self
+            .columns
+            .iter()
+            .any(|column| self.time_dimensions.contains(column));
+        if timezones.len() > 1 || (timezones.len() == 1 && unconverted) {
+            return (stmt.clone(), None);
+        }
 
-        // IN
-        run_pg_binder(
-            r#"
-                SELECT *
-                FROM testdata
-                WHERE fieldA IN ($1, $2)
-            "#,
-            "SELECT * FROM testdata WHERE fieldA IN ('test1', 'test2')",
-            vec![
-                BindValue::String("test1".to_string()),
-                BindValue::String("test2".to_string()),
-            ],
-        )?;
+        self.replace = true;
+        self.visit_statement(&mut result).unwrap();
 
-        // BETWEEN
-        run_pg_binder(
-            r#"
-                SELECT *
-                FROM testdata
-                WHERE fieldA BETWEEN $1 AND $2
-            "#,
-            "SELECT * FROM testdata WHERE fieldA BETWEEN 'test1' AND 'test2'",
-            vec![
-                BindValue::String("test1".to_string()),
-                BindValue::String("test2".to_string()),
-            ],
-        )?;
+        (result, timezones.pop())
+    }
 
-        run_pg_binder(
-            r#"
-                SELECT *
-                FROM testdata
-                WHERE fieldA = $1
-                UNION ALL
+    fn string_literal(expr: &Expr) -> Option<&str> {
+        match expr {
+            Expr::Value(Value::SingleQuotedString(value)) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    fn column(expr: &Expr) -> Option<&Expr> {
+        match expr {
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) => Some(expr),
+            Expr::Nested(expr) => Self::column(expr),
+            _ => None,
+        }
+    }
+
+    fn column_name(column: &Expr) -> String {
+        match column {
+            Expr::Identifier(ident) => ident.value.to_lowercase(),
+            Expr::CompoundIdentifier(idents) => idents
+                .last()
+                .map(|ident| ident.value.to_lowercase())
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    /// Converted column, previous conversions of the chain must be to UTC
+    fn timestamp_column(expr: &Expr) -> Option<&Expr> {
+        match Self::conversion(expr) {
+            Some((column, timezone)) if is_utc_timezone(timezone) => Some(column),
+            Some(_) => None,
+            None => Self::column(expr),
+        }
+    }
+
+    /// Converted column and the target time zone
+    fn conversion(expr: &Expr) -> Option<(&Expr, &str)> {
+        match expr {
+            Expr::AtTimeZone {
+                timestamp,
+                time_zone,
+            } => Some((Self::timestamp_column(timestamp)?, time_zone.as_str())),
+            Expr::Nested(expr) => Self::conversion(expr),
+            Expr::Function(fun) => {
+                let args = fun
+                    .args
+                    .iter()
+                    .map(|arg| match arg {
+                        FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Some(expr),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+
+                match (
+                    fun.name.to_string().to_lowercase().as_str(),
+                    args.as_slice(),
+                ) {
+                    ("timezone", [timezone, timestamp]) => Some((
+                        Self::timestamp_column(timestamp)?,
+                        Self::string_literal(timezone)?,
+                    )),
+                    ("convert_tz", [timestamp, from, to])
+                        if is_utc_timezone(Self::string_literal(from)?) =>
+                    {
+                        Some((
+                            Self::timestamp_column(timestamp)?,
+                            Self::string_literal(to)?,
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_utc_timezone(timezone: &str) -> bool {
+    matches!(
+        timezone.to_lowercase().as_str(),
+        "utc"
+            | "etc/utc"
+            | "gmt"
+            | "etc/gmt"
+            | "z"
+            | "zulu"
+            | "universal"
+            | "+00:00"
+            | "-00:00"
+            | "+00"
+            | "00:00"
+    )
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for TimezoneReplacer {
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) -> Result<(), ConnectionError> {
+        match factor {
+            ast::TableFactor::Table { name, .. } => {
+                let cube_name = name
+                    .0
+                    .last()
+                    .map(|ident| ident.value.to_lowercase())
+                    .unwrap_or_default();
+                if let Some(time_dimensions) = self.cube_time_dimensions.get(&cube_name) {
+                    self.time_dimensions.extend(time_dimensions.iter().cloned());
+                }
+            }
+            ast::TableFactor::Derived { subquery, .. } => self.visit_query(subquery)?,
+            ast::TableFactor::TableFunction { expr, .. } => self.visit_expr(expr)?,
+            ast::TableFactor::NestedJoin(table_with_joins) => {
+                self.visit_table_with_joins(&mut *table_with_joins)?
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), ConnectionError> {
+        // The whole chain of conversions is resolved before its parts
+        if let Some((column, timezone)) = Self::conversion(expr) {
+            let column_name = Self::column_name(column);
+            if !self.replace {
+                self.conversions.push((column_name, timezone.to_string()));
+            } else if self.time_dimensions.contains(&column_name) {
+                *expr = column.clone();
+            }
+
+            return Ok(());
+        }
+
+        if !self.replace {
+            if let Some(column) = Self::column(expr) {
+                self.columns.insert(Self::column_name(column));
+            }
+        }
+
+        self.visit_expr_with_placeholder_type(expr, PlaceholderType::String)
+    }
+}
+
+/// Replaces references to views (created by CREATE VIEW) with their queries
+#[derive(Debug)]
+pub struct ViewReplacer {
+    // Lower-cased name -> query
+    views: Arc<HashMap<String, ast::Query>>,
+    // Views which are being expanded, protects from cycles
+    expanding: Vec<String>,
+}
+
+impl ViewReplacer {
+    pub fn new(views: Arc<HashMap<String, ast::Query>>) -> Self {
+        Self {
+            views,
+            expanding: vec![],
+        }
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result).unwrap();
+
+        result
+    }
+
+    fn find_view(&self, factor: &ast::TableFactor) -> Option<(String, ast::TableAlias)> {
+        match factor {
+            ast::TableFactor::Table {
+                name, alias, args, ..
+            } if name.0.len() == 1 && args.is_empty() => {
+                let view_name = name.0[0].value.to_lowercase();
+                if self.views.contains_key(&view_name) && !self.expanding.contains(&view_name) {
+                    let alias = alias.clone().unwrap_or_else(|| ast::TableAlias {
+                        name: name.0[0].clone(),
+                        columns: vec![],
+                    });
+
+                    Some((view_name, alias))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for ViewReplacer {
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) -> Result<(), ConnectionError> {
+        if let Some((view_name, alias)) = self.find_view(factor) {
+            let mut subquery = Box::new(self.views[&view_name].clone());
+            // View can reference other views
+            self.expanding.push(view_name);
+            self.visit_query(&mut subquery)?;
+            self.expanding.pop();
+
+            *factor = ast::TableFactor::Derived {
+                lateral: false,
+                subquery,
+                alias: Some(alias),
+            };
+
+            return Ok(());
+        }
+
+        match factor {
+            ast::TableFactor::Derived { subquery, .. } => self.visit_query(subquery)?,
+            ast::TableFactor::NestedJoin(table_with_joins) => {
+                self.visit_table_with_joins(&mut *table_with_joins)?
+            }
+            _ => (),
+        };
+
+        Ok(())
+    }
+}
+
+/// Replaces sampled tables with subqueries which filter or order rows by RANDOM(), samples are
+/// matched to tables by their aliases or names in the order of the query
+#[derive(Debug)]
+pub struct TableSampleReplacer {
+    samples: Vec<(Ident, TableSample)>,
+}
+
+impl TableSampleReplacer {
+    pub fn new(samples: Vec<(Ident, TableSample)>) -> Self {
+        Self { samples }
+    }
+
+    pub fn replace(mut self, stmts: &[ast::Statement]) -> CompilationResult<Vec<ast::Statement>> {
+        let mut result = stmts.to_vec();
+        for stmt in result.iter_mut() {
+            self.visit_statement(stmt).unwrap();
+        }
+
+        if !self.samples.is_empty() {
+            return Err(CompilationError::unsupported(
+                "TABLESAMPLE is supported only for tables".to_string(),
+            ));
+        }
+
+        Ok(result)
+    }
+
+    fn subquery(factor: ast::TableFactor, sample: &TableSample) -> ast::Query {
+        let sql = match sample {
+            TableSample::Fraction(fraction) => {
+                format!("SELECT * FROM sample WHERE RANDOM() < {}", fraction)
+            }
+            TableSample::Rows(rows) => {
+                format!("SELECT * FROM sample ORDER BY RANDOM() LIMIT {}", rows)
+            }
+        };
+        let mut query = match Parser::parse_sql(&PostgreSqlDialect {}, &sql)
+            .unwrap()
+            .remove(0)
+        {
+            ast::Statement::Query(query) => query,
+            _ => unreachable!(),
+        };
+        if let ast::SetExpr::Select(select) = &mut query.body {
+            select.from[0].relation = factor;
+        }
+
+        *query
+    }
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for TableSampleReplacer {
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) -> Result<(), ConnectionError> {
+        match factor {
+            ast::TableFactor::Table { name, alias, .. } => {
+                let table = match alias {
+                    Some(alias) => alias.name.clone(),
+                    None => match name.0.last() {
+                        Some(table) => table.clone(),
+                        None => return Ok(()),
+                    },
+                };
+                let idx = match self
+                    .samples
+                    .iter()
+                    .position(|(sampled, _)| sampled.value == table.value)
+                {
+                    Some(idx) => idx,
+                    None => return Ok(()),
+                };
+                let (_, sample) = self.samples.remove(idx);
+
+                let alias = alias.take().unwrap_or_else(|| ast::TableAlias {
+                    name: table,
+                    columns: vec![],
+                });
+                let subquery = Self::subquery(factor.clone(), &sample);
+                *factor = ast::TableFactor::Derived {
+                    lateral: false,
+                    subquery: Box::new(subquery),
+                    alias: Some(alias),
+                };
+            }
+            ast::TableFactor::Derived { subquery, .. } => self.visit_query(subquery)?,
+            ast::TableFactor::NestedJoin(table_with_joins) => {
+                self.visit_table_with_joins(&mut *table_with_joins)?
+            }
+            _ => (),
+        };
+
+        Ok(())
+    }
+}
+
+/// Finds scalar subqueries in the projection of the top level SELECT and replaces them with
+/// values which were evaluated separately, subqueries are matched by their text
+#[derive(Debug)]
+pub struct ScalarSubqueryReplacer {
+    values: HashMap<String, Expr>,
+    aliases: HashMap<String, String>,
+    found: Vec<ast::Query>,
+}
+
+impl ScalarSubqueryReplacer {
+    pub fn new(values: HashMap<String, Expr>) -> Self {
+        Self {
+            values,
+            aliases: HashMap::new(),
+            found: vec![],
+        }
+    }
+
+    /// Unaliased subqueries get these aliases, so the column name doesn't depend on the value
+    pub fn with_aliases(mut self, aliases: HashMap<String, String>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    pub fn find(stmt: &ast::Statement) -> Vec<ast::Query> {
+        let mut finder = Self::new(HashMap::new());
+        finder.visit_projection(&mut stmt.clone()).unwrap();
+
+        finder.found
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_projection(&mut result).unwrap();
+
+        result
+    }
+
+    fn visit_projection(&mut self, stmt: &mut ast::Statement) -> Result<(), ConnectionError> {
+        if let ast::Statement::Query(query) = stmt {
+            if let ast::SetExpr::Select(select) = &mut query.body {
+                for item in select.projection.iter_mut() {
+                    if let ast::SelectItem::UnnamedExpr(Expr::Subquery(query)) = item {
+                        if let Some(alias) = self.aliases.get(&query.to_string()) {
+                            *item = ast::SelectItem::ExprWithAlias {
+                                expr: Expr::Subquery(query.clone()),
+                                alias: Ident::with_quote('"', alias),
+                            };
+                        }
+                    }
+
+                    self.visit_select_item(item)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for ScalarSubqueryReplacer {
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), ConnectionError> {
+        if let Expr::Subquery(query) = expr {
+            match self.values.get(&query.to_string()) {
+                Some(value) => *expr = value.clone(),
+                None => self.found.push(*query.clone()),
+            }
+
+            return Ok(());
+        }
+
+        self.visit_expr_with_placeholder_type(expr, PlaceholderType::String)
+    }
+}
+
+#[derive(Debug)]
+pub struct SensitiveDataSanitizer {}
+
+impl SensitiveDataSanitizer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result).unwrap();
+
+        result
+    }
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for SensitiveDataSanitizer {
+    fn visit_value(
+        &mut self,
+        val: &mut ast::Value,
+        _pt: PlaceholderType,
+    ) -> Result<(), ConnectionError> {
+        match val {
+            ast::Value::SingleQuotedString(str)
+            | ast::Value::DoubleQuotedString(str)
+            | ast::Value::NationalStringLiteral(str) => {
+                if vec!["false", "true"].contains(&str.as_str()) || str.len() < 4 {
+                    return Ok(());
+                }
+                *str = "[REPLACED]".to_string();
+            }
+            _ => (),
+        };
+
+        Ok(())
+    }
+}
+
+/// Resolves references to aliases of the projection in WHERE, like `SELECT a + b AS total FROM t
+/// WHERE total > 10`. References are replaced by the aliased expressions. Aliases which shadow
+/// a column used by the projection are kept as is, the column takes precedence as in SQL.
+/// Aliases of aggregates and window functions can't be used in WHERE, they are kept as well.
+/// GROUP BY aliases and ordinals (`GROUP BY 1, 2`) are replaced by the projection expressions,
+/// so grouping is matched to members as if the expressions were written out.
+#[derive(Debug)]
+pub struct ProjectionAliasReplacer {
+    // Aliases of the select which WHERE is visited right now
+    aliases: Vec<(Ident, Expr)>,
+}
+
+/// Columns and functions referenced by an expression of the projection
+#[derive(Debug, Default)]
+struct ProjectionExprReferences {
+    identifiers: HashSet<String>,
+    aggregate: bool,
+}
+
+impl ProjectionExprReferences {
+    const AGGREGATE_FUNCTIONS: &'static [&'static str] = &[
+        "count",
+        "sum",
+        "avg",
+        "min",
+        "max",
+        "measure",
+        "approx_distinct",
+        "array_agg",
+        "string_agg",
+        "bool_and",
+        "bool_or",
+        "stddev",
+        "stddev_pop",
+        "stddev_samp",
+        "variance",
+        "var_pop",
+        "var_samp",
+        "percentile_cont",
+    ];
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for ProjectionExprReferences {
+    fn visit_identifier(&mut self, identifier: &mut Ident) -> Result<(), ConnectionError> {
+        self.identifiers.insert(identifier.value.to_lowercase());
+
+        Ok(())
+    }
+
+    fn visit_function(&mut self, fun: &mut ast::Function) -> Result<(), ConnectionError> {
+        let name = fun.name.to_string().to_lowercase();
+        if fun.over.is_some() || Self::AGGREGATE_FUNCTIONS.contains(&name.as_str()) {
+            self.aggregate = true;
+        }
+
+        self.visit_function_args(&mut fun.args)
+    }
+}
+
+impl ProjectionAliasReplacer {
+    pub fn new() -> Self {
+        Self { aliases: vec![] }
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result).unwrap();
+
+        result
+    }
+
+    fn projection_aliases(projection: &[ast::SelectItem]) -> Vec<(Ident, Expr)> {
+        let mut references = ProjectionExprReferences::default();
+        let mut aliases = vec![];
+        for item in projection.iter() {
+            match item {
+                ast::SelectItem::ExprWithAlias { expr, alias } => {
+                    let mut expr_references = ProjectionExprReferences::default();
+                    expr_references.visit_expr(&mut expr.clone()).unwrap();
+                    if !expr_references.aggregate && !matches!(expr, Expr::Subquery(_)) {
+                        aliases.push((alias.clone(), expr.clone()));
+                    }
+                    references.identifiers.extend(expr_references.identifiers);
+                }
+                ast::SelectItem::UnnamedExpr(expr) => {
+                    references.visit_expr(&mut expr.clone()).unwrap();
+                }
+                _ => (),
+            }
+        }
+
+        aliases
+            .into_iter()
+            .filter(|(alias, _)| !references.identifiers.contains(&alias.value.to_lowercase()))
+            .collect()
+    }
+
+    /// Expression of the projection at the 1-based position, positions are unknown with wildcards
+    fn resolve_ordinal(projection: &[ast::SelectItem], ordinal: &str) -> Option<Expr> {
+        let position = ordinal.parse::<usize>().ok().filter(|p| *p > 0)?;
+        if projection.iter().any(|item| {
+            matches!(
+                item,
+                ast::SelectItem::Wildcard | ast::SelectItem::QualifiedWildcard(_)
+            )
+        }) {
+            return None;
+        }
+
+        let expr = match projection.get(position - 1)? {
+            ast::SelectItem::UnnamedExpr(expr) | ast::SelectItem::ExprWithAlias { expr, .. } => {
+                expr
+            }
+            _ => return None,
+        };
+        let mut references = ProjectionExprReferences::default();
+        references.visit_expr(&mut expr.clone()).unwrap();
+        // Literal would be an ordinal again
+        if references.aggregate || matches!(expr, Expr::Value(_) | Expr::Subquery(_)) {
+            return None;
+        }
+
+        Some(expr.clone())
+    }
+
+    fn resolve(&self, identifier: &Ident) -> Option<&Expr> {
+        self.aliases
+            .iter()
+            .find(
+                |(alias, _)| match (alias.quote_style, identifier.quote_style) {
+                    (None, None) => alias.value.eq_ignore_ascii_case(&identifier.value),
+                    _ => alias.value == identifier.value,
+                },
+            )
+            .map(|(_, expr)| expr)
+    }
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for ProjectionAliasReplacer {
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), ConnectionError> {
+        if let Expr::Identifier(identifier) = expr {
+            if let Some(resolved) = self.resolve(identifier) {
+                *expr = Expr::Nested(Box::new(resolved.clone()));
+            }
+
+            return Ok(());
+        }
+
+        self.visit_expr_with_placeholder_type(expr, PlaceholderType::String)
+    }
+
+    fn visit_select(&mut self, select: &mut Box<ast::Select>) -> Result<(), ConnectionError> {
+        let aliases = Self::projection_aliases(&select.projection);
+        let outer_aliases = std::mem::replace(&mut self.aliases, aliases);
+        if let Some(selection) = &mut select.selection {
+            self.visit_expr(selection)?;
+        }
+
+        for group_by in &mut select.group_by {
+            let resolved = match group_by {
+                Expr::Value(Value::Number(ordinal, _)) => {
+                    Self::resolve_ordinal(&select.projection, ordinal)
+                }
+                Expr::Identifier(identifier) => self.resolve(identifier).cloned(),
+                _ => None,
+            };
+            if let Some(resolved) = resolved {
+                *group_by = resolved;
+            }
+        }
+        self.aliases = vec![];
+
+        for projection in &mut select.projection {
+            self.visit_select_item(projection)?;
+        }
+
+        for from in &mut select.from {
+            self.visit_table_with_joins(from)?;
+        }
+
+        if let Some(having) = &mut select.having {
+            self.visit_expr(having)?;
+        }
+
+        self.aliases = outer_aliases;
+
+        Ok(())
+    }
+}
+
+/// ANSI `FETCH FIRST n ROWS ONLY` is the same as `LIMIT n`, it's replaced so the limit is
+/// planned and pushed down as any other. `WITH TIES` and `PERCENT` are kept as is.
+#[derive(Debug)]
+pub struct FetchFirstReplacer {}
+
+impl FetchFirstReplacer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result).unwrap();
+
+        result
+    }
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for FetchFirstReplacer {
+    fn visit_query(&mut self, query: &mut Box<ast::Query>) -> Result<(), ConnectionError> {
+        self.visit_set_expr(&mut query.body)?;
+        if let Some(with) = query.with.as_mut() {
+            self.visit_with(with)?;
+        }
+
+        match &query.fetch {
+            Some(fetch) if !fetch.with_ties && !fetch.percent && query.limit.is_none() => {
+                // FETCH FIRST ROW ONLY
+                query.limit = Some(
+                    fetch
+                        .quantity
+                        .clone()
+                        .unwrap_or_else(|| Expr::Value(Value::Number("1".to_string(), false))),
+                );
+                query.fetch = None;
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// `GROUP BY ALL` groups by every projection expression which is not an aggregate, it's
+/// expanded before planning, so grouping is matched to members as if it was written out.
+/// Positions of wildcards are unknown, so they can't be used with it.
+#[derive(Debug)]
+pub struct GroupByAllReplacer {
+    wildcard: bool,
+}
+
+impl GroupByAllReplacer {
+    pub fn new() -> Self {
+        Self { wildcard: false }
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> CompilationResult<ast::Statement> {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result).unwrap();
+
+        if self.wildcard {
+            return Err(CompilationError::unsupported(
+                "GROUP BY ALL with a wildcard projection is not supported".to_string(),
+            ));
+        }
+
+        Ok(result)
+    }
+
+    fn is_group_by_all(group_by: &[Expr]) -> bool {
+        match group_by {
+            [Expr::Identifier(identifier)] => identifier.value == GROUP_BY_ALL_MARKER,
+            _ => false,
+        }
+    }
+
+    fn expand(&mut self, projection: &[ast::SelectItem]) -> Vec<Expr> {
+        let mut group_by = vec![];
+        for item in projection.iter() {
+            let expr = match item {
+                ast::SelectItem::UnnamedExpr(expr)
+                | ast::SelectItem::ExprWithAlias { expr, .. } => expr,
+                ast::SelectItem::Wildcard | ast::SelectItem::QualifiedWildcard(_) => {
+                    self.wildcard = true;
+                    continue;
+                }
+            };
+
+            let mut references = ProjectionExprReferences::default();
+            references.visit_expr(&mut expr.clone()).unwrap();
+            // Literal would be an ordinal
+            if !references.aggregate && !matches!(expr, Expr::Value(_) | Expr::Subquery(_)) {
+                group_by.push(expr.clone());
+            }
+        }
+
+        group_by
+    }
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for GroupByAllReplacer {
+    fn visit_select(&mut self, select: &mut Box<ast::Select>) -> Result<(), ConnectionError> {
+        if Self::is_group_by_all(&select.group_by) {
+            select.group_by = self.expand(&select.projection);
+        }
+
+        for projection in &mut select.projection {
+            self.visit_select_item(projection)?;
+        }
+
+        for from in &mut select.from {
+            self.visit_table_with_joins(from)?;
+        }
+
+        if let Some(selection) = &mut select.selection {
+            self.visit_expr(selection)?;
+        }
+
+        if let Some(having) = &mut select.having {
+            self.visit_expr(having)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `bucket(expr, bound1, ..., boundN)` is a shortcut for bucketing by ascending bounds, it's
+/// compiled to CASE, so it's grouped in the wrapped SQL like a hand-written one:
+///
+/// CASE WHEN expr < bound1 THEN 0 ... WHEN expr < boundN THEN N - 1 WHEN expr >= boundN THEN N END
+///
+/// NULL values have NULL bucket. Bounds must be literals, otherwise the call is left as is.
+#[derive(Debug)]
+pub struct BucketReplacer {}
+
+impl BucketReplacer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result).unwrap();
+
+        result
+    }
+
+    fn is_literal(expr: &Expr) -> bool {
+        match expr {
+            Expr::Value(value) => !matches!(value, Value::Null | Value::Placeholder(_)),
+            Expr::UnaryOp { expr, .. } | Expr::Nested(expr) => Self::is_literal(expr),
+            _ => false,
+        }
+    }
+
+    fn rewrite_bucket(fun: &Function) -> Option<Expr> {
+        if fun.name.0.len() != 1 || fun.name.0[0].value.to_lowercase() != "bucket" {
+            return None;
+        }
+
+        let args = fun
+            .args
+            .iter()
+            .map(|arg| match arg {
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Some(expr.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let (expr, bounds) = args.split_first()?;
+        if bounds.is_empty() || !bounds.iter().all(Self::is_literal) {
+            return None;
+        }
+
+        let compare = |op: ast::BinaryOperator, bound: &Expr| Expr::BinaryOp {
+            left: Box::new(expr.clone()),
+            op,
+            right: Box::new(bound.clone()),
+        };
+        let number = |n: usize| Expr::Value(Value::Number(n.to_string(), false));
+
+        let mut conditions = bounds
+            .iter()
+            .map(|bound| compare(ast::BinaryOperator::Lt, bound))
+            .collect::<Vec<_>>();
+        conditions.push(compare(ast::BinaryOperator::GtEq, bounds.last()?));
+
+        Some(Expr::Case {
+            operand: None,
+            results: (0..conditions.len()).map(number).collect(),
+            conditions,
+            else_result: None,
+        })
+    }
+}
+
+impl<'ast> Visitor<'ast, ConnectionError> for BucketReplacer {
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), ConnectionError> {
+        self.visit_expr_with_placeholder_type(expr, PlaceholderType::String)?;
+
+        if let Expr::Function(fun) = expr {
+            if let Some(rewritten) = Self::rewrite_bucket(fun) {
+                *expr = rewritten;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile::parser::MySqlDialectWithBackTicks, CubeError};
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+
+    fn run_cast_replacer(input: &str, output: &str) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let replacer = CastReplacer::new();
+        let res = replacer.replace(&stmts[0]);
+
+        assert_eq!(res.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cast_replacer() -> Result<(), CubeError> {
+        run_cast_replacer("SELECT 'pg_class'::regclass", "SELECT 1259")?;
+        run_cast_replacer("SELECT 'pg_class'::regclass::oid", "SELECT 1259")?;
+        run_cast_replacer("SELECT 64::information_schema.cardinal_number", "SELECT 64")?;
+        run_cast_replacer(
+            "SELECT NOW()::timestamptz",
+            "SELECT CAST(NOW() AS TIMESTAMP)",
+        )?;
+        run_cast_replacer(
+            "SELECT CAST(1 + 1 as Regclass);",
+            "SELECT __cube_regclass_cast(1 + 1)",
+        )?;
+
+        Ok(())
+    }
+
+    fn run_redshift_date_part_replacer(input: &str, output: &str) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let replacer = RedshiftDatePartReplacer::new();
+        let res = replacer.replace(&stmts[0]);
+
+        assert_eq!(res.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redshift_date_part_replacer() -> Result<(), CubeError> {
+        run_redshift_date_part_replacer(
+            r#"SELECT DATEDIFF(day, DATE '1970-01-01', "ta_1"."createdAt")"#,
+            r#"SELECT DATEDIFF('day', DATE '1970-01-01', "ta_1"."createdAt")"#,
+        )?;
+
+        run_redshift_date_part_replacer(
+            r#"SELECT DATEADD(week, '2009-01-01', '2009-12-31')"#,
+            r#"SELECT DATEADD('week', '2009-01-01', '2009-12-31')"#,
+        )?;
+
+        run_redshift_date_part_replacer(
+            r#"SELECT DATEDIFF(day, DATEADD(week, '2009-01-01', '2009-12-31'), "ta_1"."createdAt")"#,
+            r#"SELECT DATEDIFF('day', DATEADD('week', '2009-01-01', '2009-12-31'), "ta_1"."createdAt")"#,
+        )?;
+
+        Ok(())
+    }
+
+    fn run_compare_date_range_replacer(input: &str, output: &str) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let replacer = CompareDateRangeReplacer::new();
+        let res = replacer.replace(&stmts[0]);
+
+        assert_eq!(res.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_date_range_replacer() -> Result<(), CubeError> {
+        run_compare_date_range_replacer(
+            "SELECT compare_date_range, status, MEASURE(count) FROM compare_date_range(Orders, createdAt, '2024-01-01', '2024-01-31', '2023-01-01', '2023-01-31') GROUP BY 1, 2",
+            "SELECT '2024-01-01 - 2024-01-31' AS compare_date_range, status, MEASURE(count) FROM Orders WHERE createdAt BETWEEN CAST('2024-01-01' AS TIMESTAMP) AND CAST('2024-01-31T23:59:59.999' AS TIMESTAMP) GROUP BY 2 UNION ALL SELECT '2023-01-01 - 2023-01-31' AS compare_date_range, status, MEASURE(count) FROM Orders WHERE createdAt BETWEEN CAST('2023-01-01' AS TIMESTAMP) AND CAST('2023-01-31T23:59:59.999' AS TIMESTAMP) GROUP BY 2",
+        )?;
+
+        run_compare_date_range_replacer(
+            "SELECT o.compare_date_range AS period, MEASURE(o.count) FROM compare_date_range(Orders, o.createdAt, '2024-01-01', '2024-01-31', '2023-01-01', '2023-01-31') AS o WHERE o.status = 'shipped' GROUP BY o.compare_date_range",
+            "SELECT '2024-01-01 - 2024-01-31' AS period, MEASURE(o.count) FROM Orders AS o WHERE (o.status = 'shipped') AND o.createdAt BETWEEN CAST('2024-01-01' AS TIMESTAMP) AND CAST('2024-01-31T23:59:59.999' AS TIMESTAMP) UNION ALL SELECT '2023-01-01 - 2023-01-31' AS period, MEASURE(o.count) FROM Orders AS o WHERE (o.status = 'shipped') AND o.createdAt BETWEEN CAST('2023-01-01' AS TIMESTAMP) AND CAST('2023-01-31T23:59:59.999' AS TIMESTAMP)",
+        )?;
+
+        // Periods ending with a timestamp are kept as is
+        run_compare_date_range_replacer(
+            "SELECT compare_date_range, MEASURE(count) FROM compare_date_range(Orders, createdAt, '2024-01-01', '2024-01-31 12:00:00', '2023-01-01', '2023-01-31 12:00:00') GROUP BY 1",
+            "SELECT '2024-01-01 - 2024-01-31 12:00:00' AS compare_date_range, MEASURE(count) FROM Orders WHERE createdAt BETWEEN CAST('2024-01-01' AS TIMESTAMP) AND CAST('2024-01-31 12:00:00' AS TIMESTAMP) UNION ALL SELECT '2023-01-01 - 2023-01-31 12:00:00' AS compare_date_range, MEASURE(count) FROM Orders WHERE createdAt BETWEEN CAST('2023-01-01' AS TIMESTAMP) AND CAST('2023-01-31 12:00:00' AS TIMESTAMP)",
+        )?;
+
+        // Periods must be pairs of dates
+        run_compare_date_range_replacer(
+            "SELECT * FROM compare_date_range(Orders, createdAt, '2024-01-01')",
+            "SELECT * FROM compare_date_range(Orders, createdAt, '2024-01-01')",
+        )?;
+
+        Ok(())
+    }
+
+    fn run_projection_alias_replacer(input: &str, output: &str) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let replacer = ProjectionAliasReplacer::new();
+        let res = replacer.replace(&stmts[0]);
+
+        assert_eq!(res.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_projection_alias_replacer() -> Result<(), CubeError> {
+        run_projection_alias_replacer(
+            "SELECT taxful_total_price + 1 AS total FROM Orders WHERE total > 10 ORDER BY total",
+            "SELECT taxful_total_price + 1 AS total FROM Orders WHERE (taxful_total_price + 1) > 10 ORDER BY total",
+        )?;
+
+        // Nested selects resolve own aliases
+        run_projection_alias_replacer(
+            "SELECT LOWER(status) AS s FROM (SELECT status, 1 AS s FROM Orders WHERE s = 1) AS t WHERE s = 'new'",
+            "SELECT LOWER(status) AS s FROM (SELECT status, 1 AS s FROM Orders WHERE (1) = 1) AS t WHERE (LOWER(status)) = 'new'",
+        )?;
+
+        // Columns take precedence over aliases, aliases of aggregates are not allowed in WHERE
+        run_projection_alias_replacer(
+            "SELECT LOWER(status) AS status, COUNT(*) AS cnt FROM Orders WHERE status = 'new' AND cnt > 1 GROUP BY 1",
+            "SELECT LOWER(status) AS status, COUNT(*) AS cnt FROM Orders WHERE status = 'new' AND cnt > 1 GROUP BY LOWER(status)",
+        )?;
+
+        // GROUP BY ordinals and aliases
+        run_projection_alias_replacer(
+            "SELECT DATE_TRUNC('month', order_date) AS m, status, COUNT(*) FROM Orders GROUP BY m, 2",
+            "SELECT DATE_TRUNC('month', order_date) AS m, status, COUNT(*) FROM Orders GROUP BY DATE_TRUNC('month', order_date), status",
+        )?;
+        run_projection_alias_replacer(
+            "SELECT 1 AS one, * FROM Orders GROUP BY 1, 2",
+            "SELECT 1 AS one, * FROM Orders GROUP BY 1, 2",
+        )?;
+
+        Ok(())
+    }
+
+    fn run_fetch_first_replacer(input: &str, output: &str) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let replacer = FetchFirstReplacer::new();
+        let res = replacer.replace(&stmts[0]);
+
+        assert_eq!(res.to_string(), output);
+
+        Ok(())
+    }
+
+    fn run_bucket_replacer(input: &str, output: &str) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let replacer = BucketReplacer::new();
+        let res = replacer.replace(&stmts[0]);
+
+        assert_eq!(res.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bucket_replacer() -> Result<(), CubeError> {
+        run_bucket_replacer(
+            "SELECT BUCKET(age, 18, 30), COUNT(*) FROM Users GROUP BY 1",
+            "SELECT CASE WHEN age < 18 THEN 0 WHEN age < 30 THEN 1 WHEN age >= 30 THEN 2 END, COUNT(*) FROM Users GROUP BY 1",
+        )?;
+
+        run_bucket_replacer(
+            "SELECT bucket(revenue, -100, 0.5) FROM Orders",
+            "SELECT CASE WHEN revenue < -100 THEN 0 WHEN revenue < 0.5 THEN 1 WHEN revenue >= 0.5 THEN 2 END FROM Orders",
+        )?;
+
+        // Bounds must be literals
+        run_bucket_replacer(
+            "SELECT bucket(revenue, cost) FROM Orders",
+            "SELECT bucket(revenue, cost) FROM Orders",
+        )?;
+        run_bucket_replacer(
+            "SELECT bucket(revenue) FROM Orders",
+            "SELECT bucket(revenue) FROM Orders",
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_first_replacer() -> Result<(), CubeError> {
+        run_fetch_first_replacer(
+            "SELECT status FROM Orders ORDER BY status OFFSET 10 ROWS FETCH FIRST 5 ROWS ONLY",
+            "SELECT status FROM Orders ORDER BY status LIMIT 5 OFFSET 10 ROWS",
+        )?;
+
+        run_fetch_first_replacer(
+            "SELECT * FROM (SELECT status FROM Orders FETCH NEXT ROW ONLY) AS t",
+            "SELECT * FROM (SELECT status FROM Orders LIMIT 1) AS t",
+        )?;
+
+        run_fetch_first_replacer(
+            "SELECT status FROM Orders ORDER BY status FETCH FIRST 5 ROWS WITH TIES",
+            "SELECT status FROM Orders ORDER BY status FETCH FIRST 5 ROWS WITH TIES",
+        )?;
+
+        Ok(())
+    }
+
+    fn run_granularity_replacer(
+        fiscal_year_start_month: u32,
+        input: &str,
+        output: &str,
+    ) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&MySqlDialectWithBackTicks {}, &input).unwrap();
+
+        let custom_granularities = vec!["fiscal_week".to_string()].into_iter().collect();
+        let replacer = GranularityReplacer::new(fiscal_year_start_month, custom_granularities);
+        let res = replacer.replace(&stmts[0]);
+
+        assert_eq!(res.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_granularity_replacer() -> Result<(), CubeError> {
+        run_granularity_replacer(
+            1,
+            "SELECT DATE_TRUNC('fiscal_year', order_date), DATE_PART('Fiscal_Quarter', order_date) FROM Orders",
+            "SELECT date_trunc('year', order_date), date_part('quarter', order_date) FROM Orders",
+        )?;
+        run_granularity_replacer(
+            4,
+            "SELECT DATE_TRUNC('fiscal_year', order_date) FROM Orders",
+            "SELECT date_trunc('year', order_date - INTERVAL '3 month') + INTERVAL '3 month' FROM Orders",
+        )?;
+        run_granularity_replacer(
+            4,
+            "SELECT DATE_PART('fiscal_year', order_date), DATE_PART('fiscal_quarter', order_date) FROM Orders",
+            "SELECT date_part('year', order_date + INTERVAL '9 month'), date_part('quarter', order_date - INTERVAL '3 month') FROM Orders",
+        )?;
+        run_granularity_replacer(
+            1,
+            "SELECT WEEK(order_date, 3), WEEK(order_date), DATE_TRUNC('isoweek', order_date) FROM Orders",
+            "SELECT date_part('week', order_date), WEEK(order_date), date_trunc('week', order_date) FROM Orders",
+        )?;
+        // Custom granularities are pushed down as is
+        run_granularity_replacer(
+            4,
+            "SELECT DATE_TRUNC('fiscal_week', order_date), DATE_TRUNC(granularity, order_date) FROM Orders",
+            "SELECT DATE_TRUNC('fiscal_week', order_date), DATE_TRUNC(granularity, order_date) FROM Orders",
+        )?;
+
+        Ok(())
+    }
+
+    fn run_timezone_replacer(
+        input: &str,
+        output: &str,
+        timezone: Option<&str>,
+    ) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let cube_time_dimensions = HashMap::from([(
+            "orders".to_string(),
+            HashSet::from(["order_date".to_string(), "created_at".to_string()]),
+        )]);
+        let (res, res_timezone) = TimezoneReplacer::new(cube_time_dimensions).replace(&stmts[0]);
+
+        assert_eq!(res.to_string(), output);
+        assert_eq!(res_timezone.as_deref(), timezone);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timezone_replacer() -> Result<(), CubeError> {
+        run_timezone_replacer(
+            "SELECT date_trunc('day', order_date AT TIME ZONE 'UTC') FROM Orders",
+            "SELECT date_trunc('day', order_date) FROM Orders",
+            None,
+        )?;
+        run_timezone_replacer(
+            "SELECT date_trunc('day', timezone('America/New_York', o.order_date)) FROM Orders AS o WHERE o.order_date AT TIME ZONE 'America/New_York' > '2024-01-01'",
+            "SELECT date_trunc('day', o.order_date) FROM Orders AS o WHERE o.order_date > '2024-01-01'",
+            Some("America/New_York"),
+        )?;
+        run_timezone_replacer(
+            "SELECT convert_tz(order_date, '+00:00', 'Europe/Berlin') FROM Orders",
+            "SELECT order_date FROM Orders",
+            Some("Europe/Berlin"),
+        )?;
+        // Conversions to several time zones and of expressions are evaluated locally
+        run_timezone_replacer(
+            "SELECT order_date AT TIME ZONE 'Europe/Berlin', order_date AT TIME ZONE 'Asia/Tokyo' FROM Orders",
+            "SELECT order_date AT TIME ZONE 'Europe/Berlin', order_date AT TIME ZONE 'Asia/Tokyo' FROM Orders",
+            None,
+        )?;
+        run_timezone_replacer(
+            "SELECT now() AT TIME ZONE 'Europe/Berlin', convert_tz(order_date, 'Asia/Tokyo', 'UTC') FROM Orders",
+            "SELECT now() AT TIME ZONE 'Europe/Berlin', convert_tz(order_date, 'Asia/Tokyo', 'UTC') FROM Orders",
+            None,
+        )?;
+        // Chained conversions from UTC convert to the last time zone
+        run_timezone_replacer(
+            "SELECT date_trunc('day', order_date AT TIME ZONE 'UTC' AT TIME ZONE 'Europe/Berlin') FROM Orders",
+            "SELECT date_trunc('day', order_date) FROM Orders",
+            Some("Europe/Berlin"),
+        )?;
+        run_timezone_replacer(
+            "SELECT timezone('Europe/Berlin', timezone('UTC', created_at)) FROM Orders",
+            "SELECT created_at FROM Orders",
+            Some("Europe/Berlin"),
+        )?;
+        // Time dimensions which are also referenced as is would be converted as well
+        run_timezone_replacer(
+            "SELECT order_date, order_date AT TIME ZONE 'Europe/Berlin' FROM Orders",
+            "SELECT order_date, order_date AT TIME ZONE 'Europe/Berlin' FROM Orders",
+            None,
+        )?;
+        run_timezone_replacer(
+            "SELECT created_at AT TIME ZONE 'Europe/Berlin' FROM Orders WHERE order_date > '2024-01-01'",
+            "SELECT created_at AT TIME ZONE 'Europe/Berlin' FROM Orders WHERE order_date > '2024-01-01'",
+            None,
+        )?;
+        run_timezone_replacer(
+            "SELECT order_date, order_date AT TIME ZONE 'UTC' FROM Orders",
+            "SELECT order_date, order_date FROM Orders",
+            None,
+        )?;
+        // Only time dimensions of referenced cubes are replaced
+        run_timezone_replacer(
+            "SELECT status AT TIME ZONE 'Europe/Berlin', order_date AT TIME ZONE 'Europe/Berlin' FROM Orders",
+            "SELECT status AT TIME ZONE 'Europe/Berlin', order_date FROM Orders",
+            Some("Europe/Berlin"),
+        )?;
+        run_timezone_replacer(
+            "WITH t AS (SELECT now() AS order_date) SELECT order_date AT TIME ZONE 'Europe/Berlin' FROM t",
+            "WITH t AS (SELECT now() AS order_date) SELECT order_date AT TIME ZONE 'Europe/Berlin' FROM t",
+            None,
+        )?;
+        run_timezone_replacer(
+            "SELECT backend_start AT TIME ZONE 'Europe/Berlin' FROM pg_catalog.pg_stat_activity",
+            "SELECT backend_start AT TIME ZONE 'Europe/Berlin' FROM pg_catalog.pg_stat_activity",
+            None,
+        )?;
+
+        Ok(())
+    }
+
+    fn run_view_replacer(input: &str, output: &str) -> Result<(), CubeError> {
+        let views = vec![
+            ("shipped", "SELECT status, MEASURE(count) AS cnt FROM Orders WHERE status = 'shipped' GROUP BY 1"),
+            ("shipped_top", "SELECT * FROM shipped ORDER BY cnt DESC LIMIT 10"),
+            // Self-reference is not expanded endlessly
+            ("cyclic", "SELECT * FROM cyclic"),
+        ]
+        .into_iter()
+        .map(|(name, sql)| {
+            let query = match Parser::parse_sql(&PostgreSqlDialect {}, sql).unwrap().remove(0) {
+                ast::Statement::Query(query) => *query,
+                _ => unreachable!(),
+            };
+
+            (name.to_string(), query)
+        })
+        .collect::<HashMap<_, _>>();
+
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let replacer = ViewReplacer::new(Arc::new(views));
+        let res = replacer.replace(&stmts[0]);
+
+        assert_eq!(res.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_view_replacer() -> Result<(), CubeError> {
+        run_view_replacer(
+            "SELECT cnt FROM Shipped",
+            "SELECT cnt FROM (SELECT status, MEASURE(count) AS cnt FROM Orders WHERE status = 'shipped' GROUP BY 1) AS Shipped",
+        )?;
+        run_view_replacer(
+            "SELECT s.status FROM shipped_top s JOIN Orders o ON s.status = o.status",
+            "SELECT s.status FROM (SELECT * FROM (SELECT status, MEASURE(count) AS cnt FROM Orders WHERE status = 'shipped' GROUP BY 1) AS shipped ORDER BY cnt DESC LIMIT 10) AS s JOIN Orders AS o ON s.status = o.status",
+        )?;
+        run_view_replacer(
+            "SELECT * FROM cyclic",
+            "SELECT * FROM (SELECT * FROM cyclic) AS cyclic",
+        )?;
+        // Schema qualified tables are not views
+        run_view_replacer(
+            "SELECT * FROM public.shipped",
+            "SELECT * FROM public.shipped",
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scalar_subquery_replacer() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT status, count / (SELECT MAX(count) FROM Orders) AS share, (SELECT 1 WHERE EXISTS (SELECT 1)) FROM Orders WHERE id > (SELECT MIN(id) FROM Orders)",
+        )
+        .unwrap();
+
+        let found = ScalarSubqueryReplacer::find(&stmts[0])
+            .iter()
+            .map(|query| query.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                "SELECT MAX(count) FROM Orders".to_string(),
+                "SELECT 1 WHERE EXISTS (SELECT 1)".to_string()
+            ]
+        );
+
+        let replacer = ScalarSubqueryReplacer::new(HashMap::from([(
+            "SELECT MAX(count) FROM Orders".to_string(),
+            Expr::Value(Value::Number("42".to_string(), false)),
+        )]));
+        assert_eq!(
+            replacer.replace(&stmts[0]).to_string(),
+            "SELECT status, count / 42 AS share, (SELECT 1 WHERE EXISTS (SELECT 1)) FROM Orders WHERE id > (SELECT MIN(id) FROM Orders)"
+        );
+
+        let replacer = ScalarSubqueryReplacer::new(HashMap::from([(
+            "SELECT 1 WHERE EXISTS (SELECT 1)".to_string(),
+            Expr::Value(Value::Number("1".to_string(), false)),
+        )]))
+        .with_aliases(HashMap::from([(
+            "SELECT 1 WHERE EXISTS (SELECT 1)".to_string(),
+            "one".to_string(),
+        )]));
+        assert_eq!(
+            replacer.replace(&stmts[0]).to_string(),
+            "SELECT status, count / (SELECT MAX(count) FROM Orders) AS share, 1 AS \"one\" FROM Orders WHERE id > (SELECT MIN(id) FROM Orders)"
+        );
+
+        Ok(())
+    }
+
+    fn run_pg_binder(
+        input: &str,
+        output: &str,
+        values: Vec<BindValue>,
+    ) -> Result<(), ConnectionError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let binder = PostgresStatementParamsBinder::new(values);
+        let mut res = stmts[0].clone();
+        binder.bind(&mut res)?;
+
+        assert_eq!(res.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pg_binder() -> Result<(), ConnectionError> {
+        run_pg_binder(
+            "SELECT $1",
+            "SELECT 'test'",
+            vec![BindValue::String("test".to_string())],
+        )?;
+
+        run_pg_binder(
+            "SELECT $1 AS t1, $2 AS t2",
+            "SELECT 'test1' AS t1, NULL AS t2",
+            vec![BindValue::String("test1".to_string()), BindValue::Null],
+        )?;
+
+        run_pg_binder(
+            "SELECT $1 AS t1, $2 AS t2, $1 as b1, $2 as b2",
+            "SELECT 'test1' AS t1, NULL AS t2, 'test1' AS b1, NULL AS b2",
+            vec![BindValue::String("test1".to_string()), BindValue::Null],
+        )?;
+
+        // binary op
+        run_pg_binder(
+            r#"
+                SELECT *
+                FROM testdata
+                WHERE fieldA = $1 AND fieldB = $2 OR (fieldC = $3 AND fieldD = $4)
+            "#,
+            "SELECT * FROM testdata WHERE fieldA = 'test' AND fieldB = 1 OR (fieldC = 2 AND fieldD = 2)",
+            vec![
+                BindValue::String("test".to_string()),
+                BindValue::Int64(1),
+                BindValue::Int64(2),
+                BindValue::Float64(2.0),
+                BindValue::Bool(true),
+            ],
+        )?;
+
+        // IN
+        run_pg_binder(
+            r#"
+                SELECT *
+                FROM testdata
+                WHERE fieldA IN ($1, $2)
+            "#,
+            "SELECT * FROM testdata WHERE fieldA IN ('test1', 'test2')",
+            vec![
+                BindValue::String("test1".to_string()),
+                BindValue::String("test2".to_string()),
+            ],
+        )?;
+
+        // BETWEEN
+        run_pg_binder(
+            r#"
+                SELECT *
+                FROM testdata
+                WHERE fieldA BETWEEN $1 AND $2
+            "#,
+            "SELECT * FROM testdata WHERE fieldA BETWEEN 'test1' AND 'test2'",
+            vec![
+                BindValue::String("test1".to_string()),
+                BindValue::String("test2".to_string()),
+            ],
+        )?;
+
+        run_pg_binder(
+            r#"
+                SELECT *
+                FROM testdata
+                WHERE fieldA = $1
+                UNION ALL
                 SELECT *
                 FROM testdata
                 WHERE fieldA = $2
@@ -1442,4 +3136,31 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_bind_values_log_policy() -> Result<(), CubeError> {
+        let values = vec![
+            BindValue::String("secret".to_string()),
+            BindValue::Int64(5),
+            BindValue::Null,
+        ];
+
+        assert_eq!(
+            "types-only".parse::<BindValuesLogPolicy>(),
+            Ok(BindValuesLogPolicy::TypesOnly)
+        );
+        assert!("verbose".parse::<BindValuesLogPolicy>().is_err());
+
+        assert_eq!(BindValuesLogPolicy::None.format(&values), None);
+        assert_eq!(
+            BindValuesLogPolicy::TypesOnly.format(&values),
+            Some("[$1: String, $2: Int64, $3: Null]".to_string())
+        );
+        assert_eq!(
+            BindValuesLogPolicy::Full.format(&values),
+            Some(r#"[$1: String("secret"), $2: Int64(5), $3: Null]"#.to_string())
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,133 @@
+use chrono::{DateTime, Utc};
+use std::sync::RwLock as RwLockSync;
+
+/// Kind of load which is served by a statement, it's classified after planning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatementClass {
+    /// Queries to system catalogs without cubes, e.g. BI tools listing tables
+    Introspection,
+    /// SHOW, DESCRIBE and EXPLAIN
+    Metadata,
+    /// Queries to cubes with grouping, typical for dashboards
+    Grouped,
+    /// Queries to cubes without grouping, i.e. extracts of raw rows
+    Ungrouped,
+    /// SET, transactions, cursors and other statements which don't return data
+    Utility,
+}
+
+impl StatementClass {
+    pub const ALL: [StatementClass; 5] = [
+        StatementClass::Introspection,
+        StatementClass::Metadata,
+        StatementClass::Grouped,
+        StatementClass::Ungrouped,
+        StatementClass::Utility,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatementClass::Introspection => "introspection",
+            StatementClass::Metadata => "metadata",
+            StatementClass::Grouped => "grouped",
+            StatementClass::Ungrouped => "ungrouped",
+            StatementClass::Utility => "utility",
+        }
+    }
+
+    fn index(&self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|class| class == self)
+            .expect("statement class is not listed")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementClassUsage {
+    pub class: StatementClass,
+    pub statements: u64,
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// Server-wide number of planned statements by class, exposed as `cubesql.statement_classes`
+/// to see which load the SQL API actually serves
+#[derive(Debug)]
+pub struct StatementClassStats {
+    classes: RwLockSync<Vec<StatementClassUsage>>,
+}
+
+impl StatementClassStats {
+    pub fn new() -> Self {
+        Self {
+            classes: RwLockSync::new(Self::empty()),
+        }
+    }
+
+    fn empty() -> Vec<StatementClassUsage> {
+        StatementClass::ALL
+            .iter()
+            .map(|class| StatementClassUsage {
+                class: *class,
+                statements: 0,
+                last_seen: None,
+            })
+            .collect()
+    }
+
+    pub fn record(&self, class: StatementClass) {
+        let mut classes = self
+            .classes
+            .write()
+            .expect("failed to unlock statement class stats for writing");
+        let usage = &mut classes[class.index()];
+        usage.statements += 1;
+        usage.last_seen = Some(Utc::now());
+    }
+
+    pub fn snapshot(&self) -> Vec<StatementClassUsage> {
+        self.classes
+            .read()
+            .expect("failed to unlock statement class stats for reading")
+            .clone()
+    }
+
+    pub fn reset(&self) {
+        *self
+            .classes
+            .write()
+            .expect("failed to unlock statement class stats for writing") = Self::empty();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statement_class_stats() {
+        let stats = StatementClassStats::new();
+        stats.record(StatementClass::Grouped);
+        stats.record(StatementClass::Grouped);
+        stats.record(StatementClass::Introspection);
+
+        let snapshot = stats
+            .snapshot()
+            .into_iter()
+            .map(|usage| (usage.class.as_str(), usage.statements))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            snapshot,
+            vec![
+                ("introspection", 1),
+                ("metadata", 0),
+                ("grouped", 2),
+                ("ungrouped", 0),
+                ("utility", 0),
+            ]
+        );
+
+        stats.reset();
+        assert!(stats.snapshot().iter().all(|usage| usage.statements == 0));
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use sqlparser::ast::Statement;
+
+use crate::{compile::engine::df::scan_schemas::ScanSchemaCache, sql::ColumnType};
+
+// Clients which build statements with inlined literals would only pollute the cache
+const MAX_ENTRIES: usize = 64;
+
+/// Statements are keyed by their text with parameter markers and types of the parameters, bind
+/// values are never a part of the key. Every value bound to the statement reuses the same scan
+/// schemas, while a statement which is prepared with other parameter types gets its own entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementSchemasKey {
+    pub statement: String,
+    pub parameter_types: Vec<ColumnType>,
+}
+
+impl StatementSchemasKey {
+    pub fn new(statement: &Statement, parameter_types: Vec<ColumnType>) -> Self {
+        Self {
+            statement: statement.to_string(),
+            parameter_types,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct StatementSchemasEntry {
+    key: StatementSchemasKey,
+    scan_schemas: Arc<ScanSchemaCache>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementSchemasStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Scan schemas of prepared statements of the session, they are shared by statements which
+/// drivers prepare again for every execution. Statements are still planned on every Bind, only
+/// Arrow schemas of their CubeScans are reused. `SET cube_scan_schema_cache = off` bypasses it.
+#[derive(Debug, Default)]
+pub struct StatementSchemas {
+    entries: Mutex<Vec<StatementSchemasEntry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl StatementSchemas {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_or_insert(&self, key: StatementSchemasKey) -> Arc<ScanSchemaCache> {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(position) = entries.iter().position(|entry| entry.key == key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+
+            // Least recently used entries are evicted first
+            let entry = entries.remove(position);
+            let scan_schemas = entry.scan_schemas.clone();
+            entries.push(entry);
+
+            return scan_schemas;
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if entries.len() >= MAX_ENTRIES {
+            entries.remove(0);
+        }
+
+        let scan_schemas = Arc::new(ScanSchemaCache::new());
+        entries.push(StatementSchemasEntry {
+            key,
+            scan_schemas: scan_schemas.clone(),
+        });
+
+        scan_schemas
+    }
+
+    pub fn stats(&self) -> StatementSchemasStats {
+        StatementSchemasStats {
+            entries: self.entries.lock().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile::parser::parse_sql_to_statement, sql::session::DatabaseProtocol};
+
+    fn key(query: &str, parameter_types: Vec<ColumnType>) -> StatementSchemasKey {
+        let statement =
+            parse_sql_to_statement(&query.to_string(), DatabaseProtocol::PostgreSQL, &mut None)
+                .unwrap();
+
+        StatementSchemasKey::new(&statement, parameter_types)
+    }
+
+    #[test]
+    fn test_statement_schemas() {
+        let cache = StatementSchemas::new();
+        let query = "SELECT * FROM KibanaSampleDataEcommerce WHERE customer_gender = $1";
+
+        let first = cache.get_or_insert(key(query, vec![ColumnType::String]));
+        let second = cache.get_or_insert(key(query, vec![ColumnType::String]));
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // Same markers with other types are planned separately
+        let other = cache.get_or_insert(key(query, vec![ColumnType::Int64]));
+        assert!(!Arc::ptr_eq(&first, &other));
+
+        assert_eq!(
+            cache.stats(),
+            StatementSchemasStats {
+                entries: 2,
+                hits: 1,
+                misses: 2,
+            }
+        );
+    }
+}
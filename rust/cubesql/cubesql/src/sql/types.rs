@@ -148,6 +148,8 @@ pub enum CommandCompletion {
     Deallocate,
     DeallocateAll,
     Discard(String),
+    CreateView,
+    DropView,
 }
 
 impl CommandCompletion {
@@ -172,6 +174,8 @@ impl CommandCompletion {
                 CommandComplete::Plain("DEALLOCATE ALL".to_string())
             }
             CommandCompletion::Discard(tp) => CommandComplete::Plain(format!("DISCARD {}", tp)),
+            CommandCompletion::CreateView => CommandComplete::Plain("CREATE VIEW".to_string()),
+            CommandCompletion::DropView => CommandComplete::Plain("DROP VIEW".to_string()),
             // ROWS COUNT
             CommandCompletion::Select(rows) => CommandComplete::Select(rows),
         }
@@ -0,0 +1,256 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock as RwLockSync,
+    },
+};
+
+use crate::compile::CompilationError;
+
+// Reasons are aggregated by message, a message with literals of the query shouldn't flood memory
+const MAX_REASONS: usize = 1000;
+const MAX_REASON_LENGTH: usize = 256;
+
+lazy_static! {
+    static ref UNKNOWN_FUNCTION_REGEX: Regex =
+        Regex::new(r"Invalid function '(?P<function>[^']+)'").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum UnsupportedQueryCategory {
+    Parse,
+    Rewrite,
+    UnsupportedFunction,
+    Type,
+    /// Statement which is recognized, but not supported, e.g. `SELECT INTO`
+    Statement,
+}
+
+impl UnsupportedQueryCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnsupportedQueryCategory::Parse => "parse",
+            UnsupportedQueryCategory::Rewrite => "rewrite",
+            UnsupportedQueryCategory::UnsupportedFunction => "unsupported_function",
+            UnsupportedQueryCategory::Type => "type",
+            UnsupportedQueryCategory::Statement => "statement",
+        }
+    }
+
+    /// Category and reason of the error, None for errors which don't point to missing support
+    /// of SQL (unknown members, authentication, transport)
+    pub fn classify(error: &CompilationError) -> Option<(Self, String)> {
+        let message = match error {
+            CompilationError::User(message, _)
+            | CompilationError::Internal(message, _, _)
+            | CompilationError::Unsupported(message, _) => message,
+            CompilationError::Fatal(_, _) | CompilationError::MemberNotFound(_, _) => return None,
+        };
+
+        if let Some(captures) = UNKNOWN_FUNCTION_REGEX.captures(message) {
+            return Some((
+                UnsupportedQueryCategory::UnsupportedFunction,
+                captures["function"].to_lowercase(),
+            ));
+        }
+
+        let category = if message.starts_with("Unable to parse") {
+            UnsupportedQueryCategory::Parse
+        } else if message.starts_with("Error during rewrite") {
+            UnsupportedQueryCategory::Rewrite
+        } else if message.contains("Coercion from")
+            || message.contains("Unsupported CAST")
+            || message.contains("Unsupported SQL type")
+        {
+            UnsupportedQueryCategory::Type
+        } else if let CompilationError::Unsupported(_, _) = error {
+            UnsupportedQueryCategory::Statement
+        } else {
+            return None;
+        };
+
+        Some((category, message.chars().take(MAX_REASON_LENGTH).collect()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnsupportedQuery {
+    pub category: UnsupportedQueryCategory,
+    pub reason: String,
+    pub queries: u64,
+    /// Last query with the reason, literals are masked
+    pub sanitized_query: Option<String>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedQueryMetrics {
+    pub queries: u64,
+    pub reasons: usize,
+}
+
+/// Server-wide statistics of queries which were rejected because of missing SQL support.
+/// Exposed as `cubesql.unsupported_queries` to prioritize constructs by real traffic.
+#[derive(Debug)]
+pub struct UnsupportedQueryStats {
+    reasons: RwLockSync<HashMap<(UnsupportedQueryCategory, String), UnsupportedQuery>>,
+    queries: AtomicU64,
+}
+
+impl UnsupportedQueryStats {
+    pub fn new() -> Self {
+        Self {
+            reasons: RwLockSync::new(HashMap::new()),
+            queries: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the category and reason if the error was counted
+    pub fn record(&self, error: &CompilationError) -> Option<(UnsupportedQueryCategory, String)> {
+        let (category, reason) = UnsupportedQueryCategory::classify(error)?;
+        let sanitized_query = match error {
+            CompilationError::User(_, meta)
+            | CompilationError::Internal(_, _, meta)
+            | CompilationError::Unsupported(_, meta) => meta
+                .as_ref()
+                .and_then(|meta| meta.get("sanitizedQuery").cloned()),
+            _ => None,
+        };
+
+        self.queries.fetch_add(1, Ordering::Relaxed);
+
+        let mut reasons = self
+            .reasons
+            .write()
+            .expect("failed to unlock unsupported query stats for writing");
+        let key = (category, reason.clone());
+        if reasons.len() >= MAX_REASONS && !reasons.contains_key(&key) {
+            return Some((category, reason));
+        }
+
+        let now = Utc::now();
+        let entry = reasons.entry(key).or_insert_with(|| UnsupportedQuery {
+            category,
+            reason: reason.clone(),
+            queries: 0,
+            sanitized_query: None,
+            last_seen: now,
+        });
+        entry.queries += 1;
+        entry.last_seen = now;
+        if sanitized_query.is_some() {
+            entry.sanitized_query = sanitized_query;
+        }
+
+        Some((category, reason))
+    }
+
+    /// Copy of the collected statistics, the most frequent reasons go first
+    pub fn snapshot(&self) -> Vec<UnsupportedQuery> {
+        let reasons = self
+            .reasons
+            .read()
+            .expect("failed to unlock unsupported query stats for reading");
+
+        let mut result = reasons.values().cloned().collect::<Vec<_>>();
+        result.sort_by(|a, b| {
+            b.queries
+                .cmp(&a.queries)
+                .then_with(|| (a.category, &a.reason).cmp(&(b.category, &b.reason)))
+        });
+
+        result
+    }
+
+    pub fn metrics(&self) -> UnsupportedQueryMetrics {
+        UnsupportedQueryMetrics {
+            queries: self.queries.load(Ordering::Relaxed),
+            reasons: self
+                .reasons
+                .read()
+                .expect("failed to unlock unsupported query stats for reading")
+                .len(),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.reasons
+            .write()
+            .expect("failed to unlock unsupported query stats for writing")
+            .clear();
+        self.queries.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unsupported_query_stats() {
+        let stats = UnsupportedQueryStats::new();
+
+        let unknown_function = CompilationError::internal(
+            "Initial planning error: Error during planning: Invalid function 'ARRAY_AGG'"
+                .to_string(),
+        )
+        .with_meta(Some(HashMap::from([(
+            "sanitizedQuery".to_string(),
+            "SELECT ARRAY_AGG(status) FROM Orders".to_string(),
+        )])));
+        assert_eq!(
+            stats.record(&unknown_function),
+            Some((
+                UnsupportedQueryCategory::UnsupportedFunction,
+                "array_agg".to_string()
+            ))
+        );
+        stats.record(&unknown_function);
+        stats.record(&CompilationError::user(
+            "Unable to parse: ParserError(\"Expected an expression\")".to_string(),
+        ));
+        stats.record(&CompilationError::unsupported(
+            "Unsupported query type: SELECT INTO".to_string(),
+        ));
+        // Not caused by missing support of SQL
+        assert_eq!(
+            stats.record(&CompilationError::fatal("Auth failed".to_string())),
+            None
+        );
+        assert_eq!(
+            stats.record(&CompilationError::user("Unknown cube 'foo'".to_string())),
+            None
+        );
+
+        let snapshot = stats
+            .snapshot()
+            .into_iter()
+            .map(|q| (q.category.as_str(), q.queries, q.sanitized_query))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            snapshot,
+            vec![
+                (
+                    "unsupported_function",
+                    2,
+                    Some("SELECT ARRAY_AGG(status) FROM Orders".to_string())
+                ),
+                ("parse", 1, None),
+                ("statement", 1, None),
+            ]
+        );
+        assert_eq!(
+            stats.metrics(),
+            UnsupportedQueryMetrics {
+                queries: 4,
+                reasons: 3,
+            }
+        );
+
+        stats.reset();
+        assert!(stats.snapshot().is_empty());
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{sql::SessionState, CubeError};
+use crate::{
+    sql::{SessionEndReason, SessionState},
+    CubeError,
+};
 use arc_swap::ArcSwap;
 use log::{Level, LevelFilter};
 use std::{collections::HashMap, fmt::Debug, sync::Arc};
@@ -70,11 +73,58 @@ impl SessionLogger {
         let protocol = self.session_state.protocol.to_string();
         meta_fields.insert("protocol".to_string(), protocol);
         meta_fields.insert("apiType".to_string(), "sql".to_string());
+        if let Some(class) = self.session_state.last_statement_class() {
+            meta_fields.insert("statementClass".to_string(), class.as_str().to_string());
+        }
 
         if !report(target.to_string(), meta_fields.clone(), level) {
             log::log!(target: target, level, "{:?}", meta_fields);
         }
     }
+
+    /// Query which was rejected because of missing SQL support
+    pub fn unsupported_query(&self, category: &str, reason: &str) {
+        let properties = HashMap::from([
+            ("category".to_string(), category.to_string()),
+            ("reason".to_string(), reason.to_string()),
+        ]);
+        self.log("Cube SQL Unsupported Query", properties, Level::Warn);
+    }
+
+    /// Connection of the session was closed, `reason` tells graceful Terminate from resets
+    pub fn session_end(&self, reason: SessionEndReason) {
+        let properties = HashMap::from([
+            (
+                "connectionId".to_string(),
+                self.session_state.connection_id.to_string(),
+            ),
+            ("reason".to_string(), reason.as_str().to_string()),
+        ]);
+        self.log("Cube SQL Session End", properties, Level::Info);
+    }
+
+    /// Panic of the connection handler, the connection is closed after it
+    pub fn connection_panic(
+        &self,
+        reason: &str,
+        statement_fingerprint: Option<String>,
+        backtrace: Option<&str>,
+    ) {
+        let mut properties = HashMap::from([
+            (
+                "connectionId".to_string(),
+                self.session_state.connection_id.to_string(),
+            ),
+            ("error".to_string(), reason.to_string()),
+        ]);
+        if let Some(fingerprint) = statement_fingerprint {
+            properties.insert("statementFingerprint".to_string(), fingerprint);
+        }
+        if let Some(backtrace) = backtrace {
+            properties.insert("backtrace".to_string(), backtrace.to_string());
+        }
+        self.log("Cube SQL Connection Panic", properties, Level::Error);
+    }
 }
 
 impl ContextLogger for SessionLogger {
@@ -1,19 +1,71 @@
 use datafusion::{arrow::datatypes::DataType, logical_plan::Column};
 use itertools::Itertools;
-use std::{collections::HashMap, ops::RangeFrom, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::RangeFrom,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use cubeclient::models::{V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure};
 
 use crate::{sql::ColumnType, transport::SqlGenerator};
 
-use super::V1CubeMetaExt;
+use super::{V1CubeMetaDimensionExt, V1CubeMetaExt};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MetaContext {
     pub cubes: Vec<V1CubeMeta>,
     pub tables: Vec<CubeMetaTable>,
+    pub schemas: Vec<CubeMetaSchema>,
     pub cube_to_data_source: HashMap<String, String>,
     pub data_source_to_sql_generator: HashMap<String, Arc<dyn SqlGenerator + Send + Sync>>,
+    /// Schema of the mounted deployment which serves the cube, see `FederatedTransport`
+    pub cube_to_mount: HashMap<String, String>,
+    /// Refresh of the model has been failing since then, the context is served from the cache
+    pub refresh_failing_since: Option<SystemTime>,
+}
+
+/// What happens with queries when the model couldn't be refreshed for longer than
+/// CUBESQL_META_STALE_THRESHOLD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetaStalePolicy {
+    /// Queries fail, the model can be changed by a migration
+    Reject,
+    /// Queries are planned against the cached model with a warning
+    Notice,
+}
+
+impl FromStr for MetaStalePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(Self::Reject),
+            "notice" => Ok(Self::Notice),
+            x => Err(format!(
+                "Unknown meta stale policy: {}, expected reject or notice",
+                x
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MetaStalePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reject => write!(f, "reject"),
+            Self::Notice => write!(f, "notice"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CubeMetaSchema {
+    pub oid: u32,
+    pub name: String,
 }
 
 #[derive(Debug, Clone)]
@@ -22,6 +74,9 @@ pub struct CubeMetaTable {
     pub record_oid: u32,
     pub array_handler_oid: u32,
     pub name: String,
+    /// None for cubes in the default schema (public)
+    pub schema: Option<String>,
+    pub namespace_oid: u32,
     pub columns: Vec<CubeMetaColumn>,
 }
 
@@ -34,11 +89,52 @@ pub struct CubeMetaColumn {
 }
 
 impl MetaContext {
+    /// All cubes stay in the default schema, see `new_with_folder_schemas`
     pub fn new(
         cubes: Vec<V1CubeMeta>,
         cube_to_data_source: HashMap<String, String>,
         data_source_to_sql_generator: HashMap<String, Arc<dyn SqlGenerator + Send + Sync>>,
     ) -> Self {
+        Self::new_with_folder_schemas(
+            cubes,
+            cube_to_data_source,
+            data_source_to_sql_generator,
+            false,
+        )
+    }
+
+    /// Folders of cubes are mapped to SQL schemas with `folder_schemas` (CUBESQL_FOLDER_SCHEMAS)
+    pub fn new_with_folder_schemas(
+        cubes: Vec<V1CubeMeta>,
+        cube_to_data_source: HashMap<String, String>,
+        data_source_to_sql_generator: HashMap<String, Arc<dyn SqlGenerator + Send + Sync>>,
+        folder_schemas: bool,
+    ) -> Self {
+        let cubes = if folder_schemas {
+            cubes
+        } else {
+            cubes
+                .into_iter()
+                .map(|cube| V1CubeMeta {
+                    folder: None,
+                    ..cube
+                })
+                .collect()
+        };
+
+        // 17000..18000 - schemas (namespaces) declared by cube folders
+        let mut schema_oid_iter: RangeFrom<u32> = 17000..;
+        let schemas: Vec<CubeMetaSchema> = cubes
+            .iter()
+            .filter_map(|cube| cube.sql_schema())
+            .unique()
+            .sorted()
+            .map(|name| CubeMetaSchema {
+                oid: schema_oid_iter.next().unwrap_or(0),
+                name,
+            })
+            .collect();
+
         // 18000 - max system table oid
         let mut oid_iter: RangeFrom<u32> = 18000..;
         let tables: Vec<CubeMetaTable> = cubes
@@ -48,6 +144,13 @@ impl MetaContext {
                 record_oid: oid_iter.next().unwrap_or(0),
                 array_handler_oid: oid_iter.next().unwrap_or(0),
                 name: cube.name.clone(),
+                schema: cube.sql_schema(),
+                namespace_oid: cube
+                    .sql_schema()
+                    .and_then(|name| schemas.iter().find(|s| s.name == name))
+                    .map(|s| s.oid)
+                    // public
+                    .unwrap_or(2200),
                 columns: cube
                     .get_columns()
                     .iter()
@@ -64,11 +167,28 @@ impl MetaContext {
         Self {
             cubes,
             tables,
+            schemas,
             cube_to_data_source,
             data_source_to_sql_generator,
+            cube_to_mount: HashMap::new(),
+            refresh_failing_since: None,
         }
     }
 
+    /// The same context, which is served after failed refreshes
+    pub fn with_refresh_failure(&self, since: SystemTime) -> Self {
+        Self {
+            refresh_failing_since: Some(since),
+            ..self.clone()
+        }
+    }
+
+    /// How long the refresh of the model has been failing
+    pub fn stale_for(&self) -> Option<Duration> {
+        self.refresh_failing_since
+            .map(|since| since.elapsed().unwrap_or_default())
+    }
+
     pub fn sql_generator_by_alias_to_cube(
         &self,
         alias_to_cube: &Vec<(String, String)>,
@@ -96,6 +216,17 @@ impl MetaContext {
         None
     }
 
+    /// Find cube which is declared in the schema (derived from the cube folder)
+    pub fn find_cube_in_schema(&self, schema: &str, name: &str) -> Option<&V1CubeMeta> {
+        self.cubes.iter().find(|cube| {
+            cube.name.eq_ignore_ascii_case(name)
+                && cube
+                    .sql_schema()
+                    .map(|s| s.eq_ignore_ascii_case(schema))
+                    .unwrap_or(false)
+        })
+    }
+
     pub fn find_cube_by_column(
         &self,
         alias_to_cube: &Vec<(String, String)>,
@@ -209,6 +340,23 @@ impl MetaContext {
 
         return false;
     }
+
+    /// Lower-cased names of time dimensions by lower-cased names of cubes
+    pub fn cube_time_dimensions(&self) -> HashMap<String, HashSet<String>> {
+        self.cubes
+            .iter()
+            .map(|cube| {
+                let time_dimensions = cube
+                    .dimensions
+                    .iter()
+                    .filter(|dimension| dimension.is_time())
+                    .map(|dimension| dimension.get_real_name().to_lowercase())
+                    .collect();
+
+                (cube.name.to_lowercase(), time_dimensions)
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -225,6 +373,7 @@ mod tests {
                 measures: vec![],
                 segments: vec![],
                 joins: None,
+                folder: None,
             },
             V1CubeMeta {
                 name: "test2".to_string(),
@@ -233,6 +382,7 @@ mod tests {
                 measures: vec![],
                 segments: vec![],
                 joins: None,
+                folder: None,
             },
         ];
 
@@ -249,4 +399,56 @@ mod tests {
             _ => panic!("wrong name!"),
         }
     }
+
+    #[test]
+    fn test_find_cube_in_schema() {
+        let test_cubes = vec![
+            V1CubeMeta {
+                name: "Orders".to_string(),
+                title: None,
+                dimensions: vec![],
+                measures: vec![],
+                segments: vec![],
+                joins: None,
+                folder: Some("Sales Data".to_string()),
+            },
+            V1CubeMeta {
+                name: "Users".to_string(),
+                title: None,
+                dimensions: vec![],
+                measures: vec![],
+                segments: vec![],
+                joins: None,
+                folder: None,
+            },
+        ];
+
+        // Folders are ignored by default
+        let test_context = MetaContext::new_with_folder_schemas(
+            test_cubes.clone(),
+            HashMap::new(),
+            HashMap::new(),
+            false,
+        );
+        assert!(test_context.schemas.is_empty());
+        assert_eq!(test_context.tables[0].namespace_oid, 2200);
+        assert!(test_context
+            .find_cube_in_schema("sales_data", "orders")
+            .is_none());
+
+        let test_context =
+            MetaContext::new_with_folder_schemas(test_cubes, HashMap::new(), HashMap::new(), true);
+
+        assert_eq!(test_context.schemas.len(), 1);
+        assert_eq!(test_context.schemas[0].name, "sales_data");
+        assert_eq!(test_context.tables[0].namespace_oid, 17000);
+        assert_eq!(test_context.tables[1].namespace_oid, 2200);
+
+        assert!(test_context
+            .find_cube_in_schema("sales_data", "orders")
+            .is_some());
+        assert!(test_context
+            .find_cube_in_schema("sales_data", "users")
+            .is_none());
+    }
 }
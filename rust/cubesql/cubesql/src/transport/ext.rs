@@ -1,5 +1,8 @@
-use cubeclient::models::{V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure, V1CubeMetaSegment};
-use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use cubeclient::models::{
+    V1CubeMeta, V1CubeMetaDimension, V1CubeMetaDimensionGranularity, V1CubeMetaMeasure,
+    V1CubeMetaSegment,
+};
+use datafusion::arrow::datatypes::{DataType, Field, TimeUnit};
 
 use crate::sql::ColumnType;
 
@@ -8,6 +11,8 @@ pub trait V1CubeMetaMeasureExt {
 
     fn is_same_agg_type(&self, expect_agg_type: &str, disable_strict_match: bool) -> bool;
 
+    fn is_count_like(&self) -> bool;
+
     fn get_sql_type(&self) -> ColumnType;
 }
 
@@ -55,7 +60,22 @@ impl V1CubeMetaMeasureExt for V1CubeMetaMeasure {
         }
     }
 
+    /// Counts are always integers, but Cube sends them as JSON numbers or strings and older
+    /// versions of Cube report the aggregation in `type` instead of `aggType`
+    fn is_count_like(&self) -> bool {
+        let agg_type = self.agg_type.as_ref().unwrap_or(&self._type).to_lowercase();
+
+        matches!(
+            agg_type.as_str(),
+            "count" | "countdistinct" | "countdistinctapprox"
+        )
+    }
+
     fn get_sql_type(&self) -> ColumnType {
+        if self.is_count_like() {
+            return ColumnType::Int64;
+        }
+
         let from_type = match &self._type.to_lowercase().as_str() {
             &"number" => ColumnType::Double,
             &"boolean" => ColumnType::Boolean,
@@ -64,9 +84,6 @@ impl V1CubeMetaMeasureExt for V1CubeMetaMeasure {
 
         match &self.agg_type {
             Some(agg_type) => match agg_type.as_str() {
-                "count" => ColumnType::Int64,
-                "countDistinct" => ColumnType::Int64,
-                "countDistinctApprox" => ColumnType::Int64,
                 "sum" => ColumnType::Double,
                 "avg" => ColumnType::Double,
                 "min" => ColumnType::Double,
@@ -99,6 +116,9 @@ pub trait V1CubeMetaDimensionExt {
     fn get_sql_type(&self) -> ColumnType;
 
     fn is_time(&self) -> bool;
+
+    /// Custom granularity declared on the time dimension, name is matched case-insensitively
+    fn find_granularity(&self, name: &str) -> Option<&V1CubeMetaDimensionGranularity>;
 }
 
 impl V1CubeMetaDimensionExt for V1CubeMetaDimension {
@@ -112,13 +132,32 @@ impl V1CubeMetaDimensionExt for V1CubeMetaDimension {
         self._type.to_lowercase().eq("time")
     }
 
+    fn find_granularity(&self, name: &str) -> Option<&V1CubeMetaDimensionGranularity> {
+        self.granularities
+            .as_ref()?
+            .iter()
+            .find(|g| g.name.eq_ignore_ascii_case(name))
+    }
+
     fn sql_can_be_null(&self) -> bool {
         // @todo Possible not null?
         true
     }
 
     fn get_sql_type(&self) -> ColumnType {
-        match self._type.to_lowercase().as_str() {
+        let dimension_type = self._type.to_lowercase();
+        // Array dimensions, e.g. tags, are declared as `string[]`
+        if let Some(item_type) = dimension_type.strip_suffix("[]") {
+            let item_type = match item_type {
+                "number" => DataType::Float64,
+                "boolean" => DataType::Boolean,
+                _ => DataType::Utf8,
+            };
+
+            return ColumnType::List(Box::new(Field::new("item", item_type, true)));
+        }
+
+        match dimension_type.as_str() {
             "time" => ColumnType::Timestamp,
             "number" => ColumnType::Double,
             "boolean" => ColumnType::Boolean,
@@ -180,6 +219,10 @@ pub trait V1CubeMetaExt {
     fn df_data_type(&self, member_name: &str) -> Option<DataType>;
 
     fn member_type(&self, member_name: &str) -> Option<MemberType>;
+
+    /// SQL schema (namespace) for the cube, derived from the folder in the cube meta.
+    /// None means that the cube lives in the default schema
+    fn sql_schema(&self) -> Option<String>;
 }
 
 pub enum MemberType {
@@ -352,13 +395,16 @@ impl V1CubeMetaExt for V1CubeMeta {
             .iter()
             .find(|m| m.name.eq_ignore_ascii_case(member_name))
         {
-            return Some(match dimension._type.as_str() {
-                "number" => MemberType::Number,
-                "boolean" => MemberType::Boolean,
-                "string" => MemberType::String,
-                "time" => MemberType::Time,
-                x => panic!("Unexpected dimension type: {}", x),
-            });
+            let dimension_type = dimension._type.as_str();
+            return Some(
+                match dimension_type.strip_suffix("[]").unwrap_or(dimension_type) {
+                    "number" => MemberType::Number,
+                    "boolean" => MemberType::Boolean,
+                    "string" => MemberType::String,
+                    "time" => MemberType::Time,
+                    x => panic!("Unexpected dimension type: {}", x),
+                },
+            );
         }
 
         if let Some(_) = self
@@ -370,6 +416,26 @@ impl V1CubeMetaExt for V1CubeMeta {
         }
         None
     }
+
+    fn sql_schema(&self) -> Option<String> {
+        let folder = self.folder.as_ref()?.trim();
+        if folder.is_empty() {
+            return None;
+        }
+
+        Some(
+            folder
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        '_'
+                    }
+                })
+                .collect(),
+        )
+    }
 }
 
 pub fn df_data_type_by_column_type(column_type: ColumnType) -> DataType {
@@ -382,3 +448,49 @@ pub fn df_data_type_by_column_type(column_type: ColumnType) -> DataType {
         _ => panic!("Unimplemented support for {:?}", column_type),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measure(_type: &str, agg_type: Option<&str>) -> V1CubeMetaMeasure {
+        V1CubeMetaMeasure {
+            name: "Orders.count".to_string(),
+            title: None,
+            _type: _type.to_string(),
+            agg_type: agg_type.map(|t| t.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_count_like_measures_sql_type() {
+        for (_type, agg_type) in [
+            ("number", Some("count")),
+            ("number", Some("countDistinct")),
+            ("number", Some("countDistinctApprox")),
+            ("number", Some("CountDistinct")),
+            ("count", None),
+            ("countDistinct", None),
+        ] {
+            let measure = measure(_type, agg_type);
+            assert!(measure.is_count_like(), "{:?}", measure);
+            assert_eq!(measure.get_sql_type(), ColumnType::Int64);
+            assert_eq!(
+                df_data_type_by_column_type(measure.get_sql_type()),
+                DataType::Int64
+            );
+        }
+
+        for (_type, agg_type, sql_type) in [
+            ("number", Some("sum"), ColumnType::Double),
+            ("number", Some("number"), ColumnType::Double),
+            ("number", None, ColumnType::Double),
+            ("string", Some("max"), ColumnType::Double),
+            ("boolean", Some("number"), ColumnType::Boolean),
+        ] {
+            let measure = measure(_type, agg_type);
+            assert!(!measure.is_count_like(), "{:?}", measure);
+            assert_eq!(measure.get_sql_type(), sql_type);
+        }
+    }
+}
@@ -0,0 +1,615 @@
+use async_trait::async_trait;
+use cubeclient::models::{V1LoadRequestQuery, V1LoadResponse};
+use datafusion::arrow::datatypes::SchemaRef;
+use itertools::Itertools;
+use log::warn;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::{Arc, RwLock as RwLockSync},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    compile::{
+        engine::df::{scan::MemberField, wrapper::SqlQuery},
+        MetaContext,
+    },
+    sql::{collect_filter_members, AuthContextRef, HttpAuthContext},
+    transport::{
+        CubeStreamReceiver, LoadRequestMeta, SpanId, SqlResponse, SqlView, TransportService,
+    },
+    CubeError,
+};
+
+/// SQL schema which is served by a separate Cube deployment
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaMount {
+    pub schema: String,
+    pub base_path: String,
+    /// Access token for the deployment, None means the one of the session
+    pub access_token: Option<String>,
+}
+
+impl SchemaMount {
+    /// CUBESQL_SCHEMA_MOUNTS is a comma separated list of schema=base_path entries. Access token
+    /// of a mount can be set with CUBESQL_SCHEMA_MOUNT_<SCHEMA>_TOKEN.
+    pub fn from_env() -> Vec<Self> {
+        env::var("CUBESQL_SCHEMA_MOUNTS")
+            .ok()
+            .map(|v| {
+                Self::parse(&v, |schema| {
+                    env::var(format!(
+                        "CUBESQL_SCHEMA_MOUNT_{}_TOKEN",
+                        schema.to_ascii_uppercase()
+                    ))
+                    .ok()
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    fn parse(value: &str, access_token: impl Fn(&str) -> Option<String>) -> Vec<Self> {
+        value
+            .split(',')
+            .filter_map(|entry| {
+                let (schema, base_path) = entry.split_once('=')?;
+                let (schema, base_path) = (schema.trim().to_ascii_lowercase(), base_path.trim());
+                if schema.is_empty() || base_path.is_empty() {
+                    return None;
+                }
+
+                Some(Self {
+                    access_token: access_token(&schema),
+                    schema,
+                    base_path: base_path.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn auth_context(&self, ctx: &AuthContextRef) -> Result<AuthContextRef, CubeError> {
+        let ctx = ctx
+            .as_any()
+            .downcast_ref::<HttpAuthContext>()
+            .ok_or_else(|| {
+                CubeError::user(format!(
+                    "Schema \"{}\" can be queried only with HTTP authentication",
+                    self.schema
+                ))
+            })?;
+
+        Ok(Arc::new(HttpAuthContext {
+            access_token: self
+                .access_token
+                .clone()
+                .unwrap_or_else(|| ctx.access_token.clone()),
+            base_path: self.base_path.clone(),
+        }))
+    }
+}
+
+/// Meta of mounted deployments is refreshed as often as the meta of HTTP transport
+const MOUNT_META_LIFETIME: Duration = Duration::from_secs(5);
+
+/// Cubes of mounted deployments are exposed under their SQL schema (`finance.orders`), the rest
+/// comes from the deployment of the session. Every load is routed to the deployment which owns
+/// its cubes, a single query can't join cubes of different deployments. Owners of cubes are
+/// stored in the meta of the session (`MetaContext::cube_to_mount`), so sessions of different
+/// tenants are routed by their own models.
+/// Cube names must be unique across deployments, duplicates of mounted cubes are skipped.
+#[derive(Debug)]
+pub struct FederatedTransport {
+    inner: Arc<dyn TransportService>,
+    mounts: Vec<SchemaMount>,
+    // Meta of mounted deployments by the index of the mount and the access token
+    mount_metas: RwLockSync<HashMap<(usize, String), (Instant, Arc<MetaContext>)>>,
+}
+
+impl FederatedTransport {
+    pub fn new(inner: Arc<dyn TransportService>, mounts: Vec<SchemaMount>) -> Self {
+        Self {
+            inner,
+            mounts,
+            mount_metas: RwLockSync::new(HashMap::new()),
+        }
+    }
+
+    async fn mount_meta(
+        &self,
+        index: usize,
+        ctx: &AuthContextRef,
+    ) -> Result<Arc<MetaContext>, CubeError> {
+        let mount_ctx = self.mounts[index].auth_context(ctx)?;
+        let access_token = mount_ctx
+            .as_any()
+            .downcast_ref::<HttpAuthContext>()
+            .map(|ctx| ctx.access_token.clone())
+            .unwrap_or_default();
+        let key = (index, access_token);
+
+        if let Some((fetched_at, meta)) = self
+            .mount_metas
+            .read()
+            .expect("failed to unlock mount metas for reading")
+            .get(&key)
+        {
+            if fetched_at.elapsed() < MOUNT_META_LIFETIME {
+                return Ok(meta.clone());
+            }
+        }
+
+        let meta = self.inner.meta(mount_ctx).await?;
+        let mut mount_metas = self
+            .mount_metas
+            .write()
+            .expect("failed to unlock mount metas for writing");
+        // Mounts which use tokens of sessions are cached per token
+        mount_metas.retain(|_, (fetched_at, _)| fetched_at.elapsed() < MOUNT_META_LIFETIME);
+        mount_metas.insert(key, (Instant::now(), meta.clone()));
+
+        Ok(meta)
+    }
+
+    /// Auth context of the deployment which owns cubes of the query
+    async fn route(
+        &self,
+        query: &V1LoadRequestQuery,
+        ctx: AuthContextRef,
+        meta_fields: &LoadRequestMeta,
+    ) -> Result<AuthContextRef, CubeError> {
+        let meta = match meta_fields.meta_context() {
+            Some(meta) => meta.clone(),
+            // Request wasn't planned against a model, e.g. it's sent by embedding code
+            None => self.meta(ctx.clone()).await?,
+        };
+        let mounts = request_cubes(query)
+            .iter()
+            .map(|cube| meta.cube_to_mount.get(cube))
+            .unique()
+            .collect::<Vec<_>>();
+
+        match mounts.as_slice() {
+            [] | [None] => Ok(ctx),
+            [Some(schema)] => self
+                .mounts
+                .iter()
+                .find(|mount| &&mount.schema == schema)
+                .ok_or_else(|| {
+                    CubeError::internal(format!("Schema \"{}\" is not mounted", schema))
+                })?
+                .auth_context(&ctx),
+            _ => Err(CubeError::user(format!(
+                "Query can't combine cubes of different schemas: {}",
+                mounts
+                    .iter()
+                    .map(|schema| schema.map(|s| s.as_str()).unwrap_or("public"))
+                    .join(", ")
+            ))),
+        }
+    }
+}
+
+/// Names of cubes which are referenced by members of the query
+fn request_cubes(query: &V1LoadRequestQuery) -> HashSet<String> {
+    let mut members = HashSet::new();
+    for member in query
+        .measures
+        .iter()
+        .chain(query.dimensions.iter())
+        .chain(query.segments.iter())
+        .flatten()
+    {
+        members.insert(member.clone());
+    }
+    for time_dimension in query.time_dimensions.iter().flatten() {
+        members.insert(time_dimension.dimension.clone());
+    }
+    for filter in query.filters.iter().flatten() {
+        collect_filter_members(filter, &mut members);
+    }
+
+    members
+        .iter()
+        .filter_map(|member| member.split('.').next())
+        .map(|cube| cube.to_string())
+        .collect()
+}
+
+#[async_trait]
+impl TransportService for FederatedTransport {
+    async fn meta(&self, ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
+        let meta = self.inner.meta(ctx.clone()).await?;
+        if ctx.as_any().downcast_ref::<HttpAuthContext>().is_none() {
+            return Ok(meta);
+        }
+
+        let mut cubes = meta.cubes.clone();
+        let mut cube_to_data_source = meta.cube_to_data_source.clone();
+        let mut data_source_to_sql_generator = meta.data_source_to_sql_generator.clone();
+        let mut cube_to_mount = HashMap::new();
+
+        for (index, mount) in self.mounts.iter().enumerate() {
+            let mounted = self.mount_meta(index, &ctx).await?;
+
+            for cube in mounted.cubes.iter() {
+                if cubes.iter().any(|c| c.name == cube.name) {
+                    warn!(
+                        "Cube \"{}\" of schema \"{}\" is skipped: cube with the same name already exists",
+                        cube.name, mount.schema
+                    );
+                    continue;
+                }
+
+                // Data source names are unique only inside of a deployment
+                if let Some(data_source) = mounted.cube_to_data_source.get(&cube.name) {
+                    let mounted_data_source = format!("{}.{}", mount.schema, data_source);
+                    if let Some(generator) = mounted.data_source_to_sql_generator.get(data_source) {
+                        data_source_to_sql_generator
+                            .insert(mounted_data_source.clone(), generator.clone());
+                    }
+                    cube_to_data_source.insert(cube.name.clone(), mounted_data_source);
+                }
+
+                let mut cube = cube.clone();
+                cube.folder = Some(mount.schema.clone());
+                cube_to_mount.insert(cube.name.clone(), mount.schema.clone());
+                cubes.push(cube);
+            }
+        }
+
+        // Folders of the session's cubes were already handled by the inner transport, mounted
+        // cubes are always exposed under the schema of the mount
+        let mut meta = MetaContext::new_with_folder_schemas(
+            cubes,
+            cube_to_data_source,
+            data_source_to_sql_generator,
+            true,
+        );
+        meta.cube_to_mount = cube_to_mount;
+
+        Ok(Arc::new(meta))
+    }
+
+    async fn sql(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+        member_to_alias: Option<HashMap<String, String>>,
+        expression_params: Option<Vec<Option<String>>>,
+    ) -> Result<SqlResponse, CubeError> {
+        let ctx = self.route(&query, ctx, &meta_fields).await?;
+        self.inner
+            .sql(
+                span_id,
+                query,
+                ctx,
+                meta_fields,
+                member_to_alias,
+                expression_params,
+            )
+            .await
+    }
+
+    async fn load(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        sql_query: Option<SqlQuery>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+    ) -> Result<V1LoadResponse, CubeError> {
+        let ctx = self.route(&query, ctx, &meta_fields).await?;
+        self.inner
+            .load(span_id, query, sql_query, ctx, meta_fields)
+            .await
+    }
+
+    async fn load_stream(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        sql_query: Option<SqlQuery>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+        schema: SchemaRef,
+        member_fields: Vec<MemberField>,
+    ) -> Result<CubeStreamReceiver, CubeError> {
+        let ctx = self.route(&query, ctx, &meta_fields).await?;
+        self.inner
+            .load_stream(
+                span_id,
+                query,
+                sql_query,
+                ctx,
+                meta_fields,
+                schema,
+                member_fields,
+            )
+            .await
+    }
+
+    async fn can_switch_user_for_session(
+        &self,
+        ctx: AuthContextRef,
+        to_user: String,
+    ) -> Result<bool, CubeError> {
+        self.inner.can_switch_user_for_session(ctx, to_user).await
+    }
+
+    async fn log_load_state(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+        event: String,
+        properties: serde_json::Value,
+    ) -> Result<(), CubeError> {
+        self.inner
+            .log_load_state(span_id, ctx, meta_fields, event, properties)
+            .await
+    }
+
+    async fn views(&self, ctx: AuthContextRef) -> Result<Vec<SqlView>, CubeError> {
+        self.inner.views(ctx).await
+    }
+
+    async fn save_view(
+        &self,
+        ctx: AuthContextRef,
+        view: SqlView,
+        replace: bool,
+    ) -> Result<(), CubeError> {
+        self.inner.save_view(ctx, view, replace).await
+    }
+
+    async fn drop_view(&self, ctx: AuthContextRef, name: String) -> Result<bool, CubeError> {
+        self.inner.drop_view(ctx, name).await
+    }
+}
+
+crate::di_service!(FederatedTransport, [TransportService]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cubeclient::models::{V1CubeMeta, V1LoadRequestQueryFilterItem};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Every deployment (base path) serves its own cubes
+    #[derive(Debug)]
+    struct TestMetaTransport {
+        cubes: HashMap<String, Vec<String>>,
+        meta_calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TransportService for TestMetaTransport {
+        async fn meta(&self, ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
+            self.meta_calls.fetch_add(1, Ordering::SeqCst);
+            let base_path = &ctx
+                .as_any()
+                .downcast_ref::<HttpAuthContext>()
+                .unwrap()
+                .base_path;
+            let cubes = self.cubes[base_path]
+                .iter()
+                .map(|name| V1CubeMeta {
+                    name: name.clone(),
+                    ..V1CubeMeta::default()
+                })
+                .collect();
+
+            Ok(Arc::new(MetaContext::new(
+                cubes,
+                HashMap::new(),
+                HashMap::new(),
+            )))
+        }
+
+        async fn sql(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _query: V1LoadRequestQuery,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+            _member_to_alias: Option<HashMap<String, String>>,
+            _expression_params: Option<Vec<Option<String>>>,
+        ) -> Result<SqlResponse, CubeError> {
+            panic!("It's a fake transport");
+        }
+
+        async fn load(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _query: V1LoadRequestQuery,
+            _sql_query: Option<SqlQuery>,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+        ) -> Result<V1LoadResponse, CubeError> {
+            panic!("It's a fake transport");
+        }
+
+        async fn load_stream(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _query: V1LoadRequestQuery,
+            _sql_query: Option<SqlQuery>,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+            _schema: SchemaRef,
+            _member_fields: Vec<MemberField>,
+        ) -> Result<CubeStreamReceiver, CubeError> {
+            panic!("It's a fake transport");
+        }
+
+        async fn can_switch_user_for_session(
+            &self,
+            _ctx: AuthContextRef,
+            _to_user: String,
+        ) -> Result<bool, CubeError> {
+            Ok(false)
+        }
+
+        async fn log_load_state(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+            _event: String,
+            _properties: serde_json::Value,
+        ) -> Result<(), CubeError> {
+            Ok(())
+        }
+    }
+
+    fn http_ctx(base_path: &str) -> AuthContextRef {
+        Arc::new(HttpAuthContext {
+            access_token: "token".to_string(),
+            base_path: base_path.to_string(),
+        })
+    }
+
+    fn base_path(ctx: &AuthContextRef) -> String {
+        ctx.as_any()
+            .downcast_ref::<HttpAuthContext>()
+            .unwrap()
+            .base_path
+            .clone()
+    }
+
+    fn measures_query(measures: &[&str]) -> V1LoadRequestQuery {
+        V1LoadRequestQuery {
+            measures: Some(measures.iter().map(|m| m.to_string()).collect()),
+            ..V1LoadRequestQuery::new()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_federated_routing() -> Result<(), CubeError> {
+        let inner = Arc::new(TestMetaTransport {
+            cubes: HashMap::from([
+                ("tenant_a".to_string(), vec!["Orders".to_string()]),
+                (
+                    "tenant_b".to_string(),
+                    vec!["Orders".to_string(), "Invoices".to_string()],
+                ),
+                ("finance".to_string(), vec!["Invoices".to_string()]),
+            ]),
+            meta_calls: AtomicUsize::new(0),
+        });
+        let transport = FederatedTransport::new(
+            inner.clone(),
+            vec![SchemaMount {
+                schema: "finance".to_string(),
+                base_path: "finance".to_string(),
+                access_token: Some("finance_token".to_string()),
+            }],
+        );
+
+        let meta_a = transport.meta(http_ctx("tenant_a")).await?;
+        assert_eq!(
+            meta_a.cube_to_mount,
+            HashMap::from([("Invoices".to_string(), "finance".to_string())])
+        );
+        assert_eq!(
+            meta_a.find_cube_with_name("Invoices").unwrap().folder,
+            Some("finance".to_string())
+        );
+        // Tenant B has own Invoices, the mounted one is skipped
+        let meta_b = transport.meta(http_ctx("tenant_b")).await?;
+        assert!(meta_b.cube_to_mount.is_empty());
+        // Meta of the mount is fetched once for both tenants
+        assert_eq!(inner.meta_calls.load(Ordering::SeqCst), 3);
+
+        let mut meta_fields = LoadRequestMeta::new("postgres".to_string(), "sql".to_string(), None);
+        meta_fields.set_meta_context(Some(meta_a));
+        let ctx = transport
+            .route(
+                &measures_query(&["Invoices.count"]),
+                http_ctx("tenant_a"),
+                &meta_fields,
+            )
+            .await?;
+        assert_eq!(base_path(&ctx), "finance");
+        assert!(transport
+            .route(
+                &measures_query(&["Invoices.count", "Orders.count"]),
+                http_ctx("tenant_a"),
+                &meta_fields,
+            )
+            .await
+            .is_err());
+
+        // Loads of other tenants are routed by their own models
+        meta_fields.set_meta_context(Some(meta_b));
+        let ctx = transport
+            .route(
+                &measures_query(&["Invoices.count"]),
+                http_ctx("tenant_b"),
+                &meta_fields,
+            )
+            .await?;
+        assert_eq!(base_path(&ctx), "tenant_b");
+
+        // Requests without the model of the session are routed by the current model
+        meta_fields.set_meta_context(None);
+        let ctx = transport
+            .route(
+                &measures_query(&["Invoices.count"]),
+                http_ctx("tenant_a"),
+                &meta_fields,
+            )
+            .await?;
+        assert_eq!(base_path(&ctx), "finance");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_mounts_parse() {
+        let mounts = SchemaMount::parse(
+            "Finance = http://a:4000/cubejs-api, marketing=http://b:4000/cubejs-api,,broken",
+            |schema| (schema == "finance").then(|| "token".to_string()),
+        );
+
+        assert_eq!(
+            mounts,
+            vec![
+                SchemaMount {
+                    schema: "finance".to_string(),
+                    base_path: "http://a:4000/cubejs-api".to_string(),
+                    access_token: Some("token".to_string()),
+                },
+                SchemaMount {
+                    schema: "marketing".to_string(),
+                    base_path: "http://b:4000/cubejs-api".to_string(),
+                    access_token: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_request_cubes() {
+        let query = V1LoadRequestQuery {
+            measures: Some(vec!["Orders.count".to_string()]),
+            dimensions: Some(vec!["Orders.status".to_string()]),
+            filters: Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("Users.city".to_string()),
+                operator: Some("equals".to_string()),
+                values: Some(vec!["Berlin".to_string()]),
+                or: None,
+                and: None,
+            }]),
+            ..V1LoadRequestQuery::new()
+        };
+
+        assert_eq!(
+            request_cubes(&query),
+            HashSet::from(["Orders".to_string(), "Users".to_string()])
+        );
+    }
+}
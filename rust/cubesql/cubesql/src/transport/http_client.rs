@@ -0,0 +1,173 @@
+use cubeclient::apis::configuration::Configuration as ClientConfiguration;
+use reqwest::{Certificate, Client, Proxy};
+use std::{fmt, fs, time::Duration};
+
+use crate::{config::env_optparse, CubeError};
+
+const PEM_CERTIFICATE_END: &str = "-----END CERTIFICATE-----";
+
+/// Options of the HTTP client which is used to call Cube API. The client is shared by all
+/// requests of the transport, so connections are pooled.
+#[derive(Clone, PartialEq, Default)]
+pub struct HttpClientOptions {
+    /// Maximum of idle connections which are kept per host, unlimited when None
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Use HTTP/2 without negotiation, Cube API must be reachable by HTTP/2 directly
+    pub http2_prior_knowledge: bool,
+    /// Time (in seconds) to establish a connection
+    pub connect_timeout: Option<u64>,
+    /// Time (in seconds) of the whole request, including reading of the response
+    pub timeout: Option<u64>,
+    /// Outbound proxy for all requests, e.g. http://proxy.internal:3128
+    pub proxy: Option<String>,
+    pub proxy_username: Option<String>,
+    pub proxy_password: Option<String>,
+    /// PEM file with certificates which are trusted in addition to the built-in roots
+    pub ca_bundle_path: Option<String>,
+}
+
+impl HttpClientOptions {
+    /// Read options from CUBESQL_TRANSPORT_HTTP_* variables
+    pub fn from_env() -> Self {
+        fn var<T>(name: &str) -> Option<T>
+        where
+            T: std::str::FromStr,
+            T::Err: fmt::Display,
+        {
+            env_optparse(&format!("CUBESQL_TRANSPORT_HTTP_{}", name))
+        }
+
+        Self {
+            pool_max_idle_per_host: var("POOL_MAX_IDLE_PER_HOST"),
+            http2_prior_knowledge: var("HTTP2_PRIOR_KNOWLEDGE").unwrap_or(false),
+            connect_timeout: var("CONNECT_TIMEOUT"),
+            timeout: var("TIMEOUT"),
+            proxy: var("PROXY"),
+            proxy_username: var("PROXY_USERNAME"),
+            proxy_password: var("PROXY_PASSWORD"),
+            ca_bundle_path: var("CA_BUNDLE"),
+        }
+    }
+
+    pub fn build_client(&self) -> Result<Client, CubeError> {
+        let mut builder = Client::builder();
+
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(Duration::from_secs(connect_timeout));
+        }
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+
+        if let Some(url) = &self.proxy {
+            let proxy = Proxy::all(url.as_str())
+                .map_err(|err| CubeError::user(format!("Invalid HTTP proxy '{}': {}", url, err)))?;
+            let proxy = match &self.proxy_username {
+                Some(username) => {
+                    proxy.basic_auth(username, self.proxy_password.as_deref().unwrap_or_default())
+                }
+                None => proxy,
+            };
+
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(path) = &self.ca_bundle_path {
+            for certificate in Self::load_ca_bundle(path)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        Ok(builder.build()?)
+    }
+
+    pub fn build_client_config(&self) -> Result<ClientConfiguration, CubeError> {
+        Ok(ClientConfiguration::with_client(self.build_client()?))
+    }
+
+    fn load_ca_bundle(path: &str) -> Result<Vec<Certificate>, CubeError> {
+        let bundle = fs::read_to_string(path).map_err(|err| {
+            CubeError::user(format!("Unable to read CA bundle '{}': {}", path, err))
+        })?;
+
+        // Certificate::from_pem reads only the first certificate of the bundle
+        let certificates = bundle
+            .split_inclusive(PEM_CERTIFICATE_END)
+            .filter(|pem| pem.contains(PEM_CERTIFICATE_END))
+            .map(|pem| {
+                Certificate::from_pem(pem.as_bytes()).map_err(|err| {
+                    CubeError::user(format!("Invalid certificate in '{}': {}", path, err))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if certificates.is_empty() {
+            return Err(CubeError::user(format!(
+                "CA bundle '{}' doesn't contain PEM certificates",
+                path
+            )));
+        }
+
+        Ok(certificates)
+    }
+}
+
+// Password of the proxy must not be printed with the config
+impl fmt::Debug for HttpClientOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpClientOptions")
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("http2_prior_knowledge", &self.http2_prior_knowledge)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("timeout", &self.timeout)
+            .field("proxy", &self.proxy)
+            .field("proxy_username", &self.proxy_username)
+            .field(
+                "proxy_password",
+                &self.proxy_password.as_ref().map(|_| "<hidden>"),
+            )
+            .field("ca_bundle_path", &self.ca_bundle_path)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_client_options_build() {
+        let options = HttpClientOptions {
+            pool_max_idle_per_host: Some(8),
+            http2_prior_knowledge: true,
+            connect_timeout: Some(5),
+            timeout: Some(30),
+            proxy: Some("http://proxy.internal:3128".to_string()),
+            proxy_username: Some("cube".to_string()),
+            proxy_password: Some("secret".to_string()),
+            ca_bundle_path: None,
+        };
+        assert!(options.build_client().is_ok());
+        assert!(!format!("{:?}", options).contains("secret"));
+
+        let invalid_proxy = HttpClientOptions {
+            proxy: Some("not a url".to_string()),
+            ..Default::default()
+        };
+        assert!(invalid_proxy.build_client().is_err());
+
+        let missing_ca_bundle = HttpClientOptions {
+            ca_bundle_path: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(missing_ca_bundle.build_client().is_err());
+    }
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+use crate::{config::env_optparse, CubeError};
+
+/// Limits (in bytes) of responses which are read from Cube, a misconfigured query shouldn't pull
+/// a multi-GB JSON document into memory. Responses are not limited when None.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransportLimits {
+    pub max_response_size: Option<usize>,
+}
+
+impl TransportLimits {
+    /// Read limits from CUBESQL_TRANSPORT_MAX_RESPONSE_SIZE
+    pub fn from_env() -> Self {
+        Self {
+            max_response_size: env_optparse("CUBESQL_TRANSPORT_MAX_RESPONSE_SIZE"),
+        }
+    }
+
+    /// Error of an aborted response, `request` identifies the offending request
+    pub fn response_size_error(call: impl fmt::Display, limit: usize, request: &str) -> CubeError {
+        CubeError::user(format!(
+            "Response of {} exceeds the limit of {} bytes (CUBESQL_TRANSPORT_MAX_RESPONSE_SIZE), request: {}",
+            call, limit, request
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::TransportCall;
+
+    #[test]
+    fn test_transport_limits_errors() {
+        assert_eq!(
+            TransportLimits::response_size_error(
+                TransportCall::Load,
+                1024,
+                r#"{"measures":["Orders.count"]}"#
+            )
+            .message,
+            r#"Response of Load request exceeds the limit of 1024 bytes (CUBESQL_TRANSPORT_MAX_RESPONSE_SIZE), request: {"measures":["Orders.count"]}"#
+        );
+    }
+}
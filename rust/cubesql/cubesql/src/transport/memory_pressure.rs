@@ -0,0 +1,274 @@
+use cubeclient::models::V1LoadRequestQuery;
+use log::warn;
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Once,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{compile::CompilationError, CubeError};
+
+const RELIEF_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const RSS_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Load shedding by the resident memory of the process. Above the high watermark, new queries
+/// which can load a lot of rows are rejected and streams stop prefetching, until the memory goes
+/// below the low watermark. The memory is sampled on a background interval, checks only read the
+/// last sample.
+#[derive(Debug)]
+pub struct MemoryPressure {
+    /// 0 means disabled
+    high_watermark: u64,
+    low_watermark: u64,
+    /// Paused streams fail when the memory doesn't go down in time
+    relief_timeout: Duration,
+    rss_reader: fn() -> Option<u64>,
+    // Last sampled memory, 0 until the first sample or if it's unknown
+    rss: Arc<AtomicU64>,
+    sampler: Once,
+    shedding: AtomicBool,
+}
+
+impl MemoryPressure {
+    /// Marks errors of shed queries, they can be retried later
+    pub const META_KEY: &'static str = "memoryPressure";
+
+    /// Low watermark defaults to 90% of the high one
+    pub fn new(high_watermark: u64, low_watermark: u64, relief_timeout: Duration) -> Self {
+        let low_watermark = if low_watermark == 0 || low_watermark > high_watermark {
+            high_watermark / 10 * 9
+        } else {
+            low_watermark
+        };
+        if high_watermark > 0 && !cfg!(target_os = "linux") {
+            warn!(
+                "Memory of the process is known only on Linux, loads are never shed by CUBESQL_MEMORY_HIGH_WATERMARK_MB on this platform"
+            );
+        }
+
+        Self {
+            high_watermark,
+            low_watermark,
+            relief_timeout,
+            rss_reader: process_rss,
+            rss: Arc::new(AtomicU64::new(0)),
+            sampler: Once::new(),
+            shedding: AtomicBool::new(false),
+        }
+    }
+
+    pub fn with_rss_reader(mut self, rss_reader: fn() -> Option<u64>) -> Self {
+        self.rss_reader = rss_reader;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.high_watermark > 0
+    }
+
+    /// Samples the memory of the process
+    pub fn sample(&self) {
+        self.rss
+            .store((self.rss_reader)().unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Sampling starts with the first check inside of the runtime and stops when this is dropped
+    fn start_sampler(&self) {
+        self.sampler.call_once(|| {
+            let handle = match tokio::runtime::Handle::try_current() {
+                Ok(handle) => handle,
+                Err(_) => return,
+            };
+            let rss = Arc::downgrade(&self.rss);
+            let rss_reader = self.rss_reader;
+            handle.spawn(async move {
+                let mut interval = tokio::time::interval(RSS_SAMPLE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    match rss.upgrade() {
+                        Some(rss) => rss.store(rss_reader().unwrap_or(0), Ordering::Relaxed),
+                        None => break,
+                    }
+                }
+            });
+        });
+    }
+
+    /// Updates the state by the last sampled memory of the process
+    pub fn is_shedding(&self) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+
+        self.start_sampler();
+        let rss = match self.rss.load(Ordering::Relaxed) {
+            0 => return false,
+            rss => rss,
+        };
+        let was_shedding = self.shedding.load(Ordering::Relaxed);
+        let shedding = if was_shedding {
+            rss > self.low_watermark
+        } else {
+            rss >= self.high_watermark
+        };
+
+        if shedding != was_shedding {
+            self.shedding.store(shedding, Ordering::Relaxed);
+            if shedding {
+                warn!(
+                    "Memory usage {} MB is above the high watermark {} MB, shedding loads",
+                    rss / 1024 / 1024,
+                    self.high_watermark / 1024 / 1024
+                );
+            } else {
+                warn!(
+                    "Memory usage {} MB is below the low watermark {} MB, shedding is stopped",
+                    rss / 1024 / 1024,
+                    self.low_watermark / 1024 / 1024
+                );
+            }
+        }
+
+        shedding
+    }
+
+    /// Ungrouped requests and requests above the query limit are rejected while loads are shed.
+    /// Requests are checked when queries are planned: portals and cursors planned before the
+    /// pressure are still executed, only their streams are paused.
+    pub fn check_request(
+        &self,
+        request: &V1LoadRequestQuery,
+        query_limit: i32,
+    ) -> Result<(), CompilationError> {
+        let ungrouped = request.ungrouped.unwrap_or(false);
+        let high_limit = request
+            .limit
+            .map(|limit| limit > query_limit)
+            .unwrap_or(false);
+        if !(ungrouped || high_limit) || !self.is_shedding() {
+            return Ok(());
+        }
+
+        Err(CompilationError::user(
+            "Server is under memory pressure, retry the query later or reduce its limit"
+                .to_string(),
+        )
+        .with_meta(Some(Self::error_meta())))
+    }
+
+    /// Waits until loads are not shed anymore. The wait is limited by the relief timeout, the
+    /// error is marked as retryable.
+    pub async fn wait_for_relief(&self) -> Result<(), CubeError> {
+        let deadline = Instant::now() + self.relief_timeout;
+        while self.is_shedding() {
+            if Instant::now() >= deadline {
+                return Err(CubeError::user(format!(
+                    "Server is under memory pressure for more than {} seconds, retry the query later",
+                    self.relief_timeout.as_secs()
+                ))
+                .with_meta(Some(Self::error_meta())));
+            }
+            tokio::time::sleep(RELIEF_POLL_INTERVAL).await;
+        }
+
+        Ok(())
+    }
+
+    fn error_meta() -> HashMap<String, String> {
+        HashMap::from([(Self::META_KEY.to_string(), "true".to_string())])
+    }
+}
+
+/// Resident memory of the process, it's known only on Linux
+fn process_rss() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status
+        .lines()
+        .find(|line| line.starts_with("VmRSS:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static RSS: AtomicU64 = AtomicU64::new(0);
+
+    fn test_rss() -> Option<u64> {
+        Some(RSS.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn test_memory_pressure_shedding() {
+        let pressure =
+            MemoryPressure::new(1000, 800, Duration::from_secs(1)).with_rss_reader(test_rss);
+        let mut ungrouped = V1LoadRequestQuery::new();
+        ungrouped.ungrouped = Some(true);
+        let mut grouped = V1LoadRequestQuery::new();
+        grouped.limit = Some(100);
+        let mut high_limit = V1LoadRequestQuery::new();
+        high_limit.limit = Some(100000);
+
+        RSS.store(900, Ordering::Relaxed);
+        pressure.sample();
+        assert!(!pressure.is_shedding());
+        assert!(pressure.check_request(&ungrouped, 50000).is_ok());
+
+        // Checks read only the last sample
+        RSS.store(1000, Ordering::Relaxed);
+        assert!(!pressure.is_shedding());
+        pressure.sample();
+        assert!(pressure.is_shedding());
+        match pressure.check_request(&ungrouped, 50000) {
+            Err(CompilationError::User(_, Some(meta))) => {
+                assert!(meta.contains_key(MemoryPressure::META_KEY))
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(pressure.check_request(&high_limit, 50000).is_err());
+        assert!(pressure.check_request(&grouped, 50000).is_ok());
+
+        // Shedding continues until the low watermark
+        RSS.store(900, Ordering::Relaxed);
+        pressure.sample();
+        assert!(pressure.is_shedding());
+        RSS.store(800, Ordering::Relaxed);
+        pressure.sample();
+        assert!(!pressure.is_shedding());
+
+        let disabled = MemoryPressure::new(0, 0, Duration::from_secs(1)).with_rss_reader(test_rss);
+        RSS.store(u64::MAX, Ordering::Relaxed);
+        disabled.sample();
+        assert!(!disabled.is_shedding());
+    }
+
+    #[tokio::test]
+    async fn test_memory_pressure_relief_timeout() {
+        fn high_rss() -> Option<u64> {
+            Some(2000)
+        }
+
+        let pressure =
+            MemoryPressure::new(1000, 800, Duration::from_millis(200)).with_rss_reader(high_rss);
+        pressure.sample();
+        let err = pressure.wait_for_relief().await.unwrap_err();
+        assert!(err
+            .meta()
+            .map(|meta| meta.contains_key(MemoryPressure::META_KEY))
+            .unwrap_or(false));
+
+        let disabled =
+            MemoryPressure::new(0, 0, Duration::from_millis(200)).with_rss_reader(high_rss);
+        disabled.sample();
+        assert!(disabled.wait_for_relief().await.is_ok());
+    }
+}
@@ -1,7 +1,27 @@
 pub(crate) mod ctx;
 pub(crate) mod ext;
+pub(crate) mod federated;
+pub(crate) mod http_client;
+pub(crate) mod limits;
+pub(crate) mod memory_pressure;
+pub(crate) mod priority;
+pub(crate) mod recording;
 pub(crate) mod service;
+pub(crate) mod shadow;
+pub(crate) mod snapshot;
+pub(crate) mod timeouts;
+pub(crate) mod views;
 
 pub use ctx::*;
 pub use ext::*;
+pub use federated::*;
+pub use http_client::*;
+pub use limits::*;
+pub use memory_pressure::*;
+pub use priority::*;
+pub use recording::*;
 pub use service::*;
+pub use shadow::*;
+pub use snapshot::*;
+pub use timeouts::*;
+pub use views::*;
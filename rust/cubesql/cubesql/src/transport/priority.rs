@@ -0,0 +1,297 @@
+use log::debug;
+use std::{
+    collections::VecDeque,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+use crate::CubeError;
+
+/// Priority class of the session, it's declared by `SET cubesql_query_priority` or by the
+/// auth context. Interactive loads are scheduled ahead of batch extracts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryPriority {
+    Interactive,
+    Batch,
+}
+
+impl QueryPriority {
+    pub fn all() -> [QueryPriority; 2] {
+        [QueryPriority::Interactive, QueryPriority::Batch]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryPriority::Interactive => "interactive",
+            QueryPriority::Batch => "batch",
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            QueryPriority::Interactive => 0,
+            QueryPriority::Batch => 1,
+        }
+    }
+}
+
+impl Default for QueryPriority {
+    fn default() -> Self {
+        QueryPriority::Interactive
+    }
+}
+
+impl fmt::Display for QueryPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for QueryPriority {
+    type Err = CubeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "interactive" => Ok(QueryPriority::Interactive),
+            "batch" => Ok(QueryPriority::Batch),
+            _ => Err(CubeError::user(format!(
+                "Unknown query priority '{}', expected one of: interactive, batch",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadQueueMetrics {
+    /// Number of loads started with this priority
+    pub loads: u64,
+    /// Number of loads which waited for a free slot
+    pub queued: u64,
+    /// Number of loads which are waiting right now
+    pub waiting: usize,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+}
+
+#[derive(Debug, Default)]
+struct LoadLimiterState {
+    running: usize,
+    queues: [VecDeque<oneshot::Sender<()>>; 2],
+    metrics: [LoadQueueMetrics; 2],
+}
+
+/// Limits the number of concurrent loads sent to Cube. When all slots are busy, loads are
+/// queued and interactive ones are resumed first.
+#[derive(Debug)]
+pub struct LoadLimiter {
+    /// 0 means unlimited
+    max_concurrent: usize,
+    state: Mutex<LoadLimiterState>,
+}
+
+/// Slot of the limiter, it's released on drop
+#[derive(Debug)]
+pub struct LoadPermit {
+    limiter: Arc<LoadLimiter>,
+}
+
+impl Drop for LoadPermit {
+    fn drop(&mut self) {
+        self.limiter.release();
+    }
+}
+
+/// Waiter can be cancelled (e.g. query was killed) after the slot was handed over to it, in
+/// that case the slot must be released
+struct LoadWaiter {
+    receiver: oneshot::Receiver<()>,
+    limiter: Arc<LoadLimiter>,
+    acquired: bool,
+}
+
+impl Drop for LoadWaiter {
+    fn drop(&mut self) {
+        if !self.acquired {
+            self.receiver.close();
+            if self.receiver.try_recv().is_ok() {
+                self.limiter.release();
+            }
+        }
+    }
+}
+
+impl LoadLimiter {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            max_concurrent,
+            state: Mutex::new(LoadLimiterState::default()),
+        }
+    }
+
+    pub async fn acquire(self: &Arc<Self>, priority: QueryPriority) -> LoadPermit {
+        let mut waiter = {
+            let mut state = self.state.lock().expect("failed to lock load limiter");
+            state.metrics[priority.index()].loads += 1;
+
+            if self.max_concurrent == 0 || state.running < self.max_concurrent {
+                state.running += 1;
+
+                return LoadPermit {
+                    limiter: self.clone(),
+                };
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            state.queues[priority.index()].push_back(sender);
+
+            LoadWaiter {
+                receiver,
+                limiter: self.clone(),
+                acquired: false,
+            }
+        };
+
+        let started = Instant::now();
+        // Sender is dropped only by the limiter after the slot was handed over
+        let _ = (&mut waiter.receiver).await;
+        waiter.acquired = true;
+
+        let wait = started.elapsed();
+        debug!(
+            "Load with {} priority waited {:?} for a free slot",
+            priority, wait
+        );
+
+        let mut state = self.state.lock().expect("failed to lock load limiter");
+        let metrics = &mut state.metrics[priority.index()];
+        metrics.queued += 1;
+        metrics.total_wait += wait;
+        if wait > metrics.max_wait {
+            metrics.max_wait = wait;
+        }
+
+        LoadPermit {
+            limiter: self.clone(),
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().expect("failed to lock load limiter");
+
+        for priority in QueryPriority::all().iter() {
+            while let Some(sender) = state.queues[priority.index()].pop_front() {
+                // Slot is handed over, the number of running loads is not changed
+                if sender.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+
+        state.running -= 1;
+    }
+
+    pub fn metrics(&self) -> Vec<(QueryPriority, LoadQueueMetrics)> {
+        let state = self.state.lock().expect("failed to lock load limiter");
+
+        QueryPriority::all()
+            .iter()
+            .map(|priority| {
+                let mut metrics = state.metrics[priority.index()].clone();
+                metrics.waiting = state.queues[priority.index()].len();
+
+                (*priority, metrics)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_load_limiter_priority() {
+        let limiter = Arc::new(LoadLimiter::new(1));
+        let permit = limiter.acquire(QueryPriority::Batch).await;
+
+        let (order_tx, mut order_rx) = mpsc::unbounded_channel();
+        let mut handles = vec![];
+        for priority in [
+            QueryPriority::Batch,
+            QueryPriority::Interactive,
+            QueryPriority::Batch,
+            QueryPriority::Interactive,
+        ] {
+            let limiter = limiter.clone();
+            let order_tx = order_tx.clone();
+            handles.push(tokio::spawn(async move {
+                let _permit = limiter.acquire(priority).await;
+                order_tx.send(priority).unwrap();
+            }));
+            // Keep the order of waiters in queues deterministic
+            tokio::task::yield_now().await;
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let metrics = limiter.metrics();
+        assert_eq!(metrics[0].1.waiting, 2);
+        assert_eq!(metrics[1].1.waiting, 2);
+
+        drop(permit);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        drop(order_tx);
+
+        let mut order = vec![];
+        while let Some(priority) = order_rx.recv().await {
+            order.push(priority);
+        }
+        assert_eq!(
+            order,
+            vec![
+                QueryPriority::Interactive,
+                QueryPriority::Interactive,
+                QueryPriority::Batch,
+                QueryPriority::Batch,
+            ]
+        );
+
+        let metrics = limiter.metrics();
+        assert_eq!(metrics[0].1.loads, 2);
+        assert_eq!(metrics[0].1.queued, 2);
+        assert_eq!(metrics[1].1.loads, 3);
+        assert_eq!(metrics[1].1.queued, 2);
+        assert_eq!(metrics[1].1.waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn test_load_limiter_cancelled_waiter() {
+        let limiter = Arc::new(LoadLimiter::new(1));
+        let permit = limiter.acquire(QueryPriority::Interactive).await;
+
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move {
+                let _permit = limiter.acquire(QueryPriority::Interactive).await;
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        waiter.abort();
+        let _ = waiter.await;
+        drop(permit);
+
+        // Slot of cancelled waiter is not leaked
+        let _permit = tokio::time::timeout(
+            Duration::from_secs(1),
+            limiter.acquire(QueryPriority::Batch),
+        )
+        .await
+        .expect("slot must be free");
+    }
+}
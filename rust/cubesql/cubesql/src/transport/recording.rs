@@ -0,0 +1,490 @@
+use async_trait::async_trait;
+use cubeclient::models::{V1LoadRequestQuery, V1LoadResponse};
+use datafusion::arrow::datatypes::SchemaRef;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::*;
+use sha1_smol::Sha1;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::{
+    compile::{
+        engine::df::{scan::MemberField, wrapper::SqlQuery},
+        MetaContext,
+    },
+    sql::AuthContextRef,
+    transport::{
+        CubeStreamReceiver, LoadRequestMeta, MetaSnapshot, SpanId, SqlResponse, SqlView,
+        TransportService,
+    },
+    CubeError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportRecordMode {
+    /// Requests are sent to Cube, responses are stored
+    Record,
+    /// Stored responses are served without Cube
+    Replay,
+}
+
+impl FromStr for TransportRecordMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            x => Err(format!(
+                "Unknown transport record mode: {}, expected record or replay",
+                x
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TransportRecordMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Record => write!(f, "record"),
+            Self::Replay => write!(f, "replay"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LoadFingerprint<'a> {
+    query: &'a V1LoadRequestQuery,
+    sql_query: Option<&'a SqlQuery>,
+}
+
+#[derive(Debug, Serialize)]
+struct SqlFingerprint<'a> {
+    query: &'a V1LoadRequestQuery,
+    // Sorted to get the same fingerprint for the same mapping
+    member_to_alias: Option<BTreeMap<&'a String, &'a String>>,
+    expression_params: &'a Option<Vec<Option<String>>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Recorded<Q, R> {
+    request: Q,
+    response: R,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedSql {
+    sql: SqlQuery,
+}
+
+/// Stores load requests and responses on disk and serves them later without a Cube deployment,
+/// it allows to reproduce issues in planning and transformation of results. Every request is
+/// stored in a separate file named by the fingerprint of the request, the data model is stored
+/// as a meta snapshot. Streaming loads are not recorded.
+#[derive(Debug)]
+pub struct RecordingTransport {
+    inner: Option<Arc<dyn TransportService>>,
+    path: PathBuf,
+    meta: Option<Arc<MetaContext>>,
+}
+
+impl RecordingTransport {
+    pub fn record(inner: Arc<dyn TransportService>, path: impl AsRef<Path>) -> Self {
+        Self {
+            inner: Some(inner),
+            path: path.as_ref().to_path_buf(),
+            meta: None,
+        }
+    }
+
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self, CubeError> {
+        let path = path.as_ref().to_path_buf();
+        let meta = MetaSnapshot::load(path.join("meta.json"))?.into_context();
+
+        Ok(Self {
+            inner: None,
+            path,
+            meta: Some(Arc::new(meta)),
+        })
+    }
+
+    pub fn mode(&self) -> TransportRecordMode {
+        if self.inner.is_some() {
+            TransportRecordMode::Record
+        } else {
+            TransportRecordMode::Replay
+        }
+    }
+
+    fn fingerprint(value: &impl Serialize) -> Result<String, CubeError> {
+        let mut hasher = Sha1::new();
+        hasher.update(serde_json::to_string(value)?.as_bytes());
+
+        Ok(hasher.digest().to_string())
+    }
+
+    fn file(&self, kind: &str, fingerprint: &str) -> PathBuf {
+        self.path.join(format!("{}-{}.json", kind, fingerprint))
+    }
+
+    fn save<Q: Serialize, R: Serialize>(
+        &self,
+        kind: &str,
+        fingerprint: &str,
+        request: Q,
+        response: R,
+    ) -> Result<(), CubeError> {
+        let path = self.file(kind, fingerprint);
+        fs::create_dir_all(&self.path)
+            .and_then(|_| {
+                fs::write(
+                    &path,
+                    serde_json::to_string_pretty(&Recorded { request, response })?,
+                )
+            })
+            .map_err(|err| {
+                CubeError::internal(format!(
+                    "Unable to record response to {}: {}",
+                    path.display(),
+                    err
+                ))
+            })
+    }
+
+    fn restore<R: DeserializeOwned>(&self, kind: &str, fingerprint: &str) -> Result<R, CubeError> {
+        let path = self.file(kind, fingerprint);
+        let content = fs::read_to_string(&path).map_err(|err| match err.kind() {
+            ErrorKind::NotFound => CubeError::user(format!(
+                "There is no recorded response for {} request {}, it was not issued during recording",
+                kind, fingerprint
+            )),
+            _ => CubeError::internal(format!(
+                "Unable to read recorded response from {}: {}",
+                path.display(),
+                err
+            )),
+        })?;
+
+        let recorded: Recorded<serde_json::Value, R> =
+            serde_json::from_str(&content).map_err(|err| {
+                CubeError::internal(format!(
+                    "Unable to parse recorded response {}: {}",
+                    path.display(),
+                    err
+                ))
+            })?;
+
+        Ok(recorded.response)
+    }
+
+    fn replay_error(&self, operation: &str) -> CubeError {
+        CubeError::user(format!(
+            "{} is not available: cubesql is replaying recorded responses",
+            operation
+        ))
+    }
+}
+
+#[async_trait]
+impl TransportService for RecordingTransport {
+    async fn meta(&self, ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
+        match &self.inner {
+            Some(inner) => {
+                let meta = inner.meta(ctx).await?;
+                fs::create_dir_all(&self.path)?;
+                MetaSnapshot::from_context(&meta).save(self.path.join("meta.json"))?;
+
+                Ok(meta)
+            }
+            None => Ok(self.meta.clone().unwrap()),
+        }
+    }
+
+    async fn sql(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+        member_to_alias: Option<HashMap<String, String>>,
+        expression_params: Option<Vec<Option<String>>>,
+    ) -> Result<SqlResponse, CubeError> {
+        let fingerprint = Self::fingerprint(&SqlFingerprint {
+            query: &query,
+            member_to_alias: member_to_alias.as_ref().map(|m| m.iter().collect()),
+            expression_params: &expression_params,
+        })?;
+
+        match &self.inner {
+            Some(inner) => {
+                let response = inner
+                    .sql(
+                        span_id,
+                        query.clone(),
+                        ctx,
+                        meta_fields,
+                        member_to_alias,
+                        expression_params,
+                    )
+                    .await?;
+                self.save(
+                    "sql",
+                    &fingerprint,
+                    &query,
+                    RecordedSql {
+                        sql: response.sql.clone(),
+                    },
+                )?;
+
+                Ok(response)
+            }
+            None => {
+                let recorded: RecordedSql = self.restore("sql", &fingerprint)?;
+
+                Ok(SqlResponse { sql: recorded.sql })
+            }
+        }
+    }
+
+    async fn load(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        sql_query: Option<SqlQuery>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+    ) -> Result<V1LoadResponse, CubeError> {
+        let fingerprint = Self::fingerprint(&LoadFingerprint {
+            query: &query,
+            sql_query: sql_query.as_ref(),
+        })?;
+
+        match &self.inner {
+            Some(inner) => {
+                let response = inner
+                    .load(span_id, query.clone(), sql_query, ctx, meta_fields)
+                    .await?;
+                self.save("load", &fingerprint, &query, &response)?;
+
+                Ok(response)
+            }
+            None => self.restore("load", &fingerprint),
+        }
+    }
+
+    async fn load_stream(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        sql_query: Option<SqlQuery>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+        schema: SchemaRef,
+        member_fields: Vec<MemberField>,
+    ) -> Result<CubeStreamReceiver, CubeError> {
+        match &self.inner {
+            Some(inner) => {
+                inner
+                    .load_stream(
+                        span_id,
+                        query,
+                        sql_query,
+                        ctx,
+                        meta_fields,
+                        schema,
+                        member_fields,
+                    )
+                    .await
+            }
+            None => Err(self.replay_error("Streaming")),
+        }
+    }
+
+    async fn can_switch_user_for_session(
+        &self,
+        ctx: AuthContextRef,
+        to_user: String,
+    ) -> Result<bool, CubeError> {
+        match &self.inner {
+            Some(inner) => inner.can_switch_user_for_session(ctx, to_user).await,
+            None => Ok(true),
+        }
+    }
+
+    async fn log_load_state(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+        event: String,
+        properties: serde_json::Value,
+    ) -> Result<(), CubeError> {
+        match &self.inner {
+            Some(inner) => {
+                inner
+                    .log_load_state(span_id, ctx, meta_fields, event, properties)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn views(&self, ctx: AuthContextRef) -> Result<Vec<SqlView>, CubeError> {
+        match &self.inner {
+            Some(inner) => inner.views(ctx).await,
+            None => Ok(vec![]),
+        }
+    }
+
+    async fn save_view(
+        &self,
+        ctx: AuthContextRef,
+        view: SqlView,
+        replace: bool,
+    ) -> Result<(), CubeError> {
+        match &self.inner {
+            Some(inner) => inner.save_view(ctx, view, replace).await,
+            None => Err(self.replay_error("CREATE VIEW")),
+        }
+    }
+
+    async fn drop_view(&self, ctx: AuthContextRef, name: String) -> Result<bool, CubeError> {
+        match &self.inner {
+            Some(inner) => inner.drop_view(ctx, name).await,
+            None => Err(self.replay_error("DROP VIEW")),
+        }
+    }
+}
+
+crate::di_service!(RecordingTransport, [TransportService]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compile::test::{get_test_meta, get_test_tenant_ctx},
+        sql::HttpAuthContext,
+    };
+    use cubeclient::models::{V1LoadResult, V1LoadResultAnnotation};
+    use serde_json::json;
+
+    #[derive(Debug)]
+    struct TestLoadTransport {}
+
+    #[async_trait]
+    impl TransportService for TestLoadTransport {
+        async fn meta(&self, _ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
+            Ok(get_test_tenant_ctx())
+        }
+
+        async fn sql(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _query: V1LoadRequestQuery,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+            _member_to_alias: Option<HashMap<String, String>>,
+            _expression_params: Option<Vec<Option<String>>>,
+        ) -> Result<SqlResponse, CubeError> {
+            Ok(SqlResponse {
+                sql: SqlQuery::new("SELECT 1".to_string(), vec![]),
+            })
+        }
+
+        async fn load(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            query: V1LoadRequestQuery,
+            _sql_query: Option<SqlQuery>,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+        ) -> Result<V1LoadResponse, CubeError> {
+            Ok(V1LoadResponse::new(vec![V1LoadResult::new(
+                V1LoadResultAnnotation::new(json!([]), json!([]), json!([]), json!([])),
+                vec![json!({ "limit": query.limit })],
+            )]))
+        }
+
+        async fn load_stream(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _query: V1LoadRequestQuery,
+            _sql_query: Option<SqlQuery>,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+            _schema: SchemaRef,
+            _member_fields: Vec<MemberField>,
+        ) -> Result<CubeStreamReceiver, CubeError> {
+            panic!("It's a fake transport");
+        }
+
+        async fn can_switch_user_for_session(
+            &self,
+            _ctx: AuthContextRef,
+            _to_user: String,
+        ) -> Result<bool, CubeError> {
+            Ok(false)
+        }
+
+        async fn log_load_state(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+            _event: String,
+            _properties: serde_json::Value,
+        ) -> Result<(), CubeError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() -> Result<(), CubeError> {
+        let path = std::env::temp_dir().join(format!("cubesql-recording-{}", uuid::Uuid::new_v4()));
+        let ctx: AuthContextRef = Arc::new(HttpAuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+        });
+        let meta_fields = LoadRequestMeta::new(
+            "postgres".to_string(),
+            "sql".to_string(),
+            Some("SQL API Unit Testing".to_string()),
+        );
+        let query = |limit: i32| V1LoadRequestQuery {
+            measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string()]),
+            limit: Some(limit),
+            ..V1LoadRequestQuery::default()
+        };
+
+        let recording = RecordingTransport::record(Arc::new(TestLoadTransport {}), &path);
+        assert_eq!(recording.mode(), TransportRecordMode::Record);
+        recording.meta(ctx.clone()).await?;
+        let recorded = recording
+            .load(None, query(10), None, ctx.clone(), meta_fields.clone())
+            .await?;
+
+        let replay = RecordingTransport::replay(&path)?;
+        assert_eq!(replay.mode(), TransportRecordMode::Replay);
+        assert_eq!(replay.meta(ctx.clone()).await?.cubes, get_test_meta());
+        assert_eq!(
+            replay
+                .load(None, query(10), None, ctx.clone(), meta_fields.clone())
+                .await?,
+            recorded
+        );
+        assert!(replay
+            .load(None, query(20), None, ctx.clone(), meta_fields)
+            .await
+            .is_err());
+
+        fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+}
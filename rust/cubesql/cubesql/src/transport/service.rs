@@ -1,6 +1,9 @@
 use async_trait::async_trait;
 use cubeclient::{
-    apis::{configuration::Configuration as ClientConfiguration, default_api as cube_api},
+    apis::{
+        configuration::Configuration as ClientConfiguration, default_api as cube_api,
+        Error as CubeApiError,
+    },
     models::{V1LoadRequest, V1LoadRequestQuery, V1LoadResponse},
 };
 
@@ -14,7 +17,7 @@ use serde_derive::*;
 use std::{
     collections::HashMap,
     fmt::Debug,
-    sync::Arc,
+    sync::{Arc, Mutex as MutexSync},
     time::{Duration, SystemTime},
 };
 use tokio::{
@@ -28,6 +31,10 @@ use crate::{
         MetaContext,
     },
     sql::{AuthContextRef, HttpAuthContext},
+    transport::{
+        HttpClientOptions, LocalSqlViewStore, MemoryPressure, QueryPriority, SqlView,
+        TransportCall, TransportLimits, TransportTimeouts,
+    },
     CubeError, RWLockAsync,
 };
 
@@ -41,6 +48,38 @@ pub struct LoadRequestMeta {
     // Optional fields
     #[serde(rename = "changeUser", skip_serializing_if = "Option::is_none")]
     change_user: Option<String>,
+    /// Remaining budget of the statement timeout, drivers pass it to the data source (e.g. as
+    /// `SET statement_timeout` or a job timeout), so abandoned queries are cancelled there too
+    #[serde(rename = "timeoutMs", skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u64>,
+    /// Heavy requests (ungrouped or with a high limit) can be routed to a read replica or a job
+    /// queue by Cube, so they don't slow down interactive queries
+    #[serde(rename = "preferReplica", skip_serializing_if = "std::ops::Not::not")]
+    prefer_replica: bool,
+    // Used only by cubesql for scheduling of loads
+    #[serde(skip)]
+    priority: QueryPriority,
+    #[serde(skip)]
+    warnings: QueryWarnings,
+    #[serde(skip)]
+    shadow: bool,
+    #[serde(skip)]
+    trace: Option<TransportTrace>,
+    /// Sent to Cube with the load, so the request can be found in the query history of Cube
+    #[serde(skip)]
+    request_id: Option<String>,
+    #[serde(skip)]
+    last_cube_request: LastCubeRequest,
+    #[serde(skip)]
+    memory_pressure: Option<Arc<MemoryPressure>>,
+    #[serde(skip)]
+    deadline: Option<Instant>,
+    /// 0 means that requests are not tagged
+    #[serde(skip)]
+    replica_routing_min_limit: i32,
+    /// Model which the request was planned against
+    #[serde(skip)]
+    meta_context: Option<Arc<MetaContext>>,
 }
 
 impl LoadRequestMeta {
@@ -51,6 +90,18 @@ impl LoadRequestMeta {
             api_type,
             app_name,
             change_user: None,
+            priority: QueryPriority::default(),
+            warnings: QueryWarnings::default(),
+            shadow: false,
+            trace: None,
+            request_id: None,
+            last_cube_request: LastCubeRequest::default(),
+            memory_pressure: None,
+            timeout_ms: None,
+            deadline: None,
+            prefer_replica: false,
+            replica_routing_min_limit: 0,
+            meta_context: None,
         }
     }
 
@@ -61,6 +112,225 @@ impl LoadRequestMeta {
     pub fn set_change_user(&mut self, change_user: Option<String>) {
         self.change_user = change_user;
     }
+
+    pub fn priority(&self) -> QueryPriority {
+        self.priority
+    }
+
+    pub fn set_priority(&mut self, priority: QueryPriority) {
+        self.priority = priority;
+    }
+
+    pub fn warnings(&self) -> &QueryWarnings {
+        &self.warnings
+    }
+
+    pub fn set_warnings(&mut self, warnings: QueryWarnings) {
+        self.warnings = warnings;
+    }
+
+    pub fn shadow(&self) -> bool {
+        self.shadow
+    }
+
+    pub fn set_shadow(&mut self, shadow: bool) {
+        self.shadow = shadow;
+    }
+
+    pub fn trace(&self) -> Option<&TransportTrace> {
+        self.trace.as_ref()
+    }
+
+    pub fn set_trace(&mut self, trace: Option<TransportTrace>) {
+        self.trace = trace;
+    }
+
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    pub fn set_request_id(&mut self, request_id: Option<String>) {
+        self.request_id = request_id;
+    }
+
+    pub fn last_cube_request(&self) -> &LastCubeRequest {
+        &self.last_cube_request
+    }
+
+    pub fn set_last_cube_request(&mut self, last_cube_request: LastCubeRequest) {
+        self.last_cube_request = last_cube_request;
+    }
+
+    /// Streams stop prefetching while loads are shed
+    pub fn memory_pressure(&self) -> Option<&Arc<MemoryPressure>> {
+        self.memory_pressure.as_ref()
+    }
+
+    pub fn set_memory_pressure(&mut self, memory_pressure: Option<Arc<MemoryPressure>>) {
+        self.memory_pressure = memory_pressure;
+    }
+
+    /// Transports which combine deployments route requests by the model of the session
+    pub fn meta_context(&self) -> Option<&Arc<MetaContext>> {
+        self.meta_context.as_ref()
+    }
+
+    pub fn set_meta_context(&mut self, meta_context: Option<Arc<MetaContext>>) {
+        self.meta_context = meta_context;
+    }
+
+    pub fn timeout_ms(&self) -> Option<u64> {
+        self.timeout_ms
+    }
+
+    /// Statement timeout starts counting when it's set, i.e. when the statement is planned
+    pub fn set_statement_timeout(&mut self, timeout: Option<Duration>) {
+        self.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.timeout_ms = timeout.map(|timeout| timeout.as_millis() as u64);
+    }
+
+    pub fn prefer_replica(&self) -> bool {
+        self.prefer_replica
+    }
+
+    pub fn set_replica_routing_min_limit(&mut self, min_limit: i32) {
+        self.replica_routing_min_limit = min_limit;
+    }
+
+    /// Tags the request with `preferReplica` when it's ungrouped or its limit reaches the
+    /// configured minimum, see CUBESQL_REPLICA_ROUTING_MIN_LIMIT
+    pub fn tag_heavy_request(&mut self, request: &V1LoadRequestQuery) {
+        if self.replica_routing_min_limit <= 0 {
+            return;
+        }
+
+        let ungrouped = request.ungrouped.unwrap_or(false);
+        let high_limit = request
+            .limit
+            .map(|limit| limit >= self.replica_routing_min_limit)
+            .unwrap_or(true);
+        self.prefer_replica = ungrouped || high_limit;
+    }
+
+    /// Updates the remaining budget before a call to the transport, fails when the statement
+    /// timeout is already exceeded
+    pub fn update_timeout(&mut self) -> Result<(), CubeError> {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return Ok(()),
+        };
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(CubeError::user(
+                "canceling statement due to statement timeout".to_string(),
+            ));
+        }
+
+        self.timeout_ms = Some((remaining.as_millis() as u64).max(1));
+        Ok(())
+    }
+}
+
+/// Warnings raised while the query is executed, they are shared with the session and sent to the
+/// client along with the result
+#[derive(Debug, Clone, Default)]
+pub struct QueryWarnings(Arc<MutexSync<Vec<String>>>);
+
+impl QueryWarnings {
+    pub fn push(&self, message: String) {
+        self.0
+            .lock()
+            .expect("failed to lock query warnings")
+            .push(message);
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.0
+            .lock()
+            .expect("failed to lock query warnings")
+            .clone()
+    }
+
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.0.lock().expect("failed to lock query warnings"))
+    }
+}
+
+/// Request which was sent to Cube, see `SHOW LAST CUBE REQUEST`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeRequest {
+    pub request_id: String,
+    pub method: String,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+/// The last request to Cube of the session, it's shared with the session like warnings
+#[derive(Debug, Clone, Default)]
+pub struct LastCubeRequest(Arc<MutexSync<Option<CubeRequest>>>);
+
+impl LastCubeRequest {
+    pub fn record(&self, request: CubeRequest) {
+        *self.0.lock().expect("failed to lock last cube request") = Some(request);
+    }
+
+    pub fn get(&self) -> Option<CubeRequest> {
+        self.0
+            .lock()
+            .expect("failed to lock last cube request")
+            .clone()
+    }
+}
+
+/// Calls to the transport made by the query which is traced, see `SET cubesql_trace_next_query`
+#[derive(Debug, Clone, Default)]
+pub struct TransportTrace(Arc<MutexSync<Vec<TransportTraceCall>>>);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportTraceCall {
+    pub method: String,
+    pub request: V1LoadRequestQuery,
+    pub sql: Option<String>,
+    pub duration_ms: u64,
+    pub rows: Option<usize>,
+    pub error: Option<String>,
+}
+
+impl TransportTrace {
+    pub fn record(
+        &self,
+        method: &str,
+        request: &V1LoadRequestQuery,
+        sql: Option<&str>,
+        duration: Duration,
+        result: Result<Option<usize>, String>,
+    ) {
+        let (rows, error) = match result {
+            Ok(rows) => (rows, None),
+            Err(error) => (None, Some(error)),
+        };
+
+        self.0
+            .lock()
+            .expect("failed to lock transport trace")
+            .push(TransportTraceCall {
+                method: method.to_string(),
+                request: request.clone(),
+                sql: sql.map(|sql| sql.to_string()),
+                duration_ms: duration.as_millis() as u64,
+                rows,
+                error,
+            });
+    }
+
+    pub fn calls(&self) -> Vec<TransportTraceCall> {
+        self.0
+            .lock()
+            .expect("failed to lock transport trace")
+            .clone()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -155,6 +425,50 @@ pub trait TransportService: Send + Sync + Debug {
         event: String,
         properties: serde_json::Value,
     ) -> Result<(), CubeError>;
+
+    // Runs the wrapped SQL under EXPLAIN of the data source and returns the plan rows
+    async fn explain_source(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        sql_query: SqlQuery,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+    ) -> Result<Vec<serde_json::Value>, CubeError> {
+        let response = self
+            .load(span_id, query, Some(sql_query), ctx, meta_fields)
+            .await?;
+
+        Ok(response
+            .results
+            .into_iter()
+            .flat_map(|result| result.data)
+            .collect())
+    }
+
+    // Views created by CREATE VIEW which are visible to the auth context, transports without a
+    // persistent storage don't have views
+    async fn views(&self, _ctx: AuthContextRef) -> Result<Vec<SqlView>, CubeError> {
+        Ok(vec![])
+    }
+
+    async fn save_view(
+        &self,
+        _ctx: AuthContextRef,
+        _view: SqlView,
+        _replace: bool,
+    ) -> Result<(), CubeError> {
+        Err(CubeError::user(
+            "CREATE VIEW is not supported by the current transport".to_string(),
+        ))
+    }
+
+    // Returns false if the view doesn't exist
+    async fn drop_view(&self, _ctx: AuthContextRef, _name: String) -> Result<bool, CubeError> {
+        Err(CubeError::user(
+            "DROP VIEW is not supported by the current transport".to_string(),
+        ))
+    }
 }
 
 #[async_trait]
@@ -181,26 +495,79 @@ struct MetaCacheBucket {
 pub struct HttpTransport {
     /// We use simple cache to improve DX with standalone mode
     /// because currently we dont persist DF in the SessionState
-    /// and it causes a lot of HTTP requests which slow down BI connections
-    cache: RwLockAsync<Option<MetaCacheBucket>>,
+    /// and it causes a lot of HTTP requests which slow down BI connections.
+    /// Cache is keyed by base path, because databases can point to different deployments
+    cache: RwLockAsync<HashMap<String, MetaCacheBucket>>,
+    views: Option<LocalSqlViewStore>,
+    /// Configuration without credentials, its client (and the pool of connections) is shared
+    client_config: ClientConfiguration,
+    timeouts: TransportTimeouts,
+    folder_schemas: bool,
 }
 
 const CACHE_LIFETIME_DURATION: Duration = Duration::from_secs(5);
 
 impl HttpTransport {
     pub fn new() -> Self {
+        Self::with_client_config(ClientConfiguration::default())
+    }
+
+    pub fn try_new(options: &HttpClientOptions) -> Result<Self, CubeError> {
+        Ok(Self::with_client_config(options.build_client_config()?))
+    }
+
+    fn with_client_config(client_config: ClientConfiguration) -> Self {
         Self {
-            cache: RwLockAsync::new(None),
+            cache: RwLockAsync::new(HashMap::new()),
+            views: None,
+            client_config,
+            timeouts: TransportTimeouts::default(),
+            folder_schemas: false,
         }
     }
 
+    pub fn with_timeouts(mut self, timeouts: TransportTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// The size of a response is checked while its body is read, before parsing
+    pub fn with_limits(mut self, limits: TransportLimits) -> Self {
+        self.client_config.max_response_size = limits.max_response_size;
+        self
+    }
+
+    pub fn with_folder_schemas(mut self, folder_schemas: bool) -> Self {
+        self.folder_schemas = folder_schemas;
+        self
+    }
+
+    /// Standalone mode has no place to store views in Cube, so they are kept in a local file
+    pub fn with_view_store(mut self, store: LocalSqlViewStore) -> Self {
+        self.views = Some(store);
+        self
+    }
+
+    fn view_store(&self) -> Result<&LocalSqlViewStore, CubeError> {
+        self.views.as_ref().ok_or_else(|| {
+            CubeError::user(
+                "Views are not configured, please set CUBESQL_VIEWS_PATH to store them".to_string(),
+            )
+        })
+    }
+
+    /// Views are shared by sessions of the same deployment, as the cache of meta
+    fn view_scope(&self, ctx: AuthContextRef) -> String {
+        self.get_client_config_for_ctx(ctx).base_path
+    }
+
     fn get_client_config_for_ctx(&self, ctx: AuthContextRef) -> ClientConfiguration {
         let http_ctx = ctx
             .as_any()
             .downcast_ref::<HttpAuthContext>()
             .expect("Unable to cast AuthContext to HttpAuthContext");
 
-        let mut cube_config = ClientConfiguration::default();
+        let mut cube_config = self.client_config.clone();
         cube_config.bearer_access_token = Some(http_ctx.access_token.clone());
         cube_config.base_path = http_ctx.base_path.clone();
 
@@ -213,35 +580,71 @@ crate::di_service!(HttpTransport, [TransportService]);
 #[async_trait]
 impl TransportService for HttpTransport {
     async fn meta(&self, ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
+        let client_config = self.get_client_config_for_ctx(ctx);
         {
             let store = self.cache.read().await;
-            if let Some(cache_bucket) = &*store {
+            if let Some(cache_bucket) = store.get(&client_config.base_path) {
                 if cache_bucket.lifetime.elapsed() < CACHE_LIFETIME_DURATION {
                     return Ok(cache_bucket.value.clone());
                 };
             };
         }
 
-        let response = cube_api::meta_v1(&self.get_client_config_for_ctx(ctx), true).await?;
+        let response = self
+            .timeouts
+            .run(TransportCall::Meta, async {
+                cube_api::meta_v1(&client_config, true)
+                    .await
+                    .map_err(|err| match err {
+                        CubeApiError::ResponseTooLarge { limit, request_id } => {
+                            TransportLimits::response_size_error(
+                                TransportCall::Meta,
+                                limit,
+                                &request_id,
+                            )
+                        }
+                        err => err.into(),
+                    })
+            })
+            .await;
 
         let mut store = self.cache.write().await;
-        if let Some(cache_bucket) = &*store {
+        if let Some(cache_bucket) = store.get_mut(&client_config.base_path) {
             if cache_bucket.lifetime.elapsed() < CACHE_LIFETIME_DURATION {
                 return Ok(cache_bucket.value.clone());
             }
+
+            // Previous model is served until the refresh succeeds, it's retried after the
+            // lifetime of the cache
+            if let Err(err) = &response {
+                log::warn!("Unable to refresh meta, serving the cached one: {}", err);
+                let since = cache_bucket
+                    .value
+                    .refresh_failing_since
+                    .unwrap_or_else(SystemTime::now);
+                cache_bucket.value = Arc::new(cache_bucket.value.with_refresh_failure(since));
+                cache_bucket.lifetime = Instant::now();
+
+                return Ok(cache_bucket.value.clone());
+            }
         };
+        let response = response?;
 
         // Not used -- doesn't make sense to implement
-        let value = Arc::new(MetaContext::new(
+        let value = Arc::new(MetaContext::new_with_folder_schemas(
             response.cubes.unwrap_or_else(Vec::new),
             HashMap::new(),
             HashMap::new(),
+            self.folder_schemas,
         ));
 
-        *store = Some(MetaCacheBucket {
-            lifetime: Instant::now(),
-            value: value.clone(),
-        });
+        store.insert(
+            client_config.base_path,
+            MetaCacheBucket {
+                lifetime: Instant::now(),
+                value: value.clone(),
+            },
+        );
 
         Ok(value)
     }
@@ -274,12 +677,33 @@ impl TransportService for HttpTransport {
         }
 
         // TODO: support meta_fields for HTTP
+        let query_json = serde_json::to_string(&query)?;
         let request = V1LoadRequest {
             query: Some(query),
             query_type: Some("multi".to_string()),
         };
-        let response =
-            cube_api::load_v1(&self.get_client_config_for_ctx(ctx), Some(request)).await?;
+        let client_config = self.get_client_config_for_ctx(ctx);
+        let response = self
+            .timeouts
+            .run(TransportCall::Load, async {
+                cube_api::load_v1(
+                    &client_config,
+                    Some(request),
+                    meta.request_id().map(|id| id.to_string()),
+                )
+                .await
+                .map_err(|err| match err {
+                    CubeApiError::ResponseTooLarge { limit, request_id } => {
+                        TransportLimits::response_size_error(
+                            TransportCall::Load,
+                            limit,
+                            &format!("{} {}", request_id, query_json),
+                        )
+                    }
+                    err => err.into(),
+                })
+            })
+            .await?;
 
         Ok(response)
     }
@@ -319,6 +743,28 @@ impl TransportService for HttpTransport {
         );
         Ok(())
     }
+
+    async fn views(&self, ctx: AuthContextRef) -> Result<Vec<SqlView>, CubeError> {
+        match &self.views {
+            Some(store) => store.list(&self.view_scope(ctx)).await,
+            None => Ok(vec![]),
+        }
+    }
+
+    async fn save_view(
+        &self,
+        ctx: AuthContextRef,
+        view: SqlView,
+        replace: bool,
+    ) -> Result<(), CubeError> {
+        self.view_store()?
+            .save(&self.view_scope(ctx), view, replace)
+            .await
+    }
+
+    async fn drop_view(&self, ctx: AuthContextRef, name: String) -> Result<bool, CubeError> {
+        self.view_store()?.drop(&self.view_scope(ctx), &name).await
+    }
 }
 
 #[derive(Debug)]
@@ -408,6 +854,16 @@ impl SqlTemplates {
         )
     }
 
+    /// Data sources which don't use the `EXPLAIN <query>` syntax can override it
+    /// with the `statements/explain` template
+    pub fn explain(&self, sql: String) -> Result<String, CubeError> {
+        if self.templates.contains_key("statements/explain") {
+            self.render_template("statements/explain", context! { sql => sql })
+        } else {
+            Ok(format!("EXPLAIN {}", sql))
+        }
+    }
+
     fn to_template_columns(
         &self,
         aliased_columns: Vec<AliasedColumn>,
@@ -0,0 +1,438 @@
+use async_trait::async_trait;
+use cubeclient::models::{V1LoadRequestQuery, V1LoadResponse};
+use datafusion::arrow::datatypes::SchemaRef;
+use log::{error, info, warn};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::Semaphore;
+
+use crate::{
+    compile::{
+        engine::df::{scan::MemberField, wrapper::SqlQuery},
+        MetaContext,
+    },
+    sql::{AuthContextRef, HttpAuthContext},
+    transport::{
+        CubeStreamReceiver, LoadRequestMeta, SpanId, SqlResponse, SqlView, TransportService,
+    },
+    CubeError,
+};
+
+/// Schema and size of a load response, which are compared between deployments
+#[derive(Debug, Clone, PartialEq)]
+struct ResultSummary {
+    members: Vec<Vec<String>>,
+    rows: Vec<usize>,
+}
+
+impl ResultSummary {
+    fn from_response(response: &V1LoadResponse) -> Self {
+        let members = response
+            .results
+            .iter()
+            .map(|result| {
+                let annotation = &result.annotation;
+                let mut members = [
+                    &annotation.measures,
+                    &annotation.dimensions,
+                    &annotation.time_dimensions,
+                ]
+                .iter()
+                .filter_map(|members| members.as_object())
+                .flat_map(|members| members.keys().cloned())
+                .collect::<Vec<_>>();
+                members.sort();
+                members
+            })
+            .collect();
+        let rows = response.results.iter().map(|r| r.data.len()).collect();
+
+        Self { members, rows }
+    }
+
+    /// Human readable differences, empty when the results match
+    fn diff(&self, shadow: &ResultSummary) -> Vec<String> {
+        let mut differences = vec![];
+        if self.members != shadow.members {
+            differences.push(format!(
+                "members {:?} != {:?}",
+                self.members, shadow.members
+            ));
+        }
+        if self.rows != shadow.rows {
+            differences.push(format!("row counts {:?} != {:?}", self.rows, shadow.rows));
+        }
+
+        differences
+    }
+}
+
+/// Shadow mode sends loads of sessions which enabled it (`SET cubesql_shadow = on`) to a secondary
+/// Cube deployment in the background, and logs differences of result schemas and row counts.
+/// It helps to verify Cube upgrades and data model migrations on real traffic: the client always
+/// gets the result of the primary deployment. Only HTTP auth contexts can be shadowed,
+/// streaming loads are not shadowed. Shadow loads are best effort: they are dropped when
+/// the limit of concurrent shadow loads is reached, so the secondary deployment can't slow down
+/// the primary traffic.
+#[derive(Debug)]
+pub struct ShadowTransport {
+    inner: Arc<dyn TransportService>,
+    shadow: Arc<dyn TransportService>,
+    base_path: String,
+    permits: Arc<Semaphore>,
+}
+
+impl ShadowTransport {
+    pub fn new(
+        inner: Arc<dyn TransportService>,
+        shadow: Arc<dyn TransportService>,
+        base_path: String,
+        max_concurrent_loads: usize,
+    ) -> Self {
+        Self {
+            inner,
+            shadow,
+            base_path,
+            permits: Arc::new(Semaphore::new(max_concurrent_loads)),
+        }
+    }
+
+    fn shadow_context(&self, ctx: &AuthContextRef) -> Option<AuthContextRef> {
+        ctx.as_any()
+            .downcast_ref::<HttpAuthContext>()
+            .map(|ctx| -> AuthContextRef {
+                Arc::new(HttpAuthContext {
+                    access_token: ctx.access_token.clone(),
+                    base_path: self.base_path.clone(),
+                })
+            })
+    }
+
+    /// Returns false if the load is dropped because of the concurrency limit
+    fn spawn_shadow_load(
+        &self,
+        primary: ResultSummary,
+        query: V1LoadRequestQuery,
+        sql_query: Option<SqlQuery>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+    ) -> bool {
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                warn!(
+                    "Shadow load is dropped, too many shadow loads are in progress on {}",
+                    self.base_path
+                );
+                return false;
+            }
+        };
+        let shadow = self.shadow.clone();
+        let base_path = self.base_path.clone();
+
+        tokio::spawn(async move {
+            let _permit = permit;
+            let query_json = serde_json::to_string(&query).unwrap_or_default();
+            match shadow.load(None, query, sql_query, ctx, meta_fields).await {
+                Ok(response) => {
+                    let differences = primary.diff(&ResultSummary::from_response(&response));
+                    if differences.is_empty() {
+                        info!("Shadow load matches {}: {}", base_path, query_json);
+                    } else {
+                        warn!(
+                            "Shadow load differs on {}: {} for {}",
+                            base_path,
+                            differences.join(", "),
+                            query_json
+                        );
+                    }
+                }
+                Err(err) => error!(
+                    "Shadow load failed on {}: {} for {}",
+                    base_path, err, query_json
+                ),
+            }
+        });
+
+        true
+    }
+}
+
+#[async_trait]
+impl TransportService for ShadowTransport {
+    async fn meta(&self, ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
+        self.inner.meta(ctx).await
+    }
+
+    async fn sql(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+        member_to_alias: Option<HashMap<String, String>>,
+        expression_params: Option<Vec<Option<String>>>,
+    ) -> Result<SqlResponse, CubeError> {
+        self.inner
+            .sql(
+                span_id,
+                query,
+                ctx,
+                meta_fields,
+                member_to_alias,
+                expression_params,
+            )
+            .await
+    }
+
+    async fn load(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        sql_query: Option<SqlQuery>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+    ) -> Result<V1LoadResponse, CubeError> {
+        let shadow_ctx = if meta_fields.shadow() {
+            self.shadow_context(&ctx)
+        } else {
+            None
+        };
+        let shadow_request = shadow_ctx.map(|shadow_ctx| {
+            (
+                query.clone(),
+                sql_query.clone(),
+                shadow_ctx,
+                meta_fields.clone(),
+            )
+        });
+
+        let response = self
+            .inner
+            .load(span_id, query, sql_query, ctx, meta_fields)
+            .await?;
+
+        if let Some((query, sql_query, shadow_ctx, meta_fields)) = shadow_request {
+            self.spawn_shadow_load(
+                ResultSummary::from_response(&response),
+                query,
+                sql_query,
+                shadow_ctx,
+                meta_fields,
+            );
+        }
+
+        Ok(response)
+    }
+
+    async fn load_stream(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        query: V1LoadRequestQuery,
+        sql_query: Option<SqlQuery>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+        schema: SchemaRef,
+        member_fields: Vec<MemberField>,
+    ) -> Result<CubeStreamReceiver, CubeError> {
+        self.inner
+            .load_stream(
+                span_id,
+                query,
+                sql_query,
+                ctx,
+                meta_fields,
+                schema,
+                member_fields,
+            )
+            .await
+    }
+
+    async fn can_switch_user_for_session(
+        &self,
+        ctx: AuthContextRef,
+        to_user: String,
+    ) -> Result<bool, CubeError> {
+        self.inner.can_switch_user_for_session(ctx, to_user).await
+    }
+
+    async fn log_load_state(
+        &self,
+        span_id: Option<Arc<SpanId>>,
+        ctx: AuthContextRef,
+        meta_fields: LoadRequestMeta,
+        event: String,
+        properties: serde_json::Value,
+    ) -> Result<(), CubeError> {
+        self.inner
+            .log_load_state(span_id, ctx, meta_fields, event, properties)
+            .await
+    }
+
+    async fn views(&self, ctx: AuthContextRef) -> Result<Vec<SqlView>, CubeError> {
+        self.inner.views(ctx).await
+    }
+
+    async fn save_view(
+        &self,
+        ctx: AuthContextRef,
+        view: SqlView,
+        replace: bool,
+    ) -> Result<(), CubeError> {
+        self.inner.save_view(ctx, view, replace).await
+    }
+
+    async fn drop_view(&self, ctx: AuthContextRef, name: String) -> Result<bool, CubeError> {
+        self.inner.drop_view(ctx, name).await
+    }
+}
+
+crate::di_service!(ShadowTransport, [TransportService]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::test::{get_test_tenant_ctx, get_test_transport};
+    use cubeclient::models::{V1LoadResult, V1LoadResultAnnotation};
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio::sync::Notify;
+
+    fn response(members: serde_json::Value, rows: usize) -> V1LoadResponse {
+        V1LoadResponse::new(vec![V1LoadResult::new(
+            V1LoadResultAnnotation::new(members, json!({}), json!([]), json!({})),
+            vec![json!({}); rows],
+        )])
+    }
+
+    #[test]
+    fn test_shadow_result_diff() {
+        let primary = ResultSummary::from_response(&response(
+            json!({ "Orders.count": {}, "Orders.amount": {} }),
+            10,
+        ));
+
+        let same = ResultSummary::from_response(&response(
+            json!({ "Orders.amount": {}, "Orders.count": {} }),
+            10,
+        ));
+        assert!(primary.diff(&same).is_empty());
+
+        let changed = ResultSummary::from_response(&response(json!({ "Orders.count": {} }), 8));
+        assert_eq!(
+            primary.diff(&changed),
+            vec![
+                r#"members [["Orders.amount", "Orders.count"]] != [["Orders.count"]]"#.to_string(),
+                "row counts [10] != [8]".to_string(),
+            ]
+        );
+    }
+
+    /// Shadow deployment which answers loads only when they are released
+    #[derive(Debug)]
+    struct BlockedLoadTransport {
+        release: Notify,
+    }
+
+    #[async_trait]
+    impl TransportService for BlockedLoadTransport {
+        async fn meta(&self, _ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
+            Ok(get_test_tenant_ctx())
+        }
+
+        async fn sql(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _query: V1LoadRequestQuery,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+            _member_to_alias: Option<HashMap<String, String>>,
+            _expression_params: Option<Vec<Option<String>>>,
+        ) -> Result<SqlResponse, CubeError> {
+            panic!("It's a fake transport");
+        }
+
+        async fn load(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _query: V1LoadRequestQuery,
+            _sql_query: Option<SqlQuery>,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+        ) -> Result<V1LoadResponse, CubeError> {
+            self.release.notified().await;
+
+            Ok(response(json!({}), 0))
+        }
+
+        async fn load_stream(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _query: V1LoadRequestQuery,
+            _sql_query: Option<SqlQuery>,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+            _schema: SchemaRef,
+            _member_fields: Vec<MemberField>,
+        ) -> Result<CubeStreamReceiver, CubeError> {
+            panic!("It's a fake transport");
+        }
+
+        async fn can_switch_user_for_session(
+            &self,
+            _ctx: AuthContextRef,
+            _to_user: String,
+        ) -> Result<bool, CubeError> {
+            Ok(false)
+        }
+
+        async fn log_load_state(
+            &self,
+            _span_id: Option<Arc<SpanId>>,
+            _ctx: AuthContextRef,
+            _meta_fields: LoadRequestMeta,
+            _event: String,
+            _properties: serde_json::Value,
+        ) -> Result<(), CubeError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shadow_loads_limit() {
+        let shadow = Arc::new(BlockedLoadTransport {
+            release: Notify::new(),
+        });
+        let transport = ShadowTransport::new(
+            get_test_transport(),
+            shadow.clone(),
+            "shadow".to_string(),
+            1,
+        );
+        let spawn = || {
+            transport.spawn_shadow_load(
+                ResultSummary::from_response(&response(json!({}), 0)),
+                V1LoadRequestQuery::default(),
+                None,
+                Arc::new(HttpAuthContext {
+                    access_token: "access_token".to_string(),
+                    base_path: "shadow".to_string(),
+                }),
+                LoadRequestMeta::new("postgres".to_string(), "sql".to_string(), None),
+            )
+        };
+
+        assert!(spawn());
+        // Shadow deployment is saturated, the load is dropped instead of being queued
+        assert!(!spawn());
+
+        shadow.release.notify_one();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while transport.permits.available_permits() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("shadow load was not completed");
+        assert!(spawn());
+    }
+}
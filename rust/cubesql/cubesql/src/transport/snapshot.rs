@@ -0,0 +1,215 @@
+use async_trait::async_trait;
+use cubeclient::models::{V1CubeMeta, V1LoadRequestQuery, V1LoadResponse};
+use datafusion::arrow::datatypes::SchemaRef;
+use serde_derive::*;
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+use crate::{
+    compile::{
+        engine::df::{scan::MemberField, wrapper::SqlQuery},
+        MetaContext,
+    },
+    sql::{AuthContextRef, SqlAuthService},
+    transport::{CubeStreamReceiver, LoadRequestMeta, SpanId, SqlResponse, TransportService},
+    CubeError,
+};
+
+/// Frozen copy of the data model fetched from Cube, it's stored as JSON file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetaSnapshot {
+    pub cubes: Vec<V1CubeMeta>,
+    #[serde(rename = "cubeToDataSource", default)]
+    pub cube_to_data_source: HashMap<String, String>,
+}
+
+impl MetaSnapshot {
+    pub fn from_context(ctx: &MetaContext) -> Self {
+        Self {
+            cubes: ctx.cubes.clone(),
+            cube_to_data_source: ctx.cube_to_data_source.clone(),
+        }
+    }
+
+    /// SQL generators are provided by Cube at runtime, they can't be restored from a snapshot.
+    /// Folders were already dropped at export when CUBESQL_FOLDER_SCHEMAS was disabled.
+    pub fn into_context(self) -> MetaContext {
+        MetaContext::new_with_folder_schemas(
+            self.cubes,
+            self.cube_to_data_source,
+            HashMap::new(),
+            true,
+        )
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CubeError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|err| {
+            CubeError::user(format!(
+                "Unable to read meta snapshot from {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+
+        serde_json::from_str(&content).map_err(|err| {
+            CubeError::user(format!(
+                "Unable to parse meta snapshot {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CubeError> {
+        let path = path.as_ref();
+        fs::write(path, serde_json::to_string_pretty(self)?).map_err(|err| {
+            CubeError::user(format!(
+                "Unable to write meta snapshot to {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    }
+}
+
+/// Fetch the data model with credentials of the default user and store it as a snapshot. Only
+/// the model of that user's security context is exported.
+pub async fn export_meta_snapshot(
+    transport: Arc<dyn TransportService>,
+    auth: Arc<dyn SqlAuthService>,
+    path: impl AsRef<Path>,
+) -> Result<MetaSnapshot, CubeError> {
+    let auth_response = auth.authenticate(None, None).await?;
+    let meta = transport.meta(auth_response.context).await?;
+
+    let snapshot = MetaSnapshot::from_context(&meta);
+    snapshot.save(path)?;
+
+    Ok(snapshot)
+}
+
+/// Transport which serves the data model from a snapshot without a live Cube API. Queries
+/// can be planned and inspected (EXPLAIN, metadata introspection), but not executed.
+#[derive(Debug)]
+pub struct MetaSnapshotTransport {
+    meta: Arc<MetaContext>,
+}
+
+impl MetaSnapshotTransport {
+    pub fn new(snapshot: MetaSnapshot) -> Self {
+        Self {
+            meta: Arc::new(snapshot.into_context()),
+        }
+    }
+
+    pub fn try_from_file(path: impl AsRef<Path>) -> Result<Self, CubeError> {
+        Ok(Self::new(MetaSnapshot::load(path)?))
+    }
+
+    fn offline_error(&self, operation: &str) -> CubeError {
+        CubeError::user(format!(
+            "{} is not available: cubesql is running from a meta snapshot without Cube API",
+            operation
+        ))
+    }
+}
+
+#[async_trait]
+impl TransportService for MetaSnapshotTransport {
+    async fn meta(&self, _ctx: AuthContextRef) -> Result<Arc<MetaContext>, CubeError> {
+        Ok(self.meta.clone())
+    }
+
+    async fn sql(
+        &self,
+        _span_id: Option<Arc<SpanId>>,
+        _query: V1LoadRequestQuery,
+        _ctx: AuthContextRef,
+        _meta_fields: LoadRequestMeta,
+        _member_to_alias: Option<HashMap<String, String>>,
+        _expression_params: Option<Vec<Option<String>>>,
+    ) -> Result<SqlResponse, CubeError> {
+        Err(self.offline_error("SQL generation"))
+    }
+
+    async fn load(
+        &self,
+        _span_id: Option<Arc<SpanId>>,
+        _query: V1LoadRequestQuery,
+        _sql_query: Option<SqlQuery>,
+        _ctx: AuthContextRef,
+        _meta_fields: LoadRequestMeta,
+    ) -> Result<V1LoadResponse, CubeError> {
+        Err(self.offline_error("Query execution"))
+    }
+
+    async fn load_stream(
+        &self,
+        _span_id: Option<Arc<SpanId>>,
+        _query: V1LoadRequestQuery,
+        _sql_query: Option<SqlQuery>,
+        _ctx: AuthContextRef,
+        _meta_fields: LoadRequestMeta,
+        _schema: SchemaRef,
+        _member_fields: Vec<MemberField>,
+    ) -> Result<CubeStreamReceiver, CubeError> {
+        Err(self.offline_error("Query execution"))
+    }
+
+    async fn can_switch_user_for_session(
+        &self,
+        _ctx: AuthContextRef,
+        _to_user: String,
+    ) -> Result<bool, CubeError> {
+        // Without Cube API there is nobody to ask, the snapshot holds the model of one user only
+        Ok(false)
+    }
+
+    async fn log_load_state(
+        &self,
+        _span_id: Option<Arc<SpanId>>,
+        _ctx: AuthContextRef,
+        _meta_fields: LoadRequestMeta,
+        _event: String,
+        _properties: serde_json::Value,
+    ) -> Result<(), CubeError> {
+        Ok(())
+    }
+}
+
+crate::di_service!(MetaSnapshotTransport, [TransportService]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::test::get_test_meta;
+
+    #[test]
+    fn test_meta_snapshot_roundtrip() -> Result<(), CubeError> {
+        let ctx = MetaContext::new(get_test_meta(), HashMap::new(), HashMap::new());
+        let snapshot = MetaSnapshot::from_context(&ctx);
+
+        let path = std::env::temp_dir().join(format!(
+            "cubesql-meta-snapshot-{}.json",
+            uuid::Uuid::new_v4()
+        ));
+        snapshot.save(&path)?;
+        let restored = MetaSnapshot::load(&path)?.into_context();
+        fs::remove_file(&path)?;
+
+        assert_eq!(restored.cubes, ctx.cubes);
+        assert_eq!(
+            restored
+                .tables
+                .iter()
+                .map(|t| (t.oid, t.name.clone()))
+                .collect::<Vec<_>>(),
+            ctx.tables
+                .iter()
+                .map(|t| (t.oid, t.name.clone()))
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+}
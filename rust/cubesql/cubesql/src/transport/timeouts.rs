@@ -0,0 +1,132 @@
+use std::{fmt, future::Future, time::Duration};
+
+use crate::{config::env_optparse, CubeError};
+
+/// Kind of a transport call, each kind has its own timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportCall {
+    Meta,
+    Sql,
+    Load,
+    StreamChunk,
+}
+
+impl fmt::Display for TransportCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TransportCall::Meta => "Meta request",
+            TransportCall::Sql => "SQL generation request",
+            TransportCall::Load => "Load request",
+            TransportCall::StreamChunk => "Load stream",
+        })
+    }
+}
+
+/// Timeouts (in seconds) of transport calls, a slow data source shouldn't make introspection of
+/// the schema time out. Calls are not limited when None.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransportTimeouts {
+    pub meta: Option<u64>,
+    pub sql: Option<u64>,
+    pub load: Option<u64>,
+    /// Inactivity between chunks of a stream, not the duration of the whole stream
+    pub stream_chunk: Option<u64>,
+}
+
+impl TransportTimeouts {
+    /// Read timeouts from CUBESQL_TRANSPORT_*_TIMEOUT variables
+    pub fn from_env() -> Self {
+        fn var(name: &str) -> Option<u64> {
+            env_optparse(&format!("CUBESQL_TRANSPORT_{}_TIMEOUT", name))
+        }
+
+        Self {
+            meta: var("META"),
+            sql: var("SQL"),
+            load: var("LOAD"),
+            stream_chunk: var("STREAM_CHUNK"),
+        }
+    }
+
+    pub fn get(&self, call: TransportCall) -> Option<Duration> {
+        let secs = match call {
+            TransportCall::Meta => self.meta,
+            TransportCall::Sql => self.sql,
+            TransportCall::Load => self.load,
+            TransportCall::StreamChunk => self.stream_chunk,
+        };
+
+        secs.map(Duration::from_secs)
+    }
+
+    /// Runs the call with its timeout, an elapsed timeout is reported with the kind of the call
+    pub async fn run<T, F>(&self, call: TransportCall, future: F) -> Result<T, CubeError>
+    where
+        F: Future<Output = Result<T, CubeError>>,
+    {
+        match self.get(call) {
+            Some(timeout) => tokio::time::timeout(timeout, future)
+                .await
+                .map_err(|_| Self::timeout_error(call, timeout))?,
+            None => future.await,
+        }
+    }
+
+    pub fn timeout_error(call: TransportCall, timeout: Duration) -> CubeError {
+        let message = match call {
+            TransportCall::StreamChunk => format!(
+                "{} received no data for {} seconds",
+                call,
+                timeout.as_secs()
+            ),
+            _ => format!("{} timed out after {} seconds", call, timeout.as_secs()),
+        };
+
+        CubeError::user(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_transport_timeouts() {
+        let timeouts = TransportTimeouts {
+            meta: Some(1),
+            load: Some(0),
+            ..Default::default()
+        };
+
+        let meta = timeouts
+            .run(TransportCall::Meta, async { Ok::<_, CubeError>(1) })
+            .await;
+        assert_eq!(meta.unwrap(), 1);
+
+        let load = timeouts
+            .run(TransportCall::Load, async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok::<_, CubeError>(1)
+            })
+            .await;
+        assert_eq!(
+            load.unwrap_err().message,
+            "Load request timed out after 0 seconds"
+        );
+
+        // Unlimited
+        let sql = timeouts
+            .run(TransportCall::Sql, async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok::<_, CubeError>(1)
+            })
+            .await;
+        assert!(sql.is_ok());
+
+        assert_eq!(
+            TransportTimeouts::timeout_error(TransportCall::StreamChunk, Duration::from_secs(5))
+                .message,
+            "Load stream received no data for 5 seconds"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use serde_derive::*;
+use sqlparser::ast;
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock as RwLockSync},
+};
+use tokio::{fs, sync::Mutex};
+
+use crate::CubeError;
+
+/// Named SQL query created by `CREATE VIEW`, it's shared between sessions of the same deployment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SqlView {
+    pub name: String,
+    pub sql: String,
+}
+
+impl SqlView {
+    pub fn new(name: String, sql: String) -> Self {
+        Self { name, sql }
+    }
+}
+
+// Scope -> views
+type ScopedViews = HashMap<String, Vec<SqlView>>;
+
+/// Persists views as JSON file, it's used by transports which can't store views in Cube.
+/// Views are scoped, e.g. by the deployment of the auth context, sessions of one scope don't see
+/// views of another one. The file is read once and kept in memory, so it must be owned by a single
+/// instance of SQL API: views created by other instances sharing the file are not picked up
+/// and can be overwritten.
+#[derive(Debug)]
+pub struct LocalSqlViewStore {
+    path: PathBuf,
+    // Loaded on the first access, writes are serialized by the lock
+    views: Mutex<Option<ScopedViews>>,
+}
+
+impl LocalSqlViewStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            views: Mutex::new(None),
+        }
+    }
+
+    async fn read(&self) -> Result<ScopedViews, CubeError> {
+        let content = match fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(err) => {
+                return Err(CubeError::internal(format!(
+                    "Unable to read views from {}: {}",
+                    self.path.display(),
+                    err
+                )))
+            }
+        };
+
+        serde_json::from_str(&content).map_err(|err| {
+            CubeError::internal(format!(
+                "Unable to parse views {}: {}",
+                self.path.display(),
+                err
+            ))
+        })
+    }
+
+    async fn write(&self, views: &ScopedViews) -> Result<(), CubeError> {
+        // Write to temporary file first, a crash must not leave partially written file
+        let tmp_path = self.path.with_extension("tmp");
+        let content = serde_json::to_string_pretty(views)?;
+        let result = match fs::write(&tmp_path, content).await {
+            Ok(()) => fs::rename(&tmp_path, &self.path).await,
+            Err(err) => Err(err),
+        };
+
+        result.map_err(|err| {
+            CubeError::internal(format!(
+                "Unable to write views to {}: {}",
+                self.path.display(),
+                err
+            ))
+        })
+    }
+
+    /// Applies the change to views of the scope, the file is written if they were changed
+    async fn update(
+        &self,
+        scope: &str,
+        f: impl FnOnce(&mut Vec<SqlView>) -> Result<bool, CubeError>,
+    ) -> Result<bool, CubeError> {
+        let mut guard = self.views.lock().await;
+        let mut views = match guard.as_ref() {
+            Some(views) => views.clone(),
+            None => self.read().await?,
+        };
+
+        let scope_views = views.entry(scope.to_string()).or_default();
+        let changed = f(scope_views)?;
+        if scope_views.is_empty() {
+            views.remove(scope);
+        }
+        // Memory follows the file, nothing is changed when the write fails
+        if changed {
+            self.write(&views).await?;
+        }
+        *guard = Some(views);
+
+        Ok(changed)
+    }
+
+    pub async fn list(&self, scope: &str) -> Result<Vec<SqlView>, CubeError> {
+        let mut guard = self.views.lock().await;
+        if guard.is_none() {
+            *guard = Some(self.read().await?);
+        }
+
+        Ok(guard
+            .as_ref()
+            .and_then(|views| views.get(scope))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    pub async fn save(&self, scope: &str, view: SqlView, replace: bool) -> Result<(), CubeError> {
+        self.update(scope, |views| {
+            match views
+                .iter_mut()
+                .find(|v| v.name.eq_ignore_ascii_case(&view.name))
+            {
+                Some(_) if !replace => {
+                    return Err(CubeError::user(format!(
+                        "View '{}' already exists",
+                        view.name
+                    )))
+                }
+                Some(existing) => *existing = view,
+                None => views.push(view),
+            }
+
+            Ok(true)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns false if there is no view with such name
+    pub async fn drop(&self, scope: &str, name: &str) -> Result<bool, CubeError> {
+        self.update(scope, |views| {
+            let len = views.len();
+            views.retain(|v| !v.name.eq_ignore_ascii_case(name));
+
+            Ok(views.len() != len)
+        })
+        .await
+    }
+}
+
+/// Parsed queries of views by their lower-cased names. Views are parsed again only when they
+/// are changed, i.e. after CREATE VIEW or DROP VIEW.
+#[derive(Debug)]
+pub struct ParsedSqlViews {
+    // Cache key (e.g. protocol of the dialect) -> views and their parsed queries
+    cache: RwLockSync<HashMap<String, (Vec<SqlView>, Arc<HashMap<String, ast::Query>>)>>,
+}
+
+impl ParsedSqlViews {
+    pub fn new() -> Self {
+        Self {
+            cache: RwLockSync::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_or_parse(
+        &self,
+        key: &str,
+        views: Vec<SqlView>,
+        parse: impl Fn(&SqlView) -> Option<ast::Query>,
+    ) -> Arc<HashMap<String, ast::Query>> {
+        if let Some((cached_views, parsed)) = self.cache.read().unwrap().get(key) {
+            if cached_views == &views {
+                return parsed.clone();
+            }
+        }
+
+        let parsed = Arc::new(
+            views
+                .iter()
+                .filter_map(|view| Some((view.name.to_lowercase(), parse(view)?)))
+                .collect::<HashMap<_, _>>(),
+        );
+        self.cache
+            .write()
+            .unwrap()
+            .insert(key.to_string(), (views, parsed.clone()));
+
+        parsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_local_view_store() -> Result<(), CubeError> {
+        let path =
+            std::env::temp_dir().join(format!("cubesql-views-{}.json", uuid::Uuid::new_v4()));
+        let store = LocalSqlViewStore::new(&path);
+
+        assert_eq!(store.list("tenant_a").await?, vec![]);
+
+        let view = SqlView::new(
+            "orders_by_status".to_string(),
+            "SELECT status, MEASURE(count) FROM Orders GROUP BY 1".to_string(),
+        );
+        store.save("tenant_a", view.clone(), false).await?;
+        assert!(store.save("tenant_a", view.clone(), false).await.is_err());
+        // Views of another scope are not visible
+        assert_eq!(store.list("tenant_b").await?, vec![]);
+        store.save("tenant_b", view.clone(), false).await?;
+
+        let replaced = SqlView::new(
+            "Orders_By_Status".to_string(),
+            "SELECT status FROM Orders GROUP BY 1".to_string(),
+        );
+        store.save("tenant_a", replaced.clone(), true).await?;
+        // Views are persisted for the next start
+        assert_eq!(
+            LocalSqlViewStore::new(&path).list("tenant_a").await?,
+            vec![replaced]
+        );
+
+        assert!(store.drop("tenant_a", "orders_by_status").await?);
+        assert!(!store.drop("tenant_a", "orders_by_status").await?);
+        assert_eq!(store.list("tenant_a").await?, vec![]);
+        assert_eq!(store.list("tenant_b").await?, vec![view]);
+
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parsed_views() {
+        let parsed_views = ParsedSqlViews::new();
+        let parses = AtomicUsize::new(0);
+        let parse = |view: &SqlView| {
+            parses.fetch_add(1, Ordering::SeqCst);
+            match Parser::parse_sql(&PostgreSqlDialect {}, &view.sql)
+                .ok()?
+                .pop()?
+            {
+                ast::Statement::Query(query) => Some(*query),
+                _ => None,
+            }
+        };
+
+        let views = vec![
+            SqlView::new("Shipped".to_string(), "SELECT 1".to_string()),
+            SqlView::new("broken".to_string(), "SELECT FROM FROM".to_string()),
+        ];
+        let parsed = parsed_views.get_or_parse("postgres", views.clone(), parse);
+        assert_eq!(parsed.keys().collect::<Vec<_>>(), vec!["shipped"]);
+        parsed_views.get_or_parse("postgres", views.clone(), parse);
+        assert_eq!(parses.load(Ordering::SeqCst), 2);
+
+        // Changed views are parsed again
+        let parsed = parsed_views.get_or_parse("postgres", views[..1].to_vec(), parse);
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parses.load(Ordering::SeqCst), 3);
+    }
+}
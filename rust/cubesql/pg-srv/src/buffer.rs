@@ -5,6 +5,7 @@ use std::{
     convert::TryFrom,
     io::{Cursor, Error, ErrorKind},
     marker::Send,
+    time::Duration,
 };
 
 use crate::{
@@ -50,19 +51,62 @@ pub async fn read_message<Reader: AsyncReadExt + Unpin + Send>(
     Ok(message)
 }
 
+/// Reads the response to AuthenticationGSS/AuthenticationGSSContinue
+pub async fn read_gss_response<Reader: AsyncReadExt + Unpin + Send>(
+    reader: &mut Reader,
+) -> Result<protocol::GSSResponse, ProtocolError> {
+    let message_tag = reader.read_u8().await?;
+    let cursor = read_contents(reader, message_tag).await?;
+
+    match message_tag {
+        b'p' => protocol::GSSResponse::deserialize(cursor).await,
+        identifier => Err(ErrorResponse::error(
+            ErrorCode::ProtocolViolation,
+            format!("Expected GSS response, got message type {:X?}", identifier),
+        )
+        .into()),
+    }
+}
+
+/// Maximum size of a frontend message, the same limit is used by PostgreSQL (MaxAllocSize)
+pub const MAX_MESSAGE_LENGTH: u32 = 0x3fffffff;
+/// Maximum size of a startup packet, the same limit is used by PostgreSQL
+pub const MAX_STARTUP_PACKET_LENGTH: u32 = 10000;
+/// Initial capacity of the buffer for a message body
+const DEFAULT_READ_CAPACITY: usize = 8 * 1024;
+
 pub async fn read_contents<Reader: AsyncReadExt + Unpin>(
     reader: &mut Reader,
     message_tag: u8,
+) -> Result<Cursor<Vec<u8>>, Error> {
+    read_contents_with_limit(reader, message_tag, MAX_MESSAGE_LENGTH).await
+}
+
+/// Same as read_contents, but message length (including the length itself) must not exceed max_length.
+pub async fn read_contents_with_limit<Reader: AsyncReadExt + Unpin>(
+    reader: &mut Reader,
+    message_tag: u8,
+    max_length: u32,
 ) -> Result<Cursor<Vec<u8>>, Error> {
     // protocol defines length for all types of messages
     let length = reader.read_u32().await?;
     if length < 4 {
         return Err(Error::new(
-            ErrorKind::Other,
+            ErrorKind::InvalidData,
             "Unexpectedly small (<0) message size",
         ));
     }
 
+    if length > max_length {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Invalid message length {} for message {:X?}, max allowed: {}",
+                length, message_tag, max_length
+            ),
+        ));
+    }
+
     trace!(
         "[pg] Receive package {:X?} with length {}",
         message_tag,
@@ -76,14 +120,22 @@ pub async fn read_contents<Reader: AsyncReadExt + Unpin>(
         )
     })?;
 
-    let buffer = if length == 0 {
-        vec![0; 0]
-    } else {
-        let mut buffer = vec![0; length];
-        reader.read_exact(&mut buffer).await?;
+    // Length is declared by the client, buffer grows with the received data instead of
+    // allocating the declared size upfront
+    let mut buffer = Vec::with_capacity(length.min(DEFAULT_READ_CAPACITY));
+    reader.take(length as u64).read_to_end(&mut buffer).await?;
 
-        buffer
-    };
+    if buffer.len() != length {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!(
+                "Unexpected end of message {:X?}, expected {} bytes, received {}",
+                message_tag,
+                length,
+                buffer.len()
+            ),
+        ));
+    }
 
     let cursor = Cursor::new(buffer);
 
@@ -199,7 +251,184 @@ pub async fn write_message<Writer: AsyncWriteExt + Unpin, Message: Serialize>(
     Ok(())
 }
 
+/// Options which control how outgoing messages are delivered to the client.
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// Buffered messages are written to the socket when buffer size reaches this limit
+    pub high_water_mark: usize,
+    /// Maximum size of the single write to the socket
+    pub chunk_size: usize,
+    /// Maximum time for the client to accept one chunk, `None` disables the limit
+    pub timeout: Option<Duration>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            high_water_mark: 256 * 1024,
+            chunk_size: 64 * 1024,
+            timeout: None,
+        }
+    }
+}
+
+/// Bounded buffer for outgoing messages.
+///
+/// Messages are serialized into the buffer and written to the socket by chunks. If the client
+/// doesn't read from the socket (TCP zero window), every write is limited by the timeout, which
+/// allows to terminate such connections instead of holding memory and upstream streams.
+#[derive(Debug)]
+pub struct WriteBuffer {
+    buffer: BytesMut,
+    options: WriteOptions,
+}
+
+impl WriteBuffer {
+    pub fn new(options: WriteOptions) -> Self {
+        Self {
+            buffer: BytesMut::with_capacity(options.chunk_size),
+            options,
+        }
+    }
+
+    /// Append F message with frame's headers to the buffer.
+    pub fn push<Message: Serialize>(&mut self, message: Message) -> Result<(), ProtocolError> {
+        message_serialize(message, &mut self.buffer)
+    }
+
+    /// Same as push, but it doesn’t append header for frame (code + size).
+    pub fn push_direct<Message: Serialize>(&mut self, message: Message) {
+        if let Some(buffer) = message.serialize() {
+            self.buffer.extend_from_slice(&buffer);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Drop buffered messages which were not written yet, e.g. a partially written response.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Buffer reached the limit and should be flushed before accepting new messages.
+    pub fn is_full(&self) -> bool {
+        self.buffer.len() >= self.options.high_water_mark
+    }
+
+    /// Write all buffered messages to the writer.
+    pub async fn flush<Writer: AsyncWriteExt + Unpin>(
+        &mut self,
+        writer: &mut Writer,
+    ) -> Result<(), ProtocolError> {
+        if !self.buffer.is_empty() {
+            let chunk_size = self.options.chunk_size.max(1);
+            for chunk in self.buffer.chunks(chunk_size) {
+                with_write_timeout(self.options.timeout, writer.write_all(chunk)).await?;
+            }
+
+            self.buffer.clear();
+            // Don't keep memory allocated by a huge batch for the whole connection lifetime
+            if self.buffer.capacity() > self.options.high_water_mark * 2 {
+                self.buffer = BytesMut::with_capacity(self.options.chunk_size);
+            }
+        }
+
+        with_write_timeout(self.options.timeout, writer.flush()).await?;
+
+        Ok(())
+    }
+}
+
+async fn with_write_timeout<F: std::future::Future<Output = Result<(), Error>>>(
+    timeout: Option<Duration>,
+    future: F,
+) -> Result<(), ProtocolError> {
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, future).await {
+            Ok(res) => Ok(res?),
+            Err(_) => Err(Error::new(
+                ErrorKind::TimedOut,
+                format!(
+                    "Client didn't accept data within write timeout ({}s)",
+                    timeout.as_secs_f64()
+                ),
+            )
+            .into()),
+        },
+        None => Ok(future.await?),
+    }
+}
+
 pub fn write_string(buffer: &mut Vec<u8>, string: &str) {
     buffer.extend_from_slice(string.as_bytes());
     buffer.push(0);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{CommandComplete, ReadyForQuery, TransactionStatus};
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn test_write_buffer_chunks() -> Result<(), ProtocolError> {
+        let mut buffer = WriteBuffer::new(WriteOptions {
+            high_water_mark: 16,
+            chunk_size: 4,
+            timeout: Some(Duration::from_secs(1)),
+        });
+
+        buffer.push(CommandComplete::Plain("SELECT 100".to_string()))?;
+        assert!(buffer.is_full());
+        buffer.push(ReadyForQuery::new(TransactionStatus::Idle))?;
+
+        let mut expected = Cursor::new(vec![]);
+        write_messages(
+            &mut expected,
+            vec![CommandComplete::Plain("SELECT 100".to_string())],
+        )
+        .await?;
+        write_message(&mut expected, ReadyForQuery::new(TransactionStatus::Idle)).await?;
+
+        let mut cursor = Cursor::new(vec![]);
+        buffer.flush(&mut cursor).await?;
+
+        assert!(buffer.is_empty());
+        assert_eq!(cursor.into_inner(), expected.into_inner());
+
+        buffer.push(CommandComplete::Plain("SELECT 100".to_string()))?;
+        buffer.clear();
+        let mut cursor = Cursor::new(vec![]);
+        buffer.flush(&mut cursor).await?;
+        assert!(cursor.into_inner().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_buffer_timeout() -> Result<(), ProtocolError> {
+        let (mut client, _server) = tokio::io::duplex(8);
+        let mut buffer = WriteBuffer::new(WriteOptions {
+            high_water_mark: 16,
+            chunk_size: 4,
+            timeout: Some(Duration::from_millis(50)),
+        });
+
+        buffer.push(CommandComplete::Plain("SELECT 100".to_string()))?;
+
+        match buffer.flush(&mut client).await {
+            Err(ProtocolError::IO { source, .. }) => {
+                assert_eq!(source.kind(), ErrorKind::TimedOut)
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        Ok(())
+    }
+}
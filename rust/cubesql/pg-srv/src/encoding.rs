@@ -46,6 +46,26 @@ impl ToProtocolValue for String {
     }
 }
 
+/// bytea, text format is hex (\x...), the default output of bytea_output
+impl ToProtocolValue for Vec<u8> {
+    fn to_text(&self, buf: &mut BytesMut) -> Result<(), ProtocolError> {
+        let mut hex = String::with_capacity(2 + self.len() * 2);
+        hex.push_str("\\x");
+        for byte in self.iter() {
+            hex.push_str(&format!("{:02x}", byte));
+        }
+
+        hex.to_text(buf)
+    }
+
+    fn to_binary(&self, buf: &mut BytesMut) -> Result<(), ProtocolError> {
+        buf.put_i32(self.len() as i32);
+        buf.extend_from_slice(self);
+
+        Ok(())
+    }
+}
+
 impl ToProtocolValue for bool {
     fn to_text(&self, buf: &mut BytesMut) -> Result<(), ProtocolError> {
         if *self {
@@ -294,6 +314,10 @@ mod tests {
         assert_text_encode(true, &[0, 0, 0, 1, 116]);
         assert_text_encode(false, &[0, 0, 0, 1, 102]);
         assert_text_encode("str".to_string(), &[0, 0, 0, 3, 115, 116, 114]);
+        assert_text_encode(
+            vec![0xde_u8, 0x01],
+            &[0, 0, 0, 6, 92, 120, 100, 101, 48, 49],
+        );
 
         Ok(())
     }
@@ -309,6 +333,7 @@ mod tests {
     fn test_binary_encoders() -> Result<(), ProtocolError> {
         assert_bind_encode(true, &[0, 0, 0, 1, 1]);
         assert_bind_encode(false, &[0, 0, 0, 1, 0]);
+        assert_bind_encode(vec![0xde_u8, 0x01], &[0, 0, 0, 2, 0xde, 0x01]);
 
         Ok(())
     }
@@ -0,0 +1,343 @@
+//! Conformance fuzzing of the frontend protocol decoding. Valid messages are truncated and
+//! mutated, then fed through an in-memory duplex stream. Decoding must not panic or hang,
+//! and every error must be representable as an ErrorResponse frame for the client.
+
+use std::{io::Cursor, time::Duration};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    buffer,
+    protocol::{ErrorCode, ErrorResponse, InitialMessage},
+    ProtocolError,
+};
+
+/// Deterministic xorshift generator, failures must be reproducible by the seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+fn frame(tag: Option<u8>, body: &[u8]) -> Vec<u8> {
+    let mut result = vec![];
+    if let Some(tag) = tag {
+        result.push(tag);
+    }
+    result.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+    result.extend_from_slice(body);
+
+    result
+}
+
+fn cstr(value: &str) -> Vec<u8> {
+    let mut result = value.as_bytes().to_vec();
+    result.push(0);
+
+    result
+}
+
+fn frontend_corpus() -> Vec<Vec<u8>> {
+    let parse = [
+        cstr("s1"),
+        cstr("SELECT $1, $2"),
+        vec![0, 2],
+        23u32.to_be_bytes().to_vec(),
+        25u32.to_be_bytes().to_vec(),
+    ]
+    .concat();
+    let bind = [
+        cstr("p1"),
+        cstr("s1"),
+        vec![0, 2, 0, 0, 0, 1],
+        vec![0, 2],
+        4i32.to_be_bytes().to_vec(),
+        vec![0, 0, 0, 42],
+        (-1i32).to_be_bytes().to_vec(),
+        vec![0, 1, 0, 0],
+    ]
+    .concat();
+
+    vec![
+        frame(Some(b'Q'), &cstr("SELECT 1")),
+        frame(Some(b'P'), &parse),
+        frame(Some(b'B'), &bind),
+        frame(Some(b'D'), &[vec![b'S'], cstr("s1")].concat()),
+        frame(Some(b'E'), &[cstr("p1"), vec![0, 0, 0, 0]].concat()),
+        frame(Some(b'C'), &[vec![b'P'], cstr("p1")].concat()),
+        frame(Some(b'p'), &cstr("password")),
+        frame(Some(b'S'), &[]),
+        frame(Some(b'H'), &[]),
+        frame(Some(b'X'), &[]),
+    ]
+}
+
+fn initial_corpus() -> Vec<Vec<u8>> {
+    let startup = [
+        vec![0, 3, 0, 0],
+        cstr("user"),
+        cstr("test"),
+        cstr("database"),
+        cstr("db"),
+        vec![0],
+    ]
+    .concat();
+
+    vec![
+        frame(None, &startup),
+        frame(None, &[0x04, 0xd2, 0x16, 0x2e, 0, 0, 0, 1, 0, 0, 0, 2]),
+        frame(None, &[0x04, 0xd2, 0x16, 0x2f]),
+        frame(None, &[0x04, 0xd2, 0x16, 0x30]),
+    ]
+}
+
+/// Produces malformed variants of the message: truncated, with bit flips, with corrupted
+/// length or counts, with garbage appended
+fn mutate(rng: &mut Rng, message: &[u8]) -> Vec<u8> {
+    let mut result = message.to_vec();
+    match rng.below(6) {
+        0 => result.truncate(rng.below(message.len())),
+        1 => {
+            for _ in 0..=rng.below(4) {
+                let idx = rng.below(result.len());
+                result[idx] ^= 1u8 << rng.below(8);
+            }
+        }
+        2 => {
+            // Length or Int16/Int32 fields with extreme values
+            let idx = rng.below(result.len());
+            let value = [0x00, 0x7f, 0x80, 0xff][rng.below(4)];
+            for byte in result.iter_mut().skip(idx).take(4) {
+                *byte = value;
+            }
+        }
+        3 => result.extend(rng.bytes(rng.below(32))),
+        4 => {
+            let idx = rng.below(result.len());
+            result.remove(idx);
+        }
+        _ => result = rng.bytes(rng.below(64)),
+    }
+
+    result
+}
+
+/// Bytes are written by a separate task and the stream is closed afterwards, the same as
+/// a client which disconnects in the middle of the message
+async fn decode<T, F, Fut>(input: Vec<u8>, decoder: F) -> Result<T, ProtocolError>
+where
+    T: Send + 'static,
+    F: FnOnce(tokio::io::DuplexStream) -> Fut,
+    Fut: std::future::Future<Output = Result<T, ProtocolError>> + Send + 'static,
+{
+    let (mut client, server) = tokio::io::duplex(64);
+    let writer = tokio::spawn(async move {
+        // Reader may stop before the whole input is consumed
+        let _ = client.write_all(&input).await;
+        let _ = client.shutdown().await;
+    });
+
+    let result = tokio::time::timeout(Duration::from_secs(5), tokio::spawn(decoder(server)))
+        .await
+        .expect("decoding must not hang on a closed stream")
+        .expect("decoding must not panic");
+    writer.await.unwrap();
+
+    result
+}
+
+/// Error must be sent as a complete ErrorResponse frame with its fields in the protocol order
+async fn assert_error_frame(err: ProtocolError) {
+    let response: ErrorResponse = err.to_error_response();
+    let severity = response.severity.to_string();
+    let mut expected = vec![
+        (b'S', severity.clone()),
+        (b'V', severity),
+        (b'C', response.code.to_string()),
+        (b'M', response.message.clone()),
+    ];
+    if let Some(detail) = &response.detail {
+        expected.push((b'D', detail.clone()));
+    }
+
+    let mut cursor = Cursor::new(vec![]);
+    buffer::write_message(&mut cursor, response).await.unwrap();
+
+    let mut frame = Cursor::new(cursor.into_inner());
+    assert_eq!(frame.read_u8().await.unwrap(), b'E');
+    let length = frame.read_u32().await.unwrap() as usize;
+    assert_eq!(length, frame.get_ref().len() - 1);
+
+    let mut fields = vec![];
+    loop {
+        let field = frame
+            .read_u8()
+            .await
+            .expect("ErrorResponse must be terminated");
+        if field == 0 {
+            break;
+        }
+        let mut value = vec![];
+        loop {
+            match frame.read_u8().await.expect("field must be a C string") {
+                0 => break,
+                byte => value.push(byte),
+            }
+        }
+        fields.push((
+            field,
+            String::from_utf8(value).expect("field must be UTF-8"),
+        ));
+    }
+    assert_eq!(
+        frame.position() as usize,
+        frame.get_ref().len(),
+        "ErrorResponse has data after the terminator"
+    );
+    assert_eq!(fields, expected);
+    assert!(matches!(fields[0].1.as_str(), "ERROR" | "FATAL"));
+    assert_eq!(fields[2].1.len(), 5, "SQLSTATE must have 5 characters");
+}
+
+#[tokio::test]
+async fn test_fuzz_frontend_messages() {
+    let mut rng = Rng::new(0x5eed);
+    let corpus = frontend_corpus();
+
+    for message in corpus.iter() {
+        // Every truncation of a valid message must be rejected
+        for len in 0..message.len() {
+            let input = message[..len].to_vec();
+            let result = decode(input, |mut stream| async move {
+                buffer::read_message(&mut stream).await
+            })
+            .await;
+            match result {
+                Ok(decoded) => panic!("Truncated message was decoded: {:?}", decoded),
+                Err(err) => assert_error_frame(err).await,
+            }
+        }
+    }
+
+    for _ in 0..2000 {
+        let message = &corpus[rng.below(corpus.len())];
+        let input = mutate(&mut rng, message);
+        let result = decode(input, |mut stream| async move {
+            buffer::read_message(&mut stream).await
+        })
+        .await;
+        if let Err(err) = result {
+            assert_error_frame(err).await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_fuzz_initial_messages() {
+    let mut rng = Rng::new(0xc0ffee);
+    let corpus = initial_corpus();
+
+    let read_initial = |mut stream: tokio::io::DuplexStream| async move {
+        let mut buffer =
+            buffer::read_contents_with_limit(&mut stream, 0, buffer::MAX_STARTUP_PACKET_LENGTH)
+                .await?;
+        InitialMessage::from(&mut buffer).await.map(|_| ())
+    };
+
+    for message in corpus.iter() {
+        for len in 0..message.len() {
+            let result = decode(message[..len].to_vec(), read_initial).await;
+            assert!(result.is_err(), "Truncated initial message was decoded");
+        }
+    }
+
+    for _ in 0..2000 {
+        let message = &corpus[rng.below(corpus.len())];
+        if let Err(err) = decode(mutate(&mut rng, message), read_initial).await {
+            assert_error_frame(err).await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_fuzz_oversized_length() {
+    // Declared length is not allocated upfront, the client has to send the data
+    for length in [u32::MAX, buffer::MAX_MESSAGE_LENGTH, 0x7fffffff, 3] {
+        let mut input = vec![b'Q'];
+        input.extend_from_slice(&length.to_be_bytes());
+        input.extend_from_slice(&cstr("SELECT 1"));
+
+        let result = decode(input, |mut stream| async move {
+            buffer::read_message(&mut stream).await
+        })
+        .await;
+        assert!(result.is_err());
+    }
+
+    let mut input = (buffer::MAX_STARTUP_PACKET_LENGTH + 1)
+        .to_be_bytes()
+        .to_vec();
+    input.extend_from_slice(&[0, 3, 0, 0]);
+    let result = decode(input, |mut stream| async move {
+        buffer::read_contents_with_limit(&mut stream, 0, buffer::MAX_STARTUP_PACKET_LENGTH)
+            .await
+            .map_err(ProtocolError::from)
+    })
+    .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_fuzz_negative_counts() {
+    let parse = [cstr("s1"), cstr("SELECT 1"), vec![0xff, 0xff]].concat();
+    let bind_length = [
+        cstr(""),
+        cstr(""),
+        vec![0, 0, 0, 1],
+        (-2i32).to_be_bytes().to_vec(),
+        vec![0, 0],
+    ]
+    .concat();
+    let bind_overflow = [
+        cstr(""),
+        cstr(""),
+        vec![0, 0, 0, 1],
+        i32::MAX.to_be_bytes().to_vec(),
+        vec![0, 0],
+    ]
+    .concat();
+
+    for input in [
+        frame(Some(b'P'), &parse),
+        frame(Some(b'B'), &bind_length),
+        frame(Some(b'B'), &bind_overflow),
+    ] {
+        let result = decode(input, |mut stream| async move {
+            buffer::read_message(&mut stream).await
+        })
+        .await;
+        match result {
+            Err(ProtocolError::ErrorResponse { source, .. }) => {
+                assert!(matches!(source.code, ErrorCode::ProtocolViolation))
+            }
+            other => panic!("Expected protocol violation, actual: {:?}", other),
+        }
+    }
+}
@@ -8,6 +8,8 @@
 
 mod decoding;
 mod encoding;
+#[cfg(test)]
+mod fuzzing;
 
 pub mod buffer;
 pub mod extended;
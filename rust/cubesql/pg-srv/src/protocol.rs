@@ -148,6 +148,14 @@ impl NoticeResponse {
             message,
         }
     }
+
+    pub fn info(code: ErrorCode, message: String) -> Self {
+        Self {
+            severity: NoticeSeverity::Info,
+            code,
+            message,
+        }
+    }
 }
 
 impl Serialize for NoticeResponse {
@@ -177,6 +185,7 @@ pub struct ErrorResponse {
     pub severity: ErrorSeverity,
     pub code: ErrorCode,
     pub message: String,
+    pub detail: Option<String>,
 }
 
 impl Display for ErrorResponse {
@@ -191,6 +200,7 @@ impl ErrorResponse {
             severity,
             code,
             message,
+            detail: None,
         }
     }
 
@@ -199,6 +209,7 @@ impl ErrorResponse {
             severity: ErrorSeverity::Error,
             code,
             message,
+            detail: None,
         }
     }
 
@@ -207,14 +218,30 @@ impl ErrorResponse {
             severity: ErrorSeverity::Fatal,
             code,
             message,
+            detail: None,
         }
     }
 
+    pub fn with_detail(mut self, detail: Option<String>) -> Self {
+        self.detail = detail;
+        self
+    }
+
     pub fn query_canceled() -> Self {
         Self {
             severity: ErrorSeverity::Error,
             code: ErrorCode::QueryCanceled,
             message: "canceling statement due to user request".to_string(),
+            detail: None,
+        }
+    }
+
+    pub fn idle_in_transaction_session_timeout() -> Self {
+        Self {
+            severity: ErrorSeverity::Fatal,
+            code: ErrorCode::IdleInTransactionSessionTimeout,
+            message: "terminating connection due to idle-in-transaction timeout".to_string(),
+            detail: None,
         }
     }
 }
@@ -234,6 +261,10 @@ impl Serialize for ErrorResponse {
         buffer::write_string(&mut buffer, &self.code.to_string());
         buffer.push(b'M');
         buffer::write_string(&mut buffer, &self.message);
+        if let Some(detail) = &self.detail {
+            buffer.push(b'D');
+            buffer::write_string(&mut buffer, detail);
+        }
         buffer.push(0);
 
         Some(buffer)
@@ -626,6 +657,25 @@ impl Deserialize for PasswordMessage {
     }
 }
 
+/// (F) GSSAPI token from the client. It has the same identifier as PasswordMessage, the type
+/// depends on the requested authentication method.
+#[derive(Debug, PartialEq)]
+pub struct GSSResponse {
+    pub data: Vec<u8>,
+}
+
+#[async_trait]
+impl Deserialize for GSSResponse {
+    async fn deserialize(buffer: Cursor<Vec<u8>>) -> Result<Self, ProtocolError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            data: buffer.into_inner(),
+        })
+    }
+}
+
 /// (F) Extended Query. Contains a textual query string, optionally some information about data
 /// types of parameter placeholders, and the name of a destination prepared-statement object
 /// (an empty string selects the unnamed prepared statement)
@@ -641,6 +691,20 @@ pub struct Parse {
     pub param_types: Vec<u32>,
 }
 
+/// Reads Int16 count of the following items, count can't be negative
+async fn read_count(buffer: &mut Cursor<Vec<u8>>, items: &str) -> Result<usize, ProtocolError> {
+    let total = buffer.read_i16().await?;
+    if total < 0 {
+        return Err(ErrorResponse::error(
+            ErrorCode::ProtocolViolation,
+            format!("Invalid number of {}: {}", items, total),
+        )
+        .into());
+    }
+
+    Ok(total as usize)
+}
+
 #[async_trait]
 impl Deserialize for Parse {
     async fn deserialize(mut buffer: Cursor<Vec<u8>>) -> Result<Self, ProtocolError>
@@ -650,8 +714,8 @@ impl Deserialize for Parse {
         let name = buffer::read_string(&mut buffer).await?;
         let query = buffer::read_string(&mut buffer).await?;
 
-        let total = buffer.read_i16().await?;
-        let mut param_types = Vec::with_capacity(total as usize);
+        let total = read_count(&mut buffer, "parameter types").await?;
+        let mut param_types = Vec::with_capacity(total);
 
         for _ in 0..total {
             param_types.push(buffer.read_u32().await?);
@@ -802,7 +866,7 @@ impl Deserialize for Bind {
 
         let mut parameter_formats = Vec::new();
         {
-            let total = buffer.read_i16().await?;
+            let total = read_count(&mut buffer, "parameter formats").await?;
             for _ in 0..total {
                 parameter_formats.push(buffer::read_format(&mut buffer).await?);
             }
@@ -810,17 +874,31 @@ impl Deserialize for Bind {
 
         let mut parameter_values = Vec::new();
         {
-            let total = buffer.read_i16().await?;
+            let total = read_count(&mut buffer, "parameter values").await?;
             for _ in 0..total {
                 let len = buffer.read_i32().await?;
                 if len == -1 {
                     parameter_values.push(None);
+                } else if len < 0 {
+                    return Err(ErrorResponse::error(
+                        ErrorCode::ProtocolViolation,
+                        format!("Invalid parameter value length: {}", len),
+                    )
+                    .into());
                 } else {
-                    let mut value = Vec::with_capacity(len as usize);
-                    for _ in 0..len {
-                        value.push(buffer.read_u8().await?);
+                    let len = len as usize;
+                    let remaining = buffer.get_ref().len() as u64 - buffer.position();
+                    if len as u64 > remaining {
+                        return Err(ErrorResponse::error(
+                            ErrorCode::ProtocolViolation,
+                            format!("Parameter value length {} exceeds the message size", len),
+                        )
+                        .into());
                     }
 
+                    let mut value = vec![0; len];
+                    buffer.read_exact(&mut value).await?;
+
                     parameter_values.push(Some(value));
                 }
             }
@@ -828,7 +906,7 @@ impl Deserialize for Bind {
 
         let mut result_formats = Vec::new();
         {
-            let total = buffer.read_i16().await?;
+            let total = read_count(&mut buffer, "result formats").await?;
 
             for _ in 0..total {
                 result_formats.push(buffer::read_format(&mut buffer).await?);
@@ -933,6 +1011,10 @@ pub enum FrontendMessage {
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum ErrorCode {
+    // 00 — Successful Completion
+    SuccessfulCompletion,
+    // 01 — Warning
+    Warning,
     // 0A — Feature Not Supported
     FeatureNotSupported,
     // 8 -  Connection Exception
@@ -945,6 +1027,7 @@ pub enum ErrorCode {
     // Class 25 — Invalid Transaction State
     ActiveSqlTransaction,
     NoActiveSqlTransaction,
+    IdleInTransactionSessionTimeout,
     // 26
     InvalidSqlStatement,
     // 34
@@ -952,7 +1035,9 @@ pub enum ErrorCode {
     // Class 42 — Syntax Error or Access Rule Violation
     DuplicateCursor,
     SyntaxError,
+    UndefinedColumn,
     // Class 53 — Insufficient Resources
+    InsufficientResources,
     ConfigurationLimitExceeded,
     // Class 55 — Object Not In Prerequisite State
     ObjectNotInPrerequisiteState,
@@ -965,6 +1050,8 @@ pub enum ErrorCode {
 impl Display for ErrorCode {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let string = match self {
+            Self::SuccessfulCompletion => "00000",
+            Self::Warning => "01000",
             Self::FeatureNotSupported => "0A000",
             Self::ProtocolViolation => "08P01",
             Self::InvalidAuthorizationSpecification => "28000",
@@ -972,10 +1059,13 @@ impl Display for ErrorCode {
             Self::DataException => "22000",
             Self::ActiveSqlTransaction => "25001",
             Self::NoActiveSqlTransaction => "25P01",
+            Self::IdleInTransactionSessionTimeout => "25P03",
             Self::InvalidSqlStatement => "26000",
             Self::InvalidCursorName => "34000",
             Self::DuplicateCursor => "42P03",
             Self::SyntaxError => "42601",
+            Self::UndefinedColumn => "42703",
+            Self::InsufficientResources => "53000",
             Self::ConfigurationLimitExceeded => "53400",
             Self::ObjectNotInPrerequisiteState => "55000",
             Self::QueryCanceled => "57014",
@@ -1046,17 +1136,28 @@ impl TransactionStatus {
 pub enum AuthenticationRequest {
     Ok,
     CleartextPassword,
+    /// Client should start GSSAPI negotiation
+    GSS,
+    /// Token from the server side of the GSSAPI negotiation
+    GSSContinue(Vec<u8>),
 }
 
 impl AuthenticationRequest {
     pub fn to_bytes(&self) -> Vec<u8> {
-        self.to_code().to_be_bytes().to_vec()
+        let mut bytes = self.to_code().to_be_bytes().to_vec();
+        if let Self::GSSContinue(data) = self {
+            bytes.extend_from_slice(data);
+        }
+
+        bytes
     }
 
     pub fn to_code(&self) -> u32 {
         match self {
             Self::Ok => 0,
             Self::CleartextPassword => 3,
+            Self::GSS => 7,
+            Self::GSSContinue(_) => 8,
         }
     }
 }
@@ -1272,6 +1373,36 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_frontend_message_parse_gss_response() -> Result<(), ProtocolError> {
+        let buffer = parse_hex_dump(
+            r#"
+            70 00 00 00 08 60 82 00 00                        p....`...
+            "#
+            .to_string(),
+        );
+        let mut cursor = Cursor::new(buffer);
+
+        let message = crate::buffer::read_gss_response(&mut cursor).await?;
+        assert_eq!(
+            message,
+            GSSResponse {
+                data: vec![0x60, 0x82, 0x00, 0x00]
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_authentication_gss_continue_bytes() {
+        assert_eq!(AuthenticationRequest::GSS.to_bytes(), vec![0, 0, 0, 7]);
+        assert_eq!(
+            AuthenticationRequest::GSSContinue(vec![1, 2]).to_bytes(),
+            vec![0, 0, 0, 8, 1, 2]
+        );
+    }
+
     #[tokio::test]
     async fn test_frontend_message_parse_password_message() -> Result<(), ProtocolError> {
         let buffer = parse_hex_dump(